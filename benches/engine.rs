@@ -0,0 +1,165 @@
+// benches/engine.rs
+
+//! 引擎微基准：命令分发、各数据类型的基本操作、过期检查与 AOF 追加
+//!
+//! 运行 `cargo bench --bench engine`，只跑其中一组时加过滤条件，如 `cargo bench --bench engine -- string`。
+//! 数据类型的操作分别在 sled 与纯内存两种存储上测量，用于比较 key 编码等改动前后的开销。
+
+use std::hint::black_box;
+
+use bytes::Bytes;
+
+use criterion::{BatchSize, Criterion, criterion_group, criterion_main};
+
+use crab_cage::config::Config;
+use crab_cage::engine::{self, Storage};
+use crab_cage::expire;
+use crab_cage::persistence::Persistence;
+use crab_cage::txn::session::TxnSession;
+use crab_cage::types::{hash, list, set, string};
+
+fn args(parts: &[&str]) -> Vec<Bytes> {
+    parts.iter().map(|p| Bytes::copy_from_slice(p.as_bytes())).collect()
+}
+
+fn sled_storage() -> Storage {
+    Storage::sled(sled::Config::new().temporary(true).open().unwrap()).unwrap()
+}
+
+/// 两种存储各跑一遍同一个基准
+fn storages() -> [(&'static str, Storage); 2] {
+    [("sled", sled_storage()), ("memory", Storage::memory())]
+}
+
+/// `engine::execute` 的完整路径：参数校验、过期清理、分发与回复构造
+fn bench_dispatch(c: &mut Criterion) {
+    let db = sled_storage();
+    let mut session = TxnSession::new(1);
+    string::set(&db, b"key", b"value").unwrap();
+
+    let mut group = c.benchmark_group("dispatch");
+    group.bench_function("PING", |b| b.iter(|| engine::execute(black_box(&args(&["PING"])), &db, &mut session)));
+    group.bench_function("GET", |b| b.iter(|| engine::execute(black_box(&args(&["GET", "key"])), &db, &mut session)));
+    group.bench_function("SET", |b| {
+        b.iter(|| engine::execute(black_box(&args(&["SET", "key", "value"])), &db, &mut session))
+    });
+    group.finish();
+}
+
+fn bench_string(c: &mut Criterion) {
+    let mut group = c.benchmark_group("string");
+    for (name, db) in storages() {
+        string::set(&db, b"key", b"value").unwrap();
+        group.bench_function(format!("{}/set", name), |b| b.iter(|| string::set(&db, black_box(b"key"), b"value").unwrap()));
+        group.bench_function(format!("{}/get", name), |b| b.iter(|| string::get(&db, black_box(b"key")).unwrap()));
+        group.bench_function(format!("{}/incr", name), |b| b.iter(|| string::incr(&db, black_box(b"counter")).unwrap()));
+    }
+    group.finish();
+}
+
+fn bench_hash(c: &mut Criterion) {
+    let mut group = c.benchmark_group("hash");
+    for (name, db) in storages() {
+        for i in 0..100 {
+            hash::hset(&db, b"user", format!("field:{}", i).as_bytes(), b"value").unwrap();
+        }
+        group.bench_function(format!("{}/hset", name), |b| {
+            b.iter(|| hash::hset(&db, black_box(b"user"), b"field:0", b"value").unwrap())
+        });
+        group.bench_function(format!("{}/hget", name), |b| {
+            b.iter(|| hash::hget(&db, black_box(b"user"), b"field:50").unwrap())
+        });
+        group.bench_function(format!("{}/hgetall_100", name), |b| {
+            b.iter(|| hash::hgetall(&db, black_box(b"user")).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn bench_list(c: &mut Criterion) {
+    let mut group = c.benchmark_group("list");
+    for (name, db) in storages() {
+        // 每次压入后立即弹出，列表长度保持不变
+        group.bench_function(format!("{}/lpush_lpop", name), |b| {
+            b.iter(|| {
+                list::lpush(&db, black_box(b"queue"), b"item").unwrap();
+                list::lpop(&db, black_box(b"queue")).unwrap()
+            })
+        });
+        for _ in 0..100 {
+            list::rpush(&db, b"range", b"item").unwrap();
+        }
+        group.bench_function(format!("{}/lrange_100", name), |b| {
+            b.iter(|| list::lrange(&db, black_box(b"range"), 0, -1).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn bench_set(c: &mut Criterion) {
+    let mut group = c.benchmark_group("set");
+    for (name, db) in storages() {
+        for i in 0..100 {
+            set::sadd(&db, b"tags", format!("member:{}", i).as_bytes()).unwrap();
+        }
+        group.bench_function(format!("{}/sadd", name), |b| {
+            b.iter(|| set::sadd(&db, black_box(b"tags"), b"member:0").unwrap())
+        });
+        group.bench_function(format!("{}/sismember", name), |b| {
+            b.iter(|| set::sismember(&db, black_box(b"tags"), b"member:50").unwrap())
+        });
+        group.bench_function(format!("{}/smembers_100", name), |b| {
+            b.iter(|| set::smembers(&db, black_box(b"tags")).unwrap())
+        });
+    }
+    group.finish();
+}
+
+/// 每条命令执行前都会做的过期检查：没有过期时间、尚未过期、已过期需要删除三种情况
+fn bench_expiry(c: &mut Criterion) {
+    let db = sled_storage();
+    string::set(&db, b"plain", b"v").unwrap();
+    string::set(&db, b"ttl", b"v").unwrap();
+    expire::expire(&db, b"ttl", 3600).unwrap();
+
+    let mut group = c.benchmark_group("expiry");
+    group.bench_function("no_ttl", |b| b.iter(|| expire::remove_if_expired(&db, black_box(b"plain")).unwrap()));
+    group.bench_function("live", |b| b.iter(|| expire::remove_if_expired(&db, black_box(b"ttl")).unwrap()));
+    group.bench_function("expired", |b| {
+        b.iter_batched(
+            || {
+                string::set(&db, b"gone", b"v").unwrap();
+                expire::expire_at(&db, b"gone", 1).unwrap();
+            },
+            |()| expire::remove_if_expired(&db, black_box(b"gone")).unwrap(),
+            BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+/// 写命令追加到 AOF（写入缓冲区，不含 fsync）
+fn bench_aof(c: &mut Criterion) {
+    let dir = tempfile::tempdir().unwrap();
+    let cfg = Config { storage: "memory".to_string(), aof: true, rdb: false, ..Config::default() };
+    let (_db, pers) = Persistence::open(
+        cfg.clone(),
+        dir.path(),
+        cfg.db_path.as_ref(),
+        cfg.aof_path.as_ref(),
+        cfg.rdb_path.as_ref(),
+    )
+    .unwrap();
+    let set = args(&["SET", "key", "value"]);
+    let multi = vec![args(&["INCR", "counter"]), args(&["LPUSH", "queue", "item"])];
+
+    let mut group = c.benchmark_group("aof");
+    group.bench_function("append", |b| b.iter(|| pers.append_aof_and_maybe_snapshot(black_box(&set))));
+    group.bench_function("append_transaction", |b| b.iter(|| pers.append_transaction(black_box(&multi))));
+    group.finish();
+
+    pers.fsync_and_close();
+}
+
+criterion_group!(benches, bench_dispatch, bench_string, bench_hash, bench_list, bench_set, bench_expiry, bench_aof);
+criterion_main!(benches);
@@ -0,0 +1,500 @@
+// src/acl.rs
+
+//! ACL 访问控制：
+//! - 用户：启用状态、密码（只保存 SHA-256 摘要）
+//! - 命令权限：`+cmd` / `-cmd` / `+@category` / `-@category`，按顺序求值，后者覆盖前者
+//! - key 权限：`~pattern` glob 模式
+//!
+//! 规则语法与 Redis `ACL SETUSER` 保持一致，服务端在调度命令前调用 [`Acl::check`]。
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::RwLock;
+
+use bytes::Bytes;
+use anyhow::{bail, Result};
+use sha2::{Digest, Sha256};
+
+use crate::command;
+use crate::config::Config;
+use crate::error::CommandError;
+use crate::glob::glob_match;
+use crate::protocol::Frame;
+
+/// 所有命令类别
+pub const CATEGORIES: &[&str] = &[
+    "keyspace", "read", "write", "set", "hash", "list", "string",
+    "fast", "slow", "transaction", "connection", "admin", "dangerous", "pubsub",
+];
+
+fn command_categories(cmd: &str) -> &'static [&'static str] {
+    command::lookup(cmd).map_or(&[], |c| c.categories)
+}
+
+/// 密码只保存 SHA-256 十六进制摘要
+fn hash_password(password: &str) -> String {
+    hex::encode(Sha256::digest(password.as_bytes()))
+}
+
+/// 一条命令规则
+#[derive(Debug, Clone, PartialEq)]
+enum CommandRule {
+    Category { name: String, allow: bool },
+    Command { name: String, allow: bool },
+}
+
+impl CommandRule {
+    /// 规则是否作用于该命令，作用时返回允许 / 拒绝
+    fn verdict(&self, cmd: &str) -> Option<bool> {
+        match self {
+            CommandRule::Category { name, allow } => {
+                (name == "all" || command_categories(cmd).contains(&name.as_str())).then_some(*allow)
+            }
+            CommandRule::Command { name, allow } => (name == cmd).then_some(*allow),
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            CommandRule::Category { name, allow } => {
+                format!("{}@{}", if *allow { '+' } else { '-' }, name)
+            }
+            CommandRule::Command { name, allow } => {
+                format!("{}{}", if *allow { '+' } else { '-' }, name.to_lowercase())
+            }
+        }
+    }
+}
+
+/// ACL 用户
+#[derive(Debug, Clone)]
+pub struct User {
+    pub name: String,
+    pub enabled: bool,
+    pub nopass: bool,
+    /// 密码的 SHA-256 摘要
+    passwords: BTreeSet<String>,
+    rules: Vec<CommandRule>,
+    key_patterns: Vec<String>,
+}
+
+impl User {
+    /// 新建用户：禁用、无密码、无任何命令和 key 权限
+    pub fn new(name: &str) -> Self {
+        User {
+            name: name.to_string(),
+            enabled: false,
+            nopass: false,
+            passwords: BTreeSet::new(),
+            rules: Vec::new(),
+            key_patterns: Vec::new(),
+        }
+    }
+
+    /// 应用一条 ACL SETUSER 规则
+    pub fn apply_rule(&mut self, rule: &str) -> Result<()> {
+        let lower = rule.to_lowercase();
+        match lower.as_str() {
+            "on" => self.enabled = true,
+            "off" => self.enabled = false,
+            "nopass" => {
+                self.nopass = true;
+                self.passwords.clear();
+            }
+            "resetpass" => {
+                self.nopass = false;
+                self.passwords.clear();
+            }
+            "allkeys" => self.key_patterns = vec!["*".to_string()],
+            "resetkeys" => self.key_patterns.clear(),
+            "allcommands" => self.rules = vec![CommandRule::Category { name: "all".into(), allow: true }],
+            "nocommands" => self.rules.clear(),
+            "reset" => *self = User::new(&self.name),
+            _ => match rule.as_bytes()[0] {
+                b'>' => {
+                    self.passwords.insert(hash_password(&rule[1..]));
+                    self.nopass = false;
+                }
+                b'<' => {
+                    if !self.passwords.remove(&hash_password(&rule[1..])) {
+                        bail!("no such password");
+                    }
+                }
+                b'#' => {
+                    let hash = lower[1..].to_string();
+                    if hash.len() != 64 || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+                        bail!("The password hash must be exactly 64 characters and contain only lowercase hexadecimal characters");
+                    }
+                    self.passwords.insert(hash);
+                    self.nopass = false;
+                }
+                b'!' => {
+                    if !self.passwords.remove(&lower[1..]) {
+                        bail!("no such password");
+                    }
+                }
+                b'~' => {
+                    let pattern = &rule[1..];
+                    if pattern == "*" {
+                        self.key_patterns = vec!["*".to_string()];
+                    } else if !self.key_patterns.iter().any(|p| p == "*" || p == pattern) {
+                        self.key_patterns.push(pattern.to_string());
+                    }
+                }
+                sign @ (b'+' | b'-') => {
+                    let allow = sign == b'+';
+                    let target = &lower[1..];
+                    let rule = if let Some(cat) = target.strip_prefix('@') {
+                        if cat != "all" && !CATEGORIES.contains(&cat) {
+                            bail!("Unknown command or category name in ACL");
+                        }
+                        CommandRule::Category { name: cat.to_string(), allow }
+                    } else {
+                        let cmd = target.to_uppercase();
+                        if command_categories(&cmd).is_empty() {
+                            bail!("Unknown command or category name in ACL");
+                        }
+                        CommandRule::Command { name: cmd, allow }
+                    };
+                    // 同一目标的旧规则已被新规则覆盖，去掉以保持描述简洁
+                    self.rules.retain(|r| !matches!((r, &rule),
+                        (CommandRule::Command { name: a, .. }, CommandRule::Command { name: b, .. }) if a == b));
+                    if matches!(&rule, CommandRule::Category { name, .. } if name == "all") {
+                        self.rules.clear();
+                    }
+                    self.rules.push(rule);
+                }
+                _ => bail!("Syntax error"),
+            },
+        }
+        Ok(())
+    }
+
+    /// 是否允许执行该命令（命令名需大写）
+    pub fn can_run(&self, cmd: &str) -> bool {
+        self.rules
+            .iter()
+            .fold(false, |allowed, rule| rule.verdict(cmd).unwrap_or(allowed))
+    }
+
+    /// 是否允许访问该 key
+    pub fn can_access_key(&self, key: &[u8]) -> bool {
+        self.key_patterns
+            .iter()
+            .any(|p| glob_match(p.as_bytes(), key))
+    }
+
+    /// 校验密码
+    pub fn check_password(&self, password: &str) -> bool {
+        self.nopass || self.passwords.contains(&hash_password(password))
+    }
+
+    fn flags(&self) -> Vec<&'static str> {
+        let mut flags = vec![if self.enabled { "on" } else { "off" }];
+        if self.nopass {
+            flags.push("nopass");
+        }
+        flags
+    }
+
+    fn commands_description(&self) -> String {
+        if self.rules.is_empty() {
+            return "-@all".to_string();
+        }
+        self.rules.iter().map(|r| r.describe()).collect::<Vec<_>>().join(" ")
+    }
+
+    fn keys_description(&self) -> String {
+        self.key_patterns
+            .iter()
+            .map(|p| format!("~{}", p))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// ACL LIST 中的一行，可以原样作为 SETUSER 规则重新加载
+    pub fn describe(&self) -> String {
+        let mut parts = vec![format!("user {}", self.name)];
+        parts.extend(self.flags().into_iter().map(String::from));
+        parts.extend(self.passwords.iter().map(|h| format!("#{}", h)));
+        if !self.key_patterns.is_empty() {
+            parts.push(self.keys_description());
+        }
+        parts.push(self.commands_description());
+        parts.join(" ")
+    }
+}
+
+/// 全局 ACL 用户表
+pub struct Acl {
+    users: RwLock<BTreeMap<String, User>>,
+}
+
+impl Default for Acl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Acl {
+    /// 只包含 default 用户（无密码、全部命令、全部 key）
+    pub fn new() -> Self {
+        let mut default = User::new("default");
+        for rule in ["on", "nopass", "~*", "+@all"] {
+            default.apply_rule(rule).expect("valid default rule");
+        }
+        let mut users = BTreeMap::new();
+        users.insert(default.name.clone(), default);
+        Acl { users: RwLock::new(users) }
+    }
+
+    /// 按配置初始化：`requirepass` 设置 default 用户密码，
+    /// `acl_users` 每行形如 `alice on >secret ~cache:* +@read`
+    pub fn from_config(cfg: &Config) -> Result<Self> {
+        let acl = Acl::new();
+        if let Some(pass) = &cfg.requirepass {
+            acl.set_user("default", &["resetpass".to_string(), format!(">{}", pass)])?;
+        }
+        for line in &cfg.acl_users {
+            let mut words = line.split_whitespace().map(String::from);
+            // 兼容 ACL LIST 的输出格式
+            let mut name = words.next();
+            if name.as_deref() == Some("user") {
+                name = words.next();
+            }
+            let Some(name) = name else { continue };
+            let rules: Vec<String> = words.collect();
+            acl.set_user(&name, &rules)?;
+        }
+        Ok(acl)
+    }
+
+    /// 新连接的初始用户：default 用户启用且无密码时自动登录
+    pub fn default_login(&self) -> Option<String> {
+        let users = self.users.read().unwrap();
+        users
+            .get("default")
+            .filter(|u| u.enabled && u.nopass)
+            .map(|u| u.name.clone())
+    }
+
+    /// 校验用户名与密码
+    pub fn authenticate(&self, username: &str, password: &str) -> bool {
+        let users = self.users.read().unwrap();
+        users
+            .get(username)
+            .is_some_and(|u| u.enabled && u.check_password(password))
+    }
+
+    /// 创建或修改用户，规则全部合法才生效
+    pub fn set_user(&self, name: &str, rules: &[String]) -> Result<()> {
+        let mut users = self.users.write().unwrap();
+        let mut user = users.get(name).cloned().unwrap_or_else(|| User::new(name));
+        for rule in rules {
+            if rule.is_empty() {
+                bail!("Error in ACL SETUSER modifier '': Syntax error");
+            }
+            user.apply_rule(rule)
+                .map_err(|e| anyhow::anyhow!("Error in ACL SETUSER modifier '{}': {}", rule, e))?;
+        }
+        users.insert(name.to_string(), user);
+        Ok(())
+    }
+
+    /// 命令执行前的权限检查，失败时返回应回复给客户端的错误
+    pub fn check(&self, user: Option<&str>, cmd: &str, parts: &[Bytes]) -> Result<(), CommandError> {
+        let users = self.users.read().unwrap();
+        let Some(user) = user.and_then(|name| users.get(name)).filter(|u| u.enabled) else {
+            return Err(CommandError::no_auth());
+        };
+        if !user.can_run(cmd) {
+            return Err(CommandError::NoPerm(format!(
+                "User {} has no permissions to run the '{}' command",
+                user.name,
+                cmd.to_lowercase()
+            )));
+        }
+        let keys = command::lookup(cmd).map(|c| c.keys(parts)).unwrap_or_default();
+        if keys.iter().any(|k| !user.can_access_key(k)) {
+            return Err(CommandError::NoPerm("No permissions to access a key".to_string()));
+        }
+        Ok(())
+    }
+
+    /// AUTH [username] password
+    pub fn auth(&self, args: &[String], current: &mut Option<String>) -> Frame {
+        let (username, password) = match args {
+            [password] => {
+                let default_nopass = self
+                    .users
+                    .read()
+                    .unwrap()
+                    .get("default")
+                    .is_some_and(|u| u.nopass);
+                if default_nopass {
+                    return Frame::error("ERR AUTH <password> called without any password configured for the default user. Are you sure your configuration is correct?");
+                }
+                ("default", password)
+            }
+            [username, password] => (username.as_str(), password),
+            _ => return Frame::error("ERR wrong number of arguments for 'auth' command"),
+        };
+
+        if self.authenticate(username, password) {
+            *current = Some(username.to_string());
+            Frame::ok()
+        } else {
+            Frame::error("WRONGPASS invalid username-password pair or user is disabled.")
+        }
+    }
+
+    /// ACL 子命令：SETUSER / GETUSER / DELUSER / LIST / USERS / WHOAMI / CAT
+    pub fn execute(&self, args: &[String], current_user: &str) -> Frame {
+        let Some(sub) = args.first() else {
+            return Frame::error("ERR wrong number of arguments for 'acl' command");
+        };
+        let sub = sub.to_uppercase();
+        match (sub.as_str(), args.len()) {
+            ("SETUSER", n) if n >= 2 => match self.set_user(&args[1], &args[2..]) {
+                Ok(()) => Frame::ok(),
+                Err(e) => CommandError::from(e).into(),
+            },
+            ("GETUSER", 2) => {
+                let users = self.users.read().unwrap();
+                let Some(user) = users.get(&args[1]) else { return Frame::Null };
+                Frame::Map(vec![
+                    (
+                        Frame::bulk("flags"),
+                        Frame::Array(user.flags().into_iter().map(Frame::bulk).collect()),
+                    ),
+                    (
+                        Frame::bulk("passwords"),
+                        Frame::Array(user.passwords.iter().map(|h| Frame::bulk(h.as_str())).collect()),
+                    ),
+                    (Frame::bulk("commands"), Frame::bulk(user.commands_description())),
+                    (Frame::bulk("keys"), Frame::bulk(user.keys_description())),
+                ])
+            }
+            ("DELUSER", n) if n >= 2 => {
+                if args[1..].iter().any(|name| name == "default") {
+                    return Frame::error("ERR The 'default' user cannot be removed");
+                }
+                let mut users = self.users.write().unwrap();
+                let removed = args[1..].iter().filter(|name| users.remove(*name).is_some()).count();
+                Frame::Integer(removed as i64)
+            }
+            ("LIST", 1) => {
+                let users = self.users.read().unwrap();
+                Frame::Array(users.values().map(|u| Frame::bulk(u.describe())).collect())
+            }
+            ("USERS", 1) => {
+                let users = self.users.read().unwrap();
+                Frame::Array(users.keys().map(|name| Frame::bulk(name.as_str())).collect())
+            }
+            ("WHOAMI", 1) => Frame::bulk(current_user),
+            ("CAT", 1) => Frame::Array(CATEGORIES.iter().map(|c| Frame::bulk(*c)).collect()),
+            ("CAT", 2) => {
+                let cat = args[1].to_lowercase();
+                if !CATEGORIES.contains(&cat.as_str()) {
+                    return Frame::error(format!("ERR Unknown category '{}'", cat));
+                }
+                Frame::Array(
+                    command::all()
+                        .into_iter()
+                        .filter(|c| c.categories.contains(&cat.as_str()))
+                        .map(|c| Frame::bulk(c.name.to_lowercase()))
+                        .collect(),
+                )
+            }
+            ("SETUSER" | "GETUSER" | "DELUSER" | "LIST" | "USERS" | "WHOAMI" | "CAT", _) => {
+                Frame::error(format!("ERR wrong number of arguments for 'acl|{}' command", sub.to_lowercase()))
+            }
+            _ => Frame::error(format!("ERR unknown subcommand '{}'. Try ACL HELP.", args[0])),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &[&str]) -> Vec<String> {
+        s.iter().map(|x| x.to_string()).collect()
+    }
+
+    fn cmd(s: &[&str]) -> Vec<Bytes> {
+        s.iter().map(|x| Bytes::copy_from_slice(x.as_bytes())).collect()
+    }
+
+    #[test]
+    fn test_default_user() {
+        let acl = Acl::new();
+        assert_eq!(acl.default_login().as_deref(), Some("default"));
+        assert!(acl.check(Some("default"), "SET", &cmd(&["SET", "k", "v"])).is_ok());
+        assert!(acl.check(None, "GET", &cmd(&["GET", "k"])).is_err());
+    }
+
+    #[test]
+    fn test_command_and_key_rules() {
+        let acl = Acl::new();
+        acl.set_user("alice", &args(&["on", ">secret", "~cache:*", "+@read", "-hgetall"])).unwrap();
+
+        assert!(acl.authenticate("alice", "secret"));
+        assert!(!acl.authenticate("alice", "wrong"));
+
+        let ok = acl.check(Some("alice"), "GET", &cmd(&["GET", "cache:1"]));
+        assert!(ok.is_ok());
+
+        // 写命令不在 @read 中
+        let denied = acl.check(Some("alice"), "SET", &cmd(&["SET", "cache:1", "v"]));
+        assert_eq!(
+            denied,
+            Err(CommandError::NoPerm("User alice has no permissions to run the 'set' command".to_string()))
+        );
+
+        // 被单独排除的命令
+        assert!(acl.check(Some("alice"), "HGETALL", &cmd(&["HGETALL", "cache:h"])).is_err());
+
+        // key 不匹配
+        let denied = acl.check(Some("alice"), "GET", &cmd(&["GET", "user:1"]));
+        assert_eq!(denied.map_err(Frame::from), Err(Frame::error("NOPERM No permissions to access a key")));
+
+        // 禁用后无法登录
+        acl.set_user("alice", &args(&["off"])).unwrap();
+        assert!(!acl.authenticate("alice", "secret"));
+    }
+
+    #[test]
+    fn test_requirepass_and_auth() {
+        let acl = Acl::new();
+        acl.set_user("default", &args(&["resetpass", ">pw"])).unwrap();
+        assert_eq!(acl.default_login(), None);
+
+        let mut current = None;
+        assert!(acl.auth(&args(&["bad"]), &mut current).is_error());
+        assert_eq!(current, None);
+        assert_eq!(acl.auth(&args(&["pw"]), &mut current), Frame::ok());
+        assert_eq!(current.as_deref(), Some("default"));
+    }
+
+    #[test]
+    fn test_acl_commands() {
+        let acl = Acl::new();
+        assert_eq!(
+            acl.execute(&args(&["SETUSER", "bob", "on", "nopass", "~*", "+get"]), "default"),
+            Frame::ok()
+        );
+        assert!(acl.execute(&args(&["SETUSER", "bob", "+nosuchcmd"]), "default").is_error());
+
+        assert_eq!(
+            acl.execute(&args(&["LIST"]), "default"),
+            Frame::Array(vec![
+                Frame::bulk("user bob on nopass ~* +get"),
+                Frame::bulk("user default on nopass ~* +@all"),
+            ])
+        );
+        assert_eq!(acl.execute(&args(&["WHOAMI"]), "bob"), Frame::bulk("bob"));
+        assert_eq!(acl.execute(&args(&["GETUSER", "nobody"]), "default"), Frame::Null);
+        assert_eq!(acl.execute(&args(&["DELUSER", "bob"]), "default"), Frame::Integer(1));
+        assert!(acl.execute(&args(&["DELUSER", "default"]), "default").is_error());
+    }
+}
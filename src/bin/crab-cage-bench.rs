@@ -0,0 +1,313 @@
+// src/bin/crab-cage-bench.rs
+
+//! 压测工具，参数与输出仿照 `redis-benchmark`
+//!
+//! `-c` 个客户端并发发送共 `-n` 条请求，每次发送 `-P` 条（pipeline）后等待全部回复；
+//! key 从 `-r` 个 key 的键空间中随机选取。`-t` 中的每种命令依次单独压测，
+//! `--mix` 则按给定权重混合多种命令压测一轮。每轮结束打印吞吐量与延迟分位数。
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result, bail};
+use clap::Parser;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use crab_cage::protocol::Frame;
+
+/// crab-cage-bench 启动参数
+#[derive(Parser, Debug, Clone)]
+#[command(author, version, about = "Benchmark a crab-cage server", long_about = None, disable_help_flag = true)]
+struct Args {
+    /// 服务端主机名
+    #[arg(short = 'h', long, default_value = "127.0.0.1")]
+    host: String,
+
+    /// 服务端端口
+    #[arg(short = 'p', long, default_value_t = 6380)]
+    port: u16,
+
+    /// 连接后用该密码认证
+    #[arg(short = 'a', long)]
+    password: Option<String>,
+
+    /// 并发客户端数
+    #[arg(short = 'c', long, default_value_t = 50)]
+    clients: usize,
+
+    /// 每轮请求总数
+    #[arg(short = 'n', long, default_value_t = 100_000)]
+    requests: u64,
+
+    /// 每个客户端每次发送的请求数
+    #[arg(short = 'P', long, default_value_t = 1)]
+    pipeline: usize,
+
+    /// 键空间大小：key 从 `key:0` .. `key:<r-1>` 中随机选取，0 表示所有请求使用同一个 key
+    #[arg(short = 'r', long, default_value_t = 0)]
+    keyspace: u64,
+
+    /// SET / LPUSH 的值大小（字节）
+    #[arg(short = 'd', long, default_value_t = 3)]
+    data_size: usize,
+
+    /// 依次压测的命令，逗号分隔
+    #[arg(short = 't', long, default_value = "set,get,incr,lpush", value_delimiter = ',')]
+    tests: Vec<String>,
+
+    /// 按权重混合压测，如 `get=8,set=2`，给出时忽略 `-t`
+    #[arg(long, value_delimiter = ',')]
+    mix: Vec<String>,
+
+    /// 每轮只打印一行汇总
+    #[arg(short = 'q', long)]
+    quiet: bool,
+
+    /// 打印帮助
+    #[arg(long, action = clap::ArgAction::Help)]
+    help: Option<bool>,
+}
+
+/// 支持压测的命令
+#[derive(Debug, Clone, Copy)]
+enum Op {
+    Set,
+    Get,
+    Incr,
+    Lpush,
+}
+
+impl Op {
+    fn parse(name: &str) -> Result<Op> {
+        Ok(match name.to_ascii_lowercase().as_str() {
+            "set" => Op::Set,
+            "get" => Op::Get,
+            "incr" => Op::Incr,
+            "lpush" => Op::Lpush,
+            _ => bail!("unsupported test '{}', expected set, get, incr or lpush", name),
+        })
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Op::Set => "SET",
+            Op::Get => "GET",
+            Op::Incr => "INCR",
+            Op::Lpush => "LPUSH",
+        }
+    }
+
+    /// 编码一条请求；不同类型的命令使用不同的 key 前缀，避免类型冲突
+    fn encode(self, key: u64, value: &str, out: &mut Vec<u8>) {
+        let parts = match self {
+            Op::Set => vec!["SET".to_string(), format!("key:{}", key), value.to_string()],
+            Op::Get => vec!["GET".to_string(), format!("key:{}", key)],
+            Op::Incr => vec!["INCR".to_string(), format!("counter:{}", key)],
+            Op::Lpush => vec!["LPUSH".to_string(), format!("mylist:{}", key), value.to_string()],
+        };
+        out.extend_from_slice(format!("*{}\r\n", parts.len()).as_bytes());
+        for part in parts {
+            out.extend_from_slice(format!("${}\r\n{}\r\n", part.len(), part).as_bytes());
+        }
+    }
+}
+
+/// 一轮压测的命令：按权重选取
+#[derive(Debug, Clone)]
+struct Workload {
+    name: String,
+    ops: Vec<(Op, u32)>,
+}
+
+impl Workload {
+    fn single(op: Op) -> Self {
+        Workload { name: op.name().to_string(), ops: vec![(op, 1)] }
+    }
+
+    fn mix(specs: &[String]) -> Result<Self> {
+        let mut ops = Vec::new();
+        for spec in specs {
+            let (name, weight) = spec.split_once('=').unwrap_or((spec, "1"));
+            let weight: u32 = weight.parse().with_context(|| format!("invalid weight in '{}'", spec))?;
+            ops.push((Op::parse(name)?, weight));
+        }
+        if ops.iter().all(|(_, w)| *w == 0) {
+            bail!("--mix needs at least one command with a positive weight");
+        }
+        Ok(Workload { name: format!("MIX({})", specs.join(",")), ops })
+    }
+
+    fn pick(&self, rng: &mut Rng) -> Op {
+        let total: u32 = self.ops.iter().map(|(_, w)| w).sum();
+        let mut n = (rng.next() % total as u64) as u32;
+        for (op, weight) in &self.ops {
+            if n < *weight {
+                return *op;
+            }
+            n -= weight;
+        }
+        self.ops[0].0
+    }
+}
+
+/// xorshift64*，压测只需要分布大致均匀
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+}
+
+/// 单个客户端的结果
+#[derive(Default)]
+struct ClientStats {
+    /// 每条请求的延迟（微秒）
+    latencies: Vec<u64>,
+    errors: u64,
+}
+
+async fn connect(args: &Args) -> Result<TcpStream> {
+    let mut stream = TcpStream::connect((args.host.as_str(), args.port))
+        .await
+        .with_context(|| format!("Could not connect to crab-cage at {}:{}", args.host, args.port))?;
+    stream.set_nodelay(true)?;
+    if let Some(password) = &args.password {
+        let auth = format!("*2\r\n$4\r\nAUTH\r\n${}\r\n{}\r\n", password.len(), password);
+        stream.write_all(auth.as_bytes()).await?;
+        let mut buf = Vec::new();
+        if let Frame::Error(e) = read_frame(&mut stream, &mut buf).await? {
+            bail!("AUTH failed: {}", e);
+        }
+    }
+    Ok(stream)
+}
+
+async fn read_frame(stream: &mut TcpStream, buf: &mut Vec<u8>) -> Result<Frame> {
+    loop {
+        if let Some((frame, used)) = Frame::decode(buf) {
+            buf.drain(..used);
+            return Ok(frame);
+        }
+        if stream.read_buf(buf).await? == 0 {
+            bail!("Server closed the connection");
+        }
+    }
+}
+
+/// 一个客户端：从共享计数中领取请求，直到总数用完
+async fn run_client(args: Arc<Args>, workload: Arc<Workload>, issued: Arc<AtomicU64>, seed: u64) -> Result<ClientStats> {
+    let mut stream = connect(&args).await?;
+    let mut rng = Rng(seed | 1);
+    let value = "x".repeat(args.data_size);
+    let mut stats = ClientStats::default();
+    let mut out = Vec::new();
+    let mut buf = Vec::new();
+
+    loop {
+        let start = issued.fetch_add(args.pipeline as u64, Ordering::Relaxed);
+        if start >= args.requests {
+            return Ok(stats);
+        }
+        let batch = (args.requests - start).min(args.pipeline as u64);
+
+        out.clear();
+        for _ in 0..batch {
+            let key = if args.keyspace == 0 { 0 } else { rng.next() % args.keyspace };
+            workload.pick(&mut rng).encode(key, &value, &mut out);
+        }
+        let sent = Instant::now();
+        stream.write_all(&out).await?;
+        for _ in 0..batch {
+            if read_frame(&mut stream, &mut buf).await?.is_error() {
+                stats.errors += 1;
+            }
+            stats.latencies.push(sent.elapsed().as_micros() as u64);
+        }
+    }
+}
+
+/// 一轮压测：返回全部请求的延迟与错误数
+async fn run_workload(args: &Arc<Args>, workload: Workload) -> Result<()> {
+    let workload = Arc::new(workload);
+    let issued = Arc::new(AtomicU64::new(0));
+    let started = Instant::now();
+
+    let tasks: Vec<_> = (0..args.clients)
+        .map(|i| {
+            let seed = started.elapsed().as_nanos() as u64 ^ (i as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+            tokio::spawn(run_client(args.clone(), workload.clone(), issued.clone(), seed))
+        })
+        .collect();
+
+    let mut latencies = Vec::with_capacity(args.requests as usize);
+    let mut errors = 0;
+    for task in tasks {
+        let stats = task.await??;
+        latencies.extend(stats.latencies);
+        errors += stats.errors;
+    }
+    let elapsed = started.elapsed();
+    report(args, &workload.name, &mut latencies, errors, elapsed);
+    Ok(())
+}
+
+fn report(args: &Args, name: &str, latencies: &mut [u64], errors: u64, elapsed: Duration) {
+    latencies.sort_unstable();
+    let rps = latencies.len() as f64 / elapsed.as_secs_f64();
+    let ms = |us: u64| us as f64 / 1000.0;
+    let percentile = |p: f64| {
+        if latencies.is_empty() {
+            return 0;
+        }
+        latencies[((latencies.len() as f64 * p).ceil() as usize).clamp(1, latencies.len()) - 1]
+    };
+
+    if args.quiet {
+        println!("{}: {:.2} requests per second, p50={:.3} msec", name, rps, ms(percentile(0.50)));
+        return;
+    }
+    println!("====== {} ======", name);
+    println!(
+        "  {} requests completed in {:.2} seconds ({} errors)",
+        latencies.len(),
+        elapsed.as_secs_f64(),
+        errors
+    );
+    println!("  {} parallel clients, pipeline {}, keyspace {}", args.clients, args.pipeline, args.keyspace);
+    println!("  throughput: {:.2} requests per second", rps);
+    println!(
+        "  latency (msec): p50={:.3} p95={:.3} p99={:.3} p99.9={:.3} max={:.3}",
+        ms(percentile(0.50)),
+        ms(percentile(0.95)),
+        ms(percentile(0.99)),
+        ms(percentile(0.999)),
+        ms(latencies.last().copied().unwrap_or(0))
+    );
+    println!();
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+    if args.clients == 0 || args.pipeline == 0 {
+        bail!("--clients and --pipeline must be at least 1");
+    }
+
+    let workloads = if args.mix.is_empty() {
+        args.tests.iter().map(|t| Op::parse(t).map(Workload::single)).collect::<Result<Vec<_>>>()?
+    } else {
+        vec![Workload::mix(&args.mix)?]
+    };
+
+    let args = Arc::new(args);
+    for workload in workloads {
+        run_workload(&args, workload).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,62 @@
+// src/bin/crab-cage-check-aof.rs
+
+//! AOF 检查 / 修复工具
+//!
+//! 崩溃后 AOF 末尾可能留下不完整的记录，服务器遇到格式损坏的记录会拒绝启动。
+//! 本工具报告第一处问题的位置，`--fix` 时把文件截断到最后一条完整记录。
+
+use std::fs::OpenOptions;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use anyhow::Result;
+use clap::Parser;
+
+use crab_cage::persistence::aof;
+
+/// crab-cage-check-aof 启动参数
+#[derive(Parser, Debug)]
+#[command(author, version, about = "Check and repair a crab-cage AOF file", long_about = None)]
+struct Args {
+    /// AOF 文件路径
+    file: PathBuf,
+
+    /// 把文件截断到最后一条完整记录
+    #[arg(long)]
+    fix: bool,
+}
+
+fn main() -> Result<ExitCode> {
+    let args = Args::parse();
+    let report = aof::check(&args.file)?;
+
+    if let Some(keys) = report.preamble_keys {
+        println!("RDB preamble: {} keys", keys);
+    }
+    println!(
+        "AOF analyzed: filename={}, size={}, ok_up_to={}, commands={}, diff={}",
+        args.file.display(),
+        report.file_len,
+        report.valid_len,
+        report.commands,
+        report.file_len - report.valid_len
+    );
+
+    let Some(problem) = report.problem else {
+        println!("AOF is valid");
+        return Ok(ExitCode::SUCCESS);
+    };
+    println!("AOF is not valid: {}", problem);
+
+    if !args.fix {
+        println!("Run with --fix to truncate the file to {} bytes", report.valid_len);
+        return Ok(ExitCode::FAILURE);
+    }
+    OpenOptions::new().write(true).open(&args.file)?.set_len(report.valid_len)?;
+    println!(
+        "Successfully truncated AOF to {} bytes ({} bytes discarded)",
+        report.valid_len,
+        report.file_len - report.valid_len
+    );
+    Ok(ExitCode::SUCCESS)
+}
@@ -0,0 +1,268 @@
+// src/bin/crab-cage-cli.rs
+
+//! 命令行客户端
+//!
+//! - 不带命令时进入交互模式（行编辑与历史记录），输入按 `redis-cli` 的规则切分参数
+//! - 命令行中直接给出命令时只执行这一条，如 `crab-cage-cli -p 6380 SET k v`
+//! - `--pipe` 从标准输入读取命令（每行一条）批量发送，最后汇总回复与错误数，用于导入数据
+//!
+//! 连接后用 `HELLO 3` 协商 RESP3，Map / Set 等回复可以按类型展示；服务端不支持时退回 RESP2。
+
+use std::io::{self, BufRead, BufWriter, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::thread;
+
+use anyhow::{Result, bail};
+use clap::Parser;
+use rustyline::Editor;
+use rustyline::error::ReadlineError;
+
+use crab_cage::client::{Client, encode};
+use crab_cage::protocol::Frame;
+use crab_cage::protocol::parser::split_inline;
+
+/// crab-cage-cli 启动参数
+#[derive(Parser, Debug)]
+#[command(author, version, about = "Command line client for crab-cage", long_about = None, disable_help_flag = true)]
+struct Args {
+    /// 服务端主机名
+    #[arg(short = 'h', long, default_value = "127.0.0.1")]
+    host: String,
+
+    /// 服务端端口
+    #[arg(short = 'p', long, default_value_t = 6380)]
+    port: u16,
+
+    /// 连接后用该密码认证
+    #[arg(short = 'a', long)]
+    password: Option<String>,
+
+    /// 认证使用的 ACL 用户名（默认 default）
+    #[arg(long)]
+    user: Option<String>,
+
+    /// 从标准输入批量发送命令
+    #[arg(long)]
+    pipe: bool,
+
+    /// 只执行这一条命令后退出
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+    command: Vec<String>,
+
+    /// 打印帮助
+    #[arg(long, action = clap::ArgAction::Help)]
+    help: Option<bool>,
+}
+
+/// 连接服务端，认证并协商 RESP3；服务端不支持时保持 RESP2
+fn open(args: &Args) -> Result<Client> {
+    let mut conn = Client::connect(format!("{}:{}", args.host, args.port))?;
+    if let Some(password) = &args.password {
+        conn.auth(args.user.as_deref(), password)?;
+    }
+    conn.call(&["HELLO", "3"])?;
+    Ok(conn)
+}
+
+/// 按 `redis-cli` 的风格展示回复，嵌套的聚合类型逐层缩进
+fn format_reply(frame: &Frame) -> String {
+    let mut out = String::new();
+    write_reply(frame, 0, &mut out);
+    out
+}
+
+fn write_reply(frame: &Frame, indent: usize, out: &mut String) {
+    match frame {
+        Frame::Simple(s) => out.push_str(s),
+        Frame::Error(e) => out.push_str(&format!("(error) {}", e)),
+        Frame::Integer(i) => out.push_str(&format!("(integer) {}", i)),
+        Frame::Bulk(b) => out.push_str(&quote(b)),
+        Frame::Null | Frame::NullArray => out.push_str("(nil)"),
+        Frame::Double(d) => out.push_str(&format!("(double) {}", d)),
+        Frame::Boolean(b) => out.push_str(if *b { "(true)" } else { "(false)" }),
+        Frame::Array(items) | Frame::Set(items) | Frame::Push(items) => {
+            if items.is_empty() {
+                out.push_str(if matches!(frame, Frame::Set(_)) { "(empty set)" } else { "(empty array)" });
+            }
+            let width = items.len().to_string().len();
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push('\n');
+                    out.push_str(&" ".repeat(indent));
+                }
+                let label = format!("{:>width$}) ", i + 1);
+                out.push_str(&label);
+                write_reply(item, indent + label.len(), out);
+            }
+        }
+        Frame::Map(pairs) => {
+            if pairs.is_empty() {
+                out.push_str("(empty hash)");
+            }
+            let width = pairs.len().to_string().len();
+            for (i, (key, value)) in pairs.iter().enumerate() {
+                if i > 0 {
+                    out.push('\n');
+                    out.push_str(&" ".repeat(indent));
+                }
+                let label = format!("{:>width$}# ", i + 1);
+                out.push_str(&label);
+                write_reply(key, indent + label.len(), out);
+                out.push_str(" => ");
+                write_reply(value, indent + label.len() + 4, out);
+            }
+        }
+    }
+}
+
+/// 带引号的字符串，不可打印字符转义为 `\xHH`
+fn quote(bytes: &[u8]) -> String {
+    let mut s = String::from("\"");
+    for &b in bytes {
+        match b {
+            b'"' => s.push_str("\\\""),
+            b'\\' => s.push_str("\\\\"),
+            b'\n' => s.push_str("\\n"),
+            b'\r' => s.push_str("\\r"),
+            b'\t' => s.push_str("\\t"),
+            0x20..=0x7e => s.push(b as char),
+            _ => s.push_str(&format!("\\x{:02x}", b)),
+        }
+    }
+    s.push('"');
+    s
+}
+
+/// 订阅类命令之后连接只接收推送，一直打印到连接断开
+fn is_streaming(parts: &[String]) -> bool {
+    ["SUBSCRIBE", "PSUBSCRIBE", "SSUBSCRIBE", "MONITOR"]
+        .iter()
+        .any(|c| parts[0].eq_ignore_ascii_case(c))
+}
+
+fn run_command(conn: &mut Client, parts: &[String]) -> Result<bool> {
+    let reply = conn.call(parts)?;
+    println!("{}", format_reply(&reply));
+    if is_streaming(parts) && !reply.is_error() {
+        loop {
+            println!("{}", format_reply(&conn.read_frame()?));
+        }
+    }
+    Ok(!reply.is_error())
+}
+
+/// 交互模式
+fn repl(args: &Args) -> Result<()> {
+    let mut conn = Some(open(args)?);
+    let mut editor = Editor::<()>::new();
+    let history = std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".crab-cage-cli-history"));
+    if let Some(path) = &history {
+        let _ = editor.load_history(path);
+    }
+    let prompt = format!("{}:{}> ", args.host, args.port);
+
+    loop {
+        let line = match editor.readline(&prompt) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted | ReadlineError::Eof) => break,
+            Err(e) => return Err(e.into()),
+        };
+        let parts = match split_inline(line.as_bytes()) {
+            Ok(parts) if parts.is_empty() => continue,
+            Ok(parts) => parts,
+            Err(_) => {
+                println!("Invalid argument(s)");
+                continue;
+            }
+        };
+        editor.add_history_entry(line.as_str());
+        if parts[0].eq_ignore_ascii_case("quit") || parts[0].eq_ignore_ascii_case("exit") {
+            break;
+        }
+
+        // 连接断开后下一条命令前重连
+        let result = match conn.as_mut() {
+            Some(c) => run_command(c, &parts),
+            None => open(args).and_then(|c| run_command(conn.insert(c), &parts)),
+        };
+        if let Err(e) = result {
+            println!("Error: {:#}", e);
+            conn = None;
+        }
+    }
+
+    if let Some(path) = &history {
+        let _ = editor.save_history(path);
+    }
+    Ok(())
+}
+
+/// 批量模式：写线程发送标准输入中的全部命令，主线程读取同样多的回复
+fn pipe(args: &Args) -> Result<ExitCode> {
+    let mut commands = Vec::new();
+    for line in io::stdin().lock().lines() {
+        let line = line?;
+        match split_inline(line.as_bytes()) {
+            Ok(parts) if parts.is_empty() => {}
+            Ok(parts) => commands.push(parts),
+            Err(e) => bail!("Invalid command {:?}: {}", line, e),
+        }
+    }
+
+    let mut conn = open(args)?;
+    let total = commands.len();
+    let stream = conn.stream().try_clone()?;
+    let writer = thread::spawn(move || -> io::Result<()> {
+        let mut out = BufWriter::new(stream);
+        for parts in &commands {
+            out.write_all(&encode(parts))?;
+        }
+        out.flush()
+    });
+
+    let mut errors = 0;
+    for _ in 0..total {
+        if let Frame::Error(e) = conn.read_frame()? {
+            eprintln!("{}", e);
+            errors += 1;
+        }
+    }
+    writer.join().expect("pipe writer panicked")?;
+
+    println!("All data transferred. errors: {}, replies: {}", errors, total);
+    Ok(if errors == 0 { ExitCode::SUCCESS } else { ExitCode::FAILURE })
+}
+
+fn main() -> Result<ExitCode> {
+    let args = Args::parse();
+    if args.pipe {
+        return pipe(&args);
+    }
+    if !args.command.is_empty() {
+        let mut conn = open(&args)?;
+        let ok = run_command(&mut conn, &args.command)?;
+        return Ok(if ok { ExitCode::SUCCESS } else { ExitCode::FAILURE });
+    }
+    repl(&args)?;
+    Ok(ExitCode::SUCCESS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_reply() {
+        assert_eq!(format_reply(&Frame::ok()), "OK");
+        assert_eq!(format_reply(&Frame::Integer(3)), "(integer) 3");
+        assert_eq!(format_reply(&Frame::bulk("a\"b\x01")), "\"a\\\"b\\x01\"");
+        assert_eq!(format_reply(&Frame::Array(vec![])), "(empty array)");
+
+        let nested = Frame::Array(vec![Frame::bulk("a"), Frame::Array(vec![Frame::Integer(1), Frame::Null])]);
+        assert_eq!(format_reply(&nested), "1) \"a\"\n2) 1) (integer) 1\n   2) (nil)");
+
+        let map = Frame::Map(vec![(Frame::bulk("k"), Frame::bulk("v")), (Frame::bulk("n"), Frame::Double(1.5))]);
+        assert_eq!(format_reply(&map), "1# \"k\" => \"v\"\n2# \"n\" => (double) 1.5");
+    }
+}
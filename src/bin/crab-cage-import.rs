@@ -0,0 +1,293 @@
+// src/bin/crab-cage-import.rs
+
+//! 从 Redis 迁移数据到 Crab-Cage
+//!
+//! 用 `SCAN` 遍历源 Redis 的全部 key，按 `TYPE` 读取值（GET / HGETALL / LRANGE / SMEMBERS）
+//! 与 `PTTL`，再以 SET / HSET / RPUSH / SADD 与 `PEXPIREAT` 写入正在运行的 crab-cage，
+//! 写入经过服务端的完整命令路径，因此会记入 AOF 并同步到从节点。
+//!
+//! 每批 SCAN 结果对源端和目标端各 pipeline 发送，减少往返。
+//! Crab-Cage 不支持的类型（zset、stream 等）与非 UTF-8 的 key / 值会被跳过并计数。
+//! hash / list / set 是逐元素追加的，应导入到空实例中，重复导入会使 list 元素重复。
+
+use std::process::ExitCode;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Result, bail};
+use clap::Parser;
+
+use crab_cage::client::Client;
+use crab_cage::protocol::Frame;
+
+/// crab-cage-import 启动参数
+#[derive(Parser, Debug)]
+#[command(author, version, about = "Import all keys from a Redis instance into crab-cage", long_about = None)]
+struct Args {
+    /// 源 Redis 地址
+    #[arg(long, default_value = "127.0.0.1:6379")]
+    from: String,
+
+    /// 源 Redis 的密码
+    #[arg(long)]
+    from_password: Option<String>,
+
+    /// 源 Redis 的数据库编号
+    #[arg(long, default_value_t = 0)]
+    db: u32,
+
+    /// 目标 crab-cage 地址
+    #[arg(long, default_value = "127.0.0.1:6380")]
+    to: String,
+
+    /// 目标 crab-cage 的密码
+    #[arg(long)]
+    to_password: Option<String>,
+
+    /// 只导入匹配该 glob 模式的 key
+    #[arg(long = "match")]
+    pattern: Option<String>,
+
+    /// 每次 SCAN 的 COUNT
+    #[arg(long, default_value_t = 1000)]
+    batch: usize,
+
+    /// 不保留 TTL，全部导入为永久 key
+    #[arg(long)]
+    no_ttl: bool,
+}
+
+/// 从源端读出的值
+#[derive(Debug, PartialEq)]
+enum Value {
+    String(String),
+    Hash(Vec<(String, String)>),
+    List(Vec<String>),
+    Set(Vec<String>),
+}
+
+/// 导入统计
+#[derive(Debug, Default)]
+struct Stats {
+    imported: u64,
+    /// 不支持的类型，按类型名计数
+    unsupported: std::collections::BTreeMap<String, u64>,
+    /// key 或值不是 UTF-8
+    binary: u64,
+    /// 读取期间被删除或已过期
+    vanished: u64,
+    /// 目标端返回错误的命令数
+    errors: u64,
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
+
+fn text(frame: Frame) -> Option<String> {
+    match frame {
+        Frame::Bulk(b) => String::from_utf8(b).ok(),
+        Frame::Simple(s) => Some(s),
+        _ => None,
+    }
+}
+
+fn texts(items: Vec<Frame>) -> Option<Vec<String>> {
+    items.into_iter().map(text).collect()
+}
+
+/// 把按类型读取的回复转换为值；含非 UTF-8 数据时为 `None`，回复与类型不符（如 key 已被删除）时原样返回 `Err`
+fn parse_value(type_name: &str, reply: Frame) -> Result<Option<Value>, Frame> {
+    let value = match (type_name, reply) {
+        ("string", Frame::Bulk(b)) => String::from_utf8(b).ok().map(Value::String),
+        ("hash", Frame::Array(items)) => texts(items).map(|flat| {
+            Value::Hash(flat.chunks_exact(2).map(|pair| (pair[0].clone(), pair[1].clone())).collect())
+        }),
+        ("hash", Frame::Map(pairs)) => pairs
+            .into_iter()
+            .map(|(f, v)| Some((text(f)?, text(v)?)))
+            .collect::<Option<Vec<_>>>()
+            .map(Value::Hash),
+        ("list", Frame::Array(items)) => texts(items).map(Value::List),
+        ("set", Frame::Array(items) | Frame::Set(items)) => texts(items).map(Value::Set),
+        (_, other) => return Err(other),
+    };
+    Ok(value)
+}
+
+/// 写入一个 key 的命令；空的聚合类型说明 key 已被删除，返回空列表
+fn write_commands(key: &str, value: &Value, expire_at_ms: Option<u64>) -> Vec<Vec<String>> {
+    let cmd = |parts: &[&str]| parts.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+    let mut commands = match value {
+        Value::String(s) => vec![cmd(&["SET", key, s])],
+        Value::Hash(fields) => fields.iter().map(|(f, v)| cmd(&["HSET", key, f, v])).collect(),
+        Value::List(items) => items.iter().map(|item| cmd(&["RPUSH", key, item])).collect(),
+        Value::Set(members) => members.iter().map(|m| cmd(&["SADD", key, m])).collect(),
+    };
+    if let Some(ts) = expire_at_ms
+        && !commands.is_empty()
+    {
+        commands.push(cmd(&["PEXPIREAT", key, &ts.to_string()]));
+    }
+    commands
+}
+
+fn read_command(type_name: &str, key: &str) -> Option<Vec<String>> {
+    let parts: &[&str] = match type_name {
+        "string" => &["GET", key],
+        "hash" => &["HGETALL", key],
+        "list" => &["LRANGE", key, "0", "-1"],
+        "set" => &["SMEMBERS", key],
+        _ => return None,
+    };
+    Some(parts.iter().map(|p| p.to_string()).collect())
+}
+
+/// 对同一连接 pipeline 发送一批命令并按序返回回复
+fn pipeline(conn: &mut Client, commands: &[Vec<String>]) -> Result<Vec<Frame>> {
+    conn.send_all(commands)?;
+    (0..commands.len()).map(|_| conn.read_frame()).collect()
+}
+
+/// 导入一批 SCAN 返回的 key
+fn import_batch(args: &Args, source: &mut Client, target: &mut Client, keys: Vec<String>, stats: &mut Stats) -> Result<()> {
+    let types = pipeline(source, &keys.iter().map(|k| vec!["TYPE".to_string(), k.clone()]).collect::<Vec<_>>())?;
+
+    let mut pending = Vec::new();
+    let mut reads = Vec::new();
+    for (key, type_reply) in keys.into_iter().zip(types) {
+        let type_name = text(type_reply).unwrap_or_default();
+        match read_command(&type_name, &key) {
+            Some(read) => {
+                reads.push(read);
+                reads.push(vec!["PTTL".to_string(), key.clone()]);
+                pending.push((key, type_name));
+            }
+            None if type_name == "none" => stats.vanished += 1,
+            None => *stats.unsupported.entry(type_name).or_default() += 1,
+        }
+    }
+    let mut replies = pipeline(source, &reads)?.into_iter();
+
+    let now = now_ms();
+    let mut writes = Vec::new();
+    for (key, type_name) in pending {
+        let (reply, pttl) = (replies.next().unwrap_or(Frame::Null), replies.next().unwrap_or(Frame::Null));
+        let expire_at_ms = match pttl {
+            Frame::Integer(-2) => {
+                stats.vanished += 1;
+                continue;
+            }
+            Frame::Integer(ms) if ms >= 0 && !args.no_ttl => Some(now + ms as u64),
+            _ => None,
+        };
+        match parse_value(&type_name, reply) {
+            Ok(Some(value)) => {
+                let commands = write_commands(&key, &value, expire_at_ms);
+                if commands.is_empty() {
+                    stats.vanished += 1;
+                } else {
+                    writes.extend(commands);
+                    stats.imported += 1;
+                }
+            }
+            Ok(None) => stats.binary += 1,
+            Err(Frame::Error(e)) => bail!("reading '{}' from source failed: {}", key, e),
+            Err(_) => stats.vanished += 1,
+        }
+    }
+
+    for (reply, command) in pipeline(target, &writes)?.into_iter().zip(&writes) {
+        if let Frame::Error(e) = reply {
+            eprintln!("{} {}: {}", command[0], command[1], e);
+            stats.errors += 1;
+        }
+    }
+    Ok(())
+}
+
+fn connect(addr: &str, password: Option<&str>) -> Result<Client> {
+    let mut conn = Client::connect(addr)?;
+    if let Some(password) = password {
+        conn.auth(None, password)?;
+    }
+    Ok(conn)
+}
+
+fn main() -> Result<ExitCode> {
+    let args = Args::parse();
+    let mut source = connect(&args.from, args.from_password.as_deref())?;
+    let mut target = connect(&args.to, args.to_password.as_deref())?;
+    if args.db != 0
+        && let Frame::Error(e) = source.call(&["SELECT", &args.db.to_string()])?
+    {
+        bail!("SELECT {} failed: {}", args.db, e);
+    }
+
+    let mut stats = Stats::default();
+    let mut cursor = "0".to_string();
+    loop {
+        let mut scan = vec!["SCAN".to_string(), cursor, "COUNT".to_string(), args.batch.to_string()];
+        if let Some(pattern) = &args.pattern {
+            scan.extend(["MATCH".to_string(), pattern.clone()]);
+        }
+        let (next, keys) = match source.call(&scan)? {
+            Frame::Array(mut reply) if reply.len() == 2 => match (reply.remove(0), reply.remove(0)) {
+                (next, Frame::Array(keys)) => (text(next).unwrap_or_default(), keys),
+                _ => bail!("unexpected SCAN reply"),
+            },
+            Frame::Error(e) => bail!("SCAN failed: {}", e),
+            _ => bail!("unexpected SCAN reply"),
+        };
+
+        let mut batch = Vec::with_capacity(keys.len());
+        for key in keys {
+            match text(key) {
+                Some(key) => batch.push(key),
+                None => stats.binary += 1,
+            }
+        }
+        import_batch(&args, &mut source, &mut target, batch, &mut stats)?;
+
+        if next == "0" || next.is_empty() {
+            break;
+        }
+        cursor = next;
+    }
+
+    println!("Imported {} keys from {} into {}.", stats.imported, args.from, args.to);
+    for (type_name, count) in &stats.unsupported {
+        println!("  skipped {} keys of unsupported type '{}'", count, type_name);
+    }
+    if stats.binary > 0 {
+        println!("  skipped {} keys with non UTF-8 key or value", stats.binary);
+    }
+    if stats.vanished > 0 {
+        println!("  skipped {} keys deleted or expired during the import", stats.vanished);
+    }
+    if stats.errors > 0 {
+        println!("  {} commands were rejected by crab-cage", stats.errors);
+        return Ok(ExitCode::FAILURE);
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_value_and_write_commands() {
+        let hash = Frame::Array(vec![Frame::bulk("f1"), Frame::bulk("a"), Frame::bulk("f2"), Frame::bulk("b")]);
+        let value = parse_value("hash", hash).unwrap().unwrap();
+        assert_eq!(value, Value::Hash(vec![("f1".into(), "a".into()), ("f2".into(), "b".into())]));
+
+        let commands = write_commands("h", &value, Some(42));
+        assert_eq!(commands.len(), 3);
+        assert_eq!(commands[0], ["HSET", "h", "f1", "a"]);
+        assert_eq!(commands[2], ["PEXPIREAT", "h", "42"]);
+
+        assert_eq!(parse_value("string", Frame::Bulk(vec![0xff])).unwrap(), None);
+        assert!(parse_value("string", Frame::Null).is_err());
+        assert!(write_commands("l", &Value::List(vec![]), Some(1)).is_empty());
+    }
+}
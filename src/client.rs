@@ -0,0 +1,93 @@
+// src/client.rs
+
+//! 阻塞式 RESP 客户端，供命令行工具（`crab-cage-cli`、`crab-cage-import`）连接服务端
+//!
+//! 请求编码为 RESP 数组，回复用 [`Frame::decode`] 解析，RESP2 / RESP3 都支持；
+//! 也可以用来连接 Redis。
+
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+
+use anyhow::{Context, Result, bail};
+
+use crate::protocol::Frame;
+
+/// 一个到服务端的连接
+pub struct Client {
+    stream: TcpStream,
+    buf: Vec<u8>,
+}
+
+impl Client {
+    pub fn connect<A: ToSocketAddrs + std::fmt::Display>(addr: A) -> Result<Self> {
+        let stream = TcpStream::connect(&addr).with_context(|| format!("Could not connect to {}", addr))?;
+        stream.set_nodelay(true)?;
+        Ok(Client { stream, buf: Vec::new() })
+    }
+
+    /// 用密码（及可选的 ACL 用户名）认证
+    pub fn auth(&mut self, user: Option<&str>, password: &str) -> Result<()> {
+        let mut parts = vec!["AUTH"];
+        parts.extend(user);
+        parts.push(password);
+        match self.call(&parts)? {
+            Frame::Error(e) => bail!("AUTH failed: {}", e),
+            _ => Ok(()),
+        }
+    }
+
+    /// 底层连接，可 `try_clone` 后在另一个线程中写入
+    pub fn stream(&self) -> &TcpStream {
+        &self.stream
+    }
+
+    /// 发送一条命令，不等待回复
+    pub fn send<S: AsRef<str>>(&mut self, parts: &[S]) -> Result<()> {
+        self.stream.write_all(&encode(parts))?;
+        Ok(())
+    }
+
+    /// 一次发送多条命令（pipeline），之后需读取同样多条回复
+    pub fn send_all<S: AsRef<str>>(&mut self, commands: &[Vec<S>]) -> Result<()> {
+        let mut out = Vec::new();
+        for parts in commands {
+            out.extend(encode(parts));
+        }
+        self.stream.write_all(&out)?;
+        Ok(())
+    }
+
+    /// 读取下一条回复
+    pub fn read_frame(&mut self) -> Result<Frame> {
+        loop {
+            if let Some((frame, used)) = Frame::decode(&self.buf) {
+                self.buf.drain(..used);
+                return Ok(frame);
+            }
+            let mut chunk = [0u8; 16 * 1024];
+            let n = self.stream.read(&mut chunk)?;
+            if n == 0 {
+                bail!("Server closed the connection");
+            }
+            self.buf.extend_from_slice(&chunk[..n]);
+        }
+    }
+
+    /// 发送一条命令并读取回复
+    pub fn call<S: AsRef<str>>(&mut self, parts: &[S]) -> Result<Frame> {
+        self.send(parts)?;
+        self.read_frame()
+    }
+}
+
+/// 把参数编码为 RESP 数组
+pub fn encode<S: AsRef<str>>(parts: &[S]) -> Vec<u8> {
+    let mut out = format!("*{}\r\n", parts.len()).into_bytes();
+    for part in parts {
+        let part = part.as_ref();
+        out.extend_from_slice(format!("${}\r\n", part.len()).as_bytes());
+        out.extend_from_slice(part.as_bytes());
+        out.extend_from_slice(b"\r\n");
+    }
+    out
+}
@@ -0,0 +1,58 @@
+// src/clock.rs
+
+//! 时钟：过期判断读取的当前时间
+//!
+//! 存储引擎持有一个 `Clock`（见 `KvEngine::clock`），默认是系统时钟；
+//! 测试换成 `MockClock` 后可以直接拨动时间，无需真的睡眠等待 key 过期。
+
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+pub trait Clock: Send + Sync {
+    /// 当前的 UNIX 毫秒
+    fn now_ms(&self) -> u64;
+}
+
+/// 系统时钟
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_ms(&self) -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+    }
+}
+
+/// 手动拨动的时钟，供测试使用
+#[derive(Debug)]
+pub struct MockClock {
+    now_ms: AtomicU64,
+}
+
+impl MockClock {
+    /// 从指定的 UNIX 毫秒开始
+    pub fn new(now_ms: u64) -> Self {
+        MockClock { now_ms: AtomicU64::new(now_ms) }
+    }
+
+    /// 从当前的系统时间开始
+    pub fn starting_now() -> Self {
+        Self::new(SystemClock.now_ms())
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.now_ms.fetch_add(by.as_millis() as u64, Ordering::SeqCst);
+    }
+
+    pub fn set(&self, now_ms: u64) {
+        self.now_ms.store(now_ms, Ordering::SeqCst);
+    }
+}
+
+impl Clock for MockClock {
+    fn now_ms(&self) -> u64 {
+        self.now_ms.load(Ordering::SeqCst)
+    }
+}
@@ -0,0 +1,582 @@
+// src/cluster.rs
+
+//! 集群：哈希槽路由与槽位迁移
+//!
+//! key 按 CRC16 分配到 16384 个哈希槽之一，key 中第一对非空 `{...}` 内的
+//! hash tag 决定槽位，使相关的 key 落在同一节点。
+//!
+//! 节点之间没有 gossip：节点表与槽位归属由运维通过 `CLUSTER MEET` /
+//! `CLUSTER SETSLOT` 在每个节点上分别设置，保存在数据目录下的 `nodes.conf`。
+//! 首次启动时本节点负责全部槽位，单个节点即可作为集群使用。
+//!
+//! 命令路由：
+//! - 槽位属于其他节点：`-MOVED <slot> <ip>:<port>`
+//! - 槽位正在迁出（MIGRATING）且 key 已不在本节点：`-ASK <slot> <ip>:<port>`
+//! - 槽位正在迁入（IMPORTING）：只执行紧跟在 `ASKING` 之后的命令
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+use crate::engine::KvEngine;
+use crate::error::CommandError;
+use crate::keys;
+use crate::persistence::{aof, dataset};
+use crate::protocol::Frame;
+use crate::replication::new_replid;
+use crate::types;
+
+/// 哈希槽个数
+pub const SLOTS: usize = 16384;
+
+/// CLUSTER MEET 等待对端回复的时间
+const MEET_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// CRC16（XMODEM），与 Redis Cluster 相同
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+/// key 所在的哈希槽
+pub fn key_hash_slot(key: &[u8]) -> u16 {
+    let tagged = key.iter().position(|&b| b == b'{').and_then(|open| {
+        let rest = &key[open + 1..];
+        rest.iter().position(|&b| b == b'}').filter(|&len| len > 0).map(|len| &rest[..len])
+    });
+    crc16(tagged.unwrap_or(key)) % SLOTS as u16
+}
+
+/// 集群中的一个节点
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Node {
+    pub id: String,
+    pub host: String,
+    pub port: u16,
+}
+
+impl Node {
+    fn addr(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+}
+
+/// 节点表与槽位归属；节点以其在 `nodes` 中的下标引用，`nodes[0]` 是本节点
+struct State {
+    nodes: Vec<Node>,
+    slots: Vec<Option<usize>>,
+    /// 正在迁出的槽位 -> 目标节点
+    migrating: BTreeMap<u16, usize>,
+    /// 正在迁入的槽位 -> 来源节点
+    importing: BTreeMap<u16, usize>,
+}
+
+/// `nodes.conf` 的内容，槽位按连续区间保存
+#[derive(Serialize, Deserialize)]
+struct SavedState {
+    nodes: Vec<Node>,
+    slots: Vec<(u16, u16, String)>,
+    migrating: BTreeMap<u16, String>,
+    importing: BTreeMap<u16, String>,
+}
+
+impl State {
+    fn find(&self, id: &str) -> Result<usize> {
+        match self.nodes.iter().position(|node| node.id == id) {
+            Some(index) => Ok(index),
+            None => bail!("I don't know about node {}", id),
+        }
+    }
+
+    /// 连续且属于同一节点的槽位区间：(起, 止, 节点)
+    fn ranges(&self) -> Vec<(u16, u16, usize)> {
+        let mut ranges: Vec<(u16, u16, usize)> = Vec::new();
+        for (slot, owner) in self.slots.iter().enumerate() {
+            let Some(owner) = *owner else { continue };
+            match ranges.last_mut() {
+                Some((_, end, last)) if *last == owner && *end as usize + 1 == slot => *end = slot as u16,
+                _ => ranges.push((slot as u16, slot as u16, owner)),
+            }
+        }
+        ranges
+    }
+
+    fn to_saved(&self) -> SavedState {
+        let id = |index: &usize| self.nodes[*index].id.clone();
+        SavedState {
+            nodes: self.nodes.clone(),
+            slots: self.ranges().into_iter().map(|(start, end, owner)| (start, end, id(&owner))).collect(),
+            migrating: self.migrating.iter().map(|(slot, node)| (*slot, id(node))).collect(),
+            importing: self.importing.iter().map(|(slot, node)| (*slot, id(node))).collect(),
+        }
+    }
+
+    fn from_saved(saved: SavedState) -> Result<State> {
+        let mut state = State {
+            nodes: saved.nodes,
+            slots: vec![None; SLOTS],
+            migrating: BTreeMap::new(),
+            importing: BTreeMap::new(),
+        };
+        if state.nodes.is_empty() {
+            bail!("no nodes");
+        }
+        for (start, end, id) in saved.slots {
+            let owner = state.find(&id)?;
+            for slot in start..=end.min(SLOTS as u16 - 1) {
+                state.slots[slot as usize] = Some(owner);
+            }
+        }
+        for (slot, id) in saved.migrating {
+            let node = state.find(&id)?;
+            state.migrating.insert(slot, node);
+        }
+        for (slot, id) in saved.importing {
+            let node = state.find(&id)?;
+            state.importing.insert(slot, node);
+        }
+        Ok(state)
+    }
+}
+
+/// 集群状态，整个进程一份
+pub struct Cluster {
+    /// `nodes.conf` 路径
+    path: PathBuf,
+    state: Mutex<State>,
+}
+
+impl Cluster {
+    /// 读取 `nodes.conf`，不存在时生成新的节点 ID 并负责全部槽位；
+    /// 本节点的地址总是以 `host` / `port` 为准
+    pub fn open(path: PathBuf, host: String, port: u16) -> Result<Self> {
+        let state = if path.exists() {
+            let data = fs::read_to_string(&path).with_context(|| format!("Failed to read {:?}", path))?;
+            let saved: SavedState =
+                serde_json::from_str(&data).with_context(|| format!("Failed to parse {:?}", path))?;
+            let mut state = State::from_saved(saved).with_context(|| format!("Invalid cluster config {:?}", path))?;
+            state.nodes[0].host = host;
+            state.nodes[0].port = port;
+            state
+        } else {
+            State {
+                nodes: vec![Node { id: new_replid(), host, port }],
+                slots: vec![Some(0); SLOTS],
+                migrating: BTreeMap::new(),
+                importing: BTreeMap::new(),
+            }
+        };
+        let cluster = Cluster { path, state: Mutex::new(state) };
+        cluster.save(&cluster.state.lock().unwrap())?;
+        Ok(cluster)
+    }
+
+    pub fn myid(&self) -> String {
+        self.state.lock().unwrap().nodes[0].id.clone()
+    }
+
+    fn save(&self, state: &State) -> Result<()> {
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(&state.to_saved())?)?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+
+    /// 检查命令的 key 是否由本节点负责，需要重定向时返回错误回复
+    ///
+    /// `asking` 表示上一条命令是 ASKING
+    pub fn route<E: KvEngine, K: AsRef<[u8]>>(&self, keys: &[K], db: &E, asking: bool) -> Option<Frame> {
+        let slot = key_hash_slot(keys.first()?.as_ref());
+        if keys.iter().any(|key| key_hash_slot(key.as_ref()) != slot) {
+            return Some(Frame::error("CROSSSLOT Keys in request don't hash to the same slot"));
+        }
+
+        let target = {
+            let state = self.state.lock().unwrap();
+            match state.slots[slot as usize] {
+                None => return Some(Frame::error("CLUSTERDOWN Hash slot not served")),
+                Some(0) => state.nodes[*state.migrating.get(&slot)?].addr(),
+                Some(_) if asking && state.importing.contains_key(&slot) => return None,
+                Some(owner) => return Some(Frame::error(format!("MOVED {} {}", slot, state.nodes[owner].addr()))),
+            }
+        };
+
+        // 迁出中：key 都还在本节点时照常执行，都已迁走时让客户端去目标节点
+        let existing = keys.iter().filter(|key| key_exists(db, key.as_ref())).count();
+        if existing == keys.len() {
+            None
+        } else if existing == 0 {
+            Some(Frame::error(format!("ASK {} {}", slot, target)))
+        } else {
+            Some(Frame::error("TRYAGAIN Multiple keys request during rehashing of slot"))
+        }
+    }
+
+    /// CLUSTER 命令
+    pub async fn command<E: KvEngine>(&self, args: &[String], db: &E) -> Frame {
+        let sub = args[0].to_uppercase();
+        match (sub.as_str(), args.len()) {
+            ("INFO", 1) => Frame::bulk(self.info()),
+            ("MYID", 1) => Frame::bulk(self.myid()),
+            ("NODES", 1) => Frame::bulk(self.nodes()),
+            ("SLOTS", 1) => self.slots(),
+            ("SHARDS", 1) => self.shards(),
+            ("KEYSLOT", 2) => Frame::Integer(key_hash_slot(args[1].as_bytes()) as i64),
+            ("COUNTKEYSINSLOT", 2) => match parse_slot(&args[1]) {
+                Ok(slot) => keys_in_slot(db, slot, usize::MAX).map_or_else(
+                    |e| CommandError::from(e).into(),
+                    |keys| Frame::Integer(keys.len() as i64),
+                ),
+                Err(e) => e,
+            },
+            ("GETKEYSINSLOT", 3) => {
+                let slot = match parse_slot(&args[1]) {
+                    Ok(slot) => slot,
+                    Err(e) => return e,
+                };
+                let Ok(count) = args[2].parse::<usize>() else {
+                    return Frame::error("ERR Invalid number of keys");
+                };
+                match keys_in_slot(db, slot, count) {
+                    Ok(keys) => Frame::Array(keys.into_iter().map(Frame::bulk).collect()),
+                    Err(e) => CommandError::from(e).into(),
+                }
+            }
+            ("MEET", 3) => self.meet(&args[1], &args[2]).await,
+            ("SETSLOT", 3 | 4) => self.setslot(&args[1..], db),
+            _ => Frame::error(format!(
+                "ERR unknown subcommand or wrong number of arguments for '{}'. Try CLUSTER HELP.",
+                args[0]
+            )),
+        }
+    }
+
+    fn info(&self) -> String {
+        let state = self.state.lock().unwrap();
+        let assigned = state.slots.iter().filter(|owner| owner.is_some()).count();
+        let mut owners: Vec<usize> = state.slots.iter().flatten().copied().collect();
+        owners.sort_unstable();
+        owners.dedup();
+        let mut out = String::new();
+        out.push_str(&format!("cluster_state:{}\n", if assigned == SLOTS { "ok" } else { "fail" }));
+        out.push_str(&format!("cluster_slots_assigned:{}\n", assigned));
+        out.push_str(&format!("cluster_slots_ok:{}\n", assigned));
+        out.push_str("cluster_slots_pfail:0\n");
+        out.push_str("cluster_slots_fail:0\n");
+        out.push_str(&format!("cluster_known_nodes:{}\n", state.nodes.len()));
+        out.push_str(&format!("cluster_size:{}\n", owners.len()));
+        out.push_str("cluster_current_epoch:0\n");
+        out.push_str("cluster_my_epoch:0\n");
+        out
+    }
+
+    /// CLUSTER NODES：每个节点一行，格式与 Redis 相同
+    fn nodes(&self) -> String {
+        let state = self.state.lock().unwrap();
+        let ranges = state.ranges();
+        let mut out = String::new();
+        for (index, node) in state.nodes.iter().enumerate() {
+            let flags = if index == 0 { "myself,master" } else { "master" };
+            out.push_str(&format!(
+                "{} {}@{} {} - 0 0 0 connected",
+                node.id,
+                node.addr(),
+                node.port as u32 + 10000,
+                flags
+            ));
+            for (start, end, _) in ranges.iter().filter(|(_, _, owner)| *owner == index) {
+                if start == end {
+                    out.push_str(&format!(" {}", start));
+                } else {
+                    out.push_str(&format!(" {}-{}", start, end));
+                }
+            }
+            if index == 0 {
+                for (slot, target) in &state.migrating {
+                    out.push_str(&format!(" [{}->-{}]", slot, state.nodes[*target].id));
+                }
+                for (slot, source) in &state.importing {
+                    out.push_str(&format!(" [{}-<-{}]", slot, state.nodes[*source].id));
+                }
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    /// CLUSTER SLOTS：`[起, 止, [ip, port, id]]`
+    fn slots(&self) -> Frame {
+        let state = self.state.lock().unwrap();
+        let ranges = state
+            .ranges()
+            .into_iter()
+            .map(|(start, end, owner)| {
+                let node = &state.nodes[owner];
+                Frame::Array(vec![
+                    Frame::Integer(start as i64),
+                    Frame::Integer(end as i64),
+                    Frame::Array(vec![
+                        Frame::bulk(node.host.as_str()),
+                        Frame::Integer(node.port as i64),
+                        Frame::bulk(node.id.as_str()),
+                    ]),
+                ])
+            })
+            .collect();
+        Frame::Array(ranges)
+    }
+
+    /// CLUSTER SHARDS：每个负责槽位的节点是一个分片
+    fn shards(&self) -> Frame {
+        let state = self.state.lock().unwrap();
+        let ranges = state.ranges();
+        let mut shards = Vec::new();
+        for (index, node) in state.nodes.iter().enumerate() {
+            let slots: Vec<Frame> = ranges
+                .iter()
+                .filter(|(_, _, owner)| *owner == index)
+                .flat_map(|(start, end, _)| [Frame::Integer(*start as i64), Frame::Integer(*end as i64)])
+                .collect();
+            if slots.is_empty() {
+                continue;
+            }
+            let node = Frame::Map(vec![
+                (Frame::bulk("id"), Frame::bulk(node.id.as_str())),
+                (Frame::bulk("port"), Frame::Integer(node.port as i64)),
+                (Frame::bulk("ip"), Frame::bulk(node.host.as_str())),
+                (Frame::bulk("endpoint"), Frame::bulk(node.host.as_str())),
+                (Frame::bulk("role"), Frame::bulk("master")),
+                (Frame::bulk("health"), Frame::bulk("online")),
+            ]);
+            shards.push(Frame::Map(vec![
+                (Frame::bulk("slots"), Frame::Array(slots)),
+                (Frame::bulk("nodes"), Frame::Array(vec![node])),
+            ]));
+        }
+        Frame::Array(shards)
+    }
+
+    /// CLUSTER MEET ip port：向对端询问节点 ID 并加入节点表
+    async fn meet(&self, host: &str, port: &str) -> Frame {
+        let Ok(port) = port.parse::<u16>() else {
+            return Frame::error(format!("ERR Invalid node address specified: {}:{}", host, port));
+        };
+        let id = match tokio::time::timeout(MEET_TIMEOUT, fetch_node_id(host, port)).await {
+            Ok(Ok(id)) => id,
+            Ok(Err(e)) => return Frame::error(format!("ERR Failed to meet {}:{}: {}", host, port, e)),
+            Err(_) => return Frame::error(format!("ERR Failed to meet {}:{}: timed out", host, port)),
+        };
+
+        let mut state = self.state.lock().unwrap();
+        match state.nodes.iter_mut().find(|node| node.id == id) {
+            Some(node) => {
+                node.host = host.to_string();
+                node.port = port;
+            }
+            None => state.nodes.push(Node { id, host: host.to_string(), port }),
+        }
+        match self.save(&state) {
+            Ok(()) => Frame::ok(),
+            Err(e) => Frame::error(format!("ERR Failed to save cluster config: {}", e)),
+        }
+    }
+
+    /// CLUSTER SETSLOT slot MIGRATING|IMPORTING|NODE node-id / CLUSTER SETSLOT slot STABLE
+    fn setslot<E: KvEngine>(&self, args: &[String], db: &E) -> Frame {
+        let slot = match parse_slot(&args[0]) {
+            Ok(slot) => slot,
+            Err(e) => return e,
+        };
+        let mut state = self.state.lock().unwrap();
+        let owner = state.slots[slot as usize];
+        let result = match (args[1].to_uppercase().as_str(), args.get(2)) {
+            ("STABLE", None) => {
+                state.migrating.remove(&slot);
+                state.importing.remove(&slot);
+                Ok(())
+            }
+            ("MIGRATING", Some(id)) => state.find(id).and_then(|target| {
+                if owner != Some(0) {
+                    bail!("I'm not the owner of hash slot {}", slot);
+                }
+                if target == 0 {
+                    bail!("I can't migrate to myself");
+                }
+                state.migrating.insert(slot, target);
+                Ok(())
+            }),
+            ("IMPORTING", Some(id)) => state.find(id).and_then(|source| {
+                if owner == Some(0) {
+                    bail!("I'm already the owner of hash slot {}", slot);
+                }
+                if source == 0 {
+                    bail!("I can't import from myself");
+                }
+                state.importing.insert(slot, source);
+                Ok(())
+            }),
+            ("NODE", Some(id)) => state.find(id).and_then(|node| {
+                if owner == Some(0) && node != 0 && !keys_in_slot(db, slot, 1)?.is_empty() {
+                    bail!(
+                        "Can't assign hashslot {} to a different node while I still hold keys for this hash slot.",
+                        slot
+                    );
+                }
+                // 迁移完成：槽位交给目标节点，或迁入的槽位归本节点
+                state.slots[slot as usize] = Some(node);
+                state.migrating.remove(&slot);
+                state.importing.remove(&slot);
+                Ok(())
+            }),
+            _ => return Frame::error("ERR syntax error"),
+        };
+        match result.and_then(|()| self.save(&state)) {
+            Ok(()) => Frame::ok(),
+            Err(e) => CommandError::from(e).into(),
+        }
+    }
+}
+
+fn parse_slot(arg: &str) -> Result<u16, Frame> {
+    match arg.parse::<u16>() {
+        Ok(slot) if (slot as usize) < SLOTS => Ok(slot),
+        _ => Err(Frame::error("ERR Invalid or out of range slot")),
+    }
+}
+
+/// key 在本节点是否存在（任一类型），已过期的视为不存在
+fn key_exists<E: KvEngine>(db: &E, key: &[u8]) -> bool {
+    let expired = db
+        .get(&keys::expire(key))
+        .ok()
+        .flatten()
+        .and_then(|ts| ts.as_ref().try_into().ok().map(u64::from_be_bytes))
+        .is_some_and(|ts| ts <= db.clock().now_ms());
+    if expired {
+        return false;
+    }
+    types::exists(db, key).unwrap_or(false)
+}
+
+/// 槽位中的 key，最多 `count` 个
+fn keys_in_slot<E: KvEngine>(db: &E, slot: u16, count: usize) -> Result<Vec<Vec<u8>>> {
+    let mut keys: Vec<Vec<u8>> = dataset::scan(db)?
+        .into_iter()
+        .map(|entry| entry.key)
+        .filter(|key| key_hash_slot(key) == slot)
+        .collect();
+    keys.dedup();
+    keys.truncate(count);
+    Ok(keys)
+}
+
+/// 通过 CLUSTER MYID 取得对端的节点 ID
+async fn fetch_node_id(host: &str, port: u16) -> Result<String> {
+    let mut stream = BufReader::new(TcpStream::connect((host, port)).await?);
+    stream.get_mut().write_all(&aof::encode(&["CLUSTER", "MYID"])).await?;
+    let mut line = String::new();
+    stream.read_line(&mut line).await?;
+    if !line.starts_with('$') {
+        bail!("unexpected reply {:?}", line.trim_end());
+    }
+    line.clear();
+    stream.read_line(&mut line).await?;
+    Ok(line.trim_end().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::execute_non_txn_command;
+
+    fn cmd(parts: &[&str]) -> Vec<String> {
+        parts.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn args(parts: &[&str]) -> Vec<bytes::Bytes> {
+        parts.iter().map(|s| bytes::Bytes::copy_from_slice(s.as_bytes())).collect()
+    }
+
+    /// 本节点之外再登记一个节点，返回其 ID
+    fn add_node(cluster: &Cluster) -> String {
+        let id = "b".repeat(40);
+        let node = Node { id: id.clone(), host: "10.0.0.2".into(), port: 7002 };
+        cluster.state.lock().unwrap().nodes.push(node);
+        id
+    }
+
+    #[test]
+    fn test_key_hash_slot() {
+        assert_eq!(crc16(b"123456789"), 0x31c3);
+        assert_eq!(key_hash_slot(b"foo"), 12182);
+        assert_eq!(key_hash_slot(b"{user1000}.following"), key_hash_slot(b"user1000"));
+        // 空的 {} 不算 hash tag
+        assert_eq!(key_hash_slot(b"{}foo"), crc16(b"{}foo") % SLOTS as u16);
+    }
+
+    #[tokio::test]
+    async fn test_redirects_during_migration() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let db = sled::Config::new().temporary(true).open()?;
+        let cluster = Cluster::open(dir.path().join("nodes.conf"), "127.0.0.1".into(), 7001)?;
+        let other = add_node(&cluster);
+        let slot = key_hash_slot(b"a").to_string();
+        execute_non_txn_command("SET", &args(&["SET", "a", "1"]), &db);
+
+        // 本节点负责全部槽位
+        assert_eq!(cluster.route(&[b"a"], &db, false), None);
+        assert!(cluster.route(&[b"a", b"b"], &db, false).is_some_and(|e| e.is_error()));
+
+        // 迁出中：仍在本节点的 key 照常执行，已迁走的 key 返回 ASK
+        let migrating = cmd(&["SETSLOT", &slot, "MIGRATING", &other]);
+        assert_eq!(cluster.command(&migrating, &db).await, Frame::ok());
+        assert_eq!(cluster.route(&[b"a"], &db, false), None);
+        assert_eq!(cluster.route(&[b"{a}x"], &db, false), Some(Frame::error(format!("ASK {} 10.0.0.2:7002", slot))));
+
+        // 还有 key 时不能把槽位交出去
+        let node = cmd(&["SETSLOT", &slot, "NODE", &other]);
+        assert!(cluster.command(&node, &db).await.is_error());
+        execute_non_txn_command("DEL", &args(&["DEL", "a"]), &db);
+        assert_eq!(cluster.command(&node, &db).await, Frame::ok());
+        assert_eq!(cluster.route(&[b"a"], &db, false), Some(Frame::error(format!("MOVED {} 10.0.0.2:7002", slot))));
+
+        // 迁入中：只接受 ASKING 之后的命令
+        let importing = cmd(&["SETSLOT", &slot, "IMPORTING", &other]);
+        assert_eq!(cluster.command(&importing, &db).await, Frame::ok());
+        assert_eq!(cluster.route(&[b"a"], &db, true), None);
+        assert!(cluster.route(&[b"a"], &db, false).is_some());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_config_survives_restart() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let db = sled::Config::new().temporary(true).open()?;
+        let path = dir.path().join("nodes.conf");
+        let cluster = Cluster::open(path.clone(), "127.0.0.1".into(), 7001)?;
+        let other = add_node(&cluster);
+        let setslot = cmd(&["SETSLOT", "100", "NODE", &other]);
+        assert_eq!(cluster.command(&setslot, &db).await, Frame::ok());
+        let nodes = cluster.nodes();
+
+        let reopened = Cluster::open(path, "127.0.0.1".into(), 7001)?;
+        assert_eq!(reopened.myid(), cluster.myid());
+        assert_eq!(reopened.nodes(), nodes);
+        assert!(nodes.contains(&format!("{} 10.0.0.2:7002@17002 master - 0 0 0 connected 100\n", other)));
+        assert!(nodes.contains("myself,master - 0 0 0 connected 0-99 101-16383\n"));
+        Ok(())
+    }
+}
@@ -0,0 +1,489 @@
+// src/command.rs
+
+//! 命令元数据表：名称、参数个数、标志、key 位置、ACL 类别与文档
+//!
+//! 服务端据此做参数个数校验，ACL 据此判断命令类别与 key，
+//! COMMAND / COMMAND INFO / COMMAND DOCS 直接把这张表返回给客户端。
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::str::FromStr;
+
+use anyhow::{Result, bail};
+use bytes::Bytes;
+
+use crate::plugin;
+use crate::protocol::Frame;
+
+/// 单条命令的元数据
+#[derive(Debug)]
+pub struct CommandSpec {
+    /// 大写命令名
+    pub name: &'static str,
+    /// 正数表示参数个数（含命令名）必须相等，负数表示至少为其绝对值
+    pub arity: i64,
+    pub flags: &'static [&'static str],
+    /// 第一个 / 最后一个 key 的位置与步长，-1 表示直到最后一个参数
+    pub first_key: i64,
+    pub last_key: i64,
+    pub step: i64,
+    /// ACL 类别（不带 @）
+    pub categories: &'static [&'static str],
+    pub group: &'static str,
+    pub summary: &'static str,
+}
+
+const fn spec(
+    name: &'static str,
+    arity: i64,
+    flags: &'static [&'static str],
+    keys: (i64, i64, i64),
+    categories: &'static [&'static str],
+    group: &'static str,
+    summary: &'static str,
+) -> CommandSpec {
+    CommandSpec {
+        name,
+        arity,
+        flags,
+        first_key: keys.0,
+        last_key: keys.1,
+        step: keys.2,
+        categories,
+        group,
+        summary,
+    }
+}
+
+const NO_KEYS: (i64, i64, i64) = (0, 0, 0);
+const ONE_KEY: (i64, i64, i64) = (1, 1, 1);
+
+/// 全部命令
+pub static COMMANDS: &[CommandSpec] = &[
+    // --- String ---
+    spec("GET", 2, &["readonly", "fast"], ONE_KEY, &["read", "string", "fast"], "string", "Returns the string value of a key."),
+    spec("SET", 3, &["write", "denyoom"], ONE_KEY, &["write", "string", "slow"], "string", "Sets the string value of a key."),
+    spec("DEL", 2, &["write"], ONE_KEY, &["write", "keyspace", "slow"], "generic", "Deletes a key."),
+    spec("GETSET", 3, &["write", "denyoom", "fast"], ONE_KEY, &["write", "string", "fast"], "string", "Returns the previous string value of a key after setting it to a new value."),
+    spec("INCR", 2, &["write", "denyoom", "fast"], ONE_KEY, &["write", "string", "fast"], "string", "Increments the integer value of a key by one."),
+    spec("DECR", 2, &["write", "denyoom", "fast"], ONE_KEY, &["write", "string", "fast"], "string", "Decrements the integer value of a key by one."),
+    // --- Hash ---
+    spec("HSET", 4, &["write", "denyoom", "fast"], ONE_KEY, &["write", "hash", "fast"], "hash", "Sets the value of a field in a hash."),
+    spec("HGET", 3, &["readonly", "fast"], ONE_KEY, &["read", "hash", "fast"], "hash", "Returns the value of a field in a hash."),
+    spec("HDEL", 3, &["write", "fast"], ONE_KEY, &["write", "hash", "fast"], "hash", "Deletes a field from a hash."),
+    spec("HKEYS", 2, &["readonly"], ONE_KEY, &["read", "hash", "slow"], "hash", "Returns all fields in a hash."),
+    spec("HVALS", 2, &["readonly"], ONE_KEY, &["read", "hash", "slow"], "hash", "Returns all values in a hash."),
+    spec("HGETALL", 2, &["readonly"], ONE_KEY, &["read", "hash", "slow"], "hash", "Returns all fields and values in a hash."),
+    // --- List ---
+    spec("LPUSH", 3, &["write", "denyoom", "fast"], ONE_KEY, &["write", "list", "fast"], "list", "Prepends an element to a list."),
+    spec("RPUSH", 3, &["write", "denyoom", "fast"], ONE_KEY, &["write", "list", "fast"], "list", "Appends an element to a list."),
+    spec("LPOP", 2, &["write", "fast"], ONE_KEY, &["write", "list", "fast"], "list", "Removes and returns the first element of a list."),
+    spec("RPOP", 2, &["write", "fast"], ONE_KEY, &["write", "list", "fast"], "list", "Removes and returns the last element of a list."),
+    spec("LRANGE", 4, &["readonly"], ONE_KEY, &["read", "list", "slow"], "list", "Returns a range of elements from a list."),
+    spec("LMPOP", -4, &["write", "movablekeys"], NO_KEYS, &["write", "list", "slow"], "list", "Returns multiple elements from the first non-empty list."),
+    spec("BLMPOP", -5, &["write", "blocking", "movablekeys"], NO_KEYS, &["write", "list", "slow", "blocking"], "list", "Returns multiple elements from the first non-empty list. Blocks until an element is available otherwise."),
+    spec("BLPOP", -3, &["write", "blocking"], (1, -2, 1), &["write", "list", "slow", "blocking"], "list", "Removes and returns the first element in a list. Blocks until an element is available otherwise."),
+    spec("BRPOP", -3, &["write", "blocking"], (1, -2, 1), &["write", "list", "slow", "blocking"], "list", "Removes and returns the last element in a list. Blocks until an element is available otherwise."),
+    // --- Set ---
+    spec("SADD", 3, &["write", "denyoom", "fast"], ONE_KEY, &["write", "set", "fast"], "set", "Adds a member to a set."),
+    spec("SREM", 3, &["write", "fast"], ONE_KEY, &["write", "set", "fast"], "set", "Removes a member from a set."),
+    spec("SMEMBERS", 2, &["readonly"], ONE_KEY, &["read", "set", "slow"], "set", "Returns all members of a set."),
+    spec("SISMEMBER", 3, &["readonly", "fast"], ONE_KEY, &["read", "set", "fast"], "set", "Determines whether a member belongs to a set."),
+    // --- Expire ---
+    spec("EXPIRE", 3, &["write", "fast"], ONE_KEY, &["write", "keyspace", "fast"], "generic", "Sets the expiration time of a key in seconds."),
+    spec("EXPIREAT", 3, &["write", "fast"], ONE_KEY, &["write", "keyspace", "fast"], "generic", "Sets the expiration time of a key to a Unix timestamp."),
+    spec("PEXPIREAT", 3, &["write", "fast"], ONE_KEY, &["write", "keyspace", "fast"], "generic", "Sets the expiration time of a key to a Unix milliseconds timestamp."),
+    spec("TTL", 2, &["readonly", "fast"], ONE_KEY, &["read", "keyspace", "fast"], "generic", "Returns the expiration time in seconds of a key."),
+    spec("PERSIST", 2, &["write", "fast"], ONE_KEY, &["write", "keyspace", "fast"], "generic", "Removes the expiration time of a key."),
+    // --- Transaction ---
+    spec("MULTI", 1, &["noscript", "loading", "stale", "fast"], NO_KEYS, &["fast", "transaction"], "transactions", "Starts a transaction."),
+    spec("EXEC", 1, &["noscript", "loading", "stale", "skip_slowlog"], NO_KEYS, &["slow", "transaction"], "transactions", "Executes all commands in a transaction."),
+    spec("DISCARD", 1, &["noscript", "loading", "stale", "fast"], NO_KEYS, &["fast", "transaction"], "transactions", "Discards a transaction."),
+    spec("WATCH", -2, &["noscript", "loading", "stale", "fast"], (1, -1, 1), &["fast", "transaction"], "transactions", "Monitors changes to keys to determine the execution of a transaction."),
+    spec("UNWATCH", 1, &["noscript", "loading", "stale", "fast"], NO_KEYS, &["fast", "transaction"], "transactions", "Forgets about watched keys of a transaction."),
+    // --- Connection ---
+    spec("PING", -1, &["fast"], NO_KEYS, &["fast", "connection"], "connection", "Returns the server's liveliness response."),
+    spec("ECHO", 2, &["fast"], NO_KEYS, &["fast", "connection"], "connection", "Returns the given string."),
+    spec("QUIT", -1, &["noscript", "loading", "stale", "fast", "no_auth"], NO_KEYS, &["fast", "connection"], "connection", "Closes the connection."),
+    spec("HELLO", -1, &["noscript", "loading", "stale", "fast", "no_auth"], NO_KEYS, &["fast", "connection"], "connection", "Handshakes with the server."),
+    spec("AUTH", -2, &["noscript", "loading", "stale", "fast", "no_auth"], NO_KEYS, &["fast", "connection"], "connection", "Authenticates the connection."),
+    spec("CLIENT", -2, &["noscript", "loading", "stale"], NO_KEYS, &["slow", "admin", "dangerous", "connection"], "connection", "A container for client connection commands."),
+    // --- Pub/Sub ---
+    spec("SUBSCRIBE", -2, &["pubsub", "noscript", "loading", "stale"], NO_KEYS, &["pubsub", "slow"], "pubsub", "Listens for messages published to channels."),
+    spec("UNSUBSCRIBE", -1, &["pubsub", "noscript", "loading", "stale"], NO_KEYS, &["pubsub", "slow"], "pubsub", "Stops listening to messages posted to channels."),
+    spec("PSUBSCRIBE", -2, &["pubsub", "noscript", "loading", "stale"], NO_KEYS, &["pubsub", "slow"], "pubsub", "Listens for messages published to channels that match one or more patterns."),
+    spec("PUNSUBSCRIBE", -1, &["pubsub", "noscript", "loading", "stale"], NO_KEYS, &["pubsub", "slow"], "pubsub", "Stops listening to messages published to channels that match one or more patterns."),
+    spec("PUBLISH", 3, &["pubsub", "loading", "stale", "fast"], NO_KEYS, &["pubsub", "fast"], "pubsub", "Posts a message to a channel."),
+    spec("SSUBSCRIBE", -2, &["pubsub", "noscript", "loading", "stale"], NO_KEYS, &["pubsub", "slow"], "pubsub", "Listens for messages published to shard channels."),
+    spec("SUNSUBSCRIBE", -1, &["pubsub", "noscript", "loading", "stale"], NO_KEYS, &["pubsub", "slow"], "pubsub", "Stops listening to messages posted to shard channels."),
+    spec("SPUBLISH", 3, &["pubsub", "loading", "stale", "fast"], NO_KEYS, &["pubsub", "fast"], "pubsub", "Posts a message to a shard channel."),
+    spec("PUBSUB", -2, &["pubsub", "loading", "stale"], NO_KEYS, &["pubsub", "slow"], "pubsub", "A container for Pub/Sub commands."),
+    // --- Scripting ---
+    spec("EVAL", -3, &["write", "noscript", "stale", "movablekeys"], NO_KEYS, &["slow", "scripting"], "scripting", "Executes a server-side Lua script."),
+    spec("EVALSHA", -3, &["write", "noscript", "stale", "movablekeys"], NO_KEYS, &["slow", "scripting"], "scripting", "Executes a server-side Lua script by SHA1 digest."),
+    spec("FCALL", -3, &["may_replicate", "noscript", "stale", "movablekeys"], NO_KEYS, &["slow", "scripting"], "scripting", "Invokes a function."),
+    spec("FCALL_RO", -3, &["readonly", "noscript", "stale", "movablekeys"], NO_KEYS, &["slow", "scripting"], "scripting", "Invokes a read-only function."),
+    spec("FUNCTION", -2, &["noscript"], NO_KEYS, &["slow", "scripting"], "scripting", "A container for function commands."),
+    spec("SCRIPT", -2, &["noscript"], NO_KEYS, &["slow", "scripting"], "scripting", "A container for Lua scripts management commands."),
+    // --- Server ---
+    spec("INFO", -1, &["loading", "stale"], NO_KEYS, &["slow", "dangerous"], "server", "Returns information and statistics about the server."),
+    spec("HOTKEYS", -2, &["admin", "noscript", "loading", "stale"], NO_KEYS, &["slow", "admin"], "server", "A container for hot key statistics commands."),
+    spec("STATS", -2, &["admin", "noscript", "loading", "stale"], NO_KEYS, &["slow", "admin"], "server", "A container for keyspace statistics commands."),
+    spec("LATENCY", -2, &["admin", "noscript", "loading", "stale"], NO_KEYS, &["slow", "admin", "dangerous"], "server", "A container for latency diagnostics commands."),
+    spec("SLOWLOG", -2, &["admin", "loading", "stale"], NO_KEYS, &["slow", "admin", "dangerous"], "server", "A container for slow log commands."),
+    spec("CONFIG", -2, &["admin", "noscript", "loading", "stale"], NO_KEYS, &["slow", "admin", "dangerous"], "server", "A container for server configuration commands."),
+    spec("ACL", -2, &["noscript", "loading", "stale"], NO_KEYS, &["slow", "admin", "dangerous"], "server", "A container for Access List Control commands."),
+    spec("SAVE", 1, &["admin", "noscript", "no_async_loading", "no_multi"], NO_KEYS, &["slow", "admin", "dangerous"], "server", "Synchronously saves the database(s) to disk."),
+    spec("BGSAVE", -1, &["admin", "noscript", "no_async_loading"], NO_KEYS, &["slow", "admin", "dangerous"], "server", "Asynchronously saves the database(s) to disk."),
+    spec("TIME", 1, &["loading", "stale", "fast"], NO_KEYS, &["fast"], "server", "Returns the server time."),
+    spec("LASTSAVE", 1, &["loading", "stale", "fast"], NO_KEYS, &["fast", "admin", "dangerous"], "server", "Returns the Unix timestamp of the last successful save to disk."),
+    spec("BGREWRITEAOF", 1, &["admin", "noscript", "no_async_loading"], NO_KEYS, &["slow", "admin", "dangerous"], "server", "Asynchronously rewrites the append-only file to disk."),
+    spec("REPLICAOF", 3, &["admin", "noscript", "stale", "no_async_loading"], NO_KEYS, &["slow", "admin", "dangerous"], "server", "Configures a server as replica of another, or promotes it to a master."),
+    spec("SLAVEOF", 3, &["admin", "noscript", "stale", "no_async_loading"], NO_KEYS, &["slow", "admin", "dangerous"], "server", "Sets a server as a replica of another, or promotes it to being a master."),
+    spec("REPLCONF", -1, &["admin", "noscript", "loading", "stale", "allow_busy"], NO_KEYS, &["slow", "admin", "dangerous"], "server", "An internal command for configuring the replication stream."),
+    spec("PSYNC", -3, &["admin", "noscript", "no_async_loading", "no_multi"], NO_KEYS, &["slow", "admin", "dangerous"], "server", "An internal command used in replication."),
+    spec("SYNC", 1, &["admin", "noscript", "no_async_loading", "no_multi"], NO_KEYS, &["slow", "admin", "dangerous"], "server", "An internal command used in replication."),
+    spec("CLUSTER", -2, &["stale"], NO_KEYS, &["slow"], "cluster", "A container for Redis Cluster commands."),
+    spec("ASKING", 1, &["fast"], NO_KEYS, &["fast", "connection"], "cluster", "Signals that a cluster client is following an -ASK redirect."),
+    spec("WAIT", 3, &["noscript"], NO_KEYS, &["slow", "connection"], "generic", "Blocks until the asynchronous replication of all preceding write commands sent by the connection is completed."),
+    spec("MEMORY", -2, &["readonly"], NO_KEYS, &["slow"], "server", "A container for memory diagnostics commands."),
+    spec("DEBUG", -2, &["admin", "noscript", "loading", "stale"], NO_KEYS, &["slow", "admin", "dangerous"], "server", "A container for debugging commands."),
+    spec("COMMAND", -1, &["loading", "stale"], NO_KEYS, &["slow", "connection"], "server", "Returns detailed information about all commands."),
+];
+
+/// 按命令名查找（大小写不敏感）
+pub fn lookup(name: impl AsRef<[u8]>) -> Option<&'static CommandSpec> {
+    let name = name.as_ref();
+    COMMANDS
+        .iter()
+        .find(|c| c.name.as_bytes().eq_ignore_ascii_case(name))
+        .or_else(|| plugin::lookup(std::str::from_utf8(name).ok()?))
+}
+
+/// 按文本读取的参数（命令名、选项、数字），不是合法的 UTF-8 时为 None
+pub fn arg_str(arg: &[u8]) -> Option<&str> {
+    std::str::from_utf8(arg).ok()
+}
+
+/// 把参数解析为数字等类型
+pub fn parse_arg<T: FromStr>(arg: &[u8]) -> Option<T> {
+    arg_str(arg)?.parse().ok()
+}
+
+/// 大写的命令名，用于分发与统计
+pub fn name_upper(arg: &[u8]) -> String {
+    String::from_utf8_lossy(arg).to_uppercase()
+}
+
+/// 管理类命令（CONFIG、CLIENT、ACL 等）的参数按文本处理，非 UTF-8 的字节替换为 U+FFFD
+pub fn text_args(args: &[Bytes]) -> Vec<String> {
+    args.iter().map(|arg| String::from_utf8_lossy(arg).into_owned()).collect()
+}
+
+/// 内置命令与已注册的插件命令
+pub fn all() -> Vec<&'static CommandSpec> {
+    COMMANDS.iter().chain(plugin::specs()).collect()
+}
+
+impl CommandSpec {
+    /// 参数个数（含命令名）是否合法
+    pub fn check_arity(&self, argc: usize) -> bool {
+        let argc = argc as i64;
+        if self.arity >= 0 {
+            argc == self.arity
+        } else {
+            argc >= -self.arity
+        }
+    }
+
+    pub fn has_flag(&self, flag: &str) -> bool {
+        self.flags.contains(&flag)
+    }
+
+    pub fn is_write(&self) -> bool {
+        self.has_flag("write")
+    }
+
+    pub fn is_readonly(&self) -> bool {
+        self.has_flag("readonly")
+    }
+
+    /// 从参数中取出所有 key
+    pub fn keys<'a, A: AsRef<[u8]>>(&self, parts: &'a [A]) -> Vec<&'a [u8]> {
+        self.key_positions(parts).into_iter().map(|i| parts[i].as_ref()).collect()
+    }
+
+    /// 所有 key 在参数中的下标
+    pub fn key_positions<A: AsRef<[u8]>>(&self, parts: &[A]) -> Vec<usize> {
+        // LMPOP numkeys key [key ...] / BLMPOP timeout numkeys key [key ...] /
+        // EVAL script numkeys key [key ...] / FCALL 同 EVAL：key 个数由参数决定
+        let numkeys_at = match self.name {
+            "LMPOP" => Some(1),
+            "BLMPOP" | "EVAL" | "EVALSHA" | "FCALL" | "FCALL_RO" => Some(2),
+            _ => None,
+        };
+        if let Some(at) = numkeys_at {
+            let numkeys = parts.get(at).and_then(|n| parse_arg::<usize>(n.as_ref())).unwrap_or(0);
+            return (at + 1..parts.len()).take(numkeys).collect();
+        }
+        // MEMORY USAGE key：只有这个子命令带 key
+        if self.name == "MEMORY" {
+            return match parts.get(1) {
+                Some(sub) if sub.as_ref().eq_ignore_ascii_case(b"USAGE") => (2..parts.len()).take(1).collect(),
+                _ => vec![],
+            };
+        }
+        if self.first_key <= 0 {
+            return vec![];
+        }
+
+        let last = if self.last_key < 0 {
+            parts.len() as i64 + self.last_key
+        } else {
+            self.last_key
+        };
+        let mut positions = Vec::new();
+        let mut i = self.first_key;
+        while i <= last && (i as usize) < parts.len() {
+            positions.push(i as usize);
+            i += self.step.max(1);
+        }
+        positions
+    }
+
+    /// COMMAND / COMMAND INFO 中的一项
+    fn info_frame(&self) -> Frame {
+        Frame::Array(vec![
+            Frame::bulk(self.name.to_lowercase()),
+            Frame::Integer(self.arity),
+            Frame::Set(self.flags.iter().map(|f| Frame::Simple(f.to_string())).collect()),
+            Frame::Integer(self.first_key),
+            Frame::Integer(self.last_key),
+            Frame::Integer(self.step),
+            Frame::Set(self.categories.iter().map(|c| Frame::Simple(format!("@{}", c))).collect()),
+            // tips / key specs / subcommands：暂不提供
+            Frame::Set(vec![]),
+            Frame::Array(vec![]),
+            Frame::Array(vec![]),
+        ])
+    }
+
+    /// COMMAND DOCS 中的一项
+    fn docs_frame(&self) -> Frame {
+        Frame::Map(vec![
+            (Frame::bulk("summary"), Frame::bulk(self.summary)),
+            (Frame::bulk("since"), Frame::bulk("1.0.0")),
+            (Frame::bulk("group"), Frame::bulk(self.group)),
+        ])
+    }
+}
+
+/// COMMAND [COUNT | LIST | INFO name ... | DOCS [name ...] | GETKEYS cmd arg ...]
+pub fn execute(args: &[Bytes]) -> Frame {
+    let Some(sub) = args.first() else {
+        return Frame::Array(all().into_iter().map(|c| c.info_frame()).collect());
+    };
+    let sub = name_upper(sub);
+
+    match sub.as_str() {
+        "COUNT" if args.len() == 1 => Frame::Integer(all().len() as i64),
+        "LIST" if args.len() == 1 => {
+            Frame::Array(all().into_iter().map(|c| Frame::bulk(c.name.to_lowercase())).collect())
+        }
+        "INFO" if args.len() == 1 => Frame::Array(all().into_iter().map(|c| c.info_frame()).collect()),
+        // 未知命令对应位置返回 nil
+        "INFO" => Frame::Array(
+            args[1..]
+                .iter()
+                .map(|name| lookup(name).map_or(Frame::Null, |c| c.info_frame()))
+                .collect(),
+        ),
+        "DOCS" => {
+            let specs: Vec<&CommandSpec> = if args.len() == 1 {
+                all()
+            } else {
+                // 未知命令直接跳过
+                args[1..].iter().filter_map(|name| lookup(name)).collect()
+            };
+            Frame::Map(
+                specs
+                    .into_iter()
+                    .map(|c| (Frame::bulk(c.name.to_lowercase()), c.docs_frame()))
+                    .collect(),
+            )
+        }
+        "GETKEYS" if args.len() >= 2 => {
+            let parts = &args[1..];
+            let Some(spec) = lookup(&parts[0]) else {
+                return Frame::error("ERR Invalid command specified");
+            };
+            if !spec.check_arity(parts.len()) {
+                return Frame::error("ERR Invalid number of arguments specified for command");
+            }
+            let keys = spec.keys(parts);
+            if keys.is_empty() {
+                return Frame::error("ERR The command has no key arguments");
+            }
+            Frame::Array(keys.into_iter().map(Frame::bulk).collect())
+        }
+        "COUNT" | "LIST" | "GETKEYS" => Frame::error(format!(
+            "ERR wrong number of arguments for 'command|{}' command",
+            sub.to_lowercase()
+        )),
+        _ => Frame::error(format!(
+            "ERR unknown subcommand '{}'. Try COMMAND HELP.",
+            String::from_utf8_lossy(&args[0])
+        )),
+    }
+}
+
+/// 参数个数校验失败时的错误
+pub fn arity_error(name: &str) -> Frame {
+    Frame::error(format!(
+        "ERR wrong number of arguments for '{}' command",
+        name.to_lowercase()
+    ))
+}
+
+/// 未知命令的错误，附带前几个参数便于排查
+pub fn unknown_command_error<A: AsRef<[u8]>>(parts: &[A]) -> Frame {
+    let args: String = parts[1..]
+        .iter()
+        .take(16)
+        .map(|a| format!("'{}' ", String::from_utf8_lossy(a.as_ref())))
+        .collect();
+    Frame::error(format!(
+        "ERR unknown command '{}', with args beginning with: {}",
+        String::from_utf8_lossy(parts[0].as_ref()), args
+    ))
+}
+
+/// 配置项 `rename_command` 生效后的命令名映射，由网络层在查命令表之前应用
+///
+/// 新名称替换回原命令名后再执行，ACL、AOF、慢日志等看到的仍是原命令名。
+#[derive(Debug, Default)]
+pub struct Renames {
+    /// 新名称（大写） -> 原命令名
+    aliases: HashMap<String, &'static str>,
+    /// 被改名或禁用的原命令名，客户端不能再直接使用
+    hidden: HashSet<&'static str>,
+}
+
+impl Renames {
+    /// 校验并构造映射：原命令须存在，新名称不能与其他仍可用的命令或另一个新名称重复
+    pub fn from_config(map: &BTreeMap<String, String>) -> Result<Self> {
+        let mut renames = Renames::default();
+        for (original, alias) in map {
+            let Some(spec) = lookup(original) else {
+                bail!("rename_command: unknown command '{}'", original);
+            };
+            let alias = alias.trim().to_uppercase();
+            if alias == spec.name {
+                continue;
+            }
+            renames.hidden.insert(spec.name);
+            if !alias.is_empty() && renames.aliases.insert(alias.clone(), spec.name).is_some() {
+                bail!("rename_command: '{}' is used for more than one command", alias);
+            }
+        }
+        for alias in renames.aliases.keys() {
+            if let Some(spec) = lookup(alias)
+                && !renames.hidden.contains(spec.name)
+            {
+                bail!("rename_command: '{}' clashes with an existing command", alias);
+            }
+        }
+        Ok(renames)
+    }
+
+    /// 把客户端使用的命令名换回原命令名；命令已被改名或禁用时返回 `false`
+    pub fn resolve(&self, parts: &mut [Bytes]) -> bool {
+        if self.aliases.is_empty() && self.hidden.is_empty() {
+            return true;
+        }
+        let name = name_upper(&parts[0]);
+        if let Some(original) = self.aliases.get(&name) {
+            parts[0] = Bytes::from_static(original.as_bytes());
+            return true;
+        }
+        !lookup(&name).is_some_and(|spec| self.hidden.contains(spec.name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &[&str]) -> Vec<Bytes> {
+        s.iter().map(|x| Bytes::copy_from_slice(x.as_bytes())).collect()
+    }
+
+    #[test]
+    fn test_arity_and_keys() {
+        let get = lookup("get").unwrap();
+        assert!(get.check_arity(2));
+        assert!(!get.check_arity(3));
+
+        let watch = lookup("WATCH").unwrap();
+        assert!(!watch.check_arity(1));
+        assert!(watch.check_arity(4));
+        assert_eq!(watch.keys(&args(&["WATCH", "a", "b", "c"])), vec![b"a", b"b", b"c"]);
+
+        let lmpop = lookup("LMPOP").unwrap();
+        assert_eq!(lmpop.keys(&args(&["LMPOP", "2", "l1", "l2", "LEFT"])), vec![b"l1", b"l2"]);
+        let blpop = lookup("BLPOP").unwrap();
+        assert!(blpop.has_flag("blocking"));
+        assert_eq!(blpop.keys(&args(&["BLPOP", "l1", "l2", "0"])), vec![b"l1", b"l2"]);
+        let blmpop = lookup("BLMPOP").unwrap();
+        assert!(blmpop.has_flag("blocking"));
+        assert!(!blmpop.check_arity(4));
+        assert_eq!(blmpop.keys(&args(&["BLMPOP", "0", "2", "l1", "l2", "LEFT", "COUNT", "2"])), vec![b"l1", b"l2"]);
+        let eval = lookup("EVAL").unwrap();
+        assert_eq!(eval.keys(&args(&["EVAL", "return 1", "1", "k", "arg"])), vec![b"k"]);
+
+        assert!(lookup("PING").unwrap().keys(&args(&["PING"])).is_empty());
+        assert!(lookup("NOSUCH").is_none());
+    }
+
+    #[test]
+    fn test_command_subcommands() {
+        // 并行运行的测试可能注册了插件命令
+        let Frame::Integer(count) = execute(&args(&["COUNT"])) else { panic!("expected integer") };
+        assert!(count as usize >= COMMANDS.len());
+
+        match execute(&args(&["INFO", "get", "nosuch"])) {
+            Frame::Array(items) => {
+                assert_eq!(items.len(), 2);
+                assert_eq!(items[1], Frame::Null);
+                let Frame::Array(info) = &items[0] else { panic!("expected array") };
+                assert_eq!(info[0], Frame::bulk("get"));
+                assert_eq!(info[1], Frame::Integer(2));
+            }
+            other => panic!("unexpected reply {:?}", other),
+        }
+
+        assert_eq!(
+            execute(&args(&["GETKEYS", "SET", "k", "v"])),
+            Frame::Array(vec![Frame::bulk("k")])
+        );
+        assert!(execute(&args(&["GETKEYS", "PING"])).is_error());
+    }
+
+    #[test]
+    fn test_renames() {
+        let map = |pairs: &[(&str, &str)]| pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        let renames = Renames::from_config(&map(&[("config", "cfg-7f3a"), ("DEBUG", "")])).unwrap();
+
+        let mut parts = args(&["Cfg-7F3A", "GET", "maxclients"]);
+        assert!(renames.resolve(&mut parts));
+        assert_eq!(parts[0], "CONFIG");
+        assert!(!renames.resolve(&mut args(&["config", "GET", "maxclients"])));
+        assert!(!renames.resolve(&mut args(&["DEBUG", "SLEEP", "0"])));
+        assert!(renames.resolve(&mut args(&["GET", "k"])));
+
+        // 两个命令互换名称是允许的
+        assert!(Renames::from_config(&map(&[("GET", "SET"), ("SET", "GET")])).is_ok());
+        assert!(Renames::from_config(&map(&[("GET", "SET")])).is_err());
+        assert!(Renames::from_config(&map(&[("NOSUCH", "X")])).is_err());
+        assert!(Renames::from_config(&map(&[("GET", "X"), ("SET", "x")])).is_err());
+    }
+}
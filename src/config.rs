@@ -1,10 +1,10 @@
 use serde::{Deserialize, Serialize};
 use std::{
+    collections::BTreeMap,
     fs,
     path::Path
 };
-use anyhow::{Context, Result};
-use serde_json;
+use anyhow::{Context, Result, bail};
 
 
 /// 进程启动后，从 config.rs 中读到的全局配置
@@ -21,37 +21,694 @@ pub struct Config {
     // 监控配置
     pub metrics_enabled: bool,
     pub metrics_port: u16,
+    /// 指标与健康检查 HTTP 服务绑定的 IP（端口为 `metrics_port`）
+    #[serde(default = "default_metrics_bind")]
+    pub metrics_bind: String,
+    /// 访问 `/metrics` 须带 `Authorization: Bearer <token>`；`/healthz` 与 `/readyz` 不需要认证
+    #[serde(default)]
+    pub metrics_token: Option<String>,
+    /// 访问 `/metrics` 的 HTTP Basic 认证，形如 `user:password`；与 `metrics_token` 同时配置时两者均可
+    #[serde(default)]
+    pub metrics_basic_auth: Option<String>,
     pub slowlog_threshold_ms: u64,
+    /// 慢日志最多保留的条数
+    #[serde(default = "default_slowlog_max_len")]
+    pub slowlog_max_len: usize,
+    /// 慢日志的持久化文件（相对于数据目录），重启后重新加载；未设置时慢日志只在内存中
+    #[serde(default)]
+    pub slowlog_file: Option<String>,
+    /// 延迟监控阈值（毫秒），耗时达到它的事件记入 LATENCY，0 表示关闭
+    #[serde(default)]
+    pub latency_monitor_threshold_ms: u64,
+    /// 单条命令的执行时间预算（毫秒），超出时中止遍历并回复错误，0 表示不限制
+    #[serde(default)]
+    pub command_timeout_ms: u64,
+    /// 热点 key 统计：每 N 条命令抽样一次，0 表示关闭
+    #[serde(default = "default_hotkeys_sample_rate")]
+    pub hotkeys_sample_rate: u64,
+    /// 单条命令最多允许的参数个数
+    #[serde(default = "default_proto_max_multibulk_len")]
+    pub proto_max_multibulk_len: usize,
+    /// 单个参数（Bulk String）最大字节数
+    #[serde(default = "default_proto_max_bulk_len")]
+    pub proto_max_bulk_len: usize,
+    /// default 用户的密码，未设置时 default 用户无需认证
+    #[serde(default)]
+    pub requirepass: Option<String>,
+    /// 额外的 ACL 用户，每行形如 `alice on >secret ~cache:* +@read`
+    #[serde(default)]
+    pub acl_users: Vec<String>,
+    /// 按监听端口划分的命名空间（端口 -> 命名空间），该端口上连接的 key 自动加上前缀
+    #[serde(default)]
+    pub namespace_ports: BTreeMap<u16, String>,
+    /// 按 ACL 用户划分的命名空间（用户名 -> 命名空间），优先于端口的命名空间
+    #[serde(default)]
+    pub namespace_users: BTreeMap<String, String>,
+    /// STATS KEYSPACE 额外按这些 glob 模式统计 key 数与字节数
+    #[serde(default)]
+    pub keyspace_stats_patterns: Vec<String>,
+    /// 每次 FCALL 可消耗的 fuel（约等于 WASM 指令数），超出时中止函数
+    #[serde(default = "default_function_fuel_limit")]
+    pub function_fuel_limit: u64,
+    /// 启动时加载的命令插件动态库（需启用 `plugins` feature）
+    #[serde(default)]
+    pub plugins: Vec<String>,
+    /// 命令改名（原命令名 -> 新名称），新名称为空字符串时禁用该命令；改名后原名称不再可用
+    #[serde(default)]
+    pub rename_command: BTreeMap<String, String>,
+    /// 写命令审计日志文件（相对于数据目录），每条写命令一行 JSON
+    #[serde(default)]
+    pub audit_log: Option<String>,
+    /// 把审计记录同时发布到该频道
+    #[serde(default)]
+    pub audit_channel: Option<String>,
+    /// TLS 证书（PEM），与 `tls_key_file` 同时配置时监听端口启用 TLS
+    #[serde(default)]
+    pub tls_cert_file: Option<String>,
+    /// TLS 私钥（PEM）
+    #[serde(default)]
+    pub tls_key_file: Option<String>,
+    /// 用于校验客户端证书的 CA（PEM）
+    #[serde(default)]
+    pub tls_ca_cert_file: Option<String>,
+    /// 是否要求客户端必须出示证书
+    #[serde(default)]
+    pub tls_auth_clients: bool,
+    /// 普通客户端空闲多少秒后由后台任务断开（订阅中、阻塞中的连接与副本除外），0 表示不断开
+    #[serde(default)]
+    pub timeout: u64,
+    /// 主动过期每秒执行的轮数，每轮检查一批过期记录并删除已过期的 key，0 表示只在访问时惰性删除
+    #[serde(default = "default_active_expire_hz")]
+    pub active_expire_hz: u64,
+    /// TCP keepalive 探测间隔（秒），0 表示关闭
+    #[serde(default = "default_tcp_keepalive")]
+    pub tcp_keepalive: u64,
+    /// 关闭 Nagle 算法（TCP_NODELAY），小命令的回复不再等待合并发送
+    #[serde(default = "default_tcp_nodelay")]
+    pub tcp_nodelay: bool,
+    /// 连接的接收缓冲区大小（SO_RCVBUF，字节），0 表示使用系统默认值
+    #[serde(default)]
+    pub tcp_rcvbuf: usize,
+    /// 连接的发送缓冲区大小（SO_SNDBUF，字节），0 表示使用系统默认值
+    #[serde(default)]
+    pub tcp_sndbuf: usize,
+    /// 监听 socket 的 accept 队列长度，实际上限还受内核 `somaxconn` 限制
+    #[serde(default = "default_tcp_backlog")]
+    pub tcp_backlog: u32,
+    /// 同时连接的客户端上限
+    #[serde(default = "default_maxclients")]
+    pub maxclients: u64,
+    /// 按 key 分片执行命令的线程数：不同 key 的命令并行，同一个 key 的命令保持顺序；
+    /// 0 表示不分片，所有命令都交给阻塞线程池
+    #[serde(default)]
+    pub exec_shards: usize,
+    /// 各类客户端待发送数据的上限，超出即断开（同 Redis 的 client-output-buffer-limit）
+    #[serde(default)]
+    pub client_output_buffer_limit: OutputBufferLimits,
+    /// 每个订阅者最多排队的未发出消息数，超出后按 `pubsub_backlog_policy` 处理；0 表示不限制
+    #[serde(default)]
+    pub pubsub_backlog_limit: usize,
+    /// 订阅者积压超出上限时的处理：`drop` 丢弃新消息，`disconnect` 断开该客户端
+    #[serde(default = "default_pubsub_backlog_policy")]
+    pub pubsub_backlog_policy: String,
+    /// 积压达到这么多条消息的订阅者视为慢订阅者，记录日志并计入统计；0 表示不检测
+    #[serde(default = "default_pubsub_slow_subscriber_backlog")]
+    pub pubsub_slow_subscriber_backlog: usize,
+    /// 是否允许执行 DEBUG 命令
+    #[serde(default)]
+    pub enable_debug_command: bool,
+    /// 只读模式：拒绝全部写命令（回复 `READONLY`），用于只读地提供恢复出的快照，或事故期间手动止写
+    #[serde(default)]
+    pub read_only: bool,
+    /// AOF 重写时以二进制 RDB 快照作为文件开头，之后再追加增量命令
+    #[serde(default)]
+    pub aof_use_rdb_preamble: bool,
+    /// 在 AOF 中写入 `#TS:<unix 秒>` 时间戳注释，用于按时间点恢复
+    #[serde(default)]
+    pub aof_timestamp_enabled: bool,
+    /// 监听地址 (host:port)，多个地址用逗号分隔，如 `127.0.0.1:6380,[::1]:6380`
+    #[serde(default = "default_listen")]
+    pub listen: String,
+    /// 数据目录：kv.db、AOF、RDB 等相对路径都放在该目录下，未设置时为当前目录
+    #[serde(default)]
+    pub dir: Option<String>,
+    /// sled 数据库目录（`storage` 为 `memory` 时不使用）
+    #[serde(default = "default_db_path")]
+    pub db_path: String,
+    /// AOF 日志文件路径
+    #[serde(default = "default_aof_path")]
+    pub aof_path: String,
+    /// RDB 快照文件路径
+    #[serde(default = "default_rdb_path")]
+    pub rdb_path: String,
+    /// 写 RDB 快照的速率上限（MB/s），0 表示不限速
+    #[serde(default)]
+    pub rdb_throttle_mb_per_sec: u64,
+    /// 写 RDB 期间有命令耗时达到这个毫秒数时暂停写盘，等命令延迟恢复再继续，0 表示关闭
+    #[serde(default)]
+    pub rdb_pause_latency_ms: u64,
+    /// 存储引擎：`sled`（落盘的 B 树）或 `memory`（纯内存，持久化只依赖 AOF / RDB）
+    #[serde(default = "default_storage")]
+    pub storage: String,
+    /// 值压缩算法：`none` 或 `lz4`，只对 sled 存储生效
+    #[serde(default = "default_compression")]
+    pub compression: String,
+    /// 值达到这个字节数才压缩
+    #[serde(default = "default_compression_threshold")]
+    pub compression_threshold: usize,
+    /// 启动后作为副本跟随的主节点，形如 `host port`
+    #[serde(default)]
+    pub replicaof: Option<String>,
+    /// 连接主节点时使用的密码（主节点设置了 requirepass 时）
+    #[serde(default)]
+    pub masterauth: Option<String>,
+    /// 复制积压缓冲区大小（字节），短暂断线的副本可从中续传
+    #[serde(default = "default_repl_backlog_size")]
+    pub repl_backlog_size: usize,
+    /// 是否以集群模式运行
+    #[serde(default)]
+    pub cluster_enabled: bool,
+    /// 集群节点表与槽位归属的保存位置（相对数据目录）
+    #[serde(default = "default_cluster_config_file")]
+    pub cluster_config_file: String,
+    /// 在集群中公布的本节点 IP，未设置时使用监听地址
+    #[serde(default)]
+    pub cluster_announce_ip: Option<String>,
+    /// 日志级别（tracing 过滤指令，如 `info`、`crab_cage=debug`），环境变量 `RUST_LOG` 优先
+    #[serde(default = "default_log_level")]
+    pub log_level: String,
+    /// 日志格式：`text` 或 `json`
+    #[serde(default = "default_log_format")]
+    pub log_format: String,
+    /// 日志文件（相对数据目录），未设置时输出到标准输出
+    #[serde(default)]
+    pub logfile: Option<String>,
+    /// 日志文件达到该字节数时轮转，0 表示不按大小轮转
+    #[serde(default)]
+    pub log_rotate_size: u64,
+    /// 日志文件打开超过该秒数时轮转（如 86400 每天一个文件），0 表示不按时间轮转
+    #[serde(default)]
+    pub log_rotate_secs: u64,
+    /// 轮转后保留的旧日志文件个数（`<logfile>.1` 最新），0 表示直接删除
+    #[serde(default = "default_log_rotate_keep")]
+    pub log_rotate_keep: usize,
+    /// pid 文件（相对数据目录），启动时写入进程号，正常退出时删除
+    #[serde(default)]
+    pub pidfile: Option<String>,
+    /// fork 到后台运行（仅 unix），标准输入输出重定向到 `/dev/null`，日志应写入 `logfile`
+    #[serde(default)]
+    pub daemonize: bool,
 }
 
-/// 从指定路径读取并反序列化 JSON 配置
-pub fn load<P: AsRef<Path>>(path: P) -> Result<Config> {
-    let path_ref = path.as_ref();
-    
-    // 如果配置文件不存在，创建默认配置
-    if !path_ref.exists() {
-        println!("Config file not found, creating default configuration...");
-        
-        let default_cfg = Config {
+fn default_metrics_bind() -> String {
+    "0.0.0.0".to_string()
+}
+
+fn default_proto_max_multibulk_len() -> usize {
+    1024 * 1024
+}
+
+fn default_proto_max_bulk_len() -> usize {
+    512 * 1024 * 1024
+}
+
+fn default_slowlog_max_len() -> usize {
+    128
+}
+
+fn default_hotkeys_sample_rate() -> u64 {
+    10
+}
+
+fn default_tcp_keepalive() -> u64 {
+    300
+}
+
+fn default_tcp_nodelay() -> bool {
+    true
+}
+
+fn default_tcp_backlog() -> u32 {
+    511
+}
+
+fn default_maxclients() -> u64 {
+    10000
+}
+
+fn default_active_expire_hz() -> u64 {
+    10
+}
+
+fn default_pubsub_backlog_policy() -> String {
+    "drop".to_string()
+}
+
+fn default_pubsub_slow_subscriber_backlog() -> usize {
+    1000
+}
+
+fn default_repl_backlog_size() -> usize {
+    1024 * 1024
+}
+
+fn default_cluster_config_file() -> String {
+    "nodes.conf".to_string()
+}
+
+fn default_storage() -> String {
+    "sled".to_string()
+}
+
+fn default_compression() -> String {
+    "none".to_string()
+}
+
+fn default_compression_threshold() -> usize {
+    1024
+}
+
+fn default_function_fuel_limit() -> u64 {
+    100_000_000
+}
+
+fn default_listen() -> String {
+    "127.0.0.1:6380".to_string()
+}
+
+fn default_db_path() -> String {
+    "kv.db".to_string()
+}
+
+fn default_aof_path() -> String {
+    "appendonly.aof".to_string()
+}
+
+fn default_rdb_path() -> String {
+    "dump.rdb".to_string()
+}
+
+fn default_log_level() -> String {
+    "info".to_string()
+}
+
+fn default_log_format() -> String {
+    "text".to_string()
+}
+
+fn default_log_rotate_keep() -> usize {
+    5
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
             aof: true,
             rdb: true,
             snapshot_interval_secs: 60,
             snapshot_threshold: 20,
             metrics_enabled: true,
             metrics_port: 9090,
+            metrics_bind: default_metrics_bind(),
+            metrics_token: None,
+            metrics_basic_auth: None,
             slowlog_threshold_ms: 10,
+            slowlog_max_len: default_slowlog_max_len(),
+            slowlog_file: None,
+            latency_monitor_threshold_ms: 0,
+            command_timeout_ms: 0,
+            hotkeys_sample_rate: default_hotkeys_sample_rate(),
+            proto_max_multibulk_len: default_proto_max_multibulk_len(),
+            proto_max_bulk_len: default_proto_max_bulk_len(),
+            requirepass: None,
+            acl_users: Vec::new(),
+            namespace_ports: BTreeMap::new(),
+            namespace_users: BTreeMap::new(),
+            keyspace_stats_patterns: Vec::new(),
+            function_fuel_limit: default_function_fuel_limit(),
+            plugins: Vec::new(),
+            rename_command: BTreeMap::new(),
+            audit_log: None,
+            audit_channel: None,
+            tls_cert_file: None,
+            tls_key_file: None,
+            tls_ca_cert_file: None,
+            tls_auth_clients: false,
+            timeout: 0,
+            active_expire_hz: default_active_expire_hz(),
+            tcp_keepalive: default_tcp_keepalive(),
+            tcp_nodelay: default_tcp_nodelay(),
+            tcp_rcvbuf: 0,
+            tcp_sndbuf: 0,
+            tcp_backlog: default_tcp_backlog(),
+            maxclients: default_maxclients(),
+            exec_shards: 0,
+            client_output_buffer_limit: OutputBufferLimits::default(),
+            pubsub_backlog_limit: 0,
+            pubsub_backlog_policy: default_pubsub_backlog_policy(),
+            pubsub_slow_subscriber_backlog: default_pubsub_slow_subscriber_backlog(),
+            enable_debug_command: false,
+            read_only: false,
+            aof_use_rdb_preamble: false,
+            aof_timestamp_enabled: false,
+            listen: default_listen(),
+            dir: None,
+            db_path: default_db_path(),
+            aof_path: default_aof_path(),
+            rdb_path: default_rdb_path(),
+            rdb_throttle_mb_per_sec: 0,
+            rdb_pause_latency_ms: 0,
+            storage: default_storage(),
+            compression: default_compression(),
+            compression_threshold: default_compression_threshold(),
+            replicaof: None,
+            masterauth: None,
+            repl_backlog_size: default_repl_backlog_size(),
+            cluster_enabled: false,
+            cluster_config_file: default_cluster_config_file(),
+            cluster_announce_ip: None,
+            log_level: default_log_level(),
+            log_format: default_log_format(),
+            logfile: None,
+            log_rotate_size: 0,
+            log_rotate_secs: 0,
+            log_rotate_keep: default_log_rotate_keep(),
+            pidfile: None,
+            daemonize: false,
+        }
+    }
+}
+
+/// SIGHUP 重新加载配置时在运行中生效的字段，其余字段修改后需要重启
+pub const RELOADABLE_FIELDS: &[&str] = &[
+    "log_level",
+    "slowlog_threshold_ms",
+    "slowlog_max_len",
+    "latency_monitor_threshold_ms",
+    "hotkeys_sample_rate",
+    "snapshot_interval_secs",
+    "read_only",
+];
+
+/// 两份配置中取值不同的字段名
+pub fn changed_fields(old: &Config, new: &Config) -> Vec<String> {
+    let (Ok(serde_json::Value::Object(old)), Ok(serde_json::Value::Object(new))) =
+        (serde_json::to_value(old), serde_json::to_value(new))
+    else {
+        return Vec::new();
+    };
+    new.into_iter()
+        .filter(|(field, value)| old.get(field) != Some(value))
+        .map(|(field, _)| field)
+        .collect()
+}
+
+/// 一类客户端的输出缓冲区上限，字节数为 0 表示不限制
+///
+/// 待发送的数据超过 `hard`，或持续超过 `soft` 达 `soft_seconds` 秒，就断开客户端
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct OutputLimit {
+    #[serde(default)]
+    pub hard: usize,
+    #[serde(default)]
+    pub soft: usize,
+    #[serde(default)]
+    pub soft_seconds: u64,
+}
+
+impl OutputLimit {
+    pub const fn new(hard: usize, soft: usize, soft_seconds: u64) -> Self {
+        OutputLimit { hard, soft, soft_seconds }
+    }
+
+    pub fn over_hard(&self, pending: usize) -> bool {
+        self.hard > 0 && pending >= self.hard
+    }
+
+    pub fn over_soft(&self, pending: usize) -> bool {
+        self.soft > 0 && pending >= self.soft
+    }
+}
+
+/// 按客户端类别划分的输出缓冲区上限，默认值与 Redis 相同
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputBufferLimits {
+    /// 普通客户端：单条回复的大小
+    #[serde(default)]
+    pub normal: OutputLimit,
+    /// 订阅了频道的客户端：尚未发出的推送消息
+    #[serde(default = "default_pubsub_output_limit")]
+    pub pubsub: OutputLimit,
+    /// 副本：尚未发出的复制流
+    #[serde(default = "default_replica_output_limit")]
+    pub replica: OutputLimit,
+}
+
+impl Default for OutputBufferLimits {
+    fn default() -> Self {
+        OutputBufferLimits {
+            normal: OutputLimit::default(),
+            pubsub: default_pubsub_output_limit(),
+            replica: default_replica_output_limit(),
+        }
+    }
+}
+
+fn default_pubsub_output_limit() -> OutputLimit {
+    OutputLimit::new(32 << 20, 8 << 20, 60)
+}
+
+fn default_replica_output_limit() -> OutputLimit {
+    OutputLimit::new(256 << 20, 64 << 20, 60)
+}
+
+/// 配置文件格式，按扩展名区分
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl Format {
+    /// `.toml` / `.yaml` / `.yml` 之外的扩展名都按 JSON 处理
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()).map(str::to_ascii_lowercase).as_deref() {
+            Some("toml") => Format::Toml,
+            Some("yaml" | "yml") => Format::Yaml,
+            _ => Format::Json,
+        }
+    }
+
+    fn parse(self, data: &str) -> Result<serde_json::Value> {
+        Ok(match self {
+            Format::Json => serde_json::from_str(data)?,
+            Format::Toml => toml::from_str(data)?,
+            Format::Yaml => serde_yaml::from_str(data)?,
+        })
+    }
+
+    fn render(self, cfg: &Config) -> Result<String> {
+        Ok(match self {
+            Format::Json => serde_json::to_string_pretty(cfg)?,
+            Format::Toml => toml::to_string_pretty(cfg)?,
+            Format::Yaml => serde_yaml::to_string(cfg)?,
+        })
+    }
+}
+
+/// 环境变量覆盖配置项时使用的前缀，如 `CRAB_CAGE_METRICS_PORT=9100`
+pub const ENV_PREFIX: &str = "CRAB_CAGE_";
+
+/// 从指定路径读取并反序列化配置（JSON / TOML / YAML，按扩展名区分），
+/// 再用 `CRAB_CAGE_*` 环境变量覆盖其中的字段
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Config> {
+    load_layered(path, &[])
+}
+
+/// 分层加载配置：默认值 < 配置文件 < `CRAB_CAGE_*` 环境变量 < `overrides`（命令行）
+///
+/// 配置文件中缺少的字段取默认值；`overrides` 为 `(字段名, 值)`，值的写法与环境变量相同。
+pub fn load_layered<P: AsRef<Path>>(path: P, overrides: &[(String, String)]) -> Result<Config> {
+    let path_ref = path.as_ref();
+    let format = Format::from_path(path_ref);
+    let mut value = serde_json::to_value(Config::default())?;
+
+    // 如果配置文件不存在，按同样的格式写入默认配置
+    if !path_ref.exists() {
+        fs::write(path_ref, format.render(&Config::default())?)?;
+    } else {
+        let data = fs::read_to_string(path_ref)
+            .with_context(|| format!("Failed to read config file {:?}", path_ref))?;
+        let file = format
+            .parse(&data)
+            .with_context(|| format!("Failed to parse config file {:?}", path_ref))?;
+        let (serde_json::Value::Object(fields), serde_json::Value::Object(file)) = (&mut value, file) else {
+            bail!("config file {:?} must be a table of fields", path_ref);
         };
-        
-        let default_json = serde_json::to_string_pretty(&default_cfg)?;
-        fs::write(path_ref, default_json)?;
-        println!("Default config created at {:?}", path_ref);
-        
-        return Ok(default_cfg);
-    }
-
-    let data = fs::read_to_string(&path)
-        .with_context(|| format!("Failed to read config file {:?}", path.as_ref()))?;
-    let cfg: Config = serde_json::from_str(&data)
-        .context("Failed to parse config.json")?;
-    Ok(cfg)
-}
\ No newline at end of file
+        fields.extend(file);
+    }
+
+    apply_env(&mut value, std::env::vars())?;
+    apply_overrides(&mut value, overrides)?;
+    serde_json::from_value(value).with_context(|| format!("Invalid configuration in {:?}", path_ref))
+}
+
+/// 用 `CRAB_CAGE_<字段名大写>` 环境变量覆盖配置字段
+///
+/// 字符串字段与可选字段直接取变量的值（可选字段为空串时表示不设置），
+/// 其余字段按 JSON 解析，如 `true`、`9100`、`["user:*"]`。
+pub fn apply_env<I>(value: &mut serde_json::Value, vars: I) -> Result<()>
+where
+    I: IntoIterator<Item = (String, String)>,
+{
+    for (name, raw) in vars {
+        let Some(field) = name.strip_prefix(ENV_PREFIX).map(str::to_ascii_lowercase) else {
+            continue;
+        };
+        // 不对应任何字段的变量忽略：Kubernetes 会为名为 crab-cage 的 Service 注入 `CRAB_CAGE_SERVICE_HOST` 等变量
+        set_field(value, &field, raw).with_context(|| format!("Invalid value for environment variable {}", name))?;
+    }
+    Ok(())
+}
+
+/// 按 `(字段名, 值)` 覆盖配置字段，字段名中的 `-` 视为 `_`；未知字段报错
+pub fn apply_overrides(value: &mut serde_json::Value, overrides: &[(String, String)]) -> Result<()> {
+    for (name, raw) in overrides {
+        let field = name.to_ascii_lowercase().replace('-', "_");
+        if !set_field(value, &field, raw.clone()).with_context(|| format!("Invalid value for '{}'", name))? {
+            bail!("unknown configuration field '{}'", name);
+        }
+    }
+    Ok(())
+}
+
+/// 按字段默认值的类型解析 `raw` 并写入，字段不存在时返回 false
+fn set_field(value: &mut serde_json::Value, field: &str, raw: String) -> Result<bool> {
+    let serde_json::Value::Object(fields) = value else {
+        bail!("configuration must be a table of fields");
+    };
+    let defaults = serde_json::to_value(Config::default())?;
+    let Some(default) = defaults.get(field) else {
+        return Ok(false);
+    };
+    let parsed = match default {
+        serde_json::Value::String(_) => serde_json::Value::String(raw),
+        serde_json::Value::Null if raw.is_empty() => serde_json::Value::Null,
+        serde_json::Value::Null => serde_json::Value::String(raw),
+        _ => serde_json::from_str(&raw).with_context(|| format!("{:?} is not a valid value", raw))?,
+    };
+    fields.insert(field.to_string(), parsed);
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn test_formats_by_extension() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+
+        let toml_path = dir.path().join("config.toml");
+        fs::write(&toml_path, "aof = false\nrdb = true\nsnapshot_interval_secs = 30\nsnapshot_threshold = 5\n\
+            metrics_enabled = false\nmetrics_port = 9100\nslowlog_threshold_ms = 1\nlatency_monitor_threshold_ms = 0\n\
+            [namespace_ports]\n6381 = \"shop\"\n")?;
+        let cfg = load(&toml_path)?;
+        assert!(!cfg.aof);
+        assert_eq!(cfg.metrics_port, 9100);
+        assert_eq!(cfg.namespace_ports.get(&6381).map(String::as_str), Some("shop"));
+
+        let yaml_path = dir.path().join("config.yml");
+        fs::write(&yaml_path, "aof: true\nrdb: false\nsnapshot_interval_secs: 30\nsnapshot_threshold: 5\n\
+            metrics_enabled: false\nmetrics_port: 9100\nslowlog_threshold_ms: 1\nlatency_monitor_threshold_ms: 0\n\
+            requirepass: secret\n")?;
+        let cfg = load(&yaml_path)?;
+        assert!(!cfg.rdb);
+        assert_eq!(cfg.requirepass.as_deref(), Some("secret"));
+
+        // 不存在时按扩展名写入默认配置，再次读取得到同样的内容
+        for name in ["new.toml", "new.yaml", "new.json"] {
+            let path = dir.path().join(name);
+            let created = load(&path)?;
+            assert!(path.exists());
+            assert_eq!(load(&path)?.metrics_port, created.metrics_port);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_env_overrides() -> Result<()> {
+        let mut value = serde_json::to_value(Config::default())?;
+        apply_env(
+            &mut value,
+            vars(&[
+                ("CRAB_CAGE_METRICS_PORT", "9100"),
+                ("CRAB_CAGE_AOF", "false"),
+                ("CRAB_CAGE_STORAGE", "memory"),
+                ("CRAB_CAGE_REQUIREPASS", "123"),
+                ("CRAB_CAGE_KEYSPACE_STATS_PATTERNS", r#"["user:*"]"#),
+                ("PATH", "/usr/bin"),
+            ]),
+        )?;
+        let cfg: Config = serde_json::from_value(value.clone())?;
+        assert_eq!(cfg.metrics_port, 9100);
+        assert!(!cfg.aof);
+        assert_eq!(cfg.storage, "memory");
+        assert_eq!(cfg.requirepass.as_deref(), Some("123"));
+        assert_eq!(cfg.keyspace_stats_patterns, vec!["user:*"]);
+
+        // 空串清除可选字段
+        apply_env(&mut value, vars(&[("CRAB_CAGE_REQUIREPASS", "")]))?;
+        assert_eq!(serde_json::from_value::<Config>(value.clone())?.requirepass, None);
+
+        apply_env(&mut value, vars(&[("CRAB_CAGE_SERVICE_HOST", "10.0.0.1")]))?;
+        assert!(value.get("service_host").is_none());
+        assert!(apply_env(&mut value, vars(&[("CRAB_CAGE_METRICS_PORT", "high")])).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_layered_precedence() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("config.json");
+        // 文件中缺少的字段取默认值
+        fs::write(&path, r#"{"metrics_port": 9100, "listen": "0.0.0.0:6380"}"#)?;
+
+        let cfg = load_layered(&path, &vars(&[("aof-path", "data/a.aof"), ("listen", "127.0.0.1:7000")]))?;
+        assert_eq!(cfg.metrics_port, 9100);
+        assert_eq!(cfg.slowlog_threshold_ms, Config::default().slowlog_threshold_ms);
+        assert_eq!(cfg.listen, "127.0.0.1:7000");
+        assert_eq!(cfg.aof_path, "data/a.aof");
+        assert_eq!(cfg.rdb_path, "dump.rdb");
+
+        assert!(load_layered(&path, &vars(&[("no_such_field", "1")])).is_err());
+        assert!(load_layered(&path, &vars(&[("metrics_port", "high")])).is_err());
+
+        // 命令行覆盖环境变量
+        let mut value = serde_json::to_value(Config::default())?;
+        apply_env(&mut value, vars(&[("CRAB_CAGE_METRICS_PORT", "9200")]))?;
+        apply_overrides(&mut value, &vars(&[("metrics-port", "9300")]))?;
+        assert_eq!(serde_json::from_value::<Config>(value)?.metrics_port, 9300);
+        Ok(())
+    }
+
+    #[test]
+    fn test_changed_fields() {
+        let old = Config::default();
+        let mut new = old.clone();
+        assert!(changed_fields(&old, &new).is_empty());
+
+        new.slowlog_threshold_ms = 50;
+        new.metrics_port = 9100;
+        let mut changed = changed_fields(&old, &new);
+        changed.sort();
+        assert_eq!(changed, vec!["metrics_port", "slowlog_threshold_ms"]);
+        assert!(RELOADABLE_FIELDS.contains(&"slowlog_threshold_ms"));
+        assert!(!RELOADABLE_FIELDS.contains(&"metrics_port"));
+    }
+}
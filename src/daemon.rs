@@ -0,0 +1,131 @@
+// src/daemon.rs
+
+//! 与 init 系统集成：pid 文件、转入后台运行与 systemd 就绪通知
+//!
+//! - pid 文件（配置 `pidfile`，相对数据目录）在启动时写入进程号，正常退出时删除；
+//! - 配置 `daemonize: true` 时 fork 到后台、脱离终端，并把标准输入输出重定向到 `/dev/null`，
+//!   日志应配置 `logfile`；必须在启动任何线程（tokio 运行时、日志）之前调用；
+//! - 由 systemd 以 `Type=notify` 启动时（设置了环境变量 `NOTIFY_SOCKET`），
+//!   在 AOF 重放完成、监听地址绑定后发送 `READY=1`，退出前发送 `STOPPING=1`。
+
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+/// 写入了当前进程号的 pid 文件，drop 时删除
+#[derive(Debug)]
+pub struct PidFile {
+    path: PathBuf,
+}
+
+impl PidFile {
+    pub fn create(path: &Path) -> Result<Self> {
+        fs::write(path, format!("{}\n", std::process::id()))
+            .with_context(|| format!("failed to write pid file {}", path.display()))?;
+        Ok(PidFile { path: path.to_path_buf() })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// fork 到后台：父进程直接退出，子进程成为新会话的首进程，标准输入输出指向 `/dev/null`
+#[cfg(unix)]
+pub fn daemonize() -> Result<()> {
+    use std::os::fd::AsRawFd;
+
+    // SAFETY: 调用时进程中只有一个线程，fork 后子进程可以安全地继续运行
+    match unsafe { libc::fork() } {
+        -1 => return Err(std::io::Error::last_os_error()).context("fork failed"),
+        0 => {}
+        _ => unsafe { libc::_exit(0) },
+    }
+    if unsafe { libc::setsid() } == -1 {
+        return Err(std::io::Error::last_os_error()).context("setsid failed");
+    }
+    let null = fs::OpenOptions::new().read(true).write(true).open("/dev/null").context("failed to open /dev/null")?;
+    for fd in 0..3 {
+        if unsafe { libc::dup2(null.as_raw_fd(), fd) } == -1 {
+            return Err(std::io::Error::last_os_error()).context("failed to redirect standard streams");
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn daemonize() -> Result<()> {
+    anyhow::bail!("daemonize is only supported on unix")
+}
+
+/// 向 systemd 发送状态（如 `READY=1`），未由 systemd 以 `Type=notify` 启动时什么也不做，返回 false
+pub fn notify(state: &str) -> Result<bool> {
+    match std::env::var_os("NOTIFY_SOCKET") {
+        Some(socket) if !socket.is_empty() => notify_socket(&socket, state).map(|_| true),
+        _ => Ok(false),
+    }
+}
+
+/// 把状态发到 `socket`：以 `@` 开头的是 Linux 抽象命名空间中的名字，其余为文件路径
+#[cfg(unix)]
+fn notify_socket(socket: &OsStr, state: &str) -> Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::net::UnixDatagram;
+
+    let sock = UnixDatagram::unbound()?;
+    let name = socket.as_bytes();
+    if let Some(abstract_name) = name.strip_prefix(b"@") {
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::linux::net::SocketAddrExt;
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(abstract_name)?;
+            sock.send_to_addr(state.as_bytes(), &addr)?;
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            let _ = abstract_name;
+            anyhow::bail!("abstract NOTIFY_SOCKET is only supported on Linux");
+        }
+    } else {
+        sock.send_to(state.as_bytes(), Path::new(socket))
+            .with_context(|| format!("failed to notify {}", Path::new(socket).display()))?;
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn notify_socket(_socket: &OsStr, _state: &str) -> Result<()> {
+    anyhow::bail!("NOTIFY_SOCKET is only supported on unix")
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixDatagram;
+
+    #[test]
+    fn test_pid_file_and_notify() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("crab-cage.pid");
+        let pid_file = PidFile::create(&path)?;
+        assert_eq!(fs::read_to_string(&path)?, format!("{}\n", std::process::id()));
+        drop(pid_file);
+        assert!(!path.exists());
+
+        let socket = dir.path().join("notify.sock");
+        let listener = UnixDatagram::bind(&socket)?;
+        notify_socket(socket.as_os_str(), "READY=1")?;
+        let mut buf = [0u8; 16];
+        let n = listener.recv(&mut buf)?;
+        assert_eq!(&buf[..n], b"READY=1");
+        Ok(())
+    }
+}
@@ -0,0 +1,413 @@
+// src/embed.rs
+
+//! 在其他程序（或测试）中嵌入运行服务端
+//!
+//! [`ServerBuilder`] 按 [`Config`] 完成 `main.rs` 中的全部启动步骤：打开存储与持久化器、
+//! 加载插件、启动可选的指标服务、加载 RDB 并重放 AOF、绑定监听地址并启动网络服务，
+//! 返回的 [`ServerHandle`] 可以取得实际监听地址并在结束时关闭服务。
+//!
+//! ```no_run
+//! # async fn demo() -> anyhow::Result<()> {
+//! let server = crab_cage::ServerBuilder::new().listen("127.0.0.1:0").in_memory().start().await?;
+//! println!("listening on {}", server.local_addr());
+//! server.shutdown().await;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! 日志不由这里初始化，需要时由宿主程序调用 `logging::init`。
+
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::{Context, Result};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use tokio::task::JoinHandle;
+use tracing::info;
+
+use crate::acl::Acl;
+use crate::config::Config;
+use crate::engine::{self, kv::DbInstance};
+use crate::hooks::{CommandHook, Hooks};
+use crate::monitor::{self, AuditLog, Monitor};
+use crate::persistence::Persistence;
+use crate::pubsub::PubSub;
+use crate::store::Store;
+use crate::{plugin, server};
+
+/// 服务端构建器，未设置的项使用 [`Config::default`]
+#[derive(Debug, Clone, Default)]
+pub struct ServerBuilder {
+    cfg: Config,
+    recover_to: Option<u64>,
+    hooks: Hooks,
+}
+
+impl ServerBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 以给定配置为基础，之后的设置覆盖其中对应的字段
+    pub fn config(mut self, cfg: Config) -> Self {
+        self.cfg = cfg;
+        self
+    }
+
+    /// 监听地址，多个地址用逗号分隔；端口为 0 时由系统分配
+    pub fn listen(mut self, addr: impl Into<String>) -> Self {
+        self.cfg.listen = addr.into();
+        self
+    }
+
+    /// 数据目录，数据库、AOF、RDB 等相对路径都放在该目录下
+    pub fn dir(mut self, dir: impl AsRef<Path>) -> Self {
+        self.cfg.dir = Some(dir.as_ref().to_string_lossy().into_owned());
+        self
+    }
+
+    /// sled 数据库目录（相对于数据目录）
+    pub fn db_path(mut self, path: impl AsRef<Path>) -> Self {
+        self.cfg.db_path = path.as_ref().to_string_lossy().into_owned();
+        self
+    }
+
+    /// 纯内存存储且关闭 AOF 与 RDB，不在磁盘上留下任何文件
+    pub fn in_memory(mut self) -> Self {
+        self.cfg.storage = "memory".to_string();
+        self.cfg.aof = false;
+        self.cfg.rdb = false;
+        self
+    }
+
+    /// 分别开启或关闭 AOF 与 RDB
+    pub fn persistence(mut self, aof: bool, rdb: bool) -> Self {
+        self.cfg.aof = aof;
+        self.cfg.rdb = rdb;
+        self
+    }
+
+    /// 在该端口提供 Prometheus 指标，`None` 时不启动；端口为 0 时由系统分配
+    pub fn metrics(mut self, port: Option<u16>) -> Self {
+        self.cfg.metrics_enabled = port.is_some();
+        if let Some(port) = port {
+            self.cfg.metrics_port = port;
+        }
+        self
+    }
+
+    /// 按时间点恢复：启动时只重放 AOF 中不晚于该 unix 时间（秒）的写入
+    pub fn recover_to(mut self, ts: u64) -> Self {
+        self.recover_to = Some(ts);
+        self
+    }
+
+    /// 注册命令钩子，多个钩子按注册顺序调用，见 [`crate::hooks`]
+    pub fn hook(mut self, hook: Arc<dyn CommandHook>) -> Self {
+        self.hooks.push(hook);
+        self
+    }
+
+    /// 启动服务，监听地址全部绑定成功后返回
+    pub async fn start(self) -> Result<ServerHandle> {
+        let cfg = self.cfg;
+
+        // 在数据目录下打开存储引擎并构造持久化器
+        let dir = cfg.dir.clone().map(PathBuf::from).unwrap_or_default();
+        let (storage, pers) = Persistence::open(
+            cfg.clone(),
+            &dir,
+            Path::new(&cfg.db_path),
+            Path::new(&cfg.aof_path),
+            Path::new(&cfg.rdb_path),
+        )?;
+        let db = DbInstance {
+            db: storage,
+            watch_manager: Arc::new(engine::watch::WatchManager::new()),
+            waiters: Arc::new(engine::waiters::Waiters::new()),
+            stats: Arc::new(engine::KeyspaceStats::default()),
+        };
+        let mut monitor = Monitor::from_config(&cfg);
+        let audit_path = cfg.audit_log.as_ref().map(|f| pers.data_path(f));
+        monitor.audit = Arc::new(AuditLog::open(audit_path.as_deref(), cfg.audit_channel.clone())?);
+        if let Some(file) = &cfg.slowlog_file {
+            monitor.slow_log.persist_to(&pers.data_path(file))?;
+        }
+        let monitor = Arc::new(monitor);
+        let acl = Arc::new(Acl::from_config(&cfg)?);
+
+        // 加载命令插件：须在重放 AOF 之前，AOF 中可能记录了插件命令
+        for name in plugin::load_all(&cfg.plugins)? {
+            info!(command = %name, "Registered plugin command");
+        }
+
+        // 指标与健康检查服务先于加载数据启动：重放 AOF 期间 /healthz 可用，/readyz 回复 503
+        let pubsub = Arc::new(PubSub::from_config(&cfg)?);
+        let ready = Arc::new(AtomicBool::new(false));
+        let (metrics_addr, metrics) = if cfg.metrics_enabled {
+            let (addr, task) = serve_metrics(&cfg, monitor.metrics.clone(), pers.clone(), db.clone(), pubsub.clone(), ready.clone())?;
+            (Some(addr), Some(task))
+        } else {
+            (None, None)
+        };
+
+        // 加载 RDB 快照，再重放其后的 AOF（或按时间点恢复），然后绑定监听地址
+        let loaded = async {
+            match self.recover_to {
+                Some(ts) => pers.recover_to(ts)?,
+                None => {
+                    pers.load_rdb()?;
+                    pers.load_aof()?;
+                }
+            }
+            server::bind(&cfg.listen, cfg.tcp_backlog).await
+        };
+        let listeners = match loaded.await {
+            Ok(listeners) => listeners,
+            Err(e) => {
+                if let Some(metrics) = metrics {
+                    metrics.abort();
+                }
+                return Err(e);
+            }
+        };
+        let addrs = listeners.iter().map(|l| l.local_addr()).collect::<std::io::Result<Vec<_>>>()?;
+        let server = tokio::spawn(server::serve(listeners, db.clone(), pers.clone(), monitor.clone(), acl, pubsub, Arc::new(self.hooks)));
+        ready.store(true, Ordering::Relaxed);
+
+        Ok(ServerHandle { addrs, metrics_addr, db, pers, monitor, server: Some(server), metrics })
+    }
+}
+
+/// 运行中的服务端
+pub struct ServerHandle {
+    addrs: Vec<SocketAddr>,
+    metrics_addr: Option<SocketAddr>,
+    db: DbInstance,
+    pers: Arc<Persistence>,
+    monitor: Arc<Monitor>,
+    server: Option<JoinHandle<Result<()>>>,
+    metrics: Option<JoinHandle<()>>,
+}
+
+impl ServerHandle {
+    /// 第一个监听地址（端口为 0 时是系统分配的实际端口）
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addrs[0]
+    }
+
+    /// 全部监听地址
+    pub fn local_addrs(&self) -> &[SocketAddr] {
+        &self.addrs
+    }
+
+    /// 指标服务的地址，未开启时为 `None`
+    pub fn metrics_addr(&self) -> Option<SocketAddr> {
+        self.metrics_addr
+    }
+
+    /// 服务端使用的数据库实例，可绕过网络层直接读写
+    pub fn db(&self) -> &DbInstance {
+        &self.db
+    }
+
+    /// 同一数据库上的类型化接口，写入与网络上的写入一样记入 AOF
+    pub fn store(&self) -> Store {
+        Store::with_persistence(self.db.clone(), self.pers.clone())
+    }
+
+    pub fn persistence(&self) -> &Arc<Persistence> {
+        &self.pers
+    }
+
+    pub fn monitor(&self) -> &Arc<Monitor> {
+        &self.monitor
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.pers.cfg
+    }
+
+    /// 等待网络服务结束：正常情况下一直运行，只在 accept 循环出错时返回错误
+    pub async fn wait(&mut self) -> Result<()> {
+        match self.server.take() {
+            Some(server) => server.await?,
+            None => Ok(()),
+        }
+    }
+
+    /// 停止接受连接与指标服务，并把 AOF 刷到磁盘
+    ///
+    /// 已建立的连接任务不会被中断；存储引擎在进程退出前保持打开，
+    /// 同一进程中不能再用同一个 sled 目录启动新的服务端。
+    pub async fn shutdown(mut self) {
+        // 等待任务真正结束，返回时监听端口已经释放
+        if let Some(server) = self.server.take() {
+            server.abort();
+            let _ = server.await;
+        }
+        if let Some(metrics) = self.metrics.take() {
+            metrics.abort();
+            let _ = metrics.await;
+        }
+        self.pers.fsync_and_close();
+    }
+}
+
+/// 在 `metrics_bind:metrics_port` 上提供 `/metrics`、`/healthz` 与 `/readyz`
+///
+/// `/healthz` 在进程存活时总是回复 200；`/readyz` 在 AOF 重放完成、监听地址绑定后才回复 200，之前回复 503。
+/// 配置了 `metrics_token` 或 `metrics_basic_auth` 时 `/metrics` 须带对应的 `Authorization` 头，否则回复 401。
+fn serve_metrics(
+    cfg: &Config,
+    metrics: Arc<monitor::Metrics>,
+    pers: Arc<Persistence>,
+    db: DbInstance,
+    pubsub: Arc<PubSub>,
+    ready: Arc<AtomicBool>,
+) -> Result<(SocketAddr, JoinHandle<()>)> {
+    use warp::http::StatusCode;
+    use warp::{Filter, Reply};
+
+    let ip: IpAddr = cfg
+        .metrics_bind
+        .parse()
+        .with_context(|| format!("invalid metrics_bind '{}'", cfg.metrics_bind))?;
+    let auth = Arc::new(MetricsAuth::from_config(cfg));
+
+    let route = warp::path("metrics").and(warp::header::optional::<String>("authorization")).map(move |header: Option<String>| {
+        if !auth.allows(header.as_deref()) {
+            let reply = warp::reply::with_status("unauthorized", StatusCode::UNAUTHORIZED);
+            return warp::reply::with_header(reply, "www-authenticate", auth.challenge()).into_response();
+        }
+        let load = monitor::Metrics::aof_load_to_prometheus(&pers.aof_load_status());
+        let persistence = monitor::Metrics::persistence_to_prometheus(&pers.status());
+        let memory = monitor::memory::collect(&db).unwrap_or_default();
+        let memory = monitor::Metrics::memory_to_prometheus(&memory);
+        let latency = monitor::Metrics::latency_to_prometheus(&pers.latency().latest());
+        let keyspace = monitor::Metrics::keyspace_to_prometheus(&db.stats);
+        let pubsub = monitor::Metrics::pubsub_to_prometheus(&pubsub.stats());
+        warp::reply::html(metrics.to_prometheus() + &load + &persistence + &memory + &latency + &keyspace + &pubsub).into_response()
+    });
+    let healthz = warp::path("healthz").and(warp::path::end()).map(|| "ok");
+    let readyz = warp::path("readyz").and(warp::path::end()).map(move || {
+        if ready.load(Ordering::Relaxed) {
+            warp::reply::with_status("ready", StatusCode::OK)
+        } else {
+            warp::reply::with_status("loading", StatusCode::SERVICE_UNAVAILABLE)
+        }
+    });
+
+    let (addr, server) = warp::serve(route.or(healthz).or(readyz)).try_bind_ephemeral((ip, cfg.metrics_port))?;
+    info!("Metrics server listening on {}", addr);
+    Ok((addr, tokio::spawn(server)))
+}
+
+/// `/metrics` 接受的凭据：`Bearer <token>` 与 `Basic base64(user:password)`，都未配置时不认证
+struct MetricsAuth {
+    bearer: Option<String>,
+    basic: Option<String>,
+}
+
+impl MetricsAuth {
+    fn from_config(cfg: &Config) -> Self {
+        MetricsAuth {
+            bearer: cfg.metrics_token.clone(),
+            basic: cfg.metrics_basic_auth.as_ref().map(|creds| BASE64.encode(creds)),
+        }
+    }
+
+    /// 认证方案不区分大小写，凭据按常量时间比较
+    fn allows(&self, header: Option<&str>) -> bool {
+        if self.bearer.is_none() && self.basic.is_none() {
+            return true;
+        }
+        let Some((scheme, creds)) = header.and_then(|h| h.trim().split_once(' ')) else {
+            return false;
+        };
+        let expected = if scheme.eq_ignore_ascii_case("bearer") {
+            &self.bearer
+        } else if scheme.eq_ignore_ascii_case("basic") {
+            &self.basic
+        } else {
+            &None
+        };
+        expected.as_ref().is_some_and(|expected| constant_time_eq(expected.as_bytes(), creds.trim().as_bytes()))
+    }
+
+    fn challenge(&self) -> &'static str {
+        if self.basic.is_some() { "Basic realm=\"crab-cage\"" } else { "Bearer" }
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::Client;
+    use crate::protocol::Frame;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_start_and_shutdown() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let server = ServerBuilder::new().listen("127.0.0.1:0").dir(dir.path()).in_memory().start().await?;
+        let addr = server.local_addr();
+        assert_ne!(addr.port(), 0);
+
+        let reply = tokio::task::spawn_blocking(move || -> Result<Frame> {
+            let mut client = Client::connect(addr)?;
+            client.call(&["SET", "k", "v"])?;
+            client.call(&["GET", "k"])
+        })
+        .await??;
+        assert_eq!(reply, Frame::bulk("v"));
+        assert_eq!(server.store().get("k")?.as_deref(), Some(&b"v"[..]));
+
+        server.shutdown().await;
+        assert!(std::net::TcpStream::connect(addr).is_err());
+        Ok(())
+    }
+
+    /// 发送一个 HTTP/1.0 GET 请求，返回状态码
+    async fn http_status(addr: SocketAddr, path: &str, auth: Option<&str>) -> Result<u16> {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let mut stream = tokio::net::TcpStream::connect(addr).await?;
+        let auth = auth.map(|a| format!("Authorization: {}\r\n", a)).unwrap_or_default();
+        stream.write_all(format!("GET {} HTTP/1.0\r\n{}\r\n", path, auth).as_bytes()).await?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await?;
+        Ok(response.split(' ').nth(1).unwrap_or_default().parse()?)
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_metrics_auth_and_probes() -> Result<()> {
+        let cfg = Config {
+            metrics_bind: "127.0.0.1".to_string(),
+            metrics_token: Some("s3cret".to_string()),
+            metrics_basic_auth: Some("prom:pw".to_string()),
+            ..Config::default()
+        };
+        let dir = tempfile::tempdir()?;
+        let server = ServerBuilder::new().config(cfg).listen("127.0.0.1:0").dir(dir.path()).in_memory().metrics(Some(0)).start().await?;
+        let addr = server.metrics_addr().unwrap();
+        assert!(addr.ip().is_loopback());
+
+        assert_eq!(http_status(addr, "/healthz", None).await?, 200);
+        assert_eq!(http_status(addr, "/readyz", None).await?, 200);
+        assert_eq!(http_status(addr, "/metrics", None).await?, 401);
+        assert_eq!(http_status(addr, "/metrics", Some("Bearer wrong")).await?, 401);
+        assert_eq!(http_status(addr, "/metrics", Some("bearer s3cret")).await?, 200);
+        // prom:pw
+        assert_eq!(http_status(addr, "/metrics", Some("Basic cHJvbTpwdw==")).await?, 200);
+
+        server.shutdown().await;
+        Ok(())
+    }
+}
@@ -0,0 +1,331 @@
+// src/engine/blocking.rs
+
+//! `KvEngine` 的异步外观
+//!
+//! sled 的读写会阻塞调用线程，后台合并、刷盘时尤其明显。网络层经由 `AsyncKv`
+//! 把存储操作派发到 tokio 的阻塞线程池（`spawn_blocking`），worker 线程只负责
+//! 收发数据，存储繁忙时其他连接依然能及时得到响应。
+//!
+//! 配置了 `exec_shards` 时，命令改由 [`ShardedExecutor`] 执行：key 按哈希
+//! 落到固定的分片线程上，不同 key 的命令在各分片上并行，同一个 key 的命令按提交顺序执行。
+//! key 跨分片的命令（包括多分片的事务）与不带 key 的命令占用涉及的全部分片，
+//! 与每个分片上前后的命令都保持先后顺序，AOF 与复制流中的顺序也随之一致。
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{mpsc, Arc, Barrier, Mutex};
+use std::thread;
+
+use anyhow::{anyhow, Result};
+use sled::IVec;
+use tokio::sync::oneshot;
+
+use crate::engine::{KvEngine, WriteBatch};
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// 按 key 分片的执行器：每个分片一个线程，按提交顺序依次执行队列中的任务
+pub struct ShardedExecutor {
+    shards: Vec<mpsc::Sender<Job>>,
+    /// 跨分片任务入队时持有，使它们在各分片队列中的相对顺序一致
+    enqueue: Mutex<()>,
+}
+
+impl ShardedExecutor {
+    /// 启动 `n` 个分片线程；执行器被丢弃后线程处理完剩余任务即退出
+    pub fn new(n: usize) -> Result<Self> {
+        let mut shards = Vec::with_capacity(n);
+        for i in 0..n.max(1) {
+            let (tx, rx) = mpsc::channel::<Job>();
+            thread::Builder::new().name(format!("crab-shard-{}", i)).spawn(move || {
+                for job in rx {
+                    job();
+                }
+            })?;
+            shards.push(tx);
+        }
+        Ok(ShardedExecutor { shards, enqueue: Mutex::new(()) })
+    }
+
+    pub fn len(&self) -> usize {
+        self.shards.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shards.is_empty()
+    }
+
+    /// key 所在的分片
+    pub fn shard_of(&self, key: &[u8]) -> usize {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        (hasher.finish() % self.shards.len() as u64) as usize
+    }
+
+    /// `keys` 涉及的分片，按序号排列；没有 key 时为全部分片
+    pub fn shards_of_keys(&self, keys: &[&[u8]]) -> Vec<usize> {
+        if keys.is_empty() {
+            return (0..self.shards.len()).collect();
+        }
+        let mut shards: Vec<usize> = keys.iter().map(|key| self.shard_of(key)).collect();
+        shards.sort_unstable();
+        shards.dedup();
+        shards
+    }
+
+    /// 把 `f` 排到第 `shard` 个分片的队列末尾，返回接收结果的通道；
+    /// `f` panic 时通道直接关闭，分片线程继续运行
+    pub fn submit<T, F>(&self, shard: usize, f: F) -> Result<oneshot::Receiver<T>>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let (tx, rx) = oneshot::channel();
+        let job: Job = Box::new(move || {
+            if let Ok(value) = panic::catch_unwind(AssertUnwindSafe(f)) {
+                let _ = tx.send(value);
+            }
+        });
+        self.shards[shard].send(job).map_err(|_| anyhow!("storage shard {} is gone", shard))?;
+        Ok(rx)
+    }
+
+    /// 在 `shards` 中的全部分片上执行 `f`：每个分片先处理完排在前面的任务，
+    /// 全部到齐后由第一个分片执行 `f`，其余分片等它完成后再继续
+    ///
+    /// 跨分片的任务持有入队锁依次排入各分片，在每个分片上的先后顺序相同，不会互相等待成环
+    pub fn submit_all<T, F>(&self, shards: &[usize], f: F) -> Result<oneshot::Receiver<T>>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        if let [shard] = shards {
+            return self.submit(*shard, f);
+        }
+
+        let (tx, rx) = oneshot::channel();
+        let barrier = Arc::new(Barrier::new(shards.len()));
+        let mut task = Some((f, tx));
+        let _order = self.enqueue.lock().unwrap();
+        for &shard in shards {
+            let barrier = barrier.clone();
+            let job: Job = match task.take() {
+                Some((f, tx)) => Box::new(move || {
+                    barrier.wait();
+                    if let Ok(value) = panic::catch_unwind(AssertUnwindSafe(f)) {
+                        let _ = tx.send(value);
+                    }
+                    barrier.wait();
+                }),
+                None => Box::new(move || {
+                    barrier.wait();
+                    barrier.wait();
+                }),
+            };
+            self.shards[shard].send(job).map_err(|_| anyhow!("storage shard {} is gone", shard))?;
+        }
+        Ok(rx)
+    }
+
+    /// 在 `shards` 中的全部分片上执行 `f` 并等待结果
+    pub async fn run<T, F>(&self, shards: &[usize], f: F) -> Result<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        self.submit_all(shards, f)?.await.map_err(|_| anyhow!("storage task on shards {:?} failed", shards))
+    }
+}
+
+#[derive(Clone)]
+pub struct AsyncKv<E> {
+    db: E,
+    shards: Option<Arc<ShardedExecutor>>,
+}
+
+impl<E> AsyncKv<E>
+where
+    E: KvEngine + Clone + Send + Sync + 'static,
+{
+    pub fn new(db: E) -> Self {
+        AsyncKv { db, shards: None }
+    }
+
+    /// 涉及 key 的操作交给 `shards` 个分片线程执行，0 表示不分片，全部使用阻塞线程池
+    pub fn with_shards(db: E, shards: usize) -> Result<Self> {
+        let shards = match shards {
+            0 => None,
+            n => Some(Arc::new(ShardedExecutor::new(n)?)),
+        };
+        Ok(AsyncKv { db, shards })
+    }
+
+    /// 底层的同步引擎，供不涉及存储 I/O 的调用（如取监视管理器）使用
+    pub fn inner(&self) -> &E {
+        &self.db
+    }
+
+    /// 在阻塞线程池中对引擎执行 `f`；`f` panic 或运行时关闭时返回错误
+    pub async fn run<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&E) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let db = self.db.clone();
+        tokio::task::spawn_blocking(move || f(&db))
+            .await
+            .map_err(|e| anyhow!("storage task failed: {}", e))
+    }
+
+    /// 按 `keys` 选择执行位置：在 key 涉及的分片上执行，与这些分片上的其他操作保持先后顺序；
+    /// 没有 key 时占用全部分片，未启用分片时与 `run` 相同
+    pub async fn run_keyed<T, F>(&self, keys: &[&[u8]], f: F) -> Result<T>
+    where
+        F: FnOnce(&E) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        if let Some(shards) = &self.shards {
+            let db = self.db.clone();
+            return shards.run(&shards.shards_of_keys(keys), move || f(&db)).await;
+        }
+        self.run(f).await
+    }
+
+    pub async fn get(&self, key: Vec<u8>) -> Result<Option<IVec>> {
+        self.run(move |db| db.get(&key)).await?
+    }
+
+    pub async fn insert(&self, key: Vec<u8>, value: Vec<u8>) -> Result<Option<IVec>> {
+        self.run(move |db| db.insert(&key, &value)).await?
+    }
+
+    pub async fn remove(&self, key: Vec<u8>) -> Result<Option<IVec>> {
+        self.run(move |db| db.remove(&key)).await?
+    }
+
+    /// 前缀扫描的结果在阻塞线程中全部收集后返回
+    pub async fn scan_prefix(&self, prefix: Vec<u8>) -> Result<Vec<(IVec, IVec)>> {
+        self.run(move |db| db.scan_prefix(&prefix).collect()).await?
+    }
+
+    pub async fn apply_batch(&self, batch: WriteBatch) -> Result<()> {
+        self.run(move |db| db.apply_batch(&batch)).await?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::memory::MemoryEngine;
+    use crate::keys;
+    use crate::types::string;
+
+    #[tokio::test]
+    async fn test_async_facade() -> Result<()> {
+        let kv = AsyncKv::new(MemoryEngine::new());
+        assert_eq!(kv.insert(keys::string(b"a"), b"1".to_vec()).await?, None);
+
+        let mut batch = WriteBatch::new();
+        batch.insert(keys::hash_field(b"h", b"f"), b"v");
+        batch.remove(keys::string(b"a"));
+        kv.apply_batch(batch).await?;
+        assert_eq!(kv.get(keys::string(b"a")).await?, None);
+        assert_eq!(kv.scan_prefix(keys::Kind::Hash.tag().to_vec()).await?.len(), 1);
+
+        // 任意同步操作都可以整体派发
+        let reply = kv.run(|db| string::set(db, b"b", b"2")).await??;
+        assert_eq!(reply, "OK");
+        assert!(kv.run(|_| -> () { panic!("boom") }).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_sharded_executor() -> Result<()> {
+        let kv = AsyncKv::with_shards(MemoryEngine::new(), 4)?;
+        let shards = kv.shards.clone().unwrap();
+        assert_eq!(shards.shard_of(b"k"), shards.shard_of(b"k"));
+        assert_eq!(shards.shards_of_keys(&[]), [0, 1, 2, 3]);
+        let other = (0..100).map(|i| format!("k{}", i)).find(|key| shards.shard_of(key.as_bytes()) != shards.shard_of(b"k")).unwrap();
+        let mut both = vec![shards.shard_of(b"k"), shards.shard_of(other.as_bytes())];
+        both.sort_unstable();
+        assert_eq!(shards.shards_of_keys(&[other.as_bytes(), b"k", other.as_bytes()]), both);
+
+        // 同一个分片上的任务按提交顺序执行
+        let shard = shards.shard_of(b"k");
+        let pending = (0..100)
+            .map(|i| {
+                let db = kv.inner().clone();
+                shards.submit(shard, move || string::set(&db, b"k", i.to_string().as_bytes()))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        for rx in pending {
+            rx.await??;
+        }
+        assert_eq!(kv.run_keyed(&[b"k"], |db| string::get(db, b"k")).await??.as_deref(), Some(&b"99"[..]));
+
+        // panic 不会带走分片线程
+        assert!(kv.run_keyed(&[b"k"], |_| -> () { panic!("boom") }).await.is_err());
+        assert_eq!(kv.run_keyed(&[b"k", other.as_bytes()], |db| string::get(db, b"k")).await??.as_deref(), Some(&b"99"[..]));
+        assert_eq!(kv.run_keyed(&[b"k"], |db| string::get(db, b"k")).await??.as_deref(), Some(&b"99"[..]));
+        Ok(())
+    }
+
+    // 跨分片的任务看到各分片上排在它之前的写入，排在它之后的任务看到它的写入
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_cross_shard_ordering() -> Result<()> {
+        let kv = AsyncKv::with_shards(MemoryEngine::new(), 4)?;
+        let shards = kv.shards.clone().unwrap();
+        let a = shards.shard_of(b"a");
+        let b_key = (0..100).map(|i| format!("b{}", i)).find(|key| shards.shard_of(key.as_bytes()) != a).unwrap();
+        let b = shards.shard_of(b_key.as_bytes());
+
+        // 分片 b 上先排一个较慢的写入，跨分片任务必须等它完成
+        let db = kv.inner().clone();
+        let key = b_key.clone();
+        let before = shards.submit(b, move || {
+            thread::sleep(std::time::Duration::from_millis(50));
+            string::set(&db, key.as_bytes(), b"1")
+        })?;
+        let db = kv.inner().clone();
+        let key = b_key.clone();
+        let cross = shards.submit_all(&shards.shards_of_keys(&[b"a", key.as_bytes()]), move || {
+            let seen = string::get(&db, key.as_bytes()).unwrap();
+            string::set(&db, b"a", b"cross").unwrap();
+            string::set(&db, key.as_bytes(), b"cross").unwrap();
+            seen
+        })?;
+        let db = kv.inner().clone();
+        let after = shards.submit(a, move || string::get(&db, b"a"))?;
+
+        before.await??;
+        assert_eq!(cross.await?.as_deref(), Some(&b"1"[..]));
+        assert_eq!(after.await??.as_deref(), Some(&b"cross"[..]));
+
+        // 多个连接同时提交互相重叠的跨分片任务与单分片任务，全部都能完成
+        let handles: Vec<_> = (0..8)
+            .map(|i| {
+                let kv = kv.clone();
+                tokio::spawn(async move {
+                    for j in 0..50 {
+                        let keys = [format!("x{}", (i + j) % 7), format!("y{}", j % 5)];
+                        let keys: Vec<&[u8]> = keys.iter().map(|k| k.as_bytes()).collect();
+                        let owned: Vec<Vec<u8>> = keys.iter().map(|k| k.to_vec()).collect();
+                        kv.run_keyed(&keys, move |db| owned.iter().try_for_each(|k| string::incr(db, k).map(drop))).await??;
+                        kv.run_keyed(&[], |db| string::incr(db, b"all").map(drop)).await??;
+                    }
+                    anyhow::Ok(())
+                })
+            })
+            .collect();
+        let all = tokio::time::timeout(std::time::Duration::from_secs(10), async {
+            for handle in handles {
+                handle.await??;
+            }
+            anyhow::Ok(())
+        });
+        all.await??;
+        assert_eq!(kv.run_keyed(&[], |db| string::get(db, b"all")).await??.as_deref(), Some(&b"400"[..]));
+        Ok(())
+    }
+}
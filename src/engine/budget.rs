@@ -0,0 +1,81 @@
+// src/engine/budget.rs
+
+//! 命令的执行时间预算
+//!
+//! 命令在阻塞线程上同步执行，无法从外部打断。连接层用 [`run`] 为一条命令设定预算
+//! （`command_timeout_ms`），耗时可能很长的只读遍历（LRANGE、HGETALL、SMEMBERS 等）
+//! 每处理一个元素调用一次 [`check`]，超出预算时以错误中止，已经读到的数据直接丢弃。
+//! 写命令的循环不做检查，避免留下写了一半的结果。
+
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+use crate::error::CommandError;
+
+/// 每调用这么多次 [`check`] 才读一次时钟
+const CHECK_EVERY: u32 = 64;
+
+thread_local! {
+    /// 当前命令的截止时间与预算
+    static DEADLINE: Cell<Option<(Instant, Duration)>> = const { Cell::new(None) };
+    static TICKS: Cell<u32> = const { Cell::new(0) };
+    /// 当前命令是否因超出预算被中止
+    static EXPIRED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// 在 `limit` 的预算内执行 `f`，返回其结果以及是否超出预算被中止；`None` 表示不限制
+pub fn run<T>(limit: Option<Duration>, f: impl FnOnce() -> T) -> (T, bool) {
+    /// `f` panic 时同样恢复外层的预算
+    struct Restore(Option<(Instant, Duration)>, bool);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            DEADLINE.set(self.0);
+            EXPIRED.set(self.1);
+        }
+    }
+
+    let _restore = Restore(DEADLINE.replace(limit.map(|limit| (Instant::now() + limit, limit))), EXPIRED.replace(false));
+    TICKS.set(0);
+    let out = f();
+    (out, EXPIRED.get())
+}
+
+/// 当前命令已超出预算时返回错误
+pub fn check() -> Result<(), CommandError> {
+    let Some((deadline, limit)) = DEADLINE.get() else {
+        return Ok(());
+    };
+    let ticks = TICKS.get().wrapping_add(1);
+    TICKS.set(ticks);
+    if !EXPIRED.get() && (!ticks.is_multiple_of(CHECK_EVERY) || Instant::now() < deadline) {
+        return Ok(());
+    }
+    EXPIRED.set(true);
+    Err(CommandError::err(format!("command exceeded the execution time limit of {} ms", limit.as_millis())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_budget() {
+        // 不在 run 中时不限制
+        assert!(check().is_ok());
+
+        let (result, expired) = run(Some(Duration::from_millis(20)), || {
+            for _ in 0..CHECK_EVERY * 2 {
+                check()?;
+            }
+            std::thread::sleep(Duration::from_millis(30));
+            (0..CHECK_EVERY).try_for_each(|_| check())
+        });
+        assert!(expired);
+        assert_eq!(result.unwrap_err().to_string(), "ERR command exceeded the execution time limit of 20 ms");
+
+        // 预算只对本次 run 有效
+        let (result, expired) = run(None, || (0..CHECK_EVERY * 2).try_for_each(|_| check()));
+        assert!(result.is_ok() && !expired);
+        assert!(check().is_ok());
+    }
+}
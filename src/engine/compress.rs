@@ -0,0 +1,117 @@
+// src/engine/compress.rs
+
+//! 值的透明压缩（配置 `compression = "lz4"`）
+//!
+//! 长度达到 `compression_threshold` 的值在写入 sled 前压缩，压缩后的记录以标志字节
+//! 0xFF 开头，后跟算法编号与压缩数据。用户值都是 UTF-8 字符串，不会以 0xFF 开头，
+//! 所以未压缩的记录（包括开启压缩前写入的）原样读取；关闭压缩后已压缩的记录也照常解压。
+//!
+//! 只有保存用户值的记录（字符串、hash 字段、列表元素）参与压缩，
+//! 列表元数据、过期时间等二进制记录保持原样。
+
+use std::borrow::Cow;
+
+use anyhow::{anyhow, bail, Result};
+use sled::IVec;
+
+use crate::config::Config;
+use crate::keys::Kind;
+
+/// 压缩记录的标志字节
+const FLAG: u8 = 0xFF;
+/// 算法编号
+const CODEC_LZ4: u8 = 1;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Codec {
+    #[default]
+    None,
+    Lz4,
+}
+
+/// 写入时的压缩策略；读取时总是按标志字节解压，与策略无关
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Compression {
+    codec: Codec,
+    /// 值达到这个字节数才压缩
+    threshold: usize,
+}
+
+impl Compression {
+    pub fn new(codec: Codec, threshold: usize) -> Self {
+        Compression { codec, threshold }
+    }
+
+    pub fn from_config(cfg: &Config) -> Result<Self> {
+        let codec = match cfg.compression.as_str() {
+            "none" => Codec::None,
+            "lz4" => Codec::Lz4,
+            other => bail!("unknown compression '{}', expected \"none\" or \"lz4\"", other),
+        };
+        Ok(Self::new(codec, cfg.compression_threshold))
+    }
+
+    /// 写入前按策略压缩 `key` 的值，不值得压缩时原样返回
+    pub fn encode<'a>(&self, key: &[u8], value: &'a [u8]) -> Cow<'a, [u8]> {
+        if self.codec == Codec::None || value.len() < self.threshold || !holds_value(key) {
+            return Cow::Borrowed(value);
+        }
+        let compressed = lz4_flex::compress_prepend_size(value);
+        if compressed.len() + 2 >= value.len() {
+            return Cow::Borrowed(value);
+        }
+        let mut out = Vec::with_capacity(compressed.len() + 2);
+        out.extend_from_slice(&[FLAG, CODEC_LZ4]);
+        out.extend_from_slice(&compressed);
+        Cow::Owned(out)
+    }
+}
+
+/// 读取后解压 `key` 的值，未压缩的记录原样返回
+pub fn decode(key: &[u8], value: IVec) -> Result<IVec> {
+    if value.first() != Some(&FLAG) || !holds_value(key) {
+        return Ok(value);
+    }
+    match value.get(1) {
+        Some(&CODEC_LZ4) => lz4_flex::decompress_size_prepended(&value[2..])
+            .map(IVec::from)
+            .map_err(|e| anyhow!("corrupted compressed value: {}", e)),
+        other => bail!("unknown compression codec {:?}", other),
+    }
+}
+
+/// 保存用户值（而非二进制元数据）的记录
+fn holds_value(key: &[u8]) -> bool {
+    [Kind::String, Kind::Hash, Kind::ListData]
+        .iter()
+        .any(|kind| key.starts_with(kind.tag()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip_and_threshold() -> Result<()> {
+        let lz4 = Compression::new(Codec::Lz4, 64);
+        let big = "{\"name\":\"crab\"}".repeat(20);
+
+        let stored = lz4.encode(b"string:k", big.as_bytes());
+        assert!(matches!(stored, Cow::Owned(_)));
+        assert!(stored.len() < big.len());
+        assert_eq!(decode(b"string:k", IVec::from(stored.as_ref()))?, big.as_bytes());
+
+        // 小于阈值、不可压缩的值，以及元数据记录都原样保存
+        assert!(matches!(lz4.encode(b"string:k", b"short"), Cow::Borrowed(_)));
+        let random: Vec<u8> = (0..200u32).map(|i| (i.wrapping_mul(2654435761) >> 13) as u8).collect();
+        assert!(matches!(lz4.encode(b"hash:h:f", &random), Cow::Borrowed(_)));
+        let meta = (-1i64).to_be_bytes().repeat(20);
+        assert!(matches!(lz4.encode(b"list:meta:l:head", &meta), Cow::Borrowed(_)));
+        assert_eq!(decode(b"list:meta:l:head", IVec::from(meta.as_slice()))?, meta.as_slice());
+
+        // 未开启压缩时照常解压已有的压缩记录
+        assert!(matches!(Compression::default().encode(b"string:k", big.as_bytes()), Cow::Borrowed(_)));
+        assert!(decode(b"string:k", IVec::from(&[FLAG, 9, 0][..])).is_err());
+        Ok(())
+    }
+}
@@ -1,15 +1,33 @@
 // src/engine/kv.rs
 
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::ops::Bound;
+use std::path::Path;
+use std::rc::Rc;
 use std::sync::Arc;
+use std::sync::atomic::AtomicU64;
 
-use anyhow::Error;
-use sled::{Db, IVec};
-use sled::transaction::TransactionalTree;
+use anyhow::{bail, Error, Result};
+use sled::{Db, IVec, Transactional, Tree};
+use sled::transaction::{
+    ConflictableTransactionError, ConflictableTransactionResult, TransactionError, TransactionalTree,
+};
 
+use crate::clock::{Clock, SystemClock};
+use crate::config::Config;
+use crate::engine::compress::{self, Compression};
+use crate::engine::memory::{MemoryEngine, MemoryTxn};
+use crate::engine::usage::KeyspaceUsage;
+use crate::engine::waiters::Waiters;
 use crate::engine::watch::WatchManager;
+use crate::keys::{self, Kind};
 
 /// 统一普通 Db 与事务上下文的最小 KV 抽象
 pub trait KvEngine {
+    /// `transaction` 中传给闭包的事务上下文
+    type Txn: KvEngine;
+
     /// GET key
     fn get(&self, key: &[u8]) -> Result<Option<IVec>, Error>;
     /// SET key -> value
@@ -19,102 +37,986 @@ pub trait KvEngine {
 
     fn scan_prefix(&self, prefix: &[u8]) -> Box<dyn Iterator<Item = Result<(IVec, IVec), Error>>>;
 
-    /// 如果底层是一个 sled::Db，就返回 Some(&Db)；否则（事务上下文）返回 None
-    fn as_db(&self) -> Option<&Db> {
+    /// 按 key 的顺序扫描以 `prefix` 开头、且大于 `after` 的记录，供分批遍历从上一批的末尾继续
+    ///
+    /// 默认实现在前缀扫描中跳过 `after` 及之前的记录；sled 与内存引擎（过期记录）直接定位到 `after` 之后
+    fn scan_prefix_after(&self, prefix: &[u8], after: &[u8]) -> Box<dyn Iterator<Item = Result<(IVec, IVec), Error>>> {
+        skip_through(self.scan_prefix(prefix), after)
+    }
+
+    /// 在一个原子事务中执行 `f`（MULTI/EXEC），冲突时由 sled 自动重试
+    fn transaction<T, F>(&self, f: F) -> Result<T, TransactionError<Error>>
+    where
+        F: Fn(&Self::Txn) -> ConflictableTransactionResult<T, Error>;
+
+    /// 与 `transaction` 相同，同时声明事务中的命令涉及的逻辑 key
+    ///
+    /// sled 事务执行期间无法遍历数据 tree，这些 key 的集合类型记录在事务开始前捕获，
+    /// 事务中对它们的前缀扫描（HGETALL、SMEMBERS 等）以此为准；其他引擎直接调用 `transaction`
+    fn transaction_for_keys<T, F>(&self, keys: &[&[u8]], f: F) -> Result<T, TransactionError<Error>>
+    where
+        F: Fn(&Self::Txn) -> ConflictableTransactionResult<T, Error>,
+    {
+        let _ = keys;
+        self.transaction(f)
+    }
+
+    /// 原子地应用一组写入
+    ///
+    /// sled 上是一次 `sled::Batch`，没有事务的读集合与冲突重试，刷盘次数也少得多；
+    /// 默认实现逐条写入，供本身已处于事务上下文中的引擎使用
+    fn apply_batch(&self, batch: &WriteBatch) -> Result<(), Error> {
+        for (key, value) in batch.ops() {
+            match value {
+                Some(value) => self.insert(key, value)?,
+                None => self.remove(key)?,
+            };
+        }
+        Ok(())
+    }
+
+    /// 如果底层是 sled，就返回数据所在的各个 Tree（句柄克隆只是引用计数 +1）；
+    /// 否则（事务上下文、内存引擎）返回 None
+    fn sled_trees(&self) -> Option<SledTrees> {
         None
     }
 
+    /// 单条写命令是否需要放进 `transaction` 中执行才能保证原子性
+    ///
+    /// 类型模块只在 `sled_trees` 返回 Tree 时自行开启事务，其他引擎（如内存引擎）
+    /// 由调用方整条命令包进事务
+    fn serialize_writes(&self) -> bool {
+        false
+    }
+
     // 获取底层数据库引用 （用于 WATCH/UNWATCH 机制）
     fn watch_manager(&self) -> Option<Arc<WatchManager>> {
         None
     }
 
+    /// 阻塞命令的等待队列，只有服务端使用的 DbInstance 才有
+    fn waiters(&self) -> Option<Arc<Waiters>> {
+        None
+    }
+
+    /// 键空间命中 / 过期统计，只有服务端使用的 DbInstance 才有
+    fn keyspace_stats(&self) -> Option<&KeyspaceStats> {
+        None
+    }
+
+    /// 按类型与 key 模式统计的键空间用量，只有 `Storage`（及包装它的 DbInstance）才有
+    fn keyspace_usage(&self) -> Option<&KeyspaceUsage> {
+        None
+    }
+
+    /// 判断过期使用的时钟；`Storage`（及基于它的事务上下文、DbInstance）可替换，其余引擎是系统时钟
+    fn clock(&self) -> &dyn Clock {
+        &SystemClock
+    }
+}
+
+/// 键空间统计，对应 INFO stats 中的同名字段
+#[derive(Debug, Default)]
+pub struct KeyspaceStats {
+    /// 只读命令访问到存在的 key 的次数
+    pub hits: AtomicU64,
+    /// 只读命令访问到不存在的 key 的次数
+    pub misses: AtomicU64,
+    /// 因过期被删除的 key 数
+    pub expired: AtomicU64,
+    /// 因内存上限被淘汰的 key 数（目前没有淘汰策略，始终为 0）
+    pub evicted: AtomicU64,
+}
+
+/// 跳过 `iter` 开头不大于 `after` 的记录
+pub(crate) fn skip_through(
+    iter: Box<dyn Iterator<Item = Result<(IVec, IVec), Error>>>,
+    after: &[u8],
+) -> Box<dyn Iterator<Item = Result<(IVec, IVec), Error>>> {
+    let after = after.to_vec();
+    Box::new(iter.skip_while(move |item| matches!(item, Ok((k, _)) if k.as_ref() <= after.as_slice())))
+}
+
+/// `scan_prefix_after` 在有序结构上的起点：`after` 排在 `prefix` 之前时从 `prefix` 开始
+pub(crate) fn start_after(prefix: &[u8], after: &[u8]) -> Bound<Vec<u8>> {
+    if after >= prefix { Bound::Excluded(after.to_vec()) } else { Bound::Included(prefix.to_vec()) }
+}
+
+/// 无法识别类型的记录所在的 sled tree（sled 的默认 tree），也是旧版本存放全部数据的 tree
+pub const DATA_TREE: &str = "";
+
+/// 一组写入，由 `KvEngine::apply_batch` 原子地应用；同一个 key 以最后一次写入为准
+#[derive(Debug, Clone, Default)]
+pub struct WriteBatch {
+    /// `None` 表示删除
+    ops: Vec<(Vec<u8>, Option<Vec<u8>>)>,
+}
+
+impl WriteBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, key: impl Into<Vec<u8>>, value: &[u8]) {
+        self.ops.push((key.into(), Some(value.to_vec())));
+    }
+
+    pub fn remove(&mut self, key: impl Into<Vec<u8>>) {
+        self.ops.push((key.into(), None));
+    }
+
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    /// 按加入的顺序遍历写入
+    pub fn ops(&self) -> impl Iterator<Item = (&[u8], Option<&[u8]>)> {
+        self.ops.iter().map(|(k, v)| (k.as_slice(), v.as_deref()))
+    }
+
+    /// 按记录所在的 tree 拆成 sled 的 Batch（键为 `SledTrees` 中的下标），值按 `compression` 压缩
+    fn to_sled(&self, compression: Compression) -> BTreeMap<usize, sled::Batch> {
+        let mut batches: BTreeMap<usize, sled::Batch> = BTreeMap::new();
+        for (key, value) in self.ops() {
+            let batch = batches.entry(SledTrees::index(key)).or_default();
+            match value {
+                Some(value) => batch.insert(key, compression.encode(key, value).as_ref()),
+                None => batch.remove(key),
+            }
+        }
+        batches
+    }
+}
+
+/// sled 上的数据 tree：各类型的记录放在 `keys::TREES` 中各自的 tree 里，
+/// 无法识别类型的记录放在 `DATA_TREE`
+///
+/// 带类型标签的前缀扫描只涉及该类型的 tree；跨类型的扫描依次遍历全部 tree，
+/// 由于 tree 按标签的字典序排列，得到的顺序与全部记录放在一个 tree 中时相同
+/// （`DATA_TREE` 中的记录排在最前）。
+#[derive(Clone)]
+pub struct SledTrees {
+    /// 下标 0 是 `DATA_TREE`，之后依次是 `keys::TREES`
+    trees: Vec<Tree>,
+}
+
+impl SledTrees {
+    pub fn open(db: &Db) -> sled::Result<Self> {
+        let mut trees = vec![db.open_tree(DATA_TREE)?];
+        for name in keys::TREES {
+            trees.push(db.open_tree(name)?);
+        }
+        Ok(SledTrees { trees })
+    }
+
+    /// 记录 `key` 所在的 tree 的下标
+    fn index(key: &[u8]) -> usize {
+        keys::kind_of(key).map_or(0, |kind| kind.tree() + 1)
+    }
+
+    /// 记录 `key` 所在的 tree
+    pub fn route(&self, key: &[u8]) -> &Tree {
+        &self.trees[Self::index(key)]
+    }
+
+    /// `kind` 类型的记录所在的 tree
+    pub fn of_kind(&self, kind: Kind) -> &Tree {
+        &self.trees[kind.tree() + 1]
+    }
+
+    /// 全部数据 tree，顺序见类型说明
+    pub fn all(&self) -> &[Tree] {
+        &self.trees
+    }
+
+    /// 全部记录数（sled 的 `len` 需要遍历 tree）
+    pub fn len(&self) -> usize {
+        self.trees.iter().map(Tree::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.trees.iter().all(Tree::is_empty)
+    }
+
+    pub fn clear(&self) -> sled::Result<()> {
+        self.trees.iter().try_for_each(Tree::clear)
+    }
+
+    /// 可能存放以 `prefix` 开头的记录的 tree
+    fn covering(&self, prefix: &[u8]) -> &[Tree] {
+        match keys::kind_of(prefix) {
+            Some(kind) => std::slice::from_ref(self.of_kind(kind)),
+            None => &self.trees,
+        }
+    }
+
+    fn scan_prefix(&self, prefix: &[u8]) -> Box<dyn Iterator<Item = Result<(IVec, IVec), Error>>> {
+        let iters: Vec<_> = self.covering(prefix).iter().map(|tree| tree.scan_prefix(prefix)).collect();
+        Box::new(iters.into_iter().flatten().map(|res| res.map_err(Into::into)))
+    }
+
+    /// 每个 tree 从 `after` 之后开始 range 扫描，到第一条不以 `prefix` 开头的记录为止
+    fn scan_prefix_after(&self, prefix: &[u8], after: &[u8]) -> Box<dyn Iterator<Item = Result<(IVec, IVec), Error>>> {
+        let start = start_after(prefix, after);
+        let iters: Vec<_> = self
+            .covering(prefix)
+            .iter()
+            .map(|tree| {
+                let prefix = prefix.to_vec();
+                tree.range((start.clone(), Bound::Unbounded))
+                    .take_while(move |res| !matches!(res, Ok((k, _)) if !k.starts_with(&prefix)))
+            })
+            .collect();
+        Box::new(iters.into_iter().flatten().map(|res| res.map_err(Into::into)))
+    }
+
+    /// 只涉及一个 tree 时直接用 sled 的 Batch，否则在覆盖全部 tree 的事务中应用
+    fn apply_batch(&self, batch: &WriteBatch, compression: Compression) -> Result<(), Error> {
+        let batches = batch.to_sled(compression);
+        if batches.len() <= 1 {
+            for (index, batch) in batches {
+                self.trees[index].apply_batch(batch)?;
+            }
+            return Ok(());
+        }
+        self.trees
+            .as_slice()
+            .transaction(|txs| {
+                for (index, batch) in &batches {
+                    txs[*index].apply_batch(batch)?;
+                }
+                Ok::<_, ConflictableTransactionError<Error>>(())
+            })
+            .map_err(|e| match e {
+                TransactionError::Abort(e) => e,
+                TransactionError::Storage(e) => e.into(),
+            })
+    }
+
+    /// 在全部数据 tree 上执行 sled 事务，`keys` 见 `KvEngine::transaction_for_keys`
+    fn transaction<T, F>(&self, compression: Compression, keys: &[&[u8]], f: F) -> Result<T, TransactionError<Error>>
+    where
+        F: Fn(&SledTxn) -> ConflictableTransactionResult<T, Error>,
+    {
+        let captured = Rc::new(Captured::capture(self, keys).map_err(TransactionError::Storage)?);
+        self.trees.as_slice().transaction(|txs| {
+            f(&SledTxn { txs: txs.clone(), compression, captured: captured.clone(), written: Rc::default() })
+        })
+    }
 }
 
+/// 直接在 Db 上操作时每次都要按名字打开各个数据 tree（加锁、分配），
+/// 只供测试与离线工具使用；服务器使用缓存了 tree 句柄的 `Storage`
 impl KvEngine for Db {
+    type Txn = SledTxn;
+
     fn get(&self, key: &[u8]) -> Result<Option<IVec>, Error> {
-        self.open_tree("")?.get(key).map_err(Into::into)
+        Ok(SledTrees::open(self)?.route(key).get(key)?)
     }
     fn insert(&self, key: &[u8], value: &[u8]) -> Result<Option<IVec>, Error> {
-        self.open_tree("")?.insert(key, value).map_err(Into::into)
+        Ok(SledTrees::open(self)?.route(key).insert(key, value)?)
     }
     fn remove(&self, key: &[u8]) -> Result<Option<IVec>, Error> {
-        self.open_tree("")?.remove(key).map_err(Into::into)
+        Ok(SledTrees::open(self)?.route(key).remove(key)?)
     }
 
     fn scan_prefix(&self, prefix: &[u8]) -> Box<dyn Iterator<Item = Result<(IVec, IVec), Error>>> {
-        Box::new(self.open_tree("").unwrap().scan_prefix(prefix).map(|res| res.map_err(Into::into)))
+        match SledTrees::open(self) {
+            Ok(trees) => trees.scan_prefix(prefix),
+            Err(e) => Box::new(std::iter::once(Err(e.into()))),
+        }
     }
 
-    fn as_db(&self) -> Option<&Db> {
-        Some(self)
+    fn scan_prefix_after(&self, prefix: &[u8], after: &[u8]) -> Box<dyn Iterator<Item = Result<(IVec, IVec), Error>>> {
+        match SledTrees::open(self) {
+            Ok(trees) => trees.scan_prefix_after(prefix, after),
+            Err(e) => Box::new(std::iter::once(Err(e.into()))),
+        }
     }
 
-    fn watch_manager(&self) -> Option<Arc<WatchManager>> {
-        None
+    fn transaction<T, F>(&self, f: F) -> Result<T, TransactionError<Error>>
+    where
+        F: Fn(&SledTxn) -> ConflictableTransactionResult<T, Error>,
+    {
+        SledTrees::open(self)?.transaction(Compression::default(), &[], f)
+    }
+
+    fn transaction_for_keys<T, F>(&self, keys: &[&[u8]], f: F) -> Result<T, TransactionError<Error>>
+    where
+        F: Fn(&SledTxn) -> ConflictableTransactionResult<T, Error>,
+    {
+        SledTrees::open(self)?.transaction(Compression::default(), keys, f)
+    }
+
+    fn apply_batch(&self, batch: &WriteBatch) -> Result<(), Error> {
+        SledTrees::open(self)?.apply_batch(batch, Compression::default())
+    }
+
+    fn sled_trees(&self) -> Option<SledTrees> {
+        SledTrees::open(self).ok()
+    }
+}
+
+/// 事务开始前记下的、逻辑 key 在各集合类型下的全部记录 key
+#[derive(Default)]
+struct Captured {
+    prefixes: Vec<Vec<u8>>,
+    records: BTreeSet<Vec<u8>>,
+}
+
+impl Captured {
+    fn capture(trees: &SledTrees, keys: &[&[u8]]) -> sled::Result<Self> {
+        let mut captured = Captured::default();
+        for key in keys {
+            for kind in [Kind::Hash, Kind::Set, Kind::ListData, Kind::ListMeta] {
+                let prefix = keys::prefix(kind, key);
+                for item in trees.of_kind(kind).scan_prefix(&prefix) {
+                    captured.records.insert(item?.0.to_vec());
+                }
+                captured.prefixes.push(prefix);
+            }
+        }
+        Ok(captured)
     }
 }
 
-impl KvEngine for TransactionalTree {
+/// 有序集合中以 `prefix` 开头的元素
+fn with_prefix<'a>(set: &'a BTreeSet<Vec<u8>>, prefix: &'a [u8]) -> impl Iterator<Item = &'a Vec<u8>> {
+    set.range(prefix.to_vec()..).take_while(move |k| k.starts_with(prefix))
+}
+
+/// sled 上的事务上下文，写入的值按 `compression` 压缩
+///
+/// sled 在事务闭包执行期间持有全局锁，事务内无法遍历数据 tree。前缀扫描的 key 集合
+/// 取自事务开始前捕获的记录（见 `KvEngine::transaction_for_keys`）加上本事务写入过的记录，
+/// 值则逐条经由事务读取，因此读到的是事务内的最新值，并参与冲突检测。
+#[derive(Clone)]
+pub struct SledTxn {
+    /// 与 `SledTrees` 中的 tree 一一对应
+    txs: Vec<TransactionalTree>,
+    compression: Compression,
+    captured: Rc<Captured>,
+    /// 本事务写入或删除过的记录 key，sled 重试事务时随上下文一起重建
+    written: Rc<RefCell<BTreeSet<Vec<u8>>>>,
+}
+
+impl SledTxn {
+    /// 记录 `key` 所在的 tree 上的事务视图
+    fn tx(&self, key: &[u8]) -> &TransactionalTree {
+        &self.txs[SledTrees::index(key)]
+    }
+}
+
+impl KvEngine for SledTxn {
+    type Txn = SledTxn;
+
     fn get(&self, key: &[u8]) -> Result<Option<IVec>, Error> {
-        TransactionalTree::get(self, key).map_err(Error::from)
+        decode(key, self.tx(key).get(key)?)
     }
+
     fn insert(&self, key: &[u8], value: &[u8]) -> Result<Option<IVec>, Error> {
-        TransactionalTree::insert(self, key, value).map_err(Error::from)
+        self.written.borrow_mut().insert(key.to_vec());
+        decode(key, self.tx(key).insert(key, self.compression.encode(key, value).as_ref())?)
     }
+
     fn remove(&self, key: &[u8]) -> Result<Option<IVec>, Error> {
-        TransactionalTree::remove(self, key).map_err(Error::from)
+        self.written.borrow_mut().insert(key.to_vec());
+        decode(key, self.tx(key).remove(key)?)
     }
 
-    // 事务操作暂不支持扫描，返回空迭代器
-    fn scan_prefix(&self, _prefix: &[u8]) -> Box<dyn Iterator<Item = Result<(IVec, IVec), Error>>> {
-        Box::new(std::iter::empty()) // 或返回错误
+    fn scan_prefix(&self, prefix: &[u8]) -> Box<dyn Iterator<Item = Result<(IVec, IVec), Error>>> {
+        if !self.captured.prefixes.iter().any(|p| prefix.starts_with(p)) {
+            return Box::new(std::iter::once(Err(Error::msg(
+                "prefix scans inside a transaction are limited to the keys of its commands",
+            ))));
+        }
+        let written = self.written.borrow();
+        let candidates: BTreeSet<&Vec<u8>> =
+            with_prefix(&self.captured.records, prefix).chain(with_prefix(&written, prefix)).collect();
+        let mut out = Vec::with_capacity(candidates.len());
+        for key in candidates {
+            match self.get(key) {
+                Ok(Some(value)) => out.push(Ok((IVec::from(key.as_slice()), value))),
+                Ok(None) => {}
+                Err(e) => {
+                    out.push(Err(e));
+                    break;
+                }
+            }
+        }
+        Box::new(out.into_iter())
     }
 
-    fn as_db(&self) -> Option<&Db> {
-        None
+    // 已经处于事务上下文中，不支持嵌套事务
+    fn transaction<T, F>(&self, _f: F) -> Result<T, TransactionError<Error>>
+    where
+        F: Fn(&SledTxn) -> ConflictableTransactionResult<T, Error>,
+    {
+        Err(TransactionError::Abort(Error::msg("nested transactions are not supported")))
     }
+}
 
-    fn watch_manager(&self) -> Option<Arc<WatchManager>> {
-        None
+/// 按配置 `storage` 选择的存储引擎，附带增量维护的键空间用量（STATS KEYSPACE）
+#[derive(Clone)]
+pub struct Storage {
+    backend: Backend,
+    usage: Arc<KeyspaceUsage>,
+    clock: Arc<dyn Clock>,
+}
+
+#[derive(Clone)]
+enum Backend {
+    /// `trees` 是打开后缓存的数据 tree 句柄；`db` 保持数据库（及其后台刷盘线程）存活；
+    /// 写入的值按 `compression` 压缩
+    Sled { db: Db, trees: SledTrees, compression: Compression },
+    Memory(MemoryEngine),
+}
+
+/// 一条记录的变化：记录 key、写入前后值的长度
+type Change = (Vec<u8>, Option<usize>, Option<usize>);
+
+impl Storage {
+    /// 在打开的 sled 数据库上使用各数据 tree，不压缩新写入的值
+    pub fn sled(db: Db) -> Result<Self> {
+        Self::sled_with(db, Compression::default())
+    }
+
+    pub fn sled_with(db: Db, compression: Compression) -> Result<Self> {
+        Self::open_sled(db, compression, KeyspaceUsage::default())
+    }
+
+    /// 空的内存引擎
+    pub fn memory() -> Self {
+        Self::with_usage(Backend::Memory(MemoryEngine::new()), KeyspaceUsage::default())
+    }
+
+    /// 打开已有数据时扫描一次，建立键空间用量的初始值
+    fn open_sled(db: Db, compression: Compression, usage: KeyspaceUsage) -> Result<Self> {
+        let trees = SledTrees::open(&db)?;
+        let storage = Self::with_usage(Backend::Sled { db, trees, compression }, usage);
+        storage.usage.rebuild(&storage)?;
+        Ok(storage)
+    }
+
+    fn with_usage(backend: Backend, usage: KeyspaceUsage) -> Self {
+        Storage { backend, usage: Arc::new(usage), clock: Arc::new(SystemClock) }
+    }
+
+    /// 换用 `clock` 判断过期（测试中用 `MockClock` 拨动时间），须在克隆出其他句柄之前调用
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// 按配置 `storage`（`sled` / `memory`）打开存储引擎，sled 的数据放在 `path` 目录下
+    ///
+    /// 旧版本写入的 sled 数据在这里一次性改写成当前的记录布局、搬到各类型的 tree（见 `keys` 模块）
+    pub fn open(cfg: &Config, path: &Path) -> Result<Self> {
+        let compression = Compression::from_config(cfg)?;
+        let usage = KeyspaceUsage::new(&cfg.keyspace_stats_patterns);
+        match cfg.storage.as_str() {
+            "sled" => {
+                let db = sled::open(path)?;
+                let upgraded = keys::upgrade_layout(&db, &db.open_tree(DATA_TREE)?)?;
+                if upgraded > 0 {
+                    tracing::info!("moved {} records to the current key layout", upgraded);
+                }
+                Self::open_sled(db, compression, usage)
+            }
+            "memory" => Ok(Self::with_usage(Backend::Memory(MemoryEngine::new()), usage)),
+            other => bail!("unknown storage '{}', expected \"sled\" or \"memory\"", other),
+        }
+    }
+
+    /// 把写入刷到磁盘，内存引擎无事可做
+    pub fn flush(&self) -> Result<()> {
+        if let Backend::Sled { db, .. } = &self.backend {
+            db.flush()?;
+        }
+        Ok(())
+    }
+
+    /// 清空全部记录
+    pub fn clear(&self) -> Result<()> {
+        match &self.backend {
+            Backend::Sled { trees, .. } => trees.clear()?,
+            Backend::Memory(mem) => mem.clear(),
+        }
+        self.usage.reset();
+        Ok(())
+    }
+}
+
+/// `Storage` 上的事务上下文，写入的变化在事务提交后计入键空间用量
+#[derive(Clone)]
+pub struct StorageTxn {
+    backend: TxnBackend,
+    changes: Rc<RefCell<Vec<Change>>>,
+    clock: Arc<dyn Clock>,
+}
+
+#[derive(Clone)]
+enum TxnBackend {
+    Sled(SledTxn),
+    Memory(MemoryTxn),
+}
+
+/// 解压读到的值
+fn decode(key: &[u8], value: Option<IVec>) -> Result<Option<IVec>> {
+    value.map(|v| compress::decode(key, v)).transpose()
+}
+
+/// 解压扫描到的值
+fn decode_all(
+    iter: Box<dyn Iterator<Item = Result<(IVec, IVec), Error>>>,
+) -> Box<dyn Iterator<Item = Result<(IVec, IVec), Error>>> {
+    Box::new(iter.map(|item| {
+        let (k, v) = item?;
+        let v = compress::decode(&k, v)?;
+        Ok((k, v))
+    }))
+}
+
+impl KvEngine for Storage {
+    type Txn = StorageTxn;
+
+    fn get(&self, key: &[u8]) -> Result<Option<IVec>, Error> {
+        match &self.backend {
+            Backend::Sled { trees, .. } => decode(key, trees.route(key).get(key)?),
+            Backend::Memory(mem) => mem.get(key),
+        }
+    }
+
+    fn insert(&self, key: &[u8], value: &[u8]) -> Result<Option<IVec>, Error> {
+        let old = match &self.backend {
+            Backend::Sled { trees, compression, .. } => {
+                decode(key, trees.route(key).insert(key, compression.encode(key, value).as_ref())?)?
+            }
+            Backend::Memory(mem) => mem.insert(key, value)?,
+        };
+        self.usage.record(key, old.as_ref().map(|v| v.len()), Some(value.len()));
+        Ok(old)
+    }
+
+    fn remove(&self, key: &[u8]) -> Result<Option<IVec>, Error> {
+        let old = match &self.backend {
+            Backend::Sled { trees, .. } => decode(key, trees.route(key).remove(key)?)?,
+            Backend::Memory(mem) => mem.remove(key)?,
+        };
+        self.usage.record(key, old.as_ref().map(|v| v.len()), None);
+        Ok(old)
+    }
+
+    fn scan_prefix(&self, prefix: &[u8]) -> Box<dyn Iterator<Item = Result<(IVec, IVec), Error>>> {
+        match &self.backend {
+            Backend::Sled { trees, .. } => decode_all(trees.scan_prefix(prefix)),
+            Backend::Memory(mem) => mem.scan_prefix(prefix),
+        }
+    }
+
+    fn scan_prefix_after(&self, prefix: &[u8], after: &[u8]) -> Box<dyn Iterator<Item = Result<(IVec, IVec), Error>>> {
+        match &self.backend {
+            Backend::Sled { trees, .. } => decode_all(trees.scan_prefix_after(prefix, after)),
+            Backend::Memory(mem) => mem.scan_prefix_after(prefix, after),
+        }
+    }
+
+    fn transaction<T, F>(&self, f: F) -> Result<T, TransactionError<Error>>
+    where
+        F: Fn(&StorageTxn) -> ConflictableTransactionResult<T, Error>,
+    {
+        self.transaction_for_keys(&[], f)
+    }
+
+    fn transaction_for_keys<T, F>(&self, keys: &[&[u8]], f: F) -> Result<T, TransactionError<Error>>
+    where
+        F: Fn(&StorageTxn) -> ConflictableTransactionResult<T, Error>,
+    {
+        // 冲突重试时每次执行都重新记录，只有最后一次（提交的那次）的变化计入
+        let committed = RefCell::new(Vec::new());
+        let run = |backend: TxnBackend| {
+            let tx = StorageTxn { backend, changes: Rc::default(), clock: self.clock.clone() };
+            let res = f(&tx);
+            *committed.borrow_mut() = tx.changes.take();
+            res
+        };
+        let res = match &self.backend {
+            Backend::Sled { trees, compression, .. } => {
+                trees.transaction(*compression, keys, |tx| run(TxnBackend::Sled(tx.clone())))
+            }
+            Backend::Memory(mem) => mem.transaction(|tx| run(TxnBackend::Memory(tx.clone()))),
+        }?;
+        for (key, old, new) in committed.into_inner() {
+            self.usage.record(&key, old, new);
+        }
+        Ok(res)
+    }
+
+    fn apply_batch(&self, batch: &WriteBatch) -> Result<(), Error> {
+        // 先按批内的顺序读出每条写入前的值长度
+        let mut current: HashMap<&[u8], Option<usize>> = HashMap::new();
+        let mut changes = Vec::with_capacity(batch.len());
+        for (key, value) in batch.ops() {
+            let old = match current.get(key) {
+                Some(len) => *len,
+                None => self.get(key)?.map(|v| v.len()),
+            };
+            let new = value.map(<[u8]>::len);
+            current.insert(key, new);
+            changes.push((key, old, new));
+        }
+        match &self.backend {
+            Backend::Sled { trees, compression, .. } => trees.apply_batch(batch, *compression)?,
+            Backend::Memory(mem) => mem.apply_batch(batch)?,
+        }
+        for (key, old, new) in changes {
+            self.usage.record(key, old, new);
+        }
+        Ok(())
+    }
+
+    fn sled_trees(&self) -> Option<SledTrees> {
+        match &self.backend {
+            Backend::Sled { trees, .. } => Some(trees.clone()),
+            Backend::Memory(_) => None,
+        }
+    }
+
+    fn serialize_writes(&self) -> bool {
+        matches!(self.backend, Backend::Memory(_))
+    }
+
+    fn keyspace_usage(&self) -> Option<&KeyspaceUsage> {
+        Some(&self.usage)
+    }
+
+    fn clock(&self) -> &dyn Clock {
+        self.clock.as_ref()
+    }
+}
+
+impl KvEngine for StorageTxn {
+    type Txn = StorageTxn;
+
+    fn get(&self, key: &[u8]) -> Result<Option<IVec>, Error> {
+        match &self.backend {
+            TxnBackend::Sled(tx) => tx.get(key),
+            TxnBackend::Memory(tx) => tx.get(key),
+        }
+    }
+
+    fn insert(&self, key: &[u8], value: &[u8]) -> Result<Option<IVec>, Error> {
+        let old = match &self.backend {
+            TxnBackend::Sled(tx) => tx.insert(key, value)?,
+            TxnBackend::Memory(tx) => tx.insert(key, value)?,
+        };
+        self.changes.borrow_mut().push((key.to_vec(), old.as_ref().map(|v| v.len()), Some(value.len())));
+        Ok(old)
+    }
+
+    fn remove(&self, key: &[u8]) -> Result<Option<IVec>, Error> {
+        let old = match &self.backend {
+            TxnBackend::Sled(tx) => tx.remove(key)?,
+            TxnBackend::Memory(tx) => tx.remove(key)?,
+        };
+        self.changes.borrow_mut().push((key.to_vec(), old.as_ref().map(|v| v.len()), None));
+        Ok(old)
+    }
+
+    fn scan_prefix(&self, prefix: &[u8]) -> Box<dyn Iterator<Item = Result<(IVec, IVec), Error>>> {
+        match &self.backend {
+            TxnBackend::Sled(tx) => tx.scan_prefix(prefix),
+            TxnBackend::Memory(tx) => tx.scan_prefix(prefix),
+        }
+    }
+
+    // 已经处于事务上下文中，不支持嵌套事务
+    fn transaction<T, F>(&self, _f: F) -> Result<T, TransactionError<Error>>
+    where
+        F: Fn(&StorageTxn) -> ConflictableTransactionResult<T, Error>,
+    {
+        Err(TransactionError::Abort(Error::msg("nested transactions are not supported")))
+    }
+
+    fn clock(&self) -> &dyn Clock {
+        self.clock.as_ref()
     }
 }
 
-/// 数据库实例，包含 sled 数据库和监视管理器
+/// 数据库实例，包含存储引擎、监视管理器、阻塞命令的等待队列与键空间统计
 #[derive(Clone)]
 pub struct DbInstance {
-    pub db: sled::Db,
+    pub db: Storage,
     pub watch_manager: Arc<WatchManager>,
+    pub waiters: Arc<Waiters>,
+    pub stats: Arc<KeyspaceStats>,
+}
+
+impl DbInstance {
+    /// 记录被修改：按逻辑 key 通知监视者，一个 key 各类型的记录（含过期时间）都归到同一个 key
+    fn notify_record(&self, record: &[u8]) {
+        if let Some(record) = keys::decode(record) {
+            self.watch_manager.notify_key_change(record.key);
+        }
+    }
 }
 
 impl KvEngine for DbInstance {
+    type Txn = StorageTxn;
+
     fn get(&self, key: &[u8]) -> Result<Option<IVec>, Error> {
-        self.db.get(key).map_err(Into::into)
+        self.db.get(key)
     }
     
     fn insert(&self, key: &[u8], value: &[u8]) -> Result<Option<IVec>, Error> {
         let res = self.db.insert(key, value)?;
-        let key_str = String::from_utf8_lossy(key);
-        self.watch_manager.notify_key_change(&key_str);
+        self.notify_record(key);
         Ok(res)
     }
     
     fn remove(&self, key: &[u8]) -> Result<Option<IVec>, Error> {
         let res = self.db.remove(key)?;
-        let key_str = String::from_utf8_lossy(key);
-        self.watch_manager.notify_key_change(&key_str);
+        self.notify_record(key);
         Ok(res)
     }
+
+    fn apply_batch(&self, batch: &WriteBatch) -> Result<(), Error> {
+        self.db.apply_batch(batch)?;
+        // 同一个 key 的记录在批次中通常相邻，只通知一次
+        let mut last = None;
+        for (record, _) in batch.ops() {
+            let key = keys::decode(record).map(|r| r.key);
+            if let Some(k) = key
+                && key != last
+            {
+                self.watch_manager.notify_key_change(k);
+            }
+            last = key;
+        }
+        Ok(())
+    }
     
     fn scan_prefix(&self, prefix: &[u8]) -> Box<dyn Iterator<Item = Result<(IVec, IVec), Error>>> {
-        Box::new(self.db.scan_prefix(prefix).map(|res| res.map_err(Into::into)))
+        self.db.scan_prefix(prefix)
+    }
+
+    fn scan_prefix_after(&self, prefix: &[u8], after: &[u8]) -> Box<dyn Iterator<Item = Result<(IVec, IVec), Error>>> {
+        self.db.scan_prefix_after(prefix, after)
+    }
+
+    fn transaction<T, F>(&self, f: F) -> Result<T, TransactionError<Error>>
+    where
+        F: Fn(&StorageTxn) -> ConflictableTransactionResult<T, Error>,
+    {
+        self.db.transaction(f)
+    }
+
+    fn transaction_for_keys<T, F>(&self, keys: &[&[u8]], f: F) -> Result<T, TransactionError<Error>>
+    where
+        F: Fn(&StorageTxn) -> ConflictableTransactionResult<T, Error>,
+    {
+        self.db.transaction_for_keys(keys, f)
     }
     
-    fn as_db(&self) -> Option<&Db> {
-        Some(&self.db)
+    fn sled_trees(&self) -> Option<SledTrees> {
+        self.db.sled_trees()
+    }
+
+    fn serialize_writes(&self) -> bool {
+        self.db.serialize_writes()
     }
     
     fn watch_manager(&self) -> Option<Arc<WatchManager>> {
         Some(self.watch_manager.clone())
     }
-}
\ No newline at end of file
+
+    fn waiters(&self) -> Option<Arc<Waiters>> {
+        Some(self.waiters.clone())
+    }
+
+    fn keyspace_stats(&self) -> Option<&KeyspaceStats> {
+        Some(&self.stats)
+    }
+
+    fn keyspace_usage(&self) -> Option<&KeyspaceUsage> {
+        self.db.keyspace_usage()
+    }
+
+    fn clock(&self) -> &dyn Clock {
+        self.db.clock()
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use crate::engine::compress::Codec;
+    use sled::transaction::ConflictableTransactionError;
+    use crate::protocol::Frame;
+    use crate::txn::executor::exec_all;
+    use crate::types::{hash, list, set, string};
+
+    // 缓存的 tree 与直接在 Db 上操作的是同一组数据 tree，已有数据无需迁移
+    #[test]
+    fn test_storage_shares_data_trees_with_db() -> Result<()> {
+        let db = sled::Config::new().temporary(true).open()?;
+        string::set(&db, b"a", b"1")?;
+
+        let storage = Storage::sled(db.clone())?;
+        assert_eq!(string::get(&storage, b"a")?.as_deref(), Some(&b"1"[..]));
+        string::incr(&storage, b"a")?;
+        assert_eq!(string::get(&db, b"a")?.as_deref(), Some(&b"2"[..]));
+
+        // 各类型的记录落在各自的 tree 中，跨类型扫描的顺序与单个 tree 相同
+        hash::hset(&storage, b"h", b"f", b"v")?;
+        list::rpush(&storage, b"l", b"x")?;
+        assert_eq!(db.open_tree(keys::TREES[Kind::String.tree()])?.len(), 1);
+        assert_eq!(db.open_tree(keys::TREES[Kind::ListData.tree()])?.len(), 3);
+        assert!(db.open_tree(DATA_TREE)?.is_empty());
+        let all: Vec<IVec> = storage.scan_prefix(b"").map(|item| item.map(|(k, _)| k)).collect::<Result<_>>()?;
+        assert_eq!(all.len(), 5);
+        assert!(all.windows(2).all(|w| w[0] < w[1]));
+
+        storage.clear()?;
+        assert!(SledTrees::open(&db)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_compressed_values() -> Result<()> {
+        let db = sled::Config::new().temporary(true).open()?;
+        // 开启压缩前写入的记录
+        string::set(&db, b"old", "x".repeat(100).as_bytes())?;
+
+        let storage = Storage::sled_with(db.clone(), Compression::new(Codec::Lz4, 32))?;
+        let big = "y".repeat(100);
+        string::set(&storage, b"big", big.as_bytes())?;
+        hash::hset(&storage, b"h", b"f", big.as_bytes())?;
+        let trees = SledTrees::open(&db)?;
+        let raw = trees.of_kind(Kind::String).get(keys::string(b"big"))?.unwrap();
+        assert!(raw[0] == 0xFF && raw.len() < big.len());
+
+        assert_eq!(string::get(&storage, b"old")?.as_deref(), Some("x".repeat(100).as_bytes()));
+        assert_eq!(string::get(&storage, b"big")?.as_deref(), Some(big.as_bytes()));
+        assert_eq!(hash::hgetall(&storage, b"h")?, vec![(b"f".to_vec(), big.clone().into_bytes())]);
+
+        // 事务中同样透明
+        let cmd = |parts: &[&str]| parts.iter().map(|s| Bytes::copy_from_slice(s.as_bytes())).collect::<Vec<_>>();
+        let reply = exec_all(&storage, &[cmd(&["SET", "t", &big]), cmd(&["GET", "t"])]);
+        assert_eq!(reply, Frame::Array(vec![Frame::ok(), Frame::bulk(big.as_str())]));
+        assert_eq!(trees.of_kind(Kind::String).get(keys::string(b"t"))?.unwrap()[0], 0xFF);
+
+        // 关闭压缩后仍能读取已压缩的记录
+        assert_eq!(string::get(&Storage::sled(db)?, b"big")?.as_deref(), Some(big.as_bytes()));
+        Ok(())
+    }
+
+    // 事务中的集合读取能看到已提交的数据与本事务之前的写入
+    #[test]
+    fn test_scan_in_transaction() -> Result<()> {
+        let db = sled::Config::new().temporary(true).open()?;
+        let storage = Storage::sled_with(db, Compression::new(Codec::Lz4, 16))?;
+        let big = "v".repeat(64);
+        hash::hset(&storage, b"h", b"a", big.as_bytes())?;
+        hash::hset(&storage, b"h", b"b", b"2")?;
+        set::sadd(&storage, b"s", b"x")?;
+        list::rpush(&storage, b"l", b"x")?;
+
+        let cmd = |parts: &[&str]| parts.iter().map(|s| Bytes::copy_from_slice(s.as_bytes())).collect::<Vec<_>>();
+        let reply = exec_all(
+            &storage,
+            &[
+                cmd(&["HDEL", "h", "b"]),
+                cmd(&["HSET", "h", "c", "3"]),
+                cmd(&["HGETALL", "h"]),
+                cmd(&["SADD", "s", "y"]),
+                cmd(&["SMEMBERS", "s"]),
+                cmd(&["RPUSH", "l", "y"]),
+                cmd(&["LRANGE", "l", "0", "-1"]),
+            ],
+        );
+        let Frame::Array(replies) = reply else { panic!("expected array, got {:?}", reply) };
+        assert_eq!(
+            replies[2],
+            Frame::Map(vec![(Frame::bulk("a"), Frame::bulk(big.as_str())), (Frame::bulk("c"), Frame::bulk("3"))])
+        );
+        assert_eq!(replies[4], Frame::Set(vec![Frame::bulk("x"), Frame::bulk("y")]));
+        assert_eq!(replies[6], Frame::Array(vec![Frame::bulk("x"), Frame::bulk("y")]));
+
+        // 中止的事务不留下任何写入
+        let reply = exec_all(&storage, &[cmd(&["SADD", "s", "z"]), cmd(&["NOSUCHCMD"])]);
+        assert!(reply.is_error());
+        assert_eq!(set::smembers(&storage, b"s")?, vec![b"x".to_vec(), b"y".to_vec()]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_batch() -> Result<()> {
+        let db = sled::Config::new().temporary(true).open()?;
+        let sled = Storage::sled_with(db.clone(), Compression::new(Codec::Lz4, 32))?;
+        let big = "z".repeat(100);
+        for storage in [sled, Storage::memory()] {
+            string::set(&storage, b"gone", b"1")?;
+            let mut batch = WriteBatch::new();
+            batch.insert(keys::string(b"a"), b"1");
+            batch.insert(keys::string(b"big"), big.as_bytes());
+            batch.remove(keys::string(b"gone"));
+            // 同一个 key 以最后一次写入为准
+            batch.insert(keys::string(b"a"), b"2");
+            // 涉及多个类型的 tree
+            batch.insert(keys::hash_field(b"h", b"f"), b"v");
+            storage.apply_batch(&batch)?;
+            assert_eq!(string::get(&storage, b"a")?.as_deref(), Some(&b"2"[..]));
+            assert_eq!(hash::hget(&storage, b"h", b"f")?.as_deref(), Some(&b"v"[..]));
+            assert_eq!(string::get(&storage, b"big")?.as_deref(), Some(big.as_bytes()));
+            assert_eq!(string::get(&storage, b"gone")?, None);
+
+            // 事务中的批量写入随事务一起提交
+            let mut batch = WriteBatch::new();
+            batch.insert(keys::string(b"t"), b"v");
+            storage
+                .transaction(|tx| tx.apply_batch(&batch).map_err(ConflictableTransactionError::Abort))
+                .map_err(|e| anyhow::anyhow!("{:?}", e))?;
+            assert_eq!(string::get(&storage, b"t")?.as_deref(), Some(&b"v"[..]));
+        }
+        // sled 上的批量写入同样按配置压缩
+        let strings = SledTrees::open(&db)?.of_kind(Kind::String).clone();
+        assert_eq!(strings.get(keys::string(b"big"))?.unwrap()[0], 0xFF);
+        Ok(())
+    }
+
+    #[test]
+    fn test_scan_prefix_after() -> Result<()> {
+        fn scan<E: KvEngine>(db: &E, prefix: &[u8], after: &[u8]) -> Result<Vec<Vec<u8>>> {
+            db.scan_prefix_after(prefix, after).map(|item| Ok(item?.0.to_vec())).collect()
+        }
+        let expires = |names: &[&str]| names.iter().map(|k| keys::expire(k.as_bytes())).collect::<Vec<_>>();
+
+        let db = sled::Config::new().temporary(true).open()?;
+        let sled = Storage::sled_with(db, Compression::new(Codec::Lz4, 4))?;
+        for storage in [sled, Storage::memory()] {
+            for key in ["c", "a", "b", "d"] {
+                string::set(&storage, key.as_bytes(), b"v")?;
+                crate::expire::expire_at(&storage, key.as_bytes(), 1)?;
+            }
+            let tag = Kind::Expire.tag();
+            // 游标排在前缀之前时从头开始
+            assert_eq!(scan(&storage, tag, b"")?, expires(&["a", "b", "c", "d"]));
+            assert_eq!(scan(&storage, tag, &keys::expire(b"b"))?, expires(&["c", "d"]));
+            assert!(scan(&storage, tag, &keys::expire(b"d"))?.is_empty());
+            // 删除的过期记录不再出现
+            crate::expire::persist(&storage, b"c")?;
+            assert_eq!(scan(&storage, tag, &keys::expire(b"a"))?, expires(&["b", "d"]));
+            // 其他类型的记录同样从游标之后开始，且不越过前缀
+            assert_eq!(
+                scan(&storage, Kind::String.tag(), &keys::string(b"b"))?,
+                vec![keys::string(b"c"), keys::string(b"d")]
+            );
+        }
+        Ok(())
+    }
+}
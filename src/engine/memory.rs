@@ -0,0 +1,323 @@
+// src/engine/memory.rs
+
+//! 纯内存存储引擎（配置 `storage = "memory"`）
+//!
+//! 记录放在 DashMap 中，不写 sled 的数据目录，持久化完全依赖 AOF / RDB，
+//! 省去 sled 的写放大。DashMap 是无序的，前缀扫描要遍历全部记录再排序，
+//! 代价与数据集大小成正比。过期记录另有一份有序的 key 索引，主动过期每轮只需
+//! 从上次的位置往后取一批。
+//!
+//! 事务之间用一把全局锁互斥：事务内的写入先缓冲，成功时一次性应用，失败时丢弃。
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Bound;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Error;
+use dashmap::DashMap;
+use dashmap::mapref::entry::Entry;
+use sled::IVec;
+use sled::transaction::{ConflictableTransactionError, ConflictableTransactionResult, TransactionError};
+
+use crate::engine::KvEngine;
+use crate::engine::kv::{self, WriteBatch};
+use crate::keys::{self, Kind};
+
+/// 全部记录，以及其中过期记录的有序 key 索引
+#[derive(Default)]
+struct Records {
+    map: DashMap<Vec<u8>, IVec>,
+    /// `Kind::Expire` 记录的 key；在持有 `map` 对应分片写锁时更新，与 `map` 保持一致
+    expires: Mutex<BTreeSet<Vec<u8>>>,
+}
+
+fn is_expire(key: &[u8]) -> bool {
+    keys::kind_of(key) == Some(Kind::Expire)
+}
+
+impl Records {
+    fn expires(&self) -> std::sync::MutexGuard<'_, BTreeSet<Vec<u8>>> {
+        self.expires.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn get(&self, key: &[u8]) -> Option<IVec> {
+        self.map.get(key).map(|v| v.clone())
+    }
+
+    fn insert(&self, key: Vec<u8>, value: IVec) -> Option<IVec> {
+        match self.map.entry(key) {
+            Entry::Occupied(mut entry) => Some(entry.insert(value)),
+            Entry::Vacant(entry) => {
+                if is_expire(entry.key()) {
+                    self.expires().insert(entry.key().clone());
+                }
+                entry.insert(value);
+                None
+            }
+        }
+    }
+
+    fn remove(&self, key: &[u8]) -> Option<IVec> {
+        if !is_expire(key) {
+            return self.map.remove(key).map(|(_, v)| v);
+        }
+        match self.map.entry(key.to_vec()) {
+            Entry::Occupied(entry) => {
+                self.expires().remove(key);
+                Some(entry.remove())
+            }
+            Entry::Vacant(_) => None,
+        }
+    }
+
+    fn clear(&self) {
+        self.map.clear();
+        self.expires().clear();
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct MemoryEngine {
+    records: Arc<Records>,
+    /// 事务（包括需要原子执行的单条写命令）之间互斥
+    txn_lock: Arc<Mutex<()>>,
+}
+
+impl MemoryEngine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 底层记录数
+    pub fn len(&self) -> usize {
+        self.records.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.map.is_empty()
+    }
+
+    pub fn clear(&self) {
+        self.records.clear();
+    }
+}
+
+/// 收集 `records` 中以 `prefix` 开头的记录，按 key 排序（与 sled 的扫描顺序一致）
+fn scan_sorted(records: &Records, prefix: &[u8]) -> BTreeMap<Vec<u8>, IVec> {
+    records
+        .map
+        .iter()
+        .filter(|entry| entry.key().starts_with(prefix))
+        .map(|entry| (entry.key().clone(), entry.value().clone()))
+        .collect()
+}
+
+impl KvEngine for MemoryEngine {
+    type Txn = MemoryTxn;
+
+    fn get(&self, key: &[u8]) -> Result<Option<IVec>, Error> {
+        Ok(self.records.get(key))
+    }
+
+    fn insert(&self, key: &[u8], value: &[u8]) -> Result<Option<IVec>, Error> {
+        Ok(self.records.insert(key.to_vec(), IVec::from(value)))
+    }
+
+    fn remove(&self, key: &[u8]) -> Result<Option<IVec>, Error> {
+        Ok(self.records.remove(key))
+    }
+
+    fn scan_prefix(&self, prefix: &[u8]) -> Box<dyn Iterator<Item = Result<(IVec, IVec), Error>>> {
+        let matched = scan_sorted(&self.records, prefix);
+        Box::new(matched.into_iter().map(|(k, v)| Ok((IVec::from(k), v))))
+    }
+
+    /// 过期记录沿索引逐条往后取，每次只短暂持有索引的锁；其他记录没有有序索引，退回到前缀扫描
+    fn scan_prefix_after(&self, prefix: &[u8], after: &[u8]) -> Box<dyn Iterator<Item = Result<(IVec, IVec), Error>>> {
+        if !is_expire(prefix) {
+            return kv::skip_through(self.scan_prefix(prefix), after);
+        }
+        let records = self.records.clone();
+        let prefix = prefix.to_vec();
+        let mut start = kv::start_after(&prefix, after);
+        Box::new(std::iter::from_fn(move || {
+            loop {
+                let key = records.expires().range((start.clone(), Bound::Unbounded)).next().cloned()?;
+                if !key.starts_with(&prefix) {
+                    return None;
+                }
+                start = Bound::Excluded(key.clone());
+                // 取出 key 之后记录可能已被删除
+                if let Some(value) = records.get(&key) {
+                    return Some(Ok((IVec::from(key), value)));
+                }
+            }
+        }))
+    }
+
+    fn transaction<T, F>(&self, f: F) -> Result<T, TransactionError<Error>>
+    where
+        F: Fn(&MemoryTxn) -> ConflictableTransactionResult<T, Error>,
+    {
+        let _guard = self.txn_lock.lock().unwrap_or_else(|e| e.into_inner());
+        loop {
+            let txn = MemoryTxn { records: self.records.clone(), writes: Rc::default() };
+            match f(&txn) {
+                Ok(value) => {
+                    txn.commit();
+                    return Ok(value);
+                }
+                Err(ConflictableTransactionError::Abort(e)) => return Err(TransactionError::Abort(e)),
+                Err(ConflictableTransactionError::Storage(e)) => return Err(TransactionError::Storage(e)),
+                // 持有全局锁时不会发生冲突，照 sled 的约定重试
+                Err(_) => continue,
+            }
+        }
+    }
+
+    // 与事务互斥；读取不加锁，可能看到只应用了一部分的批量写入
+    fn apply_batch(&self, batch: &WriteBatch) -> Result<(), Error> {
+        let _guard = self.txn_lock.lock().unwrap_or_else(|e| e.into_inner());
+        for (key, value) in batch.ops() {
+            match value {
+                Some(value) => self.records.insert(key.to_vec(), IVec::from(value)),
+                None => self.records.remove(key),
+            };
+        }
+        Ok(())
+    }
+
+    fn serialize_writes(&self) -> bool {
+        true
+    }
+}
+
+/// 内存引擎上的事务上下文：读取时优先看本事务的写入，提交前对其他连接不可见
+#[derive(Clone)]
+pub struct MemoryTxn {
+    records: Arc<Records>,
+    /// 本事务的写入，`None` 表示删除
+    writes: Rc<RefCell<BTreeMap<Vec<u8>, Option<IVec>>>>,
+}
+
+impl MemoryTxn {
+    fn commit(self) {
+        for (key, value) in self.writes.take() {
+            match value {
+                Some(value) => self.records.insert(key, value),
+                None => self.records.remove(&key),
+            };
+        }
+    }
+
+    fn write(&self, key: &[u8], value: Option<IVec>) -> Result<Option<IVec>, Error> {
+        let old = self.get(key)?;
+        self.writes.borrow_mut().insert(key.to_vec(), value);
+        Ok(old)
+    }
+}
+
+impl KvEngine for MemoryTxn {
+    type Txn = MemoryTxn;
+
+    fn get(&self, key: &[u8]) -> Result<Option<IVec>, Error> {
+        if let Some(value) = self.writes.borrow().get(key) {
+            return Ok(value.clone());
+        }
+        Ok(self.records.get(key))
+    }
+
+    fn insert(&self, key: &[u8], value: &[u8]) -> Result<Option<IVec>, Error> {
+        self.write(key, Some(IVec::from(value)))
+    }
+
+    fn remove(&self, key: &[u8]) -> Result<Option<IVec>, Error> {
+        self.write(key, None)
+    }
+
+    fn scan_prefix(&self, prefix: &[u8]) -> Box<dyn Iterator<Item = Result<(IVec, IVec), Error>>> {
+        let mut matched = scan_sorted(&self.records, prefix);
+        for (key, value) in self.writes.borrow().range(prefix.to_vec()..) {
+            if !key.starts_with(prefix) {
+                break;
+            }
+            match value {
+                Some(value) => matched.insert(key.clone(), value.clone()),
+                None => matched.remove(key),
+            };
+        }
+        Box::new(matched.into_iter().map(|(k, v)| Ok((IVec::from(k), v))))
+    }
+
+    // 已经处于事务上下文中，不支持嵌套事务
+    fn transaction<T, F>(&self, _f: F) -> Result<T, TransactionError<Error>>
+    where
+        F: Fn(&MemoryTxn) -> ConflictableTransactionResult<T, Error>,
+    {
+        Err(TransactionError::Abort(Error::msg("nested transactions are not supported")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use crate::engine::execute_non_txn_command;
+    use crate::txn::executor::exec_all;
+    use crate::protocol::Frame;
+    use crate::types::{hash, list, string};
+
+    fn cmd(parts: &[&str]) -> Vec<Bytes> {
+        parts.iter().map(|s| Bytes::copy_from_slice(s.as_bytes())).collect()
+    }
+
+    #[test]
+    fn test_commands() -> anyhow::Result<()> {
+        let db = MemoryEngine::new();
+        for parts in [
+            &["SET", "s", "v"][..],
+            &["INCR", "n"],
+            &["HSET", "h", "b", "2"],
+            &["HSET", "h", "a", "1"],
+            &["RPUSH", "l", "x"],
+            &["LPUSH", "l", "w"],
+        ] {
+            let parts = cmd(parts);
+            assert!(!execute_non_txn_command(&crate::command::name_upper(&parts[0]), &parts, &db).is_error());
+        }
+        assert_eq!(string::get(&db, b"s")?.as_deref(), Some(&b"v"[..]));
+        assert_eq!(string::get(&db, b"n")?.as_deref(), Some(&b"1"[..]));
+        // 扫描结果按 key 排序
+        assert_eq!(hash::hkeys(&db, b"h")?, vec![b"a".to_vec(), b"b".to_vec()]);
+        assert_eq!(list::lrange(&db, b"l", 0, -1)?, vec![b"w".to_vec(), b"x".to_vec()]);
+
+        db.clear();
+        assert!(db.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_transaction_commit_and_abort() -> anyhow::Result<()> {
+        let db = MemoryEngine::new();
+        string::set(&db, b"a", b"1")?;
+
+        let reply = exec_all(&db, &[cmd(&["SET", "b", "2"]), cmd(&["HSET", "h", "f", "v"]), cmd(&["HGETALL", "h"])]);
+        assert_eq!(
+            reply,
+            Frame::Array(vec![
+                Frame::ok(),
+                Frame::Integer(1),
+                Frame::Map(vec![(Frame::bulk("f"), Frame::bulk("v"))]),
+            ])
+        );
+        assert_eq!(string::get(&db, b"b")?.as_deref(), Some(&b"2"[..]));
+
+        // 出错时之前的写入全部丢弃
+        let reply = exec_all(&db, &[cmd(&["SET", "a", "changed"]), cmd(&["NOSUCHCMD"])]);
+        assert!(reply.is_error());
+        assert_eq!(string::get(&db, b"a")?.as_deref(), Some(&b"1"[..]));
+        Ok(())
+    }
+}
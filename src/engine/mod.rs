@@ -3,48 +3,56 @@
 //! # 引擎模块
 //!
 //! `engine` 模块是 Redis 类服务器的核心。它：
-//! - 从网络层接收已解析和分词的命令（`Vec<String>`）。
-//! - 与底层的 `sled::Db` 进行数据操作交互。
+//! - 从网络层接收已解析和分词的命令（借用的 `&[Bytes]`，参数是任意字节，分发过程中不复制）。
+//! - 与底层的存储引擎（`sled::Db` 或内存引擎）进行数据操作交互。
 //! - 将业务逻辑委托给类型特定的子模块（`string`、`hash`、`list`、`set`）和 `expire` 模块执行。
-//! - 返回一个响应 `String`，网络层将将其格式化为 RESP 简单字符串或错误。
+//! - 返回一个回复 `Frame`，由网络层按连接协商的协议版本（RESP2/RESP3）编码。
+pub mod blocking;
+pub mod budget;
+pub mod compress;
 pub mod kv;
-pub use kv::KvEngine;
+pub use kv::{KeyspaceStats, KvEngine, Storage, WriteBatch};
+pub mod memory;
+pub mod usage;
+pub mod waiters;
 pub mod watch;
 
+use bytes::Bytes;
+
+use crate::command::{self, parse_arg};
+use crate::error::CommandError;
 use crate::txn::session::TxnSession;
 use crate::txn::executor::exec_all;
-use crate::types::{hash, list, set, string};
+use crate::types::{self, hash, list, set, string};
 use crate::expire;
+use crate::plugin;
+use crate::script;
+use crate::protocol::Frame;
+use std::sync::atomic::Ordering;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 /// 对指定数据库执行单个客户端命令（新增 txn_session 参数）
 ///
 /// # 参数
 ///
-/// * `parts` - 包含命令名称及其参数的 `Vec<String>`
+/// * `parts` - 命令名称及其参数，只有在 MULTI 中入队时才会复制一份
 /// * `db` - 打开的 `sled::Db` 实例的引用
 /// * `txn_session` - 事务会话状态
-pub fn execute<E>(parts: Vec<String>, db: &E, txn_session: &mut TxnSession) -> String 
+pub fn execute<E>(parts: &[Bytes], db: &E, txn_session: &mut TxnSession) -> Frame 
 where 
     E: KvEngine,
 {
     // 1. 空白命令检查
     if parts.is_empty() {
-        return "ERR empty command".to_string();
+        return Frame::error("ERR empty command");
     }
 
-    let cmd = parts[0].to_uppercase();
-    let parts = parts.clone();
+    let cmd = command::name_upper(&parts[0]);
 
-    // 2. 仅在非事务模式且不是事务命令时执行过期检查
-    if !txn_session.in_multi && parts.len() > 1 {
-        match cmd.as_str() {
-            "PING" | "QUIT" => {}
-            _ => {
-                if let Some(_db) = db.as_db() {
-                    let _ = expire::remove_if_expired(_db, &parts[1]);    
-                }
-            }
-        }
+    // 2. 仅在非事务模式时执行过期检查，事务中的命令在 EXEC 时检查
+    if !txn_session.in_multi {
+        let _ = purge_expired(db, parts);
+        track_keyspace(db, parts);
     }
 
     // 3. 处理事务命令
@@ -52,90 +60,163 @@ where
         // WATCH/UNWATCH 处理
         "WATCH" => {
             if txn_session.in_multi {
-                return "ERR WATCH inside MULTI is not allowed".to_string();
+                return Frame::error("ERR WATCH inside MULTI is not allowed");
             }
 
             if parts.len() < 2 {
-                return "ERR wrong number of arguments for 'WATCH'".to_string();
+                return Frame::error("ERR wrong number of arguments for 'WATCH'");
             }
 
             let keys = &parts[1..];
             if let Some(watch_manager) = db.watch_manager() {
-                watch_manager.watch(txn_session.id, keys);
-                return "OK".to_string();
+                txn_session.watch(watch_manager, keys);
+                return Frame::ok();
             }
 
-            "ERR watch manager not available".to_string()
+            Frame::error("ERR watch manager not available")
         }
 
         "UNWATCH" => {
-            if let Some(watch_manager) = db.watch_manager() {
-                watch_manager.unwatch(txn_session.id);
-                return "OK".to_string();
-            }
-
-            "ERR watch manager not available".to_string()
+            txn_session.unwatch();
+            Frame::ok()
         }
 
         // --- 事务命令 ---
-        "MULTI" => {
-            txn_session.begin().map(|s| s.to_string()).unwrap_or_else(|e| e.to_string())
-        }
+        "MULTI" => match txn_session.begin() {
+            Ok(s) => Frame::Simple(s.to_string()),
+            Err(e) => Frame::error(e),
+        },
         "EXEC" => {
-            if let Some(watch_manager) = db.watch_manager() {
-                if watch_manager.is_dirty(txn_session.id) {
-                    watch_manager.clear_session(txn_session.id);
-                    txn_session.in_multi = false;
-                    txn_session.queue.clear();
-                    return "nil".to_string();
-                }
-            }
-
+            // 监视的 key 已被修改：放弃事务，回复 nil
+            // （take_queue 会一并解除监视；入队出过错时回复 EXECABORT）
+            let dirty = txn_session.is_dirty();
             match txn_session.take_queue() {
+                Ok(_) if dirty => Frame::NullArray,
                 Ok(queue) => {
-                    if let Some(sled_db) = db.as_db() {
-                        let results = exec_all(sled_db, &queue);
-                        if let Some(watch_manager) = db.watch_manager() {
-                            watch_manager.clear_session(txn_session.id);
-                        }
-                        
-                        results.join("\n")
-                    } else {
-                        "ERR transaction not supported".to_string()
+                    let results = exec_all(db, &queue);
+                    if matches!(results, Frame::Array(_)) {
+                        notify_watchers(db, &queue);
                     }
+
+                    results
                 }
-                Err(e) => e.to_string(),
+                Err(e) => e.into(),
             }
         }
-        "DISCARD" => {
-            let res = txn_session.discard().map(|s| s.to_string()).unwrap_or_else(|e| e.to_string());
-        
-            // 清除监视
-            if let Some(watch_manager) = db.watch_manager() {
-                watch_manager.clear_session(txn_session.id);
-            }
-
-            res
-        }
+        "DISCARD" => match txn_session.discard() {
+            Ok(s) => Frame::Simple(s.to_string()),
+            Err(e) => Frame::error(e),
+        },
         
         // --- 其他命令 ---
         _ => {
             if txn_session.in_multi {
+                // 入队前按命令表校验，出错的事务在 EXEC 时整体放弃
+                let checked = match command::lookup(&cmd) {
+                    None => Err(command::unknown_command_error(parts)),
+                    Some(spec) if !spec.check_arity(parts.len()) => Err(command::arity_error(&cmd)),
+                    Some(_) => Ok(()),
+                };
+                if let Err(reply) = checked {
+                    txn_session.flag_error();
+                    return reply;
+                }
+
                 // 事务模式下将命令加入队列
-                match txn_session.enqueue(parts) {
-                    Ok(resp) => resp.to_string(),
-                    Err(_) => "ERR not in transaction".to_string(),
+                match txn_session.enqueue(parts.to_vec()) {
+                    Ok(resp) => Frame::Simple(resp.to_string()),
+                    Err(_) => Frame::error("ERR not in transaction"),
                 }
             } else {
-                // 非事务模式直接执行命令
-                execute_non_txn_command(&cmd, &parts, db)
+                // 非事务模式直接执行命令；引擎要求时把写命令包进事务保证原子性
+                let resp = if db.serialize_writes() && command::lookup(&cmd).is_some_and(|spec| spec.is_write()) {
+                    db.transaction(|tx| Ok(execute_non_txn_command(&cmd, parts, tx)))
+                        .unwrap_or_else(|e| CommandError::parse(&e.to_string()).into())
+                } else {
+                    execute_non_txn_command(&cmd, parts, db)
+                };
+                // 阻塞命令没有取到数据时什么也没写，不能唤醒其他等待者，否则它们会相互唤醒
+                if !resp.is_error() && resp != Frame::NullArray {
+                    notify_watchers(db, &[parts]);
+                }
+                resp
+            }
+        }
+    }
+}
+
+/// 阻塞命令的超时参数：BLMPOP 紧跟在命令名之后，BLPOP / BRPOP 在最后
+pub fn block_timeout_arg(parts: &[Bytes]) -> &[u8] {
+    if parts[0].eq_ignore_ascii_case(b"BLMPOP") { &parts[1] } else { &parts[parts.len() - 1] }
+}
+
+/// 阻塞命令的超时参数（秒，可带小数），0 表示一直等待，返回 `None`
+pub fn parse_block_timeout(arg: &[u8]) -> Result<Option<std::time::Duration>, CommandError> {
+    let secs = parse_arg::<f64>(arg)
+        .filter(|secs| secs.is_finite())
+        .ok_or_else(|| CommandError::err("timeout is not a float or out of range"))?;
+    if secs < 0.0 {
+        return Err(CommandError::err("timeout is negative"));
+    }
+    Ok((secs > 0.0).then(|| std::time::Duration::from_secs_f64(secs)))
+}
+
+/// 惰性过期：删除命令涉及的 key 中已经过期的那些（key 位置取自命令表）
+pub(crate) fn purge_expired<E: KvEngine>(db: &E, parts: &[Bytes]) -> anyhow::Result<()> {
+    let Some(spec) = command::lookup(&parts[0]) else {
+        return Ok(());
+    };
+    for key in spec.keys(parts) {
+        if expire::remove_if_expired(db, key)?
+            && let Some(stats) = db.keyspace_stats()
+        {
+            stats.expired.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+    Ok(())
+}
+
+/// 只读命令按其访问的 key 是否存在累计 keyspace_hits / keyspace_misses
+fn track_keyspace<E: KvEngine>(db: &E, parts: &[Bytes]) {
+    let Some(stats) = db.keyspace_stats() else {
+        return;
+    };
+    let Some(spec) = command::lookup(&parts[0]).filter(|spec| spec.is_readonly()) else {
+        return;
+    };
+    for key in spec.keys(parts) {
+        let counter = if types::exists(db, key).unwrap_or(false) { &stats.hits } else { &stats.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// 写命令执行成功后，把命令表中声明的 key 标记为已修改，
+/// 监视这些 key 的会话在 EXEC 时会放弃事务，阻塞在这些 key 上的客户端被唤醒重试
+pub(crate) fn notify_watchers<E: KvEngine, P: AsRef<[Bytes]>>(db: &E, cmds: &[P]) {
+    let watch_manager = db.watch_manager();
+    let waiters = db.waiters();
+    if watch_manager.is_none() && waiters.is_none() {
+        return;
+    }
+    for parts in cmds {
+        let parts = parts.as_ref();
+        if let Some(spec) = command::lookup(&parts[0])
+            && spec.is_write()
+        {
+            for key in spec.keys(parts) {
+                if let Some(watch_manager) = &watch_manager {
+                    watch_manager.notify_key_change(key);
+                }
+                if let Some(waiters) = &waiters {
+                    waiters.notify(key);
+                }
             }
         }
     }
 }
 
 /// 执行非事务命令（原命令分发逻辑）
-pub fn execute_non_txn_command<E>(cmd: &str, parts: &[String], db: &E) -> String 
+pub fn execute_non_txn_command<E>(cmd: &str, parts: &[Bytes], db: &E) -> Frame 
 where 
     E: KvEngine,
 {
@@ -143,54 +224,50 @@ where
         // --- String commands ---
         "SET" => {
             if parts.len() != 3 {
-                "ERR wrong number of arguments for 'SET'".into()
+                Frame::error("ERR wrong number of arguments for 'SET'")
             } else {
-                match string::set(db, &parts[1], &parts[2]) {
-                    Ok(s) => s,
-                    Err(e) => format!("ERR {}", e),
-                }
+                status_reply(string::set(db, &parts[1], &parts[2]))
             }
         },
         "GET" => {
             if parts.len() != 2 {
-                "ERR wrong number of arguments for 'GET'".into()
+                Frame::error("ERR wrong number of arguments for 'GET'")
             } else {
-                match string::get(db, &parts[1]) {
-                    Ok(s) => s,
-                    Err(e) => format!("ERR {}", e),
-                }
+                bulk_reply(string::get(db, &parts[1]))
             }
         },
         "DEL" => {
             if parts.len() != 2 {
-                "ERR wrong number of arguments for 'DEL'".into()
+                Frame::error("ERR wrong number of arguments for 'DEL'")
             } else {
                 match string::del(db, &parts[1]) {
-                    Ok(s) => s,
-                    Err(e) => format!("ERR {}", e),
+                    Ok(existed) => Frame::Integer(existed as i64),
+                    Err(e) => CommandError::from(e).into(),
                 }
             }
         },
 
+        "GETSET" => {
+            if parts.len() != 3 {
+                Frame::error("ERR wrong number of arguments for 'GETSET'")
+            } else {
+                bulk_reply(string::getset(db, &parts[1], &parts[2]))
+            }
+        },
+
         // 原子增减操作
         "INCR" => {
             if parts.len() != 2 {
-                "ERR wrong number of arguments for 'INCR'".into()
+                Frame::error("ERR wrong number of arguments for 'INCR'")
             } else {
-                match string::incr(db, &parts[1]) {
-                    Ok(s) => s,
-                    Err(e) => format!("ERR {}", e),
-                }
+                integer_reply(string::incr(db, &parts[1]))
             }
         }
         "DECR" => {
             if parts.len() != 2 {
-                "ERR wrong number of arguments for 'DECR'".into()
+                Frame::error("ERR wrong number of arguments for 'DECR'")
             } else {
-                match string::decr(db, &parts[1]) {
-                    Ok(s) => s,
-                    Err(e) => format!("ERR {}", e),
-                }
+                integer_reply(string::decr(db, &parts[1]))
             }
         }
 
@@ -198,176 +275,305 @@ where
         // --- Hash commands ---
         "HSET" => {
             if parts.len() != 4 {
-                "ERR wrong number of arguments for 'HSET'".into()
+                Frame::error("ERR wrong number of arguments for 'HSET'")
             } else {
-                match hash::hset(db, &parts[1], &parts[2], &parts[3]) {
-                    Ok(s) => s,
-                    Err(e) => format!("ERR {}", e),
-                }
+                integer_reply(hash::hset(db, &parts[1], &parts[2], &parts[3]))
             }
         }
         "HGET" => {
             if parts.len() != 3 {
-                "ERR wrong number of arguments for 'HGET'".into()
+                Frame::error("ERR wrong number of arguments for 'HGET'")
             } else {
-                match hash::hget(db, &parts[1], &parts[2]) {
-                    Ok(s) => s,
-                    Err(e) => format!("ERR {}", e),
-                }
+                bulk_reply(hash::hget(db, &parts[1], &parts[2]))
             }
         }
         "HDEL" => {
             if parts.len() != 3 {
-                "ERR wrong number of arguments for 'HDEL'".into()
+                Frame::error("ERR wrong number of arguments for 'HDEL'")
             } else {
-                match hash::hdel(db, &parts[1], &parts[2]) {
-                    Ok(s) => s,
-                    Err(e) => format!("ERR {}", e),
-                }
+                integer_reply(hash::hdel(db, &parts[1], &parts[2]))
             }
         }
         "HKEYS" => {
             if parts.len() != 2 {
-                "ERR wrong number of arguments for 'HKEYS'".into()
+                Frame::error("ERR wrong number of arguments for 'HKEYS'")
             } else {
-                match hash::hkeys(db, &parts[1]) {
-                    Ok(s) => s,
-                    Err(e) => format!("ERR {}", e),
-                }
+                array_reply(hash::hkeys(db, &parts[1]))
             }
         }
         "HVALS" => {
             if parts.len() != 2 {
-                "ERR wrong number of arguments for 'HVALS'".into()
+                Frame::error("ERR wrong number of arguments for 'HVALS'")
             } else {
-                match hash::hvals(db, &parts[1]) {
-                    Ok(s) => s,
-                    Err(e) => format!("ERR {}", e),
-                }
+                array_reply(hash::hvals(db, &parts[1]))
             }
         }
         "HGETALL" => {
             if parts.len() != 2 {
-                "ERR wrong number of arguments for 'HGETALL'".into()
+                Frame::error("ERR wrong number of arguments for 'HGETALL'")
             } else {
-                match hash::hgetall(db, &parts[1]) {
-                    Ok(s) => s,
-                    Err(e) => format!("ERR {}", e),
-                }
+                map_reply(hash::hgetall(db, &parts[1]))
             }
         }
 
         // --- List commands ---
         "LPUSH" => {
-            if parts.len() != 3 { "ERR wrong number of arguments for 'LPUSH'".into() }
-            else { match list::lpush(db, &parts[1], &parts[2]) { Ok(s)=>s, Err(e)=>format!("ERR {}", e) } }
+            if parts.len() != 3 { Frame::error("ERR wrong number of arguments for 'LPUSH'") }
+            else { integer_reply(list::lpush(db, &parts[1], &parts[2])) }
         }
         "RPUSH" => {
-            if parts.len() != 3 { "ERR wrong number of arguments for 'RPUSH'".into() }
-            else { match list::rpush(db, &parts[1], &parts[2]) { Ok(s)=>s, Err(e)=>format!("ERR {}", e) } }
+            if parts.len() != 3 { Frame::error("ERR wrong number of arguments for 'RPUSH'") }
+            else { integer_reply(list::rpush(db, &parts[1], &parts[2])) }
         }
         "LPOP" => {
-            if parts.len() != 2 { "ERR wrong number of arguments for 'LPOP'".into() }
+            if parts.len() != 2 { Frame::error("ERR wrong number of arguments for 'LPOP'") }
             else {
-                match list::lpop(db, &parts[1]) { Ok(s)=>s, Err(e)=>format!("ERR {}", e) }
+                bulk_reply(list::lpop(db, &parts[1]))
             }
         }
         "RPOP" => {
-            if parts.len() != 2 { "ERR wrong number of arguments for 'RPOP'".into() }
+            if parts.len() != 2 { Frame::error("ERR wrong number of arguments for 'RPOP'") }
             else {
-                match list::rpop(db, &parts[1]) { Ok(s)=>s, Err(e)=>format!("ERR {}", e) }
+                bulk_reply(list::rpop(db, &parts[1]))
             }
         }
         "LRANGE" => {
-            if parts.len() != 4 { "ERR wrong number of arguments for 'LRANGE'".into() }
+            if parts.len() != 4 { Frame::error("ERR wrong number of arguments for 'LRANGE'") }
             else {
                 // Parse start and stop as signed integers
-                let start = parts[2].parse::<isize>();
-                let stop  = parts[3].parse::<isize>();
+                let start = parse_arg::<isize>(&parts[2]);
+                let stop  = parse_arg::<isize>(&parts[3]);
                 match (start, stop) {
-                    (Ok(s), Ok(e)) => match list::lrange(db, &parts[1], s, e) {
-                        Ok(r) => r,
-                        Err(er) => format!("ERR {}", er),
-                    },
-                    _ => "ERR invalid start or stop".into(),
+                    (Some(s), Some(e)) => array_reply(list::lrange(db, &parts[1], s, e)),
+                    _ => Frame::error("ERR invalid start or stop"),
                 }
             }
         }
 
+        "LMPOP" | "BLMPOP" => {
+            // LMPOP numkeys key [key ...] LEFT|RIGHT [COUNT count]
+            // BLMPOP timeout numkeys ...：与 BLPOP 一样只尝试一次，所有列表都为空时回复 nil，由连接层等待后重试
+            if parts.len() < 4 { return Frame::error(format!("ERR wrong number of arguments for '{}'", cmd)); }
+            let blocking = cmd == "BLMPOP";
+            if blocking && let Err(e) = parse_block_timeout(&parts[1]) {
+                return e.into();
+            }
+            let (keys, left, count) = match parse_lmpop(&parts[1 + blocking as usize..]) {
+                Ok(args) => args,
+                Err(e) => return e.into(),
+            };
+            match list::lmpop(db, keys, left, count) {
+                Ok(Some((key, items))) => Frame::Array(vec![
+                    Frame::bulk(key),
+                    Frame::Array(items.into_iter().map(Frame::bulk).collect()),
+                ]),
+                Ok(None) if blocking => Frame::NullArray,
+                Ok(None) => Frame::Null,
+                Err(e) => CommandError::from(e).into(),
+            }
+        }
+        "BLPOP" | "BRPOP" => {
+            // BLPOP key [key ...] timeout：这里只尝试弹出一次，所有列表都为空时回复 nil，
+            // 由连接层登记等待、在 key 被写入后重试，直到超时（见 waiters 模块）
+            if parts.len() < 3 {
+                return Frame::error(format!("ERR wrong number of arguments for '{}'", cmd));
+            }
+            if let Err(e) = parse_block_timeout(&parts[parts.len() - 1]) {
+                return e.into();
+            }
+            match list::lmpop(db, &parts[1..parts.len() - 1], cmd == "BLPOP", 1) {
+                Ok(Some((key, mut items))) => Frame::Array(vec![Frame::bulk(key), Frame::bulk(items.remove(0))]),
+                Ok(None) => Frame::NullArray,
+                Err(e) => CommandError::from(e).into(),
+            }
+        }
+
         // --- Set commands ---
         "SADD" => {
-            if parts.len() != 3 { "ERR wrong number of arguments for 'SADD'".into() }
-            else { match set::sadd(db, &parts[1], &parts[2]) { Ok(s)=>s, Err(e)=>format!("ERR {}", e) } }
+            if parts.len() != 3 { Frame::error("ERR wrong number of arguments for 'SADD'") }
+            else { integer_reply(set::sadd(db, &parts[1], &parts[2])) }
         }
         "SREM" => {
-            if parts.len() != 3 { "ERR wrong number of arguments for 'SREM'".into() }
-            else { match set::srem(db, &parts[1], &parts[2]) { Ok(s)=>s, Err(e)=>format!("ERR {}", e) } }
+            if parts.len() != 3 { Frame::error("ERR wrong number of arguments for 'SREM'") }
+            else { integer_reply(set::srem(db, &parts[1], &parts[2])) }
         }
         "SMEMBERS" => {
-            if parts.len() != 2 { "ERR wrong number of arguments for 'SMEMBERS'".into() }
-            else { match set::smembers(db, &parts[1]) { Ok(s)=>s, Err(e)=>format!("ERR {}", e) } }
+            if parts.len() != 2 { Frame::error("ERR wrong number of arguments for 'SMEMBERS'") }
+            else { set_reply(set::smembers(db, &parts[1])) }
         }
         "SISMEMBER" => {
-            if parts.len() != 3 { "ERR wrong number of arguments for 'SISMEMBER'".into() }
-            else { match set::sismember(db, &parts[1], &parts[2]) { Ok(s)=>s, Err(e)=>format!("ERR {}", e) } }
+            if parts.len() != 3 { Frame::error("ERR wrong number of arguments for 'SISMEMBER'") }
+            else { integer_reply(set::sismember(db, &parts[1], &parts[2])) }
         }
 
         // --- Expiration commands ---
         "EXPIRE" => {
             // EXPIRE <key> <seconds>: set a TTL on key
             if parts.len() != 3 {
-                return "ERR wrong number of arguments for 'EXPIRE'".to_string();
+                return Frame::error("ERR wrong number of arguments for 'EXPIRE'");
             }
             let key = &parts[1];
-            match parts[2].parse::<u64>() {
-                Ok(secs) => match expire::expire(db, key, secs) {
-                    Ok(v) => v,                  // "1" if TTL set, "0" if key does not exist
-                    Err(e) => format!("ERR {}", e),
-                },
-                Err(_) => "ERR value is not an integer or out of range".to_string(),
+            match parse_arg::<u64>(&parts[2]) {
+                // "1" if TTL set, "0" if key does not exist
+                Some(secs) => integer_reply(expire::expire(db, key, secs)),
+                None => Frame::error("ERR value is not an integer or out of range"),
+            }
+        }
+
+        "EXPIREAT" | "PEXPIREAT" => {
+            // EXPIREAT <key> <unix-seconds> / PEXPIREAT <key> <unix-ms>
+            if parts.len() != 3 {
+                return Frame::error(format!("ERR wrong number of arguments for '{}'", cmd));
+            }
+            let scale = if cmd == "EXPIREAT" { 1_000 } else { 1 };
+            match parse_arg::<u64>(&parts[2]) {
+                Some(ts) => integer_reply(expire::expire_at(db, &parts[1], ts.saturating_mul(scale))),
+                None => Frame::error("ERR value is not an integer or out of range"),
             }
         }
 
         "TTL" => {
             // TTL <key>: get remaining TTL in seconds
             if parts.len() != 2 {
-                return "ERR wrong number of arguments for 'TTL'".to_string();
-            }
-            match expire::ttl(db, &parts[1]) {
-                Ok(v) => v,   // "-2", "-1", or remaining seconds
-                Err(e) => format!("ERR {}", e),
+                return Frame::error("ERR wrong number of arguments for 'TTL'");
             }
+            // "-2", "-1", or remaining seconds
+            integer_reply(expire::ttl(db, &parts[1]))
         }
 
         "PERSIST" => {
             // PERSIST <key>: remove existing TTL
             if parts.len() != 2 {
-                return "ERR wrong number of arguments for 'PERSIST'".to_string();
-            }
-            match expire::persist(db, &parts[1]) {
-                Ok(v) => v,   // "1" if TTL removed, "0" if key or TTL did not exist
-                Err(e) => format!("ERR {}", e),
+                return Frame::error("ERR wrong number of arguments for 'PERSIST'");
             }
+            // "1" if TTL removed, "0" if key or TTL did not exist
+            integer_reply(expire::persist(db, &parts[1]))
         }
 
+        // --- Scripting ---
+        "EVAL" => script::eval(db, parts),
+        // 服务端在执行前把 EVALSHA 换成 EVAL（见 `script::ScriptCache`），到这里说明脚本不在缓存中
+        "EVALSHA" => CommandError::NoScript.into(),
+
         // --- Connection / Control commands ---
         "PING" => {
             // PING: health check, always returns "PONG"
-            "PONG".to_string()
+            Frame::Simple("PONG".to_string())
+        }
+        "ECHO" => {
+            if parts.len() != 2 {
+                Frame::error("ERR wrong number of arguments for 'ECHO'")
+            } else {
+                Frame::bulk(parts[1].clone())
+            }
+        }
+        "TIME" => {
+            // TIME: [unix 秒, 当前秒内的微秒]，均为 Bulk String
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+            Frame::Array(vec![
+                Frame::bulk(now.as_secs().to_string()),
+                Frame::bulk(now.subsec_micros().to_string()),
+            ])
         }
         "QUIT" => {
             // QUIT: client indicates intent to close connection.
             // Return "OK"; the server loop will handle terminating the session.
-            "OK".to_string()
+            Frame::ok()
         }
 
         // --- Unknown command ---
         other => {
-            format!("ERR unknown command '{}'", other)
+            // 运行时注册的插件命令
+            plugin::execute(db, parts)
+                .unwrap_or_else(|| Frame::error(format!("ERR unknown command '{}'", other)))
         }
     }
 }
 
+/// 状态类回复：以 "ERR" 开头的视为错误，其余作为 Simple String 返回
+fn status_reply(res: anyhow::Result<String>) -> Frame {
+    match res {
+        Ok(s) if s.starts_with("ERR") => Frame::Error(s),
+        Ok(s) => Frame::Simple(s),
+        Err(e) => CommandError::from(e).into(),
+    }
+}
+
+/// 值类回复：返回 Bulk String，key / 字段 / 元素不存在时为 nil
+fn bulk_reply(res: anyhow::Result<Option<Vec<u8>>>) -> Frame {
+    match res {
+        Ok(Some(v)) => Frame::Bulk(v),
+        Ok(None) => Frame::Null,
+        Err(e) => CommandError::from(e).into(),
+    }
+}
+
+/// 计数类回复：类型模块以字符串形式返回数字，这里转成 Integer
+fn integer_reply(res: anyhow::Result<String>) -> Frame {
+    match res {
+        Ok(s) => match s.parse::<i64>() {
+            Ok(n) => Frame::Integer(n),
+            Err(_) => status_reply(Ok(s)),
+        },
+        Err(e) => CommandError::from(e).into(),
+    }
+}
+
+/// LMPOP / BLMPOP 从 numkeys 开始的参数：`numkeys key [key ...] LEFT|RIGHT [COUNT count]`
+fn parse_lmpop(args: &[Bytes]) -> Result<(&[Bytes], bool, usize), CommandError> {
+    let numkeys = match parse_arg::<usize>(&args[0]) {
+        Some(n) if n > 0 => n,
+        _ => return Err(CommandError::err("numkeys should be greater than 0")),
+    };
+    // 先与剩余参数个数比较，超大的 numkeys 不会在后面的下标运算中溢出
+    if numkeys > args.len().saturating_sub(2) {
+        return Err(CommandError::err("syntax error"));
+    }
+    let left = match command::name_upper(&args[1 + numkeys]).as_str() {
+        "LEFT" => true,
+        "RIGHT" => false,
+        _ => return Err(CommandError::err("syntax error")),
+    };
+    let count = match &args[2 + numkeys..] {
+        [] => 1,
+        [opt, n] if opt.eq_ignore_ascii_case(b"COUNT") => match parse_arg::<usize>(n) {
+            Some(c) if c > 0 => c,
+            _ => return Err(CommandError::err("count should be greater than 0")),
+        },
+        _ => return Err(CommandError::err("syntax error")),
+    };
+    Ok((&args[1..1 + numkeys], left, count))
+}
+
+/// 列表类回复：RESP Array
+fn array_reply(res: anyhow::Result<Vec<Vec<u8>>>) -> Frame {
+    match res {
+        Ok(items) => Frame::Array(items.into_iter().map(Frame::bulk).collect()),
+        Err(e) => CommandError::from(e).into(),
+    }
+}
+
+/// 集合类回复：RESP3 下为 Set，RESP2 下退化为 Array
+fn set_reply(res: anyhow::Result<Vec<Vec<u8>>>) -> Frame {
+    match res {
+        Ok(items) => Frame::Set(items.into_iter().map(Frame::bulk).collect()),
+        Err(e) => CommandError::from(e).into(),
+    }
+}
+
+/// 键值对回复：RESP3 下为 Map，RESP2 下退化为扁平 Array
+fn map_reply(res: anyhow::Result<Vec<(Vec<u8>, Vec<u8>)>>) -> Frame {
+    match res {
+        Ok(pairs) => Frame::Map(
+            pairs
+                .into_iter()
+                .map(|(k, v)| (Frame::bulk(k), Frame::bulk(v)))
+                .collect(),
+        ),
+        Err(e) => CommandError::from(e).into(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -387,6 +593,10 @@ mod tests {
         (make_db(), TxnSession::new(16))
     }
 
+    fn cmd(parts: &[&str]) -> Vec<Bytes> {
+        parts.iter().map(|s| Bytes::copy_from_slice(s.as_bytes())).collect()
+    }
+
     // 新增事务测试
     #[test]
     fn test_transaction_commands() {
@@ -394,45 +604,45 @@ mod tests {
         
         // 测试 MULTI
         assert_eq!(
-            execute(vec!["MULTI"].iter().map(|s| s.to_string()).collect(),
+            execute(&cmd(&["MULTI"]),
                     &db, 
                     &mut session
                 ),
-            "OK"
+            Frame::ok()
         );
         assert!(session.in_multi);
         
         // 测试命令入队
         assert_eq!(
-            execute(vec!["SET", "tx_key", "tx_value"].iter().map(|s| s.to_string()).collect(),
+            execute(&cmd(&["SET", "tx_key", "tx_value"]),
                     &db, 
                     &mut session
                 ),
-            "QUEUED"
+            Frame::Simple("QUEUED".to_string())
         );
         assert_eq!(session.queue.len(), 1);
         
         // 测试 DISCARD
         assert_eq!(
-            execute(vec!["DISCARD"].iter().map(|s| s.to_string()).collect(),
+            execute(&cmd(&["DISCARD"]),
                     &db, 
                     &mut session
                 ),
-            "OK"
+            Frame::ok()
         );
         assert!(!session.in_multi);
         assert!(session.queue.is_empty());
         
         // 测试 EXEC
-        execute(vec!["MULTI"].iter().map(|s| s.to_string()).collect(),
+        execute(&cmd(&["MULTI"]),
                     &db, 
                     &mut session
         );
-        execute(vec!["SET", "tx_key", "tx_value"].iter().map(|s| s.to_string()).collect(),
+        execute(&cmd(&["SET", "tx_key", "tx_value"]),
                     &db, 
                     &mut session
         );
-        execute(vec!["EXEC"].iter().map(|s| s.to_string()).collect(),
+        execute(&cmd(&["EXEC"]),
                     &db, 
                     &mut session
         );
@@ -440,51 +650,222 @@ mod tests {
         assert!(session.queue.is_empty());
         
         assert_eq!(
-            execute(vec!["GET","tx_key"].iter().map(|s| s.to_string()).collect(),
+            execute(&cmd(&["GET","tx_key"]),
                     &db, 
                     &mut session
                 ),
-            "tx_value"
+            Frame::bulk("tx_value")
         );
         
         // 测试嵌套 MULTI
-        execute(vec!["MULTI"].iter().map(|s| s.to_string()).collect(),
+        execute(&cmd(&["MULTI"]),
                     &db, 
                     &mut session
         );
         assert_eq!(
-            execute(vec!["MULTI"].iter().map(|s| s.to_string()).collect(),
+            execute(&cmd(&["MULTI"]),
                     &db, 
                     &mut session
             ),
-            "ERR MULTI calls can not be nested"
+            Frame::error("ERR MULTI calls can not be nested")
         );
         // 关闭事务
         assert_eq!(
-            execute(vec!["DISCARD"].iter().map(|s| s.to_string()).collect(),
+            execute(&cmd(&["DISCARD"]),
                     &db, 
                     &mut session
                 ),
-            "OK"
+            Frame::ok()
         );
         
         // 测试 EXEC 无 MULTI
         assert_eq!(
-            execute(vec!["EXEC"].iter().map(|s| s.to_string()).collect(),
+            execute(&cmd(&["EXEC"]),
                     &db, 
                     &mut session
             ),
-            "ERR EXEC without MULTI"
+            Frame::error("ERR EXEC without MULTI")
         );
         
         // 测试 DISCARD 无 MULTI
         assert_eq!(
-            execute(vec!["DISCARD"].iter().map(|s| s.to_string()).collect(),
+            execute(&cmd(&["DISCARD"]),
                     &db, 
                     &mut session
             ),
-            "ERR DISCARD without MULTI"
+            Frame::error("ERR DISCARD without MULTI")
+        );
+    }
+
+    // WATCH 的 key 被其他连接修改后 EXEC 回复 nil
+    #[test]
+    fn test_watch_aborts_exec() {
+        let db = kv::DbInstance {
+            db: Storage::sled(make_db()).unwrap(),
+            watch_manager: std::sync::Arc::new(watch::WatchManager::new()),
+            waiters: Default::default(),
+            stats: Default::default(),
+        };
+        let mut s1 = TxnSession::new(1);
+        let mut s2 = TxnSession::new(2);
+
+        assert_eq!(execute(&cmd(&["WATCH", "k"]), &db, &mut s1), Frame::ok());
+        execute(&cmd(&["SET", "k", "other"]), &db, &mut s2);
+        execute(&cmd(&["MULTI"]), &db, &mut s1);
+        execute(&cmd(&["SET", "k", "mine"]), &db, &mut s1);
+        assert_eq!(execute(&cmd(&["EXEC"]), &db, &mut s1), Frame::NullArray);
+        assert_eq!(execute(&cmd(&["GET", "k"]), &db, &mut s1), Frame::bulk("other"));
+
+        // UNWATCH 之后的修改不影响事务提交
+        execute(&cmd(&["WATCH", "k"]), &db, &mut s1);
+        execute(&cmd(&["UNWATCH"]), &db, &mut s1);
+        execute(&cmd(&["SET", "k", "again"]), &db, &mut s2);
+        execute(&cmd(&["MULTI"]), &db, &mut s1);
+        execute(&cmd(&["SET", "k", "mine"]), &db, &mut s1);
+        assert_eq!(execute(&cmd(&["EXEC"]), &db, &mut s1), Frame::Array(vec![Frame::ok()]));
+
+        // 未在 MULTI 中时即使监视已脏也报错
+        execute(&cmd(&["WATCH", "k"]), &db, &mut s1);
+        execute(&cmd(&["SET", "k", "x"]), &db, &mut s2);
+        assert_eq!(execute(&cmd(&["EXEC"]), &db, &mut s1), Frame::error("ERR EXEC without MULTI"));
+    }
+
+    // 绕过命令分发直接写记录（过期删除、脚本、嵌入 API）时，按记录所属的用户 key 通知监视者
+    #[test]
+    fn test_watch_record_writes() {
+        let db = kv::DbInstance {
+            db: Storage::sled(make_db()).unwrap(),
+            watch_manager: std::sync::Arc::new(watch::WatchManager::new()),
+            waiters: Default::default(),
+            stats: Default::default(),
+        };
+        let manager = db.watch_manager.clone();
+        manager.watch(1, &["foo".to_string()]);
+        manager.watch(2, &["Foo".to_string()]);
+
+        db.insert(&crate::keys::hash_field(b"foo", b"f"), b"v").unwrap();
+        assert!(manager.is_dirty(1));
+        // 区分大小写
+        assert!(!manager.is_dirty(2));
+
+        manager.unwatch(1);
+        manager.watch(1, &["foo".to_string()]);
+        db.remove(&crate::keys::expire(b"foo")).unwrap();
+        assert!(manager.is_dirty(1));
+    }
+
+    // DbInstance 上的事务，以及事务中的过期命令
+    #[test]
+    fn test_exec_with_expire_on_db_instance() {
+        let db = kv::DbInstance {
+            db: Storage::sled(make_db()).unwrap(),
+            watch_manager: std::sync::Arc::new(watch::WatchManager::new()),
+            waiters: Default::default(),
+            stats: Default::default(),
+        };
+        let mut session = TxnSession::new(1);
+
+        execute(&cmd(&["MULTI"]), &db, &mut session);
+        execute(&cmd(&["SET", "k", "v"]), &db, &mut session);
+        execute(&cmd(&["EXPIRE", "k", "100"]), &db, &mut session);
+        execute(&cmd(&["TTL", "k"]), &db, &mut session);
+        execute(&cmd(&["PERSIST", "k"]), &db, &mut session);
+        execute(&cmd(&["TTL", "k"]), &db, &mut session);
+        assert_eq!(
+            execute(&cmd(&["EXEC"]), &db, &mut session),
+            Frame::Array(vec![
+                Frame::ok(),
+                Frame::Integer(1),
+                Frame::Integer(100),
+                Frame::Integer(1),
+                Frame::Integer(-1),
+            ])
+        );
+
+        // 已过期的 key 在事务中同样不可见
+        execute(&cmd(&["EXPIRE", "k", "0"]), &db, &mut session);
+        execute(&cmd(&["MULTI"]), &db, &mut session);
+        execute(&cmd(&["TTL", "k"]), &db, &mut session);
+        assert_eq!(
+            execute(&cmd(&["EXEC"]), &db, &mut session),
+            Frame::Array(vec![Frame::Integer(-1)])
+        );
+        assert_eq!(execute(&cmd(&["GET", "k"]), &db, &mut session), Frame::Null);
+    }
+
+    // 只读命令统计命中与未命中，惰性删除计入过期 key 数
+    #[test]
+    fn test_keyspace_stats() {
+        let db = kv::DbInstance {
+            db: Storage::sled(make_db()).unwrap(),
+            watch_manager: std::sync::Arc::new(watch::WatchManager::new()),
+            waiters: Default::default(),
+            stats: Default::default(),
+        };
+        let mut session = TxnSession::new(1);
+
+        execute(&cmd(&["SET", "k", "v"]), &db, &mut session);
+        execute(&cmd(&["GET", "k"]), &db, &mut session);
+        execute(&cmd(&["GET", "missing"]), &db, &mut session);
+        execute(&cmd(&["TTL", "k"]), &db, &mut session);
+        execute(&cmd(&["TTL", "missing"]), &db, &mut session);
+        // 写命令不计入
+        execute(&cmd(&["SET", "other", "v"]), &db, &mut session);
+
+        execute(&cmd(&["EXPIRE", "k", "0"]), &db, &mut session);
+        execute(&cmd(&["GET", "k"]), &db, &mut session);
+
+        let stats = db.keyspace_stats().unwrap();
+        assert_eq!(stats.hits.load(Ordering::Relaxed), 2);
+        assert_eq!(stats.misses.load(Ordering::Relaxed), 3);
+        assert_eq!(stats.expired.load(Ordering::Relaxed), 1);
+        assert_eq!(stats.evicted.load(Ordering::Relaxed), 0);
+    }
+
+    // 引擎的时钟可替换：拨动时间后命令与事务中看到的过期状态一致
+    #[test]
+    fn test_mock_clock_expiry() {
+        let clock = std::sync::Arc::new(crate::clock::MockClock::starting_now());
+        let db = kv::DbInstance {
+            db: Storage::memory().with_clock(clock.clone()),
+            watch_manager: std::sync::Arc::new(watch::WatchManager::new()),
+            waiters: Default::default(),
+            stats: Default::default(),
+        };
+        let mut session = TxnSession::new(1);
+
+        execute(&cmd(&["SET", "a", "1"]), &db, &mut session);
+        execute(&cmd(&["SET", "b", "2"]), &db, &mut session);
+        execute(&cmd(&["EXPIRE", "a", "5"]), &db, &mut session);
+        execute(&cmd(&["EXPIRE", "b", "60"]), &db, &mut session);
+        clock.advance(std::time::Duration::from_secs(5));
+        assert_eq!(execute(&cmd(&["GET", "a"]), &db, &mut session), Frame::Null);
+        assert_eq!(execute(&cmd(&["TTL", "b"]), &db, &mut session), Frame::Integer(55));
+        assert_eq!(db.keyspace_stats().unwrap().expired.load(Ordering::Relaxed), 1);
+
+        execute(&cmd(&["MULTI"]), &db, &mut session);
+        execute(&cmd(&["GET", "b"]), &db, &mut session);
+        clock.advance(std::time::Duration::from_secs(55));
+        let reply = execute(&cmd(&["EXEC"]), &db, &mut session);
+        assert_eq!(reply, Frame::Array(vec![Frame::Null]));
+    }
+
+    // 入队时校验命令，出错后 EXEC 整体放弃
+    #[test]
+    fn test_queue_time_validation() {
+        let (db, mut session) = make_db_and_session();
+
+        execute(&cmd(&["MULTI"]), &db, &mut session);
+        assert_eq!(execute(&cmd(&["SET", "a", "1"]), &db, &mut session), Frame::Simple("QUEUED".into()));
+        assert!(execute(&cmd(&["NOSUCHCMD", "x"]), &db, &mut session).is_error());
+        assert!(execute(&cmd(&["GET"]), &db, &mut session).is_error());
+        assert_eq!(session.queue.len(), 1);
+        assert_eq!(
+            execute(&cmd(&["EXEC"]), &db, &mut session),
+            Frame::error("EXECABORT Transaction discarded because of previous errors.")
         );
+        assert!(!session.in_multi);
+        assert_eq!(execute(&cmd(&["GET", "a"]), &db, &mut session), Frame::Null);
     }
 
     // 字符串命令测试
@@ -495,61 +876,69 @@ mod tests {
         // SET 命令
         assert_eq!(
             execute(
-                vec!["SET", "key1", "value1"].iter().map(|s| s.to_string()).collect(),
+                &cmd(&["SET", "key1", "value1"]),
                 &db,
                 &mut session
             ),
-            "OK"
+            Frame::ok()
         );
         // GET 命令
         assert_eq!(
             execute(
-                vec!["GET", "key1"].iter().map(|s| s.to_string()).collect(),
+                &cmd(&["GET", "key1"]),
                 &db,
                 &mut session
             ),
-            "value1"
+            Frame::bulk("value1")
         );
         // GET 不存在的键
         assert_eq!(
             execute(
-                vec!["GET", "nonexistence"].iter().map(|s| s.to_string()).collect(),
+                &cmd(&["GET", "nonexistence"]),
                 &db,
                 &mut session
             ),
-            "ERR key not found"
+            Frame::Null
         );
         // INCR 命令
         execute(
-                vec!["SET", "counter", "10"].iter().map(|s| s.to_string()).collect(),
+                &cmd(&["SET", "counter", "10"]),
                 &db,
                 &mut session
         );
         assert_eq!(
             execute(
-                vec!["INCR", "counter"].iter().map(|s| s.to_string()).collect(),
+                &cmd(&["INCR", "counter"]),
                 &db,
                 &mut session
             ),
-            "11"
+            Frame::Integer(11)
         );
         // DECR 命令
         assert_eq!(
             execute(
-                vec!["DECR", "counter"].iter().map(|s| s.to_string()).collect(),
+                &cmd(&["DECR", "counter"]),
                 &db,
                 &mut session
             ),
-            "10"
+            Frame::Integer(10)
         );
         // DEL 命令
         assert_eq!(
             execute(
-                vec!["DEL", "key1"].iter().map(|s| s.to_string()).collect(),
+                &cmd(&["DEL", "key1"]),
                 &db,
                 &mut session
             ),
-            "OK"
+            Frame::Integer(1)
+        );
+        assert_eq!(
+            execute(
+                &cmd(&["DEL", "key1"]),
+                &db,
+                &mut session
+            ),
+            Frame::Integer(0)
         );
     }
 
@@ -559,7 +948,7 @@ mod tests {
         let (db, mut session) = make_db_and_session();
 
         execute(
-                vec!["HSET", "user:1","name","Alice"].iter().map(|s| s.to_string()).collect(),
+                &cmd(&["HSET", "user:1","name","Alice"]),
                 &db,
                 &mut session
         );
@@ -567,41 +956,41 @@ mod tests {
         // HGET 命令
         assert_eq!(
             execute(
-                vec!["HGET", "user:1","name"].iter().map(|s| s.to_string()).collect(),
+                &cmd(&["HGET", "user:1","name"]),
                 &db,
                 &mut session
             ),
-            "Alice"
+            Frame::bulk("Alice")
         );
         
         // HDEL 命令
         assert_eq!(
             execute(
-                vec!["HDEL", "user:1","name"].iter().map(|s| s.to_string()).collect(),
+                &cmd(&["HDEL", "user:1","name"]),
                 &db,
                 &mut session
             ),
-            "1"
+            Frame::Integer(1)
         );
         
         // HKEYS 命令
         execute(
-                vec!["HSET", "user:1", "email", "alice@example.com"].iter().map(|s| s.to_string()).collect(),
+                &cmd(&["HSET", "user:1", "email", "alice@example.com"]),
                 &db,
                 &mut session
         );
         execute(
-                vec!["HSET", "user:1","email","alice@example.com"].iter().map(|s| s.to_string()).collect(),
+                &cmd(&["HSET", "user:1","email","alice@example.com"]),
                 &db,
                 &mut session
         );
         assert_eq!(
             execute(
-                vec!["HKEYS", "user:1"].iter().map(|s| s.to_string()).collect(),
+                &cmd(&["HKEYS", "user:1"]),
                 &db,
                 &mut session
             ),
-            "email"
+            Frame::Array(vec![Frame::bulk("email")])
         );
     }
 
@@ -611,12 +1000,12 @@ mod tests {
         let (db, mut session) = make_db_and_session();
 
         execute(
-                vec!["LPUSH", "mylist", "item1"].iter().map(|s| s.to_string()).collect(),
+                &cmd(&["LPUSH", "mylist", "item1"]),
                 &db,
                 &mut session
         );
         execute(
-                vec!["RPUSH", "mylist", "item2"].iter().map(|s| s.to_string()).collect(),
+                &cmd(&["RPUSH", "mylist", "item2"]),
                 &db,
                 &mut session
         );
@@ -624,22 +1013,67 @@ mod tests {
         // LPOP 命令
         assert_eq!(
             execute(
-                vec!["LPOP", "mylist"].iter().map(|s| s.to_string()).collect(),
+                &cmd(&["LPOP", "mylist"]),
                 &db,
                 &mut session
             ),
-            "item1"
+            Frame::bulk("item1")
         );
         
         // LRANGE 命令
         assert_eq!(
             execute(
-                vec!["LRANGE", "mylist", "0", "-1"].iter().map(|s| s.to_string()).collect(),
+                &cmd(&["LRANGE", "mylist", "0", "-1"]),
                 &db,
                 &mut session
             ),
-            "item2"
+            Frame::Array(vec![Frame::bulk("item2")])
         );
+
+        // BLPOP / BRPOP / BLMPOP 在引擎层只尝试一次：取第一个非空列表，全部为空时回复 nil
+        execute(&cmd(&["RPUSH", "mylist", "item3"]), &db, &mut session);
+        assert_eq!(
+            execute(&cmd(&["BRPOP", "empty", "mylist", "0"]), &db, &mut session),
+            Frame::Array(vec![Frame::bulk("mylist"), Frame::bulk("item3")])
+        );
+        assert_eq!(execute(&cmd(&["BLPOP", "mylist", "0.5"]), &db, &mut session), Frame::Array(vec![Frame::bulk("mylist"), Frame::bulk("item2")]));
+        assert_eq!(execute(&cmd(&["BLPOP", "mylist", "empty", "1"]), &db, &mut session), Frame::NullArray);
+        assert_eq!(execute(&cmd(&["BLPOP", "mylist", "-1"]), &db, &mut session), Frame::error("ERR timeout is negative"));
+        assert_eq!(
+            execute(&cmd(&["BLPOP", "mylist", "soon"]), &db, &mut session),
+            Frame::error("ERR timeout is not a float or out of range")
+        );
+
+        assert_eq!(
+            execute(&cmd(&["LMPOP", "18446744073709551614", "mylist", "LEFT"]), &db, &mut session),
+            Frame::error("ERR syntax error")
+        );
+        assert_eq!(
+            execute(&cmd(&["LMPOP", "18446744073709551615", "mylist", "LEFT", "COUNT", "1"]), &db, &mut session),
+            Frame::error("ERR syntax error")
+        );
+        assert_eq!(execute(&cmd(&["LMPOP", "2", "mylist", "LEFT"]), &db, &mut session), Frame::error("ERR syntax error"));
+
+        for item in ["a", "b", "c"] {
+            execute(&cmd(&["RPUSH", "mylist", item]), &db, &mut session);
+        }
+        assert_eq!(
+            execute(&cmd(&["BLMPOP", "0", "2", "empty", "mylist", "RIGHT", "COUNT", "2"]), &db, &mut session),
+            Frame::Array(vec![Frame::bulk("mylist"), Frame::Array(vec![Frame::bulk("c"), Frame::bulk("b")])])
+        );
+        assert_eq!(execute(&cmd(&["BLMPOP", "0.1", "1", "empty", "LEFT"]), &db, &mut session), Frame::NullArray);
+        assert_eq!(execute(&cmd(&["BLMPOP", "-1", "1", "mylist", "LEFT"]), &db, &mut session), Frame::error("ERR timeout is negative"));
+        assert_eq!(
+            execute(&cmd(&["BLMPOP", "0", "0", "mylist", "LEFT"]), &db, &mut session),
+            Frame::error("ERR numkeys should be greater than 0")
+        );
+        assert_eq!(execute(&cmd(&["BLMPOP", "0", "1", "mylist", "UP"]), &db, &mut session), Frame::error("ERR syntax error"));
+        assert_eq!(
+            execute(&cmd(&["BLMPOP", "0", "18446744073709551614", "mylist", "LEFT"]), &db, &mut session),
+            Frame::error("ERR syntax error")
+        );
+        assert_eq!(block_timeout_arg(&cmd(&["BLMPOP", "1.5", "1", "l", "LEFT"])), b"1.5");
+        assert_eq!(block_timeout_arg(&cmd(&["BLPOP", "l", "2"])), b"2");
     }
 
     // 集合命令测试
@@ -647,7 +1081,7 @@ mod tests {
     fn test_set_commands() {
         let (db, mut session) = make_db_and_session();
         execute(
-                vec!["SADD", "myset", "member1"].iter().map(|s| s.to_string()).collect(),
+                &cmd(&["SADD", "myset", "member1"]),
                 &db,
                 &mut session
         );
@@ -655,21 +1089,21 @@ mod tests {
         // SISMEMBER 命令
         assert_eq!(
             execute(
-                vec!["SISMEMBER", "myset", "member1"].iter().map(|s| s.to_string()).collect(),
+                &cmd(&["SISMEMBER", "myset", "member1"]),
                 &db,
                 &mut session
             ),
-            "1"
+            Frame::Integer(1)
         );
         
         // SMEMBERS 命令
         assert_eq!(
             execute(
-                vec!["SMEMBERS", "myset"].iter().map(|s| s.to_string()).collect(),
+                &cmd(&["SMEMBERS", "myset"]),
                 &db,
                 &mut session
             ),
-            "member1"
+            Frame::Set(vec![Frame::bulk("member1")])
         );
     }
 
@@ -679,7 +1113,7 @@ mod tests {
         let (db, mut session) = make_db_and_session();
 
         execute(
-                vec!["SET", "temp_key", "value"].iter().map(|s| s.to_string()).collect(),
+                &cmd(&["SET", "temp_key", "value"]),
                 &db,
                 &mut session
         );
@@ -687,29 +1121,29 @@ mod tests {
         // EXPIRE 命令
         assert_eq!(
             execute(
-                vec!["EXPIRE", "temp_key", "60"].iter().map(|s| s.to_string()).collect(),
+                &cmd(&["EXPIRE", "temp_key", "60"]),
                 &db,
                 &mut session
             ),
-            "1"
+            Frame::Integer(1)
         );
         
         // TTL 命令
         let ttl = execute(
-                vec!["TTL", "temp_key"].iter().map(|s| s.to_string()).collect(),
+                &cmd(&["TTL", "temp_key"]),
                 &db,
                 &mut session
             );
-        assert!(ttl.parse::<i64>().unwrap() > 0);
+        assert!(matches!(ttl, Frame::Integer(n) if n > 0));
         
         // PERSIST 命令
         assert_eq!(
             execute(
-                vec!["PERSIST", "temp_key"].iter().map(|s| s.to_string()).collect(),
+                &cmd(&["PERSIST", "temp_key"]),
                 &db,
                 &mut session
             ),
-            "1"
+            Frame::Integer(1)
         );
     }
 
@@ -718,15 +1152,27 @@ mod tests {
     fn test_control_commands() {
         let (db, mut session) = make_db_and_session();
         assert_eq!(            execute(
-                vec!["PING"].iter().map(|s| s.to_string()).collect(),
+                &cmd(&["PING"]),
                 &db,
                 &mut session
-            ), "PONG");
+            ), Frame::Simple("PONG".to_string()));
         assert_eq!(            execute(
-                vec!["QUIT"].iter().map(|s| s.to_string()).collect(),
+                &cmd(&["QUIT"]),
                 &db,
                 &mut session
-            ), "OK");
+            ), Frame::ok());
+        assert_eq!(
+            execute(&cmd(&["ECHO", "hello"]), &db, &mut session),
+            Frame::bulk("hello")
+        );
+        match execute(&cmd(&["TIME"]), &db, &mut session) {
+            Frame::Array(items) => {
+                let [Frame::Bulk(secs), Frame::Bulk(micros)] = items.as_slice() else { panic!("unexpected TIME reply {:?}", items) };
+                assert!(String::from_utf8_lossy(secs).parse::<u64>().unwrap() > 1_600_000_000);
+                assert!(String::from_utf8_lossy(micros).parse::<u32>().unwrap() < 1_000_000);
+            }
+            other => panic!("unexpected TIME reply {:?}", other),
+        }
     }
 
     // 错误参数测试
@@ -737,31 +1183,31 @@ mod tests {
         // SET 参数不足
         assert_eq!(
             execute(
-                vec!["SET", "Key"].iter().map(|s| s.to_string()).collect(),
+                &cmd(&["SET", "Key"]),
                 &db,
                 &mut session
             ),
-            "ERR wrong number of arguments for 'SET'"
+            Frame::error("ERR wrong number of arguments for 'SET'")
         );
         
         // GET 多余参数
         assert_eq!(
             execute(
-                vec!["GET", "key", "extra"].iter().map(|s| s.to_string()).collect(),
+                &cmd(&["GET", "key", "extra"]),
                 &db,
                 &mut session
             ),
-            "ERR wrong number of arguments for 'GET'"
+            Frame::error("ERR wrong number of arguments for 'GET'")
         );
         
         // INCR 多余参数
         assert_eq!(
             execute(
-                vec!["INCR", "counter", "extra"].iter().map(|s| s.to_string()).collect(),
+                &cmd(&["INCR", "counter", "extra"]),
                 &db,
                 &mut session
             ),
-            "ERR wrong number of arguments for 'INCR'"
+            Frame::error("ERR wrong number of arguments for 'INCR'")
         );
     }
 }
\ No newline at end of file
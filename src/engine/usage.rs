@@ -0,0 +1,259 @@
+// src/engine/usage.rs
+
+//! 按类型与 key 模式统计的键空间用量（STATS KEYSPACE）
+//!
+//! 存储引擎每写入或删除一条记录就把变化计入统计，查询时直接返回，不扫描数据集；
+//! 打开已有的 sled 数据时扫描一次建立初始值，清空数据库时归零。
+//! 事务中的写入在事务提交后才计入。
+//!
+//! - key 数：字符串以字符串记录、列表以 head 记录的出现与消失为准；hash 与集合
+//!   按 key 维护字段数，字段数从 0 变 1、从 1 变 0 时增减
+//! - 字节数：记录 key 与未压缩的值的长度之和，是近似值；批量写入先读原值再写，
+//!   并发修改同一条记录时可能有少量偏差
+//! - 模式：配置 `keyspace_stats_patterns` 中的 glob 模式按逻辑 key 匹配，
+//!   统计所有类型中匹配的 key 数与字节数
+
+use std::sync::atomic::{AtomicI64, Ordering};
+
+use anyhow::Result;
+use dashmap::DashMap;
+
+use crate::engine::KvEngine;
+use crate::glob::glob_match;
+use crate::keys::{self, Kind};
+use crate::protocol::Frame;
+
+/// 统计的数据类型，顺序与 `type_index` 一致
+const TYPES: [&str; 4] = ["string", "hash", "list", "set"];
+
+const HELP: &[&str] = &[
+    "STATS <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+    "KEYSPACE",
+    "    Return the number of keys and approximate bytes per data type and per",
+    "    configured key pattern.",
+    "HELP",
+    "    Print this help.",
+];
+
+fn type_index(kind: Kind) -> Option<usize> {
+    match kind {
+        Kind::String => Some(0),
+        Kind::Hash => Some(1),
+        Kind::ListData | Kind::ListMeta => Some(2),
+        Kind::Set => Some(3),
+        Kind::Expire => None,
+    }
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    keys: AtomicI64,
+    bytes: AtomicI64,
+}
+
+impl Counters {
+    fn add(&self, keys: i64, bytes: i64) {
+        self.keys.fetch_add(keys, Ordering::Relaxed);
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    fn reset(&self) {
+        self.keys.store(0, Ordering::Relaxed);
+        self.bytes.store(0, Ordering::Relaxed);
+    }
+
+    /// `{keys, bytes}`，偏差导致的负数按 0 返回
+    fn frame(&self) -> Frame {
+        let get = |counter: &AtomicI64| Frame::Integer(counter.load(Ordering::Relaxed).max(0));
+        Frame::Map(vec![
+            (Frame::bulk("keys"), get(&self.keys)),
+            (Frame::bulk("bytes"), get(&self.bytes)),
+        ])
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct KeyspaceUsage {
+    types: [Counters; 4],
+    /// 设置了过期时间的 key 数
+    expires: AtomicI64,
+    patterns: Vec<(String, Counters)>,
+    /// hash / 集合每个 key 的字段数，键为 `keys::prefix(kind, key)`
+    members: DashMap<Vec<u8>, u64>,
+}
+
+impl KeyspaceUsage {
+    pub fn new(patterns: &[String]) -> Self {
+        KeyspaceUsage {
+            patterns: patterns.iter().map(|p| (p.clone(), Counters::default())).collect(),
+            ..Default::default()
+        }
+    }
+
+    /// 计入一条记录的变化：`old` / `new` 是写入前后值的长度，None 表示记录不存在
+    pub fn record(&self, record_key: &[u8], old: Option<usize>, new: Option<usize>) {
+        let Some(record) = keys::decode(record_key) else { return };
+        let appeared = match (old.is_some(), new.is_some()) {
+            (false, true) => 1,
+            (true, false) => -1,
+            _ => 0,
+        };
+        let Some(index) = type_index(record.kind) else {
+            self.expires.fetch_add(appeared, Ordering::Relaxed);
+            return;
+        };
+
+        let size = |len: Option<usize>| len.map_or(0, |len| (record_key.len() + len) as i64);
+        let bytes = size(new) - size(old);
+        let keys = match record.kind {
+            Kind::String => appeared,
+            Kind::ListMeta if record.is_list_head() => appeared,
+            Kind::Hash | Kind::Set if appeared != 0 => self.count_member(keys::prefix(record.kind, record.key), appeared),
+            _ => 0,
+        };
+
+        self.types[index].add(keys, bytes);
+        for (pattern, counters) in &self.patterns {
+            if glob_match(pattern.as_bytes(), record.key) {
+                counters.add(keys, bytes);
+            }
+        }
+    }
+
+    /// 更新 hash / 集合的字段数，返回 key 数的变化
+    fn count_member(&self, prefix: Vec<u8>, delta: i64) -> i64 {
+        if delta > 0 {
+            let mut count = self.members.entry(prefix).or_insert(0);
+            *count += 1;
+            return (*count == 1) as i64;
+        }
+        let Some(mut count) = self.members.get_mut(&prefix) else { return 0 };
+        *count = count.saturating_sub(1);
+        if *count > 0 {
+            return 0;
+        }
+        drop(count);
+        self.members.remove_if(&prefix, |_, count| *count == 0);
+        -1
+    }
+
+    /// 全部归零（清空数据库时）
+    pub fn reset(&self) {
+        self.types.iter().for_each(Counters::reset);
+        self.patterns.iter().for_each(|(_, counters)| counters.reset());
+        self.expires.store(0, Ordering::Relaxed);
+        self.members.clear();
+    }
+
+    /// 扫描 `db` 中的全部记录，重新建立统计
+    pub fn rebuild(&self, db: &impl KvEngine) -> Result<()> {
+        self.reset();
+        for item in db.scan_prefix(b"") {
+            let (k, v) = item?;
+            self.record(&k, None, Some(v.len()));
+        }
+        Ok(())
+    }
+
+    /// STATS KEYSPACE 的回复
+    pub fn keyspace(&self) -> Frame {
+        let mut out: Vec<(Frame, Frame)> =
+            TYPES.iter().zip(&self.types).map(|(name, counters)| (Frame::bulk(*name), counters.frame())).collect();
+        out.push((Frame::bulk("expires"), Frame::Integer(self.expires.load(Ordering::Relaxed).max(0))));
+        out.push((
+            Frame::bulk("patterns"),
+            Frame::Map(self.patterns.iter().map(|(p, counters)| (Frame::bulk(p.as_str()), counters.frame())).collect()),
+        ));
+        Frame::Map(out)
+    }
+
+    /// 执行 STATS 子命令
+    pub fn execute(&self, args: &[String]) -> Frame {
+        match (args[0].to_uppercase().as_str(), args.len()) {
+            ("HELP", 1) => Frame::Array(HELP.iter().map(|l| Frame::Simple(l.to_string())).collect()),
+            ("KEYSPACE", 1) => self.keyspace(),
+            _ => Frame::error(format!(
+                "ERR unknown subcommand or wrong number of arguments for '{}'. Try STATS HELP.",
+                args[0]
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use crate::engine::kv::Storage;
+    use crate::txn::executor::exec_all;
+    use crate::types::{hash, list, set, string};
+
+    fn counters(frame: &Frame, name: &str) -> (i64, i64) {
+        let Frame::Map(pairs) = frame else { panic!("expected map, got {:?}", frame) };
+        let Some((_, Frame::Map(fields))) = pairs.iter().find(|(k, _)| *k == Frame::bulk(name)) else {
+            panic!("no {} in {:?}", name, frame)
+        };
+        match fields.as_slice() {
+            [(_, Frame::Integer(keys)), (_, Frame::Integer(bytes))] => (*keys, *bytes),
+            other => panic!("unexpected counters {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_incremental_counts() -> Result<()> {
+        let db = sled::Config::new().temporary(true).open()?;
+        let storage = Storage::sled(db.clone())?;
+        string::set(&storage, b"user:1", b"abc")?;
+        string::set(&storage, b"user:1", b"abcdef")?;
+        string::incr(&storage, b"n")?;
+        hash::hset(&storage, b"h", b"a", b"1")?;
+        hash::hset(&storage, b"h", b"b", b"2")?;
+        hash::hdel(&storage, b"h", b"a")?;
+        set::sadd(&storage, b"user:s", b"x")?;
+        set::srem(&storage, b"user:s", b"x")?;
+        list::rpush(&storage, b"l", b"x")?;
+        list::lpush(&storage, b"l", b"y")?;
+
+        // 事务中的写入随事务提交计入，放弃的事务不计入
+        let cmd = |parts: &[&str]| parts.iter().map(|s| Bytes::copy_from_slice(s.as_bytes())).collect::<Vec<_>>();
+        exec_all(&storage, &[cmd(&["SADD", "s", "m"]), cmd(&["EXPIRE", "s", "100"])]);
+        exec_all(&storage, &[cmd(&["SET", "t", "v"]), cmd(&["NOSUCHCMD"])]);
+
+        let reopened = Storage::sled(db)?;
+        let stats = storage.keyspace_usage().unwrap().keyspace();
+        assert_eq!(counters(&stats, "string").0, 2);
+        assert_eq!(counters(&stats, "hash").0, 1);
+        assert_eq!(counters(&stats, "list").0, 1);
+        assert_eq!(counters(&stats, "set").0, 1);
+        assert_eq!(
+            counters(&stats, "string").1,
+            (keys::string(b"user:1").len() + 6 + keys::string(b"n").len() + 1) as i64
+        );
+
+        // 重新打开时扫描得到的统计与增量结果一致
+        assert_eq!(reopened.keyspace_usage().unwrap().keyspace(), stats);
+
+        storage.clear()?;
+        assert_eq!(counters(&storage.keyspace_usage().unwrap().keyspace(), "list"), (0, 0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_patterns_and_command() {
+        let usage = KeyspaceUsage::new(&["user:*".to_string()]);
+        usage.record(&keys::string(b"user:1"), None, Some(3));
+        usage.record(&keys::hash_field(b"user:2", b"f"), None, Some(1));
+        usage.record(&keys::string(b"other"), None, Some(3));
+        usage.record(&keys::expire(b"user:1"), None, Some(8));
+
+        let stats = usage.execute(&["keyspace".to_string()]);
+        let Frame::Map(pairs) = &stats else { panic!() };
+        assert!(pairs.contains(&(Frame::bulk("expires"), Frame::Integer(1))));
+        let (_, patterns) = pairs.last().unwrap();
+        assert_eq!(counters(patterns, "user:*").0, 2);
+
+        usage.record(&keys::string(b"user:1"), Some(3), None);
+        assert_eq!(counters(&usage.keyspace(), "string"), (1, (keys::string(b"other").len() + 3) as i64));
+        assert!(usage.execute(&["NOPE".to_string()]).is_error());
+    }
+}
@@ -0,0 +1,147 @@
+// src/engine/waiters.rs
+
+//! 阻塞命令的等待与唤醒
+//!
+//! BLPOP / BRPOP / BLMPOP 等阻塞命令在没有可用数据时向 [`Waiters`] 登记它关心的 key，
+//! 写命令成功后引擎对其涉及的 key 调用 [`Waiters::notify`]，唤醒等待该 key 最久的那个客户端，
+//! 被唤醒的客户端重新执行命令，仍然拿不到数据时保留原来的排队位置继续等待。
+//!
+//! 每个 key 一个先进先出的队列，同一个 key 上先阻塞的客户端先被服务；等待结束
+//! （成功、超时或连接断开）时把通知转交给各个 key 的下一个等待者，一次写入多个元素时
+//! 后面的客户端也能依次被唤醒。多唤醒一次只会让客户端多重试一次，不会丢失数据。
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use tokio::sync::Notify;
+
+/// 一个阻塞中的客户端
+#[derive(Debug, Default)]
+struct Waiter {
+    notify: Notify,
+    /// 已被唤醒、尚未开始下一轮等待；唤醒其他等待者时跳过它
+    woken: AtomicBool,
+}
+
+/// key → 等待该 key 的客户端队列
+#[derive(Debug, Default)]
+pub struct Waiters {
+    queues: Mutex<HashMap<Vec<u8>, VecDeque<Arc<Waiter>>>>,
+}
+
+impl Waiters {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 登记一个等待 `keys` 中任意一个的客户端，返回的句柄被丢弃时注销
+    pub fn register<K: AsRef<[u8]>>(self: &Arc<Self>, keys: &[K]) -> WaitHandle {
+        let waiter = Arc::new(Waiter::default());
+        let keys: Vec<Vec<u8>> = keys.iter().map(|key| key.as_ref().to_vec()).collect();
+        let mut queues = self.queues.lock().unwrap();
+        for key in &keys {
+            queues.entry(key.clone()).or_default().push_back(waiter.clone());
+        }
+        WaitHandle { waiters: self.clone(), keys, waiter }
+    }
+
+    /// `key` 被写入：唤醒等待它最久、且尚未被唤醒的客户端
+    pub fn notify(&self, key: &[u8]) {
+        let queues = self.queues.lock().unwrap();
+        let Some(queue) = queues.get(key) else {
+            return;
+        };
+        if let Some(waiter) = queue.iter().find(|waiter| !waiter.woken.swap(true, Ordering::AcqRel)) {
+            waiter.notify.notify_one();
+        }
+    }
+
+    /// 阻塞中的客户端数（一个客户端等待多个 key 时只计一次）
+    pub fn blocked_clients(&self) -> usize {
+        let queues = self.queues.lock().unwrap();
+        let mut seen: Vec<*const Waiter> = queues.values().flatten().map(Arc::as_ptr).collect();
+        seen.sort();
+        seen.dedup();
+        seen.len()
+    }
+
+    fn unregister(&self, keys: &[Vec<u8>], waiter: &Arc<Waiter>) {
+        let mut queues = self.queues.lock().unwrap();
+        for key in keys {
+            if let Some(queue) = queues.get_mut(key) {
+                queue.retain(|w| !Arc::ptr_eq(w, waiter));
+                if queue.is_empty() {
+                    queues.remove(key);
+                }
+            }
+        }
+    }
+}
+
+/// 一次阻塞等待的登记
+#[derive(Debug)]
+pub struct WaitHandle {
+    waiters: Arc<Waiters>,
+    keys: Vec<Vec<u8>>,
+    waiter: Arc<Waiter>,
+}
+
+impl WaitHandle {
+    /// 等到任意一个 key 被写入（返回 true）或到达 `deadline`（返回 false），`None` 表示一直等待
+    ///
+    /// 登记之后、调用之前发生的写入不会丢失：`Notify` 会保留这次通知
+    pub async fn wait(&self, deadline: Option<Instant>) -> bool {
+        let notified = self.waiter.notify.notified();
+        let woken = match deadline {
+            Some(deadline) => tokio::time::timeout_at(deadline.into(), notified).await.is_ok(),
+            None => {
+                notified.await;
+                true
+            }
+        };
+        // 开始下一轮之前重新参与唤醒
+        self.waiter.woken.store(false, Ordering::Release);
+        woken
+    }
+}
+
+impl Drop for WaitHandle {
+    fn drop(&mut self) {
+        self.waiters.unregister(&self.keys, &self.waiter);
+        for key in &self.keys {
+            self.waiters.notify(key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_fifo_wakeups() {
+        let waiters = Arc::new(Waiters::new());
+        let first = waiters.register(&["a", "b"]);
+        let second = waiters.register(&["a"]);
+        assert_eq!(waiters.blocked_clients(), 2);
+
+        // 登记后、等待前的通知不会丢失，先登记的先被唤醒
+        waiters.notify(b"a");
+        assert!(first.wait(None).await);
+        let soon = Some(Instant::now() + Duration::from_millis(20));
+        assert!(!second.wait(soon).await);
+
+        // 第一个客户端结束等待时把通知转交给下一个
+        waiters.notify(b"a");
+        drop(first);
+        assert!(second.wait(None).await);
+        assert_eq!(waiters.blocked_clients(), 1);
+
+        drop(second);
+        assert_eq!(waiters.blocked_clients(), 0);
+        waiters.notify(b"nobody-waits");
+    }
+}
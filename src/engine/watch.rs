@@ -1,13 +1,47 @@
 // src/engine/watch.rs
 use dashmap::{DashMap, DashSet};
-use std::{sync::Arc, vec};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use crate::output::PushSender;
+use crate::protocol::Frame;
+
+/// 开启了 CLIENT TRACKING 的连接
+#[derive(Debug, Clone)]
+pub struct TrackingClient {
+    /// 向连接推送 RESP3 失效消息
+    pub sender: PushSender,
+    /// NOLOOP：不接收自己修改 key 引起的失效消息
+    pub noloop: bool,
+    /// BCAST 模式订阅的前缀；None 表示默认模式，只失效读过的 key
+    pub bcast_prefixes: Option<Vec<String>>,
+}
+
+/// 被监视的 key：版本号与监视它的会话
+///
+/// 版本号在 key 每次被修改时加一，WATCH 时记下当时的版本，EXEC 时比较
+#[derive(Debug, Default)]
+struct WatchedKey {
+    version: u64,
+    sessions: HashSet<u64>,
+}
 
 #[derive(Debug, Clone)]
 pub struct WatchManager {
-    // key -> 监视该 key 的会话 ID 集合
-    watched_keys: Arc<DashMap<String, DashSet<u64>>>,
-    // Session ID -> 该会话监视的 key 集合
-    session_watches: Arc<DashMap<u64, DashSet<String>>>,
+    // key -> 版本号与监视该 key 的会话 ID；没有会话监视的 key 不保留
+    watched_keys: Arc<DashMap<Vec<u8>, WatchedKey>>,
+    // Session ID -> 该会话监视的 key 及 WATCH 时记下的版本号
+    session_watches: Arc<DashMap<u64, HashMap<Vec<u8>, u64>>>,
+    // Client ID -> 开启 tracking 的连接
+    tracking_clients: Arc<DashMap<u64, TrackingClient>>,
+    // key -> 读过该 key、需要接收失效消息的 Client ID
+    tracked_keys: Arc<DashMap<Vec<u8>, DashSet<u64>>>,
+}
+
+impl Default for WatchManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl WatchManager {
@@ -15,74 +49,144 @@ impl WatchManager {
         Self { 
             watched_keys: Arc::new(DashMap::new()),
             session_watches: Arc::new(DashMap::new()), 
+            tracking_clients: Arc::new(DashMap::new()),
+            tracked_keys: Arc::new(DashMap::new()),
         }
     }
 
-    // 添加监视
-    pub fn watch(&self, session_id: u64, keys:&[String]) {
+    // 添加监视，记下 key 当前的版本号
+    // 会话已经监视的 key 保留第一次 WATCH 时的版本，两次 WATCH 之间的修改同样使事务失败
+    pub fn watch<K: AsRef<[u8]>>(&self, session_id: u64, keys: &[K]) {
         for key in keys {
-            // 添加 key 到 session 的映射
-            self.watched_keys
-                .entry(key.clone())
-                .or_insert_with(|| DashSet::new())
-                .insert(session_id);
-                
-            // 添加 session 到 key 的映射
+            let key = key.as_ref();
+            // 先释放 watched_keys 的锁再操作 session_watches，与 is_dirty 的加锁顺序保持一致
+            let version = {
+                let mut entry = self.watched_keys.entry(key.to_vec()).or_default();
+                entry.sessions.insert(session_id);
+                entry.version
+            };
+
             self.session_watches
                 .entry(session_id)
-                .or_insert_with(|| DashSet::new())
-                .insert(key.clone());
+                .or_default()
+                .entry(key.to_vec())
+                .or_insert(version);
         }
     }
 
     // 移除 session 的所有监视
     pub fn unwatch(&self, session_id: u64) {
-        if let Some(keys) = self.session_watches.remove(&session_id) {
-            for key in keys.1.iter() {
-                let key_str = key.as_str();
-                if let Some(entry) = self.watched_keys.get_mut(key_str) {
-                    entry.remove(&session_id);
+        if let Some((_, keys)) = self.session_watches.remove(&session_id) {
+            for key in keys.keys() {
+                if let Some(mut entry) = self.watched_keys.get_mut(key) {
+                    entry.sessions.remove(&session_id);
                 }
+                self.watched_keys.remove_if(key, |_, watched| watched.sessions.is_empty());
             }
         }
     }
 
-    // 通知 key 被修改
-    pub fn notify_key_change(&self, key: &str) -> Vec<u64> {
-        let mut affected_sessions = vec![];
-
-        let normalized_key = key.to_lowercase();
-
-        if let Some(sessions) = self.watched_keys.get(&normalized_key) {
-            affected_sessions = sessions.iter().map(|id| *id).collect();
-
-            // 移除该 key 的所有监视
-            if let Some(entry) = self.watched_keys.get_mut(&normalized_key) {
-                entry.clear();
+    // 通知 key 被修改：被监视的 key 版本号加一，返回监视它的会话（key 区分大小写）
+    pub fn notify_key_change(&self, key: &[u8]) -> Vec<u64> {
+        match self.watched_keys.get_mut(key) {
+            Some(mut entry) => {
+                entry.version += 1;
+                entry.sessions.iter().copied().collect()
             }
+            None => vec![],
         }
-
-        affected_sessions
     }
 
-    // 检查对话是否标记为脏
+    // 会话监视的 key 中是否有版本号与 WATCH 时不同的
     pub fn is_dirty(&self, session_id: u64) -> bool {
-        if let Some(keys) = self.session_watches.get(&session_id) {
-            for key in keys.iter() {
-                let normalized_key = key.to_lowercase();
-                let key_str = normalized_key.as_str();
-                if !self.watched_keys.contains_key(key_str) {
-                    return true;
-                }
-            }
-        }
-        false
+        let Some(keys) = self.session_watches.get(&session_id) else {
+            return false;
+        };
+        keys.iter().any(|(key, version)| {
+            self.watched_keys
+                .get(key)
+                .is_none_or(|watched| watched.version != *version)
+        })
     }
 
     // 清除会话的所有监视
     pub fn clear_session(&self, session_id: u64) {
         self.unwatch(session_id);
     }
+
+    // 开启客户端缓存追踪
+    pub fn enable_tracking(&self, client_id: u64, client: TrackingClient) {
+        self.tracking_clients.insert(client_id, client);
+    }
+
+    // 关闭追踪，并从 tracked_keys 中移除该客户端读过的 key（读过之后一直没被修改的 key 不会再触发清理）
+    pub fn disable_tracking(&self, client_id: u64) {
+        if self.tracking_clients.remove(&client_id).is_some() {
+            self.tracked_keys.retain(|_, ids| {
+                ids.remove(&client_id);
+                !ids.is_empty()
+            });
+        }
+    }
+
+    // 开启了追踪的客户端数
+    pub fn tracking_client_count(&self) -> usize {
+        self.tracking_clients.len()
+    }
+
+    // 记录了读取者的 key 数
+    pub fn tracked_key_count(&self) -> usize {
+        self.tracked_keys.len()
+    }
+
+    // 记录客户端读过的 key（BCAST 模式按前缀推送，无需记录）
+    pub fn track_keys<K: AsRef<[u8]>>(&self, client_id: u64, keys: &[K]) {
+        let default_mode = self
+            .tracking_clients
+            .get(&client_id)
+            .is_some_and(|c| c.bcast_prefixes.is_none());
+        if !default_mode {
+            return;
+        }
+        for key in keys {
+            self.tracked_keys
+                .entry(key.as_ref().to_vec())
+                .or_default()
+                .insert(client_id);
+        }
+    }
+
+    // key 被修改：向读过它的客户端以及匹配前缀的 BCAST 客户端推送失效消息
+    // 默认模式下每次读取只换来一次失效通知，推送后即移除记录
+    pub fn invalidate(&self, key: &[u8], origin: u64) {
+        let mut targets: Vec<u64> = self
+            .tracked_keys
+            .remove(key)
+            .map(|(_, ids)| ids.into_iter().collect())
+            .unwrap_or_default();
+
+        for entry in self.tracking_clients.iter() {
+            if let Some(prefixes) = &entry.bcast_prefixes
+                && (prefixes.is_empty() || prefixes.iter().any(|p| key.starts_with(p.as_bytes())))
+            {
+                targets.push(*entry.key());
+            }
+        }
+
+        for id in targets {
+            if let Some(client) = self.tracking_clients.get(&id) {
+                if client.noloop && id == origin {
+                    continue;
+                }
+                let msg = Frame::Push(vec![
+                    Frame::bulk("invalidate"),
+                    Frame::Array(vec![Frame::bulk(key)]),
+                ]);
+                // 连接已断开时发送失败，忽略即可
+                let _ = client.sender.send(msg);
+            }
+        }
+    }
 }
 
 // src/engine/watch.rs 底部添加
@@ -100,12 +204,12 @@ mod tests {
         manager.watch(session_id, &keys);
         
         // 验证键被监视
-        assert!(manager.watched_keys.contains_key("key1"));
-        assert!(manager.watched_keys.contains_key("key2"));
-        assert_eq!(manager.watched_keys.get("key1").unwrap().len(), 1);
+        assert!(manager.watched_keys.contains_key(b"key1".as_slice()));
+        assert!(manager.watched_keys.contains_key(b"key2".as_slice()));
+        assert_eq!(manager.watched_keys.get(b"key1".as_slice()).unwrap().sessions.len(), 1);
         
         // 通知键被修改
-        let affected = manager.notify_key_change("key1");
+        let affected = manager.notify_key_change(b"key1");
         assert_eq!(affected, vec![session_id]);
         
         // 验证会话被标记为脏
@@ -114,5 +218,83 @@ mod tests {
         // 清除监视
         manager.clear_session(session_id);
         assert!(!manager.session_watches.contains_key(&session_id));
+        assert!(!manager.is_dirty(session_id));
+        assert!(manager.watched_keys.is_empty());
+    }
+
+    #[test]
+    fn test_versions() {
+        let manager = WatchManager::new();
+        manager.watch(1, &["k".to_string()]);
+        manager.notify_key_change(b"k");
+
+        // 同一会话再次 WATCH 已修改的 key，仍保留第一次的版本
+        manager.watch(1, &["k".to_string()]);
+        assert!(manager.is_dirty(1));
+
+        // 修改之后才 WATCH 的会话不受之前修改的影响，之后的每次修改都能发现
+        manager.watch(2, &["k".to_string()]);
+        assert!(!manager.is_dirty(2));
+        manager.notify_key_change(b"k");
+        assert!(manager.is_dirty(2));
+
+        // 解除监视后重新 WATCH，从当前版本开始
+        manager.unwatch(1);
+        manager.watch(1, &["k".to_string()]);
+        assert!(!manager.is_dirty(1));
+    }
+
+    #[test]
+    fn test_dirty_survives_rewatch_by_other_session() {
+        let manager = WatchManager::new();
+        manager.watch(1, &["k".to_string()]);
+        manager.notify_key_change(b"k");
+
+        // 其他会话重新监视同一个 key，不影响会话 1 的脏标记
+        manager.watch(2, &["k".to_string()]);
+        assert!(manager.is_dirty(1));
+        assert!(!manager.is_dirty(2));
+
+        // key 区分大小写
+        manager.notify_key_change(b"K");
+        assert!(!manager.is_dirty(2));
+    }
+
+    #[test]
+    fn test_tracking_invalidation() {
+        let manager = WatchManager::new();
+        let (tx1, mut rx1) = crate::output::push_channel(Default::default());
+        let (tx2, mut rx2) = crate::output::push_channel(Default::default());
+        manager.enable_tracking(1, TrackingClient { sender: tx1, noloop: true, bcast_prefixes: None });
+        manager.enable_tracking(2, TrackingClient {
+            sender: tx2,
+            noloop: false,
+            bcast_prefixes: Some(vec!["user:".to_string()]),
+        });
+
+        manager.track_keys(1, &["user:1"]);
+
+        // 客户端 1 自己修改：NOLOOP 不推送，但 BCAST 客户端仍会收到
+        manager.invalidate(b"user:1", 1);
+        assert!(rx1.try_recv().is_err());
+        assert_eq!(
+            rx2.try_recv().unwrap(),
+            Frame::Push(vec![Frame::bulk("invalidate"), Frame::Array(vec![Frame::bulk("user:1")])])
+        );
+
+        // 重新读取后由其他客户端修改
+        manager.track_keys(1, &["user:1"]);
+        manager.invalidate(b"user:1", 3);
+        assert!(rx1.try_recv().is_ok());
+
+        // 通知是一次性的
+        manager.invalidate(b"user:1", 3);
+        assert!(rx1.try_recv().is_err());
+
+        // BCAST 客户端收到每一次匹配前缀的修改，前缀不匹配则不推送
+        assert!(rx2.try_recv().is_ok());
+        assert!(rx2.try_recv().is_ok());
+        manager.invalidate(b"order:1", 3);
+        assert!(rx2.try_recv().is_err());
     }
 }
\ No newline at end of file
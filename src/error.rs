@@ -0,0 +1,140 @@
+// src/error.rs
+
+//! 命令错误
+//!
+//! 客户端按错误回复的第一个单词（错误码）区分错误种类：遇到 `NOAUTH` 先认证、
+//! 遇到 `READONLY` 改连主节点、遇到 `NOSCRIPT` 改用 EVAL 重发。[`CommandError`]
+//! 列出服务端会返回的错误码，文案固定的错误由变体本身给出，与 Redis 一致。
+//!
+//! 类型模块等内部代码仍以 `anyhow` 报错，回复前经 `From<anyhow::Error>` 转换：
+//! 错误中带有 `CommandError` 时原样使用，消息以已知错误码开头时保留该错误码，
+//! 其余一律归为 `ERR`，不会出现 "ERR ERR ..." 这样重复的前缀。
+
+use std::fmt;
+
+use crate::protocol::Frame;
+
+/// 回复给客户端的命令错误
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommandError {
+    /// 通用错误，消息不含 `ERR` 前缀
+    Err(String),
+    /// 对 key 执行了与其类型不符的操作
+    WrongType,
+    /// 连接尚未认证，消息说明如何认证
+    NoAuth(String),
+    /// ACL 不允许执行该命令或访问该 key
+    NoPerm(String),
+    /// 事务入队时出过错，EXEC 放弃整个事务
+    ExecAbort,
+    /// 只读副本拒绝写命令
+    ReadOnly,
+    /// 服务端处于只读模式（`read_only`），拒绝写命令；错误码同样是 `READONLY`
+    ReadOnlyServer,
+    /// 目标 key 已经存在
+    BusyKey,
+    /// EVALSHA 的脚本不在缓存中
+    NoScript,
+}
+
+impl CommandError {
+    /// 通用错误；消息已带 `ERR ` 前缀时去掉，避免重复
+    pub fn err(msg: impl Into<String>) -> Self {
+        let msg = msg.into();
+        match msg.strip_prefix("ERR ") {
+            Some(rest) => CommandError::Err(rest.to_string()),
+            None => CommandError::Err(msg),
+        }
+    }
+
+    /// `NOAUTH Authentication required.`
+    pub fn no_auth() -> Self {
+        CommandError::NoAuth("Authentication required.".to_string())
+    }
+
+    /// 错误码，即错误回复的第一个单词
+    pub fn code(&self) -> &'static str {
+        match self {
+            CommandError::Err(_) => "ERR",
+            CommandError::WrongType => "WRONGTYPE",
+            CommandError::NoAuth(_) => "NOAUTH",
+            CommandError::NoPerm(_) => "NOPERM",
+            CommandError::ExecAbort => "EXECABORT",
+            CommandError::ReadOnly | CommandError::ReadOnlyServer => "READONLY",
+            CommandError::BusyKey => "BUSYKEY",
+            CommandError::NoScript => "NOSCRIPT",
+        }
+    }
+
+    /// 错误码之后的说明文字
+    pub fn message(&self) -> &str {
+        match self {
+            CommandError::Err(msg) | CommandError::NoAuth(msg) | CommandError::NoPerm(msg) => msg,
+            CommandError::WrongType => "Operation against a key holding the wrong kind of value",
+            CommandError::ExecAbort => "Transaction discarded because of previous errors.",
+            CommandError::ReadOnly => "You can't write against a read only replica.",
+            CommandError::ReadOnlyServer => "You can't write against a read only server.",
+            CommandError::BusyKey => "Target key name already exists.",
+            CommandError::NoScript => "No matching script. Please use EVAL.",
+        }
+    }
+
+    /// 解析一条错误回复；错误码未知时整条消息作为 `ERR` 的说明
+    pub fn parse(reply: &str) -> Self {
+        let (code, rest) = reply.split_once(' ').unwrap_or((reply, ""));
+        match code {
+            "ERR" => CommandError::Err(rest.to_string()),
+            "WRONGTYPE" => CommandError::WrongType,
+            "NOAUTH" => CommandError::NoAuth(rest.to_string()),
+            "NOPERM" => CommandError::NoPerm(rest.to_string()),
+            "EXECABORT" => CommandError::ExecAbort,
+            "READONLY" => CommandError::ReadOnly,
+            "BUSYKEY" => CommandError::BusyKey,
+            "NOSCRIPT" => CommandError::NoScript,
+            _ => CommandError::Err(reply.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.code(), self.message())
+    }
+}
+
+impl std::error::Error for CommandError {}
+
+impl From<anyhow::Error> for CommandError {
+    fn from(e: anyhow::Error) -> Self {
+        match e.downcast::<CommandError>() {
+            Ok(e) => e,
+            Err(e) => CommandError::parse(&e.to_string()),
+        }
+    }
+}
+
+impl From<CommandError> for Frame {
+    fn from(e: CommandError) -> Self {
+        Frame::Error(e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+
+    #[test]
+    fn test_codes_and_conversions() {
+        assert_eq!(Frame::from(CommandError::ExecAbort), Frame::error("EXECABORT Transaction discarded because of previous errors."));
+        assert_eq!(CommandError::err("ERR syntax error").to_string(), "ERR syntax error");
+        assert_eq!(CommandError::no_auth().to_string(), "NOAUTH Authentication required.");
+
+        // anyhow 错误：已带错误码的保留，其余归为 ERR
+        assert_eq!(CommandError::from(anyhow!("ERR value is not an integer")), CommandError::err("value is not an integer"));
+        assert_eq!(CommandError::from(anyhow!("disk full")).to_string(), "ERR disk full");
+        assert_eq!(CommandError::from(anyhow!(CommandError::WrongType)), CommandError::WrongType);
+        assert_eq!(CommandError::parse("READONLY You can't write against a read only replica."), CommandError::ReadOnly);
+        assert_eq!(CommandError::ReadOnlyServer.to_string(), "READONLY You can't write against a read only server.");
+    }
+}
@@ -1,45 +1,39 @@
 // src/expire.rs
 
 use anyhow::{Context, Result};
-use crate::engine::KvEngine;
-use std::time::{SystemTime, UNIX_EPOCH};
+use crate::engine::{KvEngine, WriteBatch};
+use crate::keys::{self, Kind};
 use std::result::Result::Ok;
-// use tokio::time::{interval, Duration};
+use std::sync::atomic::Ordering;
 
-/// 所有过期元数据都存到默认 tree 下的 key = "expire:{user_key}"
-const EXPIRE_PREFIX: &str = "expire:";
+/// 主动过期每轮最多检查的过期记录数
+pub const ACTIVE_EXPIRE_KEYS_PER_CYCLE: usize = 200;
 
-/// 返回当前的 UNIX 毫秒
-fn now_ms() -> u64 {
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_millis() as u64
+/// 设置 key 的过期时间
+pub fn expire<E:KvEngine>(db: &E, key: &[u8], secs: u64) -> Result<String> {
+    expire_at(db, key, db.clock().now_ms().saturating_add(secs.saturating_mul(1_000)))
 }
 
-/// 设置 key 的过期时间
-pub fn expire<E:KvEngine>(db: &E, key: &str, secs: u64) -> Result<String> {
-    let ts = now_ms().saturating_add(secs * 1_000);
-    let meta = format!("{}{}", EXPIRE_PREFIX, key);
-    let prev = db   
-        .insert(meta.as_bytes(), &ts.to_be_bytes())
+/// 设置 key 在指定的 UNIX 毫秒时间点过期（EXPIREAT / PEXPIREAT）
+pub fn expire_at<E:KvEngine>(db: &E, key: &[u8], ts: u64) -> Result<String> {
+    let prev = db
+        .insert(&keys::expire(key), &ts.to_be_bytes())
         .context("ERR write EXPIRE")?;
     Ok(if prev.is_none() {"1".into()} else {"0".into()})
 }
 
 /// 查询 key TTL （返回剩余时间，key 不存在 或 无 expire 返回 -1）
-pub fn ttl<E: KvEngine>(db: &E, key: &str) -> Result<String> {
-    let meta = format!("{}{}", EXPIRE_PREFIX, key);
-    if let Some(bs) = db.get(meta.as_bytes()).context("ERR get TTL")? {
+pub fn ttl<E: KvEngine>(db: &E, key: &[u8]) -> Result<String> {
+    if let Some(bs) = db.get(&keys::expire(key)).context("ERR get TTL")? {
         let mut buf = [0u8; 8];
         buf.copy_from_slice(&bs);
         let exp_ts = u64::from_be_bytes(buf);
-        let now = now_ms();
+        let now = db.clock().now_ms();
         if exp_ts <= now {
             remove_key(db, key)?;
             return Ok("-2".into());
         }
-        let left = ((exp_ts - now) + 999) / 1000;
+        let left = (exp_ts - now).div_ceil(1000);
         Ok(left.to_string())
     } else {
         Ok("-1".into())
@@ -47,90 +41,150 @@ pub fn ttl<E: KvEngine>(db: &E, key: &str) -> Result<String> {
 }
 
 /// 移除 key 的过期属性
-pub fn persist<E:KvEngine>(db: &E, key: &str) -> Result<String> {
-    let meta = format!("{}{}", EXPIRE_PREFIX, key);
+pub fn persist<E:KvEngine>(db: &E, key: &[u8]) -> Result<String> {
     let prev = db
-        .remove(meta.as_bytes())
+        .remove(&keys::expire(key))
         .context("ERR PERSIST")?;
     Ok(if prev.is_some() {"1".into()} else {"0".into()})
 }
 
-/// 检查 key 是否过期，是则删除所有相关记录
-pub fn remove_if_expired<E: KvEngine>(db: &E, key: &str) -> Result<()> {
-    let meta = format!("{}{}", EXPIRE_PREFIX, key);
-    if let Some(bs) = db.get(meta.as_bytes()).context("ERR get EXPIRE")? {
+/// 检查 key 是否过期，是则删除所有相关记录并返回 true
+pub fn remove_if_expired<E: KvEngine>(db: &E, key: &[u8]) -> Result<bool> {
+    if let Some(bs) = db.get(&keys::expire(key)).context("ERR get EXPIRE")? {
         let mut buf = [0u8; 8];
         buf.copy_from_slice(&bs);
-        if u64::from_be_bytes(buf) <= now_ms() {
-            remove_key(db.as_db().expect("ERR remove EXPIRE"), key)?;
+        if u64::from_be_bytes(buf) <= db.clock().now_ms() {
+            remove_key(db, key)?;
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// 删除 key 在各类型命名空间下的全部记录以及过期元数据
+///
+/// 只用 KvEngine 的接口，普通 Db 与事务上下文都可以调用
+pub fn remove_key<E: KvEngine>(db: &E, key: &[u8]) -> Result<()> {
+    // 全部记录收集到一个批量写入中一次删除
+    let mut batch = WriteBatch::new();
+    // 1) 主 key 与 string 值
+    batch.remove(key);
+    batch.remove(keys::string(key));
+
+    // 2) hash / list / set 的成员记录
+    for kind in [Kind::Hash, Kind::ListData, Kind::ListMeta, Kind::Set] {
+        for item in db.scan_prefix(&keys::prefix(kind, key)) {
+            let (k, _) = item?;
+            batch.remove(k.to_vec());
         }
     }
-    Ok(())
+
+    // 3) 过期元数据
+    batch.remove(keys::expire(key));
+    db.apply_batch(&batch).context("ERR remove main data")
 }
 
-/// 删除主 data tree 和 各类型 子 Tree 和 expire Tree
-pub fn remove_key<E: KvEngine>(db: &E, key: &str) -> Result<()> {
-    // 1) 默认 tree 下删主 key
-    let main_key = key.as_bytes();
-    let _ = db.remove(main_key).context("ERR remove main data")?;
-
-    // 2) 删过期元数据
-    let meta = format!("{}{}", EXPIRE_PREFIX, key);
-    let _ = db.remove(meta.as_bytes()).context("ERR remove EXPIRE")?;
-
-    // 3) 如果是 &Db，就能 drop_tree
-    if let Some(plain) = db.as_db() {
-        let _ = plain.drop_tree(format!("hash:{}", key));
-        let _ = plain.drop_tree(format!("list:{}", key));
-        let _ = plain.drop_tree(format!("set:{}", key));
-        let _ = plain.drop_tree(format!("string:{}",key));
+/// 主动过期：从 `cursor`（上一轮检查到的过期记录）之后检查至多 `max` 条过期记录，
+/// 删除其中已过期的 key，返回删除的数量
+///
+/// 检查到末尾时把 `cursor` 清空，下一轮从头开始；长期不被访问的过期 key 也能被回收
+pub fn active_expire_cycle<E: KvEngine>(db: &E, cursor: &mut Vec<u8>, max: usize) -> Result<usize> {
+    let now = db.clock().now_ms();
+    let mut due = Vec::new();
+    let mut checked = 0;
+    // 从游标之后开始扫描，每轮的代价只与 `max` 有关，与过期记录的总数无关
+    for item in db.scan_prefix_after(Kind::Expire.tag(), cursor) {
+        let (k, v) = item?;
+        let ts = v.as_ref().try_into().map(u64::from_be_bytes).unwrap_or(0);
+        if ts <= now
+            && let Some(record) = keys::decode(&k)
+        {
+            due.push(record.key.to_vec());
+        }
+        checked += 1;
+        if checked == max {
+            *cursor = k.to_vec();
+            break;
+        }
+    }
+    if checked < max {
+        cursor.clear();
+    }
+
+    // 逐个重新确认，期间被重新设置过期时间的 key 不删除
+    let mut removed = 0;
+    for key in due {
+        if remove_if_expired(db, &key)? {
+            removed += 1;
+        }
     }
-    Ok(())
+    if let Some(stats) = db.keyspace_stats() {
+        stats.expired.fetch_add(removed as u64, Ordering::Relaxed);
+    }
+    Ok(removed)
 }
-/// 后台定时清理任务
-// pub async fn start_cleaner(db: sled::Db, interval_secs: u64) {
-//     let mut iv = interval(Duration::from_secs(interval_secs));
-//     loop {
-//         iv.tick().await;
-//         let now = now_ms().to_be_bytes();
-
-//         // scan_prefix 只遍历默认 tree 下所有 "expire:" 开头的 entry
-//         for entry in db.scan_prefix(EXPIRE_PREFIX.as_bytes()) {
-//             if let Ok((k, v)) = entry {
-//                 // k = b"expire:thekey"
-//                 if v <= (&now).into() {
-//                     if let Ok(kstr) = std::str::from_utf8(&k[EXPIRE_PREFIX.len()..]) {
-//                         let _ = remove_key(&db, kstr);
-//                     }
-//                 }
-//             }
-//         }
-//     }
-// }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::thread::sleep;
+    use crate::clock::MockClock;
+    use crate::engine::Storage;
+    use crate::types::string;
+    use std::sync::Arc;
+    use std::time::Duration;
 
     #[test]
     fn test_expire_and_ttl() -> Result<()> {
-        let tmp = tempfile::tempdir()?;
-        std::env::set_current_dir(&tmp)?;
-        let db = sled::open("db")?;
+        let clock = Arc::new(MockClock::starting_now());
+        let db = Storage::memory().with_clock(clock.clone());
 
         // SET + EXPIRE
-        db.insert(b"k", b"v")?;
-        assert_eq!(expire(&db, "k", 1)?, "1");
-        // 立即 TTL 应接近 1
-        let t1 = ttl(&db, "k")?.parse::<i64>()?;
-        assert!(t1 <= 1 && t1 >= 0);
-        // 睡眠 1.2s
-        sleep(std::time::Duration::from_millis(1200));
-        // TTL 返回 -2，且 key 被删除
-        assert_eq!(ttl(&db, "k")?, "-2");
-        assert!(db.get(b"k")?.is_none());
+        string::set(&db, b"k", b"v")?;
+        assert_eq!(expire(&db, b"k", 1)?, "1");
+        assert_eq!(ttl(&db, b"k")?, "1");
+        clock.advance(Duration::from_millis(400));
+        assert_eq!(ttl(&db, b"k")?, "1");
+        // 拨过过期时间：TTL 返回 -2，且 key 被删除
+        clock.advance(Duration::from_millis(600));
+        assert_eq!(ttl(&db, b"k")?, "-2");
+        assert!(string::get(&db, b"k")?.is_none());
+        assert_eq!(ttl(&db, b"k")?, "-1");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_active_expire_cycle() -> Result<()> {
+        let clock = Arc::new(MockClock::new(1_000_000));
+        let db = Storage::memory().with_clock(clock.clone());
+        for i in 0..10 {
+            string::set(&db, format!("k{}", i).as_bytes(), b"v")?;
+            // 偶数 key 10 秒后过期，奇数 key 1 小时后过期
+            expire(&db, format!("k{}", i).as_bytes(), if i % 2 == 0 { 10 } else { 3600 })?;
+        }
+        string::set(&db, b"forever", b"v")?;
 
+        // 还没有 key 到期
+        let mut cursor = Vec::new();
+        assert_eq!(active_expire_cycle(&db, &mut cursor, 100)?, 0);
+        assert!(cursor.is_empty());
+
+        // 每轮只检查 4 条，从上一轮停下的位置继续，检查完一遍后回到开头
+        clock.advance(Duration::from_secs(10));
+        let mut removed = Vec::new();
+        for _ in 0..3 {
+            removed.push(active_expire_cycle(&db, &mut cursor, 4)?);
+        }
+        assert_eq!(removed.iter().sum::<usize>(), 5);
+        assert!(cursor.is_empty());
+        for i in 0..10 {
+            assert_eq!(string::get(&db, format!("k{}", i).as_bytes())?.is_some(), i % 2 == 1);
+        }
+        assert_eq!(string::get(&db, b"forever")?.as_deref(), Some(&b"v"[..]));
+
+        clock.advance(Duration::from_secs(3600));
+        assert_eq!(active_expire_cycle(&db, &mut cursor, 100)?, 5);
+        assert_eq!(db.scan_prefix(Kind::Expire.tag()).count(), 0);
         Ok(())
     }
-}
\ No newline at end of file
+}
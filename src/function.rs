@@ -0,0 +1,409 @@
+// src/function.rs
+
+//! WASM 服务端函数（FUNCTION LOAD / FCALL）
+//!
+//! `FUNCTION LOAD [REPLACE] <library> <code>` 注册一个 WASM 模块（WAT 文本或十六进制编码的二进制），
+//! 模块导出的每个签名为 `(numkeys: i32, ptr: i32, len: i32) -> i64` 的函数都可以用
+//! `FCALL <function> numkeys [key ...] [arg ...]` 调用。函数在 wasmi 解释器中运行，
+//! 只能通过导入的 `crab.call` 访问数据，执行步数（fuel）与线性内存都有上限。
+//!
+//! 与宿主交换的数据都是 RESP2 编码，位于模块的线性内存中：
+//! - 模块需导出 `memory` 与 `alloc(len: i32) -> i32`，宿主写入数据前先调用 `alloc` 取得缓冲区
+//! - 调用函数时传入 key 与参数组成的数组（前 `numkeys` 个是 key）
+//! - `crab.call(ptr, len) -> i64` 执行一条命令（参数数组），返回回复的位置
+//! - 函数返回回复的位置；位置都按 `(ptr << 32) | len` 打包，返回 0 表示 nil
+//!
+//! 每次调用新建一个实例，互不共享状态。和 Lua 脚本一样，整个调用在一个声明了 KEYS 的事务中执行，
+//! 出错时写入一起回滚；函数执行的写命令以 MULTI ... EXEC 的形式写入 AOF 并转发给副本，
+//! 重放时无需函数本身。函数库只保存在内存中，重启后需重新加载。
+
+use std::cell::RefCell;
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, Sender};
+
+use anyhow::Error;
+use bytes::{Bytes, BytesMut};
+use dashmap::DashMap;
+use sled::transaction::ConflictableTransactionError;
+use wasmi::core::{TrapCode, ValType};
+use wasmi::{Caller, Config, Engine, Extern, ExternType, Linker, Module, Store, StoreLimits, StoreLimitsBuilder};
+
+use crate::command::{self, parse_arg};
+use crate::engine::{self, KvEngine};
+use crate::protocol::{Frame, RESP2};
+use crate::protocol::parser::{ParserLimits, RespParser};
+use crate::script;
+
+/// 单个实例线性内存的上限
+const MAX_MEMORY: usize = 64 * 1024 * 1024;
+
+const HELP: &[&str] = &[
+    "FUNCTION <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+    "LOAD [REPLACE] <library-name> <code>",
+    "    Create a new library from WAT text or hex-encoded WASM. The library exports",
+    "    functions with the signature (numkeys: i32, ptr: i32, len: i32) -> i64.",
+    "DELETE <library-name>",
+    "    Delete a library and all its functions.",
+    "LIST",
+    "    Return information about the loaded libraries.",
+    "FLUSH [ASYNC|SYNC]",
+    "    Delete all the libraries.",
+    "HELP",
+    "    Print this help.",
+];
+
+/// 一个函数库：模块与其导出的函数名
+struct Library {
+    module: Arc<Module>,
+    functions: Vec<String>,
+}
+
+/// 已注册的函数库，所有连接共享
+pub struct Functions {
+    engine: Engine,
+    libraries: DashMap<String, Library>,
+    /// 每次调用可消耗的 fuel（约等于执行的指令数）
+    fuel: u64,
+}
+
+/// 实例的宿主数据：`crab.call` 经由通道把命令交给调用 FCALL 的线程执行
+struct Host {
+    commands: Sender<Vec<Bytes>>,
+    replies: Receiver<Frame>,
+    limits: StoreLimits,
+}
+
+impl Functions {
+    pub fn new(fuel: u64) -> Self {
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        Functions { engine: Engine::new(&config), libraries: DashMap::new(), fuel }
+    }
+
+    /// 执行 FUNCTION 子命令
+    pub fn execute(&self, args: &[String]) -> Frame {
+        match (args[0].to_uppercase().as_str(), args.len()) {
+            ("HELP", 1) => Frame::Array(HELP.iter().map(|l| Frame::Simple(l.to_string())).collect()),
+            ("LOAD", 3) => self.load(&args[1], &args[2], false),
+            ("LOAD", 4) if args[1].eq_ignore_ascii_case("REPLACE") => self.load(&args[2], &args[3], true),
+            ("DELETE", 2) => match self.libraries.remove(&args[1]) {
+                Some(_) => Frame::ok(),
+                None => Frame::error("ERR Library not found"),
+            },
+            ("LIST", 1) => Frame::Array(
+                self.libraries
+                    .iter()
+                    .map(|lib| {
+                        Frame::Map(vec![
+                            (Frame::bulk("library_name"), Frame::bulk(lib.key().as_str())),
+                            (
+                                Frame::bulk("functions"),
+                                Frame::Array(lib.functions.iter().map(|f| Frame::bulk(f.as_str())).collect()),
+                            ),
+                        ])
+                    })
+                    .collect(),
+            ),
+            ("FLUSH", 1) => {
+                self.libraries.clear();
+                Frame::ok()
+            }
+            ("FLUSH", 2) if ["ASYNC", "SYNC"].iter().any(|mode| args[1].eq_ignore_ascii_case(mode)) => {
+                self.libraries.clear();
+                Frame::ok()
+            }
+            _ => Frame::error(format!(
+                "ERR unknown subcommand or wrong number of arguments for '{}'. Try FUNCTION HELP.",
+                args[0]
+            )),
+        }
+    }
+
+    /// FUNCTION LOAD：编译模块并登记导出的函数，成功时回复库名
+    fn load(&self, name: &str, code: &str, replace: bool) -> Frame {
+        if !replace && self.libraries.contains_key(name) {
+            return Frame::error(format!("ERR Library '{}' already exists", name));
+        }
+        // 十六进制编码的二进制模块，否则按 WAT 文本解析
+        let source = hex::decode(code.trim()).unwrap_or_else(|_| code.as_bytes().to_vec());
+        let module = match wat::parse_bytes(&source).map_err(|e| e.to_string()).and_then(|wasm| {
+            Module::new(&self.engine, &wasm).map_err(|e| e.to_string())
+        }) {
+            Ok(module) => module,
+            Err(e) => return Frame::error(format!("ERR Error compiling function library: {}", e)),
+        };
+
+        let exports: Vec<_> = module.exports().map(|e| (e.name().to_string(), e.ty().clone())).collect();
+        let has = |name: &str, params: &[ValType], results: &[ValType]| {
+            exports.iter().any(|(n, ty)| {
+                n == name && matches!(ty, ExternType::Func(f) if f.params() == params && f.results() == results)
+            })
+        };
+        if !exports.iter().any(|(n, ty)| n == "memory" && matches!(ty, ExternType::Memory(_)))
+            || !has("alloc", &[ValType::I32], &[ValType::I32])
+        {
+            return Frame::error("ERR Function library must export 'memory' and 'alloc(i32) -> i32'");
+        }
+        let mut functions: Vec<String> = exports
+            .iter()
+            .filter(|(n, _)| n != "alloc")
+            .filter(|(n, _)| has(n, &[ValType::I32; 3], &[ValType::I64]))
+            .map(|(n, _)| n.clone())
+            .collect();
+        if functions.is_empty() {
+            return Frame::error("ERR No functions registered");
+        }
+        functions.sort();
+        // 函数名在所有库中唯一（替换同名库时不与自身比较）
+        for lib in self.libraries.iter().filter(|lib| lib.key() != name) {
+            if let Some(f) = functions.iter().find(|f| lib.functions.contains(f)) {
+                return Frame::error(format!("ERR Function {} already exists", f));
+            }
+        }
+
+        self.libraries.insert(name.to_string(), Library { module: Arc::new(module), functions });
+        Frame::bulk(name)
+    }
+
+    fn find(&self, function: &str) -> Option<Arc<Module>> {
+        self.libraries
+            .iter()
+            .find(|lib| lib.functions.iter().any(|f| f == function))
+            .map(|lib| lib.module.clone())
+    }
+
+    /// FCALL / FCALL_RO function numkeys [key ...] [arg ...]
+    ///
+    /// 返回回复与函数执行成功的写命令（用于 AOF 与复制）
+    pub fn fcall<E: KvEngine>(&self, db: &E, parts: &[Bytes]) -> (Frame, Vec<Vec<Bytes>>) {
+        let read_only = parts[0].eq_ignore_ascii_case(b"FCALL_RO");
+        let numkeys = match parse_arg::<i64>(&parts[2]) {
+            Some(n) if n < 0 => return (Frame::error("ERR Number of keys can't be negative"), vec![]),
+            Some(n) if n as usize > parts.len() - 3 => {
+                return (Frame::error("ERR Number of keys can't be greater than number of args"), vec![]);
+            }
+            Some(n) => n as usize,
+            None => return (Frame::error("ERR value is not an integer or out of range"), vec![]),
+        };
+        let Some((function, module)) = command::arg_str(&parts[1]).and_then(|f| Some((f, self.find(f)?))) else {
+            return (Frame::error("ERR Function not found"), vec![]);
+        };
+        let args = &parts[3..];
+        let keys: Vec<&[u8]> = args[..numkeys].iter().map(|k| &k[..]).collect();
+
+        // sled 冲突重试时重新记录，只保留提交的那次执行的写命令
+        let effects = RefCell::new(Vec::new());
+        let res = db.transaction_for_keys(&keys, |tx| {
+            effects.borrow_mut().clear();
+            self.run(tx, &module, function, numkeys, args, read_only, &effects)
+                .map_err(|msg| ConflictableTransactionError::Abort(Error::msg(msg)))
+        });
+        match res {
+            Ok(reply) => {
+                let effects = effects.into_inner();
+                engine::notify_watchers(db, &effects);
+                (reply, effects)
+            }
+            Err(e) => (Frame::error(e.to_string()), vec![]),
+        }
+    }
+
+    /// 在另一个线程中运行实例，本线程执行实例经由 `crab.call` 发来的命令，出错时返回错误消息
+    #[allow(clippy::too_many_arguments)]
+    fn run<E: KvEngine>(
+        &self,
+        db: &E,
+        module: &Module,
+        function: &str,
+        numkeys: usize,
+        args: &[Bytes],
+        read_only: bool,
+        effects: &RefCell<Vec<Vec<Bytes>>>,
+    ) -> Result<Frame, String> {
+        let (command_tx, command_rx) = mpsc::channel();
+        let (reply_tx, reply_rx) = mpsc::channel();
+        let host = Host {
+            commands: command_tx,
+            replies: reply_rx,
+            limits: StoreLimitsBuilder::new().memory_size(MAX_MEMORY).build(),
+        };
+        let input = Frame::Array(args.iter().map(|a| Frame::bulk(a.to_vec())).collect()).to_bytes(RESP2);
+
+        std::thread::scope(|scope| {
+            let guest = scope.spawn(|| self.invoke(host, module, function, numkeys, &input));
+            // 实例结束时宿主数据随 Store 一起释放，通道关闭，循环随之结束
+            for parts in command_rx {
+                let reply = match command::lookup(&parts[0]) {
+                    Some(spec) if read_only && spec.is_write() => {
+                        Frame::error("ERR Write commands are not allowed from read-only scripts")
+                    }
+                    _ => script::call_command(db, &parts),
+                };
+                if !reply.is_error() && command::lookup(&parts[0]).is_some_and(|spec| spec.is_write()) {
+                    effects.borrow_mut().push(parts);
+                }
+                let _ = reply_tx.send(reply);
+            }
+            guest.join().unwrap_or_else(|_| Err("ERR function panicked".to_string()))
+        })
+    }
+
+    /// 实例化模块、写入参数并调用函数
+    fn invoke(&self, host: Host, module: &Module, function: &str, numkeys: usize, input: &[u8]) -> Result<Frame, String> {
+        let failed = |e: wasmi::Error| match e.as_trap_code() {
+            Some(TrapCode::OutOfFuel) => "ERR function exceeded its fuel limit".to_string(),
+            _ => format!("ERR Error running function: {}", e),
+        };
+
+        let mut store = Store::new(&self.engine, host);
+        store.limiter(|host| &mut host.limits);
+        store.set_fuel(self.fuel).map_err(|e| format!("ERR {}", e))?;
+        let mut linker = Linker::<Host>::new(&self.engine);
+        linker.func_wrap("crab", "call", host_call).map_err(|e| format!("ERR {}", e))?;
+        let instance = linker
+            .instantiate(&mut store, module)
+            .and_then(|pre| pre.start(&mut store))
+            .map_err(failed)?;
+
+        let func = instance
+            .get_typed_func::<(i32, i32, i32), i64>(&store, function)
+            .map_err(|e| format!("ERR {}", e))?;
+        let Some(memory) = instance.get_memory(&store, "memory") else {
+            return Err("ERR function library has no memory".to_string());
+        };
+        let alloc = instance.get_typed_func::<i32, i32>(&store, "alloc").map_err(failed)?;
+        let ptr = alloc.call(&mut store, input.len() as i32).map_err(failed)?;
+        memory.write(&mut store, ptr as usize, input).map_err(|e| format!("ERR {}", e))?;
+
+        let packed = func.call(&mut store, (numkeys as i32, ptr, input.len() as i32)).map_err(failed)?;
+        if packed == 0 {
+            return Ok(Frame::Null);
+        }
+        let (ptr, len) = ((packed as u64 >> 32) as usize, packed as u32 as usize);
+        let mut reply = vec![0; len];
+        memory.read(&store, ptr, &mut reply).map_err(|e| format!("ERR {}", e))?;
+        match Frame::decode(&reply) {
+            Some((frame, used)) if used == len => Ok(frame),
+            _ => Err("ERR function returned an invalid reply".to_string()),
+        }
+    }
+}
+
+/// `crab.call(ptr, len) -> i64`：读出参数数组，交给宿主执行后把 RESP2 回复写回实例内存
+fn host_call(mut caller: Caller<'_, Host>, ptr: i32, len: i32) -> Result<i64, wasmi::Error> {
+    let memory = caller
+        .get_export("memory")
+        .and_then(Extern::into_memory)
+        .ok_or_else(|| wasmi::Error::new("missing memory export"))?;
+    let mut buf = BytesMut::zeroed(len as usize);
+    memory.read(&caller, ptr as usize, &mut buf).map_err(|e| wasmi::Error::new(e.to_string()))?;
+    let parts = match RespParser::new(ParserLimits::default()).parse_bytes(&mut buf) {
+        Ok(Some(parts)) => parts,
+        _ => return Err(wasmi::Error::new("crab.call expects a RESP array of bulk strings")),
+    };
+
+    let host = caller.data();
+    host.commands.send(parts).map_err(|_| wasmi::Error::new("host is gone"))?;
+    let reply = host.replies.recv().map_err(|_| wasmi::Error::new("host is gone"))?;
+    let bytes = reply.to_bytes(RESP2);
+
+    let alloc = caller
+        .get_export("alloc")
+        .and_then(Extern::into_func)
+        .ok_or_else(|| wasmi::Error::new("missing alloc export"))?
+        .typed::<i32, i32>(&caller)?;
+    let out = alloc.call(&mut caller, bytes.len() as i32)?;
+    memory.write(&mut caller, out as usize, &bytes).map_err(|e| wasmi::Error::new(e.to_string()))?;
+    Ok(((out as u32 as i64) << 32) | bytes.len() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::kv::Storage;
+    use crate::types::string;
+
+    fn args(s: &[&str]) -> Vec<String> {
+        s.iter().map(|x| x.to_string()).collect()
+    }
+
+    fn cmd(s: &[&str]) -> Vec<Bytes> {
+        s.iter().map(|x| Bytes::copy_from_slice(x.as_bytes())).collect()
+    }
+
+    const LIBRARY: &str = r#"
+        (module
+          (import "crab" "call" (func $call (param i32 i32) (result i64)))
+          (memory (export "memory") 1)
+          (global $next (mut i32) (i32.const 1024))
+          (func (export "alloc") (param $len i32) (result i32)
+            (local $ptr i32)
+            (local.set $ptr (global.get $next))
+            (global.set $next (i32.add (global.get $next) (local.get $len)))
+            (local.get $ptr))
+          (data (i32.const 0) "+PONG\r\n")
+          (data (i32.const 16) "*3\r\n$3\r\nSET\r\n$1\r\nk\r\n$1\r\nv\r\n")
+          (func (export "ping") (param i32 i32 i32) (result i64)
+            (i64.const 7))
+          (func (export "set_k") (param i32 i32 i32) (result i64)
+            (call $call (i32.const 16) (i32.const 27)))
+          (func (export "echo") (param $numkeys i32) (param $ptr i32) (param $len i32) (result i64)
+            (i64.or (i64.shl (i64.extend_i32_u (local.get $ptr)) (i64.const 32)) (i64.extend_i32_u (local.get $len))))
+          (func (export "spin") (param i32 i32 i32) (result i64)
+            (loop $l (br $l))
+            (i64.const 0)))
+    "#;
+
+    #[test]
+    fn test_load_and_list() {
+        let functions = Functions::new(1_000_000);
+        assert_eq!(functions.execute(&args(&["LOAD", "lib", LIBRARY])), Frame::bulk("lib"));
+        assert!(functions.execute(&args(&["LOAD", "lib", LIBRARY])).is_error());
+        // 函数名与其他库冲突
+        assert!(functions.execute(&args(&["LOAD", "other", LIBRARY])).is_error());
+        assert_eq!(functions.execute(&args(&["LOAD", "REPLACE", "lib", LIBRARY])), Frame::bulk("lib"));
+        assert!(functions.execute(&args(&["LOAD", "bad", "(module"])).is_error());
+        assert!(functions.execute(&args(&["LOAD", "empty", "(module (memory (export \"memory\") 1))"])).is_error());
+
+        let Frame::Array(libs) = functions.execute(&args(&["LIST"])) else { panic!() };
+        let Frame::Map(fields) = &libs[0] else { panic!() };
+        assert_eq!(
+            fields[1].1,
+            Frame::Array(["echo", "ping", "set_k", "spin"].iter().map(|f| Frame::bulk(*f)).collect())
+        );
+        assert_eq!(functions.execute(&args(&["DELETE", "lib"])), Frame::ok());
+        assert!(functions.execute(&args(&["DELETE", "lib"])).is_error());
+    }
+
+    #[test]
+    fn test_fcall() -> anyhow::Result<()> {
+        let db = sled::Config::new().temporary(true).open()?;
+        let storage = Storage::sled(db)?;
+        let functions = Functions::new(1_000_000);
+        functions.execute(&args(&["LOAD", "lib", LIBRARY]));
+
+        assert_eq!(functions.fcall(&storage, &cmd(&["FCALL", "ping", "0"])), (Frame::Simple("PONG".into()), vec![]));
+        assert_eq!(
+            functions.fcall(&storage, &cmd(&["FCALL", "echo", "1", "key", "arg"])).0,
+            Frame::Array(vec![Frame::bulk("key"), Frame::bulk("arg")])
+        );
+
+        // 回调引擎执行写命令，写命令作为 effects 返回
+        let (reply, effects) = functions.fcall(&storage, &cmd(&["FCALL", "set_k", "1", "k"]));
+        assert_eq!(reply, Frame::ok());
+        assert_eq!(effects, vec![cmd(&["SET", "k", "v"])]);
+        assert_eq!(string::get(&storage, b"k")?.as_deref(), Some(&b"v"[..]));
+
+        let (reply, effects) = functions.fcall(&storage, &cmd(&["FCALL_RO", "set_k", "1", "k"]));
+        assert_eq!(reply, Frame::error("ERR Write commands are not allowed from read-only scripts"));
+        assert!(effects.is_empty());
+
+        assert_eq!(
+            functions.fcall(&storage, &cmd(&["FCALL", "spin", "0"])).0,
+            Frame::error("ERR function exceeded its fuel limit")
+        );
+        assert_eq!(functions.fcall(&storage, &cmd(&["FCALL", "nope", "0"])).0, Frame::error("ERR Function not found"));
+        assert!(functions.fcall(&storage, &cmd(&["FCALL", "ping", "2", "k"])).0.is_error());
+        Ok(())
+    }
+}
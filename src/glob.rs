@@ -0,0 +1,147 @@
+// src/glob.rs
+
+//! Redis 风格的 glob 匹配（与 `stringmatchlen` 语义一致）
+//!
+//! 支持：
+//! - `*` 任意长度（含空）
+//! - `?` 任意单个字符
+//! - `[abc]` / `[^abc]` / `[a-z]` 字符集合
+//! - `\x` 转义
+
+/// 判断 `s` 是否匹配模式 `pattern`
+pub fn glob_match(pattern: &[u8], s: &[u8]) -> bool {
+    let (mut p, mut i) = (0, 0);
+    // 最近一个 `*` 的位置，以及它当时对应的字符串位置，用于回溯
+    let mut star: Option<(usize, usize)> = None;
+
+    while i < s.len() {
+        if p < pattern.len() {
+            match pattern[p] {
+                b'*' => {
+                    // 合并连续的 `*`
+                    while p < pattern.len() && pattern[p] == b'*' {
+                        p += 1;
+                    }
+                    if p == pattern.len() {
+                        return true;
+                    }
+                    star = Some((p, i));
+                    continue;
+                }
+                b'?' => {
+                    p += 1;
+                    i += 1;
+                    continue;
+                }
+                b'[' => {
+                    if let Some((matched, next)) = match_class(pattern, p, s[i])
+                        && matched
+                    {
+                        p = next;
+                        i += 1;
+                        continue;
+                    }
+                }
+                b'\\' if p + 1 < pattern.len() => {
+                    if pattern[p + 1] == s[i] {
+                        p += 2;
+                        i += 1;
+                        continue;
+                    }
+                }
+                c => {
+                    if c == s[i] {
+                        p += 1;
+                        i += 1;
+                        continue;
+                    }
+                }
+            }
+        }
+
+        // 当前字符不匹配：回到上一个 `*`，让它多吞一个字符
+        match star {
+            Some((sp, si)) => {
+                p = sp;
+                i = si + 1;
+                star = Some((sp, si + 1));
+            }
+            None => return false,
+        }
+    }
+
+    while p < pattern.len() && pattern[p] == b'*' {
+        p += 1;
+    }
+    p == pattern.len()
+}
+
+/// 匹配 `[...]` 字符集合，返回 (是否匹配, 集合之后的模式位置)
+fn match_class(pattern: &[u8], start: usize, c: u8) -> Option<(bool, usize)> {
+    let mut p = start + 1;
+    let negate = pattern.get(p) == Some(&b'^');
+    if negate {
+        p += 1;
+    }
+
+    let mut matched = false;
+    loop {
+        match pattern.get(p) {
+            // 没有闭合的 `]`，Redis 把它当作集合在模式结尾处结束
+            None => break,
+            Some(b']') => {
+                p += 1;
+                break;
+            }
+            Some(b'\\') if p + 1 < pattern.len() => {
+                if pattern[p + 1] == c {
+                    matched = true;
+                }
+                p += 2;
+            }
+            Some(&lo) if pattern.get(p + 1) == Some(&b'-') && p + 2 < pattern.len()
+                && pattern[p + 2] != b']' =>
+            {
+                let hi = pattern[p + 2];
+                let (lo, hi) = if lo > hi { (hi, lo) } else { (lo, hi) };
+                if c >= lo && c <= hi {
+                    matched = true;
+                }
+                p += 3;
+            }
+            Some(&x) => {
+                if x == c {
+                    matched = true;
+                }
+                p += 1;
+            }
+        }
+    }
+
+    Some((matched != negate, p))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn m(p: &str, s: &str) -> bool {
+        glob_match(p.as_bytes(), s.as_bytes())
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(m("*", ""));
+        assert!(m("*", "anything"));
+        assert!(m("user:*", "user:1"));
+        assert!(!m("user:*", "order:1"));
+        assert!(m("h?llo", "hello"));
+        assert!(!m("h?llo", "hllo"));
+        assert!(m("h[ae]llo", "hallo"));
+        assert!(!m("h[^e]llo", "hello"));
+        assert!(m("h[a-c]llo", "hbllo"));
+        assert!(m("*:cache:*", "app:cache:42"));
+        assert!(m("a\\*b", "a*b"));
+        assert!(!m("a\\*b", "axb"));
+    }
+}
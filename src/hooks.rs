@@ -0,0 +1,151 @@
+// src/hooks.rs
+
+//! 命令钩子：在命令执行前后插入嵌入方自己的逻辑
+//!
+//! 通过 [`ServerBuilder::hook`](crate::ServerBuilder::hook) 注册的 [`CommandHook`] 对每条命令调用两次：
+//!
+//! - [`before`](CommandHook::before) 在按 `rename_command` 换回原命令名之后、查命令表与 ACL
+//!   检查之前调用，可以查看、改写参数，或返回错误拒绝执行。改写后的命令照常经过
+//!   参数个数、ACL、只读等检查，不能借此绕过权限；
+//! - [`after`](CommandHook::after) 在命令由引擎执行完后调用，带上回复与执行耗时。
+//!   发布订阅、INFO、CONFIG 等由连接层直接处理的命令不经过引擎，不会触发 `after`。
+//!
+//! 钩子在连接任务中同步调用，耗时的工作（如转发影子流量）应交给其他任务完成。
+//! 钩子看到的参数与回复都是客户端视角的，不含命名空间前缀。
+
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use bytes::Bytes;
+
+use crate::error::CommandError;
+use crate::protocol::Frame;
+
+/// 调用钩子的连接
+#[derive(Debug, Clone, Copy)]
+pub struct HookContext<'a> {
+    pub client_id: u64,
+    pub peer: SocketAddr,
+    /// 当前登录的 ACL 用户，尚未认证时为 `None`
+    pub user: Option<&'a str>,
+}
+
+/// 命令执行前后的钩子
+pub trait CommandHook: Send + Sync {
+    /// 命令执行前调用，`args[0]` 为命令名；可以原地改写参数，返回错误时命令不执行，
+    /// 错误直接回复给客户端（事务中出现时整个事务在 EXEC 时放弃）
+    fn before(&self, ctx: &HookContext<'_>, args: &mut Vec<Bytes>) -> Result<(), CommandError> {
+        let _ = (ctx, args);
+        Ok(())
+    }
+
+    /// 命令由引擎执行完后调用，`args` 为 `before` 改写后的参数
+    fn after(&self, ctx: &HookContext<'_>, args: &[Bytes], reply: &Frame, duration: Duration) {
+        let _ = (ctx, args, reply, duration);
+    }
+}
+
+/// 按注册顺序依次调用的一组钩子
+#[derive(Clone, Default)]
+pub struct Hooks {
+    hooks: Vec<Arc<dyn CommandHook>>,
+}
+
+impl Hooks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, hook: Arc<dyn CommandHook>) {
+        self.hooks.push(hook);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+
+    /// 依次调用各钩子的 `before`，第一个返回错误的钩子拒绝命令，之后的钩子不再调用
+    pub fn before(&self, ctx: &HookContext<'_>, args: &mut Vec<Bytes>) -> Result<(), CommandError> {
+        for hook in &self.hooks {
+            hook.before(ctx, args)?;
+            if args.is_empty() {
+                return Err(CommandError::err("command hook removed every argument"));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn after(&self, ctx: &HookContext<'_>, args: &[Bytes], reply: &Frame, duration: Duration) {
+        for hook in &self.hooks {
+            hook.after(ctx, args, reply, duration);
+        }
+    }
+}
+
+impl fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Hooks").field("len", &self.hooks.len()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// 把 GET 改写为 STRLEN，拒绝 FLUSHALL，并记录执行过的命令
+    #[derive(Default)]
+    struct Recorder {
+        seen: Mutex<Vec<Bytes>>,
+    }
+
+    impl CommandHook for Recorder {
+        fn before(&self, _ctx: &HookContext<'_>, args: &mut Vec<Bytes>) -> Result<(), CommandError> {
+            if args[0].eq_ignore_ascii_case(b"FLUSHALL") {
+                return Err(CommandError::NoPerm("FLUSHALL is disabled".to_string()));
+            }
+            if args[0].eq_ignore_ascii_case(b"GET") {
+                args[0] = Bytes::from_static(b"STRLEN");
+            }
+            Ok(())
+        }
+
+        fn after(&self, _ctx: &HookContext<'_>, args: &[Bytes], _reply: &Frame, _duration: Duration) {
+            self.seen.lock().unwrap().push(args[0].clone());
+        }
+    }
+
+    struct Clear;
+
+    impl CommandHook for Clear {
+        fn before(&self, _ctx: &HookContext<'_>, args: &mut Vec<Bytes>) -> Result<(), CommandError> {
+            args.clear();
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_hooks() {
+        let recorder = Arc::new(Recorder::default());
+        let mut hooks = Hooks::new();
+        assert!(hooks.is_empty());
+        hooks.push(recorder.clone());
+        let ctx = HookContext { client_id: 1, peer: "127.0.0.1:1".parse().unwrap(), user: Some("default") };
+
+        let mut args = vec![Bytes::from("get"), Bytes::from("k")];
+        hooks.before(&ctx, &mut args).unwrap();
+        assert_eq!(args, ["STRLEN", "k"]);
+        hooks.after(&ctx, &args, &Frame::Integer(0), Duration::ZERO);
+        assert_eq!(*recorder.seen.lock().unwrap(), ["STRLEN"]);
+
+        let mut args = vec![Bytes::from("FLUSHALL")];
+        assert_eq!(hooks.before(&ctx, &mut args).unwrap_err().to_string(), "NOPERM FLUSHALL is disabled");
+
+        // 钩子不能留下空命令
+        hooks.push(Arc::new(Clear));
+        let mut args = vec![Bytes::from("PING")];
+        assert!(hooks.before(&ctx, &mut args).is_err());
+    }
+}
@@ -0,0 +1,354 @@
+// src/keys.rs
+
+//! 底层记录的 key 编码
+//!
+//! 一个逻辑 key 按类型拆成若干条记录，记录的 key 统一为
+//! `类型标签 + 逻辑 key 的长度（u32 大端）+ 逻辑 key + 子段`：
+//!
+//! | 类型      | 标签         | 子段                               |
+//! |-----------|--------------|------------------------------------|
+//! | string    | `string:`    | 无                                 |
+//! | hash      | `hash:`      | field                              |
+//! | set       | `set:`       | member                             |
+//! | list 元素 | `list:data:` | 序号（u64 大端，按列表顺序排列）   |
+//! | list 元数据 | `list:meta:` | `head` / `tail`                  |
+//! | 过期时间  | `expire:`    | 无                                 |
+//!
+//! 逻辑 key 带长度前缀，key / field / member 里的 `:` 不会造成冲突，
+//! 一个 key 在某类型下的全部记录恰好是以 `prefix(kind, key)` 开头的那些。
+//! 所有模块都通过这里构造和解析记录 key，调整布局只需改这一处。
+//!
+//! sled 上每种类型的记录放在各自的 tree 中（见 `TREES`），记录 key 仍保留类型标签，
+//! 存储引擎按标签把记录分到对应的 tree。
+
+use anyhow::{Context, Result};
+use sled::{Db, Tree};
+
+/// 记录所属的命名空间
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Kind {
+    String,
+    Hash,
+    Set,
+    ListData,
+    ListMeta,
+    Expire,
+}
+
+/// 各类型的记录所在的 sled tree，按名字排列，与类型标签的字典序一致
+pub const TREES: [&str; 5] = ["expire", "hash", "list", "set", "string"];
+
+impl Kind {
+    pub const ALL: [Kind; 6] = [Kind::String, Kind::Hash, Kind::Set, Kind::ListData, Kind::ListMeta, Kind::Expire];
+
+    /// 该类型的记录所在的 tree 在 `TREES` 中的下标；列表的元素与元数据同在一个 tree
+    pub fn tree(self) -> usize {
+        match self {
+            Kind::Expire => 0,
+            Kind::Hash => 1,
+            Kind::ListData | Kind::ListMeta => 2,
+            Kind::Set => 3,
+            Kind::String => 4,
+        }
+    }
+
+    /// 命名空间的标签，也是该类型全部记录的公共前缀
+    pub fn tag(self) -> &'static [u8] {
+        match self {
+            Kind::String => b"string:",
+            Kind::Hash => b"hash:",
+            Kind::Set => b"set:",
+            Kind::ListData => b"list:data:",
+            Kind::ListMeta => b"list:meta:",
+            Kind::Expire => b"expire:",
+        }
+    }
+}
+
+/// list 元数据的子段
+const HEAD: &[u8] = b"head";
+const TAIL: &[u8] = b"tail";
+
+/// `key` 在 `kind` 下全部记录的公共前缀
+pub fn prefix(kind: Kind, key: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(kind.tag().len() + 4 + key.len() + 8);
+    write_prefix(&mut out, kind, key);
+    out
+}
+
+fn write_prefix(out: &mut Vec<u8>, kind: Kind, key: &[u8]) {
+    out.extend_from_slice(kind.tag());
+    out.extend_from_slice(&(key.len() as u32).to_be_bytes());
+    out.extend_from_slice(key);
+}
+
+fn with_suffix(kind: Kind, key: &[u8], suffix: &[u8]) -> Vec<u8> {
+    let mut out = prefix(kind, key);
+    out.extend_from_slice(suffix);
+    out
+}
+
+fn seq_suffix(seq: i64) -> [u8; 8] {
+    ((seq as u64) ^ (1 << 63)).to_be_bytes()
+}
+
+pub fn string(key: &[u8]) -> Vec<u8> {
+    prefix(Kind::String, key)
+}
+
+pub fn expire(key: &[u8]) -> Vec<u8> {
+    prefix(Kind::Expire, key)
+}
+
+pub fn hash_field(key: &[u8], field: &[u8]) -> Vec<u8> {
+    with_suffix(Kind::Hash, key, field)
+}
+
+pub fn set_member(key: &[u8], member: &[u8]) -> Vec<u8> {
+    with_suffix(Kind::Set, key, member)
+}
+
+/// 列表第 `seq` 个位置的元素；序号翻转符号位后按大端编码，字节序与数值顺序一致
+pub fn list_item(key: &[u8], seq: i64) -> Vec<u8> {
+    with_suffix(Kind::ListData, key, &seq_suffix(seq))
+}
+
+pub fn list_head(key: &[u8]) -> Vec<u8> {
+    with_suffix(Kind::ListMeta, key, HEAD)
+}
+
+pub fn list_tail(key: &[u8]) -> Vec<u8> {
+    with_suffix(Kind::ListMeta, key, TAIL)
+}
+
+/// 可复用的记录 key 缓冲区
+///
+/// 探测 key 的类型、遍历列表元素这类操作要连续构造很多条记录 key，
+/// 每次都覆盖写入同一块缓冲区，只在第一次（或 key 变长时）分配内存。
+/// 返回的切片在下一次构造前有效。
+#[derive(Debug, Default)]
+pub struct KeyBuf {
+    buf: Vec<u8>,
+}
+
+impl KeyBuf {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 同 [`prefix`]
+    pub fn prefix(&mut self, kind: Kind, key: &[u8]) -> &[u8] {
+        self.buf.clear();
+        write_prefix(&mut self.buf, kind, key);
+        &self.buf
+    }
+
+    /// 同 [`string`]
+    pub fn string(&mut self, key: &[u8]) -> &[u8] {
+        self.prefix(Kind::String, key)
+    }
+
+    /// 同 [`expire`]
+    pub fn expire(&mut self, key: &[u8]) -> &[u8] {
+        self.prefix(Kind::Expire, key)
+    }
+
+    /// 同 [`list_item`]
+    pub fn list_item(&mut self, key: &[u8], seq: i64) -> &[u8] {
+        self.buf.clear();
+        write_prefix(&mut self.buf, Kind::ListData, key);
+        self.buf.extend_from_slice(&seq_suffix(seq));
+        &self.buf
+    }
+}
+
+/// 解析后的记录 key
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Record<'a> {
+    pub kind: Kind,
+    pub key: &'a [u8],
+    /// 逻辑 key 之后的部分：field、member、列表序号或 `head` / `tail`
+    pub suffix: &'a [u8],
+}
+
+impl Record<'_> {
+    /// list 元数据中的 head 记录，每个列表恰好一条
+    pub fn is_list_head(&self) -> bool {
+        self.kind == Kind::ListMeta && self.suffix == HEAD
+    }
+}
+
+/// 以类型标签开头的记录（或前缀）所属的类型
+pub fn kind_of(record: &[u8]) -> Option<Kind> {
+    // 标签互不为前缀（"list:data:" 与 "list:meta:" 在第 6 个字节就分开了）
+    Kind::ALL.into_iter().find(|kind| record.starts_with(kind.tag()))
+}
+
+/// 解析记录 key，不是本模块编码的记录时返回 None
+pub fn decode(record: &[u8]) -> Option<Record<'_>> {
+    let kind = kind_of(record)?;
+    let rest = &record[kind.tag().len()..];
+    let len = u32::from_be_bytes(rest.get(..4)?.try_into().ok()?) as usize;
+    let key = rest.get(4..4 + len)?;
+    let suffix = &rest[4 + len..];
+    let well_formed = match kind {
+        Kind::String | Kind::Expire => suffix.is_empty(),
+        Kind::ListData => suffix.len() == 8,
+        Kind::ListMeta => suffix == HEAD || suffix == TAIL,
+        Kind::Hash | Kind::Set => true,
+    };
+    well_formed.then_some(Record { kind, key, suffix })
+}
+
+/// 记录布局的版本号存放的位置
+const LAYOUT_TREE: &str = "__crab_cage_meta";
+const LAYOUT_KEY: &[u8] = b"key_layout";
+const LAYOUT_VERSION: u8 = 3;
+
+/// 把旧版本放在单个 `tree` 中的记录搬到各类型的 tree，返回搬动的记录数
+///
+/// 版本 1 的记录（`类型:key:子段`，不带长度前缀）顺带改写成当前布局；
+/// 旧布局下 hash / set 的 key 与 field 在第一个 `:` 处切分，与旧版本读取时的理解一致。
+/// 打开 sled 数据目录时调用一次；升级完成后记下布局版本，之后直接跳过。
+/// 每条记录先写入新位置再删除旧记录，中途退出后重新执行即可继续。
+pub fn upgrade_layout(db: &Db, tree: &Tree) -> Result<usize> {
+    let meta = db.open_tree(LAYOUT_TREE)?;
+    if meta.get(LAYOUT_KEY)?.as_deref() == Some(&[LAYOUT_VERSION][..]) {
+        return Ok(0);
+    }
+    let trees = TREES.iter().map(|name| db.open_tree(name)).collect::<sled::Result<Vec<_>>>()?;
+    let mut upgraded = 0;
+    for item in tree.iter() {
+        let (k, v) = item?;
+        let new_key = match decode(&k) {
+            Some(_) => k.to_vec(),
+            None => match legacy_to_current(&k) {
+                Some(new_key) => new_key,
+                None => continue,
+            },
+        };
+        let Some(kind) = kind_of(&new_key) else { continue };
+        trees[kind.tree()].insert(new_key, v)?;
+        tree.remove(&k)?;
+        upgraded += 1;
+    }
+    meta.insert(LAYOUT_KEY, &[LAYOUT_VERSION])?;
+    db.flush().context("flush upgraded key layout")?;
+    Ok(upgraded)
+}
+
+/// 按旧布局解析一条记录 key，得到它在当前布局下的 key
+fn legacy_to_current(record: &[u8]) -> Option<Vec<u8>> {
+    let record = std::str::from_utf8(record).ok()?;
+    if let Some(key) = record.strip_prefix("string:") {
+        Some(string(key.as_bytes()))
+    } else if let Some(key) = record.strip_prefix("expire:") {
+        Some(expire(key.as_bytes()))
+    } else if let Some(rest) = record.strip_prefix("hash:") {
+        let (key, field) = rest.split_once(':')?;
+        Some(hash_field(key.as_bytes(), field.as_bytes()))
+    } else if let Some(rest) = record.strip_prefix("set:") {
+        let (key, member) = rest.split_once(':')?;
+        Some(set_member(key.as_bytes(), member.as_bytes()))
+    } else if let Some(rest) = record.strip_prefix("list:data:") {
+        let (key, seq) = rest.rsplit_once(':')?;
+        let seq = (seq.parse::<u64>().ok()? ^ (1 << 63)) as i64;
+        Some(list_item(key.as_bytes(), seq))
+    } else if let Some(rest) = record.strip_prefix("list:meta:") {
+        if let Some(key) = rest.strip_suffix(":head") {
+            Some(list_head(key.as_bytes()))
+        } else {
+            rest.strip_suffix(":tail").map(|key| list_tail(key.as_bytes()))
+        }
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let cases = [
+            (string(b"a:b"), Kind::String, &b"a:b"[..], &b""[..]),
+            (expire(b""), Kind::Expire, b"", b""),
+            (hash_field(b"h", b"f:1"), Kind::Hash, b"h", b"f:1"),
+            (set_member(b"s", b""), Kind::Set, b"s", b""),
+            (list_head(b"l"), Kind::ListMeta, b"l", b"head"),
+            (list_item(b"l", -1), Kind::ListData, b"l", &0x7FFF_FFFF_FFFF_FFFFu64.to_be_bytes()),
+            // key 与 field 可以是任意字节
+            (hash_field(b"\xff\x00", b"\xfe"), Kind::Hash, b"\xff\x00", b"\xfe"),
+        ];
+        for (record, kind, key, suffix) in cases {
+            assert_eq!(decode(&record), Some(Record { kind, key, suffix }));
+        }
+        assert!(decode(&list_head(b"l")).unwrap().is_list_head());
+        assert!(!decode(&list_tail(b"l")).unwrap().is_list_head());
+        // 每种类型的 tree 以标签的第一段命名
+        for kind in Kind::ALL {
+            assert!(kind.tag().starts_with(TREES[kind.tree()].as_bytes()));
+        }
+
+        // 旧布局与截断的记录都解析不出来
+        assert_eq!(decode(b"string:abc"), None);
+        assert_eq!(decode(b"hash:h:f"), None);
+        assert_eq!(decode(&string(b"abc")[..9]), None);
+    }
+
+    #[test]
+    fn test_no_collisions() {
+        // 旧布局下这两条记录都是 `hash:a:b:c`
+        assert_ne!(hash_field(b"a", b"b:c"), hash_field(b"a:b", b"c"));
+        // 一个 key 的前缀不会覆盖另一个 key 的记录
+        assert!(!hash_field(b"ab", b"c").starts_with(&prefix(Kind::Hash, b"a")));
+        assert!(!set_member(b"a:b", b"c").starts_with(&prefix(Kind::Set, b"a")));
+        // 列表元素按序号排列
+        let items: Vec<_> = [-2i64, -1, 0, 1, 300].iter().map(|seq| list_item(b"l", *seq)).collect();
+        assert!(items.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn test_key_buf() {
+        let mut buf = KeyBuf::new();
+        assert_eq!(buf.list_item(b"a-long-list-key", 7), list_item(b"a-long-list-key", 7));
+        // 较短的 key 覆盖之前的内容，不残留旧字节
+        assert_eq!(buf.string(b"s"), string(b"s"));
+        assert_eq!(buf.expire(b"s"), expire(b"s"));
+        assert_eq!(buf.prefix(Kind::Set, b"k"), prefix(Kind::Set, b"k"));
+    }
+
+    #[test]
+    fn test_upgrade_layout() -> Result<()> {
+        let db = sled::Config::new().temporary(true).open()?;
+        let tree = db.open_tree("")?;
+        tree.insert("string:k", "v")?;
+        tree.insert("hash:h:f:1", "v")?;
+        tree.insert("set:s:m", "")?;
+        tree.insert(format!("list:data:l:{}", 1u64 << 63), "x")?;
+        tree.insert("list:meta:l:head", &0i64.to_be_bytes())?;
+        tree.insert("expire:k", &1u64.to_be_bytes())?;
+        // 已经是版本 2 布局的记录只搬动、不改写
+        tree.insert(string(b"new"), "n")?;
+        // 无法识别的记录留在原处
+        tree.insert("raw", "r")?;
+
+        assert_eq!(upgrade_layout(&db, &tree)?, 7);
+        let typed = |kind: Kind| db.open_tree(TREES[kind.tree()]).unwrap();
+        assert_eq!(typed(Kind::String).get(string(b"k"))?.as_deref(), Some(&b"v"[..]));
+        assert_eq!(typed(Kind::Hash).get(hash_field(b"h", b"f:1"))?.as_deref(), Some(&b"v"[..]));
+        assert!(typed(Kind::Set).get(set_member(b"s", b"m"))?.is_some());
+        assert_eq!(typed(Kind::ListData).get(list_item(b"l", 0))?.as_deref(), Some(&b"x"[..]));
+        assert!(typed(Kind::ListMeta).get(list_head(b"l"))?.is_some());
+        assert!(typed(Kind::Expire).get(expire(b"k"))?.is_some());
+        assert_eq!(typed(Kind::String).get(string(b"new"))?.as_deref(), Some(&b"n"[..]));
+        assert_eq!(typed(Kind::String).len(), 2);
+        assert_eq!(tree.iter().keys().collect::<sled::Result<Vec<_>>>()?, vec![sled::IVec::from("raw")]);
+
+        // 记下版本后不再扫描
+        tree.insert("string:late", "v")?;
+        assert_eq!(upgrade_layout(&db, &tree)?, 0);
+        Ok(())
+    }
+}
@@ -2,10 +2,36 @@
 //! rudis 库：protocol / server / engine / expire / txn / monitor / types
 
 pub mod config;
+pub mod logging;   // 日志初始化（tracing）
+pub mod daemon;    // pid 文件、后台运行与 systemd 通知
+pub mod command;   // 命令元数据表
+pub mod error;     // 命令错误（CommandError）
+pub mod acl;       // ACL 用户与权限
+pub mod namespace; // 多租户命名空间
+pub mod glob;      // glob 模式匹配
+pub mod protocol;  // RESP2 / RESP3 编码
 pub mod server;    // 网络层 & 命令分发
+pub mod embed;     // 嵌入式启动（ServerBuilder）
+pub mod store;     // 不经过网络层的类型化接口（Store）
+pub mod client;    // 阻塞式 RESP 客户端（命令行工具使用）
+pub mod pubsub;    // 发布 / 订阅
+pub mod output;    // 客户端输出缓冲区上限
+pub mod script;    // Lua 脚本（EVAL / EVALSHA）
+pub mod function;  // WASM 服务端函数（FUNCTION / FCALL）
+pub mod plugin;    // 运行时注册的命令插件
+pub mod hooks;     // 命令执行前后的钩子
+pub mod tls;       // TLS 终止（rustls）
 pub mod engine;    // 存储引擎（sled + 持久化）
 pub mod expire;    // 过期策略
+pub mod clock;     // 时钟（过期判断使用，测试中可拨动）
+pub mod keys;      // 底层记录的 key 编码
 pub mod types;     // String / Hash / List / Set / ... 数据结构
 pub mod persistence;
+pub mod replication; // 主从复制
+pub mod cluster;     // 集群槽位路由
 pub mod txn;
-pub mod monitor;
\ No newline at end of file
+pub mod monitor;
+
+pub use embed::{ServerBuilder, ServerHandle};
+pub use error::CommandError;
+pub use store::Store;
\ No newline at end of file
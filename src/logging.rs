@@ -0,0 +1,255 @@
+// src/logging.rs
+
+//! 日志初始化：基于 tracing，级别、格式与输出位置来自配置
+//!
+//! - `log_level`：tracing 的过滤指令，如 `info` 或 `crab_cage=debug,sled=warn`；
+//!   设置了环境变量 `RUST_LOG` 时以环境变量为准
+//! - `log_format`：`text`（便于阅读）或 `json`（每行一个 JSON 对象，便于采集）
+//! - `logfile`：追加写入的日志文件，未设置时输出到标准输出
+//! - `log_rotate_size` / `log_rotate_secs`：日志文件达到该字节数、或打开超过该秒数时轮转，
+//!   旧文件依次改名为 `<logfile>.1`、`<logfile>.2`……，只保留最近 `log_rotate_keep` 个
+//!
+//! 进程运行中可以用 [`reload_level`] 修改 `log_level`（SIGHUP 重新加载配置时）；
+//! 日志文件被外部工具（如 logrotate）移走后，用 [`reopen`] 重新打开（收到 SIGUSR1 时）。
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Context, Result};
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::EnvFilter;
+
+use crate::config::Config;
+
+type Reloader = Box<dyn Fn(EnvFilter) -> Result<()> + Send + Sync>;
+
+/// `init` 安装的订阅者的过滤器替换函数
+static RELOADER: OnceLock<Reloader> = OnceLock::new();
+
+/// `init` 打开的日志文件，`reopen` 通过它重新打开
+static LOG_FILE: OnceLock<Arc<RotatingFile>> = OnceLock::new();
+
+/// 按大小或时间轮转的日志文件，可被多个线程共享写入
+#[derive(Debug)]
+pub struct RotatingFile {
+    path: PathBuf,
+    /// 达到该字节数时轮转，0 表示不按大小轮转
+    max_size: u64,
+    /// 打开超过该时长时轮转，0 表示不按时间轮转
+    interval: Duration,
+    /// 保留的旧文件个数，0 表示轮转时直接删除
+    keep: usize,
+    state: Mutex<FileState>,
+}
+
+#[derive(Debug)]
+struct FileState {
+    file: File,
+    size: u64,
+    opened: Instant,
+}
+
+impl FileState {
+    fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+        Ok(FileState { file, size, opened: Instant::now() })
+    }
+}
+
+impl RotatingFile {
+    pub fn open(path: &Path, max_size: u64, interval: Duration, keep: usize) -> io::Result<Self> {
+        Ok(RotatingFile {
+            path: path.to_path_buf(),
+            max_size,
+            interval,
+            keep,
+            state: Mutex::new(FileState::open(path)?),
+        })
+    }
+
+    /// 第 `n` 个旧文件：`<logfile>.n`
+    fn rotated(&self, n: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", n));
+        PathBuf::from(name)
+    }
+
+    /// 重新打开日志文件（文件已被外部移走时创建新文件）
+    pub fn reopen(&self) -> io::Result<()> {
+        *self.state.lock().unwrap() = FileState::open(&self.path)?;
+        Ok(())
+    }
+
+    /// 把当前文件改名为 `.1`，已有的旧文件序号依次加一，超出 `keep` 的删除，然后打开新文件
+    fn rotate(&self, state: &mut FileState) -> io::Result<()> {
+        if self.keep == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            let _ = fs::remove_file(self.rotated(self.keep));
+            for n in (1..self.keep).rev() {
+                let from = self.rotated(n);
+                if from.exists() {
+                    fs::rename(&from, self.rotated(n + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.rotated(1))?;
+        }
+        *state = FileState::open(&self.path)?;
+        Ok(())
+    }
+
+    fn due(&self, state: &FileState) -> bool {
+        state.size > 0
+            && ((self.max_size > 0 && state.size >= self.max_size)
+                || (!self.interval.is_zero() && state.opened.elapsed() >= self.interval))
+    }
+}
+
+impl Write for &RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut state = self.state.lock().unwrap();
+        // 轮转失败时继续写入当前文件，不丢日志
+        if self.due(&state) && let Err(e) = self.rotate(&mut state) {
+            eprintln!("failed to rotate log file {}: {}", self.path.display(), e);
+            state.opened = Instant::now();
+        }
+        let n = state.file.write(buf)?;
+        state.size += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.state.lock().unwrap().file.flush()
+    }
+}
+
+/// 过滤指令：环境变量 `RUST_LOG` 优先于配置
+fn filter(cfg: &Config) -> Result<EnvFilter> {
+    let directives = match std::env::var("RUST_LOG") {
+        Ok(env) if !env.is_empty() => env,
+        _ => cfg.log_level.clone(),
+    };
+    EnvFilter::try_new(&directives).with_context(|| format!("invalid log level '{}'", directives))
+}
+
+/// 安装全局日志订阅者，进程内只能调用一次
+///
+/// `logfile` 为已解析到数据目录下的日志文件路径
+pub fn init(cfg: &Config, logfile: Option<&Path>) -> Result<()> {
+    let json = match cfg.log_format.as_str() {
+        "text" => false,
+        "json" => true,
+        other => bail!("unknown log format '{}', expected \"text\" or \"json\"", other),
+    };
+    let filter = filter(cfg)?;
+
+    let writer = match logfile {
+        Some(path) => {
+            let file = RotatingFile::open(
+                path,
+                cfg.log_rotate_size,
+                Duration::from_secs(cfg.log_rotate_secs),
+                cfg.log_rotate_keep,
+            )
+            .with_context(|| format!("failed to open log file {}", path.display()))?;
+            let file = Arc::new(file);
+            let _ = LOG_FILE.set(file.clone());
+            BoxMakeWriter::new(file)
+        }
+        None => BoxMakeWriter::new(std::io::stdout),
+    };
+
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(writer)
+        .with_ansi(logfile.is_none() && std::io::stdout().is_terminal());
+    let reloader: Reloader = if json {
+        let builder = builder.json().with_filter_reloading();
+        let handle = builder.reload_handle();
+        builder.try_init().map_err(|e| anyhow!(e))?;
+        Box::new(move |filter| handle.reload(filter).map_err(|e| anyhow!(e)))
+    } else {
+        let builder = builder.with_filter_reloading();
+        let handle = builder.reload_handle();
+        builder.try_init().map_err(|e| anyhow!(e))?;
+        Box::new(move |filter| handle.reload(filter).map_err(|e| anyhow!(e)))
+    };
+    let _ = RELOADER.set(reloader);
+    Ok(())
+}
+
+/// 按配置的 `log_level` 替换日志过滤器；未调用过 `init` 时不做任何事
+pub fn reload_level(cfg: &Config) -> Result<()> {
+    let filter = filter(cfg)?;
+    match RELOADER.get() {
+        Some(reload) => reload(filter),
+        None => Ok(()),
+    }
+}
+
+/// 重新打开日志文件；日志输出到标准输出时不做任何事
+pub fn reopen() -> Result<()> {
+    match LOG_FILE.get() {
+        Some(file) => file.reopen().with_context(|| format!("failed to reopen log file {}", file.path.display())),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rejects_unknown_format() {
+        let mut cfg: Config = serde_json::from_str(
+            r#"{"aof":false,"rdb":false,"snapshot_interval_secs":60,"snapshot_threshold":20,
+                "metrics_enabled":false,"metrics_port":9090,"slowlog_threshold_ms":10}"#,
+        )
+        .unwrap();
+        assert_eq!((cfg.log_level.as_str(), cfg.log_format.as_str()), ("info", "text"));
+
+        // 格式无效时直接报错，不会创建日志文件
+        let dir = tempfile::tempdir().unwrap();
+        let logfile = dir.path().join("crab-cage.log");
+        cfg.log_format = "xml".into();
+        let err = init(&cfg, Some(&logfile)).unwrap_err();
+        assert!(err.to_string().contains("unknown log format 'xml'"));
+        assert!(!logfile.exists());
+    }
+
+    #[test]
+    fn test_rotation() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("crab-cage.log");
+        let log = RotatingFile::open(&path, 10, Duration::ZERO, 2)?;
+        let mut writer = &log;
+        for line in ["first line\n", "second line\n", "third line\n", "fourth line\n"] {
+            writer.write_all(line.as_bytes())?;
+        }
+        // 每行都超过 10 字节，写下一行前轮转；只保留最近两个旧文件
+        assert_eq!(fs::read_to_string(&path)?, "fourth line\n");
+        assert_eq!(fs::read_to_string(dir.path().join("crab-cage.log.1"))?, "third line\n");
+        assert_eq!(fs::read_to_string(dir.path().join("crab-cage.log.2"))?, "second line\n");
+        assert!(!dir.path().join("crab-cage.log.3").exists());
+
+        // 按时间轮转
+        let timed = RotatingFile::open(&dir.path().join("timed.log"), 0, Duration::from_secs(3600), 1)?;
+        let mut writer = &timed;
+        writer.write_all(b"old\n")?;
+        timed.state.lock().unwrap().opened -= Duration::from_secs(3600);
+        writer.write_all(b"new\n")?;
+        assert_eq!(fs::read_to_string(dir.path().join("timed.log.1"))?, "old\n");
+
+        // 被外部移走后重新打开
+        fs::rename(&path, dir.path().join("moved.log"))?;
+        log.reopen()?;
+        writer = &log;
+        writer.write_all(b"after\n")?;
+        assert_eq!(fs::read_to_string(&path)?, "after\n");
+        Ok(())
+    }
+}
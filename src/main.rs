@@ -1,120 +1,228 @@
 use clap::Parser;
-use anyhow::Result;
+use anyhow::{Context, Result};
 use tokio::signal;
-use std::sync::Arc;
-
-mod config;
-mod persistence;
-mod server;
-mod engine;
-mod types;
-mod expire;
-mod txn;
-mod monitor;
-
-use config::load;
-use persistence::Persistence;
-use sled::Db;
+use tracing::{debug, info, warn};
+
+use crab_cage::{daemon, logging, ServerBuilder};
+use crab_cage::config::{self, load_layered, Config};
+use crab_cage::persistence::Persistence;
 use std::path::PathBuf;
-use monitor::Monitor;
+use crab_cage::monitor::Monitor;
 
 /// crab-cage 启动参数
+///
+/// 除 `--config` 与 `--recover-to` 外，每个参数都对应一个配置字段，
+/// 优先级为：默认值 < 配置文件 < `CRAB_CAGE_*` 环境变量 < 命令行。
 #[derive(Parser, Debug)]
 #[command(author, version, about="Rudis server with AOF+RDB", long_about = None)]
 struct Args {
-    /// 监听地址 (host:port)
-    #[arg(short, long, default_value = "127.0.0.1:6380")]
-    listen: String,
+    /// 监听地址 (host:port)，多个地址用逗号分隔，如 `127.0.0.1:6380,[::1]:6380`（配置 `listen`）
+    #[arg(short, long)]
+    listen: Option<String>,
 
-    /// JSON 配置文件路径
+    /// 配置文件路径（JSON / TOML / YAML，按扩展名区分）
     #[arg(short, long, default_value = "config.json")]
     config: PathBuf,
 
-    /// sled 数据库目录
-    #[arg(short = 'd', long, default_value = "kv.db")]
-    db_path: PathBuf,
+    /// 数据目录，下面几个相对路径都放在该目录下（配置 `dir`）
+    #[arg(long)]
+    dir: Option<String>,
+
+    /// sled 数据库目录，`storage` 为 `memory` 时不使用（配置 `db_path`）
+    #[arg(short = 'd', long)]
+    db_path: Option<String>,
+
+    /// AOF 日志文件路径（配置 `aof_path`）
+    #[arg(long)]
+    aof_path: Option<String>,
+
+    /// RDB 快照文件路径（配置 `rdb_path`）
+    #[arg(long)]
+    rdb_path: Option<String>,
+
+    /// Prometheus 指标的 HTTP 端口（配置 `metrics_port`）
+    #[arg(long)]
+    metrics_port: Option<u16>,
+
+    /// 慢日志阈值（毫秒）（配置 `slowlog_threshold_ms`）
+    #[arg(long)]
+    slowlog_threshold_ms: Option<u64>,
+
+    /// 设置任意配置字段，可重复，如 `--set storage=memory --set aof=false`；值的写法与环境变量相同
+    #[arg(long = "set", value_name = "FIELD=VALUE", value_parser = parse_override)]
+    overrides: Vec<(String, String)>,
+
+    /// 按时间点恢复：只重放 AOF 中不晚于该 unix 时间（秒）的写入，之后的记录被截掉
+    #[arg(long, value_name = "TIMESTAMP")]
+    recover_to: Option<u64>,
+
+    /// 只读模式，拒绝全部写命令（配置 `read_only`）
+    #[arg(long)]
+    read_only: bool,
 
-    /// AOF 日志文件路径
-    #[arg(long, default_value = "appendonly.aof")]
-    aof_path: PathBuf,
+    /// pid 文件路径（配置 `pidfile`）
+    #[arg(long)]
+    pidfile: Option<String>,
 
-    /// RDB 快照文件路径
-    #[arg(long, default_value = "dump.rdb")]
-    rdb_path: PathBuf,
+    /// fork 到后台运行（配置 `daemonize`）
+    #[arg(long)]
+    daemonize: bool,
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
+fn parse_override(arg: &str) -> Result<(String, String), String> {
+    arg.split_once('=')
+        .map(|(field, value)| (field.to_string(), value.to_string()))
+        .ok_or_else(|| format!("expected FIELD=VALUE, got '{}'", arg))
+}
+
+impl Args {
+    /// 命令行给出的配置字段，作为最高优先级的一层
+    fn config_overrides(&self) -> Vec<(String, String)> {
+        let named = [
+            ("listen", self.listen.clone()),
+            ("dir", self.dir.clone()),
+            ("db_path", self.db_path.clone()),
+            ("aof_path", self.aof_path.clone()),
+            ("rdb_path", self.rdb_path.clone()),
+            ("metrics_port", self.metrics_port.map(|p| p.to_string())),
+            ("slowlog_threshold_ms", self.slowlog_threshold_ms.map(|ms| ms.to_string())),
+            ("read_only", self.read_only.then(|| "true".to_string())),
+            ("pidfile", self.pidfile.clone()),
+            ("daemonize", self.daemonize.then(|| "true".to_string())),
+        ];
+        named
+            .into_iter()
+            .filter_map(|(field, value)| value.map(|v| (field.to_string(), v)))
+            .chain(self.overrides.iter().cloned())
+            .collect()
+    }
+}
+
+fn main() -> Result<()> {
     // 1. 解析命令行参数
     let args = Args::parse();
-    println!("Starting Crab-Cage with args: {:?}", args);
-
-    // 2. 读取 JSON 配置
-    let cfg = load(&args.config)?;
-    println!("Loaded config: {:?}", cfg);
-
-    // 3. 打开 sled
-    let sled_db: Db = sled::open(&args.db_path)?;
-
-    // 4. 创建监视管理器
-    let watch_manager = Arc::new(engine::watch::WatchManager::new());
-    
-    // 5. 创建数据库实例
-    let db = engine::kv::DbInstance{
-        db: sled_db.clone(),
-        watch_manager: watch_manager.clone(),
-    };
-
-    // 6. 构造持久化器 (支持自定义路径)
-    let pers = Persistence::new_with_paths(
-        cfg.clone(),
-        sled_db.clone(),
-        args.aof_path.clone(),
-        args.rdb_path.clone(),
-    )?;
-
-    // 7. 创建监控系统
-    let monitor = Arc::new(Monitor::new());
-
-    // 8. 启动前重放 AOF
-    pers.load_aof()?;
-
-    // 9. 启动网络服务
-    let serve_handle = {
-        let db = db.clone();
-        let pers = pers.clone();
-        let addr = args.listen.clone();
-        let monitor = monitor.clone();
+
+    // 2. 分层读取配置：默认值 < 配置文件（不存在时写入一份默认配置）< 环境变量 < 命令行
+    let created = !args.config.exists();
+    let overrides = args.config_overrides();
+    let cfg = load_layered(&args.config, &overrides)?;
+
+    // 3. 按需转入后台并写 pid 文件，然后初始化日志，日志文件与其他数据文件一样放在数据目录下；
+    //    转入后台须在启动任何线程（日志、tokio 运行时）之前，pid 文件记录的是后台进程的进程号
+    let dir = cfg.dir.clone().map(PathBuf::from).unwrap_or_default();
+    std::fs::create_dir_all(&dir).with_context(|| format!("failed to create data directory {}", dir.display()))?;
+    if cfg.daemonize {
+        daemon::daemonize()?;
+    }
+    let pid_file = cfg.pidfile.as_ref().map(|f| daemon::PidFile::create(&dir.join(f))).transpose()?;
+    let logfile = cfg.logfile.as_ref().map(|f| dir.join(f));
+    logging::init(&cfg, logfile.as_deref())?;
+    info!(?args, "Starting Crab-Cage");
+    if created {
+        info!(path = %args.config.display(), "Config file not found, default configuration created");
+    }
+    debug!(?cfg, "Loaded config");
+    if cfg.storage == "memory" && !cfg.aof && !cfg.rdb {
+        warn!("In-memory storage with AOF and RDB disabled: data will be lost on restart");
+    }
+    if let Some(pid_file) = &pid_file {
+        info!(path = %pid_file.path().display(), "Wrote pid file");
+    }
+
+    let runtime = tokio::runtime::Runtime::new()?;
+    let result = runtime.block_on(run(args, cfg, overrides));
+    drop(pid_file);
+    result
+}
+
+/// 启动服务并运行到收到 CTRL-C 或网络服务出错
+async fn run(args: Args, cfg: Config, overrides: Vec<(String, String)>) -> Result<()> {
+    // 4. 打开存储与持久化器、加载插件、加载 RDB 并重放 AOF（或按时间点恢复），
+    //    然后启动网络服务与 HTTP 指标服务
+    let mut builder = ServerBuilder::new().config(cfg.clone());
+    if let Some(ts) = args.recover_to {
+        builder = builder.recover_to(ts);
+    }
+    let mut server = builder.start().await?;
+
+    // AOF 重放完成、监听地址已绑定：通知 systemd 服务已就绪
+    match daemon::notify("READY=1") {
+        Ok(true) => debug!("Notified systemd: READY=1"),
+        Ok(false) => {}
+        Err(e) => warn!("Failed to notify systemd: {:#}", e),
+    }
+
+    // 5. 收到 SIGHUP 时按同样的分层重新读取配置，不断开已有连接
+    #[cfg(unix)]
+    {
+        let path = args.config.clone();
+        let pers = server.persistence().clone();
+        let monitor = server.monitor().clone();
+        let mut current = cfg.clone();
+        let mut hangup = signal::unix::signal(signal::unix::SignalKind::hangup())?;
         tokio::spawn(async move {
-            server::start_with_addr_db_and_pers(&addr, db, pers, monitor)
-                .await
-                .unwrap();
-        })
-    };
-
-    // 10. 启动HTTP指标服务
-    if cfg.metrics_enabled {
-        let metrics_port = cfg.metrics_port;
-        let metrics = monitor.metrics.clone();
+            while hangup.recv().await.is_some() {
+                match load_layered(&path, &overrides) {
+                    Ok(new) => {
+                        reload(&current, &new, &monitor, &pers);
+                        current = new;
+                    }
+                    Err(e) => warn!(path = %path.display(), "Config reload failed, keeping the current configuration: {:#}", e),
+                }
+            }
+        });
+    }
+
+    // 6. 收到 SIGUSR1 时重新打开日志文件，配合 logrotate 等外部工具移走旧日志
+    #[cfg(unix)]
+    {
+        let mut usr1 = signal::unix::signal(signal::unix::SignalKind::user_defined1())?;
         tokio::spawn(async move {
-            start_metrics_server(metrics, metrics_port).await;
+            while usr1.recv().await.is_some() {
+                match logging::reopen() {
+                    Ok(()) => info!("Log file reopened"),
+                    Err(e) => warn!("{:#}", e),
+                }
+            }
         });
     }
 
-    // 11. 等 CTRL-C 优雅退出
-    signal::ctrl_c().await?;
-    println!("Shutting down…");
-    serve_handle.abort();
-    pers.fsync_and_close();
+    // 7. 等 CTRL-C 优雅退出；网络服务出错时同样退出
+    tokio::select! {
+        res = server.wait() => res?,
+        res = signal::ctrl_c() => res?,
+    }
+    info!("Shutting down…");
+    let _ = daemon::notify("STOPPING=1");
+    server.shutdown().await;
     Ok(())
 }
 
-async fn start_metrics_server(metrics: Arc<monitor::Metrics>, port: u16) {
-    use warp::Filter;
-
-    let route = warp::path("metrics")
-        .map(move || warp::reply::html(metrics.to_prometheus()));
-
-    println!("Metrics server listening on 0.0.0.0:{}", port);
-    warp::serve(route).run(([0, 0, 0, 0], port)).await;
-}
\ No newline at end of file
+/// 应用重新加载的配置中修改过、且可在运行时修改的字段，其余修改的字段只记录警告
+///
+/// 未修改的字段不重新应用，之前用 CONFIG SET 做的调整保持不变。
+fn reload(current: &Config, new: &Config, monitor: &Monitor, pers: &Persistence) {
+    let (applied, ignored): (Vec<String>, Vec<String>) = config::changed_fields(current, new)
+        .into_iter()
+        .partition(|field| config::RELOADABLE_FIELDS.contains(&field.as_str()));
+    for field in &applied {
+        match field.as_str() {
+            "log_level" => {
+                if let Err(e) = logging::reload_level(new) {
+                    warn!("Config reload: keeping the current log level: {:#}", e);
+                }
+            }
+            "slowlog_threshold_ms" => monitor.slow_log.set_threshold_us(new.slowlog_threshold_ms.saturating_mul(1000) as i64),
+            "slowlog_max_len" => monitor.slow_log.set_max_len(new.slowlog_max_len),
+            "hotkeys_sample_rate" => monitor.hot_keys.set_sample_rate(new.hotkeys_sample_rate),
+            "latency_monitor_threshold_ms" => pers.latency().set_threshold_ms(new.latency_monitor_threshold_ms),
+            "snapshot_interval_secs" => pers.set_snapshot_interval(new.snapshot_interval_secs),
+            "read_only" => pers.set_read_only(new.read_only),
+            _ => {}
+        }
+    }
+    info!(?applied, "Configuration reloaded");
+    if !ignored.is_empty() {
+        warn!(?ignored, "Config reload: these fields only take effect after a restart");
+    }
+}
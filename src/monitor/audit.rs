@@ -0,0 +1,116 @@
+// src/monitor/audit.rs
+
+//! 写命令审计日志
+//!
+//! 与 AOF 不同，审计日志面向合规审查而不是重放：每条写命令（含执行失败与被 ACL 拒绝的）
+//! 记录一行 JSON，包含时间、客户端地址、认证用户、完整参数与结果，只追加不重写。
+//! 可以写入单独的文件（配置 `audit_log`），也可以发布到一个频道（配置 `audit_channel`）。
+
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use bytes::Bytes;
+use serde_json::json;
+use tracing::warn;
+
+use crate::protocol::Frame;
+use crate::pubsub::PubSub;
+
+/// 审计日志，两种输出都未配置时不做任何事
+#[derive(Default)]
+pub struct AuditLog {
+    file: Option<Mutex<File>>,
+    channel: Option<String>,
+}
+
+impl AuditLog {
+    /// 以追加方式打开审计文件
+    pub fn open(path: Option<&Path>, channel: Option<String>) -> Result<Self> {
+        let file = match path {
+            Some(path) => Some(Mutex::new(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .with_context(|| format!("failed to open audit log {}", path.display()))?,
+            )),
+            None => None,
+        };
+        Ok(AuditLog { file, channel })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.file.is_some() || self.channel.is_some()
+    }
+
+    /// 记录一条写命令及其回复；`user` 为 `None` 表示连接尚未认证
+    pub fn record(&self, client: &str, user: Option<&str>, args: &[Bytes], reply: &Frame, pubsub: &PubSub) {
+        if !self.is_enabled() {
+            return;
+        }
+        let line = entry(now_ms(), client, user, args, reply);
+        if let Some(file) = &self.file {
+            // 每条记录一次 write，崩溃时最多丢失最后一行
+            if let Err(e) = file.lock().unwrap().write_all(format!("{}\n", line).as_bytes()) {
+                warn!("Failed to write audit log: {}", e);
+            }
+        }
+        if let Some(channel) = &self.channel {
+            pubsub.publish(channel, line.as_bytes());
+        }
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
+
+/// 一条审计记录，错误回复记为 `"outcome": "error"` 并附带错误信息；
+/// 参数中不是合法 UTF-8 的字节按替换字符记录
+fn entry(ts_ms: u64, client: &str, user: Option<&str>, args: &[Bytes], reply: &Frame) -> String {
+    let (outcome, error) = match reply {
+        Frame::Error(e) => ("error", Some(e.as_str())),
+        _ => ("ok", None),
+    };
+    json!({
+        "ts_ms": ts_ms,
+        "client": client,
+        "user": user,
+        "command": args.iter().map(|arg| String::from_utf8_lossy(arg)).collect::<Vec<_>>(),
+        "outcome": outcome,
+        "error": error,
+    })
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_to_file() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("audit.log");
+        let audit = AuditLog::open(Some(&path), None)?;
+        let pubsub = PubSub::new();
+        let args = |s: &[&str]| s.iter().map(|x| Bytes::copy_from_slice(x.as_bytes())).collect::<Vec<_>>();
+
+        audit.record("127.0.0.1:5000", Some("alice"), &args(&["SET", "k", "v"]), &Frame::ok(), &pubsub);
+        audit.record("127.0.0.1:5000", None, &args(&["DEL", "k"]), &Frame::error("NOAUTH Authentication required."), &pubsub);
+
+        let lines: Vec<serde_json::Value> =
+            std::fs::read_to_string(&path)?.lines().map(serde_json::from_str).collect::<serde_json::Result<_>>()?;
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["user"], "alice");
+        assert_eq!(lines[0]["command"], json!(["SET", "k", "v"]));
+        assert_eq!(lines[0]["outcome"], "ok");
+        assert_eq!(lines[1]["user"], serde_json::Value::Null);
+        assert_eq!(lines[1]["outcome"], "error");
+        assert_eq!(lines[1]["error"], "NOAUTH Authentication required.");
+        Ok(())
+    }
+}
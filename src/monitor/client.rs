@@ -16,21 +16,33 @@ impl ClientTracker {
         }
     }
 
-    pub fn add_client(&self, addr: SocketAddr) -> u64 {
+    /// 注册客户端，返回客户端 ID 与该连接的终止信号
+    pub fn add_client(&self, addr: SocketAddr) -> (u64, Arc<Notify>) {
         let mut clients = self.clients.lock().unwrap();
         let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let kill_signal = Arc::new(Notify::new());
 
         clients.insert(
             id, 
             ClientInfo { 
                 addr,
+                name: None,
                 connect_time: Instant::now(), 
                 last_command: "None".to_string(), 
                 last_command_time: Instant::now(), 
+                last_active: Instant::now(),
+                blocked: false,
+                kill_signal: kill_signal.clone(),
+                protocol: 2,
+                multi: None,
+                sub: 0,
+                psub: 0,
+                replica: false,
+                traffic: Arc::new(NetTraffic::default()),
             }
         );
 
-        id
+        (id, kill_signal)
     }
 
     pub fn remove_client(&self, id:u64) {
@@ -46,21 +58,152 @@ impl ClientTracker {
         }
     }
 
-    pub fn list_clients(&self) -> String {
+    pub fn set_name(&self, id: u64, name: Option<String>) {
+        let mut clients = self.clients.lock().unwrap();
+        if let Some(client) = clients.get_mut(&id) {
+            client.name = name;
+        }
+    }
+
+    pub fn get_name(&self, id: u64) -> Option<String> {
         let clients = self.clients.lock().unwrap();
-        let mut response = String::new();
+        clients.get(&id).and_then(|c| c.name.clone())
+    }
+
+    /// 更新连接的状态（协议、事务、订阅等），连接任务在每批命令处理完后调用
+    pub fn update<F>(&self, id: u64, f: F)
+    where
+        F: FnOnce(&mut ClientInfo),
+    {
+        let mut clients = self.clients.lock().unwrap();
+        if let Some(client) = clients.get_mut(&id) {
+            f(client);
+        }
+    }
+
+    /// 连接的收发字节计数器，连接任务持有它直接累加，无需每次加锁
+    pub fn traffic(&self, id: u64) -> Option<Arc<NetTraffic>> {
+        let clients = self.clients.lock().unwrap();
+        clients.get(&id).map(|c| c.traffic.clone())
+    }
 
+    /// 通知满足条件的连接退出，返回被终止的连接数
+    pub fn kill<F>(&self, filter: F) -> usize
+    where
+        F: Fn(u64, &ClientInfo) -> bool,
+    {
+        let clients = self.clients.lock().unwrap();
+        let mut killed = 0;
         for (id, client) in clients.iter() {
-            response.push_str(&format!(
-                "id={} addr={} age={}s idle={}s cmd={}\n",
-                id,
-                client.addr,
-                client.connect_time.elapsed().as_secs(),
-                client.last_command_time.elapsed().as_secs(),
-                client.last_command
-            ));
+            if filter(*id, client) {
+                // notify_one 会保留许可，连接正在处理命令时也不会丢失信号
+                client.kill_signal.notify_one();
+                killed += 1;
+            }
+        }
+        killed
+    }
+
+    /// 断开空闲超过 `limit` 的普通连接，返回断开的连接数
+    ///
+    /// 订阅中的连接只收不发，阻塞中的连接在等待结果，副本由复制流维持，都不算空闲
+    pub fn kill_idle(&self, limit: Duration) -> usize {
+        self.kill(|_, client| client.client_type() == "normal" && !client.blocked && client.last_active.elapsed() >= limit)
+    }
+
+    /// 单个客户端的信息，格式与 CLIENT LIST 的一行相同
+    pub fn client_info(&self, id: u64) -> Option<String> {
+        let clients = self.clients.lock().unwrap();
+        clients.get(&id).map(|client| format_client(id, client))
+    }
+
+    /// 满足条件的客户端，按 id 排序，每行格式与 CLIENT INFO 相同
+    pub fn list_clients<F>(&self, filter: F) -> String
+    where
+        F: Fn(u64, &ClientInfo) -> bool,
+    {
+        let clients = self.clients.lock().unwrap();
+        let mut ids: Vec<&u64> = clients.keys().filter(|id| filter(**id, &clients[id])).collect();
+        ids.sort();
+
+        let mut response = String::new();
+        for id in ids {
+            response.push_str(&format_client(*id, &clients[id]));
         }
 
         response
     }
-}
\ No newline at end of file
+}
+
+fn format_client(id: u64, client: &ClientInfo) -> String {
+    format!(
+        "id={} addr={} name={} age={} idle={} flags={} db=0 sub={} psub={} multi={} tot-net-in={} tot-net-out={} cmd={} resp={}\n",
+        id,
+        client.addr,
+        client.name.as_deref().unwrap_or(""),
+        client.connect_time.elapsed().as_secs(),
+        client.last_command_time.elapsed().as_secs(),
+        client.flags(),
+        client.sub,
+        client.psub,
+        client.multi.map_or(-1, |n| n as i64),
+        client.traffic.input.load(Ordering::Relaxed),
+        client.traffic.output.load(Ordering::Relaxed),
+        client.last_command.to_lowercase(),
+        client.protocol
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_name_and_kill() {
+        let tracker = ClientTracker::new();
+        let (a, _) = tracker.add_client("127.0.0.1:1000".parse().unwrap());
+        let (b, _) = tracker.add_client("127.0.0.1:2000".parse().unwrap());
+
+        tracker.set_name(a, Some("worker".into()));
+        assert_eq!(tracker.get_name(a).as_deref(), Some("worker"));
+        assert!(tracker.client_info(a).unwrap().contains("name=worker"));
+        assert_eq!(tracker.get_name(b), None);
+
+        tracker.update(b, |c| {
+            c.protocol = 3;
+            c.sub = 2;
+            c.multi = Some(1);
+        });
+        tracker.traffic(b).unwrap().input.fetch_add(10, Ordering::Relaxed);
+        let line = tracker.client_info(b).unwrap();
+        assert!(line.contains(" flags=Px db=0 sub=2 psub=0 multi=1 tot-net-in=10 tot-net-out=0 "), "{}", line);
+        assert!(line.ends_with(" resp=3\n"));
+        assert!(tracker.client_info(a).unwrap().contains(" flags=N "));
+
+        let pubsub = tracker.list_clients(|_, c| c.client_type() == "pubsub");
+        assert!(pubsub.starts_with(&format!("id={} ", b)));
+        assert_eq!(pubsub.lines().count(), 1);
+        assert_eq!(tracker.list_clients(|_, _| true).lines().count(), 2);
+
+        let killed = tracker.kill(|_, c| c.addr.to_string() == "127.0.0.1:2000");
+        assert_eq!(killed, 1);
+        assert_eq!(tracker.kill(|id, _| id == 42), 0);
+    }
+
+    #[test]
+    fn test_kill_idle() {
+        let tracker = ClientTracker::new();
+        let ids: Vec<u64> = (0..4).map(|i| tracker.add_client(format!("127.0.0.1:{}", 1000 + i).parse().unwrap()).0).collect();
+        let long_ago = Instant::now() - Duration::from_secs(600);
+        for id in &ids[1..] {
+            tracker.update(*id, |c| c.last_active = long_ago);
+        }
+        tracker.update(ids[2], |c| c.sub = 1);
+        tracker.update(ids[3], |c| c.blocked = true);
+
+        // 只有空闲的普通连接被断开
+        assert_eq!(tracker.kill_idle(Duration::from_secs(300)), 1);
+        assert_eq!(tracker.kill_idle(Duration::from_secs(3600)), 0);
+        assert!(tracker.client_info(ids[3]).unwrap().contains(" flags=b "));
+    }
+}
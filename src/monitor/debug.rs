@@ -0,0 +1,148 @@
+// src/monitor/debug.rs
+
+//! DEBUG 命令：面向测试与排障的调试工具
+//!
+//! 默认关闭，需要在配置中打开 `enable_debug_command`。
+//! 新的调试功能在 [`execute`] 中增加一个子命令分支即可。
+
+use std::time::Duration;
+
+use bytes::Bytes;
+
+use crate::command::{name_upper, parse_arg};
+use crate::engine::KvEngine;
+use crate::error::CommandError;
+use crate::glob::glob_match;
+use crate::keys;
+use crate::protocol::Frame;
+use crate::types;
+
+const HELP: &[&str] = &[
+    "DEBUG <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+    "OBJECT <key>",
+    "    Show low-level info about the key and associated value.",
+    "SLEEP <seconds>",
+    "    Stop the connection for <seconds>. Decimals allowed.",
+    "STRINGMATCH-LEN",
+    "    Run a fuzz tester against the glob matcher.",
+    "CHANGE-REPL-ID",
+    "    Change the replication IDs of the instance.",
+    "HELP",
+    "    Print this help.",
+];
+
+/// DEBUG 未开启时的错误
+pub fn disabled_error() -> Frame {
+    Frame::error(
+        "ERR DEBUG command not allowed. Set enable_debug_command to true in the configuration file, and then restart the server.",
+    )
+}
+
+/// 执行 DEBUG 子命令
+pub async fn execute<E: KvEngine>(args: &[Bytes], db: &E) -> Frame {
+    let Some(sub) = args.first() else {
+        return Frame::error("ERR wrong number of arguments for 'debug' command");
+    };
+
+    let sub = name_upper(sub);
+    match (sub.as_str(), args.len()) {
+        ("HELP", 1) => Frame::Array(HELP.iter().map(|l| Frame::Simple(l.to_string())).collect()),
+        ("SLEEP", 2) => match parse_arg::<f64>(&args[1]) {
+            Some(secs) if secs >= 0.0 && secs.is_finite() => {
+                tokio::time::sleep(Duration::from_secs_f64(secs)).await;
+                Frame::ok()
+            }
+            _ => Frame::error("ERR value is not a valid float"),
+        },
+        ("OBJECT", 2) => object(db, &args[1]),
+        ("STRINGMATCH-LEN", 1) => stringmatch_len(),
+        ("CHANGE-REPL-ID", 1) => {
+            Frame::error("ERR DEBUG CHANGE-REPL-ID requires replication, which is not enabled")
+        }
+        _ => Frame::error(format!(
+            "ERR unknown subcommand or wrong number of arguments for '{}'. Try DEBUG HELP.",
+            String::from_utf8_lossy(&args[0])
+        )),
+    }
+}
+
+/// DEBUG OBJECT key：类型、编码、元素个数与序列化长度
+fn object<E: KvEngine>(db: &E, key: &[u8]) -> Frame {
+    let stats = match types::key_stats(db, key) {
+        Ok(Some(stats)) => stats,
+        Ok(None) => return Frame::error("ERR no such key"),
+        Err(e) => return CommandError::from(e).into(),
+    };
+
+    let encoding = match stats.kind {
+        "string" => {
+            // 与 Redis 一致：整数用 int，短字符串用 embstr
+            let is_int = db
+                .get(&keys::string(key))
+                .ok()
+                .flatten()
+                .and_then(|v| std::str::from_utf8(&v).ok().and_then(|s| s.parse::<i64>().ok()))
+                .is_some();
+            if is_int {
+                "int"
+            } else if stats.bytes <= 44 {
+                "embstr"
+            } else {
+                "raw"
+            }
+        }
+        "list" => "quicklist",
+        _ => "hashtable",
+    };
+
+    Frame::Simple(format!(
+        "Value at:0 refcount:1 type:{} encoding:{} len:{} serializedlength:{} lru:0 lru_seconds_idle:0",
+        stats.kind, encoding, stats.len, stats.bytes
+    ))
+}
+
+/// DEBUG STRINGMATCH-LEN：用容易导致回溯爆炸的模式检查 glob 匹配不会卡死
+fn stringmatch_len() -> Frame {
+    let pattern = "a*".repeat(64) + "b";
+    let text = "a".repeat(4096);
+    let matched = glob_match(pattern.as_bytes(), text.as_bytes());
+    if matched {
+        return Frame::error("ERR glob matcher returned a wrong result");
+    }
+    Frame::ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sled::Config;
+
+    fn make_db() -> sled::Db {
+        Config::new()
+            .temporary(true)
+            .open()
+            .expect("打开临时 sled db 失败")
+    }
+
+    fn args(s: &[&str]) -> Vec<Bytes> {
+        s.iter().map(|x| Bytes::copy_from_slice(x.as_bytes())).collect()
+    }
+
+    #[tokio::test]
+    async fn test_debug_subcommands() {
+        let db = make_db();
+        types::string::set(&db, b"n", b"42").unwrap();
+
+        match execute(&args(&["OBJECT", "n"]), &db).await {
+            Frame::Simple(s) => assert!(s.contains("type:string encoding:int")),
+            other => panic!("unexpected reply {:?}", other),
+        }
+        assert_eq!(
+            execute(&args(&["OBJECT", "missing"]), &db).await,
+            Frame::error("ERR no such key")
+        );
+        assert_eq!(execute(&args(&["SLEEP", "0"]), &db).await, Frame::ok());
+        assert!(execute(&args(&["SLEEP", "abc"]), &db).await.is_error());
+        assert_eq!(execute(&args(&["STRINGMATCH-LEN"]), &db).await, Frame::ok());
+    }
+}
@@ -0,0 +1,220 @@
+// src/monitor/hotkeys.rs
+
+//! 热点 key 统计
+//!
+//! 每 N 条命令抽样一次（`hotkeys_sample_rate`，0 表示关闭），把命令涉及的 key 计入
+//! Count-Min Sketch，并维护估计访问次数最高的若干个 key。抽样累计到一定次数后
+//! 全部计数减半，使统计偏向最近的访问。
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::protocol::Frame;
+
+/// Sketch 的行数与每行的计数器个数
+const DEPTH: usize = 4;
+const WIDTH: usize = 4096;
+/// 最多跟踪的热点 key 个数
+const TOP_K: usize = 32;
+/// 累计这么多次抽样后全部计数减半
+const DECAY_PERIOD: u64 = 100_000;
+
+/// HOTKEYS GET 未指定个数时返回的条数
+const DEFAULT_GET_COUNT: usize = 10;
+
+const HELP: &[&str] = &[
+    "HOTKEYS <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+    "GET [<count>]",
+    "    Return the <count> most accessed keys with their estimated access counts",
+    "    (default: 10).",
+    "RESET",
+    "    Reset the hot key statistics.",
+    "HELP",
+    "    Print this help.",
+];
+
+struct Sketch {
+    counters: Vec<u32>,
+    /// 候选热点 key 及其估计的抽样次数
+    top: HashMap<String, u64>,
+    samples: u64,
+}
+
+impl Sketch {
+    fn new() -> Self {
+        Sketch { counters: vec![0; DEPTH * WIDTH], top: HashMap::new(), samples: 0 }
+    }
+
+    /// 计入一次访问，返回该 key 的估计抽样次数
+    fn add(&mut self, key: &str) -> u64 {
+        let mut estimate = u32::MAX;
+        for row in 0..DEPTH {
+            let mut hasher = DefaultHasher::new();
+            (row, key).hash(&mut hasher);
+            let counter = &mut self.counters[row * WIDTH + (hasher.finish() as usize % WIDTH)];
+            *counter = counter.saturating_add(1);
+            estimate = estimate.min(*counter);
+        }
+        estimate as u64
+    }
+
+    fn record(&mut self, key: &str) {
+        let estimate = self.add(key);
+        if let Some(count) = self.top.get_mut(key) {
+            *count = estimate;
+        } else if self.top.len() < TOP_K {
+            self.top.insert(key.to_string(), estimate);
+        } else if let Some((coldest, min)) = self.top.iter().min_by_key(|(_, count)| **count).map(|(k, c)| (k.clone(), *c))
+            && estimate > min
+        {
+            self.top.remove(&coldest);
+            self.top.insert(key.to_string(), estimate);
+        }
+
+        self.samples += 1;
+        if self.samples >= DECAY_PERIOD {
+            self.samples = 0;
+            self.counters.iter_mut().for_each(|c| *c /= 2);
+            self.top.retain(|_, count| {
+                *count /= 2;
+                *count > 0
+            });
+        }
+    }
+}
+
+pub struct HotKeys {
+    sketch: Mutex<Sketch>,
+    /// 每多少条命令抽样一次，0 表示关闭
+    sample_rate: AtomicU64,
+    commands: AtomicU64,
+}
+
+impl HotKeys {
+    pub fn new(sample_rate: u64) -> Self {
+        HotKeys {
+            sketch: Mutex::new(Sketch::new()),
+            sample_rate: AtomicU64::new(sample_rate),
+            commands: AtomicU64::new(0),
+        }
+    }
+
+    pub fn sample_rate(&self) -> u64 {
+        self.sample_rate.load(Ordering::Relaxed)
+    }
+
+    pub fn set_sample_rate(&self, sample_rate: u64) {
+        self.sample_rate.store(sample_rate, Ordering::Relaxed);
+    }
+
+    /// 每条命令调用一次；被抽中时才调用 `keys` 取出命令涉及的 key
+    pub fn sample<F, K>(&self, keys: F)
+    where
+        F: FnOnce() -> Vec<K>,
+        K: AsRef<[u8]>,
+    {
+        let rate = self.sample_rate();
+        if rate == 0 || !self.commands.fetch_add(1, Ordering::Relaxed).is_multiple_of(rate) {
+            return;
+        }
+        let keys = keys();
+        if keys.is_empty() {
+            return;
+        }
+        let mut sketch = self.sketch.lock().unwrap();
+        for key in keys {
+            sketch.record(&String::from_utf8_lossy(key.as_ref()));
+        }
+    }
+
+    /// 访问最多的 `count` 个 key 及估计的访问次数（抽样次数乘以抽样间隔）
+    pub fn top(&self, count: usize) -> Vec<(String, u64)> {
+        let rate = self.sample_rate().max(1);
+        let sketch = self.sketch.lock().unwrap();
+        let mut top: Vec<(String, u64)> = sketch.top.iter().map(|(k, c)| (k.clone(), c * rate)).collect();
+        top.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        top.truncate(count);
+        top
+    }
+
+    pub fn reset(&self) {
+        *self.sketch.lock().unwrap() = Sketch::new();
+    }
+
+    /// 执行 HOTKEYS 子命令
+    pub fn execute(&self, args: &[String]) -> Frame {
+        match (args[0].to_uppercase().as_str(), args.len()) {
+            ("HELP", 1) => Frame::Array(HELP.iter().map(|l| Frame::Simple(l.to_string())).collect()),
+            ("RESET", 1) => {
+                self.reset();
+                Frame::ok()
+            }
+            ("GET", 1 | 2) => {
+                let count = match args.get(1).map(|n| n.parse::<usize>()) {
+                    None => DEFAULT_GET_COUNT,
+                    Some(Ok(n)) => n,
+                    Some(Err(_)) => return Frame::error("ERR value is not an integer or out of range"),
+                };
+                Frame::Array(
+                    self.top(count)
+                        .into_iter()
+                        .map(|(key, hits)| Frame::Array(vec![Frame::bulk(key), Frame::Integer(hits as i64)]))
+                        .collect(),
+                )
+            }
+            _ => Frame::error(format!(
+                "ERR unknown subcommand or wrong number of arguments for '{}'. Try HOTKEYS HELP.",
+                args[0]
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_top_keys() {
+        let hot = HotKeys::new(1);
+        for i in 0..200 {
+            hot.sample(|| vec!["hot"]);
+            if i % 2 == 0 {
+                hot.sample(|| vec!["warm", "hot"]);
+            }
+        }
+        // 大量只出现一次的 key 不会挤掉热点
+        let cold: Vec<String> = (0..1000).map(|i| format!("cold:{}", i)).collect();
+        for key in &cold {
+            hot.sample(|| vec![key.as_str()]);
+        }
+
+        let top = hot.top(2);
+        assert_eq!(top[0].0, "hot");
+        assert!(top[0].1 >= 300);
+        assert_eq!(top[1].0, "warm");
+        assert!(top[1].1 >= 100);
+
+        hot.reset();
+        assert!(hot.top(10).is_empty());
+    }
+
+    #[test]
+    fn test_sampling_and_command() {
+        let hot = HotKeys::new(4);
+        for _ in 0..40 {
+            hot.sample(|| vec!["k"]);
+        }
+        // 抽中 10 次，按抽样间隔换算回访问次数
+        let reply = hot.execute(&["GET".to_string()]);
+        assert_eq!(reply, Frame::Array(vec![Frame::Array(vec![Frame::bulk("k"), Frame::Integer(40)])]));
+
+        hot.set_sample_rate(0);
+        hot.sample::<_, &str>(|| panic!("disabled sampling must not collect keys"));
+        assert!(hot.execute(&["GET".to_string(), "x".to_string()]).is_error());
+        assert_eq!(hot.execute(&["RESET".to_string()]), Frame::ok());
+    }
+}
@@ -1,14 +1,22 @@
 use super::*;
-use crate::monitor::metrics::Metrics;
+use crate::monitor::metrics::{CommandStat, Metrics};
 use crate::persistence::Persistence;
 use crate::engine::KvEngine;
+use crate::monitor::memory;
+use crate::pubsub::PubSub;
+use crate::replication::{LinkState, Replication};
 
+/// `tcp_port` 为发出请求的连接所在的监听端口
 pub fn build_info_response(
     section: Option<&str>,
+    tcp_port: u16,
     db: &impl KvEngine,
     pers: &Persistence,
-    metrics: &Metrics,
+    monitor: &Monitor,
+    replication: &Replication,
+    pubsub: &PubSub,
 ) -> String {
+    let metrics = &monitor.metrics;
     let sections = section.map(|s| vec![s]).unwrap_or_else(|| {
         vec![
             "server",
@@ -16,7 +24,12 @@ pub fn build_info_response(
             "memory",
             "persistence",
             "stats",
+            "pubsub",
+            "replication",
             "commandstats",
+            "latencystats",
+            "hotkeys",
+            "cluster",
         ]
     });
 
@@ -28,8 +41,12 @@ pub fn build_info_response(
                 response.push_str("#Server\n");
                 response.push_str(&format!("Crab-Cage_version:{}\n", env!("CARGO_PKG_VERSION")));
                 response.push_str(&format!("OS:{}\n", std::env::consts::OS));
-                // 此处为硬编码端口，后续需要修改
-                // response.push_str(&format!("tcp_port:{}\n", 6380));
+                response.push_str(&format!("process_id:{}\n", std::process::id()));
+                response.push_str(&format!("run_id:{}\n", monitor.run_id));
+                response.push_str(&format!("tcp_port:{}\n", tcp_port));
+                let uptime = monitor.start_time.elapsed().as_secs();
+                response.push_str(&format!("uptime_in_seconds:{}\n", uptime));
+                response.push_str(&format!("uptime_in_days:{}\n", uptime / 86400));
             }
             "clients" => {
                 response.push_str("# Clients\n");
@@ -37,32 +54,111 @@ pub fn build_info_response(
                     "connected_clients:{}\n",
                     metrics.connected_clients.load(Ordering::Relaxed)
                 ));
+                response.push_str(&format!(
+                    "maxclients:{}\n",
+                    pers.cfg.maxclients
+                ));
                 response.push_str(&format!(
                     "total_connections:{}\n",
                     metrics.total_connections.load(Ordering::Relaxed)
                 ));
             }
             "memory" => {
-                response.push_str(&format!("# Memory\n"));
+                response.push_str("# Memory\n");
+                let stats = memory::collect(db).unwrap_or_default();
                 response.push_str(&format!(
                     "used_memory:{} bytes\n",
-                    metrics.memory_usage()
+                    stats.dataset()
                 ));
+                if let Some(rss) = memory::process_rss() {
+                    response.push_str(&format!("used_memory_rss:{} bytes\n", rss));
+                }
+                for (kind, bytes) in [
+                    ("strings", stats.strings),
+                    ("hashes", stats.hashes),
+                    ("lists", stats.lists),
+                    ("sets", stats.sets),
+                ] {
+                    response.push_str(&format!("used_memory_{}:{} bytes\n", kind, bytes));
+                }
             }
             "persistence" => {
                 response.push_str("# Persistence\n");
+                let status = pers.status();
                 response.push_str(&format!(
                     "aof_enabled:{}\n",
                     pers.cfg.aof as u8
                 ));
                 response.push_str(&format!(
                     "aof_size:{} bytes\n",
-                    pers.aof_size()
+                    status.aof_size
+                ));
+                response.push_str(&format!(
+                    "aof_last_write_status:{}\n",
+                    if status.aof_last_write_ok { "ok" } else { "err" }
+                ));
+                response.push_str(&format!(
+                    "aof_write_failures:{}\n",
+                    status.aof_write_failures
+                ));
+                response.push_str(&format!(
+                    "aof_last_fsync_usec:{}\n",
+                    status.aof_last_fsync_us
                 ));
                 response.push_str(&format!(
                     "rdb_last_save:{}\n",
-                    pers.last_save_time()
-                ));               
+                    status.rdb_last_save
+                ));
+                response.push_str(&format!(
+                    "rdb_bgsave_in_progress:{}\n",
+                    status.rdb_save_in_progress as u8
+                ));
+                response.push_str(&format!(
+                    "rdb_last_bgsave_status:{}\n",
+                    if status.rdb_last_save_ok { "ok" } else { "err" }
+                ));
+                response.push_str(&format!(
+                    "rdb_last_bgsave_time_ms:{}\n",
+                    status.rdb_last_save_duration_ms
+                ));
+                response.push_str(&format!(
+                    "rdb_last_save_size:{} bytes\n",
+                    status.rdb_last_save_size
+                ));
+                response.push_str(&format!(
+                    "rdb_save_failures:{}\n",
+                    status.rdb_save_failures
+                ));
+                let rewrite = status.aof_rewrite;
+                response.push_str(&format!(
+                    "aof_rewrite_in_progress:{}\n",
+                    rewrite.in_progress as u8
+                ));
+                response.push_str(&format!(
+                    "aof_rewrites:{}\n",
+                    rewrite.rewrites
+                ));
+                response.push_str(&format!(
+                    "aof_last_bgrewrite_status:{}\n",
+                    if rewrite.last_ok { "ok" } else { "err" }
+                ));
+                response.push_str(&format!(
+                    "aof_last_rewrite_time_sec:{}\n",
+                    rewrite.last_duration_secs
+                ));
+                let load = pers.aof_load_status();
+                response.push_str(&format!(
+                    "aof_load_commands:{}\n",
+                    load.commands
+                ));
+                response.push_str(&format!(
+                    "aof_load_bytes:{}\n",
+                    load.bytes
+                ));
+                response.push_str(&format!(
+                    "aof_load_time_ms:{}\n",
+                    load.duration_ms
+                ));
             }
             "stats" => {
                 response.push_str("# Stats\n");
@@ -74,20 +170,153 @@ pub fn build_info_response(
                     "total_keys:{}\n",
                     metrics.key_count(db)
                 ));
+                response.push_str(&format!(
+                    "total_net_input_bytes:{}\n",
+                    metrics.net_input_bytes.load(Ordering::Relaxed)
+                ));
+                response.push_str(&format!(
+                    "total_net_output_bytes:{}\n",
+                    metrics.net_output_bytes.load(Ordering::Relaxed)
+                ));
+                if let Some(stats) = db.keyspace_stats() {
+                    for (name, counter) in [
+                        ("keyspace_hits", &stats.hits),
+                        ("keyspace_misses", &stats.misses),
+                        ("expired_keys", &stats.expired),
+                        ("evicted_keys", &stats.evicted),
+                    ] {
+                        response.push_str(&format!("{}:{}\n", name, counter.load(Ordering::Relaxed)));
+                    }
+                }
+                let (input_kbps, output_kbps) = metrics.instantaneous_kbps();
+                response.push_str(&format!("instantaneous_input_kbps:{:.2}\n", input_kbps));
+                response.push_str(&format!("instantaneous_output_kbps:{:.2}\n", output_kbps));
+                response.push_str(&format!(
+                    "rejected_connections:{}\n",
+                    metrics.rejected_connections.load(Ordering::Relaxed)
+                ));
+            }
+            "pubsub" => {
+                let stats = pubsub.stats();
+                response.push_str("# Pubsub\n");
+                for (name, value) in [
+                    ("pubsub_channels", stats.channels as u64),
+                    ("pubsub_patterns", stats.patterns as u64),
+                    ("pubsub_shardchannels", stats.shard_channels as u64),
+                    ("pubsub_messages_delivered", stats.delivered),
+                    ("pubsub_messages_dropped", stats.dropped),
+                    ("pubsub_clients_disconnected", stats.disconnected),
+                    ("pubsub_slow_subscribers", stats.slow_subscribers as u64),
+                    ("pubsub_slow_subscriber_events", stats.slow_events),
+                ] {
+                    response.push_str(&format!("{}:{}\n", name, value));
+                }
+                for channel in &stats.per_channel {
+                    response.push_str(&format!(
+                        "{}stat_{}:subscribers={},delivered={},dropped={}\n",
+                        channel.kind.name(),
+                        channel.name,
+                        channel.subscribers,
+                        channel.delivered,
+                        channel.dropped
+                    ));
+                }
             }
             "commandstats" => {
-                response.push_str("# Command Stats\n");
-                for entry in metrics.command_stats.iter() {
+                response.push_str("# Commandstats\n");
+                for (name, stat) in sorted_command_stats(metrics) {
                     response.push_str(&format!(
-                        "cmd_{}:{}\n",
-                        entry.key(),
-                        entry.value()
+                        "cmdstat_{}:calls={},usec={},usec_per_call={:.2},failed_calls={}\n",
+                        name,
+                        stat.calls,
+                        stat.usec,
+                        stat.usec_per_call(),
+                        stat.failed_calls
                     ));
                 }
             }
+            "latencystats" => {
+                response.push_str("# Latencystats\n");
+                for (name, stat) in sorted_command_stats(metrics) {
+                    response.push_str(&format!(
+                        "latency_percentiles_usec_{}:p50={},p99={},p99.9={}\n",
+                        name,
+                        stat.percentile(50.0),
+                        stat.percentile(99.0),
+                        stat.percentile(99.9)
+                    ));
+                }
+            }
+            "hotkeys" => {
+                response.push_str("# Hotkeys\n");
+                response.push_str(&format!("hotkeys_sample_rate:{}\n", monitor.hot_keys.sample_rate()));
+                for (i, (key, hits)) in monitor.hot_keys.top(10).into_iter().enumerate() {
+                    response.push_str(&format!("hotkey_{}:key={},accesses={}\n", i, key, hits));
+                }
+            }
+            "replication" => {
+                let repl = replication.info();
+                response.push_str("# Replication\n");
+                match &repl.master {
+                    Some((host, port, state)) => {
+                        response.push_str("role:slave\n");
+                        response.push_str(&format!("master_host:{}\n", host));
+                        response.push_str(&format!("master_port:{}\n", port));
+                        response.push_str(&format!(
+                            "master_link_status:{}\n",
+                            if *state == LinkState::Connected { "up" } else { "down" }
+                        ));
+                        response.push_str(&format!(
+                            "master_sync_in_progress:{}\n",
+                            (*state == LinkState::Sync) as u8
+                        ));
+                        response.push_str(&format!("slave_repl_offset:{}\n", repl.offset));
+                        response.push_str("slave_read_only:1\n");
+                    }
+                    None => response.push_str("role:master\n"),
+                }
+                response.push_str(&format!("connected_slaves:{}\n", repl.replicas.len()));
+                for (i, replica) in repl.replicas.iter().enumerate() {
+                    response.push_str(&format!(
+                        "slave{}:ip={},port={},state={},offset={},lag={}\n",
+                        i,
+                        replica.ip,
+                        replica.port,
+                        if replica.online { "online" } else { "send_bulk" },
+                        replica.offset,
+                        replica.lag_secs
+                    ));
+                }
+                response.push_str(&format!("master_replid:{}\n", repl.replid));
+                response.push_str(&format!("master_repl_offset:{}\n", repl.offset));
+                response.push_str("repl_backlog_active:1\n");
+                response.push_str(&format!("repl_backlog_size:{}\n", repl.backlog_size));
+                response.push_str(&format!(
+                    "repl_backlog_first_byte_offset:{}\n",
+                    repl.backlog_first_byte_offset
+                ));
+                response.push_str(&format!("repl_backlog_histlen:{}\n", repl.backlog_histlen));
+            }
+            "cluster" => {
+                response.push_str("# Cluster\n");
+                response.push_str(&format!(
+                    "cluster_enabled:{}\n",
+                    pers.cfg.cluster_enabled as u8
+                ));
+            }
             _ => {}
         }
     }
 
     response
-}
\ No newline at end of file
+}
+/// 按命令名排序的调用统计快照
+fn sorted_command_stats(metrics: &Metrics) -> Vec<(String, CommandStat)> {
+    let mut stats: Vec<_> = metrics
+        .command_stats
+        .iter()
+        .map(|entry| (entry.key().clone(), entry.value().clone()))
+        .collect();
+    stats.sort_by(|a, b| a.0.cmp(&b.0));
+    stats
+}
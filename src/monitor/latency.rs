@@ -0,0 +1,224 @@
+// src/monitor/latency.rs
+
+//! 延迟监控：按事件类型记录耗时超过阈值的延迟尖峰
+//!
+//! 阈值以毫秒计（与 Redis 的 `latency-monitor-threshold` 相同），0 表示关闭。
+//! 每类事件保留最近 160 个采样，同一秒内的多次尖峰合并为其中最大的一个。
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::protocol::Frame;
+
+/// 执行一条命令
+pub const EVENT_COMMAND: &str = "command";
+/// 命令超出 `command_timeout_ms` 被中止
+pub const EVENT_COMMAND_TIMEOUT: &str = "command-timeout";
+/// 生成 RDB 快照
+pub const EVENT_SNAPSHOT: &str = "snapshot";
+/// 追加一条 AOF 记录
+pub const EVENT_AOF_WRITE: &str = "aof-write";
+/// AOF 文件 fsync
+pub const EVENT_AOF_FSYNC: &str = "aof-fsync";
+/// 一次完整的 AOF 重写
+pub const EVENT_AOF_REWRITE: &str = "aof-rewrite";
+
+/// 每类事件最多保留的采样数
+const HISTORY_LEN: usize = 160;
+
+const HELP: &[&str] = &[
+    "LATENCY <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+    "HISTORY <event>",
+    "    Return time-latency samples for the <event> class.",
+    "LATEST",
+    "    Return the latest latency samples for all events.",
+    "RESET [<event> ...]",
+    "    Reset latency data of one or more <event> classes.",
+    "    (default: reset all data for all event classes)",
+    "HELP",
+    "    Print this help.",
+];
+
+/// 一次延迟尖峰：发生时间（unix 秒）与耗时（毫秒）
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LatencySample {
+    pub time: u64,
+    pub latency_ms: u64,
+}
+
+/// 一类事件的最新采样与历史最大值，用于 LATENCY LATEST 与指标
+#[derive(Debug, Clone, PartialEq)]
+pub struct LatencyEvent {
+    pub name: String,
+    pub latest: LatencySample,
+    pub max_ms: u64,
+}
+
+#[derive(Default)]
+struct EventHistory {
+    samples: VecDeque<LatencySample>,
+    max_ms: u64,
+}
+
+pub struct LatencyMonitor {
+    /// 阈值（毫秒），0 表示关闭
+    threshold_ms: AtomicU64,
+    events: Mutex<BTreeMap<String, EventHistory>>,
+}
+
+impl LatencyMonitor {
+    pub fn new(threshold_ms: u64) -> Self {
+        LatencyMonitor {
+            threshold_ms: AtomicU64::new(threshold_ms),
+            events: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn threshold_ms(&self) -> u64 {
+        self.threshold_ms.load(Ordering::Relaxed)
+    }
+
+    pub fn set_threshold_ms(&self, threshold_ms: u64) {
+        self.threshold_ms.store(threshold_ms, Ordering::Relaxed);
+    }
+
+    /// 记录一次 `event` 事件的耗时，未达到阈值时忽略
+    pub fn record(&self, event: &str, duration: Duration) {
+        let threshold = self.threshold_ms();
+        let latency_ms = duration.as_millis() as u64;
+        if threshold == 0 || latency_ms < threshold {
+            return;
+        }
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+
+        let mut events = self.events.lock().unwrap();
+        let history = events.entry(event.to_string()).or_default();
+        history.max_ms = history.max_ms.max(latency_ms);
+        match history.samples.back_mut() {
+            Some(last) if last.time == time => last.latency_ms = last.latency_ms.max(latency_ms),
+            _ => {
+                if history.samples.len() == HISTORY_LEN {
+                    history.samples.pop_front();
+                }
+                history.samples.push_back(LatencySample { time, latency_ms });
+            }
+        }
+    }
+
+    /// 每类事件的最新采样，按事件名排序
+    pub fn latest(&self) -> Vec<LatencyEvent> {
+        let events = self.events.lock().unwrap();
+        events
+            .iter()
+            .filter_map(|(name, history)| {
+                history.samples.back().map(|latest| LatencyEvent {
+                    name: name.clone(),
+                    latest: *latest,
+                    max_ms: history.max_ms,
+                })
+            })
+            .collect()
+    }
+
+    /// `event` 的全部采样，从旧到新
+    pub fn history(&self, event: &str) -> Vec<LatencySample> {
+        let events = self.events.lock().unwrap();
+        events.get(event).map(|h| h.samples.iter().copied().collect()).unwrap_or_default()
+    }
+
+    /// 清空指定事件的数据，`events` 为空时清空全部；返回被清空的事件数
+    pub fn reset(&self, events: &[String]) -> usize {
+        let mut all = self.events.lock().unwrap();
+        if events.is_empty() {
+            let n = all.len();
+            all.clear();
+            return n;
+        }
+        events.iter().filter(|e| all.remove(e.as_str()).is_some()).count()
+    }
+
+    /// 执行 LATENCY 子命令
+    pub fn execute(&self, args: &[String]) -> Frame {
+        match (args[0].to_uppercase().as_str(), args.len()) {
+            ("HELP", 1) => Frame::Array(HELP.iter().map(|l| Frame::Simple(l.to_string())).collect()),
+            ("LATEST", 1) => Frame::Array(
+                self.latest()
+                    .into_iter()
+                    .map(|event| {
+                        Frame::Array(vec![
+                            Frame::bulk(event.name.as_str()),
+                            Frame::Integer(event.latest.time as i64),
+                            Frame::Integer(event.latest.latency_ms as i64),
+                            Frame::Integer(event.max_ms as i64),
+                        ])
+                    })
+                    .collect(),
+            ),
+            ("HISTORY", 2) => Frame::Array(
+                self.history(&args[1])
+                    .into_iter()
+                    .map(|s| Frame::Array(vec![Frame::Integer(s.time as i64), Frame::Integer(s.latency_ms as i64)]))
+                    .collect(),
+            ),
+            ("RESET", _) => Frame::Integer(self.reset(&args[1..]) as i64),
+            _ => Frame::error(format!(
+                "ERR unknown subcommand or wrong number of arguments for '{}'. Try LATENCY HELP.",
+                args[0]
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cmd(parts: &[&str]) -> Vec<String> {
+        parts.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_threshold_and_merge() {
+        let latency = LatencyMonitor::new(0);
+        latency.record(EVENT_COMMAND, Duration::from_secs(1));
+        assert!(latency.latest().is_empty());
+
+        latency.set_threshold_ms(10);
+        latency.record(EVENT_COMMAND, Duration::from_millis(9));
+        latency.record(EVENT_COMMAND, Duration::from_millis(20));
+        latency.record(EVENT_COMMAND, Duration::from_millis(15));
+        latency.record(EVENT_SNAPSHOT, Duration::from_millis(12));
+
+        // 同一秒内的尖峰合并为最大的一个
+        let history = latency.history(EVENT_COMMAND);
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].latency_ms, 20);
+
+        let latest = latency.latest();
+        assert_eq!(latest.iter().map(|e| e.name.as_str()).collect::<Vec<_>>(), vec!["command", "snapshot"]);
+        assert_eq!(latest[0].max_ms, 20);
+    }
+
+    #[test]
+    fn test_commands() {
+        let latency = LatencyMonitor::new(1);
+        latency.record(EVENT_AOF_WRITE, Duration::from_millis(5));
+        latency.record(EVENT_AOF_FSYNC, Duration::from_millis(7));
+
+        let Frame::Array(latest) = latency.execute(&cmd(&["LATEST"])) else { panic!("expected array") };
+        let Frame::Array(fields) = &latest[0] else { panic!("expected array") };
+        assert_eq!(fields[0], Frame::bulk("aof-fsync"));
+        assert_eq!((&fields[2], &fields[3]), (&Frame::Integer(7), &Frame::Integer(7)));
+
+        let Frame::Array(history) = latency.execute(&cmd(&["HISTORY", "aof-write"])) else { panic!("expected array") };
+        assert_eq!(history.len(), 1);
+        assert_eq!(latency.execute(&cmd(&["HISTORY", "missing"])), Frame::Array(vec![]));
+
+        assert_eq!(latency.execute(&cmd(&["RESET", "aof-write", "missing"])), Frame::Integer(1));
+        assert_eq!(latency.execute(&cmd(&["RESET"])), Frame::Integer(1));
+        assert_eq!(latency.execute(&cmd(&["LATEST"])), Frame::Array(vec![]));
+        assert!(latency.execute(&cmd(&["HISTORY"])).is_error());
+    }
+}
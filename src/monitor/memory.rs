@@ -0,0 +1,199 @@
+// src/monitor/memory.rs
+
+//! MEMORY 命令与内存统计
+//!
+//! 数据都在 sled 中，一个 key 占用的空间按它全部底层记录的字节数
+//! （记录的 key 加 value）计算；整个数据集的用量由一次全量扫描按类型汇总。
+
+use anyhow::Result;
+use bytes::Bytes;
+
+use crate::command::{name_upper, parse_arg};
+use crate::engine::KvEngine;
+use crate::error::CommandError;
+use crate::keys::{self, Kind};
+use crate::protocol::Frame;
+use crate::types;
+
+const HELP: &[&str] = &[
+    "MEMORY <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+    "STATS",
+    "    Return information about the memory usage of the server.",
+    "USAGE <key> [SAMPLES <count>]",
+    "    Return memory in bytes used by <key> and its value.",
+    "HELP",
+    "    Print this help.",
+];
+
+/// 数据集按类型划分的字节数
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MemoryStats {
+    pub keys: u64,
+    pub strings: u64,
+    pub hashes: u64,
+    pub lists: u64,
+    pub sets: u64,
+    /// 过期时间记录
+    pub expires: u64,
+    /// 不属于以上任何类型的记录
+    pub other: u64,
+}
+
+impl MemoryStats {
+    /// 数据集总字节数
+    pub fn dataset(&self) -> u64 {
+        self.strings + self.hashes + self.lists + self.sets + self.expires + self.other
+    }
+}
+
+/// 扫描整个数据集，按类型汇总字节数并统计 key 个数
+pub fn collect<E: KvEngine>(db: &E) -> Result<MemoryStats> {
+    let mut stats = MemoryStats::default();
+    // 同一 key 的 hash / set 记录是连续的，与上一条记录的 key 相同时不重复计数
+    let mut last_key: Option<(Kind, Vec<u8>)> = None;
+    for item in db.scan_prefix(b"") {
+        let (k, v) = item?;
+        let size = (k.len() + v.len()) as u64;
+        let Some(record) = keys::decode(&k) else {
+            stats.other += size;
+            continue;
+        };
+        match record.kind {
+            Kind::String => {
+                stats.keys += 1;
+                stats.strings += size;
+            }
+            Kind::Hash => stats.hashes += size,
+            Kind::Set => stats.sets += size,
+            Kind::ListData | Kind::ListMeta => {
+                if record.is_list_head() {
+                    stats.keys += 1;
+                }
+                stats.lists += size;
+            }
+            Kind::Expire => stats.expires += size,
+        }
+
+        if matches!(record.kind, Kind::Hash | Kind::Set)
+            && last_key.as_ref().is_none_or(|(kind, last)| *kind != record.kind || last != record.key)
+        {
+            stats.keys += 1;
+            last_key = Some((record.kind, record.key.to_vec()));
+        }
+    }
+    Ok(stats)
+}
+
+/// 一个 key 全部底层记录的字节数，key 不存在时返回 None
+pub fn key_usage<E: KvEngine>(db: &E, key: &[u8]) -> Result<Option<u64>> {
+    if types::key_stats(db, key)?.is_none() {
+        return Ok(None);
+    }
+    let mut total = 0u64;
+    for record in [keys::string(key), keys::expire(key)] {
+        if let Some(v) = db.get(&record)? {
+            total += (record.len() + v.len()) as u64;
+        }
+    }
+    for kind in [Kind::Hash, Kind::ListData, Kind::ListMeta, Kind::Set] {
+        for item in db.scan_prefix(&keys::prefix(kind, key)) {
+            let (k, v) = item?;
+            total += (k.len() + v.len()) as u64;
+        }
+    }
+    Ok(Some(total))
+}
+
+/// 进程常驻内存（RSS），仅 Linux 可用
+pub fn process_rss() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb * 1024)
+}
+
+/// 执行 MEMORY 子命令
+pub fn execute<E: KvEngine>(args: &[Bytes], db: &E) -> Frame {
+    let sub = name_upper(&args[0]);
+    match (sub.as_str(), args.len()) {
+        ("HELP", 1) => Frame::Array(HELP.iter().map(|l| Frame::Simple(l.to_string())).collect()),
+        ("USAGE", 2 | 4) => {
+            // 统计总是精确的，SAMPLES 只做校验
+            if args.len() == 4 {
+                if !args[2].eq_ignore_ascii_case(b"SAMPLES") {
+                    return Frame::error("ERR syntax error");
+                }
+                if parse_arg::<u64>(&args[3]).is_none() {
+                    return Frame::error("ERR value is not an integer or out of range");
+                }
+            }
+            match key_usage(db, &args[1]) {
+                Ok(Some(bytes)) => Frame::Integer(bytes as i64),
+                Ok(None) => Frame::Null,
+                Err(e) => CommandError::from(e).into(),
+            }
+        }
+        ("STATS", 1) => match collect(db) {
+            Ok(stats) => stats_frame(&stats),
+            Err(e) => CommandError::from(e).into(),
+        },
+        _ => Frame::error(format!(
+            "ERR unknown subcommand or wrong number of arguments for '{}'. Try MEMORY HELP.",
+            String::from_utf8_lossy(&args[0])
+        )),
+    }
+}
+
+fn stats_frame(stats: &MemoryStats) -> Frame {
+    let dataset = stats.dataset();
+    let per_key = dataset.checked_div(stats.keys).unwrap_or(0);
+    let mut fields = vec![
+        ("dataset.bytes", dataset),
+        ("keys.count", stats.keys),
+        ("keys.bytes-per-key", per_key),
+        ("dataset.strings.bytes", stats.strings),
+        ("dataset.hashes.bytes", stats.hashes),
+        ("dataset.lists.bytes", stats.lists),
+        ("dataset.sets.bytes", stats.sets),
+        ("dataset.expires.bytes", stats.expires),
+        ("dataset.other.bytes", stats.other),
+    ];
+    if let Some(rss) = process_rss() {
+        fields.push(("rss.bytes", rss));
+    }
+    Frame::Map(
+        fields
+            .into_iter()
+            .map(|(name, value)| (Frame::bulk(name), Frame::Integer(value as i64)))
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{hash, list, set, string};
+
+    #[test]
+    fn test_usage_and_stats() -> Result<()> {
+        let db = sled::Config::new().temporary(true).open()?;
+        string::set(&db, b"s", b"hello")?;
+        hash::hset(&db, b"h", b"f1", b"v1")?;
+        hash::hset(&db, b"h", b"f2", b"v2")?;
+        set::sadd(&db, b"m", b"x")?;
+        list::rpush(&db, b"l", b"abc")?;
+
+        // "string:" + 4 字节长度 + "s"，加上 "hello"
+        assert_eq!(key_usage(&db, b"s")?, Some(17));
+        // 两条 "hash:" + 4 字节长度 + "h" + "fN"，加上 "vN"
+        assert_eq!(key_usage(&db, b"h")?, Some(28));
+        assert_eq!(key_usage(&db, b"missing")?, None);
+
+        let stats = collect(&db)?;
+        assert_eq!(stats.keys, 4);
+        assert_eq!((stats.strings, stats.hashes), (17, 28));
+        assert_eq!(stats.lists, key_usage(&db, b"l")?.unwrap());
+        assert_eq!(stats.dataset(), stats.strings + stats.hashes + stats.sets + stats.lists);
+        Ok(())
+    }
+}
@@ -1,14 +1,107 @@
 // src/monitor/metrics.rs
 
 use super::*;
-use crate::engine::KvEngine;
+use crate::engine::{KeyspaceStats, KvEngine};
+use crate::persistence::{AofLoadStatus, PersistenceStatus};
+use super::latency::LatencyEvent;
+use super::memory::{self, MemoryStats};
+use crate::pubsub::PubSubStats;
 
 #[derive(Default)]
 pub struct Metrics {
     pub connected_clients: Arc<AtomicU64>,
     pub total_connections: Arc<AtomicU64>,
+    /// 因超过 maxclients 被拒绝的连接数
+    pub rejected_connections: Arc<AtomicU64>,
     pub command_count: Arc<AtomicU64>,
-    pub command_stats: Arc<DashMap<String, u64>>,
+    /// 按命令名（小写）统计的调用次数与耗时
+    pub command_stats: Arc<DashMap<String, CommandStat>>,
+    /// 所有连接累计读入 / 写出的字节数
+    pub net_input_bytes: Arc<AtomicU64>,
+    pub net_output_bytes: Arc<AtomicU64>,
+    traffic_samples: std::sync::Mutex<TrafficSamples>,
+}
+
+/// 瞬时速率取最近多少次采样的平均值（与 Redis 相同）
+const INSTANTANEOUS_SAMPLES: usize = 16;
+
+/// 定时采样的收发速率（字节 / 秒）
+#[derive(Default)]
+struct TrafficSamples {
+    last: Option<(Instant, u64, u64)>,
+    input: VecDeque<f64>,
+    output: VecDeque<f64>,
+}
+
+/// 耗时直方图：小于 16 微秒的值各占一格，此后每个 2 的幂区间再均分为 8 格，
+/// 相对误差不超过 12.5%
+const HISTOGRAM_LINEAR: u64 = 16;
+const HISTOGRAM_SUB_BUCKETS: u64 = 8;
+const HISTOGRAM_BUCKETS: usize = 16 + 60 * 8;
+
+/// 单个命令的调用统计，与 Redis 的 `cmdstat_*` / `latency_percentiles_usec_*` 对应
+#[derive(Clone)]
+pub struct CommandStat {
+    pub calls: u64,
+    /// 累计耗时（微秒）
+    pub usec: u64,
+    /// 执行后返回错误的次数
+    pub failed_calls: u64,
+    histogram: Box<[u64]>,
+}
+
+impl Default for CommandStat {
+    fn default() -> Self {
+        CommandStat { calls: 0, usec: 0, failed_calls: 0, histogram: vec![0; HISTOGRAM_BUCKETS].into_boxed_slice() }
+    }
+}
+
+impl CommandStat {
+    fn record(&mut self, usec: u64, failed: bool) {
+        self.calls += 1;
+        self.usec += usec;
+        if failed {
+            self.failed_calls += 1;
+        }
+        self.histogram[bucket_index(usec)] += 1;
+    }
+
+    pub fn usec_per_call(&self) -> f64 {
+        if self.calls == 0 { 0.0 } else { self.usec as f64 / self.calls as f64 }
+    }
+
+    /// 第 `p` 百分位的耗时（微秒），取所在格的上界
+    pub fn percentile(&self, p: f64) -> u64 {
+        let rank = ((self.calls as f64 * p / 100.0).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (index, count) in self.histogram.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return bucket_upper(index);
+            }
+        }
+        0
+    }
+}
+
+fn bucket_index(usec: u64) -> usize {
+    if usec < HISTOGRAM_LINEAR {
+        return usec as usize;
+    }
+    let exp = 63 - usec.leading_zeros() as u64;
+    let sub = (usec >> (exp - 3)) & (HISTOGRAM_SUB_BUCKETS - 1);
+    (HISTOGRAM_LINEAR + (exp - 4) * HISTOGRAM_SUB_BUCKETS + sub) as usize
+}
+
+fn bucket_upper(index: usize) -> u64 {
+    let index = index as u64;
+    if index < HISTOGRAM_LINEAR {
+        return index;
+    }
+    let exp = (index - HISTOGRAM_LINEAR) / HISTOGRAM_SUB_BUCKETS + 4;
+    let sub = (index - HISTOGRAM_LINEAR) % HISTOGRAM_SUB_BUCKETS;
+    // 最后一格的上界左移后溢出为 0，减一恰好得到 u64::MAX
+    ((HISTOGRAM_SUB_BUCKETS + sub + 1) << (exp - 3)).wrapping_sub(1)
 }
 
 impl Metrics {
@@ -16,22 +109,54 @@ impl Metrics {
         Metrics::default()
     }
 
-    pub fn record_command(&self, command: &str) {
+    /// 记录一次命令执行：耗时与是否返回错误
+    pub fn record_command(&self, command: &str, duration: Duration, failed: bool) {
         self.command_count.fetch_add(1, Ordering::Relaxed);
-        self.command_stats.entry(command.to_string()).and_modify(|c| *c += 1).or_insert(1);
+        self.command_stats
+            .entry(command.to_lowercase())
+            .or_default()
+            .record(duration.as_micros() as u64, failed);
+    }
+
+    /// 采样一次累计收发字节数，由服务端每 100ms 调用
+    pub fn track_instantaneous(&self) {
+        self.track_instantaneous_at(Instant::now());
+    }
+
+    fn track_instantaneous_at(&self, now: Instant) {
+        let input = self.net_input_bytes.load(Ordering::Relaxed);
+        let output = self.net_output_bytes.load(Ordering::Relaxed);
+        let mut guard = self.traffic_samples.lock().unwrap();
+        let samples = &mut *guard;
+        if let Some((at, last_input, last_output)) = samples.last {
+            let secs = now.duration_since(at).as_secs_f64();
+            if secs > 0.0 {
+                for (rates, bytes) in [(&mut samples.input, input - last_input), (&mut samples.output, output - last_output)] {
+                    if rates.len() == INSTANTANEOUS_SAMPLES {
+                        rates.pop_front();
+                    }
+                    rates.push_back(bytes as f64 / secs);
+                }
+            }
+        }
+        samples.last = Some((now, input, output));
     }
 
-    pub fn memory_usage(&self) -> u64 {
-        // 简化实现 - 实际中应计算实际内存使用量
-        1024 * 1024 // 1MB
+    /// 最近的读入 / 写出速率（KB/s）
+    pub fn instantaneous_kbps(&self) -> (f64, f64) {
+        let samples = self.traffic_samples.lock().unwrap();
+        let average = |rates: &VecDeque<f64>| {
+            if rates.is_empty() { 0.0 } else { rates.iter().sum::<f64>() / rates.len() as f64 / 1024.0 }
+        };
+        (average(&samples.input), average(&samples.output))
     }
 
     pub fn key_count(&self, db: &impl KvEngine) -> u64 {
-        // 统计键数量
-        if let Some(sled_db) = db.as_db() {
-            sled_db.open_tree("").unwrap().len() as u64
+        // 统计键数量（底层记录数），非 sled 引擎逐条扫描
+        if let Some(trees) = db.sled_trees() {
+            trees.len() as u64
         } else {
-            0
+            db.scan_prefix(b"").count() as u64
         }
     }
 
@@ -52,6 +177,36 @@ impl Metrics {
             self.total_connections.load(Ordering::Relaxed)
         ));
         
+        output.push_str("# HELP Crab-Cage_rejected_connections Connections rejected because of maxclients\n");
+        output.push_str("# TYPE Crab-Cage_rejected_connections counter\n");
+        output.push_str(&format!(
+            "Crab-Cage_rejected_connections {}\n",
+            self.rejected_connections.load(Ordering::Relaxed)
+        ));
+        
+        output.push_str("# HELP Crab-Cage_net_input_bytes_total Bytes read from clients\n");
+        output.push_str("# TYPE Crab-Cage_net_input_bytes_total counter\n");
+        output.push_str(&format!(
+            "Crab-Cage_net_input_bytes_total {}\n",
+            self.net_input_bytes.load(Ordering::Relaxed)
+        ));
+
+        output.push_str("# HELP Crab-Cage_net_output_bytes_total Bytes written to clients\n");
+        output.push_str("# TYPE Crab-Cage_net_output_bytes_total counter\n");
+        output.push_str(&format!(
+            "Crab-Cage_net_output_bytes_total {}\n",
+            self.net_output_bytes.load(Ordering::Relaxed)
+        ));
+
+        let (input_kbps, output_kbps) = self.instantaneous_kbps();
+        output.push_str("# HELP Crab-Cage_instantaneous_input_kbps Recent input rate in KB/s\n");
+        output.push_str("# TYPE Crab-Cage_instantaneous_input_kbps gauge\n");
+        output.push_str(&format!("Crab-Cage_instantaneous_input_kbps {:.2}\n", input_kbps));
+
+        output.push_str("# HELP Crab-Cage_instantaneous_output_kbps Recent output rate in KB/s\n");
+        output.push_str("# TYPE Crab-Cage_instantaneous_output_kbps gauge\n");
+        output.push_str(&format!("Crab-Cage_instantaneous_output_kbps {:.2}\n", output_kbps));
+
         output.push_str("# HELP Crab-Cage_command_count Total commands processed\n");
         output.push_str("# TYPE Crab-Cage_command_count counter\n");
         output.push_str(&format!(
@@ -65,10 +220,262 @@ impl Metrics {
             output.push_str(&format!(
                 "Crab-Cage_command_stats{{command=\"{}\"}} {}\n",
                 entry.key(),
-                entry.value()
+                entry.value().calls
+            ));
+        }
+
+        output.push_str("# HELP Crab-Cage_command_usec_total Microseconds spent executing each command\n");
+        output.push_str("# TYPE Crab-Cage_command_usec_total counter\n");
+        for entry in self.command_stats.iter() {
+            output.push_str(&format!(
+                "Crab-Cage_command_usec_total{{command=\"{}\"}} {}\n",
+                entry.key(),
+                entry.value().usec
+            ));
+        }
+
+        output.push_str("# HELP Crab-Cage_command_usec_p99 99th percentile latency of each command in microseconds\n");
+        output.push_str("# TYPE Crab-Cage_command_usec_p99 gauge\n");
+        for entry in self.command_stats.iter() {
+            output.push_str(&format!(
+                "Crab-Cage_command_usec_p99{{command=\"{}\"}} {}\n",
+                entry.key(),
+                entry.value().percentile(99.0)
             ));
         }
         
         output     
     }
-}
\ No newline at end of file
+
+    /// 数据集按类型划分的字节数与进程 RSS
+    pub fn memory_to_prometheus(stats: &MemoryStats) -> String {
+        let mut output = String::new();
+
+        output.push_str("# HELP Crab-Cage_used_memory_bytes Bytes used by the dataset\n");
+        output.push_str("# TYPE Crab-Cage_used_memory_bytes gauge\n");
+        output.push_str(&format!("Crab-Cage_used_memory_bytes {}\n", stats.dataset()));
+
+        output.push_str("# HELP Crab-Cage_dataset_bytes Bytes used by the dataset, by data type\n");
+        output.push_str("# TYPE Crab-Cage_dataset_bytes gauge\n");
+        for (kind, bytes) in [
+            ("string", stats.strings),
+            ("hash", stats.hashes),
+            ("list", stats.lists),
+            ("set", stats.sets),
+            ("expire", stats.expires),
+            ("other", stats.other),
+        ] {
+            output.push_str(&format!("Crab-Cage_dataset_bytes{{type=\"{}\"}} {}\n", kind, bytes));
+        }
+
+        if let Some(rss) = memory::process_rss() {
+            output.push_str("# HELP Crab-Cage_used_memory_rss_bytes Resident set size of the process\n");
+            output.push_str("# TYPE Crab-Cage_used_memory_rss_bytes gauge\n");
+            output.push_str(&format!("Crab-Cage_used_memory_rss_bytes {}\n", rss));
+        }
+
+        output
+    }
+
+    /// 每类事件最近一次与历史最大的延迟尖峰
+    pub fn latency_to_prometheus(events: &[LatencyEvent]) -> String {
+        let mut output = String::new();
+
+        output.push_str("# HELP Crab-Cage_latency_latest_milliseconds Latest latency spike, by event\n");
+        output.push_str("# TYPE Crab-Cage_latency_latest_milliseconds gauge\n");
+        for event in events {
+            output.push_str(&format!(
+                "Crab-Cage_latency_latest_milliseconds{{event=\"{}\"}} {}\n",
+                event.name, event.latest.latency_ms
+            ));
+        }
+
+        output.push_str("# HELP Crab-Cage_latency_max_milliseconds Largest latency spike, by event\n");
+        output.push_str("# TYPE Crab-Cage_latency_max_milliseconds gauge\n");
+        for event in events {
+            output.push_str(&format!("Crab-Cage_latency_max_milliseconds{{event=\"{}\"}} {}\n", event.name, event.max_ms));
+        }
+
+        output
+    }
+
+    /// 键空间命中、未命中、过期与淘汰的 key 数
+    pub fn keyspace_to_prometheus(stats: &KeyspaceStats) -> String {
+        let mut output = String::new();
+
+        for (name, help, counter) in [
+            ("keyspace_hits", "Lookups of existing keys by read-only commands", &stats.hits),
+            ("keyspace_misses", "Lookups of missing keys by read-only commands", &stats.misses),
+            ("expired_keys", "Keys deleted because they expired", &stats.expired),
+            ("evicted_keys", "Keys evicted because of the memory limit", &stats.evicted),
+        ] {
+            output.push_str(&format!("# HELP Crab-Cage_{}_total {}\n", name, help));
+            output.push_str(&format!("# TYPE Crab-Cage_{}_total counter\n", name));
+            output.push_str(&format!("Crab-Cage_{}_total {}\n", name, counter.load(Ordering::Relaxed)));
+        }
+
+        output
+    }
+
+    /// 发布 / 订阅的投递统计，按频道与模式细分
+    pub fn pubsub_to_prometheus(stats: &PubSubStats) -> String {
+        let mut output = String::new();
+
+        for (name, help, value) in [
+            ("pubsub_messages_delivered", "Messages delivered to subscribers", stats.delivered),
+            ("pubsub_messages_dropped", "Messages dropped because a subscriber's backlog was full", stats.dropped),
+            ("pubsub_clients_disconnected", "Subscribers disconnected because their backlog was full", stats.disconnected),
+            ("pubsub_slow_subscriber_events", "Times a subscriber's backlog reached the slow subscriber threshold", stats.slow_events),
+        ] {
+            output.push_str(&format!("# HELP Crab-Cage_{}_total {}\n", name, help));
+            output.push_str(&format!("# TYPE Crab-Cage_{}_total counter\n", name));
+            output.push_str(&format!("Crab-Cage_{}_total {}\n", name, value));
+        }
+
+        output.push_str("# HELP Crab-Cage_pubsub_slow_subscribers Subscribers whose backlog is at or above the slow subscriber threshold\n");
+        output.push_str("# TYPE Crab-Cage_pubsub_slow_subscribers gauge\n");
+        output.push_str(&format!("Crab-Cage_pubsub_slow_subscribers {}\n", stats.slow_subscribers));
+
+        for (name, help) in [
+            ("delivered", "Messages delivered, by channel or pattern"),
+            ("dropped", "Messages dropped, by channel or pattern"),
+        ] {
+            output.push_str(&format!("# HELP Crab-Cage_pubsub_channel_{}_total {}\n", name, help));
+            output.push_str(&format!("# TYPE Crab-Cage_pubsub_channel_{}_total counter\n", name));
+            for channel in &stats.per_channel {
+                let value = if name == "delivered" { channel.delivered } else { channel.dropped };
+                output.push_str(&format!(
+                    "Crab-Cage_pubsub_channel_{}_total{{kind=\"{}\",channel=\"{}\"}} {}\n",
+                    name,
+                    channel.kind.name(),
+                    escape_label(&channel.name),
+                    value
+                ));
+            }
+        }
+
+        output
+    }
+
+    /// 启动时重放 AOF 的统计
+    pub fn aof_load_to_prometheus(status: &AofLoadStatus) -> String {
+        let mut output = String::new();
+
+        output.push_str("# HELP Crab-Cage_aof_load_commands Commands replayed from the AOF at startup\n");
+        output.push_str("# TYPE Crab-Cage_aof_load_commands gauge\n");
+        output.push_str(&format!("Crab-Cage_aof_load_commands {}\n", status.commands));
+
+        output.push_str("# HELP Crab-Cage_aof_load_bytes Bytes of AOF read at startup\n");
+        output.push_str("# TYPE Crab-Cage_aof_load_bytes gauge\n");
+        output.push_str(&format!("Crab-Cage_aof_load_bytes {}\n", status.bytes));
+
+        output.push_str("# HELP Crab-Cage_aof_load_duration_seconds Time spent replaying the AOF at startup\n");
+        output.push_str("# TYPE Crab-Cage_aof_load_duration_seconds gauge\n");
+        output.push_str(&format!(
+            "Crab-Cage_aof_load_duration_seconds {:.3}\n",
+            status.duration_ms as f64 / 1000.0
+        ));
+
+        output
+    }
+
+    /// AOF 与 RDB 的运行统计：文件大小、fsync 与快照耗时、失败次数与进行中的重写
+    pub fn persistence_to_prometheus(status: &PersistenceStatus) -> String {
+        let mut output = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, value: String| {
+            output.push_str(&format!("# HELP Crab-Cage_{} {}\n", name, help));
+            output.push_str(&format!("# TYPE Crab-Cage_{} {}\n", name, kind));
+            output.push_str(&format!("Crab-Cage_{} {}\n", name, value));
+        };
+
+        metric("aof_size_bytes", "gauge", "Current size of the AOF", status.aof_size.to_string());
+        metric("aof_last_write_ok", "gauge", "Whether the last AOF write succeeded", (status.aof_last_write_ok as u8).to_string());
+        metric("aof_write_failures_total", "counter", "Failed AOF writes", status.aof_write_failures.to_string());
+        metric(
+            "aof_last_fsync_seconds",
+            "gauge",
+            "Duration of the last AOF fsync",
+            format!("{:.6}", status.aof_last_fsync_us as f64 / 1e6),
+        );
+        metric("aof_rewrite_in_progress", "gauge", "Whether an AOF rewrite is running", (status.aof_rewrite.in_progress as u8).to_string());
+        metric("aof_rewrites_total", "counter", "Completed AOF rewrites", status.aof_rewrite.rewrites.to_string());
+        metric("rdb_save_in_progress", "gauge", "Whether an RDB snapshot is running", (status.rdb_save_in_progress as u8).to_string());
+        metric("rdb_last_save_timestamp_seconds", "gauge", "Unix time of the last successful snapshot", status.rdb_last_save.to_string());
+        metric(
+            "rdb_last_save_duration_seconds",
+            "gauge",
+            "Duration of the last successful snapshot",
+            format!("{:.3}", status.rdb_last_save_duration_ms as f64 / 1000.0),
+        );
+        metric("rdb_last_save_size_bytes", "gauge", "Size of the last RDB snapshot", status.rdb_last_save_size.to_string());
+        metric("rdb_save_failures_total", "counter", "Failed RDB snapshots", status.rdb_save_failures.to_string());
+
+        output
+    }
+}
+
+/// Prometheus 标签值中的反斜杠、双引号与换行需要转义
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram_buckets() {
+        for usec in [0, 15, 16, 17, 100, 1_000, 65_535, 1 << 40, u64::MAX] {
+            let index = bucket_index(usec);
+            assert!(index < HISTOGRAM_BUCKETS);
+            assert!(usec <= bucket_upper(index), "{} > upper bound of its bucket", usec);
+            // 上界不超过真实值的 12.5%
+            assert!(bucket_upper(index) - usec <= usec / 8, "bucket of {} too wide", usec);
+        }
+    }
+
+    #[test]
+    fn test_command_stats() {
+        let metrics = Metrics::new();
+        for usec in 1..=100 {
+            metrics.record_command("GET", Duration::from_micros(usec), false);
+        }
+        metrics.record_command("get", Duration::from_millis(10), true);
+
+        let stat = metrics.command_stats.get("get").unwrap().clone();
+        assert_eq!((stat.calls, stat.failed_calls), (101, 1));
+        assert_eq!(stat.usec, 5050 + 10_000);
+        assert!((stat.usec_per_call() - 15050.0 / 101.0).abs() < 1e-9);
+        assert_eq!(stat.percentile(50.0), 51);
+        assert_eq!(stat.percentile(99.0), 103);
+        assert_eq!(stat.percentile(100.0), bucket_upper(bucket_index(10_000)));
+        assert_eq!(metrics.command_count.load(Ordering::Relaxed), 101);
+    }
+
+    #[test]
+    fn test_instantaneous_traffic() {
+        let metrics = Metrics::new();
+        let start = Instant::now();
+        metrics.track_instantaneous_at(start);
+        assert_eq!(metrics.instantaneous_kbps(), (0.0, 0.0));
+
+        // 100ms 内读入 1KB、写出 10KB
+        metrics.net_input_bytes.fetch_add(1024, Ordering::Relaxed);
+        metrics.net_output_bytes.fetch_add(10 * 1024, Ordering::Relaxed);
+        metrics.track_instantaneous_at(start + Duration::from_millis(100));
+        let (input, output) = metrics.instantaneous_kbps();
+        assert!((input - 10.0).abs() < 1e-6 && (output - 100.0).abs() < 1e-6);
+
+        // 之后没有流量，速率随新的采样逐渐回落
+        for i in 2..=INSTANTANEOUS_SAMPLES as u64 + 1 {
+            metrics.track_instantaneous_at(start + Duration::from_millis(100 * i));
+        }
+        assert_eq!(metrics.instantaneous_kbps(), (0.0, 0.0));
+    }
+
+    #[test]
+    fn test_escape_label() {
+        assert_eq!(escape_label(r#"a"b\c"#), r#"a\"b\\c"#);
+        assert_eq!(escape_label("line\nbreak"), "line\\nbreak");
+    }
+}
@@ -1,17 +1,27 @@
 // src/monitor/mod.rs
 //! 监控与诊断模块
+mod audit;
 mod client;
+pub mod debug;
+mod hotkeys;
 pub mod info;
+pub mod latency;
+pub mod memory;
 mod slowlog;
 mod metrics;
 
 use std::sync::{Arc, atomic::{AtomicU64, Ordering}};
+use crate::config::Config;
 use std::time::{Instant, Duration};
 use std::collections::VecDeque;
 use std::net::SocketAddr;
 use dashmap::DashMap;
+use tokio::sync::Notify;
 
+pub use audit::AuditLog;
 pub use client::ClientTracker;
+pub use hotkeys::HotKeys;
+pub use latency::LatencyMonitor;
 pub use slowlog::SlowLog;
 pub use metrics::Metrics;
 
@@ -21,6 +31,18 @@ pub struct Monitor {
     pub client_tracker: Arc<ClientTracker>,
     pub slow_log: Arc<SlowLog>,
     pub metrics: Arc<Metrics>,
+    pub hot_keys: Arc<HotKeys>,
+    pub audit: Arc<AuditLog>,
+    /// 每次启动随机生成的 40 位十六进制 ID，监控系统据此识别重启
+    pub run_id: String,
+    /// 启动时间，INFO server 据此计算 uptime
+    pub start_time: Instant,
+}
+
+impl Default for Monitor {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Monitor {
@@ -29,24 +51,104 @@ impl Monitor {
             client_tracker: Arc::new(ClientTracker::new()),
             slow_log: Arc::new(SlowLog::new(128)),
             metrics: Arc::new(Metrics::new()),
+            hot_keys: Arc::new(HotKeys::new(0)),
+            audit: Arc::new(AuditLog::default()),
+            run_id: crate::replication::new_replid(),
+            start_time: Instant::now(),
         }
     }
+
+    /// 按配置初始化慢日志与热点 key 抽样
+    pub fn from_config(cfg: &Config) -> Self {
+        let monitor = Monitor::new();
+        monitor.slow_log.set_threshold_us(cfg.slowlog_threshold_ms.saturating_mul(1000) as i64);
+        monitor.slow_log.set_max_len(cfg.slowlog_max_len);
+        monitor.hot_keys.set_sample_rate(cfg.hotkeys_sample_rate);
+        monitor
+    }
 }
 
 /// 客户端信息
 #[derive(Debug, Clone)]
 pub struct ClientInfo {
     pub addr: SocketAddr,
+    /// CLIENT SETNAME 设置的连接名
+    pub name: Option<String>,
     pub connect_time: Instant,
     pub last_command: String,
     pub last_command_time: Instant,
+    /// 最近一次处理完客户端发来的数据的时间，空闲回收以此为准
+    pub last_active: Instant,
+    /// 正在执行 WAIT 等会阻塞的命令
+    pub blocked: bool,
+    /// CLIENT KILL 通过它通知连接任务退出
+    pub kill_signal: Arc<Notify>,
+    /// 连接使用的协议版本（2 或 3）
+    pub protocol: u8,
+    /// MULTI 之后已排队的命令数，不在事务中时为 None
+    pub multi: Option<usize>,
+    /// 订阅的频道数（含分片频道）
+    pub sub: usize,
+    /// 订阅的模式数
+    pub psub: usize,
+    /// 连接已转为向副本发送命令流（PSYNC / SYNC）
+    pub replica: bool,
+    /// 累计收发的字节数，由连接任务直接累加
+    pub traffic: Arc<NetTraffic>,
 }
 
-/// 慢日志条目
-#[derive(Debug, Clone)]
+impl ClientInfo {
+    /// CLIENT LIST 的 TYPE：normal、pubsub 或 replica
+    pub fn client_type(&self) -> &'static str {
+        if self.replica {
+            "replica"
+        } else if self.sub + self.psub > 0 {
+            "pubsub"
+        } else {
+            "normal"
+        }
+    }
+
+    /// 与 Redis 相同的标志位：S 副本、P 订阅中、b 阻塞中、x 处于 MULTI，都没有时为 N
+    pub fn flags(&self) -> String {
+        let mut flags = String::new();
+        if self.replica {
+            flags.push('S');
+        }
+        if self.sub + self.psub > 0 {
+            flags.push('P');
+        }
+        if self.blocked {
+            flags.push('b');
+        }
+        if self.multi.is_some() {
+            flags.push('x');
+        }
+        if flags.is_empty() {
+            flags.push('N');
+        }
+        flags
+    }
+}
+
+/// 一个连接累计收发的字节数
+#[derive(Debug, Default)]
+pub struct NetTraffic {
+    pub input: AtomicU64,
+    pub output: AtomicU64,
+}
+
+/// 慢日志条目，持久化到文件时每条一行 JSON
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct SlowLogEntry {
-    pub timestamp: Instant,
+    pub id: u64,
+    /// unix 秒，重启后仍然有意义
+    pub timestamp: u64,
+    #[serde(rename = "duration_us", with = "slowlog::micros")]
     pub duration: Duration,
-    pub command: String,
+    /// 命令参数，过多或过长时已折叠
+    pub args: Vec<String>,
     pub client_addr: String,
+    /// CLIENT SETNAME 设置的连接名，未设置时为空
+    pub client_name: String,
 }
\ No newline at end of file
@@ -1,57 +1,387 @@
 // src/monitor/slowlog.rs
 
+//! 慢日志：记录执行时间超过阈值的命令
+//!
+//! 阈值以微秒计（与 Redis 的 `slowlog-log-slower-than` 相同），负数表示关闭，
+//! 0 表示记录全部命令；两者都可以通过 CONFIG SET 在运行时调整。
+//!
+//! 配置 `slowlog_file` 后，每条记录追加一行 JSON 到该文件，行数达到上限的两倍时按内存中的记录重写，
+//! 文件始终只比内存中多保留有限的旧记录；启动时从文件重新加载最新的记录，id 接着递增。
+
 use super::*;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
 use std::sync::Mutex;
+use std::sync::atomic::{AtomicI64, AtomicUsize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use bytes::Bytes;
+use tracing::warn;
+
+use crate::protocol::Frame;
+
+/// 每条记录最多保存的参数个数与单个参数的最大长度，超出部分折叠，与 Redis 相同
+const MAX_ARGC: usize = 32;
+const MAX_ARG_LEN: usize = 128;
+
+/// SLOWLOG GET 未指定个数时返回的条数
+const DEFAULT_GET_COUNT: usize = 10;
+
+const HELP: &[&str] = &[
+    "SLOWLOG <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+    "GET [<count>]",
+    "    Return top <count> entries from the slowlog (default: 10, -1 mean all).",
+    "    Entries are made of:",
+    "    id, timestamp, time in microseconds, arguments array, client IP and port,",
+    "    client name",
+    "LEN",
+    "    Return the length of the slowlog.",
+    "RESET",
+    "    Reset the slowlog.",
+    "HELP",
+    "    Print this help.",
+];
 
 pub struct SlowLog {
-    logs: Arc<Mutex<VecDeque<SlowLogEntry>>>,
-    max_entries: usize,
-    slow_threshold: Duration,
+    logs: Mutex<VecDeque<SlowLogEntry>>,
+    next_id: AtomicU64,
+    max_entries: AtomicUsize,
+    /// 阈值（微秒），负数表示关闭
+    threshold_us: AtomicI64,
+    /// 持久化文件，未配置 `slowlog_file` 时为 None
+    file: Mutex<Option<SlowLogFile>>,
+}
+
+/// 慢日志的持久化文件：只追加，行数过多时重写
+struct SlowLogFile {
+    path: PathBuf,
+    file: File,
+    /// 文件中的记录条数
+    lines: usize,
+}
+
+impl SlowLogFile {
+    fn append(&mut self, entry: &SlowLogEntry) -> Result<()> {
+        let line = serde_json::to_string(entry)?;
+        self.file.write_all(format!("{}\n", line).as_bytes())?;
+        self.lines += 1;
+        Ok(())
+    }
+
+    /// 按最新在前的 `logs` 重写文件（文件中最旧的在前），先写临时文件再改名
+    fn rewrite(&mut self, logs: &VecDeque<SlowLogEntry>) -> Result<()> {
+        let tmp = self.path.with_extension("tmp");
+        let mut out = String::new();
+        for entry in logs.iter().rev() {
+            out.push_str(&serde_json::to_string(entry)?);
+            out.push('\n');
+        }
+        fs::write(&tmp, out)?;
+        fs::rename(&tmp, &self.path)?;
+        self.file = OpenOptions::new().append(true).open(&self.path)?;
+        self.lines = logs.len();
+        Ok(())
+    }
 }
 
 impl SlowLog {
     pub fn new(max_entries: usize) -> Self {
         SlowLog {
-            logs: Arc::new(Mutex::new(VecDeque::with_capacity(max_entries))),
-            max_entries,
-            slow_threshold: Duration::from_millis(10),
+            logs: Mutex::new(VecDeque::with_capacity(max_entries)),
+            next_id: AtomicU64::new(0),
+            max_entries: AtomicUsize::new(max_entries),
+            threshold_us: AtomicI64::new(10_000),
+            file: Mutex::new(None),
+        }
+    }
+
+    /// 把慢日志持久化到 `path`：加载其中最新的记录，之后的记录追加到该文件
+    ///
+    /// 无法解析的行（例如崩溃时写了一半的最后一行）被跳过。
+    pub fn persist_to(&self, path: &Path) -> Result<()> {
+        let mut logs = self.logs.lock().unwrap();
+        if path.exists() {
+            let file = File::open(path).with_context(|| format!("failed to open slow log {}", path.display()))?;
+            for line in BufReader::new(file).lines() {
+                match serde_json::from_str::<SlowLogEntry>(&line?) {
+                    Ok(entry) => logs.push_front(entry),
+                    Err(e) => warn!(path = %path.display(), "Skipping unreadable slow log entry: {}", e),
+                }
+            }
+            logs.truncate(self.max_len());
+        }
+        if let Some(newest) = logs.front() {
+            self.next_id.fetch_max(newest.id + 1, Ordering::Relaxed);
+        }
+
+        let mut file = SlowLogFile {
+            path: path.to_path_buf(),
+            file: OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("failed to open slow log {}", path.display()))?,
+            lines: 0,
+        };
+        // 启动时重写一次，丢掉超出上限的旧记录
+        file.rewrite(&logs).with_context(|| format!("failed to rewrite slow log {}", path.display()))?;
+        *self.file.lock().unwrap() = Some(file);
+        Ok(())
+    }
+
+    pub fn threshold_us(&self) -> i64 {
+        self.threshold_us.load(Ordering::Relaxed)
+    }
+
+    pub fn set_threshold_us(&self, threshold_us: i64) {
+        self.threshold_us.store(threshold_us, Ordering::Relaxed);
+    }
+
+    pub fn max_len(&self) -> usize {
+        self.max_entries.load(Ordering::Relaxed)
+    }
+
+    /// 调整最多保留的条数，多出的旧记录立即丢弃
+    pub fn set_max_len(&self, max_entries: usize) {
+        self.max_entries.store(max_entries, Ordering::Relaxed);
+        self.logs.lock().unwrap().truncate(max_entries);
+    }
+
+    pub fn add_entry(&self, args: &[Bytes], duration: Duration, client_addr: &str, client_name: Option<String>) {
+        let threshold = self.threshold_us();
+        if threshold < 0 || duration.as_micros() < threshold as u128 {
+            return;
         }
+        self.push(args, duration, client_addr, client_name);
     }
 
-    pub fn set_threshold(&mut self, threshold_ms: u64) {
-        self.slow_threshold = Duration::from_millis(threshold_ms);
+    /// 超出执行时间预算被中止的命令，不论阈值都记入慢日志
+    pub fn add_timeout_entry(&self, args: &[Bytes], duration: Duration, client_addr: &str, client_name: Option<String>) {
+        self.push(args, duration, client_addr, client_name);
     }
 
-    pub fn add_entry(&self, command: &str, duration: Duration, client_addr: &str) {
-        if duration >= self.slow_threshold {
-            let mut logs = self.logs.lock().unwrap();
-            if logs.len() >= self.max_entries {
-                logs.pop_back();
+    fn push(&self, args: &[Bytes], duration: Duration, client_addr: &str, client_name: Option<String>) {
+        let max_entries = self.max_len();
+        if max_entries == 0 {
+            return;
+        }
+
+        let mut logs = self.logs.lock().unwrap();
+        logs.truncate(max_entries - 1);
+        logs.push_front(SlowLogEntry {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs(),
+            duration,
+            args: truncate_args(args),
+            client_addr: client_addr.to_string(),
+            client_name: client_name.unwrap_or_default(),
+        });
+        if let Some(file) = self.file.lock().unwrap().as_mut() {
+            let res = if file.lines + 1 >= max_entries * 2 { file.rewrite(&logs) } else { file.append(&logs[0]) };
+            if let Err(e) = res {
+                warn!("Failed to write slow log file: {:#}", e);
             }
-            logs.push_front(SlowLogEntry {
-                timestamp: Instant::now(),
-                duration,
-                command: command.to_string(),
-                client_addr: client_addr.to_string(),
-            });
         }
     }
 
-    pub fn get_logs(&self) -> String {
+    pub fn len(&self) -> usize {
+        self.logs.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn reset(&self) {
+        let mut logs = self.logs.lock().unwrap();
+        logs.clear();
+        if let Some(file) = self.file.lock().unwrap().as_mut()
+            && let Err(e) = file.rewrite(&logs)
+        {
+            warn!("Failed to write slow log file: {:#}", e);
+        }
+    }
+
+    /// 最新的 `count` 条记录，`None` 表示全部
+    pub fn get(&self, count: Option<usize>) -> Vec<SlowLogEntry> {
         let logs = self.logs.lock().unwrap();
-        let mut response = String::new();
-        
-        for (i, entry) in logs.iter().enumerate() {
-            response.push_str(&format!(
-                "{}. timestamp: {:?}, duration: {:?}ms, command: {}, client: {}\n",
-                i + 1,
-                entry.timestamp,
-                entry.duration.as_millis(),
-                entry.command,
-                entry.client_addr
-            ));
-        }
-        
-        response
-    }
-}
\ No newline at end of file
+        logs.iter().take(count.unwrap_or(usize::MAX)).cloned().collect()
+    }
+
+    /// 执行 SLOWLOG 子命令
+    pub fn execute(&self, args: &[String]) -> Frame {
+        match (args[0].to_uppercase().as_str(), args.len()) {
+            ("HELP", 1) => Frame::Array(HELP.iter().map(|l| Frame::Simple(l.to_string())).collect()),
+            ("LEN", 1) => Frame::Integer(self.len() as i64),
+            ("RESET", 1) => {
+                self.reset();
+                Frame::ok()
+            }
+            ("GET", 1 | 2) => {
+                let count = match args.get(1).map(|n| n.parse::<i64>()) {
+                    None => Some(DEFAULT_GET_COUNT),
+                    Some(Ok(-1)) => None,
+                    Some(Ok(n)) if n >= 0 => Some(n as usize),
+                    Some(_) => return Frame::error("ERR count should be greater than or equal to -1"),
+                };
+                Frame::Array(self.get(count).iter().map(SlowLogEntry::to_frame).collect())
+            }
+            _ => Frame::error(format!(
+                "ERR unknown subcommand or wrong number of arguments for '{}'. Try SLOWLOG HELP.",
+                args[0]
+            )),
+        }
+    }
+}
+
+impl SlowLogEntry {
+    /// SLOWLOG GET 中的一项：id、unix 时间、耗时（微秒）、参数、客户端地址、客户端名
+    fn to_frame(&self) -> Frame {
+        Frame::Array(vec![
+            Frame::Integer(self.id as i64),
+            Frame::Integer(self.timestamp as i64),
+            Frame::Integer(self.duration.as_micros() as i64),
+            Frame::Array(self.args.iter().map(|a| Frame::bulk(a.as_str())).collect()),
+            Frame::bulk(self.client_addr.as_str()),
+            Frame::bulk(self.client_name.as_str()),
+        ])
+    }
+}
+
+/// 持久化文件中的耗时以微秒整数保存
+pub(super) mod micros {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    pub fn serialize<S: Serializer>(duration: &Duration, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_u64(duration.as_micros() as u64)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Duration, D::Error> {
+        u64::deserialize(d).map(Duration::from_micros)
+    }
+}
+
+/// 折叠过多的参数与过长的参数
+fn truncate_args(args: &[Bytes]) -> Vec<String> {
+    let mut out: Vec<String> = args
+        .iter()
+        .take(if args.len() > MAX_ARGC { MAX_ARGC - 1 } else { MAX_ARGC })
+        .map(|arg| {
+            let arg = String::from_utf8_lossy(arg);
+            if arg.len() > MAX_ARG_LEN {
+                let mut end = MAX_ARG_LEN;
+                while !arg.is_char_boundary(end) {
+                    end -= 1;
+                }
+                format!("{}... ({} more bytes)", &arg[..end], arg.len() - end)
+            } else {
+                arg.into_owned()
+            }
+        })
+        .collect();
+    if args.len() > MAX_ARGC {
+        out.push(format!("... ({} more arguments)", args.len() - MAX_ARGC + 1));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cmd(parts: &[&str]) -> Vec<Bytes> {
+        parts.iter().map(|s| Bytes::copy_from_slice(s.as_bytes())).collect()
+    }
+
+    fn args(parts: &[&str]) -> Vec<String> {
+        parts.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_threshold_and_max_len() {
+        let log = SlowLog::new(2);
+        log.set_threshold_us(1000);
+        log.add_entry(&cmd(&["GET", "fast"]), Duration::from_micros(999), "127.0.0.1:1", None);
+        assert!(log.is_empty());
+
+        for key in ["a", "b", "c"] {
+            log.add_entry(&cmd(&["GET", key]), Duration::from_millis(5), "127.0.0.1:1", Some("app".into()));
+        }
+        // 只保留最新的两条，id 持续递增
+        let entries = log.get(None);
+        assert_eq!(entries.iter().map(|e| e.id).collect::<Vec<_>>(), vec![2, 1]);
+        assert_eq!(entries[0].args, args(&["GET", "c"]));
+
+        log.set_max_len(1);
+        assert_eq!(log.len(), 1);
+        log.set_threshold_us(-1);
+        log.add_entry(&cmd(&["GET", "d"]), Duration::from_secs(1), "127.0.0.1:1", None);
+        assert_eq!(log.get(None)[0].id, 2);
+        // 超时中止的命令不受阈值限制
+        log.add_timeout_entry(&cmd(&["LRANGE", "l", "0", "-1"]), Duration::from_millis(1), "127.0.0.1:1", None);
+        assert_eq!(log.get(None)[0].args, args(&["LRANGE", "l", "0", "-1"]));
+    }
+
+    #[test]
+    fn test_get_reply() {
+        let log = SlowLog::new(128);
+        log.set_threshold_us(0);
+        log.add_entry(&cmd(&["SET", "k", "v"]), Duration::from_micros(1500), "127.0.0.1:5000", Some("app".into()));
+
+        let Frame::Array(entries) = log.execute(&args(&["GET"])) else { panic!("expected array") };
+        let Frame::Array(fields) = &entries[0] else { panic!("expected array") };
+        assert_eq!(fields[0], Frame::Integer(0));
+        assert_eq!(fields[2], Frame::Integer(1500));
+        assert_eq!(fields[3], Frame::Array(vec![Frame::bulk("SET"), Frame::bulk("k"), Frame::bulk("v")]));
+        assert_eq!(fields[4], Frame::bulk("127.0.0.1:5000"));
+        assert_eq!(fields[5], Frame::bulk("app"));
+
+        assert_eq!(log.execute(&args(&["LEN"])), Frame::Integer(1));
+        assert_eq!(log.execute(&args(&["RESET"])), Frame::ok());
+        assert_eq!(log.execute(&args(&["GET", "-1"])), Frame::Array(vec![]));
+        assert!(log.execute(&args(&["GET", "-2"])).is_error());
+    }
+
+    #[test]
+    fn test_long_arguments_are_folded() {
+        let mut args = vec![Bytes::from("x".repeat(200))];
+        args.extend((0..40).map(|i| Bytes::from(i.to_string())));
+        let folded = truncate_args(&args);
+        assert_eq!(folded.len(), MAX_ARGC);
+        assert_eq!(folded[0], format!("{}... (72 more bytes)", "x".repeat(128)));
+        assert_eq!(folded[MAX_ARGC - 1], "... (10 more arguments)");
+    }
+
+    #[test]
+    fn test_persist_across_restart() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("slowlog.json");
+
+        let log = SlowLog::new(3);
+        log.set_threshold_us(0);
+        log.persist_to(&path)?;
+        for key in ["a", "b", "c", "d", "e", "f", "g"] {
+            log.add_entry(&cmd(&["GET", key]), Duration::from_micros(1500), "127.0.0.1:1", None);
+        }
+        // 文件按上限的两倍重写，不会无限增长
+        assert!(fs::read_to_string(&path)?.lines().count() < 6);
+
+        // 重启后加载最新的记录，id 接着递增
+        let restarted = SlowLog::new(3);
+        restarted.set_threshold_us(0);
+        restarted.persist_to(&path)?;
+        assert_eq!(restarted.get(None), log.get(None));
+        assert_eq!(restarted.get(None)[0].duration, Duration::from_micros(1500));
+        restarted.add_entry(&cmd(&["GET", "h"]), Duration::from_millis(1), "127.0.0.1:1", None);
+        assert_eq!(restarted.get(Some(1))[0].id, 7);
+
+        restarted.reset();
+        let empty = SlowLog::new(3);
+        empty.persist_to(&path)?;
+        assert!(empty.is_empty());
+        Ok(())
+    }
+}
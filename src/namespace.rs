@@ -0,0 +1,143 @@
+// src/namespace.rs
+
+//! 多租户命名空间
+//!
+//! 配置 `namespace_ports` / `namespace_users` 为监听端口或 ACL 用户指定命名空间后，
+//! 连接发出的命令中的 key 在执行前统一加上 `命名空间:` 前缀，回复中的 key 再去掉前缀，
+//! 多个应用共用一个实例也不会互相覆盖。用户的命名空间优先于端口的；两者都没有配置的
+//! 连接看到的是不加前缀的完整键空间，可用于管理。
+//!
+//! 前缀在 ACL 检查之后、集群路由与执行之前加上：ACL 的 key 模式针对命名空间内的 key，
+//! AOF、复制流、慢日志与热点 key 记录的则是带前缀的实际 key。
+
+use bytes::{BufMut, Bytes, BytesMut};
+
+use crate::command;
+use crate::config::Config;
+use crate::protocol::Frame;
+
+/// 连接当前所在的命名空间，未配置时返回 None
+pub fn resolve<'a>(cfg: &'a Config, port: u16, user: Option<&str>) -> Option<&'a str> {
+    user.and_then(|user| cfg.namespace_users.get(user))
+        .or_else(|| cfg.namespace_ports.get(&port))
+        .map(String::as_str)
+        .filter(|namespace| !namespace.is_empty())
+}
+
+fn prefixed(namespace: &str, key: &[u8]) -> Bytes {
+    let mut out = BytesMut::with_capacity(namespace.len() + 1 + key.len());
+    out.put_slice(namespace.as_bytes());
+    out.put_u8(b':');
+    out.put_slice(key);
+    out.freeze()
+}
+
+/// 给命令中的 key 加上命名空间前缀
+pub fn prefix_keys(namespace: &str, mut parts: Vec<Bytes>) -> Vec<Bytes> {
+    if let Some(spec) = parts.first().and_then(|name| command::lookup(name)) {
+        for i in spec.key_positions(&parts) {
+            parts[i] = prefixed(namespace, &parts[i]);
+        }
+    }
+    parts
+}
+
+/// 去掉单个 key 的前缀；不属于该命名空间的 key 原样返回
+fn unprefix_key(namespace: &str, key: Frame) -> Frame {
+    match key {
+        Frame::Bulk(bytes) if bytes.starts_with(namespace.as_bytes()) && bytes.get(namespace.len()) == Some(&b':') => {
+            Frame::Bulk(bytes[namespace.len() + 1..].to_vec())
+        }
+        other => other,
+    }
+}
+
+/// 去掉回复中 key 的前缀
+///
+/// `parts` 是加过前缀的命令；EXEC 时 `queue` 是事务中的命令，与回复数组一一对应。
+/// 目前只有 LMPOP 与 BLMPOP 在回复中带 key。
+pub fn unprefix_reply(namespace: &str, parts: &[Bytes], queue: Option<&[Vec<Bytes>]>, reply: Frame) -> Frame {
+    match (queue, reply) {
+        (Some(queue), Frame::Array(replies)) => Frame::Array(
+            queue.iter().zip(replies).map(|(parts, reply)| unprefix_reply(namespace, parts, None, reply)).collect(),
+        ),
+        (None, Frame::Array(mut items)) if (parts[0].eq_ignore_ascii_case(b"LMPOP") || parts[0].eq_ignore_ascii_case(b"BLMPOP")) && !items.is_empty() => {
+            let key = std::mem::replace(&mut items[0], Frame::Null);
+            items[0] = unprefix_key(namespace, key);
+            Frame::Array(items)
+        }
+        (_, reply) => reply,
+    }
+}
+
+/// 去掉 CLIENT TRACKING 失效消息中 key 的前缀
+pub fn unprefix_push(namespace: &str, push: Frame) -> Frame {
+    match push {
+        Frame::Push(mut items) if items.first() == Some(&Frame::bulk("invalidate")) => {
+            if let Some(Frame::Array(keys)) = items.get_mut(1) {
+                *keys = std::mem::take(keys).into_iter().map(|key| unprefix_key(namespace, key)).collect();
+            }
+            Frame::Push(items)
+        }
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &[&str]) -> Vec<Bytes> {
+        s.iter().map(|x| Bytes::copy_from_slice(x.as_bytes())).collect()
+    }
+
+    #[test]
+    fn test_resolve() {
+        let mut cfg: Config = serde_json::from_str(
+            r#"{"aof":false,"rdb":false,"snapshot_interval_secs":60,"snapshot_threshold":20,
+                "metrics_enabled":false,"metrics_port":9090,"slowlog_threshold_ms":10,
+                "namespace_ports":{"6380":"shop"},"namespace_users":{"alice":"blog"}}"#,
+        )
+        .unwrap();
+        assert_eq!(resolve(&cfg, 6380, None), Some("shop"));
+        assert_eq!(resolve(&cfg, 6380, Some("alice")), Some("blog"));
+        assert_eq!(resolve(&cfg, 6380, Some("bob")), Some("shop"));
+        assert_eq!(resolve(&cfg, 6379, Some("default")), None);
+
+        cfg.namespace_users.insert("root".into(), String::new());
+        assert_eq!(resolve(&cfg, 6379, Some("root")), None);
+    }
+
+    #[test]
+    fn test_prefix_and_unprefix() {
+        assert_eq!(prefix_keys("app", args(&["SET", "k", "v"])), args(&["SET", "app:k", "v"]));
+        assert_eq!(prefix_keys("app", args(&["WATCH", "a", "b"])), args(&["WATCH", "app:a", "app:b"]));
+        assert_eq!(
+            prefix_keys("app", args(&["LMPOP", "2", "l1", "l2", "LEFT"])),
+            args(&["LMPOP", "2", "app:l1", "app:l2", "LEFT"])
+        );
+        assert_eq!(prefix_keys("app", args(&["MEMORY", "USAGE", "k"])), args(&["MEMORY", "USAGE", "app:k"]));
+        assert_eq!(prefix_keys("app", args(&["PING", "k"])), args(&["PING", "k"]));
+
+        let lmpop = args(&["LMPOP", "1", "app:l", "LEFT"]);
+        let reply = Frame::Array(vec![Frame::bulk("app:l"), Frame::Array(vec![Frame::bulk("x")])]);
+        let expected = Frame::Array(vec![Frame::bulk("l"), Frame::Array(vec![Frame::bulk("x")])]);
+        assert_eq!(unprefix_reply("app", &lmpop, None, reply.clone()), expected);
+        let blmpop = args(&["BLMPOP", "0", "1", "app:l", "LEFT"]);
+        assert_eq!(unprefix_reply("app", &blmpop, None, reply.clone()), expected);
+
+        // EXEC 的回复逐条按对应的命令处理
+        let queue = vec![args(&["GET", "app:l"]), lmpop.clone()];
+        let exec = Frame::Array(vec![Frame::bulk("app:l"), reply]);
+        assert_eq!(
+            unprefix_reply("app", &args(&["EXEC"]), Some(&queue), exec),
+            Frame::Array(vec![Frame::bulk("app:l"), expected])
+        );
+
+        let push = Frame::Push(vec![Frame::bulk("invalidate"), Frame::Array(vec![Frame::bulk("app:k"), Frame::bulk("other:k")])]);
+        assert_eq!(
+            unprefix_push("app", push),
+            Frame::Push(vec![Frame::bulk("invalidate"), Frame::Array(vec![Frame::bulk("k"), Frame::bulk("other:k")])])
+        );
+    }
+}
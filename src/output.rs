@@ -0,0 +1,195 @@
+// src/output.rs
+
+//! 客户端输出缓冲区上限
+//!
+//! 客户端停止读取时，发给它的数据会在服务端堆积：普通客户端是尚未写完的大回复，
+//! 订阅者是推送通道中排队的消息，副本是复制流。各类客户端按 `client_output_buffer_limit`
+//! 配置的上限检查待发送的字节数，超过硬上限、或持续超过软上限 `soft_seconds` 秒就断开连接。
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tokio::sync::Notify;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender, error::TryRecvError};
+
+use crate::config::OutputLimit;
+use crate::protocol::Frame;
+
+/// 按一类客户端的上限检查待发送的字节数，记录超过软上限的起始时间
+#[derive(Debug)]
+pub struct OutputLimiter {
+    limit: OutputLimit,
+    soft_since: Option<Instant>,
+}
+
+impl OutputLimiter {
+    pub fn new(limit: OutputLimit) -> Self {
+        OutputLimiter { limit, soft_since: None }
+    }
+
+    /// 待发送 `pending` 字节时是否应断开客户端
+    pub fn exceeded(&mut self, pending: usize) -> bool {
+        self.exceeded_at(pending, Instant::now())
+    }
+
+    fn exceeded_at(&mut self, pending: usize, now: Instant) -> bool {
+        if self.limit.over_hard(pending) {
+            return true;
+        }
+        if !self.limit.over_soft(pending) {
+            self.soft_since = None;
+            return false;
+        }
+        let since = *self.soft_since.get_or_insert(now);
+        now.duration_since(since) >= Duration::from_secs(self.limit.soft_seconds)
+    }
+}
+
+/// 推送通道的两端共享的状态
+#[derive(Debug)]
+struct PushState {
+    /// 已发送、连接尚未取走的字节数
+    pending: AtomicUsize,
+    /// 已发送、连接尚未取走的消息数
+    queued: AtomicUsize,
+    limiter: Mutex<OutputLimiter>,
+    overflowed: AtomicBool,
+    notify: Notify,
+}
+
+/// 服务端主动发给连接的消息（订阅消息、客户端缓存失效通知）经由推送通道送达，
+/// 排队的字节数超出上限后通道不再接受消息，连接随即断开
+pub fn push_channel(limit: OutputLimit) -> (PushSender, PushReceiver) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let state = Arc::new(PushState {
+        pending: AtomicUsize::new(0),
+        queued: AtomicUsize::new(0),
+        limiter: Mutex::new(OutputLimiter::new(limit)),
+        overflowed: AtomicBool::new(false),
+        notify: Notify::new(),
+    });
+    (PushSender { tx, state: state.clone() }, PushReceiver { rx, state })
+}
+
+#[derive(Debug, Clone)]
+pub struct PushSender {
+    tx: UnboundedSender<Frame>,
+    state: Arc<PushState>,
+}
+
+impl PushSender {
+    /// 发送一条推送；连接已关闭或输出缓冲区超出上限时返回 false
+    pub fn send(&self, frame: Frame) -> bool {
+        let state = &self.state;
+        if state.overflowed.load(Ordering::Relaxed) {
+            return false;
+        }
+        let size = frame.size_hint();
+        let pending = state.pending.fetch_add(size, Ordering::Relaxed) + size;
+        if state.limiter.lock().unwrap().exceeded(pending) {
+            state.overflowed.store(true, Ordering::Relaxed);
+            state.notify.notify_one();
+            return false;
+        }
+        // 先计数再发送，连接取走消息时计数不会变成负数
+        state.queued.fetch_add(1, Ordering::Relaxed);
+        if self.tx.send(frame).is_err() {
+            state.pending.fetch_sub(size, Ordering::Relaxed);
+            state.queued.fetch_sub(1, Ordering::Relaxed);
+            return false;
+        }
+        true
+    }
+
+    /// 排队等待发出的消息数
+    pub fn queued(&self) -> usize {
+        self.state.queued.load(Ordering::Relaxed)
+    }
+
+    /// 按超出上限处理，断开连接；返回是否由这次调用断开
+    pub fn close(&self) -> bool {
+        let first = !self.state.overflowed.swap(true, Ordering::Relaxed);
+        self.state.notify.notify_one();
+        first
+    }
+
+    /// 输出缓冲区超出上限时完成
+    pub async fn overflowed(&self) {
+        // notify_one 在没有等待者时会保留一次通知，先检查标志再等待不会错过
+        while !self.state.overflowed.load(Ordering::Relaxed) {
+            self.state.notify.notified().await;
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct PushReceiver {
+    rx: UnboundedReceiver<Frame>,
+    state: Arc<PushState>,
+}
+
+impl PushReceiver {
+    pub async fn recv(&mut self) -> Option<Frame> {
+        let frame = self.rx.recv().await?;
+        self.taken(&frame);
+        Some(frame)
+    }
+
+    pub fn try_recv(&mut self) -> Result<Frame, TryRecvError> {
+        let frame = self.rx.try_recv()?;
+        self.taken(&frame);
+        Ok(frame)
+    }
+
+    fn taken(&self, frame: &Frame) {
+        self.state.pending.fetch_sub(frame.size_hint(), Ordering::Relaxed);
+        self.state.queued.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// 排队等待发出的字节数
+    pub fn pending(&self) -> usize {
+        self.state.pending.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limiter() {
+        let mut limiter = OutputLimiter::new(OutputLimit::new(100, 10, 60));
+        let start = Instant::now();
+        assert!(!limiter.exceeded_at(5, start));
+        assert!(limiter.exceeded_at(100, start));
+        // 超过软上限 60 秒才断开，期间回落到软上限以下会重新计时
+        assert!(!limiter.exceeded_at(50, start));
+        assert!(!limiter.exceeded_at(50, start + Duration::from_secs(30)));
+        assert!(!limiter.exceeded_at(0, start + Duration::from_secs(40)));
+        assert!(!limiter.exceeded_at(50, start + Duration::from_secs(70)));
+        assert!(limiter.exceeded_at(50, start + Duration::from_secs(130)));
+
+        // 0 表示不限制
+        assert!(!OutputLimiter::new(OutputLimit::default()).exceeded(usize::MAX));
+    }
+
+    #[tokio::test]
+    async fn test_push_channel_overflow() {
+        let (tx, mut rx) = push_channel(OutputLimit::new(100, 0, 0));
+        let msg = Frame::bulk(vec![b'x'; 40]);
+        assert!(tx.send(msg.clone()));
+        assert_eq!(rx.pending(), msg.size_hint());
+        assert_eq!(tx.queued(), 1);
+        assert_eq!(rx.recv().await, Some(msg.clone()));
+        assert_eq!((rx.pending(), tx.queued()), (0, 0));
+
+        // 连接不取消息，第三条超出硬上限，此后的消息都被丢弃
+        assert!(tx.send(msg.clone()));
+        assert!(tx.send(msg.clone()));
+        assert!(!tx.send(msg.clone()));
+        tx.overflowed().await;
+        assert!(!tx.send(Frame::ok()));
+        assert!(!tx.close());
+    }
+}
@@ -0,0 +1,630 @@
+// src/persistence/aof.rs
+
+//! AOF 记录格式
+//!
+//! 每条命令以 RESP 数组写入（与 Redis 相同），参数按原样的字节保存，
+//! 空格、换行乃至非 UTF-8 的字节都能原样重放。
+//!
+//! 读取时兼容旧版本的纯文本格式：不以 `*` 开头的行按空白切分为一条命令，
+//! 因此旧文件无需转换即可加载，新记录直接追加在其后。
+//!
+//! 以 `#` 开头的行是注释，目前只有 `#TS:<unix 秒>` 时间戳一种（与 Redis 相同），
+//! 用于按时间点恢复。
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::io::{self, BufRead, Read, Write};
+use std::path::Path;
+
+use bytes::Bytes;
+
+use super::rdb;
+use crate::command;
+use crate::glob::glob_match;
+use crate::protocol::Frame;
+
+/// 把一条命令编码为 RESP 数组
+pub fn encode<A: AsRef<[u8]>>(parts: &[A]) -> Vec<u8> {
+    Frame::Array(parts.iter().map(|p| Frame::bulk(p.as_ref())).collect()).to_bytes(2)
+}
+
+/// 把一个事务编码为 `MULTI ... EXEC` 记录，只保留写命令；没有写命令时返回 `None`
+pub fn encode_transaction(cmds: &[Vec<Bytes>]) -> Option<Vec<u8>> {
+    let writes: Vec<&Vec<Bytes>> = cmds
+        .iter()
+        .filter(|parts| command::lookup(&parts[0]).is_some_and(|spec| spec.is_write()))
+        .collect();
+    if writes.is_empty() {
+        return None;
+    }
+
+    let mut record = encode(&["MULTI"]);
+    for parts in &writes {
+        record.extend(encode(parts));
+    }
+    record.extend(encode(&["EXEC"]));
+    Some(record)
+}
+
+/// 时间戳注释 `#TS:<unix 秒>`
+pub fn timestamp_annotation(secs: u64) -> Vec<u8> {
+    format!("#TS:{}\r\n", secs).into_bytes()
+}
+
+/// AOF 中的一条记录
+#[derive(Debug, Clone, PartialEq)]
+pub enum Record {
+    Command(Vec<Bytes>),
+    /// 时间戳注释，之后的记录写入于该时间（unix 秒）或更晚
+    Timestamp(u64),
+}
+
+/// 读取 AOF 时的错误
+#[derive(Debug)]
+pub enum AofError {
+    /// 文件末尾的记录不完整（写到一半时崩溃），`offset` 为该记录的起始位置
+    Truncated { offset: u64 },
+    /// 记录格式错误
+    Corrupt { offset: u64, reason: String },
+    Io(io::Error),
+}
+
+impl fmt::Display for AofError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AofError::Truncated { offset } => {
+                write!(f, "AOF truncated: incomplete record at offset {}", offset)
+            }
+            AofError::Corrupt { offset, reason } => {
+                write!(f, "Bad file format reading the append only file at offset {}: {}", offset, reason)
+            }
+            AofError::Io(e) => write!(f, "AOF read error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for AofError {}
+
+impl From<io::Error> for AofError {
+    fn from(e: io::Error) -> Self {
+        AofError::Io(e)
+    }
+}
+
+/// 单个参数长度的默认上限，与 `proto_max_bulk_len` 的默认值相同
+pub const DEFAULT_MAX_BULK_LEN: usize = 512 * 1024 * 1024;
+
+/// 一次为参数分配的最大字节数，更长的参数随读到的数据增长
+const READ_CHUNK: usize = 64 * 1024;
+
+/// 逐条读取 AOF 中的命令
+pub struct AofReader<R> {
+    inner: R,
+    offset: u64,
+    max_bulk_len: usize,
+}
+
+impl<R: BufRead> AofReader<R> {
+    pub fn new(inner: R) -> Self {
+        AofReader { inner, offset: 0, max_bulk_len: DEFAULT_MAX_BULK_LEN }
+    }
+
+    /// 单个参数的长度上限，声明的长度超出时按格式损坏处理
+    pub fn max_bulk_len(mut self, limit: usize) -> Self {
+        self.max_bulk_len = limit;
+        self
+    }
+
+    /// 已完整读取的字节数，即下一条记录的起始位置
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// 读取下一条命令，跳过注释；文件结束时返回 `Ok(None)`
+    pub fn next_command(&mut self) -> Result<Option<Vec<Bytes>>, AofError> {
+        loop {
+            match self.next_record()? {
+                Some(Record::Command(parts)) => return Ok(Some(parts)),
+                Some(Record::Timestamp(_)) => continue,
+                None => return Ok(None),
+            }
+        }
+    }
+
+    /// 读取下一条记录，文件结束时返回 `Ok(None)`；空行与无法识别的注释直接跳过
+    pub fn next_record(&mut self) -> Result<Option<Record>, AofError> {
+        loop {
+            let first = match self.inner.fill_buf()?.first() {
+                Some(b) => *b,
+                None => return Ok(None),
+            };
+            let start = self.offset;
+            if first == b'#' {
+                if let Some(ts) = self.read_annotation(start)? {
+                    return Ok(Some(Record::Timestamp(ts)));
+                }
+                continue;
+            }
+            let parts = if first == b'*' {
+                self.read_resp(start)?
+            } else {
+                self.read_legacy()?
+            };
+            if !parts.is_empty() {
+                return Ok(Some(Record::Command(parts)));
+            }
+        }
+    }
+
+    /// 读取一行注释，是时间戳时返回其值
+    fn read_annotation(&mut self, start: u64) -> Result<Option<u64>, AofError> {
+        let line = self.read_line()?.ok_or(AofError::Truncated { offset: start })?;
+        let line = String::from_utf8_lossy(&line);
+        match line.trim_end().strip_prefix("#TS:") {
+            Some(ts) => ts.parse().map(Some).map_err(|_| corrupt(start, "invalid timestamp annotation")),
+            None => Ok(None),
+        }
+    }
+
+    /// `*N\r\n` 后跟 N 个 `$len\r\n<bytes>\r\n`
+    fn read_resp(&mut self, start: u64) -> Result<Vec<Bytes>, AofError> {
+        let count = self.read_len(b'*', start)?;
+        let mut parts = Vec::with_capacity(count.min(1024));
+        for _ in 0..count {
+            let len = self.read_len(b'$', start)?;
+            let total = len
+                .checked_add(2)
+                .filter(|_| len <= self.max_bulk_len)
+                .ok_or_else(|| corrupt(start, "invalid length"))?;
+            // 缓冲区随实际读到的数据增长，声明的长度超出文件剩余部分时不会预先分配
+            let mut data = Vec::with_capacity(total.min(READ_CHUNK));
+            (&mut self.inner).take(total as u64).read_to_end(&mut data)?;
+            if data.len() < total {
+                return Err(AofError::Truncated { offset: start });
+            }
+            self.offset += data.len() as u64;
+            if !data.ends_with(b"\r\n") {
+                return Err(corrupt(start, "bulk string not terminated by CRLF"));
+            }
+            data.truncate(len);
+            parts.push(Bytes::from(data));
+        }
+        Ok(parts)
+    }
+
+    /// 读取 `<prefix><数字>\r\n`
+    fn read_len(&mut self, prefix: u8, start: u64) -> Result<usize, AofError> {
+        let line = self.read_line()?.ok_or(AofError::Truncated { offset: start })?;
+        let line = line.strip_suffix(b"\r\n").ok_or_else(|| corrupt(start, "expected CRLF"))?;
+        match line.split_first() {
+            Some((p, digits)) if *p == prefix => std::str::from_utf8(digits)
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .ok_or_else(|| corrupt(start, "invalid length")),
+            _ => Err(corrupt(start, &format!("expected '{}'", prefix as char))),
+        }
+    }
+
+    /// 旧格式：一行一条命令，参数以空白分隔
+    fn read_legacy(&mut self) -> Result<Vec<Bytes>, AofError> {
+        let mut line = Vec::new();
+        let n = self.inner.read_until(b'\n', &mut line)?;
+        self.offset += n as u64;
+        Ok(line
+            .split(|b| b.is_ascii_whitespace())
+            .filter(|word| !word.is_empty())
+            .map(Bytes::copy_from_slice)
+            .collect())
+    }
+
+    /// 读取以 `\n` 结尾的一行；没有换行就到达文件末尾时返回 `None`
+    fn read_line(&mut self) -> Result<Option<Vec<u8>>, AofError> {
+        let mut line = Vec::new();
+        let n = self.inner.read_until(b'\n', &mut line)?;
+        if !line.ends_with(b"\n") {
+            return Ok(None);
+        }
+        self.offset += n as u64;
+        Ok(Some(line))
+    }
+}
+
+/// AOF 检查结果
+#[derive(Debug, Clone, PartialEq)]
+pub struct CheckReport {
+    pub file_len: u64,
+    /// 可以安全重放的前缀长度，修复时截断到这里
+    pub valid_len: u64,
+    /// RDB 前导快照中的 key 数，没有前导快照时为 `None`
+    pub preamble_keys: Option<usize>,
+    /// 完整读出的命令数（不含前导快照）
+    pub commands: usize,
+    /// 发现的第一处问题：截断、格式损坏或缺少 `EXEC` 的事务
+    pub problem: Option<String>,
+}
+
+/// 扫描整个 AOF，找出第一条截断或损坏的记录
+///
+/// 未以 `EXEC` 结束的事务会被整体计入损坏部分，与启动时的重放规则一致
+pub fn check(path: &Path) -> io::Result<CheckReport> {
+    let data = std::fs::read(path)?;
+    let mut report = CheckReport {
+        file_len: data.len() as u64,
+        valid_len: 0,
+        preamble_keys: None,
+        commands: 0,
+        problem: None,
+    };
+
+    let mut base = 0;
+    if data.starts_with(rdb::MAGIC) {
+        match rdb::decode_prefix(&data) {
+            Ok((snapshot, len)) => {
+                report.preamble_keys = Some(snapshot.entries.len());
+                base = len as u64;
+                report.valid_len = base;
+            }
+            Err(e) => {
+                report.problem = Some(format!("bad RDB preamble: {}", e));
+                return Ok(report);
+            }
+        }
+    }
+
+    let mut reader = AofReader::new(&data[base as usize..]);
+    let mut txn_start = None;
+    loop {
+        let start = base + reader.offset();
+        match reader.next_command() {
+            Ok(Some(parts)) => {
+                report.commands += 1;
+                if parts[0].eq_ignore_ascii_case(b"MULTI") {
+                    txn_start = Some(start);
+                } else if parts[0].eq_ignore_ascii_case(b"EXEC") {
+                    txn_start = None;
+                }
+                if txn_start.is_none() {
+                    report.valid_len = base + reader.offset();
+                }
+            }
+            Ok(None) => break,
+            Err(AofError::Io(e)) => return Err(e),
+            Err(e) => {
+                report.problem = Some(e.to_string());
+                return Ok(report);
+            }
+        }
+    }
+    if let Some(start) = txn_start {
+        report.problem = Some(format!("unterminated MULTI at offset {}", start));
+    }
+    Ok(report)
+}
+
+/// 检视 AOF 时的过滤条件，全部条件都满足的命令才计入统计与导出
+#[derive(Debug, Clone, Default)]
+pub struct InspectFilter {
+    /// 只保留写入时间不早于它的记录（unix 秒）；第一个时间戳之前的记录时间未知，不保留
+    pub since: Option<u64>,
+    /// 只保留写入时间不晚于它的记录（unix 秒）
+    pub until: Option<u64>,
+    /// 只保留这些命令（大小写不敏感），为空时不限
+    pub commands: Vec<String>,
+    /// 只保留至少有一个 key 匹配该 glob 模式的命令
+    pub key_pattern: Option<String>,
+}
+
+impl InspectFilter {
+    fn matches(&self, ts: Option<u64>, parts: &[Bytes]) -> bool {
+        if let Some(since) = self.since
+            && ts.is_none_or(|ts| ts < since)
+        {
+            return false;
+        }
+        if let (Some(until), Some(ts)) = (self.until, ts)
+            && ts > until
+        {
+            return false;
+        }
+        if !self.commands.is_empty() && !self.commands.iter().any(|c| c.as_bytes().eq_ignore_ascii_case(&parts[0])) {
+            return false;
+        }
+        match &self.key_pattern {
+            None => true,
+            Some(pattern) => command::lookup(&parts[0]).is_some_and(|spec| {
+                spec.keys(parts).iter().any(|key| glob_match(pattern.as_bytes(), key))
+            }),
+        }
+    }
+}
+
+/// AOF 检视结果，统计只包含通过过滤的命令（`MULTI` / `EXEC` 不计入）
+#[derive(Debug, Clone, Default)]
+pub struct InspectReport {
+    /// RDB 前导快照中的 key 数
+    pub preamble_keys: Option<usize>,
+    /// 读出的全部命令数
+    pub total: u64,
+    /// 通过过滤的命令数
+    pub matched: u64,
+    /// 包含通过过滤的命令的事务数
+    pub transactions: u64,
+    pub per_command: BTreeMap<String, u64>,
+    /// 按 key 计数，非 UTF-8 的字节按替换字符显示
+    pub per_key: HashMap<String, u64>,
+    /// 通过过滤的命令中最早 / 最晚的时间戳
+    pub first_timestamp: Option<u64>,
+    pub last_timestamp: Option<u64>,
+    /// 读取中止的原因（截断或损坏），之前的部分仍然统计
+    pub problem: Option<String>,
+}
+
+impl InspectReport {
+    /// 出现次数最多的 `n` 个 key
+    pub fn top_keys(&self, n: usize) -> Vec<(&str, u64)> {
+        let mut keys: Vec<(&str, u64)> = self.per_key.iter().map(|(k, c)| (k.as_str(), *c)).collect();
+        keys.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        keys.truncate(n);
+        keys
+    }
+
+    fn record(&mut self, ts: Option<u64>, parts: &[Bytes]) {
+        self.matched += 1;
+        *self.per_command.entry(command::name_upper(&parts[0])).or_default() += 1;
+        if let Some(spec) = command::lookup(&parts[0]) {
+            for key in spec.keys(parts) {
+                *self.per_key.entry(String::from_utf8_lossy(key).into_owned()).or_default() += 1;
+            }
+        }
+        if let Some(ts) = ts {
+            self.first_timestamp = Some(self.first_timestamp.map_or(ts, |t| t.min(ts)));
+            self.last_timestamp = Some(self.last_timestamp.map_or(ts, |t| t.max(ts)));
+        }
+    }
+}
+
+/// 统计 AOF 中的命令，并把通过过滤的记录按 AOF 格式写到 `export`
+///
+/// 导出的文件可以直接作为 AOF 加载：时间戳注释随记录保留，事务中通过过滤的命令仍包在 `MULTI ... EXEC` 中
+pub fn inspect(path: &Path, filter: &InspectFilter, mut export: Option<&mut dyn Write>) -> io::Result<InspectReport> {
+    let data = std::fs::read(path)?;
+    let mut report = InspectReport::default();
+
+    let mut base = 0;
+    if data.starts_with(rdb::MAGIC) {
+        match rdb::decode_prefix(&data) {
+            Ok((snapshot, len)) => {
+                report.preamble_keys = Some(snapshot.entries.len());
+                base = len;
+            }
+            Err(e) => {
+                report.problem = Some(format!("bad RDB preamble: {}", e));
+                return Ok(report);
+            }
+        }
+    }
+
+    let mut reader = AofReader::new(&data[base..]);
+    let mut ts = None;
+    let mut exported_ts = None;
+    // 进行中的事务里通过过滤的命令，EXEC 时一起导出
+    let mut txn: Option<Vec<Vec<Bytes>>> = None;
+    loop {
+        let parts = match reader.next_record() {
+            Ok(Some(Record::Timestamp(t))) => {
+                ts = Some(t);
+                continue;
+            }
+            Ok(Some(Record::Command(parts))) => parts,
+            Ok(None) => break,
+            Err(AofError::Io(e)) => return Err(e),
+            Err(e) => {
+                report.problem = Some(e.to_string());
+                break;
+            }
+        };
+        report.total += 1;
+
+        let name = command::name_upper(&parts[0]);
+        let matched = match (name.as_str(), txn.as_mut()) {
+            ("MULTI", _) => {
+                txn = Some(Vec::new());
+                continue;
+            }
+            ("EXEC", Some(_)) => txn.take().filter(|cmds| !cmds.is_empty()),
+            (_, Some(cmds)) => {
+                if filter.matches(ts, &parts) {
+                    report.record(ts, &parts);
+                    cmds.push(parts);
+                }
+                continue;
+            }
+            (_, None) if filter.matches(ts, &parts) => {
+                report.record(ts, &parts);
+                Some(vec![parts])
+            }
+            (_, None) => None,
+        };
+
+        let Some(cmds) = matched else { continue };
+        let in_txn = name == "EXEC";
+        if in_txn {
+            report.transactions += 1;
+        }
+        if let Some(out) = export.as_deref_mut() {
+            if let Some(t) = ts
+                && exported_ts != Some(t)
+            {
+                out.write_all(&timestamp_annotation(t))?;
+                exported_ts = Some(t);
+            }
+            if in_txn {
+                out.write_all(&encode_transaction(&cmds).unwrap_or_default())?;
+            } else {
+                out.write_all(&encode(&cmds[0]))?;
+            }
+        }
+    }
+    if txn.is_some() && report.problem.is_none() {
+        report.problem = Some("unterminated MULTI at end of file".to_string());
+    }
+    Ok(report)
+}
+
+fn corrupt(offset: u64, reason: &str) -> AofError {
+    AofError::Corrupt { offset, reason: reason.to_string() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cmd(parts: &[&str]) -> Vec<Bytes> {
+        parts.iter().map(|s| Bytes::copy_from_slice(s.as_bytes())).collect()
+    }
+
+    fn read_all(data: &[u8]) -> Result<Vec<Vec<Bytes>>, AofError> {
+        let mut reader = AofReader::new(data);
+        let mut out = Vec::new();
+        while let Some(parts) = reader.next_command()? {
+            out.push(parts);
+        }
+        Ok(out)
+    }
+
+    #[test]
+    fn test_binary_safe_round_trip() {
+        let binary = vec![Bytes::from("SET"), Bytes::from_static(b"\xff\x00"), Bytes::from_static(b"\x80\r\n")];
+        let commands = vec![cmd(&["SET", "k", "hello world\r\nSET x 1"]), cmd(&["SET", "", "*1"]), binary];
+        let mut data = Vec::new();
+        for c in &commands {
+            data.extend(encode(c));
+        }
+        assert_eq!(&data[..20], b"*3\r\n$3\r\nSET\r\n$1\r\nk\r\n");
+        assert_eq!(read_all(&data).unwrap(), commands);
+    }
+
+    #[test]
+    fn test_legacy_lines_mixed_with_resp() {
+        let mut data = b"SET a 1\n\nINCR  a\n".to_vec();
+        data.extend(encode(&cmd(&["SET", "b", "x y"])));
+        assert_eq!(
+            read_all(&data).unwrap(),
+            vec![cmd(&["SET", "a", "1"]), cmd(&["INCR", "a"]), cmd(&["SET", "b", "x y"])]
+        );
+    }
+
+    #[test]
+    fn test_timestamp_annotations() {
+        let mut data = timestamp_annotation(100);
+        data.extend(encode(&cmd(&["SET", "a", "1"])));
+        data.extend(b"#some future annotation\r\n");
+        data.extend(timestamp_annotation(105));
+        let mut reader = AofReader::new(&data[..]);
+        assert_eq!(reader.next_record().unwrap(), Some(Record::Timestamp(100)));
+        assert_eq!(reader.next_record().unwrap(), Some(Record::Command(cmd(&["SET", "a", "1"]))));
+        assert_eq!(reader.next_record().unwrap(), Some(Record::Timestamp(105)));
+        assert_eq!(reader.next_record().unwrap(), None);
+        assert_eq!(read_all(&data).unwrap(), vec![cmd(&["SET", "a", "1"])]);
+    }
+
+    #[test]
+    fn test_truncated_and_corrupt_records() {
+        let mut data = encode(&cmd(&["SET", "a", "1"]));
+        let full = data.len() as u64;
+        data.extend(b"*3\r\n$3\r\nSET\r\n$1\r\nb\r\n$5\r\nab");
+        let mut reader = AofReader::new(&data[..]);
+        assert!(reader.next_command().unwrap().is_some());
+        assert_eq!(reader.offset(), full);
+        assert!(matches!(reader.next_command(), Err(AofError::Truncated { offset }) if offset == full));
+
+        assert!(matches!(read_all(b"*1\r\n#3\r\nSET\r\n"), Err(AofError::Corrupt { offset: 0, .. })));
+    }
+
+    #[test]
+    fn test_bulk_length_is_bounded() {
+        let invalid = |result: Result<Vec<Vec<Bytes>>, AofError>| {
+            matches!(result, Err(AofError::Corrupt { offset: 0, reason }) if reason == "invalid length")
+        };
+        // 长度加上 CRLF 溢出，或超出参数长度上限
+        assert!(invalid(read_all(b"*1\r\n$18446744073709551615\r\nx\r\n")));
+        assert!(invalid(read_all(b"*1\r\n$18446744073709551614\r\nx\r\n")));
+        let mut reader = AofReader::new(&b"*1\r\n$5\r\nhello\r\n"[..]).max_bulk_len(4);
+        assert!(matches!(reader.next_command(), Err(AofError::Corrupt { offset: 0, .. })));
+
+        // 上限之内但超出剩余数据的长度按截断处理，不按声明的长度分配内存
+        assert!(matches!(read_all(b"*1\r\n$536870912\r\nab"), Err(AofError::Truncated { offset: 0 })));
+    }
+
+    #[test]
+    fn test_check_reports_first_problem() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("appendonly.aof");
+        let mut data = encode(&cmd(&["SET", "a", "1"]));
+        let good = data.len() as u64;
+        data.extend(encode(&cmd(&["MULTI"])));
+        data.extend(encode(&cmd(&["INCR", "a"])));
+        std::fs::write(&path, &data)?;
+
+        let report = check(&path)?;
+        assert_eq!(report.valid_len, good);
+        assert_eq!(report.commands, 3);
+        assert_eq!(report.problem.as_deref(), Some(format!("unterminated MULTI at offset {}", good).as_str()));
+
+        data.extend(encode(&cmd(&["EXEC"])));
+        let complete = data.len() as u64;
+        data.extend(b"*2\r\n$3\r\nGET");
+        std::fs::write(&path, &data)?;
+        let report = check(&path)?;
+        assert_eq!((report.valid_len, report.file_len), (complete, complete + 11));
+        assert!(report.problem.unwrap().contains("truncated"));
+
+        // 长度头部溢出时报告格式损坏，有效部分不变
+        data.truncate(complete as usize);
+        data.extend(b"*1\r\n$18446744073709551615\r\n");
+        std::fs::write(&path, &data)?;
+        let report = check(&path)?;
+        assert_eq!(report.valid_len, complete);
+        assert!(report.problem.unwrap().contains("invalid length"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_inspect_and_export() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("appendonly.aof");
+        let mut data = encode(&cmd(&["SET", "user:1", "a"]));
+        data.extend(timestamp_annotation(100));
+        data.extend(encode(&cmd(&["SET", "user:2", "b"])));
+        data.extend(encode(&cmd(&["INCR", "hits"])));
+        data.extend(timestamp_annotation(200));
+        data.extend(encode_transaction(&[cmd(&["INCR", "hits"]), cmd(&["SET", "user:1", "c"])]).unwrap());
+        std::fs::write(&path, &data)?;
+
+        let report = inspect(&path, &InspectFilter::default(), None)?;
+        assert_eq!((report.total, report.matched, report.transactions), (7, 5, 1));
+        assert_eq!(report.per_command.get("SET"), Some(&3));
+        assert_eq!(report.top_keys(1), vec![("hits", 2)]);
+        assert_eq!((report.first_timestamp, report.last_timestamp), (Some(100), Some(200)));
+        assert!(report.problem.is_none());
+
+        // 只导出 user:* 且不晚于 150 的写入；时间戳之前的记录也在其中
+        let filter = InspectFilter { until: Some(150), key_pattern: Some("user:*".into()), ..Default::default() };
+        let mut out = Vec::new();
+        let report = inspect(&path, &filter, Some(&mut out))?;
+        assert_eq!(report.matched, 2);
+        let mut expected = encode(&cmd(&["SET", "user:1", "a"]));
+        expected.extend(timestamp_annotation(100));
+        expected.extend(encode(&cmd(&["SET", "user:2", "b"])));
+        assert_eq!(out, expected);
+
+        // 事务中的命令按过滤结果导出，仍包在 MULTI ... EXEC 中
+        let filter = InspectFilter { since: Some(150), commands: vec!["set".into()], ..Default::default() };
+        let mut out = Vec::new();
+        inspect(&path, &filter, Some(&mut out))?;
+        let mut expected = timestamp_annotation(200);
+        expected.extend(encode_transaction(&[cmd(&["SET", "user:1", "c"])]).unwrap());
+        assert_eq!(out, expected);
+        Ok(())
+    }
+}
@@ -0,0 +1,137 @@
+// src/persistence/dataset.rs
+
+//! 按逻辑 key 遍历整个数据集
+//!
+//! 底层存储把每种类型拆成多条带前缀的记录（见 `keys` 模块），
+//! AOF 重写与 RDB 快照都需要按 key 重新组装出完整的值。
+
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::{Context, Result};
+
+use crate::engine::KvEngine;
+use crate::expire;
+use crate::keys::{self, Kind};
+use crate::types::{hash, list, set, string};
+
+/// 一个 key 的值，key / field / member / 元素都按原样的字节保存
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(Vec<u8>),
+    Hash(Vec<(Vec<u8>, Vec<u8>)>),
+    List(Vec<Vec<u8>>),
+    Set(Vec<Vec<u8>>),
+}
+
+/// 数据集中的一个 key
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub key: Vec<u8>,
+    pub value: Value,
+    /// 过期时间（unix 毫秒）
+    pub expire_at_ms: Option<u64>,
+}
+
+/// 扫描整个数据集，按 key 排序返回；已过期的 key 直接跳过
+///
+/// 各类型的命名空间相互独立，同名 key 可能同时存在多种类型，此时每种类型各返回一个 `Entry`
+pub fn scan<E: KvEngine>(db: &E) -> Result<Vec<Entry>> {
+    // 先读出全部过期时间，用来跳过已过期的 key
+    let mut expires: HashMap<Vec<u8>, u64> = HashMap::new();
+    for item in db.scan_prefix(Kind::Expire.tag()) {
+        let (k, v) = item?;
+        let record = decode(&k)?;
+        let ts = u64::from_be_bytes(v.as_ref().try_into().context("corrupt expire record")?);
+        expires.insert(record.key.to_vec(), ts);
+    }
+    let now = db.clock().now_ms();
+    let alive = |key: &[u8]| expires.get(key).is_none_or(|ts| *ts > now);
+
+    // BTreeMap 保证输出顺序稳定，方便比对与测试
+    let mut values: BTreeMap<Vec<u8>, Vec<Value>> = BTreeMap::new();
+
+    for item in db.scan_prefix(Kind::String.tag()) {
+        let (k, v) = item?;
+        let key = decode(&k)?.key;
+        if alive(key) {
+            values.entry(key.to_vec()).or_default().push(Value::String(v.to_vec()));
+        }
+    }
+
+    for kind in [Kind::Hash, Kind::Set] {
+        for item in db.scan_prefix(kind.tag()) {
+            let (k, v) = item?;
+            let record = decode(&k)?;
+            if !alive(record.key) {
+                continue;
+            }
+            let member = record.suffix.to_vec();
+            let key_values = values.entry(record.key.to_vec()).or_default();
+            // 同一 key 的记录在扫描中是连续的，只需看最后一个值
+            match (kind == Kind::Hash, key_values.last_mut()) {
+                (true, Some(Value::Hash(fields))) => fields.push((member, v.to_vec())),
+                (true, _) => key_values.push(Value::Hash(vec![(member, v.to_vec())])),
+                (false, Some(Value::Set(members))) => members.push(member),
+                (false, _) => key_values.push(Value::Set(vec![member])),
+            }
+        }
+    }
+
+    // list 以 meta 中的 head 记录发现 key，再按顺序读出全部元素
+    let mut list_keys = Vec::new();
+    for item in db.scan_prefix(Kind::ListMeta.tag()) {
+        let (k, _) = item?;
+        let record = decode(&k)?;
+        if record.is_list_head() {
+            list_keys.push(record.key.to_vec());
+        }
+    }
+    for key in list_keys {
+        if alive(&key) {
+            let items = list::lrange(db, &key, 0, -1)?;
+            values.entry(key).or_default().push(Value::List(items));
+        }
+    }
+
+    let mut entries = Vec::new();
+    for (key, key_values) in values {
+        let expire_at_ms = expires.get(&key).copied();
+        for value in key_values {
+            entries.push(Entry { key: key.clone(), value, expire_at_ms });
+        }
+    }
+    Ok(entries)
+}
+
+/// 把一个 key 写回数据库
+pub fn restore<E: KvEngine>(db: &E, entry: &Entry) -> Result<()> {
+    let key = entry.key.as_slice();
+    match &entry.value {
+        Value::String(v) => {
+            string::set(db, key, v)?;
+        }
+        Value::Hash(fields) => {
+            for (f, v) in fields {
+                hash::hset(db, key, f, v)?;
+            }
+        }
+        Value::List(items) => {
+            for v in items {
+                list::rpush(db, key, v)?;
+            }
+        }
+        Value::Set(members) => {
+            for m in members {
+                set::sadd(db, key, m)?;
+            }
+        }
+    }
+    if let Some(ts) = entry.expire_at_ms {
+        expire::expire_at(db, key, ts)?;
+    }
+    Ok(())
+}
+
+fn decode(record: &[u8]) -> Result<keys::Record<'_>> {
+    keys::decode(record).with_context(|| format!("malformed record key {:?}", String::from_utf8_lossy(record)))
+}
@@ -0,0 +1,149 @@
+// src/persistence/export.rs
+
+//! 把数据集导出为 JSON Lines 或 CSV，供审计或导入分析系统
+//!
+//! 每个 key 一行，包含 key、类型、值、过期时间（unix 毫秒）与导出时剩余的 TTL（毫秒）。
+//! hash 的值为 JSON 对象，list / set 的值为 JSON 数组；CSV 中这些值以 JSON 文本放在 `value` 列。
+//! 导出格式是文本，不是合法 UTF-8 的字节按替换字符输出。
+
+use std::io::{self, Write};
+
+use serde_json::{json, Map, Value as Json};
+
+use super::dataset::{Entry, Value};
+use crate::glob::glob_match;
+
+/// 导出格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    JsonLines,
+    Csv,
+}
+
+/// 导出选项
+#[derive(Debug, Clone, Default)]
+pub struct ExportOptions {
+    /// 只导出匹配该 glob 模式的 key
+    pub pattern: Option<String>,
+    /// 是否导出在 `now_ms` 时已过期的 key
+    pub include_expired: bool,
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::String(_) => "string",
+        Value::Hash(_) => "hash",
+        Value::List(_) => "list",
+        Value::Set(_) => "set",
+    }
+}
+
+fn text(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+fn value_json(value: &Value) -> Json {
+    match value {
+        Value::String(s) => Json::String(text(s)),
+        Value::Hash(fields) => Json::Object(fields.iter().map(|(f, v)| (text(f), Json::String(text(v)))).collect::<Map<_, _>>()),
+        Value::List(items) | Value::Set(items) => Json::Array(items.iter().map(|i| Json::String(text(i))).collect()),
+    }
+}
+
+/// CSV 字段：含逗号、引号或换行时加引号，内部的引号写两次
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+/// 按格式写出数据集，返回导出的 key 数
+pub fn write<W: Write>(entries: &[Entry], format: Format, options: &ExportOptions, now_ms: u64, out: &mut W) -> io::Result<usize> {
+    if format == Format::Csv {
+        writeln!(out, "key,type,value,expire_at_ms,ttl_ms")?;
+    }
+
+    let mut count = 0;
+    for entry in entries {
+        if let Some(pattern) = &options.pattern
+            && !glob_match(pattern.as_bytes(), &entry.key)
+        {
+            continue;
+        }
+        if !options.include_expired && entry.expire_at_ms.is_some_and(|ts| ts <= now_ms) {
+            continue;
+        }
+        let ttl_ms = entry.expire_at_ms.map(|ts| ts.saturating_sub(now_ms));
+
+        match format {
+            Format::JsonLines => {
+                let line = json!({
+                    "key": text(&entry.key),
+                    "type": type_name(&entry.value),
+                    "value": value_json(&entry.value),
+                    "expire_at_ms": entry.expire_at_ms,
+                    "ttl_ms": ttl_ms,
+                });
+                writeln!(out, "{}", line)?;
+            }
+            Format::Csv => {
+                let value = match &entry.value {
+                    Value::String(s) => text(s),
+                    other => value_json(other).to_string(),
+                };
+                let opt = |v: Option<u64>| v.map(|v| v.to_string()).unwrap_or_default();
+                writeln!(
+                    out,
+                    "{},{},{},{},{}",
+                    csv_field(&text(&entry.key)),
+                    type_name(&entry.value),
+                    csv_field(&value),
+                    opt(entry.expire_at_ms),
+                    opt(ttl_ms)
+                )?;
+            }
+        }
+        count += 1;
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries() -> Vec<Entry> {
+        vec![
+            Entry { key: "user:1".into(), value: Value::String("a,\"b\"".into()), expire_at_ms: Some(5_000) },
+            Entry {
+                key: "user:h".into(),
+                value: Value::Hash(vec![("f".into(), "1".into())]),
+                expire_at_ms: None,
+            },
+            Entry { key: "old".into(), value: Value::List(vec!["x".into()]), expire_at_ms: Some(500) },
+        ]
+    }
+
+    #[test]
+    fn test_json_lines() {
+        let mut out = Vec::new();
+        let n = write(&entries(), Format::JsonLines, &ExportOptions::default(), 1_000, &mut out).unwrap();
+        assert_eq!(n, 2);
+        let lines: Vec<Json> = String::from_utf8(out).unwrap().lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(lines[0]["ttl_ms"], 4_000);
+        assert_eq!(lines[1], json!({"key": "user:h", "type": "hash", "value": {"f": "1"}, "expire_at_ms": null, "ttl_ms": null}));
+    }
+
+    #[test]
+    fn test_csv_with_filters() {
+        let mut out = Vec::new();
+        let options = ExportOptions { pattern: Some("user:?".into()), include_expired: true };
+        write(&entries(), Format::Csv, &options, 1_000, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "key,type,value,expire_at_ms,ttl_ms\nuser:1,string,\"a,\"\"b\"\"\",5000,4000\nuser:h,hash,\"{\"\"f\"\":\"\"1\"\"}\",,\n"
+        );
+    }
+}
@@ -0,0 +1,1211 @@
+// src/persistence/mod.rs
+
+pub mod aof;
+pub mod dataset;
+pub mod export;
+pub mod rdb;
+pub mod rewrite;
+pub mod snapshot;
+pub mod throttle;
+
+use anyhow::{bail, Context, Result};
+use bytes::Bytes;
+use sled::Db;
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc, Arc, Mutex, RwLockReadGuard,
+    },
+    thread, time::{Duration, Instant, UNIX_EPOCH},
+};
+use crate::{command, config::Config, engine, txn::executor::exec_all};
+use crate::engine::{KvEngine, Storage};
+use crate::monitor::latency::{self, LatencyMonitor};
+use crate::protocol::Frame;
+use aof::{AofError, AofReader, Record};
+use snapshot::SnapshotTracker;
+use throttle::SnapshotThrottle;
+use tracing::{error, info, warn};
+
+/// 持久化器：AOF 日志 + RDB 快照
+pub struct Persistence {
+    pub cfg:     Config,
+    db:      Storage,
+    /// 数据目录，相对路径都解析到这里
+    dir: PathBuf,
+    aof_path: PathBuf,
+    rdb_path: PathBuf,
+    aof_writer: Option<Arc<Mutex<AofFile>>>,
+    /// 已由 RDB 快照覆盖的 AOF 字节数，`load_aof` 从这里开始重放
+    aof_replay_from: AtomicU64,
+    write_count: AtomicU64,
+    /// AOF 重写期间新追加的记录，重写完成时补写到新文件末尾
+    /// （锁顺序：先 aof_writer，再 rewrite_buf）
+    rewrite_buf: Mutex<Option<Vec<Vec<u8>>>>,
+    rewrite_in_progress: AtomicBool,
+    rewrite_last_ok: AtomicBool,
+    rewrite_count: AtomicU64,
+    rewrite_last_secs: AtomicU64,
+    /// RDB 快照进行中（SAVE / BGSAVE / 自动快照互斥）
+    save_in_progress: AtomicBool,
+    save_last_ok: AtomicBool,
+    /// 最后一次成功保存的 unix 时间（秒）
+    last_save: AtomicU64,
+    /// 最近一次成功快照的耗时（毫秒）与 RDB 文件大小（字节）
+    save_last_duration_ms: AtomicU64,
+    save_last_size: AtomicU64,
+    save_failures: AtomicU64,
+    /// 最近一次写 AOF 是否成功，以及累计失败次数
+    aof_last_write_ok: AtomicBool,
+    aof_write_failures: AtomicU64,
+    /// 最近一次 fsync AOF 的耗时（微秒）
+    aof_last_fsync_us: AtomicU64,
+    /// 快照与 AOF 重写期间记录被改动数据的原值
+    snapshots: SnapshotTracker,
+    /// 写入达到阈值时通知快照线程
+    snapshot_trigger: Option<mpsc::SyncSender<()>>,
+    /// 启动时重放 AOF 的统计
+    aof_load: Mutex<AofLoadStatus>,
+    /// 快照、AOF 写入与 fsync 等事件的延迟尖峰（命令的延迟由连接任务记录）
+    latency: LatencyMonitor,
+    /// 写 RDB 快照的限速，以及命令变慢时的暂停
+    throttle: SnapshotThrottle,
+    /// 自动快照的周期（秒），SIGHUP 重新加载配置时可修改
+    snapshot_interval_secs: AtomicU64,
+    /// 只读模式：连接层拒绝全部写命令，CONFIG SET 与 SIGHUP 重新加载配置时可修改
+    read_only: AtomicBool,
+}
+
+/// 打开的 AOF 文件，以及当前长度与全文 CRC64
+///
+/// RDB 快照记录生成时的 (长度, CRC64)，加载时据此确认 AOF 的前缀
+/// 正是快照已包含的部分，只需重放其后的内容
+struct AofFile {
+    file: File,
+    len: u64,
+    crc: u64,
+    /// 最近写入的时间戳注释（unix 秒）
+    last_ts: u64,
+}
+
+impl AofFile {
+    fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let len = file.metadata()?.len();
+        let crc = file_crc64(path, len)?;
+        Ok(AofFile { file, len, crc, last_ts: 0 })
+    }
+
+    fn write_all(&mut self, record: &[u8]) -> std::io::Result<()> {
+        self.file.write_all(record)?;
+        self.len += record.len() as u64;
+        self.crc = rdb::crc64_update(self.crc, record);
+        Ok(())
+    }
+}
+
+/// 计算文件前 `len` 字节的 CRC64
+fn file_crc64(path: &Path, len: u64) -> Result<u64> {
+    let mut reader = BufReader::new(File::open(path)?).take(len);
+    let mut crc = 0;
+    let mut buf = [0u8; 64 * 1024];
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        crc = rdb::crc64_update(crc, &buf[..n]);
+    }
+    Ok(crc)
+}
+
+/// RDB 辅助字段：快照对应的 AOF 长度与 CRC64
+const AUX_AOF_OFFSET: &str = "aof-offset";
+const AUX_AOF_CRC: &str = "aof-crc64";
+
+/// AOF 重写状态，用于 INFO persistence
+#[derive(Debug, Clone, PartialEq)]
+pub struct AofRewriteStatus {
+    pub in_progress: bool,
+    pub last_ok: bool,
+    pub rewrites: u64,
+    pub last_duration_secs: u64,
+}
+
+/// 持久化的运行统计，用于 INFO persistence 与 Prometheus
+#[derive(Debug, Clone, PartialEq)]
+pub struct PersistenceStatus {
+    pub aof_enabled: bool,
+    pub aof_size: u64,
+    pub aof_last_write_ok: bool,
+    pub aof_write_failures: u64,
+    pub aof_last_fsync_us: u64,
+    pub aof_rewrite: AofRewriteStatus,
+    pub rdb_enabled: bool,
+    pub rdb_save_in_progress: bool,
+    pub rdb_last_save: u64,
+    pub rdb_last_save_ok: bool,
+    pub rdb_last_save_duration_ms: u64,
+    pub rdb_last_save_size: u64,
+    pub rdb_save_failures: u64,
+}
+
+/// 启动时重放 AOF 的统计，用于 INFO persistence 与 Prometheus
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AofLoadStatus {
+    /// 重放的命令数（事务中的命令逐条计数）
+    pub commands: u64,
+    /// 读取的字节数（含前导快照）
+    pub bytes: u64,
+    pub duration_ms: u64,
+}
+
+/// 重放 AOF 时定期打印进度与预计剩余时间
+struct LoadProgress {
+    start: Instant,
+    last_report: Instant,
+    from: u64,
+    total: u64,
+}
+
+impl LoadProgress {
+    const INTERVAL: Duration = Duration::from_secs(5);
+
+    fn new(from: u64, total: u64) -> Self {
+        let now = Instant::now();
+        LoadProgress { start: now, last_report: now, from, total }
+    }
+
+    fn tick(&mut self, offset: u64, commands: u64) {
+        if self.last_report.elapsed() < Self::INTERVAL {
+            return;
+        }
+        self.last_report = Instant::now();
+        let done = offset.saturating_sub(self.from);
+        let todo = self.total.saturating_sub(self.from).max(1);
+        let elapsed = self.start.elapsed().as_secs_f64();
+        let eta = elapsed * todo.saturating_sub(done) as f64 / done.max(1) as f64;
+        info!(
+            "AOF: loading {:.1}% ({} of {} bytes, {} commands), ETA {:.0}s",
+            done as f64 * 100.0 / todo as f64,
+            done,
+            todo,
+            commands,
+            eta
+        );
+    }
+}
+
+impl Persistence {
+    /// 在数据目录 `dir` 下打开存储引擎（配置 `storage`）并构造持久化器
+    ///
+    /// `db_path`、`aof_path`、`rdb_path` 为相对路径时都相对于 `dir`（绝对路径保持不变），
+    /// 快照与重写的临时文件和目标文件放在同一目录；`dir` 不存在时自动创建
+    pub fn open(
+        cfg: Config,
+        dir: &Path,
+        db_path: &Path,
+        aof_path: &Path,
+        rdb_path: &Path,
+    ) -> Result<(Storage, Arc<Self>)> {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("failed to create data directory {}", dir.display()))?;
+        let db = Storage::open(&cfg, &dir.join(db_path))?;
+        let pers = Self::build(cfg, db.clone(), dir.to_path_buf(), dir.join(aof_path), dir.join(rdb_path))?;
+        Ok((db, pers))
+    }
+
+    /// 新 API：指定 AOF/RDB 文件路径
+    pub fn new_with_paths(
+        cfg: Config,
+        db: Db,
+        aof_path: PathBuf,
+        rdb_path: PathBuf,
+    ) -> Result<Arc<Self>> {
+        Self::build(cfg, Storage::sled(db)?, PathBuf::new(), aof_path, rdb_path)
+    }
+
+    fn build(
+        cfg: Config,
+        db: Storage,
+        dir: PathBuf,
+        aof_path: PathBuf,
+        rdb_path: PathBuf,
+    ) -> Result<Arc<Self>> {
+        // 打开或创建 AOF
+        let aof_writer = if cfg.aof {
+            Some(Arc::new(Mutex::new(AofFile::open(&aof_path)?)))
+        } else {
+            None
+        };
+
+        let (snapshot_trigger, trigger_rx) = if cfg.rdb {
+            let (tx, rx) = mpsc::sync_channel(1);
+            (Some(tx), Some(rx))
+        } else {
+            (None, None)
+        };
+
+        let pers = Arc::new(Self {
+            cfg: cfg.clone(),
+            db: db.clone(),
+            dir,
+            aof_path,
+            rdb_path: rdb_path.clone(),
+            aof_writer,
+            aof_replay_from: AtomicU64::new(0),
+            write_count: AtomicU64::new(0),
+            rewrite_buf: Mutex::new(None),
+            rewrite_in_progress: AtomicBool::new(false),
+            rewrite_last_ok: AtomicBool::new(true),
+            rewrite_count: AtomicU64::new(0),
+            rewrite_last_secs: AtomicU64::new(0),
+            save_in_progress: AtomicBool::new(false),
+            save_last_ok: AtomicBool::new(true),
+            last_save: AtomicU64::new(file_mtime_secs(&rdb_path)),
+            save_last_duration_ms: AtomicU64::new(0),
+            save_last_size: AtomicU64::new(0),
+            save_failures: AtomicU64::new(0),
+            aof_last_write_ok: AtomicBool::new(true),
+            aof_write_failures: AtomicU64::new(0),
+            aof_last_fsync_us: AtomicU64::new(0),
+            snapshots: SnapshotTracker::new(),
+            snapshot_trigger,
+            aof_load: Mutex::new(AofLoadStatus::default()),
+            latency: LatencyMonitor::new(cfg.latency_monitor_threshold_ms),
+            throttle: SnapshotThrottle::new(
+                cfg.rdb_throttle_mb_per_sec * 1024 * 1024,
+                Duration::from_millis(cfg.rdb_pause_latency_ms),
+            ),
+            snapshot_interval_secs: AtomicU64::new(cfg.snapshot_interval_secs),
+            read_only: AtomicBool::new(cfg.read_only),
+        });
+
+        // RDB 快照线程：定时，或在写入达到阈值时被唤醒
+        if let Some(rx) = trigger_rx {
+            let p = pers.clone();
+            thread::spawn(move || {
+                // 每轮重新读取周期，修改后从下一轮开始生效
+                while !matches!(
+                    rx.recv_timeout(Duration::from_secs(p.snapshot_interval_secs.load(Ordering::Relaxed))),
+                    Err(mpsc::RecvTimeoutError::Disconnected)
+                ) {
+                    p.auto_snapshot();
+                }
+            });
+        }
+
+        Ok(pers)
+    }
+
+    /// 启动时加载 RDB 快照，须在 `load_aof` 之前调用
+    ///
+    /// 快照代表完整的数据集，加载前先清空数据库。开启 AOF 时：
+    /// - AOF 为空：以快照为准，加载后立即重写 AOF，使其重新包含完整数据
+    /// - AOF 的前缀与快照记录的位置一致：之后 `load_aof` 只重放其后的部分
+    /// - 否则（如快照之后 AOF 被重写过）AOF 更新更全，忽略快照
+    pub fn load_rdb(&self) -> Result<()> {
+        if !self.rdb_path.exists() {
+            return Ok(());
+        }
+        let aof_len = self.aof_writer.as_ref().map(|w| w.lock().unwrap().len);
+        let snapshot = match rdb::decode(&std::fs::read(&self.rdb_path)?) {
+            Ok(snapshot) => snapshot,
+            Err(e) if aof_len.is_some_and(|len| len > 0) => {
+                warn!("RDB: ignoring unreadable snapshot, replaying the AOF instead: {}", e);
+                return Ok(());
+            }
+            Err(e) => return Err(e.context(format!("failed to load {}", self.rdb_path.display()))),
+        };
+
+        let replay_from = match aof_len {
+            None | Some(0) => 0,
+            Some(len) => match self.snapshot_aof_offset(&snapshot, len)? {
+                Some(offset) => offset,
+                None => {
+                    warn!("RDB: snapshot does not match the AOF, replaying the full AOF instead");
+                    return Ok(());
+                }
+            },
+        };
+
+        self.restore_snapshot(&snapshot)?;
+        self.aof_replay_from.store(replay_from, Ordering::SeqCst);
+        if aof_len == Some(0) {
+            self.rewrite_aof()?;
+        }
+        Ok(())
+    }
+
+    /// 清空数据库并写入快照中未过期的 key
+    fn restore_snapshot(&self, snapshot: &rdb::Snapshot) -> Result<()> {
+        self.db.clear()?;
+        let now = self.db.clock().now_ms();
+        for entry in &snapshot.entries {
+            if entry.expire_at_ms.is_none_or(|ts| ts > now) {
+                dataset::restore(&self.db, entry)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// 快照记录的 AOF 位置若仍是当前 AOF 的前缀，返回该位置
+    fn snapshot_aof_offset(&self, snapshot: &rdb::Snapshot, aof_len: u64) -> Result<Option<u64>> {
+        let aux = |name| snapshot.aux.get(name).and_then(|v: &String| v.parse::<u64>().ok());
+        let (Some(offset), Some(crc)) = (aux(AUX_AOF_OFFSET), aux(AUX_AOF_CRC)) else {
+            return Ok(None);
+        };
+        if offset > aof_len || file_crc64(&self.aof_path, offset)? != crc {
+            return Ok(None);
+        }
+        Ok(Some(offset))
+    }
+
+    /// 启动时重放 AOF（已由 RDB 快照覆盖的前缀会被跳过）
+    ///
+    /// AOF 以 RDB 前导快照开头时，先加载快照再重放其后的命令；
+    /// `MULTI` 与 `EXEC` 之间的命令作为一个事务原子地重放；
+    /// 文件末尾不完整的记录或缺少 `EXEC` 的事务（写到一半时崩溃）整体丢弃，
+    /// 并把文件截断到最后一条完整记录，避免之后追加的命令接在残缺记录后面
+    pub fn load_aof(&self) -> Result<()> {
+        self.replay_aof(None)
+    }
+
+    /// 按时间点恢复（`--recover-to`）：清空数据库后从头重放 AOF，
+    /// 遇到晚于 `ts`（unix 秒）的时间戳注释即停止，并把 AOF 截断到该处
+    ///
+    /// 需开启 `aof_timestamp_enabled`；AOF 重写会丢弃历史，
+    /// 只能恢复到最近一次重写之后的时间点
+    pub fn recover_to(&self, ts: u64) -> Result<()> {
+        if self.aof_writer.is_none() {
+            bail!("point-in-time recovery requires the AOF to be enabled");
+        }
+        self.db.clear()?;
+        self.aof_replay_from.store(0, Ordering::SeqCst);
+        self.replay_aof(Some(ts))
+    }
+
+    fn replay_aof(&self, stop_after: Option<u64>) -> Result<()> {
+        if let Some(w) = &self.aof_writer && self.aof_path.exists() {
+            let mut base = self.aof_replay_from.load(Ordering::SeqCst);
+            let mut progress = LoadProgress::new(base, w.lock().unwrap().len);
+            let mut commands = 0;
+            let mut f = BufReader::new(File::open(&self.aof_path)?);
+            let reader: Box<dyn BufRead> = if base == 0 && f.fill_buf()?.starts_with(rdb::MAGIC) {
+                // 前导快照代表重写时的完整数据集，整段读入解析后从其后继续重放
+                let mut data = Vec::new();
+                f.read_to_end(&mut data)?;
+                let (snapshot, len) = rdb::decode_prefix(&data)
+                    .with_context(|| format!("bad RDB preamble in {}", self.aof_path.display()))?;
+                self.restore_snapshot(&snapshot)?;
+                data.drain(..len);
+                base = len as u64;
+                Box::new(std::io::Cursor::new(data))
+            } else {
+                f.seek(SeekFrom::Start(base))?;
+                Box::new(f)
+            };
+            // 调小 proto_max_bulk_len 之前写入的参数照常加载
+            let mut reader = AofReader::new(reader).max_bulk_len(self.cfg.proto_max_bulk_len.max(aof::DEFAULT_MAX_BULK_LEN));
+            let mut txn: Option<Vec<Vec<Bytes>>> = None;
+            let mut txn_start = 0;
+            let mut valid_len = None;
+            loop {
+                let record_start = base + reader.offset();
+                let parts = match reader.next_record() {
+                    Ok(Some(Record::Command(parts))) => parts,
+                    Ok(Some(Record::Timestamp(ts))) => {
+                        if stop_after.is_some_and(|limit| ts > limit) {
+                            info!(
+                                "AOF: reached timestamp {}, discarding records from offset {}",
+                                ts, record_start
+                            );
+                            valid_len = Some(record_start);
+                            break;
+                        }
+                        continue;
+                    }
+                    Ok(None) => break,
+                    Err(AofError::Truncated { offset }) => {
+                        warn!("AOF: discarding incomplete record at offset {}", base + offset);
+                        valid_len = Some(base + offset);
+                        break;
+                    }
+                    Err(e) => {
+                        return Err(anyhow::Error::from(e).context(format!(
+                            "failed to load {}, run `crab-cage-check-aof --fix {}` to repair it",
+                            self.aof_path.display(),
+                            self.aof_path.display()
+                        )));
+                    }
+                };
+                match (command::name_upper(&parts[0]).as_str(), txn.as_mut()) {
+                    ("MULTI", _) => {
+                        txn = Some(Vec::new());
+                        txn_start = record_start;
+                    }
+                    ("EXEC", Some(_)) => {
+                        let cmds = txn.take().unwrap_or_default();
+                        if let Frame::Error(e) = exec_all(&self.db, &cmds) {
+                            warn!("AOF: failed to replay transaction: {}", e);
+                        }
+                        commands += cmds.len() as u64;
+                    }
+                    (_, Some(cmds)) => cmds.push(parts),
+                    // 调用 engine 执行业务命令（包括 SET/DEL/EXPIRE/..）
+                    (cmd, None) => {
+                        let _ = engine::execute_non_txn_command(cmd, &parts, &self.db);
+                        commands += 1;
+                    }
+                }
+                progress.tick(base + reader.offset(), commands);
+            }
+            if let Some(cmds) = txn {
+                warn!(
+                    "AOF: discarding incomplete transaction with {} command(s) at end of file",
+                    cmds.len()
+                );
+                valid_len = Some(txn_start);
+            }
+            let status = AofLoadStatus {
+                commands,
+                bytes: valid_len.unwrap_or(base + reader.offset()).saturating_sub(progress.from),
+                duration_ms: progress.start.elapsed().as_millis() as u64,
+            };
+            info!(
+                "AOF: loaded {} commands ({} bytes) in {:.3}s",
+                status.commands,
+                status.bytes,
+                status.duration_ms as f64 / 1000.0
+            );
+            *self.aof_load.lock().unwrap() = status;
+            if let Some(len) = valid_len {
+                let mut current = w.lock().unwrap();
+                OpenOptions::new().write(true).open(&self.aof_path)?.set_len(len)?;
+                current.len = len;
+                current.crc = file_crc64(&self.aof_path, len)?;
+            }
+            self.db.flush()?;
+        }
+        Ok(())
+    }
+
+    /// 执行命令前调用，快照或 AOF 重写进行中时先记下命令涉及的 key 的原值
+    ///
+    /// 返回的守卫须持有到命令执行完、AOF 追加完为止（EXEC 时传入整个事务队列）；
+    /// 持有期间不能发起同步的 SAVE / 重写
+    pub fn write_guard(&self, cmds: &[Vec<Bytes>]) -> RwLockReadGuard<'_, ()> {
+        let keys: Vec<&[u8]> = cmds
+            .iter()
+            .filter_map(|parts| command::lookup(&parts[0]).map(|spec| spec.keys(parts)))
+            .flatten()
+            .collect();
+        self.snapshots.write_guard(&self.db, &keys)
+    }
+
+    /// 写命令后追加 AOF 并触发 RDB
+    pub fn append_aof_and_maybe_snapshot(&self, parts: &[Bytes]) {
+        self.write_aof(&aof::encode(parts));
+        self.maybe_snapshot(1);
+    }
+
+    /// 追加一条完整的 AOF 记录；重写进行中时同时写入缓冲区
+    ///
+    /// 开启 `aof_timestamp_enabled` 时，每进入新的一秒先写一条时间戳注释
+    fn write_aof(&self, record: &[u8]) {
+        if let Some(w) = &self.aof_writer {
+            let mut f = w.lock().unwrap();
+            let mut data = Vec::new();
+            let now = unix_secs();
+            if self.cfg.aof_timestamp_enabled && now > f.last_ts {
+                data.extend(aof::timestamp_annotation(now));
+                f.last_ts = now;
+            }
+            data.extend_from_slice(record);
+            let start = Instant::now();
+            let res = f.write_all(&data);
+            self.latency.record(latency::EVENT_AOF_WRITE, start.elapsed());
+            if let Err(e) = &res {
+                self.aof_write_failures.fetch_add(1, Ordering::Relaxed);
+                error!("AOF write failed: {}", e);
+            }
+            self.aof_last_write_ok.store(res.is_ok(), Ordering::Relaxed);
+            if let Some(buf) = self.rewrite_buf.lock().unwrap().as_mut() {
+                buf.push(data);
+            }
+        }
+    }
+
+    /// EXEC 成功后以 `MULTI ... EXEC` 的形式追加整个事务
+    ///
+    /// 整段记录一次性写入，重放时原子地执行；只记录写命令，
+    /// 没有写命令的事务不落盘
+    pub fn append_transaction(&self, cmds: &[Vec<Bytes>]) {
+        if let Some(record) = aof::encode_transaction(cmds) {
+            self.write_aof(&record);
+            let writes = cmds
+                .iter()
+                .filter(|parts| command::lookup(&parts[0]).is_some_and(|spec| spec.is_write()))
+                .count();
+            self.maybe_snapshot(writes as u64);
+        }
+    }
+
+    /// 累计写命令数，达到阈值时唤醒快照线程
+    fn maybe_snapshot(&self, writes: u64) {
+        if let Some(trigger) = &self.snapshot_trigger {
+            let prev = self.write_count.fetch_add(writes, Ordering::SeqCst);
+            if prev + writes >= self.cfg.snapshot_threshold {
+                self.write_count.store(0, Ordering::SeqCst);
+                // 已有一个待处理的通知时无需重复
+                let _ = trigger.try_send(());
+            }
+        }
+    }
+
+    /// BGREWRITEAOF：在后台线程中重写 AOF，立即返回
+    pub fn bgrewrite_aof(self: &Arc<Self>) -> Result<()> {
+        if self.aof_writer.is_none() {
+            bail!("AOF is disabled");
+        }
+        if self.rewrite_in_progress.swap(true, Ordering::SeqCst) {
+            bail!("Background append only file rewriting already in progress");
+        }
+        let p = self.clone();
+        thread::spawn(move || {
+            if let Err(e) = p.run_rewrite() {
+                error!("AOF rewrite failed: {}", e);
+            }
+        });
+        Ok(())
+    }
+
+    /// 同步重写 AOF
+    pub fn rewrite_aof(&self) -> Result<()> {
+        if self.aof_writer.is_none() {
+            bail!("AOF is disabled");
+        }
+        if self.rewrite_in_progress.swap(true, Ordering::SeqCst) {
+            bail!("Background append only file rewriting already in progress");
+        }
+        self.run_rewrite()
+    }
+
+    /// 执行重写并更新状态，调用前需已置位 rewrite_in_progress
+    fn run_rewrite(&self) -> Result<()> {
+        let start = Instant::now();
+        let result = self.do_rewrite();
+        self.latency.record(latency::EVENT_AOF_REWRITE, start.elapsed());
+        if result.is_err() {
+            self.rewrite_buf.lock().unwrap().take();
+        } else {
+            self.rewrite_count.fetch_add(1, Ordering::Relaxed);
+            self.rewrite_last_secs.store(start.elapsed().as_secs(), Ordering::Relaxed);
+        }
+        self.rewrite_last_ok.store(result.is_ok(), Ordering::Relaxed);
+        self.rewrite_in_progress.store(false, Ordering::SeqCst);
+        result
+    }
+
+    /// 1) 在两条命令之间开始缓冲新写入，同时取得此刻数据集的视图
+    /// 2) 由视图生成最小命令流写入临时文件
+    ///    （开启 `aof_use_rdb_preamble` 时改为写入一份二进制 RDB 快照）
+    /// 3) 持有 AOF 锁补写缓冲区，fsync 后原子替换旧文件
+    fn do_rewrite(&self) -> Result<()> {
+        let Some(w) = &self.aof_writer else {
+            bail!("AOF is disabled");
+        };
+        let view = self.snapshots.begin(&self.db, || {
+            let _f = w.lock().unwrap();
+            *self.rewrite_buf.lock().unwrap() = Some(Vec::new());
+        })?;
+
+        let tmp = self.aof_path.with_extension("rewrite");
+        let mut out = BufWriter::new(File::create(&tmp)?);
+        let (mut len, mut crc) = (0, 0);
+        let mut emit = |record: &[u8]| -> Result<()> {
+            out.write_all(record)?;
+            len += record.len() as u64;
+            crc = rdb::crc64_update(crc, record);
+            Ok(())
+        };
+        if self.cfg.aof_use_rdb_preamble {
+            let snapshot = rdb::Snapshot { entries: dataset::scan(&view)?, ..Default::default() };
+            emit(&rdb::encode(&snapshot))?;
+        } else {
+            for cmd in rewrite::dataset_commands(&view)? {
+                emit(&aof::encode(&cmd))?;
+            }
+        }
+        view.finish()?;
+        let mut f = out.into_inner()?;
+
+        let mut current = w.lock().unwrap();
+        let buffered = self.rewrite_buf.lock().unwrap().take().unwrap_or_default();
+        for record in buffered {
+            f.write_all(&record)?;
+            len += record.len() as u64;
+            crc = rdb::crc64_update(crc, &record);
+        }
+        let fsync_start = Instant::now();
+        f.sync_all()?;
+        self.record_fsync(fsync_start.elapsed());
+        std::fs::rename(&tmp, &self.aof_path)?;
+        let file = OpenOptions::new().append(true).open(&self.aof_path)?;
+        *current = AofFile { file, len, crc, last_ts: 0 };
+        Ok(())
+    }
+
+    /// AOF 重写状态
+    pub fn aof_rewrite_status(&self) -> AofRewriteStatus {
+        AofRewriteStatus {
+            in_progress: self.rewrite_in_progress.load(Ordering::SeqCst),
+            last_ok: self.rewrite_last_ok.load(Ordering::Relaxed),
+            rewrites: self.rewrite_count.load(Ordering::Relaxed),
+            last_duration_secs: self.rewrite_last_secs.load(Ordering::Relaxed),
+        }
+    }
+
+    /// SAVE：同步生成快照
+    pub fn save(&self) -> Result<()> {
+        if self.save_in_progress.swap(true, Ordering::SeqCst) {
+            bail!("Background save already in progress");
+        }
+        self.run_snapshot()
+    }
+
+    /// BGSAVE：在后台线程中生成快照，立即返回
+    pub fn bgsave(self: &Arc<Self>) -> Result<()> {
+        if self.save_in_progress.swap(true, Ordering::SeqCst) {
+            bail!("Background save already in progress");
+        }
+        let p = self.clone();
+        thread::spawn(move || {
+            if let Err(e) = p.run_snapshot() {
+                error!("RDB snapshot failed: {}", e);
+            }
+        });
+        Ok(())
+    }
+
+    /// 定时或写入达到阈值时触发的快照；已有快照在进行时直接跳过
+    fn auto_snapshot(&self) {
+        if !self.save_in_progress.swap(true, Ordering::SeqCst)
+            && let Err(e) = self.run_snapshot()
+        {
+            error!("RDB snapshot failed: {}", e);
+        }
+    }
+
+    /// 执行快照并更新状态，调用前需已置位 save_in_progress
+    fn run_snapshot(&self) -> Result<()> {
+        let start = Instant::now();
+        let result = self.do_snapshot();
+        self.latency.record(latency::EVENT_SNAPSHOT, start.elapsed());
+        match &result {
+            Ok(size) => {
+                self.last_save.store(unix_secs(), Ordering::SeqCst);
+                self.save_last_duration_ms.store(start.elapsed().as_millis() as u64, Ordering::Relaxed);
+                self.save_last_size.store(*size, Ordering::Relaxed);
+            }
+            Err(_) => {
+                self.save_failures.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.save_last_ok.store(result.is_ok(), Ordering::Relaxed);
+        self.save_in_progress.store(false, Ordering::SeqCst);
+        result.map(|_| ())
+    }
+
+    /// 执行一次全量 RDB 快照，返回 RDB 文件的字节数
+    ///
+    /// 在两条命令之间记下 AOF 位置并取得此刻数据集的视图，
+    /// 快照恰好包含该位置之前的全部写入，扫描期间写入照常进行
+    fn do_snapshot(&self) -> Result<u64> {
+        // 确保 sled 数据落盘
+        self.db.flush()?;
+
+        let mut snapshot = rdb::Snapshot::default();
+        let view = self.snapshots.begin(&self.db, || {
+            if let Some(w) = &self.aof_writer {
+                let current = w.lock().unwrap();
+                snapshot.aux.insert(AUX_AOF_OFFSET.into(), current.len.to_string());
+                snapshot.aux.insert(AUX_AOF_CRC.into(), current.crc.to_string());
+            }
+        })?;
+        snapshot.entries = dataset::scan(&view)?;
+        view.finish()?;
+
+        // 写入临时文件
+        let tmp = self.rdb_path.with_extension("tmp");
+        let data = rdb::encode(&snapshot);
+        let mut f = File::create(&tmp)?;
+        let paused = self.throttle.write(&mut f, &data)?;
+        if !paused.is_zero() {
+            info!("RDB snapshot paused {} ms for slow commands", paused.as_millis());
+        }
+        f.sync_all()?;
+
+        // 原子替换
+        std::fs::rename(tmp, &self.rdb_path)?;
+        Ok(data.len() as u64)
+    }
+
+    /// 生成一份当前数据集的 RDB（不落盘），用于副本的全量同步
+    ///
+    /// `at_start` 在两条命令之间执行，RDB 恰好包含此前的全部写入
+    pub fn dump_rdb(&self, at_start: impl FnOnce()) -> Result<Vec<u8>> {
+        let view = self.snapshots.begin(&self.db, at_start)?;
+        let snapshot = rdb::Snapshot { entries: dataset::scan(&view)?, ..Default::default() };
+        view.finish()?;
+        Ok(rdb::encode(&snapshot))
+    }
+
+    /// 用收到的快照替换整个数据集（副本全量同步），开启 AOF 时随即重写 AOF
+    pub fn load_snapshot(&self, snapshot: &rdb::Snapshot) -> Result<()> {
+        self.restore_snapshot(snapshot)?;
+        if self.aof_writer.is_some() {
+            self.rewrite_aof()?;
+        }
+        Ok(())
+    }
+
+    /// 优雅关闭时调用，强制 fsync AOF
+    pub fn fsync_and_close(&self) {
+        if let Some(w) = &self.aof_writer
+            && let Ok(f) = w.lock()
+        {
+            let start = Instant::now();
+            if let Err(e) = f.file.sync_all() {
+                error!("AOF fsync failed: {}", e);
+            }
+            self.record_fsync(start.elapsed());
+        }
+    }
+
+    fn record_fsync(&self, elapsed: Duration) {
+        self.aof_last_fsync_us.store(elapsed.as_micros() as u64, Ordering::Relaxed);
+        self.latency.record(latency::EVENT_AOF_FSYNC, elapsed);
+    }
+
+    /// 启动时重放 AOF 的统计
+    pub fn aof_load_status(&self) -> AofLoadStatus {
+        self.aof_load.lock().unwrap().clone()
+    }
+
+    /// 延迟监控，连接任务也把命令的耗时记到这里
+    pub fn latency(&self) -> &LatencyMonitor {
+        &self.latency
+    }
+
+    /// 记录一条命令的耗时：计入延迟监控，并供写快照时判断是否需要暂停
+    pub fn observe_command(&self, duration: Duration) {
+        self.latency.record(latency::EVENT_COMMAND, duration);
+        self.throttle.observe_command(duration);
+    }
+
+    /// 修改自动快照的周期（秒）
+    pub fn set_snapshot_interval(&self, secs: u64) {
+        self.snapshot_interval_secs.store(secs, Ordering::Relaxed);
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only.load(Ordering::Relaxed)
+    }
+
+    /// 开启或关闭只读模式
+    pub fn set_read_only(&self, read_only: bool) {
+        self.read_only.store(read_only, Ordering::Relaxed);
+    }
+
+    /// 数据目录下的文件路径（如 pid 文件），绝对路径保持不变
+    pub fn data_path(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.dir.join(path)
+    }
+
+    // 获取 AOF 大小
+    pub fn aof_size(&self) -> u64 {
+        if self.aof_path.exists() {
+            std::fs::metadata(&self.aof_path)
+                .map(|m| m.len())
+                .unwrap_or(0)
+        } else {
+            0
+        }
+    }
+
+    // 获取最后一次成功保存 RDB 的 unix 时间（LASTSAVE）
+    pub fn last_save_time(&self) -> u64 {
+        self.last_save.load(Ordering::SeqCst)
+    }
+
+    // 是否有快照正在进行
+    pub fn save_in_progress(&self) -> bool {
+        self.save_in_progress.load(Ordering::SeqCst)
+    }
+
+    // 最近一次快照是否成功
+    pub fn last_save_ok(&self) -> bool {
+        self.save_last_ok.load(Ordering::Relaxed)
+    }
+
+    /// AOF 与 RDB 的运行统计
+    pub fn status(&self) -> PersistenceStatus {
+        PersistenceStatus {
+            aof_enabled: self.aof_writer.is_some(),
+            aof_size: self.aof_size(),
+            aof_last_write_ok: self.aof_last_write_ok.load(Ordering::Relaxed),
+            aof_write_failures: self.aof_write_failures.load(Ordering::Relaxed),
+            aof_last_fsync_us: self.aof_last_fsync_us.load(Ordering::Relaxed),
+            aof_rewrite: self.aof_rewrite_status(),
+            rdb_enabled: self.snapshot_trigger.is_some(),
+            rdb_save_in_progress: self.save_in_progress(),
+            rdb_last_save: self.last_save_time(),
+            rdb_last_save_ok: self.last_save_ok(),
+            rdb_last_save_duration_ms: self.save_last_duration_ms.load(Ordering::Relaxed),
+            rdb_last_save_size: self.save_last_size.load(Ordering::Relaxed),
+            rdb_save_failures: self.save_failures.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// 文件的修改时间（unix 秒），文件不存在时为 0
+fn file_mtime_secs(path: &Path) -> u64 {
+    std::fs::metadata(path)
+        .and_then(|m| m.modified())
+        .map(|t| t.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs())
+        .unwrap_or(0)
+}
+
+fn unix_secs() -> u64 {
+    std::time::SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::string;
+
+    fn make_pers(dir: &std::path::Path) -> Arc<Persistence> {
+        make_pers_with(dir, "")
+    }
+
+    /// `extra` 为追加的配置项，如 `,"aof_use_rdb_preamble":true`
+    fn make_pers_with(dir: &std::path::Path, extra: &str) -> Arc<Persistence> {
+        let cfg: Config = serde_json::from_str(&format!(
+            r#"{{"aof":true,"rdb":false,"snapshot_interval_secs":60,"snapshot_threshold":20,
+                "metrics_enabled":false,"metrics_port":9090,"slowlog_threshold_ms":10{}}}"#,
+            extra
+        ))
+        .unwrap();
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        Persistence::new_with_paths(cfg, db, dir.join("appendonly.aof"), dir.join("dump.rdb")).unwrap()
+    }
+
+    fn cmd(parts: &[&str]) -> Vec<Bytes> {
+        parts.iter().map(|s| Bytes::copy_from_slice(s.as_bytes())).collect()
+    }
+
+    fn read_aof(path: &std::path::Path) -> Result<Vec<Vec<Bytes>>> {
+        let mut reader = AofReader::new(BufReader::new(File::open(path)?));
+        let mut out = Vec::new();
+        while let Some(parts) = reader.next_command()? {
+            out.push(parts);
+        }
+        Ok(out)
+    }
+
+    #[test]
+    fn test_open_resolves_paths_in_data_dir() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let data = tmp.path().join("data");
+        let elsewhere = tmp.path().join("elsewhere.rdb");
+        let cfg = make_pers(tmp.path()).cfg.clone();
+        let (_db, pers) = Persistence::open(
+            cfg,
+            &data,
+            Path::new("kv.db"),
+            Path::new("appendonly.aof"),
+            &elsewhere,
+        )?;
+        pers.append_aof_and_maybe_snapshot(&cmd(&["SET", "a", "1"]));
+        pers.save()?;
+
+        assert!(data.join("kv.db").is_dir());
+        assert_eq!(read_aof(&data.join("appendonly.aof"))?, vec![cmd(&["SET", "a", "1"])]);
+        assert!(elsewhere.exists());
+        assert_eq!(pers.data_path("crab-cage.pid"), data.join("crab-cage.pid"));
+        Ok(())
+    }
+
+    // 内存引擎不创建 sled 目录，重启后数据完全由 AOF 恢复
+    #[test]
+    fn test_memory_storage_recovers_from_aof() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let cfg = make_pers_with(tmp.path(), r#","storage":"memory""#).cfg.clone();
+        let open = || Persistence::open(cfg.clone(), tmp.path(), Path::new("kv.db"), Path::new("appendonly.aof"), Path::new("dump.rdb"));
+
+        let (db, pers) = open()?;
+        let binary = vec![Bytes::from_static(b"SET"), Bytes::from_static(b"\xff k"), Bytes::from_static(b"v\r\n\xfe")];
+        for parts in [cmd(&["SET", "a", "1"]), cmd(&["RPUSH", "l", "x"]), binary] {
+            engine::execute_non_txn_command(&command::name_upper(&parts[0]), &parts, &db);
+            pers.append_aof_and_maybe_snapshot(&parts);
+        }
+        pers.fsync_and_close();
+        assert!(!tmp.path().join("kv.db").exists());
+
+        let (db, pers) = open()?;
+        assert_eq!(string::get(&db, b"a")?, None);
+        pers.load_aof()?;
+        assert_eq!(string::get(&db, b"a")?.as_deref(), Some(&b"1"[..]));
+        assert_eq!(crate::types::list::lrange(&db, b"l", 0, -1)?, vec![b"x".to_vec()]);
+        assert_eq!(string::get(&db, b"\xff k")?.as_deref(), Some(&b"v\r\n\xfe"[..]));
+
+        let mut cfg = cfg.clone();
+        cfg.storage = "rocks".into();
+        let err = Persistence::open(cfg, tmp.path(), Path::new("kv.db"), Path::new("a.aof"), Path::new("d.rdb")).err().unwrap();
+        assert!(err.to_string().contains("unknown storage 'rocks'"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_transaction_replay() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let pers = make_pers(dir.path());
+        let path = dir.path().join("appendonly.aof");
+        pers.append_aof_and_maybe_snapshot(&cmd(&["SET", "a", "1"]));
+        pers.append_transaction(&[cmd(&["SET", "b", "x y\nz"]), cmd(&["GET", "a"]), cmd(&["INCR", "a"])]);
+        pers.fsync_and_close();
+
+        assert_eq!(read_aof(&path)?, vec![
+            cmd(&["SET", "a", "1"]),
+            cmd(&["MULTI"]),
+            cmd(&["SET", "b", "x y\nz"]),
+            cmd(&["INCR", "a"]),
+            cmd(&["EXEC"]),
+        ]);
+        let complete_len = std::fs::metadata(&path)?.len();
+
+        // 模拟写到一半崩溃：末尾的事务缺少 EXEC
+        let mut f = OpenOptions::new().append(true).open(&path)?;
+        f.write_all(&aof::encode(&cmd(&["MULTI"])))?;
+        f.write_all(&aof::encode(&cmd(&["SET", "c", "3"])))?;
+
+        let replayed = make_pers(dir.path());
+        replayed.load_aof()?;
+        assert_eq!(string::get(&replayed.db, b"a")?.as_deref(), Some(&b"2"[..]));
+        assert_eq!(string::get(&replayed.db, b"b")?.as_deref(), Some(&b"x y\nz"[..]));
+        assert_eq!(string::get(&replayed.db, b"c")?, None);
+        // 残缺的事务已从文件中截掉
+        assert_eq!(std::fs::metadata(&path)?.len(), complete_len);
+        let status = replayed.aof_load_status();
+        assert_eq!((status.commands, status.bytes), (3, complete_len));
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_legacy_and_truncated_aof() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("appendonly.aof");
+        let mut data = b"SET a 1\nMULTI\nINCR a\nEXEC\n".to_vec();
+        data.extend(aof::encode(&cmd(&["SET", "b", "2 3"])));
+        let complete_len = data.len() as u64;
+        data.extend(b"*3\r\n$3\r\nSET\r\n$1\r\nc");
+        std::fs::write(&path, &data)?;
+
+        let pers = make_pers(dir.path());
+        pers.load_aof()?;
+        assert_eq!(string::get(&pers.db, b"a")?.as_deref(), Some(&b"2"[..]));
+        assert_eq!(string::get(&pers.db, b"b")?.as_deref(), Some(&b"2 3"[..]));
+        assert_eq!(std::fs::metadata(&path)?.len(), complete_len);
+        Ok(())
+    }
+
+    #[test]
+    fn test_snapshot_is_binary_rdb() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let pers = make_pers(dir.path());
+        string::set(&pers.db, b"a", b"1 2")?;
+        crate::types::list::rpush(&pers.db, b"l", b"x")?;
+        pers.do_snapshot()?;
+
+        let data = std::fs::read(dir.path().join("dump.rdb"))?;
+        assert!(data.starts_with(rdb::MAGIC));
+        let snapshot = rdb::decode(&data)?;
+        assert_eq!(snapshot.entries, dataset::scan(&pers.db)?);
+        assert_eq!(snapshot.entries.len(), 2);
+        assert_eq!(snapshot.aux[AUX_AOF_OFFSET], "0");
+        Ok(())
+    }
+
+    #[test]
+    fn test_save_updates_lastsave() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let pers = make_pers(dir.path());
+        assert_eq!(pers.last_save_time(), 0);
+        pers.save()?;
+        assert!(pers.last_save_time() > 0);
+        assert!(dir.path().join("dump.rdb").exists());
+        let status = pers.status();
+        assert_eq!(status.rdb_last_save_size, std::fs::metadata(dir.path().join("dump.rdb"))?.len());
+        assert_eq!(status.rdb_save_failures, 0);
+
+        // 临时文件无法创建时快照失败，计入失败次数，上次成功快照的统计保持不变
+        std::fs::create_dir(dir.path().join("dump.tmp"))?;
+        assert!(pers.save().is_err());
+        let failed = pers.status();
+        assert_eq!((failed.rdb_save_failures, failed.rdb_last_save_ok), (1, false));
+        assert_eq!(failed.rdb_last_save_size, status.rdb_last_save_size);
+
+        // 同一时间只允许一个快照
+        pers.save_in_progress.store(true, Ordering::SeqCst);
+        assert!(pers.save().is_err());
+        assert!(pers.bgsave().is_err());
+        Ok(())
+    }
+
+    /// 执行命令并写入 AOF
+    fn run(pers: &Persistence, raw: &str) {
+        let parts = cmd(&raw.split(' ').collect::<Vec<_>>());
+        engine::execute_non_txn_command(&command::name_upper(&parts[0]), &parts, &pers.db);
+        pers.append_aof_and_maybe_snapshot(&parts);
+    }
+
+    fn reload(dir: &std::path::Path) -> Result<Arc<Persistence>> {
+        let pers = make_pers(dir);
+        pers.load_rdb()?;
+        pers.load_aof()?;
+        Ok(pers)
+    }
+
+    #[test]
+    fn test_load_rdb_then_aof_tail() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let pers = make_pers(dir.path());
+        for raw in ["SET a 1", "INCR a", "RPUSH l x"] {
+            run(&pers, raw);
+        }
+        pers.do_snapshot()?;
+        for raw in ["INCR a", "RPUSH l y"] {
+            run(&pers, raw);
+        }
+        pers.fsync_and_close();
+
+        // 快照之后只重放 AOF 尾部，INCR / RPUSH 不会被重复执行
+        let replayed = reload(dir.path())?;
+        assert_eq!(string::get(&replayed.db, b"a")?.as_deref(), Some(&b"3"[..]));
+        assert_eq!(crate::types::list::lrange(&replayed.db, b"l", 0, -1)?, vec![b"x".to_vec(), b"y".to_vec()]);
+
+        // AOF 重写后快照记录的位置失效，改为完整重放新的 AOF
+        replayed.rewrite_aof()?;
+        run(&replayed, "INCR a");
+        replayed.fsync_and_close();
+        let replayed = reload(dir.path())?;
+        assert_eq!(string::get(&replayed.db, b"a")?.as_deref(), Some(&b"4"[..]));
+        assert_eq!(crate::types::list::lrange(&replayed.db, b"l", 0, -1)?, vec![b"x".to_vec(), b"y".to_vec()]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_rdb_without_aof_seeds_aof() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let pers = make_pers(dir.path());
+        run(&pers, "SET a 1");
+        pers.do_snapshot()?;
+        std::fs::remove_file(dir.path().join("appendonly.aof"))?;
+
+        // AOF 丢失时以快照为准，并重新生成包含完整数据的 AOF
+        let replayed = reload(dir.path())?;
+        assert_eq!(string::get(&replayed.db, b"a")?.as_deref(), Some(&b"1"[..]));
+        assert_eq!(read_aof(&dir.path().join("appendonly.aof"))?, vec![cmd(&["SET", "a", "1"])]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_rewrite_with_rdb_preamble() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("appendonly.aof");
+        let pers = make_pers_with(dir.path(), r#","aof_use_rdb_preamble":true"#);
+        for raw in ["SET a 1", "INCR a", "RPUSH l x"] {
+            run(&pers, raw);
+        }
+        pers.rewrite_aof()?;
+        run(&pers, "INCR a");
+        run(&pers, "RPUSH l y");
+        pers.fsync_and_close();
+        assert!(std::fs::read(&path)?.starts_with(rdb::MAGIC));
+
+        // 加载前导快照后只重放其后的增量命令
+        let replayed = make_pers(dir.path());
+        replayed.load_aof()?;
+        assert_eq!(string::get(&replayed.db, b"a")?.as_deref(), Some(&b"3"[..]));
+        assert_eq!(crate::types::list::lrange(&replayed.db, b"l", 0, -1)?, vec![b"x".to_vec(), b"y".to_vec()]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_recover_to_timestamp() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("appendonly.aof");
+        let mut data = Vec::new();
+        for (ts, raw) in [(100, "SET a 1"), (200, "INCR a"), (300, "SET b 3")] {
+            data.extend(aof::timestamp_annotation(ts));
+            data.extend(aof::encode(&cmd(&raw.split(' ').collect::<Vec<_>>())));
+        }
+        std::fs::write(&path, &data)?;
+        let cut = data.windows(7).position(|w| w == b"#TS:300").unwrap() as u64;
+
+        let pers = make_pers(dir.path());
+        string::set(&pers.db, b"stale", b"x")?;
+        pers.recover_to(250)?;
+        assert_eq!(string::get(&pers.db, b"a")?.as_deref(), Some(&b"2"[..]));
+        assert_eq!(string::get(&pers.db, b"b")?, None);
+        assert_eq!(string::get(&pers.db, b"stale")?, None);
+        assert_eq!(std::fs::metadata(&path)?.len(), cut);
+        Ok(())
+    }
+
+    #[test]
+    fn test_timestamp_annotations_written() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let pers = make_pers_with(dir.path(), r#","aof_timestamp_enabled":true"#);
+        run(&pers, "SET a 1");
+        run(&pers, "SET b 2");
+        pers.fsync_and_close();
+
+        let data = std::fs::read(dir.path().join("appendonly.aof"))?;
+        assert!(data.starts_with(b"#TS:"));
+        let mut reader = AofReader::new(&data[..]);
+        let mut timestamps = 0;
+        while let Some(record) = reader.next_record()? {
+            timestamps += matches!(record, Record::Timestamp(_)) as usize;
+        }
+        // 同一秒内只写一次（跨秒时最多两次）
+        assert!((1..=2).contains(&timestamps));
+        Ok(())
+    }
+
+    #[test]
+    fn test_rewrite_aof() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let pers = make_pers(dir.path());
+        for raw in ["SET a 1", "SET a 2", "INCR a", "SET b x", "DEL b"] {
+            run(&pers, raw);
+        }
+
+        pers.rewrite_aof()?;
+        let status = pers.aof_rewrite_status();
+        assert!(!status.in_progress && status.last_ok);
+        assert_eq!(status.rewrites, 1);
+
+        // 重写后继续追加到新文件
+        pers.append_aof_and_maybe_snapshot(&cmd(&["SET", "c", "3"]));
+        pers.fsync_and_close();
+        let aof = read_aof(&dir.path().join("appendonly.aof"))?;
+        assert_eq!(aof, vec![cmd(&["SET", "a", "3"]), cmd(&["SET", "c", "3"])]);
+        assert!(!dir.path().join("appendonly.rewrite").exists());
+        Ok(())
+    }
+}
@@ -0,0 +1,287 @@
+// src/persistence/rdb.rs
+
+//! RDB 快照文件格式
+//!
+//! ```text
+//! "CRABRDB" 版本号(1 字节)
+//! 辅助字段：0xFA 名称 值（如快照对应的 AOF 位置）
+//! 每个 key：[0xFC 过期时间(u64 LE，unix 毫秒)] 类型(1 字节) key 值
+//! 0xFF CRC64(u64 LE，覆盖之前的全部字节)
+//! ```
+//!
+//! 字符串以 varint 长度为前缀，按原样的字节保存；hash / list / set 先写元素个数再依次写元素。
+//! 类型编号与 Redis 相同，CRC64 使用与 Redis 相同的 Jones 多项式。
+
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Context, Result};
+
+use super::dataset::{Entry, Value};
+
+pub const MAGIC: &[u8] = b"CRABRDB";
+pub const VERSION: u8 = 1;
+
+const TYPE_STRING: u8 = 0;
+const TYPE_LIST: u8 = 1;
+const TYPE_SET: u8 = 2;
+const TYPE_HASH: u8 = 4;
+const OP_AUX: u8 = 0xFA;
+const OP_EXPIRE_MS: u8 = 0xFC;
+const OP_EOF: u8 = 0xFF;
+
+/// 一个 RDB 文件的内容
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Snapshot {
+    /// 辅助字段
+    pub aux: BTreeMap<String, String>,
+    pub entries: Vec<Entry>,
+}
+
+/// 把快照编码为 RDB 文件内容
+pub fn encode(snapshot: &Snapshot) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    out.push(VERSION);
+    for (name, value) in &snapshot.aux {
+        out.push(OP_AUX);
+        put_str(&mut out, name.as_bytes());
+        put_str(&mut out, value.as_bytes());
+    }
+    for entry in &snapshot.entries {
+        if let Some(ts) = entry.expire_at_ms {
+            out.push(OP_EXPIRE_MS);
+            out.extend_from_slice(&ts.to_le_bytes());
+        }
+        let type_tag = match &entry.value {
+            Value::String(_) => TYPE_STRING,
+            Value::List(_) => TYPE_LIST,
+            Value::Set(_) => TYPE_SET,
+            Value::Hash(_) => TYPE_HASH,
+        };
+        out.push(type_tag);
+        put_str(&mut out, &entry.key);
+        match &entry.value {
+            Value::String(v) => put_str(&mut out, v),
+            Value::List(items) | Value::Set(items) => {
+                put_len(&mut out, items.len() as u64);
+                for item in items {
+                    put_str(&mut out, item);
+                }
+            }
+            Value::Hash(fields) => {
+                put_len(&mut out, fields.len() as u64);
+                for (f, v) in fields {
+                    put_str(&mut out, f);
+                    put_str(&mut out, v);
+                }
+            }
+        }
+    }
+    out.push(OP_EOF);
+    let crc = crc64(&out);
+    out.extend_from_slice(&crc.to_le_bytes());
+    out
+}
+
+/// 解析 RDB 文件内容，任何损坏都返回错误
+pub fn decode(data: &[u8]) -> Result<Snapshot> {
+    let (snapshot, len) = decode_prefix(data)?;
+    if len != data.len() {
+        bail!("unexpected data after RDB EOF marker");
+    }
+    Ok(snapshot)
+}
+
+/// 解析位于 `data` 开头的一份 RDB（如 AOF 的前导快照），
+/// 返回快照与其占用的字节数
+pub fn decode_prefix(data: &[u8]) -> Result<(Snapshot, usize)> {
+    if !data.starts_with(MAGIC) {
+        bail!("not a crab-cage RDB file");
+    }
+    let mut r = Reader { data, pos: MAGIC.len() };
+    let version = r.byte()?;
+    if version != VERSION {
+        bail!("unsupported RDB version {}", version);
+    }
+
+    let mut snapshot = Snapshot::default();
+    let mut expire_at_ms = None;
+    loop {
+        match r.byte()? {
+            OP_EOF => break,
+            OP_AUX => {
+                let name = r.text()?;
+                snapshot.aux.insert(name, r.text()?);
+            }
+            OP_EXPIRE_MS => {
+                expire_at_ms = Some(u64::from_le_bytes(r.take(8)?.try_into().unwrap()));
+            }
+            type_tag => {
+                let key = r.string()?;
+                let value = match type_tag {
+                    TYPE_STRING => Value::String(r.string()?),
+                    TYPE_LIST => Value::List(r.strings()?),
+                    TYPE_SET => Value::Set(r.strings()?),
+                    TYPE_HASH => {
+                        let n = r.len()?;
+                        let mut fields = Vec::new();
+                        for _ in 0..n {
+                            fields.push((r.string()?, r.string()?));
+                        }
+                        Value::Hash(fields)
+                    }
+                    other => bail!("unknown RDB value type {} at offset {}", other, r.pos - 1),
+                };
+                snapshot.entries.push(Entry { key, value, expire_at_ms: expire_at_ms.take() });
+            }
+        }
+    }
+
+    let body_len = r.pos;
+    let expected = u64::from_le_bytes(r.take(8)?.try_into().unwrap());
+    if crc64(&data[..body_len]) != expected {
+        bail!("RDB checksum mismatch");
+    }
+    Ok((snapshot, r.pos))
+}
+
+/// varint（LEB128）编码的长度
+fn put_len(out: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        out.push((n as u8) | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+fn put_str(out: &mut Vec<u8>, s: &[u8]) {
+    put_len(out, s.len() as u64);
+    out.extend_from_slice(s);
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, n: usize) -> Result<&[u8]> {
+        let end = self.pos.checked_add(n).filter(|end| *end <= self.data.len());
+        let Some(end) = end else {
+            bail!("unexpected end of RDB file at offset {}", self.pos);
+        };
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn len(&mut self) -> Result<usize> {
+        let mut n: u64 = 0;
+        for shift in (0..64).step_by(7) {
+            let b = self.byte()?;
+            n |= u64::from(b & 0x7f) << shift;
+            if b & 0x80 == 0 {
+                return usize::try_from(n).context("RDB length out of range");
+            }
+        }
+        bail!("invalid length encoding at offset {}", self.pos)
+    }
+
+    fn string(&mut self) -> Result<Vec<u8>> {
+        let n = self.len()?;
+        Ok(self.take(n)?.to_vec())
+    }
+
+    /// 辅助字段是文本
+    fn text(&mut self) -> Result<String> {
+        String::from_utf8(self.string()?).context("non-utf8 aux field in RDB file")
+    }
+
+    fn strings(&mut self) -> Result<Vec<Vec<u8>>> {
+        let n = self.len()?;
+        (0..n).map(|_| self.string()).collect()
+    }
+}
+
+/// CRC-64/Jones（反射输入输出，初值 0），与 Redis 的 crc64 一致
+pub fn crc64(data: &[u8]) -> u64 {
+    crc64_update(0, data)
+}
+
+/// 在 `crc` 的基础上继续累加 `data`，用于分段计算
+pub fn crc64_update(mut crc: u64, data: &[u8]) -> u64 {
+    for &b in data {
+        crc = CRC64_TABLE[((crc ^ u64::from(b)) & 0xff) as usize] ^ (crc >> 8);
+    }
+    crc
+}
+
+const CRC64_TABLE: [u64; 256] = {
+    // 0xad93d23594c935a9 的位反转形式
+    const POLY: u64 = 0x95ac_9329_ac4b_c9b5;
+    let mut table = [0u64; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u64;
+        let mut j = 0;
+        while j < 8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ POLY } else { crc >> 1 };
+            j += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Snapshot {
+        let aux = BTreeMap::from([("aof-offset".to_string(), "42".to_string())]);
+        let entries = vec![
+            Entry { key: "h".into(), value: Value::Hash(vec![("f".into(), "v v".into())]), expire_at_ms: None },
+            Entry { key: "l".into(), value: Value::List(vec!["a".into(), "".into()]), expire_at_ms: Some(1 << 40) },
+            Entry { key: "s".into(), value: Value::String("x".repeat(300).into()), expire_at_ms: None },
+            Entry { key: "st".into(), value: Value::Set(vec!["m".into()]), expire_at_ms: None },
+            Entry { key: b"\xff\x00".to_vec(), value: Value::String(b"\x80\r\n".to_vec()), expire_at_ms: None },
+        ];
+        Snapshot { aux, entries }
+    }
+
+    #[test]
+    fn test_crc64_check_value() {
+        assert_eq!(crc64(b"123456789"), 0xe9c6_d914_c4b8_d9ca);
+        assert_eq!(crc64_update(crc64(b"1234"), b"56789"), crc64(b"123456789"));
+    }
+
+    #[test]
+    fn test_round_trip() -> Result<()> {
+        let data = encode(&sample());
+        assert!(data.starts_with(b"CRABRDB\x01"));
+        assert_eq!(decode(&data)?, sample());
+        assert_eq!(decode(&encode(&Snapshot::default()))?, Snapshot::default());
+        Ok(())
+    }
+
+    #[test]
+    fn test_detects_corruption() {
+        let data = encode(&sample());
+        for pos in [3, MAGIC.len(), 20, data.len() - 1] {
+            let mut bad = data.clone();
+            bad[pos] ^= 0x01;
+            assert!(decode(&bad).is_err(), "flipped byte {} not detected", pos);
+        }
+        assert!(decode(&data[..data.len() - 3]).is_err());
+        let mut trailing = data.clone();
+        trailing.extend(b"*1\r\n");
+        assert!(decode(&trailing).is_err());
+        assert_eq!(decode_prefix(&trailing).unwrap(), (sample(), data.len()));
+        assert!(decode(b"1 1 61 62\n").is_err());
+    }
+}
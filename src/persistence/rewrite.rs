@@ -0,0 +1,96 @@
+// src/persistence/rewrite.rs
+
+//! AOF 重写：由当前数据集生成一份最小的命令流
+//!
+//! - string → `SET key value`
+//! - hash   → 每个 field 一条 `HSET key field value`
+//! - list   → 按顺序 `RPUSH key value`
+//! - set    → 每个成员一条 `SADD key member`
+//! - 过期时间 → `PEXPIREAT key unix-ms`，已过期的 key 直接跳过
+
+use anyhow::Result;
+use bytes::Bytes;
+
+use super::dataset::{self, Value};
+use crate::engine::KvEngine;
+
+/// 扫描整个数据集，返回重建它所需的命令
+pub fn dataset_commands<E: KvEngine>(db: &E) -> Result<Vec<Vec<Bytes>>> {
+    let mut out = Vec::new();
+    for entry in dataset::scan(db)? {
+        let key = Bytes::from(entry.key);
+        let cmd = |name: &'static str, args: &[&[u8]]| {
+            let mut parts = vec![Bytes::from_static(name.as_bytes()), key.clone()];
+            parts.extend(args.iter().map(|a| Bytes::copy_from_slice(a)));
+            parts
+        };
+        match entry.value {
+            Value::String(v) => out.push(cmd("SET", &[&v])),
+            Value::Hash(fields) => {
+                out.extend(fields.iter().map(|(f, v)| cmd("HSET", &[f, v])));
+            }
+            Value::List(items) => out.extend(items.iter().map(|v| cmd("RPUSH", &[v]))),
+            Value::Set(members) => out.extend(members.iter().map(|m| cmd("SADD", &[m]))),
+        }
+        if let Some(ts) = entry.expire_at_ms {
+            out.push(cmd("PEXPIREAT", &[ts.to_string().as_bytes()]));
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expire;
+    use crate::types::{hash, list, set, string};
+    use sled::Config;
+
+    fn make_db() -> sled::Db {
+        Config::new()
+            .temporary(true)
+            .open()
+            .expect("打开临时 sled db 失败")
+    }
+
+    fn line(cmd: &[Bytes]) -> String {
+        cmd.iter().map(|p| String::from_utf8_lossy(p)).collect::<Vec<_>>().join(" ")
+    }
+
+    #[test]
+    fn test_dataset_commands_round_trip() -> Result<()> {
+        let db = make_db();
+        string::set(&db, b"s", b"v")?;
+        string::incr(&db, b"n")?;
+        hash::hset(&db, b"h", b"f:1", b"x")?;
+        list::rpush(&db, b"l", b"b")?;
+        list::lpush(&db, b"l", b"a")?;
+        set::sadd(&db, b"st", b"m")?;
+        expire::expire(&db, b"s", 100)?;
+        string::set(&db, b"gone", b"v")?;
+        expire::expire_at(&db, b"gone", 1)?;
+
+        let cmds = dataset_commands(&db)?;
+        let lines: Vec<String> = cmds.iter().map(|c| line(c)).collect();
+        assert_eq!(&lines[..5], [
+            "HSET h f:1 x",
+            "RPUSH l a",
+            "RPUSH l b",
+            "SET n 1",
+            "SET s v",
+        ]);
+        assert!(lines[5].starts_with("PEXPIREAT s "));
+        assert_eq!(lines[6], "SADD st m");
+        assert_eq!(lines.len(), 7);
+
+        // 在新库中重放得到相同的数据
+        let replica = make_db();
+        for c in &cmds {
+            crate::engine::execute_non_txn_command(&crate::command::name_upper(&c[0]), c, &replica);
+        }
+        assert_eq!(list::lrange(&replica, b"l", 0, -1)?, vec![b"a".to_vec(), b"b".to_vec()]);
+        assert_eq!(hash::hget(&replica, b"h", b"f:1")?.as_deref(), Some(&b"x"[..]));
+        assert_eq!(line(&dataset_commands(&replica)?[0]), "HSET h f:1 x");
+        Ok(())
+    }
+}
@@ -0,0 +1,271 @@
+// src/persistence/snapshot.rs
+
+//! 时间点一致的数据集视图（写时复制）
+//!
+//! sled 的迭代器不是时间点一致的：边扫描边写入会得到一份"半新半旧"的数据集。
+//! 快照开始后，每条命令执行前先记下它涉及的 key 的全部底层记录的原值
+//! （同一条记录只记第一次），扫描时用记下的原值替换已被改动的记录，
+//! 得到快照开始那一刻的数据集，写入方无需暂停。
+//!
+//! 服务器在执行命令并追加 AOF 期间持有 `write_guard` 返回的读锁；开始快照时
+//! 短暂持有写锁，使快照的起点恰好落在两条命令之间，与记录下的 AOF 位置、
+//! 重写缓冲区的起点严格对应。
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard, RwLock, RwLockReadGuard};
+
+use anyhow::{bail, Error, Result};
+use sled::transaction::{ConflictableTransactionResult, TransactionError};
+use sled::IVec;
+
+use crate::clock::Clock;
+use crate::engine::kv::StorageTxn;
+use crate::engine::{KvEngine, Storage};
+use crate::keys::{self, Kind};
+
+/// 记录快照期间被改动的数据
+#[derive(Default)]
+pub struct SnapshotTracker {
+    gate: RwLock<()>,
+    /// 同一时间只进行一个快照，RDB 快照与 AOF 重写互相等待
+    session: Mutex<()>,
+    active: AtomicBool,
+    preimages: Mutex<Preimages>,
+}
+
+#[derive(Default)]
+struct Preimages {
+    /// 底层记录在快照开始时的值，`None` 表示当时不存在
+    records: HashMap<Vec<u8>, Option<IVec>>,
+    /// 已整段记下的前缀：其下不在 `records` 中的记录在快照开始时不存在
+    prefixes: Vec<Vec<u8>>,
+    /// 记录原值失败时快照不再可信
+    error: Option<String>,
+}
+
+impl Preimages {
+    /// 记录在快照开始时的值；`None` 表示未被改动，以当前值为准
+    fn lookup(&self, record: &[u8]) -> Option<Option<IVec>> {
+        if let Some(v) = self.records.get(record) {
+            return Some(v.clone());
+        }
+        self.covered(record).then_some(None)
+    }
+
+    fn covered(&self, record: &[u8]) -> bool {
+        self.prefixes.iter().any(|p| record.starts_with(p))
+    }
+
+    /// 记下逻辑 key 的全部底层记录（与 `expire::remove_key` 覆盖的范围一致）
+    fn capture_key(&mut self, db: &Storage, key: &[u8]) -> Result<()> {
+        for record in [keys::string(key), keys::expire(key)] {
+            if !self.records.contains_key(&record) && !self.covered(&record) {
+                let old = db.get(&record)?;
+                self.records.insert(record, old);
+            }
+        }
+        for kind in [Kind::Hash, Kind::ListData, Kind::ListMeta, Kind::Set] {
+            let prefix = keys::prefix(kind, key);
+            if self.covered(&prefix) {
+                continue;
+            }
+            for item in db.scan_prefix(&prefix) {
+                let (k, v) = item?;
+                self.records.entry(k.to_vec()).or_insert(Some(v));
+            }
+            self.prefixes.push(prefix);
+        }
+        Ok(())
+    }
+}
+
+impl SnapshotTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 执行命令前调用：快照进行中时先记下 `keys` 的原值
+    ///
+    /// 返回的读锁须一直持有到命令执行完、AOF 追加完为止
+    pub fn write_guard(&self, db: &Storage, keys: &[&[u8]]) -> RwLockReadGuard<'_, ()> {
+        let gate = self.gate.read().unwrap_or_else(|e| e.into_inner());
+        if self.active.load(Ordering::SeqCst) {
+            let mut preimages = self.preimages.lock().unwrap();
+            let result = keys.iter().try_for_each(|key| preimages.capture_key(db, key));
+            if let Err(e) = result {
+                preimages.error.get_or_insert(e.to_string());
+            }
+        }
+        gate
+    }
+
+    /// 开始一次快照：在没有命令执行的瞬间调用 `at_start`（如记录 AOF 位置），
+    /// 返回快照开始时数据集的只读视图；视图被丢弃时快照结束
+    pub fn begin<'a>(&'a self, db: &Storage, at_start: impl FnOnce()) -> Result<SnapshotView<'a>> {
+        let session = self.session.lock().unwrap_or_else(|e| e.into_inner());
+        {
+            let _gate = self.gate.write().unwrap_or_else(|e| e.into_inner());
+            *self.preimages.lock().unwrap() = Preimages::default();
+            self.active.store(true, Ordering::SeqCst);
+            at_start();
+        }
+        Ok(SnapshotView { tracker: self, db: db.clone(), _session: session })
+    }
+}
+
+/// 快照开始时的数据集，只支持读取
+pub struct SnapshotView<'a> {
+    tracker: &'a SnapshotTracker,
+    db: Storage,
+    _session: MutexGuard<'a, ()>,
+}
+
+impl SnapshotView<'_> {
+    /// 扫描完成后确认期间没有丢失原值
+    pub fn finish(self) -> Result<()> {
+        if let Some(e) = self.tracker.preimages.lock().unwrap().error.take() {
+            bail!("failed to record data changed during the snapshot: {}", e);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for SnapshotView<'_> {
+    fn drop(&mut self) {
+        self.tracker.active.store(false, Ordering::SeqCst);
+        *self.tracker.preimages.lock().unwrap() = Preimages::default();
+    }
+}
+
+// 先读当前值、再查原值：若查不到原值，说明对应的写入尚未开始，读到的当前值就是快照开始时的值
+impl KvEngine for SnapshotView<'_> {
+    type Txn = StorageTxn;
+
+    fn get(&self, key: &[u8]) -> Result<Option<IVec>, Error> {
+        let live = self.db.get(key)?;
+        Ok(self.tracker.preimages.lock().unwrap().lookup(key).unwrap_or(live))
+    }
+
+    fn insert(&self, _key: &[u8], _value: &[u8]) -> Result<Option<IVec>, Error> {
+        bail!("snapshot view is read-only")
+    }
+
+    fn remove(&self, _key: &[u8]) -> Result<Option<IVec>, Error> {
+        bail!("snapshot view is read-only")
+    }
+
+    fn scan_prefix(&self, prefix: &[u8]) -> Box<dyn Iterator<Item = Result<(IVec, IVec), Error>>> {
+        let mut live = Vec::new();
+        for item in self.db.scan_prefix(prefix) {
+            match item {
+                Ok(kv) => live.push(kv),
+                Err(e) => return Box::new(std::iter::once(Err(e))),
+            }
+        }
+
+        let preimages = self.tracker.preimages.lock().unwrap();
+        let mut out = BTreeMap::new();
+        for (k, v) in live {
+            if let Some(v) = preimages.lookup(&k).unwrap_or(Some(v)) {
+                out.insert(k, v);
+            }
+        }
+        // 快照开始后被删除的记录
+        for (k, v) in &preimages.records {
+            if let Some(v) = v
+                && k.starts_with(prefix)
+            {
+                out.entry(IVec::from(k.as_slice())).or_insert_with(|| v.clone());
+            }
+        }
+        Box::new(out.into_iter().map(Ok))
+    }
+
+    fn transaction<T, F>(&self, _f: F) -> Result<T, TransactionError<Error>>
+    where
+        F: Fn(&StorageTxn) -> ConflictableTransactionResult<T, Error>,
+    {
+        Err(TransactionError::Abort(Error::msg("snapshot view is read-only")))
+    }
+
+    fn clock(&self) -> &dyn Clock {
+        self.db.clock()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use crate::engine::execute_non_txn_command;
+    use crate::persistence::dataset;
+    use crate::txn::executor::exec_all;
+    use crate::types::{list, string};
+
+    fn cmd(parts: &[&str]) -> Vec<Bytes> {
+        parts.iter().map(|s| Bytes::copy_from_slice(s.as_bytes())).collect()
+    }
+
+    /// 模拟服务器：持有写锁执行一条命令
+    fn run(tracker: &SnapshotTracker, db: &Storage, parts: &[&str]) {
+        let parts = cmd(parts);
+        let _gate = tracker.write_guard(db, &[&parts[1]]);
+        execute_non_txn_command(&crate::command::name_upper(&parts[0]), &parts, db);
+    }
+
+    #[test]
+    fn test_view_ignores_writes_after_begin() -> Result<()> {
+        let db = Storage::sled(sled::Config::new().temporary(true).open()?)?;
+        let tracker = SnapshotTracker::new();
+        for parts in [
+            &["SET", "s", "old"][..],
+            &["HSET", "h", "f", "1"],
+            &["RPUSH", "l", "a"],
+            &["SADD", "gone", "m"],
+            &["INCR", "n"],
+        ] {
+            run(&tracker, &db, parts);
+        }
+        let before = dataset::scan(&db)?;
+
+        let view = tracker.begin(&db, || {})?;
+        run(&tracker, &db, &["SET", "s", "new"]);
+        run(&tracker, &db, &["HSET", "h", "g", "2"]);
+        run(&tracker, &db, &["RPUSH", "l", "b"]);
+        run(&tracker, &db, &["DEL", "gone"]);
+        run(&tracker, &db, &["INCR", "n"]);
+        run(&tracker, &db, &["SET", "created", "x"]);
+        run(&tracker, &db, &["EXPIRE", "h", "100"]);
+        // 事务在 sled 事务内部写入，同样按逻辑 key 记录
+        {
+            let queue = vec![cmd(&["HSET", "h", "t", "3"]), cmd(&["LPUSH", "l", "z"])];
+            let _gate = tracker.write_guard(&db, &[b"h", b"l"]);
+            exec_all(&db, &queue);
+        }
+
+        assert_eq!(dataset::scan(&view)?, before);
+        view.finish()?;
+        assert_eq!(string::get(&db, b"s")?.as_deref(), Some(&b"new"[..]));
+        assert_eq!(list::lrange(&db, b"l", 0, -1)?, vec![b"z".to_vec(), b"a".to_vec(), b"b".to_vec()]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_no_capture_outside_snapshot() -> Result<()> {
+        let db = Storage::sled(sled::Config::new().temporary(true).open()?)?;
+        let tracker = SnapshotTracker::new();
+        run(&tracker, &db, &["SET", "a", "1"]);
+        assert!(tracker.preimages.lock().unwrap().records.is_empty());
+
+        let view = tracker.begin(&db, || {})?;
+        run(&tracker, &db, &["SET", "a", "2"]);
+        drop(view);
+        run(&tracker, &db, &["SET", "a", "3"]);
+        assert!(tracker.preimages.lock().unwrap().records.is_empty());
+
+        let view = tracker.begin(&db, || {})?;
+        assert_eq!(view.get(&keys::string(b"a"))?, Some(IVec::from("3")));
+        Ok(())
+    }
+}
@@ -0,0 +1,112 @@
+// src/persistence/throttle.rs
+
+//! RDB 快照写盘限速
+//!
+//! 大数据集的快照一次性写出会占满磁盘带宽，同时进行的 AOF 写入与命令随之变慢：
+//! - 按 `rdb_throttle_mb_per_sec` 分块写入，超出速率时先睡眠再写下一块
+//! - 设置了 `rdb_pause_latency_ms` 时，最近刚有命令慢于阈值就暂停写盘，
+//!   单次快照最多暂停 `MAX_PAUSE`，保证快照总能完成
+
+use std::{
+    io::{self, Write},
+    sync::atomic::{AtomicU64, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// 每次写入的块大小
+const CHUNK: usize = 256 * 1024;
+/// 慢命令结束后这段时间内视为命令延迟仍然偏高
+const SLOW_WINDOW: Duration = Duration::from_millis(100);
+/// 暂停期间检查命令延迟的间隔
+const PAUSE_STEP: Duration = Duration::from_millis(10);
+/// 单次快照累计暂停的上限
+const MAX_PAUSE: Duration = Duration::from_secs(5);
+
+pub struct SnapshotThrottle {
+    /// 速率上限（字节/秒），0 表示不限速
+    bytes_per_sec: u64,
+    /// 触发暂停的命令耗时，零表示关闭
+    pause_latency: Duration,
+    /// 最近一次慢命令结束的时刻（相对 `origin` 的毫秒数 + 1，0 表示还没有）
+    last_slow: AtomicU64,
+    origin: Instant,
+}
+
+impl SnapshotThrottle {
+    pub fn new(bytes_per_sec: u64, pause_latency: Duration) -> Self {
+        SnapshotThrottle { bytes_per_sec, pause_latency, last_slow: AtomicU64::new(0), origin: Instant::now() }
+    }
+
+    /// 记录一条命令的耗时，连接任务在每条命令执行后调用
+    pub fn observe_command(&self, duration: Duration) {
+        if !self.pause_latency.is_zero() && duration >= self.pause_latency {
+            let now = self.origin.elapsed().as_millis() as u64 + 1;
+            self.last_slow.store(now, Ordering::Relaxed);
+        }
+    }
+
+    fn commands_slow(&self) -> bool {
+        match self.last_slow.load(Ordering::Relaxed) {
+            0 => false,
+            at => self.origin.elapsed() < Duration::from_millis(at - 1) + SLOW_WINDOW,
+        }
+    }
+
+    /// 分块写出 `data`，返回因命令延迟而暂停的总时长
+    pub fn write(&self, out: &mut impl Write, data: &[u8]) -> io::Result<Duration> {
+        let start = Instant::now();
+        let mut paused = Duration::ZERO;
+        let mut written = 0u64;
+        for chunk in data.chunks(CHUNK) {
+            while paused < MAX_PAUSE && self.commands_slow() {
+                thread::sleep(PAUSE_STEP);
+                paused += PAUSE_STEP;
+            }
+            out.write_all(chunk)?;
+            written += chunk.len() as u64;
+            if self.bytes_per_sec > 0 {
+                // 暂停的时间不计入配额，否则暂停之后会以全速补写
+                let due = Duration::from_secs_f64(written as f64 / self.bytes_per_sec as f64) + paused;
+                if let Some(wait) = due.checked_sub(start.elapsed()) {
+                    thread::sleep(wait);
+                }
+            }
+        }
+        Ok(paused)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limit() {
+        let throttle = SnapshotThrottle::new(4 * CHUNK as u64, Duration::ZERO);
+        let data = vec![7u8; 2 * CHUNK];
+        let mut out = Vec::new();
+        let start = Instant::now();
+        assert_eq!(throttle.write(&mut out, &data).unwrap(), Duration::ZERO);
+        assert!(start.elapsed() >= Duration::from_millis(450));
+        assert_eq!(out, data);
+
+        // 不限速时直接写完
+        let start = Instant::now();
+        SnapshotThrottle::new(0, Duration::ZERO).write(&mut Vec::new(), &data).unwrap();
+        assert!(start.elapsed() < Duration::from_millis(200));
+    }
+
+    #[test]
+    fn test_pause_on_slow_commands() {
+        let throttle = SnapshotThrottle::new(0, Duration::from_millis(50));
+        throttle.observe_command(Duration::from_millis(10));
+        assert!(!throttle.commands_slow());
+
+        throttle.observe_command(Duration::from_millis(80));
+        assert!(throttle.commands_slow());
+        let paused = throttle.write(&mut Vec::new(), b"payload").unwrap();
+        assert!(paused >= Duration::from_millis(50) && paused <= SLOW_WINDOW + PAUSE_STEP * 5);
+        assert!(!throttle.commands_slow());
+    }
+}
@@ -0,0 +1,269 @@
+// src/plugin.rs
+
+//! 命令插件：在运行时注册自定义命令
+//!
+//! 实现 [`CommandHandler`] 并调用 [`register`] 后，新命令和内置命令走同一条路径：
+//! 它的 [`CommandSpec`] 会出现在 `command::lookup` 与 `COMMAND` 的结果中，
+//! 因此参数个数校验、ACL 类别与 key 权限、AOF 与复制（写命令）、WATCH、
+//! 指标与慢日志都无需插件另行处理。
+//!
+//! 插件通过 [`Context::call`] 执行内置命令来读写数据。写命令整体在一个事务中执行，
+//! 出错时已做的写入一起回滚；命令访问的 key 应在 spec 的 key 位置中声明。
+//!
+//! 启用 `plugins` feature 后，还可以按配置项 `plugins` 在启动时加载动态库，
+//! 见 [`load_library`]。
+
+use std::sync::{Arc, LazyLock};
+
+use anyhow::{Error, bail};
+use bytes::Bytes;
+use dashmap::DashMap;
+use sled::transaction::ConflictableTransactionError;
+
+use crate::command::{self, CommandSpec};
+use crate::engine::KvEngine;
+use crate::protocol::Frame;
+use crate::script;
+
+/// 自定义命令
+pub trait CommandHandler: Send + Sync {
+    /// 命令元数据；`name` 须为大写，且不能与已有命令重名
+    fn spec(&self) -> CommandSpec;
+
+    /// 执行命令，`args[0]` 为命令名；参数个数已按 spec 校验过
+    fn execute(&self, ctx: &mut Context<'_>, args: &[Bytes]) -> Frame;
+}
+
+/// 插件执行命令时可用的数据访问接口
+pub struct Context<'a> {
+    call: &'a mut dyn FnMut(&[Bytes]) -> Frame,
+}
+
+impl Context<'_> {
+    /// 执行一条内置（或其他插件的）命令，限制与脚本中的 `redis.call` 相同
+    pub fn call<S: AsRef<[u8]>>(&mut self, args: &[S]) -> Frame {
+        let parts: Vec<Bytes> = args.iter().map(|a| Bytes::copy_from_slice(a.as_ref())).collect();
+        (self.call)(&parts)
+    }
+}
+
+struct Plugin {
+    /// 注册时泄漏出的 spec，使 `command::lookup` 能返回 `&'static CommandSpec`
+    spec: &'static CommandSpec,
+    handler: Arc<dyn CommandHandler>,
+}
+
+/// 全部已注册的插件命令，key 为大写命令名
+static REGISTRY: LazyLock<DashMap<String, Plugin>> = LazyLock::new(DashMap::new);
+
+/// 注册一条自定义命令
+pub fn register(handler: Arc<dyn CommandHandler>) -> anyhow::Result<()> {
+    let spec = handler.spec();
+    if spec.name.is_empty() || spec.name.bytes().any(|b| b.is_ascii_lowercase() || b.is_ascii_whitespace()) {
+        bail!("invalid command name '{}': must be non-empty uppercase", spec.name);
+    }
+    if spec.arity == 0 {
+        bail!("invalid arity for command '{}'", spec.name);
+    }
+    if command::COMMANDS.iter().any(|c| c.name == spec.name) {
+        bail!("command '{}' already exists", spec.name);
+    }
+
+    match REGISTRY.entry(spec.name.to_string()) {
+        dashmap::Entry::Occupied(_) => bail!("command '{}' already exists", spec.name),
+        dashmap::Entry::Vacant(entry) => {
+            entry.insert(Plugin { spec: Box::leak(Box::new(spec)), handler });
+            Ok(())
+        }
+    }
+}
+
+/// 注销一条自定义命令，返回它是否存在
+pub fn unregister(name: &str) -> bool {
+    REGISTRY.remove(&name.to_uppercase()).is_some()
+}
+
+/// 按命令名查找插件命令的元数据（大小写不敏感）
+pub fn lookup(name: &str) -> Option<&'static CommandSpec> {
+    REGISTRY.get(&name.to_uppercase()).map(|plugin| plugin.spec)
+}
+
+/// 全部插件命令的元数据，按名称排序
+pub fn specs() -> Vec<&'static CommandSpec> {
+    let mut specs: Vec<_> = REGISTRY.iter().map(|plugin| plugin.spec).collect();
+    specs.sort_by_key(|spec| spec.name);
+    specs
+}
+
+/// 执行插件命令；不是插件命令时返回 `None`
+pub fn execute<E: KvEngine>(db: &E, parts: &[Bytes]) -> Option<Frame> {
+    let (spec, handler) = {
+        let plugin = REGISTRY.get(&command::name_upper(&parts[0]))?;
+        (plugin.spec, plugin.handler.clone())
+    };
+    if !spec.check_arity(parts.len()) {
+        return Some(command::arity_error(spec.name));
+    }
+
+    // 和脚本一样：sled 上写命令自行开启事务，其他情况下调用方已处于事务上下文中
+    let reply = if spec.is_write() && db.sled_trees().is_some() {
+        db.transaction_for_keys(&spec.keys(parts), |tx| match run(tx, handler.as_ref(), parts) {
+            Frame::Error(msg) => Err(ConflictableTransactionError::Abort(Error::msg(msg))),
+            reply => Ok(reply),
+        })
+        .unwrap_or_else(|e| Frame::error(e.to_string()))
+    } else {
+        run(db, handler.as_ref(), parts)
+    };
+    Some(reply)
+}
+
+fn run<E: KvEngine>(db: &E, handler: &dyn CommandHandler, parts: &[Bytes]) -> Frame {
+    let mut call = |args: &[Bytes]| script::call_command(db, args);
+    handler.execute(&mut Context { call: &mut call }, parts)
+}
+
+/// 动态库中注册函数的符号名
+#[cfg(feature = "plugins")]
+pub const ENTRY_SYMBOL: &[u8] = b"crab_cage_plugin_register";
+
+/// 动态库的注册函数：库在其中调用 `register` 注册自己的命令
+///
+/// 插件须与服务端使用同一版本的编译器与本 crate 构建，例如：
+///
+/// ```ignore
+/// #[unsafe(no_mangle)]
+/// pub fn crab_cage_plugin_register(register: &mut dyn FnMut(Arc<dyn CommandHandler>)) {
+///     register(Arc::new(MyCommand));
+/// }
+/// ```
+#[cfg(feature = "plugins")]
+pub type EntryFn = fn(&mut dyn FnMut(Arc<dyn CommandHandler>));
+
+/// 加载一个插件动态库并注册其中的命令，返回注册的命令名
+///
+/// 库被加载后不再卸载，注册的处理器一直有效。
+#[cfg(feature = "plugins")]
+pub fn load_library(path: &std::path::Path) -> anyhow::Result<Vec<String>> {
+    // SAFETY: 加载任意动态库本身无法验证；插件与服务端用同一工具链构建时，入口函数签名与 `EntryFn` 一致
+    let library = unsafe { libloading::Library::new(path) }?;
+    let entry = unsafe { library.get::<EntryFn>(ENTRY_SYMBOL) }?;
+
+    let mut names = Vec::new();
+    let mut result = Ok(());
+    entry(&mut |handler: Arc<dyn CommandHandler>| {
+        let name = handler.spec().name;
+        match register(handler) {
+            Ok(()) => names.push(name.to_string()),
+            Err(e) if result.is_ok() => result = Err(e),
+            Err(_) => {}
+        }
+    });
+    std::mem::forget(library);
+    result.map(|()| names)
+}
+
+/// 加载配置中列出的全部插件动态库
+#[cfg(feature = "plugins")]
+pub fn load_all(paths: &[String]) -> anyhow::Result<Vec<String>> {
+    let mut names = Vec::new();
+    for path in paths {
+        let loaded = load_library(std::path::Path::new(path))
+            .map_err(|e| anyhow::anyhow!("failed to load plugin '{}': {}", path, e))?;
+        names.extend(loaded);
+    }
+    Ok(names)
+}
+
+/// 未启用 `plugins` feature 时不能加载动态库
+#[cfg(not(feature = "plugins"))]
+pub fn load_all(paths: &[String]) -> anyhow::Result<Vec<String>> {
+    if paths.is_empty() {
+        Ok(Vec::new())
+    } else {
+        bail!("plugins are configured but crab-cage was built without the `plugins` feature")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{self, Storage};
+    use crate::txn::session::TxnSession;
+
+    /// `COPYSTR src dst`：把字符串从 src 复制到 dst
+    struct CopyStr;
+
+    impl CommandHandler for CopyStr {
+        fn spec(&self) -> CommandSpec {
+            CommandSpec {
+                name: "COPYSTR",
+                arity: 3,
+                flags: &["write", "denyoom"],
+                first_key: 1,
+                last_key: 2,
+                step: 1,
+                categories: &["write", "string", "slow"],
+                group: "string",
+                summary: "Copies a string value to another key.",
+            }
+        }
+
+        fn execute(&self, ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+            match ctx.call(&[&b"GET"[..], &args[1]]) {
+                Frame::Bulk(value) => ctx.call(&[&b"SET"[..], &args[2], &value]),
+                Frame::Null => Frame::error("ERR no such key"),
+                other => other,
+            }
+        }
+    }
+
+    fn args(s: &[&str]) -> Vec<Bytes> {
+        s.iter().map(|x| Bytes::copy_from_slice(x.as_bytes())).collect()
+    }
+
+    #[test]
+    fn test_register_and_execute() -> anyhow::Result<()> {
+        register(Arc::new(CopyStr)).unwrap();
+        assert!(register(Arc::new(CopyStr)).is_err());
+
+        let spec = command::lookup("copystr").unwrap();
+        assert!(spec.is_write());
+        assert_eq!(spec.keys(&args(&["COPYSTR", "a", "b"])), vec![b"a", b"b"]);
+        assert!(specs().iter().any(|s| s.name == "COPYSTR"));
+
+        let db = Storage::sled(sled::Config::new().temporary(true).open()?)?;
+        let mut session = TxnSession::new(16);
+        engine::execute(&args(&["SET", "a", "1"]), &db, &mut session);
+        assert_eq!(engine::execute(&args(&["COPYSTR", "a", "b"]), &db, &mut session), Frame::ok());
+        assert_eq!(engine::execute(&args(&["GET", "b"]), &db, &mut session), Frame::bulk("1"));
+        assert!(engine::execute(&args(&["COPYSTR", "missing", "b"]), &db, &mut session).is_error());
+        assert!(engine::execute(&args(&["COPYSTR", "a"]), &db, &mut session).is_error());
+
+        // 事务中也能排队执行
+        engine::execute(&args(&["MULTI"]), &db, &mut session);
+        engine::execute(&args(&["COPYSTR", "b", "c"]), &db, &mut session);
+        assert_eq!(engine::execute(&args(&["EXEC"]), &db, &mut session), Frame::Array(vec![Frame::ok()]));
+        assert_eq!(engine::execute(&args(&["GET", "c"]), &db, &mut session), Frame::bulk("1"));
+
+        assert!(unregister("copystr"));
+        assert!(command::lookup("COPYSTR").is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_register_rejects_builtin_names() {
+        struct Get;
+        impl CommandHandler for Get {
+            fn spec(&self) -> CommandSpec {
+                let mut spec = CopyStr.spec();
+                spec.name = "GET";
+                spec
+            }
+            fn execute(&self, _ctx: &mut Context<'_>, _args: &[Bytes]) -> Frame {
+                Frame::Null
+            }
+        }
+        assert!(register(Arc::new(Get)).is_err());
+    }
+}
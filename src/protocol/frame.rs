@@ -0,0 +1,310 @@
+// src/protocol/frame.rs
+
+//! RESP 回复帧
+//!
+//! 引擎与网络层之间统一使用 `Frame` 表示一条回复，
+//! 真正写到 socket 之前再按连接协商好的协议版本编码：
+//! - RESP2：Map / Set / Push 退化为普通数组，Double 退化为 Bulk String，
+//!   Boolean 退化为整数，Null 编码为 `$-1`
+//! - RESP3：使用各自原生的类型前缀（`%` `~` `>` `,` `#` `_`）
+
+/// RESP2 协议版本号
+pub const RESP2: u8 = 2;
+/// RESP3 协议版本号
+pub const RESP3: u8 = 3;
+
+/// 一条 RESP 回复
+#[derive(Debug, Clone, PartialEq)]
+pub enum Frame {
+    /// `+OK`
+    Simple(String),
+    /// `-ERR ...`
+    Error(String),
+    /// `:1`
+    Integer(i64),
+    /// `$3\r\nfoo`
+    Bulk(Vec<u8>),
+    /// 空回复（RESP2 `$-1`，RESP3 `_`）
+    Null,
+    /// 空数组回复（RESP2 `*-1`，RESP3 `_`），如被放弃的 EXEC
+    NullArray,
+    /// `*N`
+    Array(Vec<Frame>),
+    /// RESP3 `%N`，键值对
+    Map(Vec<(Frame, Frame)>),
+    /// RESP3 `~N`
+    Set(Vec<Frame>),
+    /// RESP3 `,1.5`
+    Double(f64),
+    /// RESP3 `#t` / `#f`
+    Boolean(bool),
+    /// RESP3 `>N`，服务端主动推送
+    Push(Vec<Frame>),
+}
+
+impl Frame {
+    /// `+OK`
+    pub fn ok() -> Frame {
+        Frame::Simple("OK".to_string())
+    }
+
+    /// 以字符串构造 Bulk String
+    pub fn bulk<T: Into<Vec<u8>>>(data: T) -> Frame {
+        Frame::Bulk(data.into())
+    }
+
+    /// 错误回复，调用方负责带上 `ERR` 等前缀
+    pub fn error<T: Into<String>>(msg: T) -> Frame {
+        Frame::Error(msg.into())
+    }
+
+    /// 是否为错误回复
+    pub fn is_error(&self) -> bool {
+        matches!(self, Frame::Error(_))
+    }
+
+    /// 编码后大致的字节数，用于统计尚未发出的数据量，不实际编码
+    pub fn size_hint(&self) -> usize {
+        match self {
+            Frame::Simple(s) | Frame::Error(s) => s.len() + 3,
+            Frame::Bulk(data) => data.len() + 8,
+            Frame::Array(items) | Frame::Set(items) | Frame::Push(items) => {
+                8 + items.iter().map(Frame::size_hint).sum::<usize>()
+            }
+            Frame::Map(pairs) => 8 + pairs.iter().map(|(k, v)| k.size_hint() + v.size_hint()).sum::<usize>(),
+            Frame::Integer(_) | Frame::Double(_) => 16,
+            Frame::Null | Frame::NullArray | Frame::Boolean(_) => 5,
+        }
+    }
+
+    /// 按指定协议版本编码成字节
+    pub fn to_bytes(&self, proto: u8) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.encode(proto, &mut out);
+        out
+    }
+
+    /// 按指定协议版本编码并追加到 `out`
+    pub fn encode(&self, proto: u8, out: &mut Vec<u8>) {
+        let resp3 = proto >= RESP3;
+        match self {
+            Frame::Simple(s) => {
+                out.push(b'+');
+                out.extend_from_slice(s.as_bytes());
+                out.extend_from_slice(b"\r\n");
+            }
+            Frame::Error(s) => {
+                out.push(b'-');
+                out.extend_from_slice(s.as_bytes());
+                out.extend_from_slice(b"\r\n");
+            }
+            Frame::Integer(n) => {
+                out.extend_from_slice(format!(":{}\r\n", n).as_bytes());
+            }
+            Frame::Bulk(data) => {
+                out.extend_from_slice(format!("${}\r\n", data.len()).as_bytes());
+                out.extend_from_slice(data);
+                out.extend_from_slice(b"\r\n");
+            }
+            Frame::Null => {
+                if resp3 {
+                    out.extend_from_slice(b"_\r\n");
+                } else {
+                    out.extend_from_slice(b"$-1\r\n");
+                }
+            }
+            Frame::NullArray => {
+                if resp3 {
+                    out.extend_from_slice(b"_\r\n");
+                } else {
+                    out.extend_from_slice(b"*-1\r\n");
+                }
+            }
+            Frame::Array(items) => encode_aggregate(b'*', items, proto, out),
+            Frame::Set(items) => {
+                encode_aggregate(if resp3 { b'~' } else { b'*' }, items, proto, out)
+            }
+            Frame::Push(items) => {
+                encode_aggregate(if resp3 { b'>' } else { b'*' }, items, proto, out)
+            }
+            Frame::Map(pairs) => {
+                if resp3 {
+                    out.extend_from_slice(format!("%{}\r\n", pairs.len()).as_bytes());
+                } else {
+                    out.extend_from_slice(format!("*{}\r\n", pairs.len() * 2).as_bytes());
+                }
+                for (k, v) in pairs {
+                    k.encode(proto, out);
+                    v.encode(proto, out);
+                }
+            }
+            Frame::Double(d) => {
+                let text = format_double(*d);
+                if resp3 {
+                    out.extend_from_slice(format!(",{}\r\n", text).as_bytes());
+                } else {
+                    Frame::Bulk(text.into_bytes()).encode(proto, out);
+                }
+            }
+            Frame::Boolean(b) => {
+                if resp3 {
+                    out.extend_from_slice(if *b { b"#t\r\n" } else { b"#f\r\n" });
+                } else {
+                    Frame::Integer(*b as i64).encode(proto, out);
+                }
+            }
+        }
+    }
+}
+
+impl Frame {
+    /// 解析一条完整的 RESP2 / RESP3 回复，返回回复与消耗的字节数；数据不完整或格式错误时返回 None
+    ///
+    /// 用于解析 WASM 函数返回的回复（见 `function` 模块）与 `crab-cage-cli` 收到的回复
+    pub fn decode(buf: &[u8]) -> Option<(Frame, usize)> {
+        let end = buf.windows(2).position(|w| w == b"\r\n")?;
+        let line = std::str::from_utf8(buf.get(1..end)?).ok()?;
+        let mut pos = end + 2;
+        let frame = match buf[0] {
+            b'+' => Frame::Simple(line.to_string()),
+            b'-' => Frame::Error(line.to_string()),
+            b':' => Frame::Integer(line.parse().ok()?),
+            b'$' => match line.parse::<i64>().ok()? {
+                len if len < 0 => Frame::Null,
+                len => {
+                    let data = buf.get(pos..pos + len as usize)?;
+                    pos += len as usize;
+                    if buf.get(pos..pos + 2)? != b"\r\n" {
+                        return None;
+                    }
+                    pos += 2;
+                    Frame::Bulk(data.to_vec())
+                }
+            },
+            b'*' => match line.parse::<i64>().ok()? {
+                n if n < 0 => Frame::NullArray,
+                n => Frame::Array(decode_items(buf, &mut pos, n as usize)?),
+            },
+            b'_' => Frame::Null,
+            b',' => Frame::Double(match line {
+                "inf" => f64::INFINITY,
+                "-inf" => f64::NEG_INFINITY,
+                "nan" => f64::NAN,
+                _ => line.parse().ok()?,
+            }),
+            b'#' => match line {
+                "t" => Frame::Boolean(true),
+                "f" => Frame::Boolean(false),
+                _ => return None,
+            },
+            b'%' => {
+                let mut flat = decode_items(buf, &mut pos, line.parse::<usize>().ok()?.checked_mul(2)?)?.into_iter();
+                let mut pairs = Vec::with_capacity(flat.len() / 2);
+                while let (Some(k), Some(v)) = (flat.next(), flat.next()) {
+                    pairs.push((k, v));
+                }
+                Frame::Map(pairs)
+            }
+            b'~' => Frame::Set(decode_items(buf, &mut pos, line.parse().ok()?)?),
+            b'>' => Frame::Push(decode_items(buf, &mut pos, line.parse().ok()?)?),
+            _ => return None,
+        };
+        Some((frame, pos))
+    }
+}
+
+/// 从 `pos` 开始依次解析 `n` 条回复
+fn decode_items(buf: &[u8], pos: &mut usize, n: usize) -> Option<Vec<Frame>> {
+    let mut items = Vec::with_capacity(n.min(1024));
+    for _ in 0..n {
+        let (item, used) = Frame::decode(&buf[*pos..])?;
+        items.push(item);
+        *pos += used;
+    }
+    Some(items)
+}
+
+fn encode_aggregate(prefix: u8, items: &[Frame], proto: u8, out: &mut Vec<u8>) {
+    out.push(prefix);
+    out.extend_from_slice(format!("{}\r\n", items.len()).as_bytes());
+    for item in items {
+        item.encode(proto, out);
+    }
+}
+
+/// RESP3 对无穷与 NaN 有固定写法
+fn format_double(d: f64) -> String {
+    if d.is_nan() {
+        "nan".to_string()
+    } else if d.is_infinite() {
+        if d > 0.0 { "inf".to_string() } else { "-inf".to_string() }
+    } else {
+        d.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_resp2() {
+        assert_eq!(Frame::ok().to_bytes(RESP2), b"+OK\r\n");
+        assert_eq!(Frame::error("ERR x").to_bytes(RESP2), b"-ERR x\r\n");
+        assert_eq!(Frame::Integer(-3).to_bytes(RESP2), b":-3\r\n");
+        assert_eq!(Frame::bulk("foo").to_bytes(RESP2), b"$3\r\nfoo\r\n");
+        assert_eq!(Frame::Null.to_bytes(RESP2), b"$-1\r\n");
+        assert_eq!(Frame::NullArray.to_bytes(RESP2), b"*-1\r\n");
+
+        // Map 在 RESP2 下退化为扁平数组
+        let map = Frame::Map(vec![(Frame::bulk("a"), Frame::Integer(1))]);
+        assert_eq!(map.to_bytes(RESP2), b"*2\r\n$1\r\na\r\n:1\r\n");
+        assert_eq!(Frame::Double(1.5).to_bytes(RESP2), b"$3\r\n1.5\r\n");
+        assert_eq!(Frame::Boolean(true).to_bytes(RESP2), b":1\r\n");
+    }
+
+    #[test]
+    fn test_decode_resp2() {
+        let frame = Frame::Array(vec![
+            Frame::ok(),
+            Frame::error("ERR x"),
+            Frame::Integer(-3),
+            Frame::bulk("a\r\nb"),
+            Frame::Null,
+            Frame::Array(vec![]),
+        ]);
+        let bytes = frame.to_bytes(RESP2);
+        assert_eq!(Frame::decode(&bytes), Some((frame, bytes.len())));
+        assert_eq!(Frame::decode(b"*-1\r\n"), Some((Frame::NullArray, 5)));
+        assert_eq!(Frame::decode(b"$3\r\nfo"), None);
+        assert_eq!(Frame::decode(b"?x\r\n"), None);
+    }
+
+    #[test]
+    fn test_decode_resp3() {
+        let frame = Frame::Push(vec![
+            Frame::Map(vec![(Frame::bulk("a"), Frame::Double(1.5)), (Frame::bulk("b"), Frame::Boolean(true))]),
+            Frame::Set(vec![Frame::bulk("x")]),
+            Frame::Null,
+            Frame::Double(f64::INFINITY),
+        ]);
+        let bytes = frame.to_bytes(RESP3);
+        assert_eq!(Frame::decode(&bytes), Some((frame, bytes.len())));
+        assert_eq!(Frame::decode(b"%1\r\n$1\r\na\r\n"), None);
+    }
+
+    #[test]
+    fn test_encode_resp3() {
+        assert_eq!(Frame::Null.to_bytes(RESP3), b"_\r\n");
+        assert_eq!(Frame::NullArray.to_bytes(RESP3), b"_\r\n");
+
+        let map = Frame::Map(vec![(Frame::bulk("a"), Frame::Integer(1))]);
+        assert_eq!(map.to_bytes(RESP3), b"%1\r\n$1\r\na\r\n:1\r\n");
+        let set = Frame::Set(vec![Frame::bulk("x")]);
+        assert_eq!(set.to_bytes(RESP3), b"~1\r\n$1\r\nx\r\n");
+        let push = Frame::Push(vec![Frame::bulk("message")]);
+        assert_eq!(push.to_bytes(RESP3), b">1\r\n$7\r\nmessage\r\n");
+        assert_eq!(Frame::Double(f64::INFINITY).to_bytes(RESP3), b",inf\r\n");
+        assert_eq!(Frame::Boolean(false).to_bytes(RESP3), b"#f\r\n");
+    }
+}
@@ -0,0 +1,7 @@
+// src/protocol/mod.rs
+//! 协议层：RESP 请求解析与 RESP2 / RESP3 回复帧的编码
+pub mod frame;
+pub mod parser;
+
+pub use frame::{Frame, RESP2, RESP3};
+pub use parser::{ParserLimits, ProtocolError, RespParser};
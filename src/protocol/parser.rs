@@ -0,0 +1,433 @@
+// src/protocol/parser.rs
+
+//! 流式 RESP 请求解析器
+//!
+//! 网络层把读到的字节追加到连接的 `BytesMut` 缓冲区后调用 [`RespParser::parse_bytes`]：
+//! - 缓冲区里有完整命令时返回 `Ok(Some(parts))`，并从缓冲区移除已消费的字节
+//! - 数据不足时返回 `Ok(None)`，已解析出的参数保存在解析器状态里，
+//!   下次读到更多数据后从断点继续（不会重复解析）
+//! - 输入格式错误时返回 `Err(ProtocolError)`，并丢弃缓冲区、重置状态，
+//!   由调用方回复错误而不是直接断开连接
+//!
+//! 返回的参数是从缓冲区切出的 `Bytes`，与缓冲区共享内存，不复制负载，也不要求是 UTF-8；
+//! 已消费的字节随之从缓冲区头部移走，流水线中剩余的数据不需要整体前移。
+//! 缓冲区在参数全部释放后由下一次读取复用。[`RespParser::parse`] 在此之上要求参数是 UTF-8 文本。
+//!
+//! 同时支持 RESP 多条批量请求（`*N\r\n$len\r\n...`）与行内文本命令，
+//! 行内命令支持单/双引号包裹参数及转义，规则与 redis-cli 相同。
+
+use std::fmt;
+
+use bytes::{Buf, Bytes, BytesMut};
+
+/// 行内命令与长度行的最大长度（与 Redis 一致，64KB）
+const MAX_INLINE_LEN: usize = 64 * 1024;
+
+/// 解析器限制
+#[derive(Debug, Clone, Copy)]
+pub struct ParserLimits {
+    /// 单条命令最多包含的参数个数
+    pub max_multibulk_len: usize,
+    /// 单个参数的最大字节数
+    pub max_bulk_len: usize,
+}
+
+impl Default for ParserLimits {
+    fn default() -> Self {
+        ParserLimits {
+            max_multibulk_len: 1024 * 1024,
+            max_bulk_len: 512 * 1024 * 1024,
+        }
+    }
+}
+
+/// 协议错误，`Display` 输出与 Redis 的错误文案一致
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProtocolError {
+    InvalidMultibulkLength,
+    InvalidBulkLength,
+    ExpectedBulk(u8),
+    /// 负载之后不是 `\r\n`，即 `$len` 与实际长度不符
+    ExpectedCrlf,
+    TooBigInlineRequest,
+    TooBigCountString,
+    UnbalancedQuotes,
+    InvalidUtf8,
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProtocolError::InvalidMultibulkLength => {
+                write!(f, "Protocol error: invalid multibulk length")
+            }
+            ProtocolError::InvalidBulkLength => write!(f, "Protocol error: invalid bulk length"),
+            ProtocolError::ExpectedBulk(got) => {
+                write!(f, "Protocol error: expected '$', got '{}'", *got as char)
+            }
+            ProtocolError::ExpectedCrlf => write!(f, "Protocol error: expected CRLF"),
+            ProtocolError::TooBigInlineRequest => {
+                write!(f, "Protocol error: too big inline request")
+            }
+            ProtocolError::TooBigCountString => {
+                write!(f, "Protocol error: too big count string")
+            }
+            ProtocolError::UnbalancedQuotes => {
+                write!(f, "Protocol error: unbalanced quotes in request")
+            }
+            ProtocolError::InvalidUtf8 => write!(f, "Protocol error: invalid UTF-8 argument"),
+        }
+    }
+}
+
+impl std::error::Error for ProtocolError {}
+
+/// 解析状态
+#[derive(Debug)]
+enum State {
+    /// 等待新命令的第一个字节
+    Start,
+    /// 已读到 `*N`，还需 `remaining` 个参数
+    Args { remaining: usize, args: Vec<Bytes> },
+    /// 已读到 `$len`，等待 `len` 字节负载加 `\r\n`
+    Bulk { remaining: usize, args: Vec<Bytes>, len: usize },
+}
+
+/// 可恢复的 RESP 请求解析器，每个连接一个
+#[derive(Debug)]
+pub struct RespParser {
+    limits: ParserLimits,
+    state: State,
+}
+
+impl RespParser {
+    pub fn new(limits: ParserLimits) -> Self {
+        RespParser { limits, state: State::Start }
+    }
+
+    /// 从 `buf` 中解析一条命令，参数要求是 UTF-8 文本，细节见模块文档
+    ///
+    /// 参数不是合法 UTF-8 时返回 `Err(InvalidUtf8)`，此时这条命令已经从缓冲区移除，
+    /// 流水线中之后的命令不受影响
+    pub fn parse(&mut self, buf: &mut BytesMut) -> Result<Option<Vec<String>>, ProtocolError> {
+        match self.parse_bytes(buf)? {
+            Some(args) => args
+                .into_iter()
+                .map(|arg| String::from_utf8(Vec::from(arg)).map_err(|_| ProtocolError::InvalidUtf8))
+                .collect::<Result<_, _>>()
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// 从 `buf` 中解析一条命令，参数是缓冲区的零拷贝切片
+    pub fn parse_bytes(&mut self, buf: &mut BytesMut) -> Result<Option<Vec<Bytes>>, ProtocolError> {
+        let res = self.step(buf);
+        if res.is_err() {
+            buf.clear();
+            self.state = State::Start;
+        }
+        res
+    }
+
+    /// 按状态机推进，每读完一个完整的行或负载就从缓冲区头部移走
+    fn step(&mut self, buf: &mut BytesMut) -> Result<Option<Vec<Bytes>>, ProtocolError> {
+        loop {
+            match std::mem::replace(&mut self.state, State::Start) {
+                State::Start => {
+                    if buf.is_empty() {
+                        return Ok(None);
+                    }
+                    if buf[0] != b'*' {
+                        return self.parse_inline(buf);
+                    }
+                    let Some((line_len, next)) = read_line(buf, ProtocolError::TooBigCountString)? else {
+                        return Ok(None);
+                    };
+                    let count = parse_len(&buf[1..line_len])
+                        .ok_or(ProtocolError::InvalidMultibulkLength)?;
+                    buf.advance(next);
+                    if count > self.limits.max_multibulk_len as i64 {
+                        return Err(ProtocolError::InvalidMultibulkLength);
+                    }
+                    if count <= 0 {
+                        // `*0` / `*-1`：空命令，由调用方忽略
+                        return Ok(Some(Vec::new()));
+                    }
+                    let count = count as usize;
+                    self.state = State::Args {
+                        remaining: count,
+                        args: Vec::with_capacity(count.min(1024)),
+                    };
+                }
+                State::Args { remaining, args } => {
+                    if remaining == 0 {
+                        return Ok(Some(args));
+                    }
+                    if buf.is_empty() {
+                        self.state = State::Args { remaining, args };
+                        return Ok(None);
+                    }
+                    if buf[0] != b'$' {
+                        return Err(ProtocolError::ExpectedBulk(buf[0]));
+                    }
+                    let Some((line_len, next)) = read_line(buf, ProtocolError::TooBigCountString)? else {
+                        self.state = State::Args { remaining, args };
+                        return Ok(None);
+                    };
+                    let len = parse_len(&buf[1..line_len]).ok_or(ProtocolError::InvalidBulkLength)?;
+                    buf.advance(next);
+                    if len < 0 || len as usize > self.limits.max_bulk_len {
+                        return Err(ProtocolError::InvalidBulkLength);
+                    }
+                    self.state = State::Bulk { remaining, args, len: len as usize };
+                }
+                State::Bulk { remaining, mut args, len } => {
+                    if buf.len() < len + 2 {
+                        self.state = State::Bulk { remaining, args, len };
+                        return Ok(None);
+                    }
+                    // 长度不符时继续解析只会把负载的剩余部分当成下一条命令
+                    if &buf[len..len + 2] != b"\r\n" {
+                        return Err(ProtocolError::ExpectedCrlf);
+                    }
+                    args.push(buf.split_to(len).freeze());
+                    buf.advance(2);
+                    self.state = State::Args { remaining: remaining - 1, args };
+                }
+            }
+        }
+    }
+
+    /// 行内命令：一整行按空白切分，引号内的空白保留
+    fn parse_inline(&mut self, buf: &mut BytesMut) -> Result<Option<Vec<Bytes>>, ProtocolError> {
+        let Some((line_len, next)) = read_line(buf, ProtocolError::TooBigInlineRequest)? else {
+            return Ok(None);
+        };
+        let args = split_inline(&buf[..line_len])?;
+        buf.advance(next);
+        Ok(Some(args.into_iter().map(Bytes::from).collect()))
+    }
+}
+
+/// 按 Redis `sdssplitargs` 的规则切分行内命令：
+/// - 参数之间以空白分隔
+/// - 双引号内支持 `\n` `\r` `\t` `\b` `\a` `\\` `\"` 与 `\xHH` 转义
+/// - 单引号内只支持 `\'` 转义
+/// - 闭合引号后必须紧跟空白或行尾
+pub fn split_inline(line: &[u8]) -> Result<Vec<String>, ProtocolError> {
+    let mut args = Vec::new();
+    let mut i = 0;
+
+    loop {
+        while i < line.len() && line[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        if i >= line.len() {
+            break;
+        }
+
+        let mut current = Vec::new();
+        let mut in_dq = false;
+        let mut in_sq = false;
+        loop {
+            if in_dq {
+                match line.get(i) {
+                    None => return Err(ProtocolError::UnbalancedQuotes),
+                    Some(b'\\')
+                        if i + 3 < line.len()
+                            && line[i + 1] == b'x'
+                            && line[i + 2].is_ascii_hexdigit()
+                            && line[i + 3].is_ascii_hexdigit() =>
+                    {
+                        current.push(hex_value(line[i + 2]) * 16 + hex_value(line[i + 3]));
+                        i += 3;
+                    }
+                    Some(b'\\') if i + 1 < line.len() => {
+                        i += 1;
+                        current.push(match line[i] {
+                            b'n' => b'\n',
+                            b'r' => b'\r',
+                            b't' => b'\t',
+                            b'b' => 0x08,
+                            b'a' => 0x07,
+                            other => other,
+                        });
+                    }
+                    Some(b'"') => {
+                        // 闭合引号后必须是空白或行尾
+                        if i + 1 < line.len() && !line[i + 1].is_ascii_whitespace() {
+                            return Err(ProtocolError::UnbalancedQuotes);
+                        }
+                        i += 1;
+                        break;
+                    }
+                    Some(&c) => current.push(c),
+                }
+            } else if in_sq {
+                match line.get(i) {
+                    None => return Err(ProtocolError::UnbalancedQuotes),
+                    Some(b'\\') if line.get(i + 1) == Some(&b'\'') => {
+                        i += 1;
+                        current.push(b'\'');
+                    }
+                    Some(b'\'') => {
+                        if i + 1 < line.len() && !line[i + 1].is_ascii_whitespace() {
+                            return Err(ProtocolError::UnbalancedQuotes);
+                        }
+                        i += 1;
+                        break;
+                    }
+                    Some(&c) => current.push(c),
+                }
+            } else {
+                match line.get(i) {
+                    None => break,
+                    Some(c) if c.is_ascii_whitespace() => break,
+                    Some(b'"') => in_dq = true,
+                    Some(b'\'') => in_sq = true,
+                    Some(&c) => current.push(c),
+                }
+            }
+            i += 1;
+        }
+
+        args.push(String::from_utf8(current).map_err(|_| ProtocolError::InvalidUtf8)?);
+    }
+
+    Ok(args)
+}
+
+fn hex_value(c: u8) -> u8 {
+    match c {
+        b'0'..=b'9' => c - b'0',
+        b'a'..=b'f' => c - b'a' + 10,
+        _ => c - b'A' + 10,
+    }
+}
+
+/// 在缓冲区开头找一整行，返回行的长度（不含行尾的 `\r\n` / `\n`）与下一行的起始位置
+fn read_line(buf: &[u8], too_long: ProtocolError) -> Result<Option<(usize, usize)>, ProtocolError> {
+    match buf.iter().position(|&b| b == b'\n') {
+        Some(i) if i > 0 && buf[i - 1] == b'\r' => Ok(Some((i - 1, i + 1))),
+        Some(i) => Ok(Some((i, i + 1))),
+        None if buf.len() > MAX_INLINE_LEN => Err(too_long),
+        None => Ok(None),
+    }
+}
+
+fn parse_len(digits: &[u8]) -> Option<i64> {
+    std::str::from_utf8(digits).ok()?.parse::<i64>().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parser() -> RespParser {
+        RespParser::new(ParserLimits::default())
+    }
+
+    #[test]
+    fn test_parse_multibulk() {
+        let mut p = parser();
+        let mut buf = BytesMut::from(&b"*2\r\n$3\r\nGET\r\n$3\r\nfoo\r\n"[..]);
+        assert_eq!(p.parse(&mut buf), Ok(Some(vec!["GET".to_string(), "foo".to_string()])));
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_parse_resumes_across_reads() {
+        let mut p = parser();
+        let mut buf = BytesMut::from(&b"*2\r\n$3\r\nGE"[..]);
+        assert_eq!(p.parse(&mut buf), Ok(None));
+        buf.extend_from_slice(b"T\r\n$3\r\nf");
+        assert_eq!(p.parse(&mut buf), Ok(None));
+        buf.extend_from_slice(b"oo\r\n*1\r\n$4\r\nPING\r\n");
+        assert_eq!(p.parse(&mut buf), Ok(Some(vec!["GET".to_string(), "foo".to_string()])));
+        // 流水线中的下一条命令仍留在缓冲区
+        assert_eq!(p.parse(&mut buf), Ok(Some(vec!["PING".to_string()])));
+        assert_eq!(p.parse(&mut buf), Ok(None));
+    }
+
+    #[test]
+    fn test_parse_bytes_shares_buffer() {
+        let mut p = parser();
+        let mut buf = BytesMut::from(&b"*2\r\n$3\r\nGET\r\n$1\r\n\xff\r\n*1\r\n$4\r\nPING\r\n"[..]);
+        let start = buf.as_ptr() as usize;
+        let args = p.parse_bytes(&mut buf).unwrap().unwrap();
+        assert_eq!(args, vec![Bytes::from_static(b"GET"), Bytes::from_static(b"\xff")]);
+        // 参数直接指向读缓冲区中的负载
+        assert_eq!(args[0].as_ptr() as usize, start + 8);
+
+        // 非 UTF-8 参数只让这一条命令失败，流水线中之后的命令照常解析
+        let mut buf = BytesMut::from(&b"*1\r\n$1\r\n\xff\r\n*1\r\n$4\r\nPING\r\n"[..]);
+        assert_eq!(p.parse(&mut buf), Err(ProtocolError::InvalidUtf8));
+        assert_eq!(p.parse(&mut buf), Ok(Some(vec!["PING".to_string()])));
+    }
+
+    #[test]
+    fn test_parse_inline() {
+        let mut p = parser();
+        let mut buf = BytesMut::from(&b"SET a  b\r\n"[..]);
+        assert_eq!(
+            p.parse(&mut buf),
+            Ok(Some(vec!["SET".to_string(), "a".to_string(), "b".to_string()]))
+        );
+    }
+
+    #[test]
+    fn test_parse_inline_quotes() {
+        let args = |s: &str| split_inline(s.as_bytes());
+        assert_eq!(
+            args(r#"SET greeting "hello world""#),
+            Ok(vec!["SET".to_string(), "greeting".to_string(), "hello world".to_string()])
+        );
+        assert_eq!(
+            args(r#"SET k 'it\'s' "a\tb\x41\"""#),
+            Ok(vec![
+                "SET".to_string(),
+                "k".to_string(),
+                "it's".to_string(),
+                "a\tbA\"".to_string(),
+            ])
+        );
+        assert_eq!(args(r#"SET k """#), Ok(vec!["SET".to_string(), "k".to_string(), String::new()]));
+        assert_eq!(args(r#"SET k "abc"#), Err(ProtocolError::UnbalancedQuotes));
+        assert_eq!(args(r#"SET k "abc"d"#), Err(ProtocolError::UnbalancedQuotes));
+    }
+
+    #[test]
+    fn test_malformed_input_resets() {
+        let mut p = parser();
+        let mut buf = BytesMut::from(&b"*x\r\n"[..]);
+        assert_eq!(p.parse(&mut buf), Err(ProtocolError::InvalidMultibulkLength));
+        assert!(buf.is_empty());
+
+        let mut buf = BytesMut::from(&b"*1\r\n:3\r\n"[..]);
+        assert_eq!(p.parse(&mut buf), Err(ProtocolError::ExpectedBulk(b':')));
+
+        // `$len` 与负载长度不符：报错并丢弃缓冲区，而不是把剩余的字节当成下一条命令
+        let mut buf = BytesMut::from(&b"*2\r\n$3\r\nSET\r\n$2\r\nabc\r\n*1\r\n$4\r\nPING\r\n"[..]);
+        assert_eq!(p.parse(&mut buf), Err(ProtocolError::ExpectedCrlf));
+        assert!(buf.is_empty());
+        // 负载完整到达之前不做判断
+        let mut buf = BytesMut::from(&b"*1\r\n$4\r\nPIN"[..]);
+        assert_eq!(p.parse(&mut buf), Ok(None));
+        buf.extend_from_slice(b"GG\r\n");
+        assert_eq!(p.parse(&mut buf), Err(ProtocolError::ExpectedCrlf));
+
+        // 出错后可以继续解析新的命令
+        let mut buf = BytesMut::from(&b"*1\r\n$4\r\nPING\r\n"[..]);
+        assert_eq!(p.parse(&mut buf), Ok(Some(vec!["PING".to_string()])));
+    }
+
+    #[test]
+    fn test_limits() {
+        let mut p = RespParser::new(ParserLimits { max_multibulk_len: 2, max_bulk_len: 4 });
+        let mut buf = BytesMut::from(&b"*3\r\n"[..]);
+        assert_eq!(p.parse(&mut buf), Err(ProtocolError::InvalidMultibulkLength));
+        let mut buf = BytesMut::from(&b"*1\r\n$5\r\n"[..]);
+        assert_eq!(p.parse(&mut buf), Err(ProtocolError::InvalidBulkLength));
+    }
+}
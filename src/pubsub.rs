@@ -0,0 +1,609 @@
+// src/pubsub.rs
+
+//! 发布 / 订阅：
+//! - 全局的频道、模式与分片频道订阅表 [`PubSub`]，所有连接共享
+//! - 每个连接自己的订阅集合 [`Subscriptions`]
+//!
+//! 消息通过连接的推送通道发出，统一使用 Push 帧：
+//! RESP3 下编码为 `>`，RESP2 下降级为普通数组，与 Redis 行为一致。
+//!
+//! 分片频道（SSUBSCRIBE / SPUBLISH）在单机模式下没有槽位之分，
+//! 只是与普通频道分开登记，消息类型为 `smessage`。
+//!
+//! 每个频道 / 模式统计投递成功与丢弃的消息数，频道没有订阅者后统计随之移除。
+//! 订阅者排队未发出的消息超过 `pubsub_backlog_limit` 条时，按 `pubsub_backlog_policy`
+//! 丢弃新消息或断开该客户端；积压达到 `pubsub_slow_subscriber_backlog` 条时记为慢订阅者。
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use anyhow::{Result, bail};
+use dashmap::DashMap;
+use tracing::warn;
+
+use crate::config::Config;
+use crate::glob::glob_match;
+use crate::output::PushSender;
+use crate::protocol::Frame;
+
+/// 一个频道（或模式）的订阅者与投递统计
+#[derive(Default)]
+struct Channel {
+    /// Client ID -> 推送通道
+    subs: HashMap<u64, PushSender>,
+    delivered: AtomicU64,
+    dropped: AtomicU64,
+}
+
+/// 频道 / 模式 -> 订阅者
+type Registry = DashMap<String, Channel>;
+
+/// 订阅者积压超出上限时的处理
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BacklogPolicy {
+    /// 丢弃新消息，连接保持
+    Drop,
+    /// 断开该客户端
+    Disconnect,
+}
+
+/// 订阅种类
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// SUBSCRIBE
+    Channel,
+    /// PSUBSCRIBE
+    Pattern,
+    /// SSUBSCRIBE
+    Shard,
+}
+
+impl Kind {
+    /// 统计中使用的种类名
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::Channel => "channel",
+            Kind::Pattern => "pattern",
+            Kind::Shard => "shardchannel",
+        }
+    }
+
+    fn subscribe_reply(self) -> &'static str {
+        match self {
+            Kind::Channel => "subscribe",
+            Kind::Pattern => "psubscribe",
+            Kind::Shard => "ssubscribe",
+        }
+    }
+
+    fn unsubscribe_reply(self) -> &'static str {
+        match self {
+            Kind::Channel => "unsubscribe",
+            Kind::Pattern => "punsubscribe",
+            Kind::Shard => "sunsubscribe",
+        }
+    }
+}
+
+/// 全局订阅表
+pub struct PubSub {
+    channels: Registry,
+    patterns: Registry,
+    shard_channels: Registry,
+    /// 每个订阅者最多排队的消息数，0 表示不限制
+    backlog_limit: usize,
+    backlog_policy: BacklogPolicy,
+    /// 积压达到该条数视为慢订阅者，0 表示不检测
+    slow_backlog: usize,
+    delivered: AtomicU64,
+    dropped: AtomicU64,
+    /// 因积压超出上限被断开的客户端数
+    disconnected: AtomicU64,
+    /// 订阅者积压达到慢订阅者阈值的次数
+    slow_events: AtomicU64,
+}
+
+impl Default for PubSub {
+    fn default() -> Self {
+        PubSub {
+            channels: Registry::default(),
+            patterns: Registry::default(),
+            shard_channels: Registry::default(),
+            backlog_limit: 0,
+            backlog_policy: BacklogPolicy::Drop,
+            slow_backlog: 0,
+            delivered: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            disconnected: AtomicU64::new(0),
+            slow_events: AtomicU64::new(0),
+        }
+    }
+}
+
+/// 发布 / 订阅的投递统计，用于 INFO 与 /metrics
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PubSubStats {
+    pub channels: usize,
+    pub patterns: usize,
+    pub shard_channels: usize,
+    pub delivered: u64,
+    pub dropped: u64,
+    pub disconnected: u64,
+    /// 当前积压达到阈值的订阅者数
+    pub slow_subscribers: usize,
+    pub slow_events: u64,
+    /// 各频道 / 模式的统计，按种类与名字排序
+    pub per_channel: Vec<ChannelStats>,
+}
+
+/// 单个频道 / 模式的投递统计
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChannelStats {
+    pub kind: Kind,
+    pub name: String,
+    pub subscribers: usize,
+    pub delivered: u64,
+    pub dropped: u64,
+}
+
+/// 单个连接当前的订阅
+#[derive(Debug, Default)]
+pub struct Subscriptions {
+    pub channels: BTreeSet<String>,
+    pub patterns: BTreeSet<String>,
+    pub shard_channels: BTreeSet<String>,
+}
+
+impl Subscriptions {
+    fn set_mut(&mut self, kind: Kind) -> &mut BTreeSet<String> {
+        match kind {
+            Kind::Channel => &mut self.channels,
+            Kind::Pattern => &mut self.patterns,
+            Kind::Shard => &mut self.shard_channels,
+        }
+    }
+
+    /// (un)subscribe 回复中的计数：
+    /// 普通频道与模式合并计数，分片频道单独计数（与 Redis 一致）
+    pub fn count(&self, kind: Kind) -> usize {
+        match kind {
+            Kind::Channel | Kind::Pattern => self.channels.len() + self.patterns.len(),
+            Kind::Shard => self.shard_channels.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.channels.is_empty() && self.patterns.is_empty() && self.shard_channels.is_empty()
+    }
+}
+
+fn register(registry: &Registry, name: &str, client_id: u64, sender: &PushSender) {
+    registry
+        .entry(name.to_string())
+        .or_default()
+        .subs
+        .insert(client_id, sender.clone());
+}
+
+fn unregister(registry: &Registry, name: &str, client_id: u64) {
+    if let Some(mut channel) = registry.get_mut(name) {
+        channel.subs.remove(&client_id);
+    }
+    // 没有订阅者的频道直接移除，PUBSUB CHANNELS 只列出活跃频道
+    registry.remove_if(name, |_, channel| channel.subs.is_empty());
+}
+
+fn reply(kind: &str, name: Option<&str>, count: usize) -> Frame {
+    Frame::Push(vec![
+        Frame::bulk(kind),
+        name.map_or(Frame::Null, Frame::bulk),
+        Frame::Integer(count as i64),
+    ])
+}
+
+impl PubSub {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 按配置设置订阅者的积压上限与慢订阅者阈值
+    pub fn from_config(cfg: &Config) -> Result<Self> {
+        let backlog_policy = match cfg.pubsub_backlog_policy.as_str() {
+            "drop" => BacklogPolicy::Drop,
+            "disconnect" => BacklogPolicy::Disconnect,
+            other => bail!("unknown pubsub_backlog_policy '{}', expected \"drop\" or \"disconnect\"", other),
+        };
+        Ok(PubSub {
+            backlog_limit: cfg.pubsub_backlog_limit,
+            backlog_policy,
+            slow_backlog: cfg.pubsub_slow_subscriber_backlog,
+            ..Self::default()
+        })
+    }
+
+    fn registry(&self, kind: Kind) -> &Registry {
+        match kind {
+            Kind::Channel => &self.channels,
+            Kind::Pattern => &self.patterns,
+            Kind::Shard => &self.shard_channels,
+        }
+    }
+
+    /// SUBSCRIBE / PSUBSCRIBE / SSUBSCRIBE，每个频道一条回复
+    pub fn subscribe(
+        &self,
+        kind: Kind,
+        names: &[String],
+        client_id: u64,
+        sender: &PushSender,
+        subs: &mut Subscriptions,
+    ) -> Vec<Frame> {
+        let registry = self.registry(kind);
+
+        names
+            .iter()
+            .map(|name| {
+                if subs.set_mut(kind).insert(name.clone()) {
+                    register(registry, name, client_id, sender);
+                }
+                reply(kind.subscribe_reply(), Some(name), subs.count(kind))
+            })
+            .collect()
+    }
+
+    /// UNSUBSCRIBE / PUNSUBSCRIBE / SUNSUBSCRIBE，不带参数时退订该种类的全部
+    pub fn unsubscribe(
+        &self,
+        kind: Kind,
+        names: &[String],
+        client_id: u64,
+        subs: &mut Subscriptions,
+    ) -> Vec<Frame> {
+        let registry = self.registry(kind);
+
+        let targets: Vec<String> = if names.is_empty() {
+            subs.set_mut(kind).iter().cloned().collect()
+        } else {
+            names.to_vec()
+        };
+
+        // 本来就没有任何订阅时，回复一条频道为 nil 的消息
+        if targets.is_empty() {
+            return vec![reply(kind.unsubscribe_reply(), None, subs.count(kind))];
+        }
+
+        targets
+            .iter()
+            .map(|name| {
+                if subs.set_mut(kind).remove(name) {
+                    unregister(registry, name, client_id);
+                }
+                reply(kind.unsubscribe_reply(), Some(name), subs.count(kind))
+            })
+            .collect()
+    }
+
+    /// 连接断开时清理它的全部订阅
+    pub fn remove_client(&self, client_id: u64, subs: &Subscriptions) {
+        for name in &subs.channels {
+            unregister(&self.channels, name, client_id);
+        }
+        for name in &subs.patterns {
+            unregister(&self.patterns, name, client_id);
+        }
+        for name in &subs.shard_channels {
+            unregister(&self.shard_channels, name, client_id);
+        }
+    }
+
+    /// 向一个订阅者投递消息：积压超出上限时丢弃（或断开该客户端），投递成功返回 true
+    fn deliver(&self, channel: &Channel, client_id: u64, sender: &PushSender, msg: Frame) -> bool {
+        let queued = sender.queued();
+        let sent = if self.backlog_limit > 0 && queued >= self.backlog_limit {
+            if self.backlog_policy == BacklogPolicy::Disconnect && sender.close() {
+                warn!("Disconnecting subscriber {} with {} pending messages", client_id, queued);
+                self.disconnected.fetch_add(1, Ordering::Relaxed);
+            }
+            false
+        } else {
+            sender.send(msg)
+        };
+        if !sent {
+            channel.dropped.fetch_add(1, Ordering::Relaxed);
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+
+        channel.delivered.fetch_add(1, Ordering::Relaxed);
+        self.delivered.fetch_add(1, Ordering::Relaxed);
+        // 积压刚达到阈值时记录一次，之后直到积压回落都不再重复
+        if self.slow_backlog > 0 && queued + 1 == self.slow_backlog {
+            warn!("Subscriber {} is slow: {} messages pending", client_id, queued + 1);
+            self.slow_events.fetch_add(1, Ordering::Relaxed);
+        }
+        true
+    }
+
+    /// PUBLISH，返回收到消息的客户端数
+    pub fn publish(&self, channel: &str, message: &[u8]) -> usize {
+        let mut receivers = 0;
+
+        if let Some(entry) = self.channels.get(channel) {
+            for (&client_id, sender) in &entry.subs {
+                let msg = Frame::Push(vec![
+                    Frame::bulk("message"),
+                    Frame::bulk(channel),
+                    Frame::bulk(message.to_vec()),
+                ]);
+                if self.deliver(&entry, client_id, sender, msg) {
+                    receivers += 1;
+                }
+            }
+        }
+
+        for entry in self.patterns.iter() {
+            if !glob_match(entry.key().as_bytes(), channel.as_bytes()) {
+                continue;
+            }
+            for (&client_id, sender) in &entry.value().subs {
+                let msg = Frame::Push(vec![
+                    Frame::bulk("pmessage"),
+                    Frame::bulk(entry.key().as_str()),
+                    Frame::bulk(channel),
+                    Frame::bulk(message.to_vec()),
+                ]);
+                if self.deliver(entry.value(), client_id, sender, msg) {
+                    receivers += 1;
+                }
+            }
+        }
+
+        receivers
+    }
+
+    /// SPUBLISH，只投递给该分片频道的订阅者，模式订阅不参与匹配
+    pub fn spublish(&self, channel: &str, message: &[u8]) -> usize {
+        let Some(entry) = self.shard_channels.get(channel) else {
+            return 0;
+        };
+        entry
+            .subs
+            .iter()
+            .filter(|&(&client_id, sender)| {
+                let msg = Frame::Push(vec![
+                    Frame::bulk("smessage"),
+                    Frame::bulk(channel),
+                    Frame::bulk(message.to_vec()),
+                ]);
+                self.deliver(&entry, client_id, sender, msg)
+            })
+            .count()
+    }
+
+    /// 投递统计与当前的慢订阅者数
+    pub fn stats(&self) -> PubSubStats {
+        let mut per_channel = Vec::new();
+        let mut slow = HashSet::new();
+        for (kind, registry) in [
+            (Kind::Channel, &self.channels),
+            (Kind::Pattern, &self.patterns),
+            (Kind::Shard, &self.shard_channels),
+        ] {
+            let mut stats: Vec<ChannelStats> = registry
+                .iter()
+                .map(|entry| {
+                    let channel = entry.value();
+                    if self.slow_backlog > 0 {
+                        slow.extend(
+                            channel.subs.iter().filter(|(_, sender)| sender.queued() >= self.slow_backlog).map(|(id, _)| *id),
+                        );
+                    }
+                    ChannelStats {
+                        kind,
+                        name: entry.key().clone(),
+                        subscribers: channel.subs.len(),
+                        delivered: channel.delivered.load(Ordering::Relaxed),
+                        dropped: channel.dropped.load(Ordering::Relaxed),
+                    }
+                })
+                .collect();
+            stats.sort_by(|a, b| a.name.cmp(&b.name));
+            per_channel.extend(stats);
+        }
+
+        PubSubStats {
+            channels: self.channels.len(),
+            patterns: self.patterns.len(),
+            shard_channels: self.shard_channels.len(),
+            delivered: self.delivered.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            disconnected: self.disconnected.load(Ordering::Relaxed),
+            slow_subscribers: slow.len(),
+            slow_events: self.slow_events.load(Ordering::Relaxed),
+            per_channel,
+        }
+    }
+
+    /// PUBSUB CHANNELS / NUMSUB / NUMPAT / SHARDCHANNELS / SHARDNUMSUB
+    pub fn introspect(&self, args: &[String]) -> Frame {
+        let Some(sub) = args.first() else {
+            return Frame::error("ERR wrong number of arguments for 'pubsub' command");
+        };
+
+        match (sub.to_uppercase().as_str(), args.len()) {
+            ("CHANNELS", 1 | 2) => list_channels(&self.channels, args.get(1)),
+            ("SHARDCHANNELS", 1 | 2) => list_channels(&self.shard_channels, args.get(1)),
+            ("NUMSUB", _) => count_subscribers(&self.channels, &args[1..]),
+            ("SHARDNUMSUB", _) => count_subscribers(&self.shard_channels, &args[1..]),
+            ("NUMPAT", 1) => Frame::Integer(self.patterns.len() as i64),
+            _ => Frame::error(format!(
+                "ERR unknown subcommand or wrong number of arguments for '{}'. Try PUBSUB HELP.",
+                sub
+            )),
+        }
+    }
+}
+
+/// 列出活跃频道，可按 glob 模式过滤
+fn list_channels(registry: &Registry, pattern: Option<&String>) -> Frame {
+    let mut names: Vec<String> = registry
+        .iter()
+        .map(|e| e.key().clone())
+        .filter(|name| pattern.is_none_or(|p| glob_match(p.as_bytes(), name.as_bytes())))
+        .collect();
+    names.sort();
+    Frame::Array(names.into_iter().map(Frame::bulk).collect())
+}
+
+/// 频道名与订阅数交替排列
+fn count_subscribers(registry: &Registry, names: &[String]) -> Frame {
+    let mut out = Vec::with_capacity(names.len() * 2);
+    for name in names {
+        let count = registry.get(name).map_or(0, |channel| channel.subs.len());
+        out.push(Frame::bulk(name.as_str()));
+        out.push(Frame::Integer(count as i64));
+    }
+    Frame::Array(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::OutputLimit;
+    use crate::output::push_channel;
+
+    fn names(s: &[&str]) -> Vec<String> {
+        s.iter().map(|x| x.to_string()).collect()
+    }
+
+    #[test]
+    fn test_publish_and_unsubscribe() {
+        let hub = PubSub::new();
+        let (tx1, mut rx1) = push_channel(OutputLimit::default());
+        let (tx2, mut rx2) = push_channel(OutputLimit::default());
+        let mut subs1 = Subscriptions::default();
+        let mut subs2 = Subscriptions::default();
+
+        let replies = hub.subscribe(Kind::Channel, &names(&["news", "sport"]), 1, &tx1, &mut subs1);
+        assert_eq!(replies[1], reply("subscribe", Some("sport"), 2));
+        hub.subscribe(Kind::Pattern, &names(&["n*"]), 2, &tx2, &mut subs2);
+
+        assert_eq!(hub.publish("news", b"hello"), 2);
+        assert_eq!(
+            rx1.try_recv().unwrap(),
+            Frame::Push(vec![Frame::bulk("message"), Frame::bulk("news"), Frame::bulk("hello")])
+        );
+        assert_eq!(
+            rx2.try_recv().unwrap(),
+            Frame::Push(vec![
+                Frame::bulk("pmessage"),
+                Frame::bulk("n*"),
+                Frame::bulk("news"),
+                Frame::bulk("hello"),
+            ])
+        );
+
+        // 不带参数退订全部
+        let replies = hub.unsubscribe(Kind::Channel, &[], 1, &mut subs1);
+        assert_eq!(replies.len(), 2);
+        assert!(subs1.is_empty());
+        assert_eq!(hub.unsubscribe(Kind::Channel, &[], 1, &mut subs1), vec![reply("unsubscribe", None, 0)]);
+        assert_eq!(hub.publish("sport", b"x"), 0);
+    }
+
+    #[test]
+    fn test_introspection() {
+        let hub = PubSub::new();
+        let (tx, _rx) = push_channel(OutputLimit::default());
+        let mut subs = Subscriptions::default();
+        hub.subscribe(Kind::Channel, &names(&["a:1", "a:2", "b"]), 1, &tx, &mut subs);
+        hub.subscribe(Kind::Pattern, &names(&["a:*"]), 1, &tx, &mut subs);
+
+        assert_eq!(
+            hub.introspect(&names(&["CHANNELS", "a:*"])),
+            Frame::Array(vec![Frame::bulk("a:1"), Frame::bulk("a:2")])
+        );
+        assert_eq!(
+            hub.introspect(&names(&["NUMSUB", "b", "zzz"])),
+            Frame::Array(vec![
+                Frame::bulk("b"),
+                Frame::Integer(1),
+                Frame::bulk("zzz"),
+                Frame::Integer(0),
+            ])
+        );
+        assert_eq!(hub.introspect(&names(&["NUMPAT"])), Frame::Integer(1));
+
+        hub.remove_client(1, &subs);
+        assert_eq!(hub.introspect(&names(&["CHANNELS"])), Frame::Array(vec![]));
+        assert_eq!(hub.introspect(&names(&["NUMPAT"])), Frame::Integer(0));
+    }
+
+    #[test]
+    fn test_shard_channels() {
+        let hub = PubSub::new();
+        let (tx, mut rx) = push_channel(OutputLimit::default());
+        let mut subs = Subscriptions::default();
+        hub.subscribe(Kind::Channel, &names(&["orders"]), 1, &tx, &mut subs);
+
+        // 分片频道单独计数
+        let replies = hub.subscribe(Kind::Shard, &names(&["orders"]), 1, &tx, &mut subs);
+        assert_eq!(replies, vec![reply("ssubscribe", Some("orders"), 1)]);
+
+        assert_eq!(hub.spublish("orders", b"o1"), 1);
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            Frame::Push(vec![Frame::bulk("smessage"), Frame::bulk("orders"), Frame::bulk("o1")])
+        );
+        assert!(rx.try_recv().is_err());
+
+        assert_eq!(
+            hub.introspect(&names(&["SHARDNUMSUB", "orders"])),
+            Frame::Array(vec![Frame::bulk("orders"), Frame::Integer(1)])
+        );
+        hub.unsubscribe(Kind::Shard, &[], 1, &mut subs);
+        assert_eq!(hub.introspect(&names(&["SHARDCHANNELS"])), Frame::Array(vec![]));
+        assert_eq!(hub.spublish("orders", b"o2"), 0);
+        assert!(!subs.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_backlog_limits_and_stats() {
+        let cfg = Config { pubsub_backlog_limit: 3, pubsub_slow_subscriber_backlog: 2, ..Config::default() };
+        let hub = PubSub::from_config(&cfg).unwrap();
+        let (tx1, mut rx1) = push_channel(OutputLimit::default());
+        let (tx2, _rx2) = push_channel(OutputLimit::default());
+        let (mut subs1, mut subs2) = (Subscriptions::default(), Subscriptions::default());
+        hub.subscribe(Kind::Channel, &names(&["news"]), 1, &tx1, &mut subs1);
+        hub.subscribe(Kind::Pattern, &names(&["n*"]), 2, &tx2, &mut subs2);
+
+        // 两个订阅者都不读取：积压满 3 条后新消息被丢弃
+        for _ in 0..4 {
+            hub.publish("news", b"x");
+        }
+        let stats = hub.stats();
+        assert_eq!((stats.delivered, stats.dropped, stats.slow_subscribers, stats.slow_events), (6, 2, 2, 2));
+        assert_eq!(
+            stats.per_channel[0],
+            ChannelStats { kind: Kind::Channel, name: "news".to_string(), subscribers: 1, delivered: 3, dropped: 1 }
+        );
+        assert_eq!(stats.per_channel[1].kind, Kind::Pattern);
+
+        // 取走消息后积压回落，恢复投递
+        while rx1.try_recv().is_ok() {}
+        assert_eq!(hub.publish("news", b"y"), 1);
+        assert_eq!(hub.stats().slow_subscribers, 1);
+
+        // disconnect 策略：积压超出上限时断开订阅者
+        let cfg = Config { pubsub_backlog_limit: 1, pubsub_backlog_policy: "disconnect".to_string(), ..Config::default() };
+        let hub = PubSub::from_config(&cfg).unwrap();
+        hub.subscribe(Kind::Channel, &names(&["news"]), 1, &tx1, &mut Subscriptions::default());
+        hub.publish("news", b"a");
+        hub.publish("news", b"b");
+        tx1.overflowed().await;
+        assert_eq!(hub.stats().disconnected, 1);
+
+        let cfg = Config { pubsub_backlog_policy: "block".to_string(), ..Config::default() };
+        assert!(PubSub::from_config(&cfg).is_err());
+    }
+}
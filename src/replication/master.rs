@@ -0,0 +1,170 @@
+// src/replication/master.rs
+
+//! 主节点一侧：为连上来的副本做全量同步并转发命令流
+//!
+//! 副本的握手与 Redis 相同：`PING`、`REPLCONF listening-port <port>`、`PSYNC ? -1`。
+//! 主节点回复 `+FULLRESYNC <replid> <offset>`，随后以 `$<长度>\r\n<RDB>`
+//! （末尾没有 `\r\n`）发送全量数据，之后连接上只有命令流。
+//!
+//! 重连的副本发送 `PSYNC <复制 ID> <偏移量>`：能够续传时主节点回复
+//! `+CONTINUE <复制 ID>`，随后直接补发积压缓冲区中缺失的命令流。
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use anyhow::Result;
+use bytes::BytesMut;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tracing::info;
+
+use super::{ReplicaStream, Replication};
+use crate::persistence::Persistence;
+use crate::protocol::{Frame, ParserLimits, RespParser};
+
+/// REPLCONF：握手阶段副本告知自己的监听端口与能力
+pub fn replconf(args: &[String], listening_port: &mut Option<u16>) -> Frame {
+    if !args.len().is_multiple_of(2) {
+        return Frame::error("ERR syntax error");
+    }
+    for pair in args.chunks(2) {
+        match pair[0].to_lowercase().as_str() {
+            "listening-port" => match pair[1].parse::<u16>() {
+                Ok(port) => *listening_port = Some(port),
+                Err(_) => return Frame::error("ERR value is not an integer or out of range"),
+            },
+            // 目前不区分副本能力
+            "capa" | "ip-address" => {}
+            other => return Frame::error(format!("ERR Unrecognized REPLCONF option: {}", other)),
+        }
+    }
+    Frame::ok()
+}
+
+/// PSYNC / SYNC：把当前连接转为复制连接，直到副本断开才返回
+///
+/// `args` 为 PSYNC 的参数（SYNC 没有参数）。无法续传时做全量同步：在两条命令之间
+/// 登记副本并生成 RDB，因此 RDB 恰好包含命令流开始前的全部写入；
+/// 生成 RDB 期间的写入先积压在该副本的队列中
+#[allow(clippy::too_many_arguments)]
+pub async fn serve_replica<R, W>(
+    args: &[String],
+    reader: &mut R,
+    writer: &mut W,
+    read_buf: &mut BytesMut,
+    peer: SocketAddr,
+    client_id: u64,
+    listening_port: Option<u16>,
+    pers: &Arc<Persistence>,
+    replication: &Arc<Replication>,
+) -> Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let port = listening_port.unwrap_or(0);
+    // 副本请求的是下一个字节的偏移量，与 Redis 相同
+    let partial = match args {
+        [replid, offset] => offset
+            .parse::<u64>()
+            .ok()
+            .and_then(|offset| replication.attach_at(client_id, peer.ip(), port, replid, offset.checked_sub(1)?)),
+        _ => None,
+    };
+
+    let result = async {
+        let mut stream = match partial {
+            Some((stream, missing)) => {
+                writer.write_all(format!("+CONTINUE {}\r\n", replication.replid()).as_bytes()).await?;
+                writer.write_all(&missing).await?;
+                writer.flush().await?;
+                info!(
+                    "Partial resynchronization with replica {} (listening port {:?}) accepted, {} bytes of backlog sent",
+                    peer,
+                    listening_port,
+                    missing.len()
+                );
+                stream
+            }
+            None => full_resync(writer, peer, client_id, listening_port, pers, replication).await?,
+        };
+        replication.set_online(client_id);
+
+        let mut parser = RespParser::new(ParserLimits {
+            max_multibulk_len: pers.cfg.proto_max_multibulk_len,
+            max_bulk_len: pers.cfg.proto_max_bulk_len,
+        });
+        loop {
+            tokio::select! {
+                record = stream.recv() => {
+                    // 副本被移除（如本节点改为跟随其他主节点）时关闭连接
+                    let Some(record) = record else { break };
+                    writer.write_all(&record).await?;
+                    while let Some(record) = stream.try_recv() {
+                        writer.write_all(&record).await?;
+                    }
+                    writer.flush().await?;
+                }
+                read = reader.read_buf(read_buf) => {
+                    if read? == 0 {
+                        break;
+                    }
+                    // 副本只会发来 REPLCONF ACK <偏移量>
+                    let mut parse = || parser.parse(read_buf).map_err(|e| anyhow::anyhow!("bad data from replica: {}", e));
+                    while let Some(parts) = parse()? {
+                        if let [cmd, sub, offset] = &parts[..]
+                            && cmd.eq_ignore_ascii_case("REPLCONF")
+                            && sub.eq_ignore_ascii_case("ACK")
+                            && let Ok(offset) = offset.parse::<u64>()
+                        {
+                            replication.ack(client_id, offset);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+    .await;
+    replication.detach(client_id);
+    result
+}
+
+/// 生成 RDB 并发送给副本，返回此后的命令流
+async fn full_resync<W>(
+    writer: &mut W,
+    peer: SocketAddr,
+    client_id: u64,
+    listening_port: Option<u16>,
+    pers: &Arc<Persistence>,
+    replication: &Arc<Replication>,
+) -> Result<ReplicaStream>
+where
+    W: AsyncWrite + Unpin,
+{
+    let (p, repl) = (pers.clone(), replication.clone());
+    let dumped = tokio::task::spawn_blocking(move || {
+        let mut attached = None;
+        let port = listening_port.unwrap_or(0);
+        let rdb = p.dump_rdb(|| attached = Some(repl.attach(client_id, peer.ip(), port)));
+        if rdb.is_err() {
+            repl.detach(client_id);
+        }
+        rdb.map(|rdb| (rdb, attached))
+    })
+    .await?;
+    let (rdb, Some((stream, replid, offset))) = dumped? else {
+        unreachable!("dump_rdb always runs its start hook");
+    };
+
+    writer.write_all(format!("+FULLRESYNC {} {}\r\n", replid, offset).as_bytes()).await?;
+    writer.write_all(format!("${}\r\n", rdb.len()).as_bytes()).await?;
+    writer.write_all(&rdb).await?;
+    writer.flush().await?;
+    info!(
+        "Synchronization with replica {} (listening port {:?}) succeeded ({} bytes)",
+        peer,
+        listening_port,
+        rdb.len()
+    );
+    Ok(stream)
+}
@@ -0,0 +1,627 @@
+// src/replication/mod.rs
+
+//! 主从复制
+//!
+//! - 主节点：副本发送 `PSYNC` 后，主节点在两条命令之间登记该副本并生成一份 RDB
+//!   （见 `persistence::snapshot`）作为全量同步，之后把每条成功的写命令以与 AOF
+//!   相同的 RESP 记录实时转发给全部副本
+//! - 副本：`REPLICAOF host port` 后在后台连接主节点，加载全量数据，再通过引擎
+//!   逐条执行命令流；断线后自动重连
+//! - 部分重同步：主节点在积压缓冲区中保留最近转发过的命令流；副本重连时以
+//!   `PSYNC <复制 ID> <偏移量 + 1>` 请求续传，缓冲区仍覆盖该位置时主节点回复
+//!   `+CONTINUE` 并补发缺失的部分，否则退回全量同步
+//! - 确认：副本每处理完一批命令流就以 `REPLCONF ACK <偏移量>` 告知主节点，
+//!   `WAIT` 据此等待足够多的副本追上
+//! - 副本只读：普通客户端的写命令被拒绝，数据只来自主节点
+//!
+//! 复制偏移量是命令流的字节数：主节点每转发一条记录就前进相应字节，
+//! 副本从全量同步时主节点的偏移量开始，按收到的命令流同步前进
+
+pub mod master;
+pub mod replica;
+
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use bytes::Bytes;
+use sha2::{Digest, Sha256};
+use tokio::sync::{mpsc, Notify};
+use tokio::task::AbortHandle;
+use tracing::{info, warn};
+
+use crate::config::OutputLimit;
+use crate::engine::KvEngine;
+use crate::output::OutputLimiter;
+use crate::persistence::{aof, Persistence};
+use crate::protocol::Frame;
+
+/// 副本与主节点之间连接的状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkState {
+    /// 正在连接或等待重连
+    Connecting,
+    /// 已连上，正在接收全量数据
+    Sync,
+    /// 全量同步完成，正在接收命令流
+    Connected,
+}
+
+/// 本节点作为副本时跟随的主节点
+struct MasterLink {
+    host: String,
+    port: u16,
+    state: LinkState,
+    task: AbortHandle,
+}
+
+/// 连到本节点的一个副本
+struct ReplicaConn {
+    tx: mpsc::UnboundedSender<Vec<u8>>,
+    /// 已转发、尚未写给副本的字节数
+    pending: Arc<AtomicUsize>,
+    limiter: OutputLimiter,
+    ip: IpAddr,
+    /// 副本通过 REPLCONF listening-port 告知的端口，未告知时为 0
+    port: u16,
+    /// 全量数据或续传数据已发送完毕
+    online: bool,
+    /// 副本确认已处理到的偏移量
+    ack: u64,
+    last_ack: Instant,
+}
+
+impl ReplicaConn {
+    /// 登记一个副本，返回其命令流
+    fn open(ip: IpAddr, port: u16, offset: u64, limit: OutputLimit) -> (Self, ReplicaStream) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let pending = Arc::new(AtomicUsize::new(0));
+        let conn = ReplicaConn {
+            tx,
+            pending: pending.clone(),
+            limiter: OutputLimiter::new(limit),
+            ip,
+            port,
+            online: false,
+            ack: offset,
+            last_ack: Instant::now(),
+        };
+        (conn, ReplicaStream { rx, pending })
+    }
+
+    /// 转发一条记录；副本积压的数据超出输出缓冲区上限或连接已关闭时返回 false
+    fn send(&mut self, record: &[u8]) -> bool {
+        let pending = self.pending.fetch_add(record.len(), Ordering::Relaxed) + record.len();
+        if self.limiter.exceeded(pending) {
+            warn!("Dropping replica {}:{} for exceeding the replica output buffer limit ({} bytes pending)", self.ip, self.port, pending);
+            return false;
+        }
+        self.tx.send(record.to_vec()).is_ok()
+    }
+}
+
+/// 发给一个副本的命令流；主节点丢弃该副本后 `recv` 返回 None
+struct ReplicaStream {
+    rx: mpsc::UnboundedReceiver<Vec<u8>>,
+    pending: Arc<AtomicUsize>,
+}
+
+impl ReplicaStream {
+    async fn recv(&mut self) -> Option<Vec<u8>> {
+        let record = self.rx.recv().await?;
+        self.pending.fetch_sub(record.len(), Ordering::Relaxed);
+        Some(record)
+    }
+
+    fn try_recv(&mut self) -> Option<Vec<u8>> {
+        let record = self.rx.try_recv().ok()?;
+        self.pending.fetch_sub(record.len(), Ordering::Relaxed);
+        Some(record)
+    }
+}
+
+/// INFO replication 中的一个副本
+#[derive(Debug, Clone)]
+pub struct ReplicaInfo {
+    pub ip: IpAddr,
+    pub port: u16,
+    pub online: bool,
+    pub offset: u64,
+    /// 距上次确认的秒数
+    pub lag_secs: u64,
+}
+
+/// INFO replication 所需的复制状态
+#[derive(Debug, Clone)]
+pub struct ReplicationInfo {
+    /// 作为副本时跟随的主节点：(host, port, 连接状态)
+    pub master: Option<(String, u16, LinkState)>,
+    pub replicas: Vec<ReplicaInfo>,
+    pub replid: String,
+    pub offset: u64,
+    pub backlog_size: usize,
+    /// 积压缓冲区中第一个字节的偏移量（与 Redis 相同从 1 开始计）
+    pub backlog_first_byte_offset: u64,
+    pub backlog_histlen: usize,
+}
+
+/// 复制积压缓冲区：最近转发过的命令流，超出容量时丢弃最旧的字节
+struct Backlog {
+    buf: VecDeque<u8>,
+    size: usize,
+    /// `buf` 第一个字节对应的复制偏移量
+    start: u64,
+}
+
+impl Backlog {
+    fn push(&mut self, record: &[u8]) {
+        self.buf.extend(record);
+        let excess = self.buf.len().saturating_sub(self.size);
+        self.buf.drain(..excess);
+        self.start += excess as u64;
+    }
+
+    /// 从 `offset` 到当前偏移量之间的命令流；`offset` 不在缓冲区范围内时返回 `None`
+    fn since(&self, offset: u64) -> Option<Vec<u8>> {
+        let skip = usize::try_from(offset.checked_sub(self.start)?).ok()?;
+        (skip <= self.buf.len()).then(|| self.buf.range(skip..).copied().collect())
+    }
+
+    /// 清空缓冲区，从 `offset` 重新开始记录
+    fn reset(&mut self, offset: u64) {
+        self.buf.clear();
+        self.start = offset;
+    }
+}
+
+/// 复制状态，整个进程一份
+pub struct Replication {
+    /// 复制 ID：本节点数据历史的标识，跟随主节点时与主节点相同
+    replid: Mutex<String>,
+    /// 复制偏移量
+    offset: AtomicU64,
+    /// 本节点的监听端口，作为副本时告知主节点
+    listening_port: u16,
+    master: Mutex<Option<MasterLink>>,
+    /// 已连接的副本，按客户端 ID 索引
+    replicas: Mutex<HashMap<u64, ReplicaConn>>,
+    /// 与 `offset` 一同在 `replicas` 锁内更新
+    backlog: Mutex<Backlog>,
+    /// 副本确认偏移量时通知等待中的 WAIT
+    acked: Notify,
+    /// 每个副本尚未发出的命令流的上限
+    output_limit: OutputLimit,
+}
+
+impl Replication {
+    pub fn new(listening_port: u16, backlog_size: usize, output_limit: OutputLimit) -> Self {
+        Replication {
+            replid: Mutex::new(new_replid()),
+            offset: AtomicU64::new(0),
+            listening_port,
+            master: Mutex::new(None),
+            replicas: Mutex::new(HashMap::new()),
+            backlog: Mutex::new(Backlog { buf: VecDeque::new(), size: backlog_size, start: 0 }),
+            acked: Notify::new(),
+            output_limit,
+        }
+    }
+
+    pub fn replid(&self) -> String {
+        self.replid.lock().unwrap().clone()
+    }
+
+    pub fn offset(&self) -> u64 {
+        self.offset.load(Ordering::SeqCst)
+    }
+
+    /// 是否正在跟随某个主节点
+    pub fn is_replica(&self) -> bool {
+        self.master.lock().unwrap().is_some()
+    }
+
+    /// 转发一条写命令
+    pub fn feed_command(&self, parts: &[Bytes]) {
+        self.feed(&aof::encode(parts));
+    }
+
+    /// 以 `MULTI ... EXEC` 的形式转发一个事务中的写命令
+    pub fn feed_transaction(&self, cmds: &[Vec<Bytes>]) {
+        if let Some(record) = aof::encode_transaction(cmds) {
+            self.feed(&record);
+        }
+    }
+
+    /// 推进偏移量并把记录发给全部副本；须在 `Persistence::write_guard` 持有期间调用，
+    /// 与全量同步的起点严格区分先后
+    fn feed(&self, record: &[u8]) {
+        let mut replicas = self.replicas.lock().unwrap();
+        self.backlog.lock().unwrap().push(record);
+        self.offset.fetch_add(record.len() as u64, Ordering::SeqCst);
+        replicas.retain(|_, replica| replica.send(record));
+    }
+
+    /// 复制状态快照，供 INFO replication 使用
+    pub fn info(&self) -> ReplicationInfo {
+        let master = self.master.lock().unwrap().as_ref().map(|link| (link.host.clone(), link.port, link.state));
+        let replicas = self.replicas.lock().unwrap();
+        let backlog = self.backlog.lock().unwrap();
+        let mut list: Vec<(u64, ReplicaInfo)> = replicas
+            .iter()
+            .map(|(id, replica)| {
+                let info = ReplicaInfo {
+                    ip: replica.ip,
+                    port: replica.port,
+                    online: replica.online,
+                    offset: replica.ack,
+                    lag_secs: replica.last_ack.elapsed().as_secs(),
+                };
+                (*id, info)
+            })
+            .collect();
+        list.sort_by_key(|(id, _)| *id);
+        ReplicationInfo {
+            master,
+            replicas: list.into_iter().map(|(_, info)| info).collect(),
+            replid: self.replid(),
+            offset: self.offset(),
+            backlog_size: backlog.size,
+            backlog_first_byte_offset: backlog.start + 1,
+            backlog_histlen: backlog.buf.len(),
+        }
+    }
+
+    /// 登记一个开始全量同步的副本，返回其命令流以及同步起点的 (复制 ID, 偏移量)
+    fn attach(&self, id: u64, ip: IpAddr, port: u16) -> (ReplicaStream, String, u64) {
+        let mut replicas = self.replicas.lock().unwrap();
+        let offset = self.offset();
+        let (conn, stream) = ReplicaConn::open(ip, port, offset, self.output_limit);
+        replicas.insert(id, conn);
+        (stream, self.replid(), offset)
+    }
+
+    /// 尝试从 `offset` 续传：复制 ID 一致且积压缓冲区覆盖该位置时登记副本，
+    /// 返回其命令流以及需要补发的部分
+    fn attach_at(
+        &self,
+        id: u64,
+        ip: IpAddr,
+        port: u16,
+        replid: &str,
+        offset: u64,
+    ) -> Option<(ReplicaStream, Vec<u8>)> {
+        let mut replicas = self.replicas.lock().unwrap();
+        if *self.replid.lock().unwrap() != replid {
+            return None;
+        }
+        let missing = self.backlog.lock().unwrap().since(offset)?;
+        let (conn, stream) = ReplicaConn::open(ip, port, offset, self.output_limit);
+        replicas.insert(id, conn);
+        Some((stream, missing))
+    }
+
+    /// REPLCONF ACK：记录副本已处理到的偏移量
+    fn ack(&self, id: u64, offset: u64) {
+        if let Some(replica) = self.replicas.lock().unwrap().get_mut(&id) {
+            replica.ack = replica.ack.max(offset);
+            replica.last_ack = Instant::now();
+        }
+        self.acked.notify_waiters();
+    }
+
+    /// 已确认处理到 `offset` 的副本数
+    fn count_acked(&self, offset: u64) -> usize {
+        self.replicas.lock().unwrap().values().filter(|replica| replica.ack >= offset).count()
+    }
+
+    /// 等待至少 `numreplicas` 个副本确认当前偏移量之前的全部写入，超时（`None` 表示一直等待）
+    /// 后返回已确认的副本数
+    pub async fn wait(&self, numreplicas: usize, timeout: Option<Duration>) -> usize {
+        let target = self.offset();
+        let deadline = timeout.map(|t| tokio::time::Instant::now() + t);
+        loop {
+            // 先登记通知再检查，避免错过检查与等待之间到达的确认
+            let notified = self.acked.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            let acked = self.count_acked(target);
+            if acked >= numreplicas {
+                return acked;
+            }
+            match deadline {
+                Some(deadline) => {
+                    if tokio::time::timeout_at(deadline, notified).await.is_err() {
+                        return self.count_acked(target);
+                    }
+                }
+                None => notified.await,
+            }
+        }
+    }
+
+    /// WAIT numreplicas timeout（毫秒，0 表示一直等待）
+    pub async fn wait_command(&self, args: &[String]) -> Frame {
+        if self.is_replica() {
+            return Frame::error("ERR WAIT cannot be used with replica instances.");
+        }
+        let (Ok(numreplicas), Ok(timeout)) = (args[0].parse::<usize>(), args[1].parse::<u64>()) else {
+            return Frame::error("ERR value is not an integer or out of range");
+        };
+        let timeout = (timeout > 0).then(|| Duration::from_millis(timeout));
+        Frame::Integer(self.wait(numreplicas, timeout).await as i64)
+    }
+
+    /// 全量数据或续传数据已发送完毕
+    fn set_online(&self, id: u64) {
+        if let Some(replica) = self.replicas.lock().unwrap().get_mut(&id) {
+            replica.online = true;
+        }
+    }
+
+    fn detach(&self, id: u64) {
+        self.replicas.lock().unwrap().remove(&id);
+    }
+
+    fn set_link_state(&self, state: LinkState) {
+        if let Some(link) = self.master.lock().unwrap().as_mut() {
+            link.state = state;
+        }
+    }
+
+    /// 全量同步完成：沿用主节点的复制 ID 与偏移量
+    ///
+    /// 数据集已被替换，断开本节点的下级副本，它们重连后重新全量同步
+    fn start_following(&self, replid: String, offset: u64) {
+        let mut replicas = self.replicas.lock().unwrap();
+        replicas.clear();
+        *self.replid.lock().unwrap() = replid;
+        self.backlog.lock().unwrap().reset(offset);
+        self.offset.store(offset, Ordering::SeqCst);
+        drop(replicas);
+        self.set_link_state(LinkState::Connected);
+    }
+
+    /// 重连时请求续传的位置：(复制 ID, 下一个需要的字节的偏移量)
+    fn psync_position(&self) -> (String, u64) {
+        let _replicas = self.replicas.lock().unwrap();
+        (self.replid(), self.offset() + 1)
+    }
+
+    /// REPLICAOF host port：在后台开始跟随新的主节点
+    pub fn follow<E>(self: &Arc<Self>, host: String, port: u16, db: E, pers: Arc<Persistence>)
+    where
+        E: KvEngine + Send + Sync + 'static,
+    {
+        let mut master = self.master.lock().unwrap();
+        if let Some(old) = master.take() {
+            old.task.abort();
+        }
+        let task = tokio::spawn(replica::run(self.clone(), host.clone(), port, db, pers));
+        *master = Some(MasterLink { host, port, state: LinkState::Connecting, task: task.abort_handle() });
+    }
+
+    /// REPLICAOF NO ONE：停止复制，以当前数据成为主节点
+    ///
+    /// 数据从此与原主节点分叉，换一个新的复制 ID
+    pub fn promote(&self) {
+        if let Some(old) = self.master.lock().unwrap().take() {
+            old.task.abort();
+            *self.replid.lock().unwrap() = new_replid();
+        }
+    }
+
+    /// REPLICAOF / SLAVEOF 命令
+    pub fn replicaof_command<E>(self: &Arc<Self>, args: &[String], db: E, pers: Arc<Persistence>) -> Frame
+    where
+        E: KvEngine + Send + Sync + 'static,
+    {
+        if args[0].eq_ignore_ascii_case("NO") && args[1].eq_ignore_ascii_case("ONE") {
+            self.promote();
+            return Frame::ok();
+        }
+        let Ok(port) = args[1].parse::<u16>() else {
+            return Frame::error("ERR Invalid master port");
+        };
+        let already = self
+            .master
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|link| link.host == args[0] && link.port == port);
+        if already {
+            return Frame::Simple("OK Already connected to specified master".into());
+        }
+        info!("Replicating from {}:{}", args[0], port);
+        self.follow(args[0].clone(), port, db, pers);
+        Frame::ok()
+    }
+}
+
+/// 生成 40 个十六进制字符的随机复制 ID（集群节点 ID 使用同样的格式）
+pub(crate) fn new_replid() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let mut hasher = Sha256::new();
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    hasher.update(now.as_nanos().to_le_bytes());
+    hasher.update(std::process::id().to_le_bytes());
+    hasher.update(COUNTER.fetch_add(1, Ordering::Relaxed).to_le_bytes());
+    let mut id = hex::encode(hasher.finalize());
+    id.truncate(40);
+    id
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    use anyhow::Result;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    use crate::acl::Acl;
+    use crate::config::Config;
+    use crate::engine::kv::DbInstance;
+    use crate::engine::watch::WatchManager;
+    use crate::monitor::Monitor;
+    use crate::server;
+
+    /// 在随机端口启动一个完整的服务器，返回端口
+    async fn start_server(dir: &std::path::Path) -> Result<u16> {
+        let port = TcpListener::bind("127.0.0.1:0").await?.local_addr()?.port();
+        let cfg: Config = serde_json::from_str(
+            r#"{"aof":true,"rdb":false,"snapshot_interval_secs":60,"snapshot_threshold":20,
+                "metrics_enabled":false,"metrics_port":9090,"slowlog_threshold_ms":10}"#,
+        )?;
+        let (storage, pers) = Persistence::open(
+            cfg.clone(),
+            dir,
+            "kv.db".as_ref(),
+            "appendonly.aof".as_ref(),
+            "dump.rdb".as_ref(),
+        )?;
+        let db = DbInstance { db: storage, watch_manager: Arc::new(WatchManager::new()), waiters: Default::default(), stats: Default::default() };
+        let acl = Arc::new(Acl::from_config(&cfg)?);
+        let addr = format!("127.0.0.1:{}", port);
+        tokio::spawn(async move {
+            server::start_with_addr_db_and_pers(&addr, db, pers, Arc::new(Monitor::new()), acl).await
+        });
+        for _ in 0..50 {
+            if TcpStream::connect(("127.0.0.1", port)).await.is_ok() {
+                return Ok(port);
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        anyhow::bail!("server on port {} did not start", port)
+    }
+
+    async fn call(stream: &mut TcpStream, parts: &[&str]) -> Result<String> {
+        stream.write_all(&aof::encode(parts)).await?;
+        let mut buf = vec![0u8; 4096];
+        let n = stream.read(&mut buf).await?;
+        Ok(String::from_utf8_lossy(&buf[..n]).into_owned())
+    }
+
+    /// 反复执行命令直到得到期望的回复
+    async fn wait_for(stream: &mut TcpStream, parts: &[&str], expected: &str) -> Result<()> {
+        let mut last = String::new();
+        for _ in 0..100 {
+            last = call(stream, parts).await?;
+            if last == expected {
+                return Ok(());
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        anyhow::bail!("{:?} returned {:?}, expected {:?}", parts, last, expected)
+    }
+
+    /// 读满 `len` 字节
+    async fn read_n(stream: &mut TcpStream, len: usize) -> Result<String> {
+        let mut buf = vec![0u8; len];
+        stream.read_exact(&mut buf).await?;
+        Ok(String::from_utf8_lossy(&buf).into_owned())
+    }
+
+    #[test]
+    fn test_backlog_keeps_recent_bytes() {
+        let mut backlog = Backlog { buf: VecDeque::new(), size: 4, start: 10 };
+        backlog.push(b"abc");
+        assert_eq!(backlog.since(10).as_deref(), Some(&b"abc"[..]));
+        assert_eq!(backlog.since(13).as_deref(), Some(&b""[..]));
+        assert_eq!(backlog.since(14), None);
+
+        // 超出容量后最旧的字节被丢弃
+        backlog.push(b"de");
+        assert_eq!(backlog.since(10), None);
+        assert_eq!(backlog.since(11).as_deref(), Some(&b"bcde"[..]));
+
+        backlog.reset(100);
+        assert_eq!(backlog.since(100).as_deref(), Some(&b""[..]));
+        assert_eq!(backlog.since(11), None);
+    }
+
+    #[tokio::test]
+    async fn test_psync_continues_from_backlog() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let port = start_server(dir.path()).await?;
+        let mut client = TcpStream::connect(("127.0.0.1", port)).await?;
+
+        // 以原始连接充当副本：先全量同步，记下复制 ID 与偏移量后断开
+        let mut replica = TcpStream::connect(("127.0.0.1", port)).await?;
+        let reply = call(&mut replica, &["PSYNC", "?", "-1"]).await?;
+        let fields: Vec<&str> = reply.split_whitespace().collect();
+        assert_eq!(fields[0], "+FULLRESYNC");
+        let (replid, offset) = (fields[1].to_string(), fields[2].parse::<u64>()?);
+        drop(replica);
+
+        // 断线期间的写入在重连后从积压缓冲区补发
+        call(&mut client, &["SET", "a", "1"]).await?;
+        let mut replica = TcpStream::connect(("127.0.0.1", port)).await?;
+        let next = (offset + 1).to_string();
+        let expected = format!("+CONTINUE {}\r\n", replid);
+        replica.write_all(&aof::encode(&["PSYNC".to_string(), replid.clone(), next.clone()])).await?;
+        assert_eq!(read_n(&mut replica, expected.len()).await?, expected);
+        let record = aof::encode(&["SET".to_string(), "a".to_string(), "1".to_string()]);
+        assert_eq!(read_n(&mut replica, record.len()).await?.as_bytes(), record);
+
+        // 复制 ID 不符时退回全量同步
+        let mut other = TcpStream::connect(("127.0.0.1", port)).await?;
+        assert!(call(&mut other, &["PSYNC", &"0".repeat(40), &next]).await?.starts_with("+FULLRESYNC"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_full_sync_then_command_stream() -> Result<()> {
+        let (master_dir, replica_dir) = (tempfile::tempdir()?, tempfile::tempdir()?);
+        let master_port = start_server(master_dir.path()).await?;
+        let replica_port = start_server(replica_dir.path()).await?;
+        let mut master = TcpStream::connect(("127.0.0.1", master_port)).await?;
+        let mut replica = TcpStream::connect(("127.0.0.1", replica_port)).await?;
+
+        // 全量同步：同步前已有的数据，以及副本上原有数据被替换
+        call(&mut master, &["SET", "a", "1"]).await?;
+        call(&mut master, &["RPUSH", "l", "x"]).await?;
+        call(&mut replica, &["SET", "stale", "1"]).await?;
+        let port = master_port.to_string();
+        assert_eq!(call(&mut replica, &["REPLICAOF", "127.0.0.1", &port]).await?, "+OK\r\n");
+        wait_for(&mut replica, &["GET", "a"], "$1\r\n1\r\n").await?;
+        assert_eq!(call(&mut replica, &["GET", "stale"]).await?, "$-1\r\n");
+
+        // 命令流：单条写命令与事务；失败的命令不转发
+        call(&mut master, &["INCR", "a"]).await?;
+        call(&mut master, &["INCR", "l"]).await?;
+        call(&mut master, &["MULTI"]).await?;
+        call(&mut master, &["RPUSH", "l", "y"]).await?;
+        call(&mut master, &["EXEC"]).await?;
+        wait_for(&mut replica, &["LRANGE", "l", "0", "-1"], "*2\r\n$1\r\nx\r\n$1\r\ny\r\n").await?;
+        assert_eq!(call(&mut replica, &["GET", "a"]).await?, "$1\r\n2\r\n");
+
+        // WAIT：副本已确认全部写入；要求的副本数不足时等到超时
+        call(&mut master, &["SET", "w", "1"]).await?;
+        assert_eq!(call(&mut master, &["WAIT", "1", "0"]).await?, ":1\r\n");
+        assert_eq!(call(&mut master, &["WAIT", "2", "50"]).await?, ":1\r\n");
+        assert!(call(&mut replica, &["WAIT", "1", "0"]).await?.starts_with("-ERR WAIT cannot be used"));
+
+        // 副本只读
+        assert!(call(&mut replica, &["SET", "b", "1"]).await?.starts_with("-READONLY"));
+
+        // INFO replication 两端的视图
+        let info = call(&mut master, &["INFO", "replication"]).await?;
+        assert!(info.contains("role:master\n") && info.contains("connected_slaves:1\n"), "{}", info);
+        assert!(info.contains(&format!("slave0:ip=127.0.0.1,port={},state=online", replica_port)), "{}", info);
+        let info = call(&mut replica, &["INFO", "replication"]).await?;
+        assert!(info.contains(&format!("role:slave\nmaster_host:127.0.0.1\nmaster_port:{}\n", port)), "{}", info);
+        assert!(info.contains("master_link_status:up\n"), "{}", info);
+
+        // HELLO 报告的角色
+        assert!(call(&mut master, &["HELLO", "2"]).await?.contains("$4\r\nrole\r\n$6\r\nmaster\r\n"));
+        assert!(call(&mut replica, &["HELLO", "2"]).await?.contains("$4\r\nrole\r\n$7\r\nreplica\r\n"));
+
+        // 提升为主节点后可写
+        assert_eq!(call(&mut replica, &["REPLICAOF", "NO", "ONE"]).await?, "+OK\r\n");
+        assert_eq!(call(&mut replica, &["SET", "b", "1"]).await?, "+OK\r\n");
+        Ok(())
+    }
+}
@@ -0,0 +1,191 @@
+// src/replication/replica.rs
+
+//! 副本一侧：连接主节点、加载全量数据并执行命令流
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use bytes::{Bytes, BytesMut};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tracing::{info, warn};
+
+use super::{LinkState, Replication};
+use crate::command;
+use crate::engine::{self, KvEngine};
+use crate::persistence::{aof, rdb, Persistence};
+use crate::protocol::{Frame, ParserLimits, RespParser};
+use crate::txn::executor::exec_all;
+
+/// 断线后重连前的等待时间
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// 跟随主节点，直到任务被取消（REPLICAOF NO ONE 或改为跟随其他主节点）
+///
+/// 重连时先尝试从断开的位置续传，主节点无法续传时重新全量同步
+pub(super) async fn run<E>(replication: Arc<Replication>, host: String, port: u16, db: E, pers: Arc<Persistence>)
+where
+    E: KvEngine + Send + Sync + 'static,
+{
+    loop {
+        replication.set_link_state(LinkState::Connecting);
+        let result = async {
+            let stream = TcpStream::connect((host.as_str(), port)).await?;
+            sync(stream, &replication, &db, &pers).await
+        }
+        .await;
+        match result {
+            Ok(()) => info!("Master {}:{} closed the replication link", host, port),
+            Err(e) => warn!("Replication with master {}:{} failed: {}", host, port, e),
+        }
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+/// 在一条已建立的连接上完成握手与同步，然后持续执行命令流，直到连接关闭
+pub async fn sync<S, E>(stream: S, replication: &Replication, db: &E, pers: &Arc<Persistence>) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    E: KvEngine,
+{
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut reader = BufReader::new(reader);
+
+    // 1) 握手
+    if let Some(password) = &pers.cfg.masterauth {
+        request(&mut reader, &mut writer, &["AUTH", password]).await?;
+    }
+    request(&mut reader, &mut writer, &["PING"]).await?;
+    let port = replication.listening_port.to_string();
+    request(&mut reader, &mut writer, &["REPLCONF", "listening-port", &port]).await?;
+    let (replid, next) = replication.psync_position();
+    let reply = request(&mut reader, &mut writer, &["PSYNC", &replid, &next.to_string()]).await?;
+    match reply.split_whitespace().collect::<Vec<_>>()[..] {
+        ["FULLRESYNC", replid, offset] => {
+            full_sync(&mut reader, replication, pers, replid.to_string(), offset.parse()?).await?
+        }
+        ["CONTINUE", ..] => {
+            replication.set_link_state(LinkState::Connected);
+            info!("Partial resync with master accepted, offset {}", replication.offset());
+        }
+        _ => bail!("unexpected reply to PSYNC: {}", reply),
+    }
+    let mut acked = replication.offset();
+    send_ack(&mut writer, acked).await?;
+
+    // 3) 命令流
+    let mut parser = RespParser::new(ParserLimits {
+        max_multibulk_len: pers.cfg.proto_max_multibulk_len,
+        max_bulk_len: pers.cfg.proto_max_bulk_len,
+    });
+    let mut buf = BytesMut::with_capacity(4096);
+    let mut txn: Option<Vec<Vec<Bytes>>> = None;
+    loop {
+        while let Some(parts) = parser.parse_bytes(&mut buf).map_err(|e| anyhow::anyhow!("bad command stream: {}", e))? {
+            if !parts.is_empty() {
+                apply(parts, &mut txn, replication, db, pers);
+            }
+        }
+        // 每处理完一批命令流就向主节点确认偏移量，供 WAIT 使用
+        if replication.offset() != acked {
+            acked = replication.offset();
+            send_ack(&mut writer, acked).await?;
+        }
+        if reader.read_buf(&mut buf).await? == 0 {
+            return Ok(());
+        }
+    }
+}
+
+/// 2) 全量同步：`$<长度>\r\n` 之后是 RDB 内容，加载后替换本地数据集
+async fn full_sync<R>(
+    reader: &mut R,
+    replication: &Replication,
+    pers: &Arc<Persistence>,
+    replid: String,
+    offset: u64,
+) -> Result<()>
+where
+    R: AsyncBufReadExt + Unpin,
+{
+    replication.set_link_state(LinkState::Sync);
+    let header = read_line(reader).await?;
+    let len = header
+        .strip_prefix('$')
+        .and_then(|n| n.parse::<usize>().ok())
+        .with_context(|| format!("bad RDB header from master: {}", header))?;
+    let mut payload = vec![0u8; len];
+    reader.read_exact(&mut payload).await?;
+    let snapshot = rdb::decode(&payload).context("bad RDB from master")?;
+    let keys = snapshot.entries.len();
+    let p = pers.clone();
+    tokio::task::spawn_blocking(move || p.load_snapshot(&snapshot)).await??;
+    replication.start_following(replid, offset);
+    info!("Full sync from master finished: {} keys, offset {}", keys, offset);
+    Ok(())
+}
+
+/// 执行主节点发来的一条命令：与普通客户端一样写入 AOF，并转发给本节点的下级副本
+fn apply<E: KvEngine>(
+    parts: Vec<Bytes>,
+    txn: &mut Option<Vec<Vec<Bytes>>>,
+    replication: &Replication,
+    db: &E,
+    pers: &Persistence,
+) {
+    let name = command::name_upper(&parts[0]);
+    match (name.as_str(), txn.as_mut()) {
+        ("MULTI", _) => *txn = Some(Vec::new()),
+        ("EXEC", Some(_)) => {
+            let cmds = txn.take().unwrap_or_default();
+            let _write_guard = pers.write_guard(&cmds);
+            if let Frame::Error(e) = exec_all(db, &cmds) {
+                warn!("Replication: failed to apply transaction from master: {}", e);
+            }
+            pers.append_transaction(&cmds);
+            replication.feed_transaction(&cmds);
+        }
+        (_, Some(cmds)) => cmds.push(parts),
+        (_, None) => {
+            let _write_guard = pers.write_guard(std::slice::from_ref(&parts));
+            let reply = engine::execute_non_txn_command(&name, &parts, db);
+            if let Frame::Error(e) = &reply {
+                warn!("Replication: failed to apply {} from master: {}", name, e);
+            } else if command::lookup(&name).is_some_and(|spec| spec.is_write()) {
+                pers.append_aof_and_maybe_snapshot(&parts);
+            }
+            replication.feed_command(&parts);
+        }
+    }
+}
+
+/// REPLCONF ACK：告知主节点已处理到的偏移量，主节点不回复
+async fn send_ack<W: AsyncWrite + Unpin>(writer: &mut W, offset: u64) -> Result<()> {
+    writer.write_all(&aof::encode(&["REPLCONF", "ACK", &offset.to_string()])).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+/// 发送一条命令并读取单行回复，错误回复转为 `Err`
+async fn request<R, W>(reader: &mut R, writer: &mut W, parts: &[&str]) -> Result<String>
+where
+    R: AsyncBufReadExt + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    writer.write_all(&aof::encode(parts)).await?;
+    writer.flush().await?;
+    let line = read_line(reader).await?;
+    match line.strip_prefix('+') {
+        Some(reply) => Ok(reply.to_string()),
+        None => bail!("master replied to {} with {}", parts[0], line),
+    }
+}
+
+async fn read_line<R: AsyncBufReadExt + Unpin>(reader: &mut R) -> Result<String> {
+    let mut line = String::new();
+    if reader.read_line(&mut line).await? == 0 {
+        bail!("connection closed by master");
+    }
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
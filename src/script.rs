@@ -0,0 +1,413 @@
+// src/script.rs
+
+//! Lua 脚本（EVAL / EVALSHA）
+//!
+//! 每次执行新建一个只加载 base / table / string / math 库的 Lua 解释器，
+//! 全局变量 `KEYS` / `ARGV` 是命令中的 key 与其余参数，`redis.call` / `redis.pcall`
+//! 经由命令表校验后执行引擎中的命令，回复按 RESP2 的规则在 Lua 值与回复之间转换。
+//!
+//! sled 上整个脚本在一个声明了 `KEYS` 的事务中执行，相对其他命令是原子的；
+//! 脚本运行出错（包括 `redis.call` 中的命令出错）时整个脚本的写入一起回滚。
+//! 脚本中访问的 key 应当通过 `KEYS` 传入：事务中只能对这些 key 做前缀扫描（HGETALL 等）。
+//!
+//! 服务端在执行前把 EVAL 的脚本记入 `ScriptCache`，EVALSHA 换成对应脚本原文的 EVAL，
+//! 因此 AOF 与复制流中记录的总是完整的脚本。`SCRIPT LOAD` 只编译并缓存脚本，
+//! 客户端之后可以只发送 SHA1；缓存只在内存中，重启或 `SCRIPT FLUSH` 后 EVALSHA 返回 NOSCRIPT，
+//! 客户端按惯例改用 EVAL 重新发送脚本。
+
+use anyhow::Error;
+use bytes::Bytes;
+use dashmap::DashMap;
+use mlua::{Lua, LuaOptions, StdLib, Table, Value, Variadic};
+use sha1::{Digest, Sha1};
+use sled::transaction::ConflictableTransactionError;
+
+use crate::command::{self, parse_arg};
+use crate::error::CommandError;
+use crate::engine::{self, KvEngine};
+use crate::protocol::Frame;
+
+/// `redis.call` 在 `redis.pcall` 之上实现：命令出错时抛出 Lua 错误
+const PRELUDE: &str = r#"
+function redis.call(...)
+    local reply = redis.pcall(...)
+    if type(reply) == "table" and reply.err then
+        error(reply.err, 0)
+    end
+    return reply
+end
+"#;
+
+const HELP: &[&str] = &[
+    "SCRIPT <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+    "EXISTS <sha1> [<sha1> ...]",
+    "    Return information about the existence of the scripts in the script cache.",
+    "FLUSH [ASYNC|SYNC]",
+    "    Flush the Lua scripts cache.",
+    "LOAD <script>",
+    "    Load a script into the scripts cache without executing it.",
+    "HELP",
+    "    Print this help.",
+];
+
+/// 脚本正文的 SHA1（小写十六进制）
+pub fn sha1_hex(body: &[u8]) -> String {
+    hex::encode(Sha1::digest(body))
+}
+
+/// 按 SHA1 缓存执行过的脚本，所有连接共享
+#[derive(Debug, Default)]
+pub struct ScriptCache {
+    scripts: DashMap<String, Bytes>,
+}
+
+impl ScriptCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 缓存脚本，返回其 SHA1
+    pub fn load(&self, body: &[u8]) -> String {
+        let sha = sha1_hex(body);
+        self.scripts.entry(sha.clone()).or_insert_with(|| Bytes::copy_from_slice(body));
+        sha
+    }
+
+    pub fn get(&self, sha: &[u8]) -> Option<Bytes> {
+        let sha = std::str::from_utf8(sha).ok()?.to_lowercase();
+        self.scripts.get(&sha).map(|body| body.clone())
+    }
+
+    pub fn len(&self) -> usize {
+        self.scripts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scripts.is_empty()
+    }
+
+    pub fn flush(&self) {
+        self.scripts.clear();
+    }
+
+    /// 执行 SCRIPT 子命令
+    pub fn execute(&self, args: &[Bytes]) -> Frame {
+        match (command::name_upper(&args[0]).as_str(), args.len()) {
+            ("HELP", 1) => Frame::Array(HELP.iter().map(|l| Frame::Simple(l.to_string())).collect()),
+            ("EXISTS", n) if n > 1 => Frame::Array(
+                args[1..].iter().map(|sha| Frame::Integer(self.get(sha).is_some() as i64)).collect(),
+            ),
+            // 缓存在内存中，同步与异步清空没有区别
+            ("FLUSH", 1) => {
+                self.flush();
+                Frame::ok()
+            }
+            ("FLUSH", 2) if ["ASYNC", "SYNC"].iter().any(|mode| args[1].eq_ignore_ascii_case(mode.as_bytes())) => {
+                self.flush();
+                Frame::ok()
+            }
+            ("FLUSH", _) => Frame::error("ERR SCRIPT FLUSH only support SYNC|ASYNC option"),
+            // 先编译一次，语法错误的脚本不进入缓存
+            ("LOAD", 2) => match compile(&args[1]) {
+                Ok(()) => Frame::bulk(self.load(&args[1])),
+                Err(msg) => Frame::error(msg),
+            },
+            _ => Frame::error(format!(
+                "ERR unknown subcommand or wrong number of arguments for '{}'. Try SCRIPT HELP.",
+                String::from_utf8_lossy(&args[0])
+            )),
+        }
+    }
+
+    /// 执行前的处理：EVAL 的脚本记入缓存，EVALSHA 换成对应脚本的 EVAL；
+    /// 脚本不在缓存中时返回 NOSCRIPT 错误。其他命令原样返回
+    pub fn resolve(&self, mut parts: Vec<Bytes>) -> Result<Vec<Bytes>, Frame> {
+        if parts[0].eq_ignore_ascii_case(b"EVAL") {
+            self.load(&parts[1]);
+        } else if parts[0].eq_ignore_ascii_case(b"EVALSHA") {
+            let Some(body) = self.get(&parts[1]) else {
+                return Err(CommandError::NoScript.into());
+            };
+            parts[0] = Bytes::from_static(b"EVAL");
+            parts[1] = body;
+        }
+        Ok(parts)
+    }
+}
+
+/// EVAL script numkeys [key ...] [arg ...]
+pub fn eval<E: KvEngine>(db: &E, parts: &[Bytes]) -> Frame {
+    let numkeys = match parse_arg::<i64>(&parts[2]) {
+        Some(n) if n < 0 => return Frame::error("ERR Number of keys can't be negative"),
+        Some(n) if n as usize > parts.len() - 3 => {
+            return Frame::error("ERR Number of keys can't be greater than number of args");
+        }
+        Some(n) => n as usize,
+        None => return Frame::error("ERR value is not an integer or out of range"),
+    };
+    let (keys, argv) = parts[3..].split_at(numkeys);
+    let body = &parts[1][..];
+
+    // sled 上自行开启事务；其他情况下调用方已处于事务上下文中（EXEC、内存引擎的写命令）
+    if db.sled_trees().is_some() {
+        let key_refs: Vec<&[u8]> = keys.iter().map(|k| &k[..]).collect();
+        db.transaction_for_keys(&key_refs, |tx| {
+            run(tx, body, keys, argv).map_err(|msg| ConflictableTransactionError::Abort(Error::msg(msg)))
+        })
+        .unwrap_or_else(|e| Frame::error(e.to_string()))
+    } else {
+        run(db, body, keys, argv).unwrap_or_else(Frame::error)
+    }
+}
+
+/// 在 `db` 上执行脚本，出错时返回错误消息
+fn run<E: KvEngine>(db: &E, body: &[u8], keys: &[Bytes], argv: &[Bytes]) -> Result<Frame, String> {
+    let lua = sandbox(keys, argv).map_err(|e| format!("ERR Error creating script environment: {}", e))?;
+    lua.scope(|scope| {
+        let redis: Table = lua.globals().get("redis")?;
+        redis.set("pcall", scope.create_function(|lua, args: Variadic<Value>| to_lua(lua, call(db, &args)))?)?;
+        let reply = lua.load(body).set_name("user_script").eval::<Value>()?;
+        Ok(from_lua(reply))
+    })
+    .map_err(error_message)
+}
+
+/// 只编译不执行，检查脚本的语法
+fn compile(body: &[u8]) -> Result<(), String> {
+    let lua = sandbox(&[], &[]).map_err(|e| format!("ERR Error creating script environment: {}", e))?;
+    lua.load(body).set_name("user_script").into_function().map(drop).map_err(error_message)
+}
+
+/// 新建解释器：只加载无副作用的标准库，设置 `KEYS` / `ARGV` 与 `redis` 表
+fn sandbox(keys: &[Bytes], argv: &[Bytes]) -> mlua::Result<Lua> {
+    let lua = Lua::new_with(StdLib::TABLE | StdLib::STRING | StdLib::MATH, LuaOptions::new())?;
+    let globals = lua.globals();
+    for name in ["dofile", "loadfile"] {
+        globals.set(name, Value::Nil)?;
+    }
+    let strings = |args: &[Bytes]| args.iter().map(|a| lua.create_string(a)).collect::<mlua::Result<Vec<_>>>();
+    globals.set("KEYS", lua.create_sequence_from(strings(keys)?)?)?;
+    globals.set("ARGV", lua.create_sequence_from(strings(argv)?)?)?;
+
+    let redis = lua.create_table()?;
+    redis.set("error_reply", lua.create_function(|lua, msg: String| reply_table(lua, "err", msg))?)?;
+    redis.set("status_reply", lua.create_function(|lua, msg: String| reply_table(lua, "ok", msg))?)?;
+    redis.set("sha1hex", lua.create_function(|_, body: mlua::String| Ok(sha1_hex(body.as_bytes())))?)?;
+    globals.set("redis", redis)?;
+    lua.load(PRELUDE).set_name("redis").exec()?;
+    drop(globals);
+    Ok(lua)
+}
+
+fn reply_table<'lua>(lua: &'lua Lua, field: &str, msg: String) -> mlua::Result<Table<'lua>> {
+    let table = lua.create_table()?;
+    table.set(field, msg)?;
+    Ok(table)
+}
+
+/// `redis.pcall(cmd, arg, ...)`
+fn call<E: KvEngine>(db: &E, args: &[Value]) -> Frame {
+    let mut parts = Vec::with_capacity(args.len());
+    for arg in args {
+        match arg {
+            Value::String(s) => parts.push(Bytes::copy_from_slice(s.as_bytes())),
+            Value::Integer(i) => parts.push(Bytes::from(i.to_string())),
+            Value::Number(n) => parts.push(Bytes::from(n.to_string())),
+            _ => return Frame::error("ERR Lua redis lib command arguments must be strings or integers"),
+        }
+    }
+    call_command(db, &parts)
+}
+
+/// 脚本与服务端函数中执行一条命令：按命令表校验，不允许事务、订阅等命令
+pub(crate) fn call_command<E: KvEngine>(db: &E, parts: &[Bytes]) -> Frame {
+    if parts.is_empty() {
+        return Frame::error("ERR Please specify at least one argument for this redis lib call");
+    }
+
+    let cmd = command::name_upper(&parts[0]);
+    match command::lookup(&cmd) {
+        None => return command::unknown_command_error(parts),
+        Some(spec) if !spec.check_arity(parts.len()) => return command::arity_error(&cmd),
+        Some(spec) if spec.has_flag("noscript") || spec.has_flag("pubsub") => {
+            return Frame::error("ERR This Redis command is not allowed from script");
+        }
+        Some(_) => {}
+    }
+    if let Err(e) = engine::purge_expired(db, parts) {
+        return CommandError::from(e).into();
+    }
+    engine::execute_non_txn_command(&cmd, parts, db)
+}
+
+/// 命令回复转换为 Lua 值
+fn to_lua(lua: &Lua, frame: Frame) -> mlua::Result<Value<'_>> {
+    Ok(match frame {
+        Frame::Simple(s) => Value::Table(reply_table(lua, "ok", s)?),
+        Frame::Error(e) => Value::Table(reply_table(lua, "err", e)?),
+        Frame::Integer(i) => Value::Integer(i),
+        Frame::Bulk(b) => Value::String(lua.create_string(&b)?),
+        Frame::Null | Frame::NullArray => Value::Boolean(false),
+        Frame::Double(d) => Value::String(lua.create_string(d.to_string())?),
+        Frame::Boolean(b) => Value::Integer(b as i64),
+        Frame::Array(items) | Frame::Set(items) | Frame::Push(items) => {
+            let table = lua.create_table_with_capacity(items.len(), 0)?;
+            for item in items {
+                table.raw_push(to_lua(lua, item)?)?;
+            }
+            Value::Table(table)
+        }
+        // 与 RESP2 一致，键值对展开成数组
+        Frame::Map(pairs) => {
+            let table = lua.create_table_with_capacity(pairs.len() * 2, 0)?;
+            for (k, v) in pairs {
+                table.raw_push(to_lua(lua, k)?)?;
+                table.raw_push(to_lua(lua, v)?)?;
+            }
+            Value::Table(table)
+        }
+    })
+}
+
+/// 脚本的返回值转换为回复
+fn from_lua(value: Value) -> Frame {
+    match value {
+        Value::Boolean(true) => Frame::Integer(1),
+        Value::Integer(i) => Frame::Integer(i),
+        Value::Number(n) => Frame::Integer(n as i64),
+        Value::String(s) => Frame::Bulk(s.as_bytes().to_vec()),
+        Value::Table(table) => {
+            if let Ok(Value::String(err)) = table.raw_get::<_, Value>("err") {
+                return Frame::Error(err.to_string_lossy().into_owned());
+            }
+            if let Ok(Value::String(ok)) = table.raw_get::<_, Value>("ok") {
+                return Frame::Simple(ok.to_string_lossy().into_owned());
+            }
+            // 数组在第一个 nil 处截止
+            Frame::Array(table.sequence_values::<Value>().map_while(Result::ok).map(from_lua).collect())
+        }
+        _ => Frame::Null,
+    }
+}
+
+/// 脚本出错时的错误回复：`redis.call` 抛出的命令错误原样返回，不带调用栈
+fn error_message(e: mlua::Error) -> String {
+    match e {
+        mlua::Error::SyntaxError { message, .. } => format!("ERR Error compiling script: {}", message),
+        mlua::Error::CallbackError { cause, .. } => error_message((*cause).clone()),
+        mlua::Error::RuntimeError(msg) => {
+            let msg = msg.split("\nstack traceback:").next().unwrap_or_default();
+            if has_error_code(msg) { msg.to_string() } else { format!("ERR Error running script: {}", msg) }
+        }
+        other => format!("ERR Error running script: {}", other),
+    }
+}
+
+/// 形如 `ERR ...`、`WRONGTYPE ...` 的错误消息
+fn has_error_code(msg: &str) -> bool {
+    msg.split(' ').next().is_some_and(|code| code.len() > 1 && code.bytes().all(|b| b.is_ascii_uppercase()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::kv::Storage;
+    use crate::txn::executor::exec_all;
+    use crate::types::{hash, string};
+
+    fn args(s: &[&str]) -> Vec<Bytes> {
+        s.iter().map(|x| Bytes::copy_from_slice(x.as_bytes())).collect()
+    }
+
+    #[test]
+    fn test_eval_replies_and_bindings() -> anyhow::Result<()> {
+        let db = sled::Config::new().temporary(true).open()?;
+        let storage = Storage::sled(db)?;
+
+        assert_eq!(eval(&storage, &args(&["EVAL", "return 1 + 1", "0"])), Frame::Integer(2));
+        assert_eq!(
+            eval(&storage, &args(&["EVAL", "return {KEYS[1], ARGV[1], nil, 'x'}", "1", "k", "a"])),
+            Frame::Array(vec![Frame::bulk("k"), Frame::bulk("a")])
+        );
+        assert_eq!(eval(&storage, &args(&["EVAL", "return redis.status_reply('FINE')", "0"])), Frame::Simple("FINE".into()));
+        assert_eq!(eval(&storage, &args(&["EVAL", "return false", "0"])), Frame::Null);
+
+        let set = "redis.call('SET', KEYS[1], ARGV[1]); return redis.call('GET', KEYS[1])";
+        assert_eq!(eval(&storage, &args(&["EVAL", set, "1", "k", "v"])), Frame::bulk("v"));
+        assert_eq!(string::get(&storage, b"k")?.as_deref(), Some(&b"v"[..]));
+
+        // pcall 把命令错误作为 {err = ...} 返回，call 直接抛出
+        let pcall = "local r = redis.pcall('HSET', KEYS[1]); return r.err";
+        assert_eq!(
+            eval(&storage, &args(&["EVAL", pcall, "1", "h"])),
+            Frame::bulk("ERR wrong number of arguments for 'hset' command")
+        );
+        assert!(eval(&storage, &args(&["EVAL", "return redis.call('MULTI')", "0"])).is_error());
+        assert!(eval(&storage, &args(&["EVAL", "return (", "0"])).is_error());
+        assert!(eval(&storage, &args(&["EVAL", "return 1", "2", "k"])).is_error());
+        assert!(eval(&storage, &args(&["EVAL", "return dofile('/etc/passwd')", "0"])).is_error());
+        Ok(())
+    }
+
+    #[test]
+    fn test_eval_is_atomic() -> anyhow::Result<()> {
+        let db = sled::Config::new().temporary(true).open()?;
+        let storage = Storage::sled(db)?;
+
+        // 出错的脚本回滚此前的写入
+        let failing = "redis.call('SET', KEYS[1], 'x'); redis.call('HSET', KEYS[1]); return 1";
+        let reply = eval(&storage, &args(&["EVAL", failing, "1", "k"]));
+        assert_eq!(reply, Frame::error("ERR wrong number of arguments for 'hset' command"));
+        assert_eq!(string::get(&storage, b"k")?, None);
+
+        // 事务中可以读取 KEYS 中的集合
+        hash::hset(&storage, b"h", b"f", b"1")?;
+        let script = "redis.call('HSET', KEYS[1], 'g', '2'); return #redis.call('HGETALL', KEYS[1])";
+        assert_eq!(eval(&storage, &args(&["EVAL", script, "1", "h"])), Frame::Integer(4));
+
+        // 限流：每个窗口最多 2 次
+        let limiter = "local n = redis.call('INCR', KEYS[1]) \
+                       if n == 1 then redis.call('EXPIRE', KEYS[1], ARGV[1]) end \
+                       return n <= tonumber(ARGV[2]) and 1 or 0";
+        let replies: Vec<Frame> =
+            (0..3).map(|_| eval(&storage, &args(&["EVAL", limiter, "1", "rate", "60", "2"]))).collect();
+        assert_eq!(replies, vec![Frame::Integer(1), Frame::Integer(1), Frame::Integer(0)]);
+
+        // 分布式锁：只有持有者才能释放，不存在的 key 读到 nil
+        let unlock = "if redis.call('GET', KEYS[1]) == ARGV[1] then return redis.call('DEL', KEYS[1]) else return 0 end";
+        assert_eq!(eval(&storage, &args(&["EVAL", unlock, "1", "lock", "me"])), Frame::Integer(0));
+        string::set(&storage, b"lock", b"me")?;
+        assert_eq!(eval(&storage, &args(&["EVAL", unlock, "1", "lock", "me"])), Frame::Integer(1));
+
+        // 在 EXEC 中执行
+        let cmds = vec![args(&["EVAL", "return redis.call('SET', KEYS[1], ARGV[1])", "1", "t", "v"])];
+        assert_eq!(exec_all(&storage, &cmds), Frame::Array(vec![Frame::ok()]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_script_cache() {
+        assert_eq!(sha1_hex(b""), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+
+        let cache = ScriptCache::new();
+        assert!(cache.resolve(args(&["EVALSHA", "abc", "0"])).is_err());
+        let eval = args(&["EVAL", "return 1", "0"]);
+        assert_eq!(cache.resolve(eval.clone()), Ok(eval.clone()));
+        let sha = sha1_hex(b"return 1").to_uppercase();
+        assert_eq!(cache.resolve(args(&["evalsha", &sha, "0"])), Ok(eval));
+
+        // SCRIPT LOAD / EXISTS / FLUSH
+        let sha = sha1_hex(b"return 2");
+        assert_eq!(cache.execute(&args(&["LOAD", "return 2"])), Frame::bulk(sha.clone()));
+        assert!(cache.execute(&args(&["LOAD", "return ("])).is_error());
+        assert_eq!(cache.len(), 2);
+        assert_eq!(
+            cache.execute(&args(&["exists", &sha, "ffff"])),
+            Frame::Array(vec![Frame::Integer(1), Frame::Integer(0)])
+        );
+        assert!(cache.execute(&args(&["FLUSH", "LATER"])).is_error());
+        assert_eq!(cache.execute(&args(&["FLUSH", "ASYNC"])), Frame::ok());
+        assert!(cache.is_empty());
+        assert!(cache.resolve(args(&["EVALSHA", &sha, "0"])).is_err());
+    }
+}
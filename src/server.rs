@@ -1,77 +1,339 @@
 // src/server.rs
 //! 这是 rudis 服务的网络层：
-//! - 监听 TCP 连接  
-//! - 解码请求（文本 / RESP）  
+//! - 监听 TCP 连接（可选 TLS，见 `tls`）  
+//! - 解码请求（文本 / RESP，见 `protocol::parser`）  
 //! - 调度到 engine 执行  
 //! - 写命令时同步到持久化器  
-//! - 以 RESP Simple String/Error 形式回复
-use anyhow::Result;
+//! - 按连接协商的协议版本（RESP2 / RESP3，见 HELLO）编码回复
+use anyhow::{Context, Result};
+use bytes::{Bytes, BytesMut};
 use std::{sync::{
     atomic::{AtomicU64, Ordering}, Arc
-}, time::Instant};
+}, time::{Duration, Instant}};
 use std::io::ErrorKind;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task::{Context as TaskContext, Poll};
 use tokio::{
-    net::{TcpListener, TcpStream},
-    io::{AsyncReadExt, AsyncBufReadExt, AsyncWriteExt, BufReader}
+    net::{TcpListener, TcpSocket, TcpStream},
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufWriter},
+    task::JoinSet,
 };
-use crate::{engine, persistence::Persistence, txn::session::TxnSession};
-use crate::engine::KvEngine;
-use crate::monitor::{Monitor, info};
+use socket2::{SockRef, TcpKeepalive};
+use tokio::sync::Notify;
+use tokio_rustls::TlsAcceptor;
+use tracing::{debug, info, warn};
+use crate::{acl::Acl, command, engine, expire, namespace, persistence::Persistence, tls, txn::session::TxnSession};
+use crate::config::Config;
+use crate::error::CommandError;
+use crate::hooks::{HookContext, Hooks};
+use crate::output::{self, PushSender};
+use crate::pubsub::{self, PubSub, Subscriptions};
+use crate::function::Functions;
+use crate::script::ScriptCache;
+use crate::replication::{master, Replication};
+use crate::cluster::Cluster;
+use crate::glob::glob_match;
+use crate::engine::{blocking::AsyncKv, watch::{TrackingClient, WatchManager}, KvEngine};
+use crate::monitor::{Monitor, NetTraffic, debug, info, latency, memory};
+use crate::protocol::{Frame, ParserLimits, RespParser, RESP2, RESP3};
 
 /// 按指定地址启动服务
+///
+/// `addr` 可以是逗号分隔的多个地址（如 `127.0.0.1:6380, [::1]:6380`），
+/// 每个地址一个 accept 循环，共享同一份引擎、持久化器、监控与发布订阅表
 pub async fn start_with_addr_db_and_pers<E>(
     addr: &str,
     db: E,
     pers: Arc<Persistence>,
     monitor: Arc<Monitor>,
+    acl: Arc<Acl>,
 ) -> Result<()> 
 where 
     E: KvEngine + Send + Sync + 'static + Clone,
 {
-    let listener = TcpListener::bind(addr).await?;
-    println!("Carb-Cage server listening on {}", addr);
-    serve_with_db(listener, db, pers, monitor).await
+    let pubsub = Arc::new(PubSub::from_config(&pers.cfg)?);
+    serve(bind(addr, pers.cfg.tcp_backlog).await?, db, pers, monitor, acl, pubsub, Arc::new(Hooks::new())).await
 }
 
+/// 绑定逗号分隔的全部监听地址，`backlog` 为每个监听 socket 的 accept 队列长度
+///
+/// 先全部绑定成功再开始服务，任一地址不可用时直接启动失败；
+/// 端口为 0 时由系统分配，可从返回的 listener 上取得实际地址
+pub async fn bind(addr: &str, backlog: u32) -> Result<Vec<TcpListener>> {
+    let addrs: Vec<&str> = addr.split(',').map(str::trim).filter(|a| !a.is_empty()).collect();
+    if addrs.is_empty() {
+        anyhow::bail!("No listen address given");
+    }
+
+    let mut listeners = Vec::with_capacity(addrs.len());
+    for addr in addrs {
+        let listener = bind_one(addr, backlog)
+            .await
+            .with_context(|| format!("Failed to bind {}", addr))?;
+        listeners.push(listener);
+    }
+    Ok(listeners)
+}
+
+/// 依次尝试地址解析出的每个 IP，与 `TcpListener::bind` 相同，只是可以指定 backlog
+async fn bind_one(addr: &str, backlog: u32) -> std::io::Result<TcpListener> {
+    let mut last_err = None;
+    for sock_addr in tokio::net::lookup_host(addr).await? {
+        let socket = if sock_addr.is_ipv4() { TcpSocket::new_v4()? } else { TcpSocket::new_v6()? };
+        #[cfg(unix)]
+        socket.set_reuseaddr(true)?;
+        match socket.bind(sock_addr) {
+            Ok(()) => return socket.listen(backlog),
+            Err(e) => last_err = Some(e),
+        }
+    }
+    Err(last_err.unwrap_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, "could not resolve to any address")
+    }))
+}
+
+/// 在已绑定的 listener 上提供服务，直到任一 accept 循环出错；`hooks` 在每条命令执行前后调用
+pub async fn serve<E>(
+    listeners: Vec<TcpListener>,
+    db: E,
+    pers: Arc<Persistence>,
+    monitor: Arc<Monitor>,
+    acl: Arc<Acl>,
+    pubsub: Arc<PubSub>,
+    hooks: Arc<Hooks>,
+) -> Result<()>
+where
+    E: KvEngine + Send + Sync + 'static + Clone,
+{
+    if listeners.is_empty() {
+        anyhow::bail!("No listen address given");
+    }
+
+    let tls = tls::build_acceptor(&pers.cfg)?;
+    let renames = Arc::new(command::Renames::from_config(&pers.cfg.rename_command)?);
+    let scripts = Arc::new(ScriptCache::new());
+    let functions = Arc::new(Functions::new(pers.cfg.function_fuel_limit));
+    // 存储操作经由异步外观派发到阻塞线程池或按 key 分片的执行线程，所有连接共用
+    let storage = AsyncKv::with_shards(db.clone(), pers.cfg.exec_shards)?;
+
+    for listener in &listeners {
+        info!(
+            "Carb-Cage server listening on {}{}",
+            listener.local_addr()?,
+            if tls.is_some() { " (TLS)" } else { "" }
+        );
+    }
+
+    // 作为副本时通过第一个监听地址的端口向主节点登记
+    let replication = Arc::new(Replication::new(
+        listeners[0].local_addr()?.port(),
+        pers.cfg.repl_backlog_size,
+        pers.cfg.client_output_buffer_limit.replica,
+    ));
+    if let Some(master) = &pers.cfg.replicaof {
+        let Some((host, port)) = master.split_once(' ').and_then(|(h, p)| Some((h, p.trim().parse().ok()?))) else {
+            anyhow::bail!("Invalid replicaof setting {:?}, expected \"host port\"", master);
+        };
+        info!("Replicating from {}:{}", host, port);
+        replication.follow(host.to_string(), port, db.clone(), pers.clone());
+    }
+
+    let cluster = if pers.cfg.cluster_enabled {
+        let local = listeners[0].local_addr()?;
+        let host = match &pers.cfg.cluster_announce_ip {
+            Some(ip) => ip.clone(),
+            None if local.ip().is_unspecified() => "127.0.0.1".to_string(),
+            None => local.ip().to_string(),
+        };
+        let path = pers.data_path(&pers.cfg.cluster_config_file);
+        let cluster = Cluster::open(path, host, local.port())?;
+        info!("Cluster mode enabled, node id {}", cluster.myid());
+        Some(Arc::new(cluster))
+    } else {
+        None
+    };
+
+    let mut accept_loops = JoinSet::new();
+    // 定时采样收发字节数，用于 INFO 与 /metrics 中的瞬时速率
+    let metrics = monitor.metrics.clone();
+    accept_loops.spawn(async move {
+        let mut tick = tokio::time::interval(Duration::from_millis(100));
+        loop {
+            tick.tick().await;
+            metrics.track_instantaneous();
+        }
+    });
+    // 空闲回收：每秒检查一次，断开空闲超过 timeout 秒的普通连接
+    if pers.cfg.timeout > 0 {
+        let tracker = monitor.client_tracker.clone();
+        let limit = Duration::from_secs(pers.cfg.timeout);
+        accept_loops.spawn(async move {
+            let mut tick = tokio::time::interval(Duration::from_secs(1));
+            loop {
+                tick.tick().await;
+                let closed = tracker.kill_idle(limit);
+                if closed > 0 {
+                    debug!("Closed {} idle client(s)", closed);
+                }
+            }
+        });
+    }
+    // 主动过期：每轮从上一轮停下的位置起检查一批过期记录，回收不再被访问的过期 key；
+    // 每轮只定位并检查一小批记录；启用分片时占用全部分片，删除与分片上的命令不会交错
+    if pers.cfg.active_expire_hz > 0 {
+        let storage = storage.clone();
+        let period = Duration::from_millis(1000 / pers.cfg.active_expire_hz.min(1000));
+        accept_loops.spawn(async move {
+            let mut tick = tokio::time::interval(period);
+            let mut cursor = Vec::new();
+            loop {
+                tick.tick().await;
+                let cycle = storage
+                    .run_keyed(&[], move |db| {
+                        let removed = expire::active_expire_cycle(db, &mut cursor, expire::ACTIVE_EXPIRE_KEYS_PER_CYCLE);
+                        (cursor, removed)
+                    })
+                    .await;
+                cursor = match cycle {
+                    Ok((next, Ok(removed))) => {
+                        if removed > 0 {
+                            debug!("Actively expired {} key(s)", removed);
+                        }
+                        next
+                    }
+                    Ok((_, Err(e))) => {
+                        warn!("Active expire cycle failed: {}", e);
+                        Vec::new()
+                    }
+                    Err(_) => Vec::new(),
+                };
+            }
+        });
+    }
+    for listener in listeners {
+        accept_loops.spawn(serve_with_db(
+            listener,
+            storage.clone(),
+            pers.clone(),
+            monitor.clone(),
+            acl.clone(),
+            pubsub.clone(),
+            scripts.clone(),
+            functions.clone(),
+            replication.clone(),
+            cluster.clone(),
+            tls.clone(),
+            renames.clone(),
+            hooks.clone(),
+        ));
+    }
+
+    // 任一 accept 循环出错即返回，其余循环随 JoinSet 一起被取消
+    while let Some(res) = accept_loops.join_next().await {
+        res??;
+    }
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn serve_with_db<E>(
     listener: TcpListener, 
-    db: E, 
+    storage: AsyncKv<E>, 
     pers: Arc<Persistence>,
     monitor: Arc<Monitor>,
+    acl: Arc<Acl>,
+    pubsub: Arc<PubSub>,
+    scripts: Arc<ScriptCache>,
+    functions: Arc<Functions>,
+    replication: Arc<Replication>,
+    cluster: Option<Arc<Cluster>>,
+    tls: Option<TlsAcceptor>,
+    renames: Arc<command::Renames>,
+    hooks: Arc<Hooks>,
 ) -> Result<()> 
 where 
     E: KvEngine + Send + Sync +'static + Clone,
 {
     // Sesson ID 计数器
     static SESSION_COUNTER: AtomicU64 = AtomicU64::new(1);
+    // 按端口划分命名空间时使用
+    let local_port = listener.local_addr()?.port();
 
     loop {
         let (stream, peer) = listener.accept().await?;
-        println!("Accepted connection from {}", peer);
+        debug!("Accepted connection from {}", peer);
+
+        // 开启 TCP keepalive，及时发现对端已经消失的连接
+        if pers.cfg.tcp_keepalive > 0 {
+            let keepalive = TcpKeepalive::new()
+                .with_time(Duration::from_secs(pers.cfg.tcp_keepalive));
+            if let Err(e) = SockRef::from(&stream).set_tcp_keepalive(&keepalive) {
+                warn!("Failed to set TCP keepalive for {}: {}", peer, e);
+            }
+        }
+        if let Err(e) = tune_socket(&stream, &pers.cfg) {
+            warn!("Failed to tune socket for {}: {}", peer, e);
+        }
 
-        let db = db.clone();
+        let storage = storage.clone();
         let pers = pers.clone();
         let monitor = monitor.clone();
+        let acl = acl.clone();
+        let pubsub = pubsub.clone();
+        let scripts = scripts.clone();
+        let functions = functions.clone();
+        let replication = replication.clone();
+        let cluster = cluster.clone();
+        let tls = tls.clone();
+        let renames = renames.clone();
+        let hooks = hooks.clone();
 
-        // 注册客户端
-        let client_id = monitor.client_tracker.add_client(peer);
-        monitor.metrics.connected_clients.fetch_add(1, Ordering::Relaxed);
+        // 超过 maxclients：回复错误后直接关闭
+        // 先占位再判断，多个 accept 循环并发时也不会超限
         monitor.metrics.total_connections.fetch_add(1, Ordering::Relaxed);
+        let connected = monitor.metrics.connected_clients.fetch_add(1, Ordering::Relaxed) + 1;
+        if connected > pers.cfg.maxclients {
+            monitor.metrics.connected_clients.fetch_sub(1, Ordering::Relaxed);
+            monitor.metrics.rejected_connections.fetch_add(1, Ordering::Relaxed);
+            warn!("Rejecting {}: max number of clients reached", peer);
+            tokio::spawn(async move {
+                let reply = Frame::error("ERR max number of clients reached").to_bytes(RESP2);
+                let result = match tls {
+                    Some(acceptor) => match acceptor.accept(stream).await {
+                        Ok(stream) => reject(stream, &reply).await,
+                        Err(e) => Err(e),
+                    },
+                    None => reject(stream, &reply).await,
+                };
+                if let Err(e) = result {
+                    debug!("Failed to reject {}: {}", peer, e);
+                }
+            });
+            continue;
+        }
+
+        // 注册客户端
+        let (client_id, kill_signal) = monitor.client_tracker.add_client(peer);
         
         tokio::spawn(async move {
-            if let Err(e) = 
-                handle_connection(
-                    stream, 
-                    db, 
-                    pers,
-                    monitor.clone(),
-                    client_id,
-                    SESSION_COUNTER
-                        .fetch_add(1, Ordering::SeqCst))
+            let session_id = SESSION_COUNTER.fetch_add(1, Ordering::SeqCst);
+            // TLS 握手放在连接任务里，避免慢客户端阻塞 accept 循环
+            let result = match tls {
+                Some(acceptor) => match acceptor.accept(stream).await {
+                    Ok(stream) => {
+                        handle_connection(stream, peer, local_port, storage, pers, monitor.clone(), acl, pubsub, scripts, functions, replication, cluster, renames, hooks, client_id, kill_signal, session_id)
+                            .await
+                    }
+                    Err(e) => Err(anyhow::anyhow!("TLS handshake with {} failed: {}", peer, e)),
+                },
+                None => {
+                    handle_connection(stream, peer, local_port, storage, pers, monitor.clone(), acl, pubsub, scripts, functions, replication, cluster, renames, hooks, client_id, kill_signal, session_id)
                         .await
-                    
-            {
-                eprintln!("Connection error: {}", e);
+                }
+            };
+            if let Err(e) = result {
+                warn!(%peer, "Connection error: {}", e);
             }
 
             // 断开连接时清理
@@ -81,154 +343,1073 @@ where
     }
 }
 
-async fn handle_connection<E>(
-    stream: TcpStream,
-    db: E,
+/// 连接结束时关闭客户端追踪并退订全部频道与模式，读写出错提前返回时同样执行
+/// （监视随 txn_session 销毁自动解除）
+struct ConnectionGuard {
+    client_id: u64,
+    watch_manager: Option<Arc<WatchManager>>,
+    pubsub: Arc<PubSub>,
+    /// 当前连接的频道 / 模式订阅
+    subscriptions: Subscriptions,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        if let Some(watch_manager) = &self.watch_manager {
+            watch_manager.disable_tracking(self.client_id);
+        }
+        self.pubsub.remove_client(self.client_id, &self.subscriptions);
+    }
+}
+
+/// 统计写出的字节数，计入连接的 tot-net-out 与全局的 total_net_output_bytes
+struct CountingWriter<W> {
+    inner: W,
+    traffic: Arc<NetTraffic>,
+    total: Arc<AtomicU64>,
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for CountingWriter<W> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut TaskContext<'_>, buf: &[u8]) -> Poll<std::io::Result<usize>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(n)) = &poll {
+            this.traffic.output.fetch_add(*n as u64, Ordering::Relaxed);
+            this.total.fetch_add(*n as u64, Ordering::Relaxed);
+        }
+        poll
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+/// 按配置设置新连接的 TCP_NODELAY 与收发缓冲区大小
+fn tune_socket(stream: &TcpStream, cfg: &Config) -> std::io::Result<()> {
+    stream.set_nodelay(cfg.tcp_nodelay)?;
+    let sock = SockRef::from(stream);
+    if cfg.tcp_rcvbuf > 0 {
+        sock.set_recv_buffer_size(cfg.tcp_rcvbuf)?;
+    }
+    if cfg.tcp_sndbuf > 0 {
+        sock.set_send_buffer_size(cfg.tcp_sndbuf)?;
+    }
+    Ok(())
+}
+
+/// 写出拒绝原因后关闭连接
+async fn reject<S: AsyncWrite + Unpin>(mut stream: S, reply: &[u8]) -> std::io::Result<()> {
+    stream.write_all(reply).await?;
+    stream.shutdown().await
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn handle_connection<S, E>(
+    stream: S,
+    peer: SocketAddr,
+    local_port: u16,
+    storage: AsyncKv<E>,
     pers: Arc<Persistence>,
     monitor: Arc<Monitor>,
+    acl: Arc<Acl>,
+    pubsub: Arc<PubSub>,
+    scripts: Arc<ScriptCache>,
+    functions: Arc<Functions>,
+    replication: Arc<Replication>,
+    cluster: Option<Arc<Cluster>>,
+    renames: Arc<command::Renames>,
+    hooks: Arc<Hooks>,
     client_id: u64,
+    kill_signal: Arc<Notify>,
     session_id: u64,
 ) -> Result<()> 
 where 
-    E: KvEngine + Send + Sync + 'static,
+    S: AsyncRead + AsyncWrite + Unpin,
+    E: KvEngine + Send + Sync + 'static + Clone,
 {
-    let peer = stream.peer_addr()?;
-    let (reader, mut writer) = stream.into_split();
-    let mut reader = BufReader::new(reader);
+    let (mut reader, writer) = tokio::io::split(stream);
+    // 收发的字节数记到客户端表中，供 CLIENT LIST 使用
+    let traffic = monitor.client_tracker.traffic(client_id).unwrap_or_default();
+    // 回复先写入缓冲区，等本批读到的命令全部处理完再统一 flush，
+    // 流水线场景下 N 条命令只需一次 write 系统调用
+    let mut writer = BufWriter::with_capacity(16 * 1024, CountingWriter {
+        inner: writer,
+        traffic: traffic.clone(),
+        total: monitor.metrics.net_output_bytes.clone(),
+    });
 
+    let db = storage.inner().clone();
+    let mut guard = ConnectionGuard {
+        client_id,
+        watch_manager: db.watch_manager(),
+        pubsub: pubsub.clone(),
+        subscriptions: Subscriptions::default(),
+    };
+    let subscriptions = &mut guard.subscriptions;
     // 每个连接创建一个单独的事务会话
     let mut txn_session = TxnSession::new(session_id);
+    // 连接默认使用 RESP2，客户端可通过 HELLO 3 切换到 RESP3
+    let mut protocol = RESP2;
+    // 当前登录的 ACL 用户，default 用户需要密码时为 None，需先 AUTH
+    let mut user: Option<String> = acl.default_login();
+    // 服务端主动推送（CLIENT TRACKING 失效消息等）经由该通道写回连接
+    // 排队的推送超出订阅客户端的输出缓冲区上限时断开连接
+    let (push_tx, mut push_rx) = output::push_channel(pers.cfg.client_output_buffer_limit.pubsub);
+    let normal_output = pers.cfg.client_output_buffer_limit.normal;
+    let mut tracking = false;
+    // 对端是副本时通过 REPLCONF 告知的监听端口
+    let mut replica_port: Option<u16> = None;
+    // 集群模式下上一条命令是否为 ASKING，只对紧随其后的一条命令有效
+    let mut asking = false;
 
+    // 读缓冲区与可恢复的 RESP 解析器，半包数据会留到下次读取后继续解析
+    let mut read_buf = BytesMut::with_capacity(4096);
+    let mut parser = RespParser::new(ParserLimits {
+        max_multibulk_len: pers.cfg.proto_max_multibulk_len,
+        max_bulk_len: pers.cfg.proto_max_bulk_len,
+    });
     loop {
-        // 1) 读第一个字节以区分 RESP vs 文本
-        let mut first = [0u8; 1];
-        match reader.read_exact(&mut first).await {
-            Ok(_) => {}
-            Err(e) if e.kind() == ErrorKind::UnexpectedEof
-                     || e.kind() == ErrorKind::ConnectionReset => {
-                println!("{} disconnected", peer);
-
-                // 断开前，清理监视
-                if let Some(watch_manager) = db.watch_manager() {
-                    watch_manager.clear_session(session_id);
+        // 1) 从缓冲区解析出一条完整命令，数据不足时继续读 socket
+        let mut parts: Vec<Bytes> = match parser.parse_bytes(&mut read_buf) {
+            Ok(Some(parts)) => parts,
+            Ok(None) => {
+                // 缓冲区已处理完，更新 CLIENT LIST 中的连接状态，
+                // 把积攒的回复发出去后再等待新数据
+                monitor.client_tracker.update(client_id, |client| {
+                    client.protocol = protocol;
+                    client.multi = txn_session.in_multi.then_some(txn_session.queue.len());
+                    client.sub = subscriptions.channels.len() + subscriptions.shard_channels.len();
+                    client.psub = subscriptions.patterns.len();
+                    client.last_active = Instant::now();
+                });
+                writer.flush().await?;
+                // 等待数据时也响应 CLIENT KILL（包括空闲回收）与服务端推送
+                let read = tokio::select! {
+                    read = reader.read_buf(&mut read_buf) => read,
+                    _ = kill_signal.notified() => {
+                        info!("Client {} killed", peer);
+                        Ok(0)
+                    }
+                    Some(push) = push_rx.recv() => {
+                        let push = match namespace::resolve(&pers.cfg, local_port, user.as_deref()) {
+                            Some(ns) => namespace::unprefix_push(ns, push),
+                            None => push,
+                        };
+                        // 客户端不读取时写入一直阻塞，新的推送继续排队直到超出上限
+                        let push = push.to_bytes(protocol);
+                        tokio::select! {
+                            written = writer.write_all(&push) => {
+                                written?;
+                                continue;
+                            }
+                            _ = push_tx.overflowed() => {
+                                warn!("Closing client {} for exceeding the pubsub output buffer limit or backlog", peer);
+                                Ok(0)
+                            }
+                        }
+                    }
+                    _ = push_tx.overflowed() => {
+                        warn!("Closing client {} for exceeding the pubsub output buffer limit or backlog", peer);
+                        Ok(0)
+                    }
+                };
+                let n = match read {
+                    Ok(n) => n,
+                    Err(e) if e.kind() == ErrorKind::ConnectionReset => 0,
+                    Err(e) => return Err(e.into()),
+                };
+                traffic.input.fetch_add(n as u64, Ordering::Relaxed);
+                monitor.metrics.net_input_bytes.fetch_add(n as u64, Ordering::Relaxed);
+                if n == 0 {
+                    debug!("{} disconnected", peer);
+                    break;
                 }
-
-                break;
+                continue;
             }
-            Err(e) => return Err(e.into()),
+            Err(e) => {
+                // 2) 格式错误：回复错误并丢弃已缓冲的数据，连接保持可用
+                let reply = Frame::from(CommandError::err(e.to_string()));
+                writer.write_all(&reply.to_bytes(protocol)).await?;
+                continue;
+            }
+        };
+
+        if parts.is_empty() {
+            continue;
         }
 
-        // 2) 解析成 Vec<String>
-        let parts: Vec<String> = if first[0] == b'*' {
-            // RESP Array + Bulk String
-            // 读 "*N\r\n"
-            let mut line = String::new();
-            reader.read_line(&mut line).await?;
-            let count: usize = line.trim().parse()?;
+        // 按 rename_command 换回原命令名，被改名或禁用的原名称按未知命令处理
+        if !renames.resolve(&mut parts) {
+            txn_session.flag_error();
+            let reply = command::unknown_command_error(&parts);
+            writer.write_all(&reply.to_bytes(protocol)).await?;
+            continue;
+        }
 
-            let mut cmd = Vec::with_capacity(count);
-            for _ in 0..count {
-                // 读 "$len\r\n"
-                line.clear();
-                reader.read_line(&mut line).await?;
-                let len: usize = line.trim_start_matches('$').trim().parse()?;
+        // 命令钩子：可以改写参数或拒绝执行，改写后的命令照常经过下面的各项检查
+        if !hooks.is_empty() {
+            let ctx = HookContext { client_id, peer, user: user.as_deref() };
+            if let Err(e) = hooks.before(&ctx, &mut parts) {
+                txn_session.flag_error();
+                writer.write_all(&Frame::from(e).to_bytes(protocol)).await?;
+                continue;
+            }
+        }
 
-                // 读 payload + "\r\n"
-                let mut buf = vec![0u8; len];
-                reader.read_exact(&mut buf).await?;
-                let mut crlf = [0u8; 2];
-                reader.read_exact(&mut crlf).await?;
+        let cmd_name = command::name_upper(&parts[0]);
+        let after_asking = std::mem::take(&mut asking);
+
+        // 3) 查命令表：未知命令与参数个数错误直接拒绝，
+        //    事务中出现时整个事务在 EXEC 时放弃
+        match command::lookup(&cmd_name) {
+            None => {
+                txn_session.flag_error();
+                let reply = command::unknown_command_error(&parts);
+                writer.write_all(&reply.to_bytes(protocol)).await?;
+                continue;
+            }
+            Some(spec) if !spec.check_arity(parts.len()) => {
+                txn_session.flag_error();
+                let reply = command::arity_error(&cmd_name);
+                writer.write_all(&reply.to_bytes(protocol)).await?;
+                continue;
+            }
+            Some(_) => {}
+        }
 
-                cmd.push(String::from_utf8(buf)?);
+        // 4) 鉴权与 ACL 检查，AUTH / HELLO / QUIT 无需登录即可执行
+        match cmd_name.as_str() {
+            "AUTH" => {
+                let reply = acl.auth(&command::text_args(&parts[1..]), &mut user);
+                writer.write_all(&reply.to_bytes(protocol)).await?;
+                continue;
+            }
+            "HELLO" | "QUIT" => {}
+            _ => {
+                if let Err(e) = acl.check(user.as_deref(), &cmd_name, &parts) {
+                    let reply = Frame::from(e);
+                    if command::lookup(&cmd_name).is_some_and(|spec| spec.is_write()) {
+                        monitor.audit.record(&peer.to_string(), user.as_deref(), &parts, &reply, &pubsub);
+                    }
+                    txn_session.flag_error();
+                    writer.write_all(&reply.to_bytes(protocol)).await?;
+                    continue;
+                }
             }
-            cmd
+        }
+
+        // 副本只读：数据只来自主节点的复制流（FCALL 可能写入，同样拒绝）；
+        // 只读模式同样拒绝写命令，开启前已入队的写命令在 EXEC 时拒绝
+        let writes = |name: &[u8]| command::lookup(name).is_some_and(|spec| spec.is_write() || spec.has_flag("may_replicate"));
+        let rejected = if replication.is_replica() {
+            Some(CommandError::ReadOnly)
+        } else if pers.is_read_only() {
+            Some(CommandError::ReadOnlyServer)
         } else {
-            // 简单文本协议
-            let mut line = String::new();
-            reader.read_line(&mut line).await?;
-            let mut full = String::new();
-            full.push(first[0] as char);
-            full.push_str(&line);
-            full
-                .trim_end()
-                .split_whitespace()
-                .map(str::to_string)
-                .collect()
+            None
         };
+        if let Some(e) = rejected
+            && (writes(cmd_name.as_bytes()) || (cmd_name == "EXEC" && txn_session.queue.iter().any(|args| writes(&args[0]))))
+        {
+            // EXEC 被拒绝时整个事务随之放弃
+            if cmd_name == "EXEC" {
+                let _ = txn_session.discard();
+            } else {
+                txn_session.flag_error();
+            }
+            let reply = Frame::from(e);
+            writer.write_all(&reply.to_bytes(protocol)).await?;
+            continue;
+        }
 
-        if parts.is_empty() {
+        // 多租户：命令中的 key 加上连接所在命名空间的前缀（ACL 检查针对的是前缀之前的 key）；
+        // 命令钩子看到的是加前缀之前的参数
+        let hooked = (!hooks.is_empty()).then(|| parts.clone());
+        let namespace = namespace::resolve(&pers.cfg, local_port, user.as_deref());
+        let parts = match namespace {
+            Some(ns) => namespace::prefix_keys(ns, parts),
+            None => parts,
+        };
+
+        // 脚本：EVAL 的脚本记入缓存，EVALSHA 换成脚本原文，AOF 与复制流中记录的总是 EVAL
+        let parts = match scripts.resolve(parts) {
+            Ok(parts) => parts,
+            Err(reply) => {
+                txn_session.flag_error();
+                writer.write_all(&reply.to_bytes(protocol)).await?;
+                continue;
+            }
+        };
+
+        // 集群模式：key 所在的槽位不由本节点负责时重定向客户端
+        if let Some(cluster) = &cluster
+            && let Some(spec) = command::lookup(&cmd_name)
+            && let Some(reply) = cluster.route(&spec.keys(&parts), &db, after_asking)
+        {
+            txn_session.flag_error();
+            writer.write_all(&reply.to_bytes(protocol)).await?;
             continue;
         }
 
-        // 3) 处理监控命令
-        let cmd_name = parts[0].to_uppercase();
+        // RESP2 下订阅中的连接只能执行订阅相关命令（RESP3 可以混用）
+        if protocol == RESP2 && !subscriptions.is_empty() {
+            match cmd_name.as_str() {
+                "SUBSCRIBE" | "UNSUBSCRIBE" | "PSUBSCRIBE" | "PUNSUBSCRIBE" | "SSUBSCRIBE"
+                | "SUNSUBSCRIBE" | "QUIT" => {}
+                "PING" => {
+                    let payload = parts.get(1).cloned().unwrap_or_default();
+                    let reply = Frame::Array(vec![Frame::bulk("pong"), Frame::bulk(payload.to_vec())]);
+                    writer.write_all(&reply.to_bytes(protocol)).await?;
+                    continue;
+                }
+                _ => {
+                    let reply = Frame::error(format!(
+                        "ERR Can't execute '{}': only (P|S)SUBSCRIBE / (P|S)UNSUBSCRIBE / PING / QUIT / RESET are allowed in this context",
+                        String::from_utf8_lossy(&parts[0]).to_lowercase()
+                    ));
+                    writer.write_all(&reply.to_bytes(protocol)).await?;
+                    continue;
+                }
+            }
+        }
+
+        // 5) 处理发布订阅、监控与管理命令
         match cmd_name.as_str() {
+            "SUBSCRIBE" | "PSUBSCRIBE" | "SSUBSCRIBE" => {
+                let kind = match cmd_name.as_str() {
+                    "PSUBSCRIBE" => pubsub::Kind::Pattern,
+                    "SSUBSCRIBE" => pubsub::Kind::Shard,
+                    _ => pubsub::Kind::Channel,
+                };
+                for reply in pubsub.subscribe(kind, &command::text_args(&parts[1..]), client_id, &push_tx, subscriptions) {
+                    writer.write_all(&reply.to_bytes(protocol)).await?;
+                }
+                continue;
+            }
+            "UNSUBSCRIBE" | "PUNSUBSCRIBE" | "SUNSUBSCRIBE" => {
+                let kind = match cmd_name.as_str() {
+                    "PUNSUBSCRIBE" => pubsub::Kind::Pattern,
+                    "SUNSUBSCRIBE" => pubsub::Kind::Shard,
+                    _ => pubsub::Kind::Channel,
+                };
+                for reply in pubsub.unsubscribe(kind, &command::text_args(&parts[1..]), client_id, subscriptions) {
+                    writer.write_all(&reply.to_bytes(protocol)).await?;
+                }
+                continue;
+            }
+            "PUBLISH" | "SPUBLISH" => {
+                let receivers = if cmd_name == "SPUBLISH" {
+                    pubsub.spublish(&String::from_utf8_lossy(&parts[1]), &parts[2])
+                } else {
+                    pubsub.publish(&String::from_utf8_lossy(&parts[1]), &parts[2])
+                };
+                writer.write_all(&Frame::Integer(receivers as i64).to_bytes(protocol)).await?;
+                continue;
+            }
+            "PUBSUB" => {
+                let reply = pubsub.introspect(&command::text_args(&parts[1..]));
+                writer.write_all(&reply.to_bytes(protocol)).await?;
+                continue;
+            }
+            "HELLO" => {
+                let reply = hello(&command::text_args(&parts[1..]), &mut protocol, client_id, &acl, &mut user, &monitor, &replication, cluster.is_some());
+                writer.write_all(&reply.to_bytes(protocol)).await?;
+                continue;
+            }
             "INFO" => {
-                let section = parts.get(1).map(|s| s.as_str());
-                let response = info::build_info_response(section, &db, &pers, &monitor.metrics);
-                writer.write_all(format!("${}\r\n{}\r\n", response.len(), response).as_bytes()).await?;
+                let section = parts.get(1).map(|s| String::from_utf8_lossy(s));
+                let response = info::build_info_response(section.as_deref(), local_port, &db, &pers, &monitor, &replication, &pubsub);
+                writer.write_all(&Frame::bulk(response).to_bytes(protocol)).await?;
+                continue;
+            }
+            "CLIENT" if parts.get(1).is_some_and(|s| s.eq_ignore_ascii_case(b"TRACKING")) => {
+                let reply = client_tracking(&command::text_args(&parts[2..]), client_id, protocol, &db, &push_tx, &mut tracking);
+                writer.write_all(&reply.to_bytes(protocol)).await?;
+                continue;
+            }
+            "CLIENT" => {
+                let reply = client_command(&command::text_args(&parts[1..]), client_id, &monitor);
+                writer.write_all(&reply.to_bytes(protocol)).await?;
                 continue;
             }
-            "CLIENT" if parts.len() > 1 && parts[1].to_uppercase() == "LIST" => {
-                let response = monitor.client_tracker.list_clients();
-                writer.write_all(format!("${}\r\n{}\r\n", response.len(), response).as_bytes()).await?;
+            "MEMORY" => {
+                let args = parts[1..].to_vec();
+                let reply = storage.run(move |db| memory::execute(&args, db)).await?;
+                writer.write_all(&reply.to_bytes(protocol)).await?;
                 continue;
             }
             "SLOWLOG" => {
-                let response = monitor.slow_log.get_logs();
-                writer.write_all(format!("${}\r\n{}\r\n", response.len(), response).as_bytes()).await?;
+                let reply = monitor.slow_log.execute(&command::text_args(&parts[1..]));
+                writer.write_all(&reply.to_bytes(protocol)).await?;
+                continue;
+            }
+            "FUNCTION" => {
+                let reply = functions.execute(&command::text_args(&parts[1..]));
+                writer.write_all(&reply.to_bytes(protocol)).await?;
+                continue;
+            }
+            // 函数的写入以 MULTI ... EXEC 的形式持久化，不能再嵌套在事务中
+            "FCALL" | "FCALL_RO" if txn_session.in_multi => {
+                txn_session.flag_error();
+                let reply = Frame::error("ERR FCALL is not allowed inside MULTI");
+                writer.write_all(&reply.to_bytes(protocol)).await?;
+                continue;
+            }
+            "SCRIPT" => {
+                let reply = scripts.execute(&parts[1..]);
+                writer.write_all(&reply.to_bytes(protocol)).await?;
+                continue;
+            }
+            "HOTKEYS" => {
+                let reply = monitor.hot_keys.execute(&command::text_args(&parts[1..]));
+                writer.write_all(&reply.to_bytes(protocol)).await?;
+                continue;
+            }
+            "STATS" => {
+                let args = command::text_args(&parts[1..]);
+                let reply = storage
+                    .run(move |db| {
+                        db.keyspace_usage()
+                            .map_or_else(|| Frame::error("ERR keyspace statistics are unavailable"), |usage| usage.execute(&args))
+                    })
+                    .await?;
+                writer.write_all(&reply.to_bytes(protocol)).await?;
+                continue;
+            }
+            "LATENCY" => {
+                let reply = pers.latency().execute(&command::text_args(&parts[1..]));
+                writer.write_all(&reply.to_bytes(protocol)).await?;
+                continue;
+            }
+            "CONFIG" => {
+                let reply = config_command(&command::text_args(&parts[1..]), &monitor, &pers);
+                writer.write_all(&reply.to_bytes(protocol)).await?;
+                continue;
+            }
+            "SAVE" | "BGSAVE" => {
+                let (result, started) = if cmd_name == "SAVE" {
+                    (pers.save(), "OK")
+                } else {
+                    (pers.bgsave(), "Background saving started")
+                };
+                let reply = match result {
+                    Ok(()) => Frame::Simple(started.into()),
+                    Err(e) => CommandError::from(e).into(),
+                };
+                writer.write_all(&reply.to_bytes(protocol)).await?;
+                continue;
+            }
+            "LASTSAVE" => {
+                let reply = Frame::Integer(pers.last_save_time() as i64);
+                writer.write_all(&reply.to_bytes(protocol)).await?;
+                continue;
+            }
+            "BGREWRITEAOF" => {
+                let reply = match pers.bgrewrite_aof() {
+                    Ok(()) => Frame::Simple("Background append only file rewriting started".into()),
+                    Err(e) => CommandError::from(e).into(),
+                };
+                writer.write_all(&reply.to_bytes(protocol)).await?;
+                continue;
+            }
+            "REPLICAOF" | "SLAVEOF" => {
+                let reply = replication.replicaof_command(&command::text_args(&parts[1..]), db.clone(), pers.clone());
+                writer.write_all(&reply.to_bytes(protocol)).await?;
+                continue;
+            }
+            "REPLCONF" => {
+                let reply = master::replconf(&command::text_args(&parts[1..]), &mut replica_port);
+                writer.write_all(&reply.to_bytes(protocol)).await?;
+                continue;
+            }
+            "CLUSTER" | "ASKING" => {
+                let reply = match &cluster {
+                    Some(cluster) if cmd_name == "CLUSTER" => cluster.command(&command::text_args(&parts[1..]), &db).await,
+                    Some(_) => {
+                        asking = true;
+                        Frame::ok()
+                    }
+                    None => Frame::error("ERR This instance has cluster support disabled"),
+                };
+                writer.write_all(&reply.to_bytes(protocol)).await?;
+                continue;
+            }
+            "WAIT" => {
+                writer.flush().await?;
+                monitor.client_tracker.update(client_id, |client| client.blocked = true);
+                let reply = replication.wait_command(&command::text_args(&parts[1..])).await;
+                monitor.client_tracker.update(client_id, |client| client.blocked = false);
+                writer.write_all(&reply.to_bytes(protocol)).await?;
+                continue;
+            }
+            "PSYNC" | "SYNC" => {
+                // 此后连接只用于复制，直到副本断开
+                writer.flush().await?;
+                monitor.client_tracker.update(client_id, |client| client.replica = true);
+                info!("Replica {} asks for synchronization", peer);
+                let result = master::serve_replica(
+                    &command::text_args(&parts[1..]), &mut reader, &mut writer, &mut read_buf, peer, client_id, replica_port, &pers, &replication,
+                )
+                .await;
+                info!("Replica {} disconnected", peer);
+                return result;
+            }
+            "DEBUG" => {
+                let reply = if pers.cfg.enable_debug_command {
+                    // DEBUG SLEEP 用于模拟慢命令，需要计入慢日志
+                    let start_time = Instant::now();
+                    let reply = debug::execute(&parts[1..], &db).await;
+                    let name = monitor.client_tracker.get_name(client_id);
+                    monitor.slow_log.add_entry(&parts, start_time.elapsed(), &peer.to_string(), name);
+                    pers.observe_command(start_time.elapsed());
+                    reply
+                } else {
+                    debug::disabled_error()
+                };
+                writer.write_all(&reply.to_bytes(protocol)).await?;
+                continue;
+            }
+            "COMMAND" => {
+                let reply = command::execute(&parts[1..]);
+                writer.write_all(&reply.to_bytes(protocol)).await?;
+                continue;
+            }
+            "ACL" => {
+                let reply = acl.execute(&command::text_args(&parts[1..]), user.as_deref().unwrap_or("default"));
+                writer.write_all(&reply.to_bytes(protocol)).await?;
                 continue;
             }
             _=>{}
         }
 
-        // 4) 调度到 engine
-        let is_write = matches!(cmd_name.as_str(), 
-            // string
-            "SET" | "DEL" | "GET" | "INCR" | "DECR" |
-            "HSET" | "HGET" | "HDEL" | "HKEYS" | "HVALS" | "HGETALL" |
-            "LPUSH" | "RPUSH" | "LPOP" | "RPOP" | "LRANGE" |
-            "SADD" | "SREM" | "SMEMBERS" | "SISMEMBER" |
-            "EXPIRE" | "TTL" | "PERSIST" |
-            "MULTI" | "EXEC" | "DISCARD" |
-            "WATCH" | "UNWATCH" |
-            "PING" | "QUIT"
-         );
-        let raw = parts.join(" ");
-
-        let start_time = Instant::now();
-        let resp = engine::execute(parts.clone(), &db, &mut txn_session);
-        let duration = start_time.elapsed();
+        // 6) 调度到 engine
+        // 以命令表中的 write 标记判断是否需要持久化
+        let spec = command::lookup(&cmd_name);
+        let is_write = spec.is_some_and(|spec| spec.is_write());
+        monitor.hot_keys.sample(|| spec.map(|spec| spec.keys(&parts)).unwrap_or_default());
+
+        // EXEC 之后队列即被清空，先留一份用于 AOF 与失效通知
+        let exec_queue = (cmd_name == "EXEC").then(|| txn_session.queue.clone());
+
+        // 阻塞命令（BLPOP 等）在第一次尝试之前就登记等待，两者之间的写入也会唤醒它；
+        // 事务中的阻塞命令与 Redis 一样只尝试一次
+        let blocking = spec.is_some_and(|spec| spec.has_flag("blocking")) && !txn_session.in_multi;
+        let wait = match (spec, db.waiters()) {
+            (Some(spec), Some(waiters)) if blocking => engine::parse_block_timeout(engine::block_timeout_arg(&parts))
+                .ok()
+                .map(|timeout| (waiters.register(&spec.keys(&parts)), timeout.map(|timeout| Instant::now() + timeout))),
+            _ => None,
+        };
+
+        let (resp, effects, duration, timed_out) = loop {
+            // 快照进行中时先记下命令涉及的 key 的原值；执行与追加 AOF 期间持有，
+            // 使快照的起点总是落在两条命令之间。
+            // 存储与 AOF 的读写都会阻塞，整段放到阻塞线程池中执行，事务会话随之移入移出
+            let session = std::mem::replace(&mut txn_session, TxnSession::new(session_id));
+            let (resp, effects, duration, timed_out, session) = {
+                // 启用分片时按命令涉及的 key 选择执行线程，EXEC 取整个事务队列的 key
+                let keys = match &exec_queue {
+                    Some(queue) => queue
+                        .iter()
+                        .flat_map(|args| command::lookup(&args[0]).map(|spec| spec.keys(args)).unwrap_or_default())
+                        .collect(),
+                    None => spec.map(|spec| spec.keys(&parts)).unwrap_or_default(),
+                };
+                let (pers, replication, functions, parts, exec_queue) =
+                    (pers.clone(), replication.clone(), functions.clone(), parts.clone(), exec_queue.clone());
+                let is_fcall = matches!(cmd_name.as_str(), "FCALL" | "FCALL_RO");
+                let budget = (pers.cfg.command_timeout_ms > 0).then(|| Duration::from_millis(pers.cfg.command_timeout_ms));
+                storage
+                    .run_keyed(&keys, move |db| {
+                        let mut txn_session = session;
+                        let _write_guard = pers.write_guard(exec_queue.as_deref().unwrap_or(std::slice::from_ref(&parts)));
+
+                        let start_time = Instant::now();
+                        // FCALL 返回函数执行成功的写命令，代替 FCALL 本身写入 AOF 与复制流；
+                        // 超出执行时间预算的遍历以错误中止
+                        let ((resp, effects), timed_out) = engine::budget::run(budget, || {
+                            if is_fcall {
+                                functions.fcall(db, &parts)
+                            } else {
+                                (engine::execute(&parts, db, &mut txn_session), Vec::new())
+                            }
+                        });
+                        let duration = start_time.elapsed();
+
+                        // 7) 执行成功的写命令才追加 AOF & 触发快照，失败的命令重放时不再执行
+                        // 注意：事务中的命令只在 EXEC 成功后以 MULTI ... EXEC 的形式整体持久化
+                        // 同时转发给副本；阻塞命令没有取到数据时什么也没写
+                        let popped_nothing = blocking && resp == Frame::NullArray;
+                        if let (Some(queue), Frame::Array(_)) = (&exec_queue, &resp) {
+                            pers.append_transaction(queue);
+                            replication.feed_transaction(queue);
+                        } else if !effects.is_empty() {
+                            pers.append_transaction(&effects);
+                            replication.feed_transaction(&effects);
+                        } else if is_write && !txn_session.in_multi && !resp.is_error() && !popped_nothing {
+                            pers.append_aof_and_maybe_snapshot(&parts);
+                            replication.feed_command(&parts);
+                        }
+                        (resp, effects, duration, timed_out, txn_session)
+                    })
+                    .await?
+            };
+            txn_session = session;
+
+            // 阻塞命令没有取到数据：等到涉及的 key 被写入后重试，超时或连接被 CLIENT KILL 时回复 nil
+            match &wait {
+                Some((handle, deadline)) if resp == Frame::NullArray && deadline.is_none_or(|deadline| Instant::now() < deadline) => {
+                    writer.flush().await?;
+                    monitor.client_tracker.update(client_id, |client| client.blocked = true);
+                    let woken = tokio::select! {
+                        woken = handle.wait(*deadline) => woken,
+                        _ = kill_signal.notified() => {
+                            // 留给主循环处理
+                            kill_signal.notify_one();
+                            false
+                        }
+                    };
+                    monitor.client_tracker.update(client_id, |client| client.blocked = false);
+                    if !woken {
+                        break (resp, effects, duration, timed_out);
+                    }
+                }
+                _ => break (resp, effects, duration, timed_out),
+            }
+        };
+        drop(wait);
 
         // 更新监控数据
         monitor.client_tracker.update_command(client_id, &cmd_name);
-        monitor.metrics.record_command(&cmd_name);
-        monitor.slow_log.add_entry(&raw, duration, &peer.to_string());
+        monitor.metrics.record_command(&cmd_name, duration, resp.is_error());
+        let name = monitor.client_tracker.get_name(client_id);
+        pers.observe_command(duration);
+        // 超出执行时间预算被中止的命令不论阈值都记入慢日志
+        if timed_out {
+            warn!("Aborted {} from client {} after {} ms", cmd_name, peer, duration.as_millis());
+            monitor.slow_log.add_timeout_entry(&parts, duration, &peer.to_string(), name);
+            pers.latency().record(latency::EVENT_COMMAND_TIMEOUT, duration);
+        } else {
+            monitor.slow_log.add_entry(&parts, duration, &peer.to_string(), name);
+        }
 
-        // 4) 写命令时追加 AOF & 触发快照
-        // 注意：事务中的命令只在 EXEC 时持久化
-        if is_write {
-            if cmd_name == "EXEC" {
-                // 对于 EXEC 命令，持久化整个事务队列
-                if let Some(cmds) = txn_session.get_queued_commands() {
-                    for cmd in cmds {
-                        pers.append_aof_and_maybe_snapshot(&cmd, &db.as_db().unwrap());
+        // 审计：记录执行过的写命令及各自的结果，事务中的命令在 EXEC 时按各自的回复记录
+        if monitor.audit.is_enabled() {
+            let client = peer.to_string();
+            let record = |args: &[Bytes], reply: &Frame| monitor.audit.record(&client, user.as_deref(), args, reply, &pubsub);
+            match (&exec_queue, &resp) {
+                (Some(queue), Frame::Array(replies)) => {
+                    for (args, reply) in queue.iter().zip(replies) {
+                        if command::lookup(&args[0]).is_some_and(|spec| spec.is_write()) {
+                            record(args, reply);
+                        }
                     }
                 }
-            } else if !txn_session.in_multi {
-                // 非事务模式下的写命令直接持久化
-                pers.append_aof_and_maybe_snapshot(&raw, &db.as_db().unwrap());
+                (Some(queue), _) => {
+                    let reply = match &resp {
+                        Frame::Error(_) => resp.clone(),
+                        _ => Frame::error("ERR transaction aborted because a watched key was modified"),
+                    };
+                    for args in queue.iter().filter(|args| command::lookup(&args[0]).is_some_and(|spec| spec.is_write())) {
+                        record(args, &reply);
+                    }
+                }
+                _ if !effects.is_empty() => effects.iter().for_each(|args| record(args, &Frame::ok())),
+                _ if blocking && resp == Frame::NullArray => {}
+                _ if is_write && !txn_session.in_multi => record(&parts, &resp),
+                _ => {}
             }
         }
 
-        // 5) 用 RESP SimpleString / Error 回复
-        let out = if resp.starts_with("ERR") {
-            format!("-{}\r\n", resp)
-        } else {
-            format!("+{}\r\n", resp)
+        // 8) 客户端缓存：记录读过的 key，写成功后通知其他连接失效
+        if let Some(watch_manager) = db.watch_manager() {
+            let executed: Vec<&Vec<Bytes>> = match (&exec_queue, &resp) {
+                (Some(queue), Frame::Array(_)) => queue.iter().collect(),
+                _ if !effects.is_empty() => effects.iter().collect(),
+                _ if blocking && resp == Frame::NullArray => vec![],
+                _ if !txn_session.in_multi && !resp.is_error() => vec![&parts],
+                _ => vec![],
+            };
+            for cmd_parts in executed {
+                let Some(spec) = command::lookup(&cmd_parts[0]) else { continue };
+                let keys = spec.keys(cmd_parts);
+                if spec.is_write() {
+                    for key in keys {
+                        watch_manager.invalidate(key, client_id);
+                    }
+                } else if tracking && spec.is_readonly() {
+                    watch_manager.track_keys(client_id, &keys);
+                }
+            }
+        }
+
+        // 9) 去掉回复中 key 的命名空间前缀，按当前协议版本编码回复
+        let resp = match namespace {
+            Some(ns) => namespace::unprefix_reply(ns, &parts, exec_queue.as_deref(), resp),
+            None => resp,
         };
-        writer.write_all(out.as_bytes()).await?;
+        if let Some(args) = &hooked {
+            hooks.after(&HookContext { client_id, peer, user: user.as_deref() }, args, &resp, duration);
+        }
+        let reply = resp.to_bytes(protocol);
+        // 普通客户端的输出缓冲区上限：回复超过硬上限直接断开，
+        // 超过软上限时客户端须在 soft_seconds 秒内读完
+        if normal_output.over_hard(reply.len()) {
+            warn!("Closing client {} for exceeding the normal output buffer limit ({} bytes reply)", peer, reply.len());
+            break;
+        }
+        if normal_output.over_soft(reply.len()) {
+            let limit = Duration::from_secs(normal_output.soft_seconds);
+            if tokio::time::timeout(limit, writer.write_all(&reply)).await.is_err() {
+                warn!("Closing client {} for exceeding the normal output buffer limit ({} bytes reply)", peer, reply.len());
+                break;
+            }
+        } else {
+            writer.write_all(&reply).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// CLIENT TRACKING ON|OFF [BCAST] [PREFIX prefix ...] [NOLOOP]
+///
+/// 失效消息以 RESP3 Push 帧发送，因此要求连接先 HELLO 3
+fn client_tracking<E: KvEngine>(
+    args: &[String],
+    client_id: u64,
+    protocol: u8,
+    db: &E,
+    push_tx: &PushSender,
+    tracking: &mut bool,
+) -> Frame {
+    let Some(switch) = args.first() else {
+        return Frame::error("ERR wrong number of arguments for 'client|tracking' command");
+    };
+    let Some(watch_manager) = db.watch_manager() else {
+        return Frame::error("ERR client tracking not available");
+    };
+
+    let mut bcast = false;
+    let mut noloop = false;
+    let mut prefixes = Vec::new();
+    let mut i = 1;
+    while i < args.len() {
+        match args[i].to_uppercase().as_str() {
+            "BCAST" => bcast = true,
+            "NOLOOP" => noloop = true,
+            "PREFIX" if i + 1 < args.len() => {
+                prefixes.push(args[i + 1].clone());
+                i += 1;
+            }
+            "REDIRECT" | "OPTIN" | "OPTOUT" => {
+                return Frame::error(format!(
+                    "ERR CLIENT TRACKING option '{}' is not supported",
+                    args[i].to_lowercase()
+                ));
+            }
+            _ => return Frame::error("ERR syntax error"),
+        }
+        i += 1;
+    }
+
+    match switch.to_uppercase().as_str() {
+        "ON" => {
+            if protocol != RESP3 {
+                return Frame::error("ERR CLIENT TRACKING requires RESP3, switch with HELLO 3 first");
+            }
+            if !prefixes.is_empty() && !bcast {
+                return Frame::error("ERR PREFIX option requires BCAST mode to be enabled");
+            }
+            watch_manager.enable_tracking(client_id, TrackingClient {
+                sender: push_tx.clone(),
+                noloop,
+                bcast_prefixes: bcast.then_some(prefixes),
+            });
+            *tracking = true;
+            Frame::ok()
+        }
+        "OFF" => {
+            watch_manager.disable_tracking(client_id);
+            *tracking = false;
+            Frame::ok()
+        }
+        _ => Frame::error("ERR syntax error"),
     }
+}
+
+/// CLIENT ID | LIST | INFO | SETNAME | GETNAME | KILL
+fn client_command(args: &[String], client_id: u64, monitor: &Monitor) -> Frame {
+    let tracker = &monitor.client_tracker;
+    let Some(sub) = args.first() else {
+        return Frame::error("ERR wrong number of arguments for 'client' command");
+    };
+    let sub = sub.to_uppercase();
+
+    match (sub.as_str(), args.len()) {
+        ("ID", 1) => Frame::Integer(client_id as i64),
+        ("INFO", 1) => Frame::bulk(tracker.client_info(client_id).unwrap_or_default()),
+        ("GETNAME", 1) => match tracker.get_name(client_id) {
+            Some(name) => Frame::bulk(name),
+            None => Frame::Null,
+        },
+        ("SETNAME", 2) => {
+            let name = &args[1];
+            if let Err(e) = check_client_name(name) {
+                return e;
+            }
+            tracker.set_name(client_id, (!name.is_empty()).then(|| name.clone()));
+            Frame::ok()
+        }
+        // 旧格式：CLIENT KILL ip:port
+        ("KILL", 2) => {
+            let addr = &args[1];
+            match tracker.kill(|_, c| c.addr.to_string() == *addr) {
+                0 => Frame::error("ERR No such client"),
+                _ => Frame::ok(),
+            }
+        }
+        // 新格式：CLIENT KILL [ID id] [ADDR ip:port] [SKIPME yes|no]
+        ("KILL", n) if n > 2 && n % 2 == 1 => {
+            let mut id_filter = None;
+            let mut addr_filter = None;
+            let mut skipme = true;
+            for pair in args[1..].chunks(2) {
+                match pair[0].to_uppercase().as_str() {
+                    "ID" => match pair[1].parse::<u64>() {
+                        Ok(id) => id_filter = Some(id),
+                        Err(_) => return Frame::error("ERR client-id should be greater than 0"),
+                    },
+                    "ADDR" => addr_filter = Some(pair[1].clone()),
+                    "SKIPME" => match pair[1].to_lowercase().as_str() {
+                        "yes" => skipme = true,
+                        "no" => skipme = false,
+                        _ => return Frame::error("ERR syntax error"),
+                    },
+                    _ => return Frame::error("ERR syntax error"),
+                }
+            }
+            let killed = tracker.kill(|id, c| {
+                id_filter.is_none_or(|want| want == id)
+                    && addr_filter.as_ref().is_none_or(|want| *want == c.addr.to_string())
+                    && !(skipme && id == client_id)
+            });
+            Frame::Integer(killed as i64)
+        }
+        // CLIENT LIST [TYPE normal|master|replica|pubsub] [ID id [id ...]]
+        ("LIST", _) => {
+            let mut type_filter = None;
+            let mut id_filter: Option<Vec<u64>> = None;
+            let mut i = 1;
+            while i < args.len() {
+                match args[i].to_uppercase().as_str() {
+                    "TYPE" if i + 1 < args.len() => {
+                        type_filter = Some(match args[i + 1].to_lowercase().as_str() {
+                            "normal" => "normal",
+                            "pubsub" => "pubsub",
+                            "replica" | "slave" => "replica",
+                            // 本节点连向主节点的连接不在客户端表中
+                            "master" => "master",
+                            _ => return Frame::error(format!("ERR Unknown client type '{}'", args[i + 1])),
+                        });
+                        i += 2;
+                    }
+                    // ID 之后的参数都是客户端 id
+                    "ID" if i + 1 < args.len() => {
+                        let ids: Option<Vec<u64>> = args[i + 1..].iter().map(|id| id.parse().ok().filter(|&id| id > 0)).collect();
+                        match ids {
+                            Some(ids) => id_filter = Some(ids),
+                            None => return Frame::error("ERR Invalid client ID"),
+                        }
+                        i = args.len();
+                    }
+                    _ => return Frame::error("ERR syntax error"),
+                }
+            }
+            Frame::bulk(tracker.list_clients(|id, client| {
+                type_filter.is_none_or(|want| want == client.client_type())
+                    && id_filter.as_ref().is_none_or(|ids| ids.contains(&id))
+            }))
+        }
+        ("ID" | "INFO" | "GETNAME" | "SETNAME" | "KILL", _) => Frame::error(format!(
+            "ERR wrong number of arguments for 'client|{}' command",
+            sub.to_lowercase()
+        )),
+        _ => Frame::error(format!(
+            "ERR unknown subcommand '{}'. Try CLIENT HELP.",
+            args[0]
+        )),
+    }
+}
 
+/// 与 Redis 一致：客户端名字只允许可见 ASCII 字符，空字符串表示清除名字
+fn check_client_name(name: &str) -> Result<(), Frame> {
+    if name.bytes().any(|b| !(b'!'..=b'~').contains(&b)) {
+        return Err(Frame::error("ERR Client names cannot contain spaces, newlines or special characters."));
+    }
     Ok(())
+}
+
+/// CONFIG GET pattern [pattern ...] / CONFIG SET parameter value [parameter value ...]
+///
+/// 目前可在运行时调整的参数：
+/// - `slowlog-log-slower-than`：慢日志阈值（微秒），负数关闭
+/// - `slowlog-max-len`：慢日志最多保留的条数
+/// - `latency-monitor-threshold`：延迟监控阈值（毫秒），0 关闭
+/// - `hotkeys-sample-rate`：热点 key 每 N 条命令抽样一次，0 关闭
+/// - `read-only`：`yes` 开启只读模式，拒绝全部写命令
+fn config_command(args: &[String], monitor: &Monitor, pers: &Persistence) -> Frame {
+    let slow_log = &monitor.slow_log;
+    let latency = pers.latency();
+    let params = [
+        ("slowlog-log-slower-than", slow_log.threshold_us().to_string()),
+        ("slowlog-max-len", slow_log.max_len().to_string()),
+        ("latency-monitor-threshold", latency.threshold_ms().to_string()),
+        ("hotkeys-sample-rate", monitor.hot_keys.sample_rate().to_string()),
+        ("read-only", if pers.is_read_only() { "yes" } else { "no" }.to_string()),
+    ];
+
+    match (args[0].to_uppercase().as_str(), args.len()) {
+        ("GET", n) if n >= 2 => {
+            let matched = params
+                .iter()
+                .filter(|(name, _)| {
+                    args[1..].iter().any(|p| glob_match(p.to_lowercase().as_bytes(), name.as_bytes()))
+                })
+                .map(|(name, value)| (Frame::bulk(*name), Frame::bulk(value.as_str())))
+                .collect();
+            Frame::Map(matched)
+        }
+        ("SET", n) if n >= 3 && n % 2 == 1 => {
+            // 先全部校验再应用，任一参数无效时都不修改
+            let mut updates = Vec::new();
+            for pair in args[1..].chunks(2) {
+                let name = pair[0].to_lowercase();
+                let valid = match name.as_str() {
+                    "slowlog-log-slower-than" => pair[1].parse::<i64>().is_ok(),
+                    "slowlog-max-len" => pair[1].parse::<usize>().is_ok(),
+                    "latency-monitor-threshold" | "hotkeys-sample-rate" => pair[1].parse::<u64>().is_ok(),
+                    "read-only" => {
+                        if !matches!(pair[1].to_lowercase().as_str(), "yes" | "no") {
+                            return Frame::error(format!(
+                                "ERR CONFIG SET failed (possibly related to argument '{}') - argument must be 'yes' or 'no'",
+                                pair[0]
+                            ));
+                        }
+                        true
+                    }
+                    _ => {
+                        return Frame::error(format!(
+                            "ERR Unknown option or number of arguments for CONFIG SET - '{}'",
+                            pair[0]
+                        ))
+                    }
+                };
+                if !valid {
+                    return Frame::error(format!(
+                        "ERR CONFIG SET failed (possibly related to argument '{}') - argument couldn't be parsed into an integer",
+                        pair[0]
+                    ));
+                }
+                updates.push((name, pair[1].as_str()));
+            }
+            for (name, value) in updates {
+                match name.as_str() {
+                    "slowlog-log-slower-than" => slow_log.set_threshold_us(value.parse().unwrap_or_default()),
+                    "slowlog-max-len" => slow_log.set_max_len(value.parse().unwrap_or_default()),
+                    "latency-monitor-threshold" => latency.set_threshold_ms(value.parse().unwrap_or_default()),
+                    "read-only" => pers.set_read_only(value.eq_ignore_ascii_case("yes")),
+                    _ => monitor.hot_keys.set_sample_rate(value.parse().unwrap_or_default()),
+                }
+            }
+            Frame::ok()
+        }
+        ("GET" | "SET", _) => Frame::error(format!(
+            "ERR wrong number of arguments for 'config|{}' command",
+            args[0].to_lowercase()
+        )),
+        _ => Frame::error(format!("ERR unknown subcommand '{}'. Try CONFIG HELP.", args[0])),
+    }
+}
+
+/// HELLO [protover [AUTH username password] [SETNAME clientname]]
+///
+/// 协商连接使用的协议版本，并以 Map 形式返回服务端信息。
+/// 回复本身已按新协议编码（与 Redis 行为一致）。
+/// 未登录的连接必须通过 AUTH 选项同时完成认证；任何选项出错时整条命令不生效，登录状态不变。
+#[allow(clippy::too_many_arguments)]
+fn hello(
+    args: &[String],
+    protocol: &mut u8,
+    client_id: u64,
+    acl: &Acl,
+    user: &mut Option<String>,
+    monitor: &Monitor,
+    replication: &Replication,
+    cluster_enabled: bool,
+) -> Frame {
+    let mut requested = *protocol;
+
+    if let Some(ver) = args.first() {
+        requested = match ver.parse::<u8>() {
+            Ok(v) if v == RESP2 || v == RESP3 => v,
+            Ok(_) => return Frame::error("NOPROTO unsupported protocol version"),
+            Err(_) => {
+                return Frame::error("ERR Protocol version is not an integer or out of range");
+            }
+        };
+    }
+
+    // 解析可选参数，AUTH 与 SETNAME 在全部选项解析成功、认证检查通过后才生效
+    let mut authenticated = None;
+    let mut setname = None;
+    let mut i = 1;
+    while i < args.len() {
+        match args[i].to_uppercase().as_str() {
+            "AUTH" if i + 2 < args.len() => {
+                if !acl.authenticate(&args[i + 1], &args[i + 2]) {
+                    return Frame::error("WRONGPASS invalid username-password pair or user is disabled.");
+                }
+                authenticated = Some(&args[i + 1]);
+                i += 3;
+            }
+            "SETNAME" if i + 1 < args.len() => {
+                if let Err(e) = check_client_name(&args[i + 1]) {
+                    return e;
+                }
+                setname = Some(&args[i + 1]);
+                i += 2;
+            }
+            other => {
+                return Frame::error(format!("ERR Syntax error in HELLO option '{}'", other));
+            }
+        }
+    }
+
+    if user.is_none() && authenticated.is_none() {
+        return CommandError::NoAuth("HELLO must be called with the client already authenticated, otherwise the HELLO <proto> AUTH <user> <pass> option can be used to authenticate the client and select the RESP protocol version at the same time".to_string()).into();
+    }
+
+    if let Some(name) = authenticated {
+        *user = Some(name.clone());
+    }
+    *protocol = requested;
+    if let Some(name) = setname {
+        monitor.client_tracker.set_name(client_id, (!name.is_empty()).then(|| name.clone()));
+    }
+
+    Frame::Map(vec![
+        (Frame::bulk("server"), Frame::bulk("crab-cage")),
+        (Frame::bulk("version"), Frame::bulk(env!("CARGO_PKG_VERSION"))),
+        (Frame::bulk("proto"), Frame::Integer(requested as i64)),
+        (Frame::bulk("id"), Frame::Integer(client_id as i64)),
+        (Frame::bulk("mode"), Frame::bulk(if cluster_enabled { "cluster" } else { "standalone" })),
+        (Frame::bulk("role"), Frame::bulk(if replication.is_replica() { "replica" } else { "master" })),
+        (Frame::bulk("modules"), Frame::Array(vec![])),
+    ])
 }
\ No newline at end of file
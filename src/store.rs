@@ -0,0 +1,263 @@
+// src/store.rs
+
+//! 不经过网络层使用引擎的类型化接口
+//!
+//! [`Store`] 把常用命令包装为返回 Rust 类型的方法：不存在的 key 得到 `None` 或空集合，
+//! 而不是需要调用方解析的回复帧。
+//!
+//! 每个方法执行的都是对应的命令，与脚本中的 `redis.call` 走同一条路径：
+//! 按命令表校验参数、先清理已过期的 key，再交给引擎执行。用 [`Store::with_persistence`]
+//! 创建时，成功的写命令还会追加到 AOF 并通知 WATCH 与客户端缓存，与网络上的写入效果一致。
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use anyhow::{Result, bail};
+use bytes::Bytes;
+
+use crate::command;
+use crate::engine::KvEngine;
+use crate::engine::kv::DbInstance;
+use crate::error::CommandError;
+use crate::persistence::Persistence;
+use crate::protocol::Frame;
+use crate::script;
+
+/// 数据库实例上的类型化操作
+#[derive(Clone)]
+pub struct Store<E = DbInstance> {
+    db: E,
+    pers: Option<Arc<Persistence>>,
+}
+
+impl<E: KvEngine> Store<E> {
+    /// 只读写引擎，写入不记入 AOF
+    pub fn new(db: E) -> Self {
+        Store { db, pers: None }
+    }
+
+    /// 写命令成功后追加到该持久化器的 AOF，并触发自动快照
+    pub fn with_persistence(db: E, pers: Arc<Persistence>) -> Self {
+        Store { db, pers: Some(pers) }
+    }
+
+    /// 底层的数据库实例
+    pub fn engine(&self) -> &E {
+        &self.db
+    }
+
+    /// 执行任意一条命令并返回原始回复；错误回复转换为 `Err`，
+    /// 可以用 `downcast_ref::<CommandError>()` 取得错误码
+    pub fn command<S: AsRef<[u8]>>(&self, args: &[S]) -> Result<Frame> {
+        let parts: Vec<Bytes> = args.iter().map(|a| Bytes::copy_from_slice(a.as_ref())).collect();
+        if parts.is_empty() {
+            bail!("ERR empty command");
+        }
+        let spec = command::lookup(&parts[0]);
+        let is_write = spec.is_some_and(|spec| spec.is_write());
+
+        let reply = match &self.pers {
+            Some(pers) => {
+                // 与网络层相同：快照进行中时持有写锁，追加 AOF 后才释放
+                let _write_guard = pers.write_guard(std::slice::from_ref(&parts));
+                let reply = script::call_command(&self.db, &parts);
+                if is_write && !reply.is_error() {
+                    pers.append_aof_and_maybe_snapshot(&parts);
+                }
+                reply
+            }
+            None => script::call_command(&self.db, &parts),
+        };
+
+        if is_write
+            && !reply.is_error()
+            && let (Some(spec), Some(watch_manager)) = (spec, self.db.watch_manager())
+        {
+            for key in spec.keys(&parts) {
+                watch_manager.invalidate(key, 0);
+            }
+        }
+
+        match reply {
+            Frame::Error(msg) => Err(CommandError::parse(&msg).into()),
+            reply => Ok(reply),
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Result<Option<Bytes>> {
+        optional_bytes(self.command(&["GET", key])?)
+    }
+
+    pub fn set(&self, key: &str, value: &str) -> Result<()> {
+        self.command(&["SET", key, value]).map(|_| ())
+    }
+
+    /// 写入新值并返回旧值，key 不存在时为 `None`
+    pub fn getset(&self, key: &str, value: &str) -> Result<Option<Bytes>> {
+        optional_bytes(self.command(&["GETSET", key, value])?)
+    }
+
+    /// 删除字符串 key，返回它是否存在
+    pub fn del(&self, key: &str) -> Result<bool> {
+        Ok(integer(self.command(&["DEL", key])?)? == 1)
+    }
+
+    pub fn incr(&self, key: &str) -> Result<i64> {
+        integer(self.command(&["INCR", key])?)
+    }
+
+    /// 设置过期时间（秒），key 不存在时返回 `false`
+    pub fn expire(&self, key: &str, secs: u64) -> Result<bool> {
+        Ok(integer(self.command(&["EXPIRE", key, &secs.to_string()])?)? == 1)
+    }
+
+    /// 剩余生存时间（秒）；key 不存在或没有过期时间时为 `None`
+    pub fn ttl(&self, key: &str) -> Result<Option<u64>> {
+        let ttl = integer(self.command(&["TTL", key])?)?;
+        Ok(u64::try_from(ttl).ok())
+    }
+
+    /// 设置 hash 字段，返回是否是新字段
+    pub fn hset(&self, key: &str, field: &str, value: &str) -> Result<bool> {
+        Ok(integer(self.command(&["HSET", key, field, value])?)? == 1)
+    }
+
+    pub fn hget(&self, key: &str, field: &str) -> Result<Option<Bytes>> {
+        optional_bytes(self.command(&["HGET", key, field])?)
+    }
+
+    /// 删除 hash 字段，返回它是否存在
+    pub fn hdel(&self, key: &str, field: &str) -> Result<bool> {
+        Ok(integer(self.command(&["HDEL", key, field])?)? == 1)
+    }
+
+    pub fn hget_all(&self, key: &str) -> Result<HashMap<String, Bytes>> {
+        match self.command(&["HGETALL", key])? {
+            Frame::Map(pairs) => pairs
+                .into_iter()
+                .map(|(field, value)| Ok((String::from_utf8(bytes(field)?.to_vec())?, bytes(value)?)))
+                .collect(),
+            other => bail!("unexpected reply to HGETALL: {:?}", other),
+        }
+    }
+
+    /// 头部插入，返回插入后的列表长度
+    pub fn lpush(&self, key: &str, value: &str) -> Result<i64> {
+        integer(self.command(&["LPUSH", key, value])?)
+    }
+
+    /// 尾部追加，返回追加后的列表长度
+    pub fn rpush(&self, key: &str, value: &str) -> Result<i64> {
+        integer(self.command(&["RPUSH", key, value])?)
+    }
+
+    pub fn lpop(&self, key: &str) -> Result<Option<Bytes>> {
+        optional_bytes(self.command(&["LPOP", key])?)
+    }
+
+    pub fn rpop(&self, key: &str) -> Result<Option<Bytes>> {
+        optional_bytes(self.command(&["RPOP", key])?)
+    }
+
+    /// 闭区间 `[start, stop]` 内的元素，支持负数下标
+    pub fn lrange(&self, key: &str, start: i64, stop: i64) -> Result<Vec<Bytes>> {
+        items(self.command(&["LRANGE", key, &start.to_string(), &stop.to_string()])?)
+    }
+
+    /// 添加集合成员，返回是否是新成员
+    pub fn sadd(&self, key: &str, member: &str) -> Result<bool> {
+        Ok(integer(self.command(&["SADD", key, member])?)? == 1)
+    }
+
+    /// 删除集合成员，返回它是否存在
+    pub fn srem(&self, key: &str, member: &str) -> Result<bool> {
+        Ok(integer(self.command(&["SREM", key, member])?)? == 1)
+    }
+
+    pub fn sismember(&self, key: &str, member: &str) -> Result<bool> {
+        Ok(integer(self.command(&["SISMEMBER", key, member])?)? == 1)
+    }
+
+    pub fn smembers(&self, key: &str) -> Result<Vec<Bytes>> {
+        items(self.command(&["SMEMBERS", key])?)
+    }
+}
+
+fn bytes(frame: Frame) -> Result<Bytes> {
+    match frame {
+        Frame::Bulk(b) => Ok(Bytes::from(b)),
+        Frame::Simple(s) => Ok(Bytes::from(s)),
+        other => bail!("expected a string reply, got {:?}", other),
+    }
+}
+
+/// nil 回复（key、字段或元素不存在）转换为 `None`
+fn optional_bytes(frame: Frame) -> Result<Option<Bytes>> {
+    match frame {
+        Frame::Null => Ok(None),
+        other => bytes(other).map(Some),
+    }
+}
+
+fn integer(frame: Frame) -> Result<i64> {
+    match frame {
+        Frame::Integer(n) => Ok(n),
+        other => bail!("expected an integer reply, got {:?}", other),
+    }
+}
+
+fn items(frame: Frame) -> Result<Vec<Bytes>> {
+    match frame {
+        Frame::Array(items) | Frame::Set(items) => items.into_iter().map(bytes).collect(),
+        other => bail!("expected an array reply, got {:?}", other),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::Storage;
+
+    #[test]
+    fn test_typed_commands() -> Result<()> {
+        let store = Store::new(Storage::sled(sled::Config::new().temporary(true).open()?)?);
+
+        assert_eq!(store.get("missing")?, None);
+        store.set("k", "v")?;
+        assert_eq!(store.get("k")?, Some(Bytes::from("v")));
+        assert_eq!(store.getset("k", "w")?, Some(Bytes::from("v")));
+        assert_eq!(store.getset("fresh", "w")?, None);
+        assert!(store.del("k")?);
+        assert!(!store.del("k")?);
+        assert_eq!(store.incr("n")?, 1);
+        assert!(store.get("n").is_ok());
+
+        assert!(store.hset("h", "f", "1")?);
+        assert!(!store.hset("h", "f", "2")?);
+        assert_eq!(store.hget("h", "f")?, Some(Bytes::from("2")));
+        assert_eq!(store.hget("h", "missing")?, None);
+        assert_eq!(store.hget_all("h")?, HashMap::from([("f".to_string(), Bytes::from("2"))]));
+        assert!(store.hget_all("nothing")?.is_empty());
+
+        store.rpush("l", "a")?;
+        assert_eq!(store.lpush("l", "b")?, 2);
+        assert_eq!(store.lrange("l", 0, -1)?, vec![Bytes::from("b"), Bytes::from("a")]);
+        assert_eq!(store.rpop("l")?, Some(Bytes::from("a")));
+        assert_eq!(store.lpop("l")?, Some(Bytes::from("b")));
+        assert_eq!(store.lpop("l")?, None);
+
+        assert!(store.sadd("s", "m")?);
+        assert!(store.sismember("s", "m")?);
+        assert_eq!(store.smembers("s")?, vec![Bytes::from("m")]);
+
+        assert_eq!(store.ttl("s")?, None);
+        assert!(store.expire("s", 100)?);
+        assert!(store.ttl("s")?.is_some_and(|ttl| ttl <= 100));
+
+        // 错误回复以 Err 返回
+        store.set("k", "abc")?;
+        let err = store.incr("k").unwrap_err();
+        assert_eq!(err.downcast_ref::<CommandError>().map(CommandError::code), Some("ERR"));
+        Ok(())
+    }
+}
@@ -0,0 +1,155 @@
+// src/tls.rs
+
+//! TLS 终止：根据配置中的证书 / 私钥 / CA 构造 rustls 接收器
+//!
+//! - 同时配置 `tls_cert_file` 与 `tls_key_file` 时，监听端口改为 TLS
+//! - 配置 `tls_ca_cert_file` 后会校验客户端证书；
+//!   `tls_auth_clients` 为 true 时客户端必须出示证书，否则证书可选
+
+use std::{fs::File, io::BufReader, sync::Arc};
+
+use anyhow::{anyhow, bail, Context, Result};
+use tokio_rustls::rustls::{
+    pki_types::{CertificateDer, PrivateKeyDer},
+    server::WebPkiClientVerifier,
+    RootCertStore, ServerConfig,
+};
+use tokio_rustls::TlsAcceptor;
+
+use crate::config::Config;
+
+/// 未配置证书时返回 None，表示使用明文 TCP
+pub fn build_acceptor(cfg: &Config) -> Result<Option<TlsAcceptor>> {
+    let (cert_path, key_path) = match (&cfg.tls_cert_file, &cfg.tls_key_file) {
+        (Some(cert), Some(key)) => (cert, key),
+        (None, None) => {
+            if cfg.tls_auth_clients {
+                bail!("tls_auth_clients requires tls_cert_file and tls_key_file");
+            }
+            return Ok(None);
+        }
+        _ => bail!("tls_cert_file and tls_key_file must be configured together"),
+    };
+
+    let certs = load_certs(cert_path)?;
+    let key = load_key(key_path)?;
+
+    let builder = ServerConfig::builder();
+    let builder = match &cfg.tls_ca_cert_file {
+        Some(ca_path) => {
+            let mut roots = RootCertStore::empty();
+            for cert in load_certs(ca_path)? {
+                roots.add(cert).context("Invalid CA certificate")?;
+            }
+            let verifier = WebPkiClientVerifier::builder(Arc::new(roots));
+            let verifier = if cfg.tls_auth_clients {
+                verifier.build()
+            } else {
+                verifier.allow_unauthenticated().build()
+            }
+            .map_err(|e| anyhow!("Failed to build client verifier: {}", e))?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => {
+            if cfg.tls_auth_clients {
+                bail!("tls_auth_clients requires tls_ca_cert_file");
+            }
+            builder.with_no_client_auth()
+        }
+    };
+
+    let server_cfg = builder
+        .with_single_cert(certs, key)
+        .context("Invalid TLS certificate or private key")?;
+    Ok(Some(TlsAcceptor::from(Arc::new(server_cfg))))
+}
+
+fn load_certs(path: &str) -> Result<Vec<CertificateDer<'static>>> {
+    let file = File::open(path).with_context(|| format!("Failed to open certificate {:?}", path))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<std::io::Result<Vec<_>>>()
+        .with_context(|| format!("Failed to parse certificate {:?}", path))?;
+    if certs.is_empty() {
+        bail!("No certificate found in {:?}", path);
+    }
+    Ok(certs)
+}
+
+fn load_key(path: &str) -> Result<PrivateKeyDer<'static>> {
+    let file = File::open(path).with_context(|| format!("Failed to open private key {:?}", path))?;
+    rustls_pemfile::private_key(&mut BufReader::new(file))
+        .with_context(|| format!("Failed to parse private key {:?}", path))?
+        .ok_or_else(|| anyhow!("No private key found in {:?}", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio_rustls::rustls::{pki_types::ServerName, ClientConfig};
+    use tokio_rustls::TlsConnector;
+
+    fn base_config() -> Config {
+        serde_json::from_str(
+            r#"{"aof":false,"rdb":false,"snapshot_interval_secs":60,"snapshot_threshold":20,
+                "metrics_enabled":false,"metrics_port":9090,"slowlog_threshold_ms":10}"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_plaintext_without_certs() {
+        let cfg = base_config();
+        assert!(build_acceptor(&cfg).unwrap().is_none());
+
+        let mut cfg = base_config();
+        cfg.tls_cert_file = Some("cert.pem".into());
+        assert!(build_acceptor(&cfg).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_tls_handshake() {
+        let dir = tempdir().unwrap();
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let cert_path = dir.path().join("cert.pem");
+        let key_path = dir.path().join("key.pem");
+        std::fs::write(&cert_path, cert.cert.pem()).unwrap();
+        std::fs::write(&key_path, cert.key_pair.serialize_pem()).unwrap();
+
+        let mut cfg = base_config();
+        cfg.tls_cert_file = Some(cert_path.to_string_lossy().into_owned());
+        cfg.tls_key_file = Some(key_path.to_string_lossy().into_owned());
+        let acceptor = build_acceptor(&cfg).unwrap().unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut tls = acceptor.accept(stream).await.unwrap();
+            let mut buf = [0u8; 4];
+            tls.read_exact(&mut buf).await.unwrap();
+            tls.write_all(&buf).await.unwrap();
+            tls.flush().await.unwrap();
+        });
+
+        let mut roots = RootCertStore::empty();
+        roots.add(cert.cert.der().clone()).unwrap();
+        let client_cfg = ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let connector = TlsConnector::from(Arc::new(client_cfg));
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let mut tls = connector
+            .connect(ServerName::try_from("localhost").unwrap(), stream)
+            .await
+            .unwrap();
+        tls.write_all(b"PING").await.unwrap();
+        let mut buf = [0u8; 4];
+        tls.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"PING");
+
+        server.await.unwrap();
+    }
+}
@@ -1,22 +1,32 @@
 // src/txn/executor.rs
 
-use anyhow::{Result, Error};
-use sled::{transaction::{ConflictableTransactionError, TransactionError}, Tree};
-use crate::engine;
-use sled::{Db};
+use anyhow::Error;
+use bytes::Bytes;
+use sled::transaction::ConflictableTransactionError;
+use crate::command;
+use crate::engine::{self, KvEngine};
+use crate::error::CommandError;
+use crate::protocol::Frame;
 
 // 事务的执行命令
-// 逐一执行事务队列中的每条命令
+// 通过 KvEngine::transaction_for_keys 在同一个 sled 事务中逐一执行队列中的每条命令，
+// 并声明队列涉及的 key，使事务中的集合读取（HGETALL / SMEMBERS 等）可用
+// 执行前先惰性清理命令涉及的已过期 key，与非事务模式保持一致
 // 任一命令若返回 ERR ， 则 Abort
-pub fn exec_all(db: &Db, cmds: &[Vec<String>]) -> Vec<String> {
-    let tree: Tree = db.open_tree("").expect("Failed to open transaction tree");
-    
-    let res: Result<Vec<String>, TransactionError<Error>> = tree.transaction(|tx| {
+// 成功时返回每条命令回复组成的数组
+pub fn exec_all<E: KvEngine>(db: &E, cmds: &[Vec<Bytes>]) -> Frame {
+    let keys: Vec<&[u8]> = cmds
+        .iter()
+        .filter_map(|parts| command::lookup(&parts[0]).map(|spec| spec.keys(parts)))
+        .flatten()
+        .collect();
+    let res = db.transaction_for_keys(&keys, |tx| {
         let mut out = Vec::with_capacity(cmds.len());
         for parts in cmds {
-            let r = engine::execute_non_txn_command(&parts[0].to_uppercase(), parts, tx);
-            if r.starts_with("ERR") {
-                return Err(ConflictableTransactionError::Abort(Error::msg(r)));
+            engine::purge_expired(tx, parts).map_err(ConflictableTransactionError::Abort)?;
+            let r = engine::execute_non_txn_command(&command::name_upper(&parts[0]), parts, tx);
+            if let Frame::Error(msg) = r {
+                return Err(ConflictableTransactionError::Abort(Error::msg(msg)));
             }
             out.push(r);
         }
@@ -24,8 +34,7 @@ pub fn exec_all(db: &Db, cmds: &[Vec<String>]) -> Vec<String> {
     });
 
     match res {
-        Ok(v) => v,
-        Err(e) => vec![format!("ERR {}", e)],
+        Ok(v) => Frame::Array(v),
+        Err(e) => CommandError::parse(&e.to_string()).into(),
     }
 }
-
@@ -1,16 +1,47 @@
 // src/txn/session.rs
 
+use std::sync::Arc;
+
+use bytes::Bytes;
+
+use crate::engine::watch::WatchManager;
+use crate::error::CommandError;
+
 /// 保存单个连接的 MULTI 队列状态
 #[derive(Debug)]
 pub struct TxnSession {
     pub id: u64,
     pub in_multi: bool,
-    pub queue: Vec<Vec<String>>,
+    pub queue: Vec<Vec<Bytes>>,
+    /// 入队时出现过错误（未知命令、参数个数错误等），EXEC 时整个事务被放弃
+    pub aborted: bool,
+    /// WATCH 时登记监视的管理器；EXEC / DISCARD / 会话销毁时自动解除监视
+    watch_manager: Option<Arc<WatchManager>>,
 }
 
 impl TxnSession {
     pub fn new(id: u64) -> Self {
-        TxnSession { id, in_multi: false, queue: Vec::new() }
+        TxnSession { id, in_multi: false, queue: Vec::new(), aborted: false, watch_manager: None }
+    }
+
+    /// WATCH key [key ...]
+    pub fn watch<K: AsRef<[u8]>>(&mut self, manager: Arc<WatchManager>, keys: &[K]) {
+        manager.watch(self.id, keys);
+        self.watch_manager = Some(manager);
+    }
+
+    /// UNWATCH：解除全部监视
+    pub fn unwatch(&mut self) {
+        if let Some(manager) = self.watch_manager.take() {
+            manager.clear_session(self.id);
+        }
+    }
+
+    /// 监视的 key 是否在 WATCH 之后被修改过
+    pub fn is_dirty(&self) -> bool {
+        self.watch_manager
+            .as_ref()
+            .is_some_and(|manager| manager.is_dirty(self.id))
     }
 
     pub fn begin(&mut self) -> Result<&'static str, &'static str> {
@@ -19,11 +50,13 @@ impl TxnSession {
         } else {
             self.in_multi = true;
             self.queue.clear();
+            self.aborted = false;
             Ok("OK")
         }
     }
 
-    pub fn enqueue(&mut self, cmd: Vec<String>) -> Result<&'static str, ()> {
+    #[allow(clippy::result_unit_err)]
+    pub fn enqueue(&mut self, cmd: Vec<Bytes>) -> Result<&'static str, ()> {
         if !self.in_multi {
             Err(())
         } else {
@@ -32,36 +65,49 @@ impl TxnSession {
         }
     }
 
+    /// 事务中的命令入队失败，标记整个事务在 EXEC 时放弃
+    pub fn flag_error(&mut self) {
+        if self.in_multi {
+            self.aborted = true;
+        }
+    }
+
     pub fn discard(&mut self) -> Result<&'static str, &'static str> {
         if !self.in_multi {
             Err("ERR DISCARD without MULTI")
         } else {
             self.in_multi = false;
             self.queue.clear();
+            self.aborted = false;
+            self.unwatch();
             Ok("OK")
         }
     }
 
-    pub fn take_queue(&mut self) -> Result<Vec<Vec<String>>, &'static str> {
+    pub fn take_queue(&mut self) -> Result<Vec<Vec<Bytes>>, CommandError> {
         if !self.in_multi {
-            Err("ERR EXEC without MULTI")
+            Err(CommandError::err("EXEC without MULTI"))
+        } else if self.aborted {
+            self.in_multi = false;
+            self.queue.clear();
+            self.aborted = false;
+            self.unwatch();
+            Err(CommandError::ExecAbort)
         } else {
             self.in_multi = false;
+            self.unwatch();
             Ok(std::mem::take(&mut self.queue))
         }
     }
+}
 
-    /// 获取当前队列中的命令（不改变状态）
-    pub fn get_queued_commands(&self) -> Option<Vec<String>> {
-        if !self.in_multi || self.queue.is_empty() {
-            return None;
-        }
-        
-        Some(self.queue.iter().map(|parts| parts.join(" ")).collect())
+// 连接断开（包括出错退出）时会话被销毁，监视随之解除
+impl Drop for TxnSession {
+    fn drop(&mut self) {
+        self.unwatch();
     }
 }
 
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -109,7 +155,7 @@ mod tests {
     fn test_enqueue_success() {
         let mut session = TxnSession::new(16);
         session.begin().unwrap();
-        let cmd = vec!["SET".to_string(), "key".to_string(), "value".to_string()];
+        let cmd = vec![Bytes::from("SET"), Bytes::from("key"), Bytes::from("value")];
         assert_eq!(session.enqueue(cmd.clone()), Ok("QUEUED"));
         assert_eq!(session.queue, vec![cmd]);
     }
@@ -120,7 +166,7 @@ mod tests {
     #[test]
     fn test_enqueue_failure_not_in_multi() {
         let mut session = TxnSession::new(16);
-        let cmd = vec!["SET".to_string(), "key".to_string(), "value".to_string()];
+        let cmd = vec![Bytes::from("SET"), Bytes::from("key"), Bytes::from("value")];
         assert_eq!(session.enqueue(cmd), Err(()));
         assert!(session.queue.is_empty());
     }
@@ -131,7 +177,7 @@ mod tests {
     fn test_discard_success() {
         let mut session = TxnSession::new(16);
         session.begin().unwrap();
-        session.enqueue(vec!["CMD".to_string()]).unwrap();
+        session.enqueue(vec![Bytes::from("CMD")]).unwrap();
         assert_eq!(session.discard(), Ok("OK"));
         assert!(!session.in_multi);
         assert!(session.queue.is_empty());
@@ -151,8 +197,8 @@ mod tests {
     fn test_take_queue_success() {
         let mut session = TxnSession::new(16);
         session.begin().unwrap();
-        let cmd1 = vec!["CMD1".to_string()];
-        let cmd2 = vec!["CMD2".to_string()];
+        let cmd1 = vec![Bytes::from("CMD1")];
+        let cmd2 = vec![Bytes::from("CMD2")];
         session.enqueue(cmd1.clone()).unwrap();
         session.enqueue(cmd2.clone()).unwrap();
 
@@ -166,11 +212,51 @@ mod tests {
     #[test]
     fn test_take_queue_failure_not_in_multi() {
         let mut session = TxnSession::new(16);
-        assert_eq!(
-            session.take_queue(),
-            Err("ERR EXEC without MULTI")
-        );
+        assert_eq!(session.take_queue(), Err(CommandError::err("EXEC without MULTI")));
+        assert!(!session.in_multi);
+    }
+
+    // 入队出错后 EXEC 返回 EXECABORT，并退出事务
+    #[test]
+    fn test_take_queue_after_error() {
+        let mut session = TxnSession::new(16);
+        session.begin().unwrap();
+        session.enqueue(vec![Bytes::from("CMD1")]).unwrap();
+        session.flag_error();
+        assert_eq!(session.take_queue(), Err(CommandError::ExecAbort));
         assert!(!session.in_multi);
+        assert!(session.queue.is_empty());
+
+        // 新事务不受影响
+        session.begin().unwrap();
+        assert_eq!(session.take_queue(), Ok(vec![]));
+    }
+
+    // EXEC / DISCARD / 会话销毁都会解除监视
+    #[test]
+    fn test_auto_unwatch() {
+        let manager = Arc::new(WatchManager::new());
+        let keys = vec!["k".to_string()];
+
+        let mut session = TxnSession::new(16);
+        session.watch(manager.clone(), &keys);
+        manager.notify_key_change(b"k");
+        assert!(session.is_dirty());
+        session.begin().unwrap();
+        session.take_queue().unwrap();
+        assert!(!session.is_dirty());
+        assert!(!manager.is_dirty(16));
+
+        session.watch(manager.clone(), &keys);
+        session.begin().unwrap();
+        session.discard().unwrap();
+        manager.notify_key_change(b"k");
+        assert!(!manager.is_dirty(16));
+
+        session.watch(manager.clone(), &keys);
+        drop(session);
+        manager.notify_key_change(b"k");
+        assert!(!manager.is_dirty(16));
     }
 
     // 输出执行队列后，可重新开启事务
@@ -182,8 +268,8 @@ mod tests {
         assert_eq!(session.begin(), Ok("OK"));
         
         // 添加命令
-        let cmd1 = vec!["GET".to_string(), "key1".to_string()];
-        let cmd2 = vec!["SET".to_string(), "key2".to_string(), "value".to_string()];
+        let cmd1 = vec![Bytes::from("GET"), Bytes::from("key1")];
+        let cmd2 = vec![Bytes::from("SET"), Bytes::from("key2"), Bytes::from("value")];
         assert_eq!(session.enqueue(cmd1.clone()), Ok("QUEUED"));
         assert_eq!(session.enqueue(cmd2.clone()), Ok("QUEUED"));
         
@@ -3,7 +3,7 @@
 //! # Hash Type Support
 //! 
 //! This module implements Redis-like Hash data structures on top of `sled`.
-//! Each hash key is stored as a separate `sled::Tree` named `"hash:<key>"`.
+//! Each field is stored as its own record, keyed by `keys::hash_field(key, field)`.
 //! 
 //! Supported commands:
 //! - `HSET`
@@ -14,9 +14,10 @@
 //! - `HGETALL`
 
 use anyhow::{Context, Ok, Result};
+use crate::engine::budget;
 use crate::engine::kv::KvEngine;
+use crate::keys::{self, Kind};
 
-const PREFIX: &str = "hash:";
 
 /// Execute the HSET command:
 /// Set the string value of a hash field.
@@ -36,14 +37,14 @@ const PREFIX: &str = "hash:";
 /// # Errors
 ///
 /// Returns an error if opening the tree, inserting the value, or flushing the tree fails.
-pub fn hset<E>(db: &E, key: &str, field: &str, value: &str) -> Result<String> 
+pub fn hset<E>(db: &E, key: &[u8], field: &[u8], value: &[u8]) -> Result<String> 
 where 
     E: KvEngine,
 {
-    let namespaced = format!("{}{}:{}", PREFIX, key, field);
+    let namespaced = keys::hash_field(key, field);
     let prev = db
-        .insert(namespaced.as_bytes(), value.as_bytes())
-        .with_context(|| format!("ERR failed to HSET {}/{}", key, field))?;
+        .insert(&namespaced, value)
+        .with_context(|| format!("ERR failed to HSET {}/{}", String::from_utf8_lossy(key), String::from_utf8_lossy(field)))?;
 
     Ok(if prev.is_none() { "1".into() } else { "0".into() })
 }
@@ -59,24 +60,18 @@ where
 ///
 /// # Returns
 ///
-/// * The field’s value as `String` if it exists.
-/// * `"nil"` if the field does not exist.
+/// * The field’s value as raw bytes if it exists.
+/// * `None` if the field does not exist.
 ///
 /// # Errors
 ///
-/// Returns an error if opening the tree, reading the value, or UTF-8 conversion fails.
-pub fn hget<E>(db: &E, key: &str, field: &str) -> Result<String> 
+/// Returns an error if opening the tree or reading the value fails.
+pub fn hget<E>(db: &E, key: &[u8], field: &[u8]) -> Result<Option<Vec<u8>>> 
 where 
     E:KvEngine,
 {
-    let namespaced = format!("{}{}:{}", PREFIX, key, field);
-    if let Some(bytes) = db.get(namespaced.as_bytes())? {
-        let s = std::str::from_utf8(&bytes)
-            .context("ERR non-utf8 in HGET")?;
-        Ok(s.to_string())
-    } else {
-        Ok("nil".into())
-    }
+    let namespaced = keys::hash_field(key, field);
+    Ok(db.get(&namespaced)?.map(|bytes| bytes.to_vec()))
 }
 
 /// Execute the HDEL command:
@@ -96,12 +91,12 @@ where
 /// # Errors
 ///
 /// Returns an error if opening the tree, removing the value, or flushing the tree fails.
-pub fn hdel<E>(db: &E, key: &str, field: &str) -> Result<String> 
+pub fn hdel<E>(db: &E, key: &[u8], field: &[u8]) -> Result<String> 
 where 
     E:KvEngine
 {
-    let namespaced = format!("{}{}:{}", PREFIX, key, field);
-    let removed = db.remove(namespaced.as_bytes())?;
+    let namespaced = keys::hash_field(key, field);
+    let removed = db.remove(&namespaced)?;
     Ok(if removed.is_some() { "1".into() } else { "0".into() })
 }
 
@@ -115,25 +110,25 @@ where
 ///
 /// # Returns
 ///
-/// A comma-separated `String` of all field names. Returns an empty string if the hash does not exist or has no fields.
+/// All field names in the hash. Returns an empty `Vec` if the hash does not exist or has no fields.
 ///
 /// # Errors
 ///
-/// Returns an error if opening the tree, iterating, or UTF-8 conversion fails.
-pub fn hkeys<E>(db: &E, key: &str) -> Result<String> 
+/// Returns an error if opening the tree or iterating fails.
+pub fn hkeys<E>(db: &E, key: &[u8]) -> Result<Vec<Vec<u8>>> 
 where 
     E:KvEngine,
 {
-    let prefix = format!("{}{}:", PREFIX, key);
+    let prefix = keys::prefix(Kind::Hash, key);
     let mut fields = Vec::new();
     
-    for entry in db.scan_prefix(prefix.as_bytes()) {
+    for entry in db.scan_prefix(&prefix) {
+        budget::check()?;
         let (k, _) = entry?;
-        let field = std::str::from_utf8(&k[prefix.len()..])?;
-        fields.push(field.to_string());
+        fields.push(k[prefix.len()..].to_vec());
     }
     
-    Ok(fields.join(","))
+    Ok(fields)
 }
 
 /// Execute the HVALS command:
@@ -146,25 +141,25 @@ where
 ///
 /// # Returns
 ///
-/// A comma-separated `String` of all values. Returns an empty string if the hash does not exist or has no fields.
+/// All values in the hash. Returns an empty `Vec` if the hash does not exist or has no fields.
 ///
 /// # Errors
 ///
-/// Returns an error if opening the tree, iterating, or UTF-8 conversion fails.
-pub fn hvals<E>(db: &E, key: &str) -> Result<String> 
+/// Returns an error if opening the tree or iterating fails.
+pub fn hvals<E>(db: &E, key: &[u8]) -> Result<Vec<Vec<u8>>> 
 where 
     E: KvEngine,
 {
-    let prefix = format!("{}{}:", PREFIX, key);
+    let prefix = keys::prefix(Kind::Hash, key);
     let mut values = Vec::new();
     
-    for entry in db.scan_prefix(prefix.as_bytes()) {
+    for entry in db.scan_prefix(&prefix) {
+        budget::check()?;
         let (_, v) = entry?;
-        let value = std::str::from_utf8(&v)?;
-        values.push(value.to_string());
+        values.push(v.to_vec());
     }
     
-    Ok(values.join(","))
+    Ok(values)
 }
 
 /// Execute the HGETALL command:
@@ -177,24 +172,24 @@ where
 ///
 /// # Returns
 ///
-/// A comma-separated `String` in the form `field1,value1,field2,value2,…`.
-/// Returns an empty string if the hash does not exist or has no fields.
+/// `(field, value)` pairs in key order.
+/// Returns an empty `Vec` if the hash does not exist or has no fields.
 ///
 /// # Errors
 ///
-/// Returns an error if opening the tree, iterating, or UTF-8 conversion fails.
-pub fn hgetall<E>(db: &E, key: &str) -> Result<String> 
+/// Returns an error if opening the tree or iterating fails.
+pub fn hgetall<E>(db: &E, key: &[u8]) -> Result<Vec<(Vec<u8>, Vec<u8>)>> 
 where 
     E: KvEngine
 {
-    let prefix = format!("{}{}:", PREFIX, key);
+    let prefix = keys::prefix(Kind::Hash, key);
     let mut entries = Vec::new();
-    for entry in db.scan_prefix(prefix.as_bytes()) {
+    for entry in db.scan_prefix(&prefix) {
+        budget::check()?;
         let (k, v) = entry?;
-        entries.push(std::str::from_utf8(&k[prefix.len()..])?.to_string());
-        entries.push(std::str::from_utf8(&v)?.to_string());
+        entries.push((k[prefix.len()..].to_vec(), v.to_vec()));
     }
-    Ok(entries.join(","))
+    Ok(entries)
 }
 
 #[cfg(test)]
@@ -218,41 +213,53 @@ mod tests {
         let db = make_db();
 
         // HSET on a new field should return "1"
-        assert_eq!(hset(&db, "myhash", "f1", "v1")?, "1");
+        assert_eq!(hset(&db, b"myhash", b"f1", b"v1")?, "1");
         // HSET on an existing field should return "0"
-        assert_eq!(hset(&db, "myhash", "f1", "v1a")?, "0");
+        assert_eq!(hset(&db, b"myhash", b"f1", b"v1a")?, "0");
         // HGET existing field
-        assert_eq!(hget(&db, "myhash", "f1")?, "v1a");
-        // HGET non-existent field returns "nil"
-        assert_eq!(hget(&db, "myhash", "f2")?, "nil");
+        assert_eq!(hget(&db, b"myhash", b"f1")?.as_deref(), Some(&b"v1a"[..]));
+        // HGET non-existent field returns None
+        assert_eq!(hget(&db, b"myhash", b"f2")?, None);
 
         // Add another field for key/value listings
-        hset(&db, "myhash", "f2", "v2")?;
+        hset(&db, b"myhash", b"f2", b"v2")?;
 
-        // HKEYS should list fields sorted lexicographically after split+sort
-        let hk = hkeys(&db, "myhash")?;
-        let mut ks: Vec<&str> = hk.split(',').collect();
+        // HKEYS should list fields sorted lexicographically after sort
+        let mut ks = hkeys(&db, b"myhash")?;
         ks.sort();
-        assert_eq!(ks, vec!["f1", "f2"]);
+        assert_eq!(ks, vec![b"f1".to_vec(), b"f2".to_vec()]);
 
         // HVALS should list values
-        let hv = hvals(&db, "myhash")?;
-        let mut vs: Vec<&str> = hv.split(',').collect();
+        let mut vs = hvals(&db, b"myhash")?;
         vs.sort();
-        assert_eq!(vs, vec!["v1a", "v2"]);
+        assert_eq!(vs, vec![b"v1a".to_vec(), b"v2".to_vec()]);
 
         // HGETALL should list interleaved field,value pairs
-        let hga = hgetall(&db, "myhash")?;
-        let mut elems: Vec<&str> = hga.split(',').collect();
+        let mut elems = hgetall(&db, b"myhash")?;
         elems.sort();
-        assert_eq!(elems, vec!["f1", "f2", "v1a", "v2"]);
+        assert_eq!(
+            elems,
+            vec![(b"f1".to_vec(), b"v1a".to_vec()), (b"f2".to_vec(), b"v2".to_vec())]
+        );
 
-        // HDEL existing field returns "1" and subsequent HGET returns "nil"
-        assert_eq!(hdel(&db, "myhash", "f1")?, "1");
-        assert_eq!(hget(&db, "myhash", "f1")?, "nil");
+        // HDEL existing field returns "1" and subsequent HGET returns None
+        assert_eq!(hdel(&db, b"myhash", b"f1")?, "1");
+        assert_eq!(hget(&db, b"myhash", b"f1")?, None);
         // HDEL non-existent field returns "0"
-        assert_eq!(hdel(&db, "myhash", "no")?, "0");
+        assert_eq!(hdel(&db, b"myhash", b"no")?, "0");
+
+        Ok(())
+    }
 
+    /// key 或 field 中含 ':' 时不同的 hash 互不干扰
+    #[test]
+    fn test_hash_keys_with_colons() -> Result<()> {
+        let db = make_db();
+        hset(&db, b"a", b"b:c", b"1")?;
+        assert_eq!(hset(&db, b"a:b", b"c", b"2")?, "1");
+        assert_eq!(hget(&db, b"a", b"b:c")?.as_deref(), Some(&b"1"[..]));
+        assert_eq!(hgetall(&db, b"a")?, vec![(b"b:c".to_vec(), b"1".to_vec())]);
+        assert_eq!(hkeys(&db, b"a:b")?, vec![b"c".to_vec()]);
         Ok(())
     }
 }
\ No newline at end of file
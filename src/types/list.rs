@@ -1,21 +1,13 @@
 // src/types/list.rs
 
 use anyhow::{Context, Result};
-use sled::transaction::ConflictableTransactionError;
-use std::str;
-use crate::engine::kv::KvEngine;
+use crate::engine::budget;
+use crate::engine::kv::{KvEngine, WriteBatch};
+use crate::keys::{self, KeyBuf};
 
-const DATA_PREFIX: &str = "list:data:";
-const META_PREFIX: &str = "list:meta:";
-
-/// 将序列号转换为排序友好的 u64 表示
-fn seq_to_u64(seq: i64) -> u64 {
-    (seq as u64) ^ (1 << 63)
-}
-
-/// 获取/设置 i64 元数据
-fn get_i64<E: KvEngine>(db: &E, key: &str) -> Result<Option<i64>> {
-    if let Some(bs) = db.get(key.as_bytes())? {
+/// 读取 i64 元数据
+fn get_i64<E: KvEngine>(db: &E, key: &[u8]) -> Result<Option<i64>> {
+    if let Some(bs) = db.get(key)? {
         let arr: [u8; 8] = bs.as_ref().try_into()?;
         Ok(Some(i64::from_be_bytes(arr)))
     } else {
@@ -23,15 +15,10 @@ fn get_i64<E: KvEngine>(db: &E, key: &str) -> Result<Option<i64>> {
     }
 }
 
-fn put_i64<E: KvEngine>(db: &E, key: &str, value: i64) -> Result<()> {
-    db.insert(key.as_bytes(), &value.to_be_bytes())?;
-    Ok(())
-}
-
 /// 获取列表的 head 和 tail
-fn get_bounds<E: KvEngine>(db: &E, key: &str) -> Result<Option<(i64, i64)>> {
-    let head_key = format!("{}{}:head", META_PREFIX, key);
-    let tail_key = format!("{}{}:tail", META_PREFIX, key);
+fn get_bounds<E: KvEngine>(db: &E, key: &[u8]) -> Result<Option<(i64, i64)>> {
+    let head_key = keys::list_head(key);
+    let tail_key = keys::list_tail(key);
     
     let head = match get_i64(db, &head_key)? {
         Some(h) => h,
@@ -39,51 +26,29 @@ fn get_bounds<E: KvEngine>(db: &E, key: &str) -> Result<Option<(i64, i64)>> {
     };
     
     let tail = get_i64(db, &tail_key)?
-        .with_context(|| format!("Missing tail metadata for list '{}'", key))?;
+        .with_context(|| format!("Missing tail metadata for list '{}'", String::from_utf8_lossy(key)))?;
     
     Ok(Some((head, tail)))
 }
 
 /// LPUSH 实现
-pub fn lpush<E: KvEngine>(db: &E, key: &str, value: &str) -> Result<String> {
+pub fn lpush<E: KvEngine>(db: &E, key: &[u8], value: &[u8]) -> Result<String> {
     let (head, tail) = match get_bounds(db, key)? {
         Some((h, t)) => (h, t),
         None => (0, -1),  // 空列表
     };
     
     let new_head = head - 1;
-    let data_key = format!("{}{}:{}", DATA_PREFIX, key, seq_to_u64(new_head));
-    
-    // 在事务中执行所有操作
-    if let Some(plain_db) = db.as_db() {
-        let tree = plain_db.open_tree("")?;
-        tree.transaction(|tx| {
-            tx.insert(data_key.as_bytes(), value.as_bytes())?;
-            
-            // 更新 head
-            let head_key = format!("{}{}:head", META_PREFIX, key);
-            tx.insert(head_key.as_bytes(), &new_head.to_be_bytes())?;
-            
-            // 如果是第一个元素，更新 tail
-            if tail < head {
-                let tail_key = format!("{}{}:tail", META_PREFIX, key);
-                tx.insert(tail_key.as_bytes(), &new_head.to_be_bytes())?;
-            }
-            
-            Ok::<(), ConflictableTransactionError>(())
-        })?;
-    } else {
-        // 在事务上下文中
-        db.insert(data_key.as_bytes(), value.as_bytes())?;
-        
-        let head_key = format!("{}{}:head", META_PREFIX, key);
-        db.insert(head_key.as_bytes(), &new_head.to_be_bytes())?;
-        
-        if tail < head {
-            let tail_key = format!("{}{}:tail", META_PREFIX, key);
-            db.insert(tail_key.as_bytes(), &new_head.to_be_bytes())?;
-        }
+
+    // 元素与元数据在一个批量写入中原子地更新
+    let mut batch = WriteBatch::new();
+    batch.insert(keys::list_item(key, new_head), value);
+    batch.insert(keys::list_head(key), &new_head.to_be_bytes());
+    // 如果是第一个元素，同时更新 tail
+    if tail < head {
+        batch.insert(keys::list_tail(key), &new_head.to_be_bytes());
     }
+    db.apply_batch(&batch)?;
     
     // 计算新长度
     let new_tail = if tail < head { new_head } else { tail };
@@ -92,44 +57,23 @@ pub fn lpush<E: KvEngine>(db: &E, key: &str, value: &str) -> Result<String> {
 }
 
 /// RPUSH 实现
-pub fn rpush<E: KvEngine>(db: &E, key: &str, value: &str) -> Result<String> {
+pub fn rpush<E: KvEngine>(db: &E, key: &[u8], value: &[u8]) -> Result<String> {
     let (head, tail) = match get_bounds(db, key)? {
         Some((h, t)) => (h, t),
         None => (0, -1),
     };
     
     let new_tail = tail + 1;
-    let data_key = format!("{}{}:{}", DATA_PREFIX, key, seq_to_u64(new_tail));
-    
-    // 在事务中执行所有操作
-    if let Some(plain_db) = db.as_db() {
-        let tree = plain_db.open_tree("")?;
-        tree.transaction(|tx| {
-            tx.insert(data_key.as_bytes(), value.as_bytes())?;
-            
-            // 更新 tail
-            let tail_key = format!("{}{}:tail", META_PREFIX, key);
-            tx.insert(tail_key.as_bytes(), &new_tail.to_be_bytes())?;
-            
-            // 如果是第一个元素，更新 head
-            if tail < head {
-                let head_key = format!("{}{}:head", META_PREFIX, key);
-                tx.insert(head_key.as_bytes(), &new_tail.to_be_bytes())?;
-            }
-            
-            Ok::<(), ConflictableTransactionError>(())
-        })?;
-    } else {
-        db.insert(data_key.as_bytes(), value.as_bytes())?;
-        
-        let tail_key = format!("{}{}:tail", META_PREFIX, key);
-        db.insert(tail_key.as_bytes(), &new_tail.to_be_bytes())?;
-        
-        if tail < head {
-            let head_key = format!("{}{}:head", META_PREFIX, key);
-            db.insert(head_key.as_bytes(), &new_tail.to_be_bytes())?;
-        }
+
+    // 元素与元数据在一个批量写入中原子地更新
+    let mut batch = WriteBatch::new();
+    batch.insert(keys::list_item(key, new_tail), value);
+    batch.insert(keys::list_tail(key), &new_tail.to_be_bytes());
+    // 如果是第一个元素，同时更新 head
+    if tail < head {
+        batch.insert(keys::list_head(key), &new_tail.to_be_bytes());
     }
+    db.apply_batch(&batch)?;
     
     // 计算新长度
     let new_head = if tail < head { new_tail } else { head };
@@ -137,81 +81,59 @@ pub fn rpush<E: KvEngine>(db: &E, key: &str, value: &str) -> Result<String> {
     Ok(len.to_string())
 }
 
-/// LPOP 实现
-pub fn lpop<E: KvEngine>(db: &E, key: &str) -> Result<String> {
+/// LPOP 实现，列表为空或不存在时返回 `None`
+pub fn lpop<E: KvEngine>(db: &E, key: &[u8]) -> Result<Option<Vec<u8>>> {
     let (head, tail) = match get_bounds(db, key)? {
         Some(ht) => ht,
-        None => return Ok("nil".into()),
+        None => return Ok(None),
     };
     
-    let data_key = format!("{}{}:{}", DATA_PREFIX, key, seq_to_u64(head));
-    let result = if let Some(bs) = db.remove(data_key.as_bytes())? {
-        // 更新元数据
+    let data_key = keys::list_item(key, head);
+    let result = if let Some(bs) = db.get(&data_key)? {
+        // 删除元素并更新元数据
+        let mut batch = WriteBatch::new();
+        batch.remove(data_key);
         if head + 1 > tail {
             // 列表为空，删除元数据
-            let head_key = format!("{}{}:head", META_PREFIX, key);
-            let tail_key = format!("{}{}:tail", META_PREFIX, key);
-            
-            if let Some(plain_db) = db.as_db() {
-                let tree = plain_db.open_tree("")?;
-                tree.transaction(|tx| {
-                    tx.remove(head_key.as_bytes())?;
-                    tx.remove(tail_key.as_bytes())?;
-                    Ok::<(), ConflictableTransactionError>(())
-                })?;
-            } else {
-                db.remove(head_key.as_bytes())?;
-                db.remove(tail_key.as_bytes())?;
-            }
+            batch.remove(keys::list_head(key));
+            batch.remove(keys::list_tail(key));
         } else {
-            // 更新 head
-            let head_key = format!("{}{}:head", META_PREFIX, key);
-            put_i64(db, &head_key, head + 1)?;
+            batch.insert(keys::list_head(key), &(head + 1).to_be_bytes());
         }
-        
-        String::from_utf8(bs.to_vec())?
+        db.apply_batch(&batch)?;
+
+        Some(bs.to_vec())
     } else {
-        "nil".into()
+        None
     };
     
     Ok(result)
 }
 
-/// RPOP 实现
-pub fn rpop<E: KvEngine>(db: &E, key: &str) -> Result<String> {
+/// RPOP 实现，列表为空或不存在时返回 `None`
+pub fn rpop<E: KvEngine>(db: &E, key: &[u8]) -> Result<Option<Vec<u8>>> {
     let (head, tail) = match get_bounds(db, key)? {
         Some(ht) => ht,
-        None => return Ok("nil".into()),
+        None => return Ok(None),
     };
     
-    let data_key = format!("{}{}:{}", DATA_PREFIX, key, seq_to_u64(tail));
-    let result = if let Some(bs) = db.remove(data_key.as_bytes())? {
-        // 更新元数据
+    let data_key = keys::list_item(key, tail);
+    let result = if let Some(bs) = db.get(&data_key)? {
+        // 删除元素并更新元数据
+        let mut batch = WriteBatch::new();
+        batch.remove(data_key);
         if head > tail - 1 {
             // 列表为空，删除元数据
-            let head_key = format!("{}{}:head", META_PREFIX, key);
-            let tail_key = format!("{}{}:tail", META_PREFIX, key);
-            
-            if let Some(plain_db) = db.as_db() {
-                let tree = plain_db.open_tree("")?;
-                tree.transaction(|tx| {
-                    tx.remove(head_key.as_bytes())?;
-                    tx.remove(tail_key.as_bytes())?;
-                    Ok::<(), ConflictableTransactionError>(())
-                })?;
-            } else {
-                db.remove(head_key.as_bytes())?;
-                db.remove(tail_key.as_bytes())?;
-            }
+            batch.remove(keys::list_head(key));
+            batch.remove(keys::list_tail(key));
         } else {
-            // 更新 tail
-            let tail_key = format!("{}{}:tail", META_PREFIX, key);
-            put_i64(db, &tail_key, tail - 1)?;
+            batch.insert(keys::list_tail(key), &(tail - 1).to_be_bytes());
         }
+        db.apply_batch(&batch)?;
         
-        String::from_utf8(bs.to_vec())?
+        Some(bs.to_vec())
     } else {
-        "nil".into()
+        None
     };
     
     Ok(result)
@@ -220,18 +142,18 @@ pub fn rpop<E: KvEngine>(db: &E, key: &str) -> Result<String> {
 /// LRANGE 实现
 pub fn lrange<E: KvEngine>(
     db: &E, 
-    key: &str, 
+    key: &[u8], 
     start: isize, 
     stop: isize
-) -> Result<String> {
+) -> Result<Vec<Vec<u8>>> {
     let (head, tail) = match get_bounds(db, key)? {
         Some((h, t)) => (h, t),
-        None => return Ok(String::new()), // 空列表
+        None => return Ok(Vec::new()), // 空列表
     };
     
     let total = (tail - head + 1) as isize;
     if total <= 0 {
-        return Ok(String::new());
+        return Ok(Vec::new());
     }
     
     // 处理负索引
@@ -243,23 +165,55 @@ pub fn lrange<E: KvEngine>(
     let e = e.max(0).min(total - 1) as i64;
     
     if s > e {
-        return Ok(String::new());
+        return Ok(Vec::new());
     }
     
-    let mut results = Vec::new();
+    let mut results = Vec::with_capacity((e - s + 1) as usize);
+    let mut buf = KeyBuf::new();
     for idx in s..=e {
+        budget::check()?;
         let seq = head + idx;
-        let data_key = format!("{}{}:{}", DATA_PREFIX, key, seq_to_u64(seq));
-        
-        if let Some(bs) = db.get(data_key.as_bytes())? {
-            let value = String::from_utf8(bs.to_vec())?;
-            results.push(value);
+
+        if let Some(bs) = db.get(buf.list_item(key, seq))? {
+            results.push(bs.to_vec());
         }
     }
     
-    Ok(results.join(","))
+    Ok(results)
 }
 
+/// LMPOP 的结果：弹出元素的 key 与弹出的元素
+pub type Popped = (Vec<u8>, Vec<Vec<u8>>);
+
+/// LMPOP 实现：依次检查 `keys`，从第一个非空列表的头部（`left`）或尾部弹出最多 `count` 个元素
+///
+/// 所有列表都为空时返回 `None`，否则返回 `(key, 弹出的元素)`
+pub fn lmpop<E: KvEngine, K: AsRef<[u8]>>(
+    db: &E,
+    keys: &[K],
+    left: bool,
+    count: usize,
+) -> Result<Option<Popped>> {
+    for key in keys {
+        let key = key.as_ref();
+        let (head, tail) = match get_bounds(db, key)? {
+            Some(ht) => ht,
+            None => continue,
+        };
+        let len = (tail - head + 1).max(0) as usize;
+        if len == 0 {
+            continue;
+        }
+
+        let mut popped = Vec::with_capacity(count.min(len));
+        for _ in 0..count.min(len) {
+            let v = if left { lpop(db, key)? } else { rpop(db, key)? };
+            popped.extend(v);
+        }
+        return Ok(Some((key.to_vec(), popped)));
+    }
+    Ok(None)
+}
 
 #[cfg(test)]
 mod tests {
@@ -279,15 +233,43 @@ mod tests {
     let db = make_db();
     
     // 基本操作
-    assert_eq!(lpush(&db, "mylist", "world").unwrap(), "1");
-    assert_eq!(lpush(&db, "mylist", "hello").unwrap(), "2");
-    assert_eq!(rpush(&db, "mylist", "!").unwrap(), "3");
+    assert_eq!(lpush(&db, b"mylist", b"world").unwrap(), "1");
+    assert_eq!(lpush(&db, b"mylist", b"hello").unwrap(), "2");
+    assert_eq!(rpush(&db, b"mylist", b"!").unwrap(), "3");
     
-    assert_eq!(lpop(&db, "mylist").unwrap(), "hello");
-    assert_eq!(rpop(&db, "mylist").unwrap(), "!");
+    assert_eq!(lpop(&db, b"mylist").unwrap().as_deref(), Some(&b"hello"[..]));
+    assert_eq!(rpop(&db, b"mylist").unwrap().as_deref(), Some(&b"!"[..]));
     
     // 范围查询
-    assert_eq!(lrange(&db, "mylist", 0, -1).unwrap(), "world");
+    assert_eq!(lrange(&db, b"mylist", 0, -1).unwrap(), vec![b"world".to_vec()]);
     
 }
+
+    #[test]
+    fn test_lmpop() {
+        let db = make_db();
+        let keys = ["empty", "l1", "l2"];
+
+        assert_eq!(lmpop(&db, &keys, true, 1).unwrap(), None);
+
+        rpush(&db, b"l1", b"a").unwrap();
+        rpush(&db, b"l1", b"b").unwrap();
+        rpush(&db, b"l1", b"c").unwrap();
+        rpush(&db, b"l2", b"x").unwrap();
+
+        // 跳过空列表，从第一个非空列表弹出
+        assert_eq!(
+            lmpop(&db, &keys, true, 2).unwrap(),
+            Some((b"l1".to_vec(), vec![b"a".to_vec(), b"b".to_vec()]))
+        );
+        // count 超过长度时只弹出现有元素
+        assert_eq!(
+            lmpop(&db, &keys, false, 5).unwrap(),
+            Some((b"l1".to_vec(), vec![b"c".to_vec()]))
+        );
+        assert_eq!(
+            lmpop(&db, &keys, false, 1).unwrap(),
+            Some((b"l2".to_vec(), vec![b"x".to_vec()]))
+        );
+    }
 }
\ No newline at end of file
@@ -1,4 +1,89 @@
 pub mod hash;
 pub mod list;
 pub mod set;
-pub mod string;
\ No newline at end of file
+pub mod string;
+
+use anyhow::Result;
+use crate::engine::kv::KvEngine;
+use crate::keys::{KeyBuf, Kind};
+
+/// key 在底层存储中的概况
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyStats {
+    /// 数据类型：string / hash / list / set
+    pub kind: &'static str,
+    /// 元素个数（string 为 1）
+    pub len: usize,
+    /// 所有记录的 value 字节数之和（hash 额外计入 field）
+    pub bytes: usize,
+}
+
+/// key 在任一类型的命名空间下有记录（不检查过期时间）
+pub fn exists<E: KvEngine>(db: &E, key: &[u8]) -> Result<bool> {
+    let mut buf = KeyBuf::new();
+    if db.get(buf.string(key))?.is_some() {
+        return Ok(true);
+    }
+    for kind in [Kind::Hash, Kind::ListMeta, Kind::Set] {
+        if db.scan_prefix(buf.prefix(kind, key)).next().is_some() {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// 依次探测各类型的命名空间，key 不存在时返回 None
+pub fn key_stats<E: KvEngine>(db: &E, key: &[u8]) -> Result<Option<KeyStats>> {
+    let mut buf = KeyBuf::new();
+    if let Some(v) = db.get(buf.string(key))? {
+        return Ok(Some(KeyStats { kind: "string", len: 1, bytes: v.len() }));
+    }
+
+    let probes = [("hash", Kind::Hash, true), ("list", Kind::ListData, false), ("set", Kind::Set, true)];
+    for (kind, namespace, count_suffix) in probes {
+        let prefix = buf.prefix(namespace, key);
+        let mut stats = KeyStats { kind, len: 0, bytes: 0 };
+        for item in db.scan_prefix(prefix) {
+            let (k, v) = item?;
+            stats.len += 1;
+            stats.bytes += v.len();
+            // hash 的 field、set 的 member 存在 key 的后缀里
+            if count_suffix {
+                stats.bytes += k.len() - prefix.len();
+            }
+        }
+        if stats.len > 0 {
+            return Ok(Some(stats));
+        }
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sled::Config;
+
+    fn make_db() -> sled::Db {
+        Config::new()
+            .temporary(true)
+            .open()
+            .expect("打开临时 sled db 失败")
+    }
+
+    #[test]
+    fn test_key_stats() -> Result<()> {
+        let db = make_db();
+        string::set(&db, b"s", b"hello")?;
+        hash::hset(&db, b"h", b"f1", b"v1")?;
+        hash::hset(&db, b"h", b"f2", b"v2")?;
+        list::rpush(&db, b"l", b"abc")?;
+
+        assert_eq!(key_stats(&db, b"s")?, Some(KeyStats { kind: "string", len: 1, bytes: 5 }));
+        assert_eq!(key_stats(&db, b"h")?, Some(KeyStats { kind: "hash", len: 2, bytes: 8 }));
+        assert_eq!(key_stats(&db, b"l")?.map(|s| (s.kind, s.len)), Some(("list", 1)));
+        assert_eq!(key_stats(&db, b"missing")?, None);
+        Ok(())
+    }
+}
@@ -3,8 +3,8 @@
 //! # Set Type Support
 //!
 //! This module implements Redis-like Set data structures on top of `sled`.
-//! Each set is stored as a separate `sled::Tree` named `"set:<key>"`.
-//! Members are stored as the tree’s keys with empty values `()`,
+//! Each member is stored as its own record, keyed by `keys::set_member(key, member)`,
+//! with an empty value,
 //! providing O(log n) insertion, removal, and membership checks.
 //!
 //! Supported commands:
@@ -14,9 +14,10 @@
 //! - `SISMEMBER`
 
 use anyhow::{Result,Context};
+use crate::engine::budget;
 use crate::engine::kv::KvEngine;
+use crate::keys::{self, Kind};
 
-const PREFIX: &str = "set:";
 
 /// Execute the SADD command:
 /// Add the specified `member` to the set stored at `key`.
@@ -36,14 +37,14 @@ const PREFIX: &str = "set:";
 ///
 /// Returns an error if opening the tree, inserting the member,
 /// or flushing the tree fails.
-pub fn sadd<E>(db: &E, key: &str, member: &str) -> Result<String> 
+pub fn sadd<E>(db: &E, key: &[u8], member: &[u8]) -> Result<String> 
 where 
     E: KvEngine,
 {
-    let namespaced = format!("{}{}:{}", PREFIX, key, member);
+    let namespaced = keys::set_member(key, member);
     let prev = db
-        .insert(namespaced.as_bytes(), &[])
-        .with_context(|| format!("ERR failed to SADD {}/{}", key, member))?;
+        .insert(&namespaced, &[])
+        .with_context(|| format!("ERR failed to SADD {}/{}", String::from_utf8_lossy(key), String::from_utf8_lossy(member)))?;
     Ok(if prev.is_none() { "1".into() } else { "0".into() })
 }
 
@@ -65,14 +66,14 @@ where
 ///
 /// Returns an error if opening the tree, removing the member,
 /// or flushing the tree fails.
-pub fn srem<E>(db: &E, key: &str, member: &str) -> Result<String> 
+pub fn srem<E>(db: &E, key: &[u8], member: &[u8]) -> Result<String> 
 where 
     E: KvEngine,
 {
-    let namespaced = format!("{}{}:{}", PREFIX, key, member);
+    let namespaced = keys::set_member(key, member);
     let prev = db
-        .remove(namespaced.as_bytes())
-        .with_context(|| format!("ERR failed to SREM {}/{}", key, member))?;
+        .remove(&namespaced)
+        .with_context(|| format!("ERR failed to SREM {}/{}", String::from_utf8_lossy(key), String::from_utf8_lossy(member)))?;
     Ok(if prev.is_some() { "1".into() } else { "0".into() })
 }
 
@@ -94,14 +95,14 @@ where
 /// # Errors
 ///
 /// Returns an error if opening the tree or checking for the key fails.
-pub fn sismember<E>(db: &E, key: &str, member: &str) -> Result<String> 
+pub fn sismember<E>(db: &E, key: &[u8], member: &[u8]) -> Result<String> 
 where 
     E:KvEngine
 {
-    let namespaced = format!("{}{}:{}", PREFIX,key,member);
+    let namespaced = keys::set_member(key, member);
     let exist = db
-        .get(namespaced.as_bytes())
-        .with_context(|| format!("ERR failed to SISMEMBER {}/{}", key, member))?
+        .get(&namespaced)
+        .with_context(|| format!("ERR failed to SISMEMBER {}/{}", String::from_utf8_lossy(key), String::from_utf8_lossy(member)))?
         .is_some();
     Ok(if exist { "1".into() } else { "0".into() })
 }
@@ -116,24 +117,24 @@ where
 ///
 /// # Returns
 ///
-/// A comma-separated `String` of all members in the set.
-/// Returns an empty string if the set does not exist or has no members.
+/// All members of the set.
+/// Returns an empty `Vec` if the set does not exist or has no members.
 ///
 /// # Errors
 ///
-/// Returns an error if opening the tree, iterating entries,
-/// or converting bytes to UTF-8 strings fails.
-pub fn smembers<E>(db: &E, key: &str) -> Result<String> 
+/// Returns an error if opening the tree or iterating entries fails.
+pub fn smembers<E>(db: &E, key: &[u8]) -> Result<Vec<Vec<u8>>> 
 where 
     E:KvEngine
 {
-    let prefix = format!("{}{}:",PREFIX,key);
+    let prefix = keys::prefix(Kind::Set, key);
     let mut members = Vec::new();
-    for item in db.scan_prefix(prefix.as_bytes()) {
+    for item in db.scan_prefix(&prefix) {
+        budget::check()?;
         let (k, _) = item?;
-        members.push(std::str::from_utf8(&k[prefix.len()..])?.to_string());
+        members.push(k[prefix.len()..].to_vec());
     }
-    Ok(members.join(","))
+    Ok(members)
 }
 
 #[cfg(test)]
@@ -157,27 +158,26 @@ mod tests {
         let db = make_db();
 
         // SADD: add members "a" and "b"
-        assert_eq!(sadd(&db, "S", "a")?, "1");
-        assert_eq!(sadd(&db, "S", "a")?, "0"); // already exists
-        assert_eq!(sadd(&db, "S", "b")?, "1");
+        assert_eq!(sadd(&db, b"S", b"a")?, "1");
+        assert_eq!(sadd(&db, b"S", b"a")?, "0"); // already exists
+        assert_eq!(sadd(&db, b"S", b"b")?, "1");
 
         // SISMEMBER: check membership
-        assert_eq!(sismember(&db, "S", "a")?, "1");
-        assert_eq!(sismember(&db, "S", "x")?, "0");
+        assert_eq!(sismember(&db, b"S", b"a")?, "1");
+        assert_eq!(sismember(&db, b"S", b"x")?, "0");
 
         // SMEMBERS: list all members
-        let members = smembers(&db, "S")?;
-        let mut ms: Vec<&str> = members.split(',').collect();
+        let mut ms = smembers(&db, b"S")?;
         ms.sort();
-        assert_eq!(ms, vec!["a", "b"]);
+        assert_eq!(ms, vec![b"a".to_vec(), b"b".to_vec()]);
 
         // SREM: remove member "a"
-        assert_eq!(srem(&db, "S", "a")?, "1");
-        assert_eq!(srem(&db, "S", "a")?, "0"); // already removed
+        assert_eq!(srem(&db, b"S", b"a")?, "1");
+        assert_eq!(srem(&db, b"S", b"a")?, "0"); // already removed
 
         // After removal, only "b" remains
-        let remaining = smembers(&db, "S")?;
-        assert_eq!(remaining, "b");
+        let remaining = smembers(&db, b"S")?;
+        assert_eq!(remaining, vec![b"b".to_vec()]);
 
         Ok(())
     }
@@ -4,15 +4,15 @@
 //!
 //! 本模块直接基于 sled 提供 SET/GET/DEL 三种语义：
 //! - SET key value → "OK" 表示写入成功
-//! - GET key → 返回 value 或者 "ERR key not found"
-//! - DEL key → "OK"（删除成功）或 "ERR key not found"
+//! - GET key → 返回 value，键不存在时为 `None`（回复 nil）
+//! - DEL key → 键是否存在（回复删除的个数 1 或 0）
 
 use sled::transaction::ConflictableTransactionError;
 use anyhow::{Result, Context, anyhow};
 use std::str;
 use crate::engine::kv::KvEngine;
+use crate::keys;
 
-const PREFIX: &str = "string:";
 
 /// 将一个字符串写入指定的键，已有值会被覆盖。
 ///
@@ -25,158 +25,141 @@ const PREFIX: &str = "string:";
 ///
 /// # 错误
 /// - 底层 sled 插入失败时，返回带上下文的错误
-pub fn set<E>(db: &E, key: &str, value: &str) -> Result<String> 
+pub fn set<E>(db: &E, key: &[u8], value: &[u8]) -> Result<String> 
 where 
     E:KvEngine,
 {
-    let namespaced = format!("{}{}", PREFIX, key);
-    db.insert(namespaced.as_bytes(), value.as_bytes())
-        .with_context(|| format!("ERR failed to SET key '{}'", key))?;
+    let namespaced = keys::string(key);
+    db.insert(&namespaced, value)
+        .with_context(|| format!("ERR failed to SET key '{}'", String::from_utf8_lossy(key)))?;
     Ok("OK".to_string())
 }
 
 /// 从指定键读取一个字符串。
 ///
 /// # 返回
-/// - Ok(value)           – 键存在且值为合法 UTF-8 字符串  
-/// - Ok("ERR key not found") – 键不存在  
+/// - Ok(Some(value)) – 键存在，value 为原样存储的字节  
+/// - Ok(None)        – 键不存在  
 ///
 /// # 错误
-/// - sled 读取失败  
-/// - 存储的字节不是合法 UTF-8 时，带上下文的错误
-pub fn get<E>(db: &E, key: &str) -> Result<String> 
+/// - sled 读取失败时，带上下文的错误
+pub fn get<E>(db: &E, key: &[u8]) -> Result<Option<Vec<u8>>> 
 where 
     E:KvEngine,
 {
-    let namespaced = format!("{}{}", PREFIX, key);
+    let namespaced = keys::string(key);
     let maybe = db
-        .get(namespaced.as_bytes())
-        .with_context(|| format!("ERR failed to GET key '{}'", key))?;
-    if let Some(ivec) = maybe {
-        let s = str::from_utf8(&ivec)
-            .with_context(|| format!("ERR non-utf8 data for key '{}'", key))?;
-        Ok(s.to_string())
-    } else {
-        Ok("ERR key not found".to_string())
-    }
+        .get(&namespaced)
+        .with_context(|| format!("ERR failed to GET key '{}'", String::from_utf8_lossy(key)))?;
+    Ok(maybe.map(|ivec| ivec.to_vec()))
 }
 
 /// 删除指定键。
 ///
 /// # 返回
-/// - Ok("OK")               – 键存在且删除成功  
-/// - Ok("ERR key not found") – 键不存在  
+/// - Ok(true)  – 键存在且删除成功  
+/// - Ok(false) – 键不存在  
 ///
 /// # 错误
 /// - sled 删除操作失败时，带上下文的错误
-pub fn del<E>(db: &E, key: &str) -> Result<String> 
+pub fn del<E>(db: &E, key: &[u8]) -> Result<bool> 
 where 
     E:KvEngine,
 {
-    let namespaced = format!("{}{}", PREFIX, key);
+    let namespaced = keys::string(key);
     let existed = db
-        .remove(namespaced.as_bytes())
-        .with_context(|| format!("ERR failed to DEL key '{}'", key))?
+        .remove(&namespaced)
+        .with_context(|| format!("ERR failed to DEL key '{}'", String::from_utf8_lossy(key)))?
         .is_some();
-    if existed {
-        Ok("OK".to_string())
-    } else {
-        Ok("ERR key not found".to_string())
+    Ok(existed)
+}
+
+/// GETSET：原子地写入新值并返回旧值，键不存在时旧值为 `None`。
+///
+/// 与 INCR 相同，sled 上在单个事务中完成读和写，并发的 GETSET 不会拿到同一个旧值；
+/// 事务上下文中直接读写，由外层事务保证原子。
+pub fn getset<E>(db: &E, key: &[u8], value: &[u8]) -> Result<Option<Vec<u8>>>
+where
+    E: KvEngine,
+{
+    let full_key = keys::string(key);
+    if db.sled_trees().is_some() {
+        return db
+            .transaction(|tx| swap(tx, &full_key, value).map_err(ConflictableTransactionError::Abort))
+            .map_err(|e| anyhow!("{}", e));
     }
+    swap(db, &full_key, value)
+}
+
+/// GETSET 的读改写
+fn swap<E: KvEngine>(tx: &E, full_key: &[u8], value: &[u8]) -> Result<Option<Vec<u8>>> {
+    let old = tx.get(full_key)?.map(|iv| iv.to_vec());
+    tx.insert(full_key, value)?;
+    Ok(old)
 }
 
 /// 原子地 +1：
-/// - 如果底层是 &Db，就用 sled::transaction 保证本条命令的原子性  
-/// - 如果是事务上下文 &TransactionalTree，就直接用 `db.get` / `db.insert`，
+/// - 如果底层是 sled，就在字符串所在的 Tree 上用 sled::transaction 保证本条命令的原子性  
+/// - 如果是事务上下文（或内存引擎），就直接用 `db.get` / `db.insert`，
 ///   由外层事务一并保证原子
-pub fn incr<E>(db: &E, key: &str) -> Result<String>
+pub fn incr<E>(db: &E, key: &[u8]) -> Result<String>
 where
     E: KvEngine,
 {
-    let full_key = format!("{}{}", PREFIX, key);
-    // 1) 如果能拆出 &Db，那就在这个 &Db 上开事务
-    if let Some(plain) = db.as_db() {
-        let tree = plain.open_tree("")?;
-        let new = tree.transaction(|tx| {
-            // 获取原始字节值
-            let bytes = tx.get(full_key.as_bytes())?;
-
-            // 转换并解析为 i64
-            let old = if let Some(iv) = bytes {
-                // 1. 转换为字符串
-                let s = String::from_utf8(iv.to_vec())
-                    .map_err(|_| ConflictableTransactionError::Abort("ERR value is not a valid UTF-8 string"))?;
-                
-                // 2. 解析为整数
-                s.parse::<i64>()
-                    .map_err(|_| ConflictableTransactionError::Abort("ERR value is not an integer"))?
-            } else {
-                0 // 键不存在时默认为 0
-            };
-            
-            // 检查溢出
-            let new = old.checked_add(1)
-                .ok_or(ConflictableTransactionError::Abort("ERR increment would overflow"))?;
-            
-            // 写入新值
-            tx.insert(full_key.as_bytes(), new.to_string().as_bytes())?;
-            Ok(new)
-        }).map_err(|e| anyhow!("{}", e))?;
-        
+    let full_key = keys::string(key);
+    // 1) 如果底层是 sled，就经由引擎开事务（写入照常压缩、计入键空间用量）
+    if db.sled_trees().is_some() {
+        let new = db
+            .transaction(|tx| add_checked(tx, &full_key, 1, "ERR increment would overflow").map_err(ConflictableTransactionError::Abort))
+            .map_err(|e| anyhow!("{}", e))?;
         return Ok(new.to_string());
     }
 
     // 2) 否则我们在事务上下文里：直接用 KvEngine 的 get/insert，外层事务保证原子
-    let old = db.get(full_key.as_bytes())?
-        .map(|iv| String::from_utf8(iv.to_vec()).ok())
-        .flatten()
+    let old = db.get(&full_key)?
+        .and_then(|iv| String::from_utf8(iv.to_vec()).ok())
         .and_then(|s| s.parse::<i64>().ok())
         .unwrap_or(0);
     let new = old.checked_add(1)
         .ok_or_else(|| anyhow!("overflow"))?;
-    db.insert(full_key.as_bytes(), new.to_string().as_bytes())
+    db.insert(&full_key, new.to_string().as_bytes())
         .context("ERR failed to INCR")?;
     Ok(new.to_string())
 }
 
+/// INCR / DECR 在事务中的读改写：已有值必须是整数，结果溢出时返回 `overflow`
+fn add_checked<E: KvEngine>(tx: &E, full_key: &[u8], delta: i64, overflow: &str) -> Result<i64> {
+    let old = match tx.get(full_key)? {
+        Some(iv) => str::from_utf8(&iv)
+            .map_err(|_| anyhow!("ERR value is not a valid UTF-8 string"))?
+            .parse::<i64>()
+            .map_err(|_| anyhow!("ERR value is not an integer"))?,
+        None => 0, // 键不存在时默认为 0
+    };
+    let new = old.checked_add(delta).ok_or_else(|| anyhow!("{}", overflow))?;
+    tx.insert(full_key, new.to_string().as_bytes())?;
+    Ok(new)
+}
+
 /// 同理实现 DECR
-pub fn decr<E>(db: &E, key: &str) -> Result<String>
+pub fn decr<E>(db: &E, key: &[u8]) -> Result<String>
 where
     E: KvEngine,
 {
-    let full_key = format!("{}{}", PREFIX, key);
-    if let Some(plain) = db.as_db() {
-        let tree = plain.open_tree("")?;
-        let new = tree.transaction(|tx| {
-            let bytes = tx.get(full_key.as_bytes())?;
-            
-            let old = if let Some(iv) = bytes {
-                let s = String::from_utf8(iv.to_vec())
-                    .map_err(|_| ConflictableTransactionError::Abort("ERR value is not a valid UTF-8 string"))?;
-                
-                s.parse::<i64>()
-                    .map_err(|_| ConflictableTransactionError::Abort("ERR value is not an integer"))?
-            } else {
-                0
-            };
-            
-            let new = old.checked_sub(1)
-                .ok_or(ConflictableTransactionError::Abort("ERR decrement would underflow"))?;
-            
-            tx.insert(full_key.as_bytes(), new.to_string().as_bytes())?;
-            Ok(new)
-        }).map_err(|e| anyhow!("{}", e))?;
-        
+    let full_key = keys::string(key);
+    if db.sled_trees().is_some() {
+        let new = db
+            .transaction(|tx| add_checked(tx, &full_key, -1, "ERR decrement would underflow").map_err(ConflictableTransactionError::Abort))
+            .map_err(|e| anyhow!("{}", e))?;
         return Ok(new.to_string());
     }
-    let old = db.get(full_key.as_bytes())?
-        .map(|iv| String::from_utf8(iv.to_vec()).ok())
-        .flatten()
+    let old = db.get(&full_key)?
+        .and_then(|iv| String::from_utf8(iv.to_vec()).ok())
         .and_then(|s| s.parse::<i64>().ok())
         .unwrap_or(0);
     let new = old.checked_sub(1)
         .ok_or_else(|| anyhow!("underflow"))?;
-    db.insert(full_key.as_bytes(), new.to_string().as_bytes())
+    db.insert(&full_key, new.to_string().as_bytes())
         .context("ERR failed to DECR")?;
     Ok(new.to_string())
 }
@@ -201,19 +184,19 @@ mod tests {
         let db = make_db();
 
         // 1) set & get
-        assert_eq!(set(&db, "foo", "bar")?, "OK");
-        assert_eq!(get(&db, "foo")?, "bar");
+        assert_eq!(set(&db, b"foo", b"bar")?, "OK");
+        assert_eq!(get(&db, b"foo")?.as_deref(), Some(&b"bar"[..]));
 
         // 2) overwrite
-        assert_eq!(set(&db, "foo", "baz")?, "OK");
-        assert_eq!(get(&db, "foo")?, "baz");
+        assert_eq!(set(&db, b"foo", b"baz")?, "OK");
+        assert_eq!(get(&db, b"foo")?.as_deref(), Some(&b"baz"[..]));
 
         // 3) del existing
-        assert_eq!(del(&db, "foo")?, "OK");
-        assert_eq!(get(&db, "foo")?, "ERR key not found");
+        assert!(del(&db, b"foo")?);
+        assert_eq!(get(&db, b"foo")?, None);
 
         // 4) del again → not found
-        assert_eq!(del(&db, "foo")?, "ERR key not found");
+        assert!(!del(&db, b"foo")?);
 
         Ok(())
     }
@@ -221,14 +204,34 @@ mod tests {
         #[test]
     fn test_get_nonexistent() -> Result<()> {
         let db = make_db();
-        assert_eq!(get(&db, "does_not_exist")?, "ERR key not found");
+        assert_eq!(get(&db, b"does_not_exist")?, None);
         Ok(())
     }
 
     #[test]
     fn test_del_nonexistent() -> Result<()> {
         let db = make_db();
-        assert_eq!(del(&db, "does_not_exist")?, "ERR key not found");
+        assert!(!del(&db, b"does_not_exist")?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_getset() -> Result<()> {
+        let db = make_db();
+        assert_eq!(getset(&db, b"job", b"1")?, None);
+        assert_eq!(getset(&db, b"job", b"2")?, Some(b"1".to_vec()));
+        assert_eq!(get(&db, b"job")?.as_deref(), Some(&b"2"[..]));
+        Ok(())
+    }
+
+    /// key 与 value 按原样的字节存取，不要求是 UTF-8
+    #[test]
+    fn test_binary_safe() -> Result<()> {
+        let db = make_db();
+        set(&db, b"\xff\x00k", b"\x80\x81")?;
+        assert_eq!(get(&db, b"\xff\x00k")?, Some(b"\x80\x81".to_vec()));
+        assert_eq!(getset(&db, b"\xff\x00k", b"\xc3")?, Some(b"\x80\x81".to_vec()));
+        assert_eq!(incr(&db, b"\xff\x00k").unwrap_err().to_string(), "ERR value is not a valid UTF-8 string");
         Ok(())
     }
 
@@ -237,18 +240,18 @@ mod tests {
         let db = make_db();
 
         // incr 1 → 1
-        assert_eq!(incr(&db, "counter")?, "1");
+        assert_eq!(incr(&db, b"counter")?, "1");
         // incr again → 2
-        assert_eq!(incr(&db, "counter")?, "2");
+        assert_eq!(incr(&db, b"counter")?, "2");
         // decr → 1
-        assert_eq!(decr(&db, "counter")?, "1");
+        assert_eq!(decr(&db, b"counter")?, "1");
         // decr → 0
-        assert_eq!(decr(&db, "counter")?, "0");
+        assert_eq!(decr(&db, b"counter")?, "0");
         // decr → -1
-        assert_eq!(decr(&db, "counter")?, "-1");
+        assert_eq!(decr(&db, b"counter")?, "-1");
 
         // 再 incr 回到 0
-        assert_eq!(incr(&db, "counter")?, "0");
+        assert_eq!(incr(&db, b"counter")?, "0");
 
         Ok(())
     }
@@ -256,13 +259,13 @@ mod tests {
 #[test]
 fn test_incr_overflow() {
     let db = make_db();
-    let key = "overflow";
-    let full_key = format!("{}{}",PREFIX,key);
+    let key = b"overflow";
+    let full_key = keys::string(key);
     let max = i64::MAX.to_string();
     
     // 写入后立即读取验证
-    set(&db, &key, &max).unwrap();
-    let value = db.get(full_key.as_bytes()).unwrap();
+    set(&db, key, max.as_bytes()).unwrap();
+    let value = db.get(&full_key).unwrap();
     if let Some(iv) = value {
         let s = String::from_utf8_lossy(&iv);
         println!("Value after set: {}", s);
@@ -270,8 +273,8 @@ fn test_incr_overflow() {
         println!("Value not found after set!");
     }
     
-    let result = incr(&db, &key);
-    let value = db.get(full_key.as_bytes()).unwrap();
+    let result = incr(&db, key);
+    let value = db.get(&full_key).unwrap();
     if let Some(iv) = value {
         let s = String::from_utf8_lossy(&iv);
         println!("Value after incr: {}", s);
@@ -291,14 +294,14 @@ fn test_incr_overflow() {
     #[test]
     fn test_decr_underflow() {
         let db = make_db();
-        let key = "underflow";
-        let full_key = format!("{}{}",PREFIX,key);
+        let key = b"underflow";
+        let full_key = keys::string(key);
         let min = i64::MIN.to_string();
 
-        set(&db, &key, &min).unwrap();
+        set(&db, key, min.as_bytes()).unwrap();
 
-        let result = decr(&db, &key);
-        let value = db.get(full_key.as_bytes()).unwrap();
+        let result = decr(&db, key);
+        let value = db.get(&full_key).unwrap();
         if let Some(iv) = value {
             let s = String::from_utf8_lossy(&iv);
             print!("Value after decr:{}", s)
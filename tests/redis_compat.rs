@@ -0,0 +1,657 @@
+// tests/redis_compat.rs
+
+//! 用 `redis` crate 驱动真实的服务端，检查与常用客户端的协议兼容性
+//!
+//! 每个用例在临时端口上启动一个纯内存的服务端，结束时关闭。
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+
+use crab_cage::config::{Config, OutputBufferLimits, OutputLimit};
+use crab_cage::engine::blocking::ShardedExecutor;
+use crab_cage::hooks::{CommandHook, HookContext};
+use crab_cage::protocol::Frame;
+use crab_cage::{command, CommandError, ServerBuilder, ServerHandle};
+use redis::{Commands, Connection, RedisResult};
+use tokio::runtime::Runtime;
+
+/// 测试用服务端：自带运行时，测试本身用同步的 redis 客户端
+struct TestServer {
+    runtime: Runtime,
+    handle: Option<ServerHandle>,
+    client: redis::Client,
+    _dir: Option<tempfile::TempDir>,
+}
+
+impl TestServer {
+    fn start() -> Self {
+        Self::start_with(Config::default())
+    }
+
+    fn start_with(cfg: Config) -> Self {
+        Self::start_builder(ServerBuilder::new().config(cfg))
+    }
+
+    /// 在给定构建器（可带钩子等非配置项）上启动
+    fn start_builder(builder: ServerBuilder) -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        let mut server = Self::start_at(builder.in_memory(), dir.path());
+        server._dir = Some(dir);
+        server
+    }
+
+    /// 在给定的数据目录上启动，存储与持久化按构建器的配置，用于重启后检查持久化的数据
+    fn start_at(builder: ServerBuilder, dir: &std::path::Path) -> Self {
+        let runtime = tokio::runtime::Builder::new_multi_thread().enable_all().build().unwrap();
+        let builder = builder.listen("127.0.0.1:0").dir(dir).metrics(None);
+        let handle = runtime.block_on(builder.start()).unwrap();
+        let client = redis::Client::open(format!("redis://{}/", handle.local_addr())).unwrap();
+        TestServer { runtime, handle: Some(handle), client, _dir: None }
+    }
+
+    fn connect(&self) -> Connection {
+        self.client.get_connection().unwrap()
+    }
+}
+
+/// 等待后台清理等异步发生的条件成立，最多等 5 秒
+fn wait_until(cond: impl Fn() -> bool) -> bool {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !cond() {
+        if Instant::now() > deadline {
+            return false;
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    true
+}
+
+/// 以 RESP 数组编码一条命令，用于不经过 redis crate 的原始连接
+fn resp_command(args: &[&str]) -> Vec<u8> {
+    let mut out = format!("*{}\r\n", args.len()).into_bytes();
+    for arg in args {
+        out.extend_from_slice(format!("${}\r\n{}\r\n", arg.len(), arg).as_bytes());
+    }
+    out
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            self.runtime.block_on(handle.shutdown());
+        }
+    }
+}
+
+#[test]
+fn test_strings() -> RedisResult<()> {
+    let server = TestServer::start();
+    let mut con = server.connect();
+
+    let _: () = con.set("greeting", "hello")?;
+    let value: String = con.get("greeting")?;
+    assert_eq!(value, "hello");
+
+    // `Commands::incr` 发送的是 INCRBY，这里直接用 INCR / DECR
+    assert_eq!(redis::cmd("INCR").arg("counter").query::<i64>(&mut con)?, 1);
+    assert_eq!(redis::cmd("INCR").arg("counter").query::<i64>(&mut con)?, 2);
+    assert_eq!(redis::cmd("DECR").arg("counter").query::<i64>(&mut con)?, 1);
+
+    assert_eq!(con.getset::<_, _, String>("greeting", "bye")?, "hello");
+    assert_eq!(con.getset::<_, _, Option<String>>("fresh", "1")?, None);
+
+    assert_eq!(con.del::<_, i64>("greeting")?, 1);
+    assert_eq!(con.del::<_, i64>("greeting")?, 0);
+    assert_eq!(con.get::<_, Option<String>>("greeting")?, None);
+    assert_eq!(redis::cmd("PING").query::<String>(&mut con)?, "PONG");
+    Ok(())
+}
+
+#[test]
+fn test_info_server() -> RedisResult<()> {
+    let server = TestServer::start();
+    let mut con = server.connect();
+
+    let info: String = redis::cmd("INFO").arg("server").query(&mut con)?;
+    let field = |name: &str| -> String {
+        info.lines().find_map(|line| line.strip_prefix(name)?.strip_prefix(':')).unwrap().to_string()
+    };
+    let port = server.handle.as_ref().unwrap().local_addr().port();
+    assert_eq!(field("tcp_port"), port.to_string());
+    let run_id = field("run_id");
+    assert_eq!(run_id.len(), 40);
+    assert!(run_id.bytes().all(|b| b.is_ascii_hexdigit()));
+    assert!(field("uptime_in_seconds").parse::<u64>().is_ok());
+
+    // 同一进程中的 run_id 不变
+    let again: String = redis::cmd("INFO").arg("server").query(&mut con)?;
+    assert!(again.contains(&format!("run_id:{}", run_id)));
+    Ok(())
+}
+
+#[test]
+fn test_hashes_lists_and_sets() -> RedisResult<()> {
+    let server = TestServer::start();
+    let mut con = server.connect();
+
+    let _: () = con.hset("user:1", "name", "crab")?;
+    let _: () = con.hset("user:1", "legs", "10")?;
+    assert_eq!(con.hget::<_, _, String>("user:1", "name")?, "crab");
+    let all: HashMap<String, String> = con.hgetall("user:1")?;
+    assert_eq!(all, HashMap::from([("name".into(), "crab".into()), ("legs".into(), "10".into())]));
+
+    let _: () = con.rpush("queue", "a")?;
+    let _: () = con.rpush("queue", "b")?;
+    let _: () = con.lpush("queue", "z")?;
+    assert_eq!(con.lrange::<_, Vec<String>>("queue", 0, -1)?, ["z", "a", "b"]);
+    assert_eq!(con.lpop::<_, String>("queue", None)?, "z");
+    assert_eq!(con.hget::<_, _, Option<String>>("user:1", "missing")?, None);
+    assert_eq!(con.rpop::<_, Option<String>>("empty", None)?, None);
+
+    let _: () = con.sadd("tags", "rust")?;
+    let _: () = con.sadd("tags", "kv")?;
+    let mut tags: Vec<String> = con.smembers("tags")?;
+    tags.sort();
+    assert_eq!(tags, ["kv", "rust"]);
+    assert!(con.sismember::<_, _, bool>("tags", "rust")?);
+    Ok(())
+}
+
+// key 与 value 按原样的字节保存，非 UTF-8、空格与换行都不受影响
+#[test]
+fn test_binary_safe_arguments() -> RedisResult<()> {
+    let server = TestServer::start();
+    let mut con = server.connect();
+
+    let key: &[u8] = b"bin:\xff\x00";
+    let value: &[u8] = b"\xfe\xed \r\n\x00end";
+    let _: () = con.set(key, value)?;
+    assert_eq!(con.get::<_, Vec<u8>>(key)?, value);
+
+    let _: () = con.hset("h", b"\xc3\x28", value)?;
+    assert_eq!(con.hget::<_, _, Vec<u8>>("h", b"\xc3\x28")?, value);
+    let _: () = con.rpush(b"\x80list", value)?;
+    assert_eq!(con.lrange::<_, Vec<Vec<u8>>>(b"\x80list", 0, -1)?, [value]);
+    let _: () = con.sadd("s", b"\xff")?;
+    assert!(con.sismember::<_, _, bool>("s", b"\xff")?);
+    Ok(())
+}
+
+#[test]
+fn test_expire() -> RedisResult<()> {
+    let server = TestServer::start();
+    let mut con = server.connect();
+
+    let _: () = con.set("session", "token")?;
+    assert_eq!(con.ttl::<_, i64>("session")?, -1);
+    assert!(con.expire::<_, bool>("session", 100)?);
+    let ttl: i64 = con.ttl("session")?;
+    assert!((1..=100).contains(&ttl));
+    Ok(())
+}
+
+#[test]
+fn test_pipeline() -> RedisResult<()> {
+    let server = TestServer::start();
+    let mut con = server.connect();
+
+    let (a, b, n): (String, String, i64) = redis::pipe()
+        .set("a", "1")
+        .ignore()
+        .set("b", "2")
+        .ignore()
+        .get("a")
+        .get("b")
+        .cmd("INCR")
+        .arg("n")
+        .query(&mut con)?;
+    assert_eq!((a.as_str(), b.as_str(), n), ("1", "2", 1));
+    Ok(())
+}
+
+#[test]
+fn test_transactions() -> RedisResult<()> {
+    let server = TestServer::start();
+    let mut con = server.connect();
+
+    // atomic() 以 MULTI / EXEC 包裹整个 pipeline
+    let (n, value): (i64, String) =
+        redis::pipe().atomic().cmd("INCR").arg("n").set("k", "v").ignore().get("k").query(&mut con)?;
+    assert_eq!((n, value.as_str()), (1, "v"));
+
+    // WATCH 的 key 被其他连接修改后 EXEC 返回 nil，redis::transaction 会自动重试
+    let mut other = server.connect();
+    let mut attempts = 0;
+    let n: i64 = redis::transaction(&mut con, &["n"], |con, pipe| {
+        attempts += 1;
+        if attempts == 1 {
+            let _: () = other.set("n", 10)?;
+        }
+        let current: i64 = redis::cmd("GET").arg("n").query(con)?;
+        pipe.set("n", current + 1).ignore().get("n").query::<Option<(i64,)>>(con).map(|r| r.map(|(n,)| n))
+    })?;
+    assert_eq!(attempts, 2);
+    assert_eq!(n, 11);
+    Ok(())
+}
+
+#[test]
+fn test_pubsub() -> RedisResult<()> {
+    let server = TestServer::start();
+    let mut subscriber = server.connect();
+    let mut publisher = server.connect();
+
+    let mut pubsub = subscriber.as_pubsub();
+    pubsub.subscribe("news")?;
+    pubsub.set_read_timeout(Some(Duration::from_secs(5)))?;
+
+    assert_eq!(publisher.publish::<_, _, i64>("news", "hello")?, 1);
+    let message = pubsub.get_message()?;
+    assert_eq!(message.get_channel_name(), "news");
+    assert_eq!(message.get_payload::<String>()?, "hello");
+    Ok(())
+}
+
+#[test]
+fn test_output_buffer_limits() -> RedisResult<()> {
+    let limits = OutputBufferLimits {
+        normal: OutputLimit::new(64 * 1024, 0, 0),
+        pubsub: OutputLimit::new(1 << 20, 0, 0),
+        ..OutputBufferLimits::default()
+    };
+    let server = TestServer::start_with(Config { client_output_buffer_limit: limits, ..Config::default() });
+    let mut con = server.connect();
+
+    // 回复超过普通客户端的硬上限：连接被断开
+    let _: () = con.set("big", "x".repeat(100 * 1024))?;
+    assert!(con.get::<_, String>("big").is_err());
+
+    // 订阅者不读取，排队的消息超过上限后被断开，之后的消息不再投递给它
+    let mut subscriber = server.connect();
+    let mut pubsub = subscriber.as_pubsub();
+    pubsub.subscribe("flood")?;
+    let mut publisher = server.connect();
+    let message = "m".repeat(16 * 1024);
+    let dropped = (0..4096).any(|_| publisher.publish::<_, _, i64>("flood", &message).unwrap() == 0);
+    assert!(dropped, "subscriber was never disconnected");
+    Ok(())
+}
+
+#[test]
+fn test_pubsub_backlog_stats() -> RedisResult<()> {
+    let server = TestServer::start_with(Config { pubsub_backlog_limit: 16, ..Config::default() });
+    let mut subscriber = server.connect();
+    let mut pubsub = subscriber.as_pubsub();
+    pubsub.subscribe("flood")?;
+
+    // 订阅者不读取，积压超过 16 条后新消息被丢弃，连接保持
+    let mut publisher = server.connect();
+    let message = "m".repeat(16 * 1024);
+    let dropped = (0..4096).any(|_| publisher.publish::<_, _, i64>("flood", &message).unwrap() == 0);
+    assert!(dropped, "no message was dropped");
+
+    let info: String = redis::cmd("INFO").arg("pubsub").query(&mut publisher)?;
+    let field = |name: &str| -> u64 {
+        info.lines().find_map(|line| line.strip_prefix(name)?.strip_prefix(':')?.parse().ok()).unwrap()
+    };
+    assert_eq!(field("pubsub_channels"), 1);
+    assert!(field("pubsub_messages_dropped") >= 1);
+    assert!(info.contains("channelstat_flood:subscribers=1,"));
+    Ok(())
+}
+
+#[test]
+fn test_read_only_mode() -> RedisResult<()> {
+    let server = TestServer::start_with(Config { read_only: true, ..Config::default() });
+    let mut con = server.connect();
+
+    let err = con.set::<_, _, ()>("k", "v").unwrap_err();
+    assert_eq!(err.code(), Some("READONLY"));
+    assert_eq!(con.get::<_, Option<String>>("k")?, None);
+
+    // 运行时关闭只读模式
+    let _: () = redis::cmd("CONFIG").arg("SET").arg("read-only").arg("no").query(&mut con)?;
+    let _: () = con.set("k", "v")?;
+
+    // 事务入队后开启只读模式：EXEC 被拒绝，事务放弃
+    let mut admin = server.connect();
+    let _: () = redis::cmd("MULTI").query(&mut con)?;
+    let _: () = redis::cmd("SET").arg("k").arg("changed").query(&mut con)?;
+    let _: () = redis::cmd("CONFIG").arg("SET").arg("read-only").arg("yes").query(&mut admin)?;
+    let err = redis::cmd("EXEC").query::<()>(&mut con).unwrap_err();
+    assert_eq!(err.code(), Some("READONLY"));
+    assert_eq!(con.get::<_, String>("k")?, "v");
+    Ok(())
+}
+
+#[test]
+fn test_idle_clients_are_closed() -> RedisResult<()> {
+    let server = TestServer::start_with(Config { timeout: 1, ..Config::default() });
+    let mut idle = server.connect();
+    let mut subscriber = server.connect();
+    let mut pubsub = subscriber.as_pubsub();
+    pubsub.subscribe("news")?;
+    assert_eq!(redis::cmd("PING").query::<String>(&mut idle)?, "PONG");
+
+    std::thread::sleep(Duration::from_millis(2500));
+    assert!(redis::cmd("PING").query::<String>(&mut idle).is_err());
+    // 订阅中的连接不算空闲
+    let mut publisher = server.connect();
+    assert_eq!(publisher.publish::<_, _, i64>("news", "still here")?, 1);
+    pubsub.set_read_timeout(Some(Duration::from_secs(5)))?;
+    assert_eq!(pubsub.get_message()?.get_payload::<String>()?, "still here");
+    Ok(())
+}
+
+#[test]
+fn test_blocking_pop() -> RedisResult<()> {
+    let server = TestServer::start();
+    let mut con = server.connect();
+
+    // 有数据时立即返回，所有列表都为空时等到超时回复 nil
+    con.rpush::<_, _, i64>("jobs", "first")?;
+    let popped: Option<(String, String)> = redis::cmd("BLPOP").arg("none").arg("jobs").arg(0).query(&mut con)?;
+    assert_eq!(popped, Some(("jobs".to_string(), "first".to_string())));
+    let start = Instant::now();
+    let popped: Option<(String, String)> = redis::cmd("BRPOP").arg("jobs").arg(0.2).query(&mut con)?;
+    assert_eq!(popped, None);
+    assert!(start.elapsed() >= Duration::from_millis(200));
+
+    // 两个客户端先后阻塞在同一个 key 上，按阻塞的先后顺序各取到一个元素
+    let waiters: Vec<_> = (0..2)
+        .map(|i| {
+            let mut con = server.connect();
+            let handle = std::thread::spawn(move || -> RedisResult<(String, String)> {
+                redis::cmd("BLPOP").arg("jobs").arg("other").arg(5).query(&mut con)
+            });
+            std::thread::sleep(Duration::from_millis(200 + i * 100));
+            handle
+        })
+        .collect();
+    con.rpush::<_, _, i64>("jobs", "a")?;
+    std::thread::sleep(Duration::from_millis(100));
+    con.rpush::<_, _, i64>("jobs", "b")?;
+    let popped: Vec<_> = waiters.into_iter().map(|h| h.join().unwrap()).collect::<RedisResult<_>>()?;
+    assert_eq!(popped, vec![("jobs".to_string(), "a".to_string()), ("jobs".to_string(), "b".to_string())]);
+    assert!(con.lrange::<_, Vec<String>>("jobs", 0, -1)?.is_empty());
+
+    // BLMPOP 的超时在 numkeys 之前，被另一个连接的写入唤醒后一次取走最多 COUNT 个元素
+    let mut blocked = server.connect();
+    let handle = std::thread::spawn(move || -> RedisResult<Option<(String, Vec<String>)>> {
+        redis::cmd("BLMPOP").arg(5).arg(2).arg("none").arg("jobs").arg("LEFT").arg("COUNT").arg(2).query(&mut blocked)
+    });
+    std::thread::sleep(Duration::from_millis(200));
+    // 三个元素在同一个事务中写入，被唤醒时已经全部可见
+    redis::pipe().atomic().rpush("jobs", "x").rpush("jobs", "y").rpush("jobs", "z").query::<()>(&mut con)?;
+    assert_eq!(handle.join().unwrap()?, Some(("jobs".to_string(), vec!["x".to_string(), "y".to_string()])));
+    assert_eq!(con.lrange::<_, Vec<String>>("jobs", 0, -1)?, ["z"]);
+    let start = Instant::now();
+    let popped: Option<(String, Vec<String>)> = redis::cmd("BLMPOP").arg(0.2).arg(1).arg("none").arg("RIGHT").query(&mut con)?;
+    assert_eq!(popped, None);
+    assert!(start.elapsed() >= Duration::from_millis(200));
+    Ok(())
+}
+
+#[test]
+fn test_sharded_execution() -> RedisResult<()> {
+    let server = TestServer::start_with(Config { exec_shards: 4, ..Config::default() });
+
+    // 多个连接并发修改同一个 key 与各自的 key
+    std::thread::scope(|scope| {
+        for i in 0..4 {
+            let mut con = server.connect();
+            scope.spawn(move || {
+                for _ in 0..200 {
+                    redis::cmd("INCR").arg("shared").query::<i64>(&mut con).unwrap();
+                    redis::cmd("INCR").arg(format!("own:{}", i)).query::<i64>(&mut con).unwrap();
+                }
+            });
+        }
+    });
+
+    let mut con = server.connect();
+    assert_eq!(con.get::<_, i64>("shared")?, 800);
+    for i in 0..4 {
+        assert_eq!(con.get::<_, i64>(format!("own:{}", i))?, 200);
+    }
+    // 跨分片的事务照常执行
+    let (a, b): (i64, i64) = redis::pipe().atomic().cmd("INCR").arg("own:0").cmd("INCR").arg("own:3").query(&mut con)?;
+    assert_eq!((a, b), (201, 201));
+    Ok(())
+}
+
+// 跨分片的事务与单 key 命令交错追加同一个列表，AOF 中的顺序与执行顺序一致，重启后列表不变
+#[test]
+fn test_sharded_execution_order() -> RedisResult<()> {
+    let dir = tempfile::tempdir().unwrap();
+    let cfg = Config { exec_shards: 4, ..Config::default() };
+    let shards = ShardedExecutor::new(4).unwrap();
+    let other = (0..100).map(|i| format!("other:{}", i)).find(|key| shards.shard_of(key.as_bytes()) != shards.shard_of(b"list")).unwrap();
+
+    let server = TestServer::start_at(ServerBuilder::new().config(cfg.clone()).in_memory().persistence(true, false), dir.path());
+
+    std::thread::scope(|scope| {
+        for c in 0..4 {
+            let mut single = server.connect();
+            scope.spawn(move || {
+                for i in 0..200 {
+                    single.rpush::<_, _, ()>("list", format!("s{}:{}", c, i)).unwrap();
+                }
+            });
+            let (mut cross, other) = (server.connect(), &other);
+            scope.spawn(move || {
+                for i in 0..200 {
+                    redis::pipe().atomic().rpush("list", format!("t{}:{}", c, i)).rpush(other, i).query::<()>(&mut cross).unwrap();
+                }
+            });
+        }
+    });
+    let before: Vec<String> = server.connect().lrange("list", 0, -1)?;
+    assert_eq!(before.len(), 1600);
+    drop(server);
+
+    let server = TestServer::start_at(ServerBuilder::new().config(cfg).in_memory().persistence(true, false), dir.path());
+    assert_eq!(server.connect().lrange::<_, Vec<String>>("list", 0, -1)?, before);
+    Ok(())
+}
+
+#[test]
+fn test_connection_commands() -> RedisResult<()> {
+    let server = TestServer::start();
+    let mut con = server.connect();
+
+    assert_eq!(redis::cmd("ECHO").arg("hello world").query::<String>(&mut con)?, "hello world");
+    let (secs, micros): (u64, u32) = redis::cmd("TIME").query(&mut con)?;
+    assert!(secs > 1_600_000_000);
+    assert!(micros < 1_000_000);
+    Ok(())
+}
+
+#[test]
+fn test_errors() {
+    let server = TestServer::start();
+    let mut con = server.connect();
+
+    let err = redis::cmd("NOSUCHCOMMAND").query::<()>(&mut con).unwrap_err();
+    assert!(err.to_string().contains("unknown command"), "{}", err);
+
+    let err = redis::cmd("GET").query::<()>(&mut con).unwrap_err();
+    assert!(err.to_string().contains("wrong number of arguments"), "{}", err);
+    assert_eq!(err.code(), Some("ERR"));
+
+    // 客户端按错误码区分错误种类
+    let err = redis::cmd("EVALSHA").arg("ffffffffffffffffffffffffffffffffffffffff").arg(0).query::<()>(&mut con).unwrap_err();
+    assert_eq!(err.code(), Some("NOSCRIPT"));
+
+    // 出错后连接仍可继续使用
+    let _: () = con.set("k", "v").unwrap();
+}
+
+/// 拒绝 FLUSHALL、把 GET 的 key 改写到 `v2:` 下，并记录引擎执行过的命令
+#[derive(Default)]
+struct SoftQuota {
+    executed: std::sync::Mutex<Vec<(String, String)>>,
+}
+
+impl CommandHook for SoftQuota {
+    fn before(&self, _ctx: &HookContext<'_>, args: &mut Vec<Bytes>) -> Result<(), CommandError> {
+        match command::name_upper(&args[0]).as_str() {
+            "FLUSHALL" => Err(CommandError::err("FLUSHALL is disabled by a hook")),
+            "GET" => {
+                args[1] = [b"v2:".as_slice(), &args[1]].concat().into();
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn after(&self, _ctx: &HookContext<'_>, args: &[Bytes], reply: &Frame, _duration: Duration) {
+        let args: Vec<_> = args.iter().map(|arg| String::from_utf8_lossy(arg)).collect();
+        self.executed.lock().unwrap().push((args.join(" "), format!("{:?}", reply)));
+    }
+}
+
+#[test]
+fn test_command_hooks() -> RedisResult<()> {
+    let hook = std::sync::Arc::new(SoftQuota::default());
+    let server = TestServer::start_builder(ServerBuilder::new().hook(hook.clone()));
+    let mut con = server.connect();
+
+    let _: () = con.set("greeting", "hello")?;
+    let _: () = con.set("v2:greeting", "hi")?;
+    assert_eq!(con.get::<_, String>("greeting")?, "hi");
+    let err = redis::cmd("FLUSHALL").query::<()>(&mut con).unwrap_err();
+    assert!(err.to_string().contains("FLUSHALL is disabled by a hook"), "{}", err);
+    assert_eq!(con.ttl::<_, i64>("greeting")?, -1);
+
+    // 被拒绝的命令不会执行，after 只看到引擎执行过的命令
+    let executed: Vec<String> = hook.executed.lock().unwrap().iter().map(|(args, _)| args.clone()).collect();
+    assert_eq!(executed, ["SET greeting hello", "SET v2:greeting hi", "GET v2:greeting", "TTL greeting"]);
+    Ok(())
+}
+
+// 开启追踪的连接因写入出错而断开，追踪登记与记录的 key 随之清除
+#[test]
+fn test_tracking_cleared_on_abrupt_disconnect() -> RedisResult<()> {
+    use std::io::Write;
+
+    let server = TestServer::start();
+    let mut con = server.connect();
+    let _: () = con.set("big", "x".repeat(1 << 20))?;
+    let watch = server.handle.as_ref().unwrap().db().watch_manager.clone();
+
+    // 开启追踪后反复读取大 value 但从不读回复，服务端写满 socket 缓冲区后阻塞在写入上
+    let mut raw = std::net::TcpStream::connect(server.handle.as_ref().unwrap().local_addr())?;
+    let mut request = resp_command(&["HELLO", "3"]);
+    request.extend(resp_command(&["CLIENT", "TRACKING", "ON"]));
+    for _ in 0..64 {
+        request.extend(resp_command(&["GET", "big"]));
+    }
+    raw.write_all(&request)?;
+    assert!(wait_until(|| watch.tracking_client_count() == 1 && watch.tracked_key_count() == 1));
+
+    // 带着未读的数据关闭连接，内核回复 RST，服务端的写入出错返回
+    drop(raw);
+    assert!(wait_until(|| watch.tracking_client_count() == 0 && watch.tracked_key_count() == 0));
+    Ok(())
+}
+
+// 订阅者因写入出错而断开，订阅随之移除，PUBLISH 与 PUBSUB NUMSUB 不再计入它
+#[test]
+fn test_subscriptions_removed_on_abrupt_disconnect() -> RedisResult<()> {
+    use std::io::Write;
+
+    let limits = OutputBufferLimits { pubsub: OutputLimit::new(256 << 20, 0, 0), ..OutputBufferLimits::default() };
+    let server = TestServer::start_with(Config { client_output_buffer_limit: limits, ..Config::default() });
+    let mut con = server.connect();
+    let numsub = |con: &mut Connection| -> i64 {
+        let (_, n): (String, i64) = redis::cmd("PUBSUB").arg("NUMSUB").arg("flood").query(con).unwrap();
+        n
+    };
+
+    let mut raw = std::net::TcpStream::connect(server.handle.as_ref().unwrap().local_addr())?;
+    raw.write_all(&resp_command(&["SUBSCRIBE", "flood"]))?;
+    assert!(wait_until(|| numsub(&mut server.connect()) == 1));
+
+    // 订阅者从不读取，服务端写满 socket 缓冲区后阻塞在推送上
+    let message = "m".repeat(64 * 1024);
+    for _ in 0..256 {
+        assert_eq!(con.publish::<_, _, i64>("flood", &message)?, 1);
+    }
+    // 带着未读的数据关闭连接，内核回复 RST，服务端的写入出错返回
+    drop(raw);
+    assert!(wait_until(|| numsub(&mut server.connect()) == 0));
+    assert_eq!(con.publish::<_, _, i64>("flood", "gone")?, 0);
+    Ok(())
+}
+
+// HELLO 的 SETNAME 选项与 CLIENT SETNAME 一样设置连接名字
+#[test]
+fn test_hello_setname() -> RedisResult<()> {
+    let server = TestServer::start();
+    let mut con = server.connect();
+    let _: redis::Value = redis::cmd("HELLO").arg("2").arg("SETNAME").arg("worker-1").query(&mut con)?;
+    assert_eq!(redis::cmd("CLIENT").arg("GETNAME").query::<String>(&mut con)?, "worker-1");
+
+    // 名字不合法时整个握手失败，原名字不变
+    let err = redis::cmd("HELLO").arg("2").arg("SETNAME").arg("bad name").query::<redis::Value>(&mut con).unwrap_err();
+    assert!(err.to_string().contains("Client names cannot contain spaces"));
+    assert_eq!(redis::cmd("CLIENT").arg("GETNAME").query::<String>(&mut con)?, "worker-1");
+    Ok(())
+}
+
+// HELLO 的任何选项出错时 AUTH 也不生效，连接仍未登录
+#[test]
+fn test_hello_auth_is_atomic() -> RedisResult<()> {
+    let server = TestServer::start_with(Config { requirepass: Some("pw".into()), ..Config::default() });
+    let mut con = server.connect();
+    let err = redis::cmd("HELLO").arg("2").arg("AUTH").arg("default").arg("pw").arg("BOGUS").query::<redis::Value>(&mut con).unwrap_err();
+    assert!(err.to_string().contains("Syntax error in HELLO option 'BOGUS'"));
+    let err = redis::cmd("PING").query::<String>(&mut con).unwrap_err();
+    assert_eq!(err.code(), Some("NOAUTH"));
+
+    let err = redis::cmd("HELLO")
+        .arg("2")
+        .arg("AUTH")
+        .arg("default")
+        .arg("pw")
+        .arg("SETNAME")
+        .arg("bad name")
+        .query::<redis::Value>(&mut con)
+        .unwrap_err();
+    assert!(err.to_string().contains("Client names cannot contain spaces"));
+    assert_eq!(redis::cmd("PING").query::<String>(&mut con).unwrap_err().code(), Some("NOAUTH"));
+
+    let _: redis::Value = redis::cmd("HELLO").arg("2").arg("AUTH").arg("default").arg("pw").query(&mut con)?;
+    assert_eq!(redis::cmd("PING").query::<String>(&mut con)?, "PONG");
+    Ok(())
+}
+
+/// HELLO 回复中 `field` 字段的值（以 RESP2 协商，回复是键值交替的数组）
+fn hello_field(con: &mut Connection, field: &str) -> String {
+    let redis::Value::Array(items) = redis::cmd("HELLO").arg("2").query(con).unwrap() else {
+        panic!("HELLO did not reply with an array");
+    };
+    items
+        .chunks(2)
+        .find(|kv| redis::from_redis_value::<String>(&kv[0]).is_ok_and(|k| k == field))
+        .map(|kv| redis::from_redis_value(&kv[1]).unwrap())
+        .unwrap()
+}
+
+// HELLO 按实际的部署方式报告 mode 与 role
+#[test]
+fn test_hello_topology() -> RedisResult<()> {
+    let server = TestServer::start();
+    let mut con = server.connect();
+    assert_eq!(hello_field(&mut con, "mode"), "standalone");
+    assert_eq!(hello_field(&mut con, "role"), "master");
+
+    let server = TestServer::start_with(Config { cluster_enabled: true, ..Config::default() });
+    let mut con = server.connect();
+    assert_eq!(hello_field(&mut con, "mode"), "cluster");
+    assert_eq!(hello_field(&mut con, "role"), "master");
+    Ok(())
+}